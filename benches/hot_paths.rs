@@ -0,0 +1,119 @@
+//! Micro-benchmarks for the work every commit and check spends its time on:
+//! hashing, building Merkle trees and Reed-Solomon encoding.
+//!
+//! ```text
+//! cargo bench --bench hot_paths
+//! cargo bench --bench hot_paths -- parity   # one group
+//! ```
+//!
+//! Criterion keeps the last run under `target/criterion` and reports the change
+//! against it, so run once before a change and once after.
+
+use blockframe::chunker::Chunker;
+use blockframe::merkle_tree::MerkleTree;
+use blockframe::utils::{
+    BLOCK_SEGMENTS, DEFAULT_BLOCK_PARITY_RATIO, HashSession, blake3_hash_bytes, block_parity_shards,
+};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+use sha2::{Digest, Sha256};
+use std::hint::black_box;
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+
+fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut data = vec![0u8; len];
+    StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+    data
+}
+
+/// BLAKE3 against SHA-256 on whole buffers, and through the streaming
+/// [`HashSession`] used for files.
+fn hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hashing");
+    for size in [4 * KB, 64 * KB, MB, 16 * MB] {
+        let data = random_bytes(size, 0);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("blake3", size), &data, |b, data| {
+            b.iter(|| blake3_hash_bytes(black_box(data)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("sha256", size), &data, |b, data| {
+            b.iter(|| Sha256::digest(black_box(data)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("session_blake3_sha256", size),
+            &data,
+            |b, data| {
+                b.iter(|| {
+                    HashSession::new()
+                        .with_sha256()
+                        .hash_reader(black_box(&data[..]), None)
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Tree construction as the number of leaves grows, from a tier 1 file to a
+/// large tier 3 one.
+fn merkle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merkle_from_hashes");
+    for leaves in [16, 256, 4096, 65_536] {
+        let hashes: Vec<String> = (0..leaves)
+            .map(|i: u32| blake3_hash_bytes(&i.to_le_bytes()).unwrap())
+            .collect();
+        group.throughput(Throughput::Elements(leaves as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(leaves), &hashes, |b, hashes| {
+            b.iter(|| MerkleTree::from_hashes(black_box(hashes.clone())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Parity for a full tier 3 block and for a single tier 1/2 segment across
+/// shard sizes.
+fn parity(c: &mut Criterion) {
+    let chunker = Chunker::new().unwrap();
+    let parity_shards = block_parity_shards(BLOCK_SEGMENTS, DEFAULT_BLOCK_PARITY_RATIO);
+
+    let mut group = c.benchmark_group("parity");
+    group.sample_size(20);
+    for shard_size in [4 * KB, 64 * KB, MB] {
+        let segments: Vec<Vec<u8>> = (0..BLOCK_SEGMENTS)
+            .map(|i| random_bytes(shard_size, i as u64))
+            .collect();
+        let refs: Vec<&[u8]> = segments.iter().map(|s| s.as_slice()).collect();
+        group.throughput(Throughput::Bytes((shard_size * BLOCK_SEGMENTS) as u64));
+        group.bench_with_input(
+            BenchmarkId::new("block_rs30", shard_size),
+            &refs,
+            |b, refs| {
+                b.iter(|| {
+                    chunker
+                        .generate_parity(black_box(refs), BLOCK_SEGMENTS, parity_shards)
+                        .unwrap()
+                })
+            },
+        );
+
+        group.throughput(Throughput::Bytes(shard_size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("segment_rs1", shard_size),
+            &segments[0],
+            |b, segment| {
+                b.iter(|| {
+                    chunker
+                        .generate_parity_segmented(black_box(segment))
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hashing, merkle, parity);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+//! The parts of blockframe a client needs to check what it downloaded: the
+//! Merkle hashing rules, proofs, and the hashes a manifest records.
+//!
+//! `no_std` with `alloc` and no filesystem, so it builds for WASM verifiers
+//! and embedded gateways that can't take the whole `blockframe` crate, which
+//! uses the same rules through this crate.
+//!
+//! ```
+//! use blockframe_core::merkle::{hash_hex, proof_of, root_of, verify_proof};
+//!
+//! let chunks: [&[u8]; 3] = [b"block", b"frame", b"rs"];
+//! let leaves: Vec<String> = chunks.iter().map(|c| hash_hex(c)).collect();
+//! let root = root_of(&leaves).unwrap();
+//! let proof = proof_of(&leaves, 2).unwrap();
+//! assert!(verify_proof(b"rs", &proof, &root));
+//! assert!(!verify_proof(b"rS", &proof, &root));
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod manifest;
+pub mod merkle;
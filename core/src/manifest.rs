@@ -0,0 +1,309 @@
+//! The hashes a `manifest.json` records and what they prove.
+//!
+//! [`Manifest`] reads just the fields verification needs, from a manifest
+//! file or from `serve`'s manifest response, and leaves the rest. Its shard
+//! hashes are checked against a root the client already trusts, e.g. from a
+//! signed manifest or a link it was given, so a shard is only accepted when
+//! the manifest it came with leads to that root.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use serde::Deserialize;
+
+use crate::merkle::{Proof, hash_hex, proof_of, root_from_proof, root_of};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub original_hash: String,
+    pub size: i64,
+    pub tier: u8,
+    #[serde(default)]
+    pub segment_size: u64,
+    pub merkle_tree: Tree,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tree {
+    /// Tier 1: the data shard and its three parity shards, in that order.
+    #[serde(default)]
+    pub leaves: BTreeMap<i32, String>,
+    /// Tier 2: each segment and its parity.
+    #[serde(default)]
+    pub segments: BTreeMap<usize, SegmentHashes>,
+    /// Tier 3: each block's segments and parity.
+    #[serde(default)]
+    pub blocks: BTreeMap<usize, BlockHashes>,
+    pub root: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SegmentHashes {
+    pub data: String,
+    pub parity: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockHashes {
+    pub segments: Vec<String>,
+    pub parity: Vec<String>,
+}
+
+/// A shard of an archived file, data or parity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shard {
+    /// Tier 1 `data.dat`.
+    Tiny,
+    /// Tier 1 parity shard N.
+    TinyParity(usize),
+    /// Tier 2 segment N.
+    Segment(usize),
+    /// Tier 2 parity shard P of segment N, as `SegmentParity(N, P)`.
+    SegmentParity(usize, usize),
+    /// Tier 3 segment N of block B, as `Block(B, N)`.
+    Block(usize, usize),
+    /// Tier 3 parity shard P of block B, as `BlockParity(B, P)`.
+    BlockParity(usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Not a manifest, with serde's reason.
+    Parse(String),
+    /// A tier other than 1, 2 or 3.
+    UnknownTier(u8),
+    /// The manifest has no hash for the shard.
+    MissingShard(Shard),
+    /// The shard's bytes don't hash to what the manifest records.
+    HashMismatch { shard: Shard, actual: String },
+    /// The manifest's hashes lead to another root than the trusted one.
+    RootMismatch { expected: String, actual: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(reason) => write!(f, "not a manifest: {}", reason),
+            Self::UnknownTier(tier) => write!(f, "unknown tier {}", tier),
+            Self::MissingShard(shard) => write!(f, "manifest has no hash for {:?}", shard),
+            Self::HashMismatch { shard, actual } => {
+                write!(f, "{:?} hashes to {}, not its manifest hash", shard, actual)
+            }
+            Self::RootMismatch { expected, actual } => {
+                write!(f, "manifest leads to root {}, not {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl Manifest {
+    /// Reads a `manifest.json`, or the `{"manifest": ..}` body `serve` answers
+    /// a manifest request with.
+    pub fn parse(json: &[u8]) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Served {
+            manifest: Manifest,
+        }
+        // not an untagged enum: serde can't read the integer map keys through one
+        serde_json::from_slice::<Manifest>(json)
+            .or_else(|_| serde_json::from_slice::<Served>(json).map(|s| s.manifest))
+            .map_err(|e| Error::Parse(e.to_string()))
+    }
+
+    /// The hash the manifest records for `shard`.
+    pub fn shard_hash(&self, shard: Shard) -> Result<&str, Error> {
+        let tree = &self.merkle_tree;
+        let hash = match shard {
+            Shard::Tiny => tree.leaves.get(&0),
+            Shard::TinyParity(p) => i32::try_from(p + 1)
+                .ok()
+                .and_then(|leaf| tree.leaves.get(&leaf)),
+            Shard::Segment(n) => tree.segments.get(&n).map(|s| &s.data),
+            Shard::SegmentParity(n, p) => tree.segments.get(&n).and_then(|s| s.parity.get(p)),
+            Shard::Block(b, n) => tree.blocks.get(&b).and_then(|b| b.segments.get(n)),
+            Shard::BlockParity(b, p) => tree.blocks.get(&b).and_then(|b| b.parity.get(p)),
+        };
+        hash.map(String::as_str).ok_or(Error::MissingShard(shard))
+    }
+
+    /// The root the manifest's shard hashes lead to. For a manifest that
+    /// wasn't tampered with it is `merkle_tree.root`.
+    pub fn computed_root(&self) -> Result<String, Error> {
+        let tree = &self.merkle_tree;
+        let root = match self.tier {
+            1 => root_of(&tree.leaves.values().collect::<Vec<_>>()),
+            2 => root_of(
+                &tree
+                    .segments
+                    .values()
+                    .filter_map(|s| root_of(&segment_leaves(s)))
+                    .collect::<Vec<_>>(),
+            ),
+            3 => root_of(
+                &tree
+                    .blocks
+                    .values()
+                    .filter_map(|b| root_of(&block_leaves(b)))
+                    .collect::<Vec<_>>(),
+            ),
+            tier => return Err(Error::UnknownTier(tier)),
+        };
+        Ok(root.unwrap_or_default())
+    }
+
+    /// The proof from `shard` up to the file's root: through its segment's or
+    /// block's tree first for tiers 2 and 3, then the file's.
+    pub fn proof(&self, shard: Shard) -> Result<Proof, Error> {
+        let tree = &self.merkle_tree;
+        let missing = || Error::MissingShard(shard);
+        match (self.tier, shard) {
+            (1, Shard::Tiny | Shard::TinyParity(_)) => {
+                let index = match shard {
+                    Shard::TinyParity(p) => p + 1,
+                    _ => 0,
+                };
+                let leaves: Vec<&String> = tree.leaves.values().collect();
+                proof_of(&leaves, index).ok_or_else(missing)
+            }
+            (2, Shard::Segment(n) | Shard::SegmentParity(n, _)) => {
+                let index = match shard {
+                    Shard::SegmentParity(_, p) => p + 1,
+                    _ => 0,
+                };
+                let position = tree.segments.keys().position(|&k| k == n);
+                let segment = tree.segments.get(&n).ok_or_else(missing)?;
+                let roots: Vec<String> = tree
+                    .segments
+                    .values()
+                    .filter_map(|s| root_of(&segment_leaves(s)))
+                    .collect();
+                nested_proof(&segment_leaves(segment), index, &roots, position).ok_or_else(missing)
+            }
+            (3, Shard::Block(b, n) | Shard::BlockParity(b, n)) => {
+                let block = tree.blocks.get(&b).ok_or_else(missing)?;
+                let index = match shard {
+                    Shard::BlockParity(..) => block.segments.len() + n,
+                    _ => n,
+                };
+                let position = tree.blocks.keys().position(|&k| k == b);
+                let roots: Vec<String> = tree
+                    .blocks
+                    .values()
+                    .filter_map(|b| root_of(&block_leaves(b)))
+                    .collect();
+                nested_proof(&block_leaves(block), index, &roots, position).ok_or_else(missing)
+            }
+            (1..=3, _) => Err(missing()),
+            (tier, _) => Err(Error::UnknownTier(tier)),
+        }
+    }
+
+    /// Checks that `bytes` are the shard `shard` of the file with the trusted
+    /// root `root`: they hash to what the manifest records, and the manifest's
+    /// hashes lead from there to `root`.
+    pub fn verify_shard(&self, shard: Shard, bytes: &[u8], root: &str) -> Result<(), Error> {
+        let expected = self.shard_hash(shard)?;
+        let actual = hash_hex(bytes);
+        if actual != expected {
+            return Err(Error::HashMismatch { shard, actual });
+        }
+        let reached = root_from_proof(expected, &self.proof(shard)?);
+        if reached != root {
+            return Err(Error::RootMismatch {
+                expected: root.to_string(),
+                actual: reached,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A tier 2 segment's tree: its data, then its parity.
+fn segment_leaves(segment: &SegmentHashes) -> Vec<&String> {
+    core::iter::once(&segment.data)
+        .chain(&segment.parity)
+        .collect()
+}
+
+/// A tier 3 block's tree: its segments, then its parity.
+fn block_leaves(block: &BlockHashes) -> Vec<&String> {
+    block.segments.iter().chain(&block.parity).collect()
+}
+
+/// Proof for leaf `index` of `inner`, followed by the proof for that tree's
+/// root at `position` among `roots`.
+fn nested_proof(
+    inner: &[&String],
+    index: usize,
+    roots: &[String],
+    position: Option<usize>,
+) -> Option<Proof> {
+    let mut proof = proof_of(inner, index)?;
+    proof.extend(proof_of(roots, position?)?);
+    Some(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    fn hashes(prefix: &str, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| hash_hex(format!("{}{}", prefix, i).as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_shards_verify_against_the_root() {
+        let segments: Vec<String> = (0..3)
+            .map(|n| {
+                let parity = hashes(&format!("p{}-", n), 3);
+                format!(
+                    r#""{}": {{"data": "{}", "parity": ["{}", "{}", "{}"]}}"#,
+                    n,
+                    hash_hex(format!("segment {}", n).as_bytes()),
+                    parity[0],
+                    parity[1],
+                    parity[2]
+                )
+            })
+            .collect();
+        let json = format!(
+            r#"{{"manifest": {{"name": "a.bin", "original_hash": "", "size": 3, "tier": 2,
+                "segment_size": 1, "time_of_creation": "", "merkle_tree":
+                {{"segments": {{{}}}, "root": ""}}}}, "signature": null}}"#,
+            segments.join(", ")
+        );
+        let manifest = Manifest::parse(json.as_bytes()).unwrap();
+        let root = manifest.computed_root().unwrap();
+
+        manifest
+            .verify_shard(Shard::Segment(1), b"segment 1", &root)
+            .unwrap();
+        manifest
+            .verify_shard(Shard::SegmentParity(2, 1), b"p2-1", &root)
+            .unwrap();
+        assert!(matches!(
+            manifest.verify_shard(Shard::Segment(1), b"segment 2", &root),
+            Err(Error::HashMismatch { .. })
+        ));
+        assert!(matches!(
+            manifest.verify_shard(Shard::Segment(1), b"segment 1", &hash_hex(b"other")),
+            Err(Error::RootMismatch { .. })
+        ));
+        assert_eq!(
+            manifest.verify_shard(Shard::Segment(3), b"segment 3", &root),
+            Err(Error::MissingShard(Shard::Segment(3)))
+        );
+        assert_eq!(
+            manifest.proof(Shard::Block(0, 0)),
+            Err(Error::MissingShard(Shard::Block(0, 0)))
+        );
+        assert!(Manifest::parse(b"{}").is_err());
+    }
+}
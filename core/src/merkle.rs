@@ -0,0 +1,147 @@
+//! How blockframe builds its Merkle trees.
+//!
+//! Every hash is lowercase hex BLAKE3. A parent is the hash of its children's
+//! hex hashes written one after the other, and a level with an odd number of
+//! nodes pairs the last one with itself.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Lowercase hex BLAKE3 of `bytes`, as manifests record shard hashes.
+pub fn hash_hex(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().as_str().into()
+}
+
+/// The parent of the nodes `left` and `right`.
+pub fn parent_hash(left: &str, right: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    hasher.finalize().to_hex().as_str().into()
+}
+
+/// Whether `hash` is 64 hex digits, as a BLAKE3 hash is written.
+pub fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Root of the tree over `leaves`, `None` when there are none.
+pub fn root_of<S: AsRef<str>>(leaves: &[S]) -> Option<String> {
+    let mut level: Vec<String> = leaves.iter().map(|l| l.as_ref().into()).collect();
+    if level.is_empty() {
+        return None;
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| parent_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level.pop()
+}
+
+/// One step from a node up to its parent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    /// Hash of the node it pairs with.
+    pub sibling: String,
+    /// Whether that node is the left child.
+    pub sibling_on_left: bool,
+}
+
+/// The steps from a leaf up to a root. A proof through nested trees, like a
+/// segment's tree and then the file's, is the steps of each in turn.
+pub type Proof = Vec<ProofStep>;
+
+/// Proof for leaf `index` of the tree over `leaves`, `None` if out of range.
+pub fn proof_of<S: AsRef<str>>(leaves: &[S], index: usize) -> Option<Proof> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut level: Vec<String> = leaves.iter().map(|l| l.as_ref().into()).collect();
+    let mut index = index;
+    let mut proof = Proof::new();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        proof.push(ProofStep {
+            sibling: level.get(sibling).unwrap_or(&level[index]).clone(),
+            sibling_on_left: sibling < index,
+        });
+        level = level
+            .chunks(2)
+            .map(|pair| parent_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// The root `leaf` leads to along `proof`.
+pub fn root_from_proof(leaf: &str, proof: &[ProofStep]) -> String {
+    proof.iter().fold(String::from(leaf), |node, step| {
+        if step.sibling_on_left {
+            parent_hash(&step.sibling, &node)
+        } else {
+            parent_hash(&node, &step.sibling)
+        }
+    })
+}
+
+/// Whether `chunk` is a leaf of the tree with root `root`.
+pub fn verify_proof(chunk: &[u8], proof: &[ProofStep], root: &str) -> bool {
+    root_from_proof(&hash_hex(chunk), proof) == root
+}
+
+/// [`verify_proof`] for a proof of sibling hashes alone, as
+/// `MerkleTree::get_proof` gives. Which side each is on follows from the
+/// leaf's `index`.
+pub fn verify_indexed_proof(chunk: &[u8], index: usize, siblings: &[String], root: &str) -> bool {
+    let mut index = index;
+    let mut node = hash_hex(chunk);
+    for sibling in siblings {
+        node = if index.is_multiple_of(2) {
+            parent_hash(&node, sibling)
+        } else {
+            parent_hash(sibling, &node)
+        };
+        index /= 2;
+    }
+    node == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proofs_lead_to_the_root() {
+        for count in 1..=9 {
+            let leaves: Vec<String> = (0..count).map(|i| hash_hex(&[i as u8])).collect();
+            let root = root_of(&leaves).unwrap();
+            for index in 0..count {
+                let proof = proof_of(&leaves, index).unwrap();
+                assert!(verify_proof(&[index as u8], &proof, &root));
+                assert!(!verify_proof(&[index as u8 + 1], &proof, &root));
+
+                let siblings: Vec<String> = proof.iter().map(|s| s.sibling.clone()).collect();
+                assert!(verify_indexed_proof(
+                    &[index as u8],
+                    index,
+                    &siblings,
+                    &root
+                ));
+            }
+            assert!(proof_of(&leaves, count).is_none());
+        }
+        assert!(root_of::<String>(&[]).is_none());
+
+        let (a, b) = (hash_hex(b"a"), hash_hex(b"b"));
+        assert_eq!(
+            root_of(&[&a, &b]).unwrap(),
+            hash_hex((a.clone() + &b).as_bytes())
+        );
+        assert!(is_hash(&a) && !is_hash("xyz"));
+        assert_eq!(proof_of(&[&a], 0).unwrap(), Proof::new());
+    }
+}
@@ -0,0 +1,54 @@
+/*
+ * blockframe C API, built as libblockframe_ffi from ffi/ with
+ * `cargo build --profile release-ffi -p blockframe-ffi`, into
+ * target/release-ffi/.
+ *
+ * Strings are UTF-8 and NUL terminated. A call that fails returns -1;
+ * bf_last_error() then says why. A panic inside the library is returned
+ * the same way instead of aborting the process.
+ */
+#ifndef BLOCKFRAME_H
+#define BLOCKFRAME_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* What bf_health_check returns for an archived file. */
+enum bf_health {
+    BF_HEALTHY = 0,
+    BF_DEGRADED = 1,
+    BF_RECOVERABLE = 2,
+    BF_UNRECOVERABLE = 3,
+};
+
+/* The reason the last failing call on this thread failed, or NULL. Valid
+ * until the next failing call on this thread; don't free it. */
+const char *bf_last_error(void);
+
+/* Commits the file at path into the archive at archive_dir, creating the
+ * archive if missing. Returns 0. */
+int bf_commit(const char *archive_dir, const char *path);
+
+/* Restores the archived file name to out_path, recovering damaged shards
+ * from parity. Returns 0. The file is written next to out_path and renamed
+ * over it once complete, so on failure a file already at out_path is left
+ * as it was. */
+int bf_reconstruct_to_path(const char *archive_dir, const char *name, const char *out_path);
+
+/* Checks the archived file name. Returns one of enum bf_health. */
+int bf_health_check(const char *archive_dir, const char *name);
+
+/* Whether the len bytes at bytes lead to root along proof, a JSON array of
+ * {"sibling": "<hash>", "sibling_on_left": <bool>} steps. Returns 1 if they
+ * do, 0 if not. */
+int bf_verify_segment(const uint8_t *bytes, size_t len, const char *proof, const char *root);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BLOCKFRAME_H */
@@ -0,0 +1,290 @@
+//! A C API over the archive, for backup tools that want blockframe's storage
+//! format without linking Rust or running the `blockframe` binary.
+//!
+//! `include/blockframe.h` declares these functions. Strings are UTF-8 and
+//! NUL terminated. A call that fails returns -1 and leaves its reason for
+//! `bf_last_error` on the calling thread, a panic inside it included. Build
+//! with `--profile release-ffi`, which unwinds, so a panic can be caught.
+
+use blockframe::chunker::Chunker;
+use blockframe::filestore::FileStore;
+use blockframe::filestore::models::HealthStatus;
+use blockframe_core::merkle::{self, Proof};
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, OsString, c_char, c_int};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Runs `f`, turning an error into -1 and keeping its message. A panic is
+/// caught the same way rather than unwinding into the caller, which needs
+/// the unwinding `release-ffi` profile; under `panic = "abort"` it aborts.
+fn guard(f: impl FnOnce() -> Result<c_int>) -> c_int {
+    let result = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => Err(panic_message(payload.as_ref()).into()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            // a message with a NUL in it is cut there rather than lost
+            let message = e.to_string();
+            let end = message.find('\0').unwrap_or(message.len());
+            let message = CString::new(&message[..end]).unwrap_or_default();
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+            -1
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let reason = match payload.downcast_ref::<&str>() {
+        Some(reason) => reason.to_string(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown reason".to_string()),
+    };
+    format!("panicked: {}", reason)
+}
+
+/// # Safety
+///
+/// `ptr` must be null or a NUL terminated string that lives for `'a`.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(format!("{} is null", name).into());
+    }
+    let s = unsafe { CStr::from_ptr(ptr) };
+    Ok(s.to_str().map_err(|_| format!("{} is not UTF-8", name))?)
+}
+
+/// The message of the last call on this thread that returned -1, or null if
+/// there was none. Valid until the next failing call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn bf_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Commits the file at `path` into the archive at `archive_dir`, which is
+/// created if missing. Returns 0.
+///
+/// # Safety
+///
+/// Both arguments must be NUL terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_commit(archive_dir: *const c_char, path: *const c_char) -> c_int {
+    guard(|| {
+        let archive_dir = unsafe { str_arg(archive_dir, "archive_dir")? };
+        let path = unsafe { str_arg(path, "path")? };
+        fs::create_dir_all(archive_dir)?;
+        let chunker = Chunker::builder().archive_dir(archive_dir).build()?;
+        chunker.commit(Path::new(path))?;
+        Ok(0)
+    })
+}
+
+/// Restores the archived file `name` to `out_path`, recovering damaged
+/// shards from parity. Returns 0. The file is restored next to `out_path`
+/// and renamed over it once complete, so on failure whatever was at
+/// `out_path` before is left as it was.
+///
+/// # Safety
+///
+/// All arguments must be NUL terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_reconstruct_to_path(
+    archive_dir: *const c_char,
+    name: *const c_char,
+    out_path: *const c_char,
+) -> c_int {
+    guard(|| {
+        let archive_dir = unsafe { str_arg(archive_dir, "archive_dir")? };
+        let name = unsafe { str_arg(name, "name")? };
+        let out_path = unsafe { str_arg(out_path, "out_path")? };
+        let out_path = Path::new(out_path);
+        let store = FileStore::new(Path::new(archive_dir))?;
+        let file = store.find(&name.to_string())?;
+        let staged = staging_path(out_path)?;
+        let restored = fs::File::create_new(&staged)
+            .map_err(Into::into)
+            .and_then(|out| store.reconstruct_to(&file, out))
+            .and_then(|_| Ok(fs::rename(&staged, out_path)?));
+        if let Err(e) = restored {
+            let _ = fs::remove_file(&staged);
+            return Err(e);
+        }
+        Ok(0)
+    })
+}
+
+/// A name next to `out_path` for a restore into it, of its own per call.
+fn staging_path(out_path: &Path) -> Result<PathBuf> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = out_path
+        .file_name()
+        .ok_or_else(|| format!("{} is not a file path", out_path.display()))?;
+    let mut staged = OsString::from(".");
+    staged.push(name);
+    staged.push(format!(
+        ".{}-{}.partial",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(out_path.with_file_name(staged))
+}
+
+/// Checks the archived file `name`. Returns 0 healthy, 1 degraded,
+/// 2 recoverable or 3 unrecoverable, as `blockframe health` reports them.
+///
+/// # Safety
+///
+/// Both arguments must be NUL terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_health_check(archive_dir: *const c_char, name: *const c_char) -> c_int {
+    guard(|| {
+        let archive_dir = unsafe { str_arg(archive_dir, "archive_dir")? };
+        let name = unsafe { str_arg(name, "name")? };
+        let store = FileStore::new(Path::new(archive_dir))?;
+        let file = store.find(&name.to_string())?;
+        Ok(match store.health_check(&file)?.status {
+            HealthStatus::Healthy => 0,
+            HealthStatus::Degraded => 1,
+            HealthStatus::Recoverable => 2,
+            HealthStatus::Unrecoverable => 3,
+        })
+    })
+}
+
+/// Whether the `len` bytes at `bytes` lead to `root` along `proof`, a JSON
+/// array of `{"sibling": .., "sibling_on_left": ..}` steps. Returns 1 if
+/// they do and 0 if not.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes, or be null with `len` 0.
+/// `proof` and `root` must be NUL terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bf_verify_segment(
+    bytes: *const u8,
+    len: usize,
+    proof: *const c_char,
+    root: *const c_char,
+) -> c_int {
+    guard(|| {
+        let bytes = match (bytes.is_null(), len) {
+            (true, 0) => &[][..],
+            (true, _) => return Err("bytes is null".into()),
+            (false, _) => unsafe { std::slice::from_raw_parts(bytes, len) },
+        };
+        let proof: Proof = serde_json::from_str(unsafe { str_arg(proof, "proof")? })?;
+        let root = unsafe { str_arg(root, "root")? };
+        Ok(merkle::verify_proof(bytes, &proof, root) as c_int)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockframe_core::merkle::{hash_hex, proof_of, root_of};
+
+    fn c(s: impl AsRef<str>) -> CString {
+        CString::new(s.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_commit_check_and_restore() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive = c(temp_dir.path().join("archive").to_string_lossy());
+        let source = temp_dir.path().join("notes.txt");
+        fs::write(&source, b"kept in C").unwrap();
+        let restored = temp_dir.path().join("restored.txt");
+
+        unsafe {
+            assert_eq!(
+                bf_commit(archive.as_ptr(), c(source.to_string_lossy()).as_ptr()),
+                0
+            );
+            assert_eq!(
+                bf_health_check(archive.as_ptr(), c("notes.txt").as_ptr()),
+                0
+            );
+            let out = c(restored.to_string_lossy());
+            assert_eq!(
+                bf_reconstruct_to_path(archive.as_ptr(), c("notes.txt").as_ptr(), out.as_ptr()),
+                0
+            );
+            assert_eq!(fs::read(&restored).unwrap(), b"kept in C");
+
+            // a failed restore leaves the file already at out_path alone
+            let archive_dir = temp_dir.path().join("archive");
+            let entry = fs::read_dir(&archive_dir).unwrap().find_map(|entry| {
+                let path = entry.unwrap().path();
+                path.join("data.dat").exists().then_some(path)
+            });
+            let entry = entry.unwrap();
+            for shard in fs::read_dir(&entry).unwrap() {
+                let path = shard.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "dat") {
+                    fs::remove_file(path).unwrap();
+                }
+            }
+            fs::write(&restored, b"the caller's file").unwrap();
+            assert_eq!(
+                bf_reconstruct_to_path(archive.as_ptr(), c("notes.txt").as_ptr(), out.as_ptr()),
+                -1
+            );
+            assert_eq!(fs::read(&restored).unwrap(), b"the caller's file");
+            // nor a partial restore next to it
+            assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+
+            assert_eq!(bf_health_check(archive.as_ptr(), c("missing").as_ptr()), -1);
+            assert!(!bf_last_error().is_null());
+            assert_eq!(bf_commit(std::ptr::null(), archive.as_ptr()), -1);
+            let message = CStr::from_ptr(bf_last_error()).to_str().unwrap();
+            assert_eq!(message, "archive_dir is null");
+        }
+    }
+
+    #[test]
+    fn test_panic_is_returned_as_an_error() {
+        assert_eq!(guard(|| panic!("boom")), -1);
+        let message = unsafe { CStr::from_ptr(bf_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panicked: boom");
+        assert_eq!(guard(|| panic!("{} of {}", 3, 4)), -1);
+        let message = unsafe { CStr::from_ptr(bf_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panicked: 3 of 4");
+    }
+
+    #[test]
+    fn test_verify_segment() {
+        let chunks: [&[u8]; 3] = [b"a", b"b", b"c"];
+        let leaves: Vec<String> = chunks.iter().map(|chunk| hash_hex(chunk)).collect();
+        let root = c(root_of(&leaves).unwrap());
+        let proof = c(serde_json::to_string(&proof_of(&leaves, 1).unwrap()).unwrap());
+
+        unsafe {
+            let verify = |bytes: &[u8]| {
+                bf_verify_segment(bytes.as_ptr(), bytes.len(), proof.as_ptr(), root.as_ptr())
+            };
+            assert_eq!(verify(b"b"), 1);
+            assert_eq!(verify(b"c"), 0);
+            assert_eq!(
+                bf_verify_segment(b"b".as_ptr(), 1, c("[{").as_ptr(), root.as_ptr()),
+                -1
+            );
+        }
+    }
+}
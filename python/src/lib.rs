@@ -0,0 +1,192 @@
+//! Python bindings for the archive, built with `maturin build` from this
+//! directory:
+//!
+//! ```python
+//! import blockframe
+//!
+//! blockframe.Chunker("archive_directory").commit("movie.mkv")
+//! store = blockframe.FileStore("archive_directory")
+//! for name in store.list():
+//!     if store.health(name)["status"] != "healthy":
+//!         store.repair(name)
+//! with open("movie.mkv", "wb") as out:
+//!     store.reconstruct_to("movie.mkv", out)
+//! ```
+//!
+//! Commits, checks and restores run without the GIL, so other Python threads
+//! keep going while they do.
+
+use blockframe::chunker::Chunker as RustChunker;
+use blockframe::filestore::FileStore as RustFileStore;
+use blockframe::filestore::models::{File, HealthStatus};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Errors cross back into Python as `RuntimeError` with their message. They
+/// are flattened to strings first, since `Box<dyn Error>` can't leave a
+/// thread that let go of the GIL.
+fn py_err(message: String) -> PyErr {
+    PyRuntimeError::new_err(message)
+}
+
+/// Archives files, see `blockframe::chunker::Chunker`.
+#[pyclass]
+struct Chunker {
+    inner: RustChunker,
+}
+
+#[pymethods]
+impl Chunker {
+    #[new]
+    fn new(archive_dir: PathBuf) -> PyResult<Self> {
+        std::fs::create_dir_all(&archive_dir)?;
+        let inner = RustChunker::builder()
+            .archive_dir(archive_dir)
+            .build()
+            .map_err(|e| py_err(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    /// Commits the file at `path`, at the tier its size picks or `tier`.
+    /// Returns a dict of what was archived.
+    #[pyo3(signature = (path, tier=None))]
+    fn commit<'py>(
+        &self,
+        py: Python<'py>,
+        path: PathBuf,
+        tier: Option<u8>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let chunked = py
+            .detach(|| self.inner.commit_as(&path, tier).map_err(|e| e.to_string()))
+            .map_err(py_err)?;
+        let dict = PyDict::new(py);
+        dict.set_item("name", &chunked.file_name)?;
+        dict.set_item("size", chunked.file_size)?;
+        dict.set_item("hash", &chunked.file_hash)?;
+        dict.set_item("dir", &chunked.file_dir)?;
+        dict.set_item("segments", chunked.num_segments)?;
+        Ok(dict)
+    }
+}
+
+/// An archive directory, see `blockframe::filestore::FileStore`.
+#[pyclass]
+struct FileStore {
+    inner: RustFileStore,
+}
+
+impl FileStore {
+    fn find(&self, name: &str) -> PyResult<File> {
+        self.inner
+            .find(&name.to_string())
+            .map_err(|e| py_err(e.to_string()))
+    }
+}
+
+#[pymethods]
+impl FileStore {
+    #[new]
+    fn new(archive_dir: PathBuf) -> PyResult<Self> {
+        Ok(Self {
+            inner: RustFileStore::new(&archive_dir)?,
+        })
+    }
+
+    /// Names of the archived files.
+    fn list(&self) -> PyResult<Vec<String>> {
+        let files = self.inner.get_all().map_err(|e| py_err(e.to_string()))?;
+        Ok(files.into_iter().map(|file| file.file_name).collect())
+    }
+
+    /// Checks `name` and returns its health report as a dict, with `status`
+    /// one of "healthy", "degraded", "recoverable" or "unrecoverable".
+    fn health<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyDict>> {
+        let file = self.find(name)?;
+        let report = py
+            .detach(|| self.inner.health_check(&file).map_err(|e| e.to_string()))
+            .map_err(py_err)?;
+        let status = match report.status {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Recoverable => "recoverable",
+            HealthStatus::Unrecoverable => "unrecoverable",
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("status", status)?;
+        dict.set_item("missing_data", report.missing_data)?;
+        dict.set_item("missing_parity", report.missing_parity)?;
+        dict.set_item("corrupt_segments", report.corrupt_segments)?;
+        dict.set_item("details", report.details)?;
+        Ok(dict)
+    }
+
+    /// Rebuilds the missing and corrupt shards of `name` from parity.
+    fn repair(&self, py: Python<'_>, name: &str) -> PyResult<()> {
+        let file = self.find(name)?;
+        py.detach(|| self.inner.repair(&file).map_err(|e| e.to_string()))
+            .map_err(py_err)
+    }
+
+    /// Streams the original bytes of `name` into `out`, anything with a
+    /// `write(bytes)` method, and returns their hash. Damaged shards are
+    /// recovered on the way.
+    fn reconstruct_to(&self, py: Python<'_>, name: &str, out: Py<PyAny>) -> PyResult<String> {
+        let file = self.find(name)?;
+        py.detach(|| {
+            self.inner
+                .reconstruct_to(&file, PyWriter(out))
+                .map_err(|e| e.to_string())
+        })
+        .map_err(py_err)
+    }
+
+    /// Restores `name` to the file at `path`.
+    fn reconstruct_to_path(&self, py: Python<'_>, name: &str, path: PathBuf) -> PyResult<String> {
+        let file = self.find(name)?;
+        py.detach(|| restore_to_path(&self.inner, &file, &path))
+            .map_err(py_err)
+    }
+}
+
+fn restore_to_path(store: &RustFileStore, file: &File, path: &Path) -> Result<String, String> {
+    let out = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    store.reconstruct_to(file, out).map_err(|e| {
+        let _ = std::fs::remove_file(path);
+        e.to_string()
+    })
+}
+
+/// A Python file object as a `Write`, taking the GIL for each write.
+struct PyWriter(Py<PyAny>);
+
+impl Write for PyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Python::attach(|py| {
+            self.0
+                .call_method1(py, "write", (PyBytes::new(py, buf),))
+                .map_err(io::Error::other)?;
+            Ok(buf.len())
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::attach(|py| {
+            if self.0.bind(py).hasattr("flush")? {
+                self.0.call_method0(py, "flush")?;
+            }
+            Ok::<_, PyErr>(())
+        })
+        .map_err(io::Error::other)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "blockframe")]
+fn blockframe_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Chunker>()?;
+    m.add_class::<FileStore>()?;
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Recording duplicate files as aliases instead of encoding them again.
+//!
+//! Entry directories are named `{name}_{hash}` after the file's BLAKE3 hash, so
+//! a commit can tell from the directory names alone whether the same bytes are
+//! already archived. When they are, under a different name, the commit writes
+//! `{name}_{hash}/alias.json` pointing at the original entry and stops there: no
+//! shards, no manifest.
+//!
+//! [`FileStore`] lists an alias under its own name with the original's manifest
+//! and shard paths and [`File::alias_of`] set, so restoring, serving, mounting
+//! and health checks all read the original's shards. Repairing the original
+//! repairs its aliases with it. Packs are never aliased to, their entry holds
+//! many files rather than the one being committed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{AuditEntry, AuditLog, AuditOp};
+use crate::chunker::{ChunkedFile, Chunker};
+use crate::filestore::FileStore;
+use crate::filestore::legacy;
+use crate::filestore::models::{File, FileData, parse_time};
+use crate::merkle_tree::manifest::ManifestFile;
+
+/// File in an alias entry's directory, in place of `manifest.json`.
+pub const ALIAS_FILE: &str = "alias.json";
+
+/// Contents of `alias.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alias {
+    /// Name the duplicate was committed under.
+    pub name: String,
+    /// Entry directory of the original, e.g. `report.pdf_<hash>`.
+    pub target: String,
+    pub original_hash: String,
+    pub size: u64,
+    pub time_of_creation: String,
+}
+
+/// Whether `file_dir` is an alias entry.
+pub fn is_alias(file_dir: &Path) -> bool {
+    file_dir.join(ALIAS_FILE).is_file()
+}
+
+/// Entry directory in `archive_dir` holding encoded shards for the file with
+/// `hash`, if there is one. Aliases, packs and legacy entries don't count.
+pub fn find_original(archive_dir: &Path, hash: &str) -> Option<PathBuf> {
+    let suffix = format!("_{}", hash);
+    fs::read_dir(archive_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| !name.starts_with('.') && name.ends_with(&suffix))
+        })
+        .find(|dir| {
+            !legacy::is_legacy(dir)
+                && ManifestFile::new(dir.join("manifest.json").display().to_string()).is_ok_and(
+                    |manifest| manifest.pack.is_empty() && manifest.original_hash == hash,
+                )
+        })
+}
+
+/// Names of the aliases in `archive_dir` whose original is the entry in
+/// `file_dir`.
+pub fn aliases_of(archive_dir: &Path, file_dir: &Path) -> Vec<String> {
+    let Some(target) = file_dir.file_name() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(archive_dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| fs::read_to_string(entry.path().join(ALIAS_FILE)).ok())
+        .filter_map(|json| serde_json::from_str::<Alias>(&json).ok())
+        .filter(|alias| target.to_string_lossy() == alias.target)
+        .map(|alias| alias.name)
+        .collect()
+}
+
+impl Chunker {
+    /// Records the file `name` with content `hash` as an alias if the same
+    /// bytes are already archived under another name. Returns `None` when the
+    /// file still has to be encoded: nothing identical is archived, or it is
+    /// archived under this same name.
+    pub(crate) fn commit_duplicate(
+        &self,
+        name: &str,
+        hash: &str,
+    ) -> Result<Option<ChunkedFile>, Box<dyn std::error::Error>> {
+        if !self.archive_dir().is_dir() {
+            return Ok(None);
+        }
+        let Some(original_dir) = find_original(self.archive_dir(), hash) else {
+            return Ok(None);
+        };
+        let manifest = ManifestFile::new(original_dir.join("manifest.json").display().to_string())?;
+        if manifest.name == name {
+            return Ok(None);
+        }
+
+        let target = original_dir
+            .file_name()
+            .ok_or("original entry has no name")?
+            .to_string_lossy()
+            .to_string();
+        let alias = Alias {
+            name: name.to_string(),
+            target,
+            original_hash: hash.to_string(),
+            size: manifest.size.max(0) as u64,
+            time_of_creation: Utc::now().to_rfc3339(),
+        };
+        let file_dir = self.get_dir(name, hash)?;
+        let work = self.work_dir()?;
+        fs::write(
+            work.path().join(ALIAS_FILE),
+            serde_json::to_string_pretty(&alias)?,
+        )?;
+        self.publish(work, &file_dir)?;
+        tracing::info!(
+            "COMMIT | {} is identical to {}, recorded as an alias",
+            name,
+            manifest.name
+        );
+        AuditLog::for_archive(self.archive_dir()).append(
+            &AuditEntry::new(AuditOp::Commit, name)
+                .hash(hash)
+                .details(format!("alias of {}", manifest.name)),
+        )?;
+
+        Ok(Some(ChunkedFile::from_manifest(
+            file_dir,
+            name.to_string(),
+            &manifest,
+        )?))
+    }
+}
+
+impl FileStore {
+    /// The alias entry in `file_dir` as a [`File`] reading from its original.
+    pub(crate) fn resolve_alias(
+        &self,
+        file_dir: &Path,
+    ) -> Result<File, Box<dyn std::error::Error>> {
+        let alias: Alias = serde_json::from_str(&fs::read_to_string(file_dir.join(ALIAS_FILE))?)?;
+        let manifest_path = self.store_path.join(&alias.target).join("manifest.json");
+        let manifest = ManifestFile::new(manifest_path.display().to_string())
+            .map_err(|e| format!("original {} is unreadable: {}", alias.target, e))?;
+        if manifest.original_hash != alias.original_hash {
+            return Err(format!("original {} no longer matches its hash", alias.target).into());
+        }
+        Ok(File {
+            file_name: alias.name,
+            committed: parse_time(&alias.time_of_creation),
+            file_data: FileData::new(alias.original_hash, manifest_path.display().to_string()),
+            alias_of: Some(manifest.name.clone()),
+            manifest,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_duplicate_commit_is_recorded_as_alias() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+
+        let bytes: Vec<u8> = (0..3000).map(|i| (i % 251) as u8).collect();
+        let original = temp_dir.path().join("report.pdf");
+        let copy = temp_dir.path().join("report-final.pdf");
+        fs::write(&original, &bytes).unwrap();
+        fs::write(&copy, &bytes).unwrap();
+
+        let first = chunker.commit(&original).unwrap();
+        let second = chunker.commit(&copy).unwrap();
+        assert_eq!(second.file_hash, first.file_hash);
+        assert!(is_alias(&second.file_dir));
+        assert!(!second.file_dir.join("data.dat").exists());
+
+        // committing the original again re-encodes it rather than aliasing itself
+        let again = chunker.commit(&original).unwrap();
+        assert_eq!(again.file_dir, first.file_dir);
+        assert!(!is_alias(&again.file_dir));
+
+        let store = FileStore::new(&archive_dir).unwrap();
+        assert_eq!(store.get_all().unwrap().len(), 2);
+        let alias = store.find(&"report-final.pdf".to_string()).unwrap();
+        assert_eq!(alias.alias_of.as_deref(), Some("report.pdf"));
+        let mut restored = Vec::new();
+        store.reconstruct_to(&alias, &mut restored).unwrap();
+        assert_eq!(restored, bytes);
+
+        // an alias whose original is gone is left out of the listing
+        fs::remove_dir_all(&first.file_dir).unwrap();
+        store.invalidate();
+        assert!(store.get_all().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,335 @@
+//! Append-only audit trail of mutating archive operations.
+//!
+//! Every commit and repair appends one JSON line to `audit.log` in the archive
+//! directory recording who did what, when, to which file, and the resulting
+//! hash. The file is only ever opened in append mode.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub const AUDIT_FILE: &str = "audit.log";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOp {
+    Commit,
+    Repair,
+    Delete,
+    Undelete,
+    Replicate,
+    Retier,
+}
+
+impl AuditOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOp::Commit => "commit",
+            AuditOp::Repair => "repair",
+            AuditOp::Delete => "delete",
+            AuditOp::Undelete => "undelete",
+            AuditOp::Replicate => "replicate",
+            AuditOp::Retier => "retier",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AuditOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "commit" => Ok(AuditOp::Commit),
+            "repair" => Ok(AuditOp::Repair),
+            "delete" => Ok(AuditOp::Delete),
+            "undelete" => Ok(AuditOp::Undelete),
+            "replicate" => Ok(AuditOp::Replicate),
+            "retier" => Ok(AuditOp::Retier),
+            _ => Err(format!("unknown audit operation '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub host: String,
+    pub operation: AuditOp,
+    pub file_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// `ok`, or `failed: <reason>`.
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl AuditEntry {
+    /// Creates an entry stamped with the current time, user and host.
+    pub fn new(operation: AuditOp, file_name: &str) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            user: current_user(),
+            host: host_name(),
+            operation,
+            file_name: file_name.to_string(),
+            hash: None,
+            outcome: "ok".to_string(),
+            details: None,
+        }
+    }
+
+    pub fn hash(mut self, hash: &str) -> Self {
+        self.hash = Some(hash.to_string());
+        self
+    }
+
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    pub fn failed(mut self, reason: impl std::fmt::Display) -> Self {
+        self.outcome = format!("failed: {}", reason);
+        self
+    }
+}
+
+/// Filters for [`AuditLog::query`]. Unset fields match everything.
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    pub operation: Option<AuditOp>,
+    pub file_name: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Keep only the most recent `limit` matches.
+    pub limit: Option<usize>,
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// Audit log living in `archive_dir/audit.log`.
+    pub fn for_archive(archive_dir: &Path) -> Self {
+        Self {
+            path: archive_dir.join(AUDIT_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a single entry as one JSON line.
+    pub fn append(&self, entry: &AuditEntry) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        // a single write_all on an O_APPEND handle keeps concurrent writers from interleaving lines
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Returns matching entries in the order they were written.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = Vec::new();
+        for (line_no, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: AuditEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!("AUDIT | skipping malformed line {}: {}", line_no + 1, e);
+                    continue;
+                }
+            };
+            if query.operation.is_some_and(|op| op != entry.operation) {
+                continue;
+            }
+            if query
+                .file_name
+                .as_ref()
+                .is_some_and(|name| *name != entry.file_name)
+            {
+                continue;
+            }
+            if query.since.is_some_and(|since| entry.timestamp < since) {
+                continue;
+            }
+            entries.push(entry);
+        }
+
+        if let Some(limit) = query.limit
+            && entries.len() > limit
+        {
+            entries.drain(..entries.len() - limit);
+        }
+        Ok(entries)
+    }
+
+    /// Current length of the log in bytes, 0 if it doesn't exist yet.
+    pub fn end_offset(&self) -> u64 {
+        fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0)
+    }
+
+    /// Entries written after byte `offset`, and the offset to continue from.
+    /// A trailing line that is still being written is left for the next call.
+    /// If the log shrank below `offset` it is read from the start again.
+    pub fn read_from(
+        &self,
+        offset: u64,
+    ) -> Result<(Vec<AuditEntry>, u64), Box<dyn std::error::Error>> {
+        let mut file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(e.into()),
+        };
+        let offset = if file.metadata()?.len() < offset {
+            0
+        } else {
+            offset
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let complete = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let entries = buf[..complete]
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+            .filter_map(|line| match serde_json::from_slice(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    tracing::warn!("AUDIT | skipping malformed entry: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Ok((entries, offset + complete as u64))
+    }
+}
+
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(feature = "host-info")]
+fn host_name() -> String {
+    sysinfo::System::host_name().unwrap_or_default()
+}
+
+/// `gethostname` on Unix, `COMPUTERNAME` elsewhere, empty if neither works.
+#[cfg(not(feature = "host-info"))]
+fn host_name() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        // SAFETY: the buffer is valid for its whole length
+        if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0 {
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            return String::from_utf8_lossy(&name[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_append_and_query() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = AuditLog::for_archive(temp_dir.path());
+
+        log.append(&AuditEntry::new(AuditOp::Commit, "a.txt").hash("aa"))
+            .unwrap();
+        log.append(&AuditEntry::new(AuditOp::Repair, "a.txt").failed("no parity"))
+            .unwrap();
+        log.append(&AuditEntry::new(AuditOp::Commit, "b.txt").hash("bb"))
+            .unwrap();
+
+        let all = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 3);
+
+        let commits = log
+            .query(&AuditQuery {
+                operation: Some(AuditOp::Commit),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(commits.len(), 2);
+
+        let latest = log
+            .query(&AuditQuery {
+                file_name: Some("a.txt".to_string()),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].operation, AuditOp::Repair);
+        assert!(latest[0].outcome.starts_with("failed"));
+    }
+
+    #[test]
+    fn test_query_missing_log_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = AuditLog::for_archive(temp_dir.path());
+        assert!(log.query(&AuditQuery::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_from_only_returns_new_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = AuditLog::for_archive(temp_dir.path());
+        log.append(&AuditEntry::new(AuditOp::Commit, "a.txt"))
+            .unwrap();
+
+        let (entries, offset) = log.read_from(0).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(offset, log.end_offset());
+
+        log.append(&AuditEntry::new(AuditOp::Repair, "a.txt"))
+            .unwrap();
+        // a half written line is left for the next read
+        OpenOptions::new()
+            .append(true)
+            .open(log.path())
+            .unwrap()
+            .write_all(b"{\"timestamp\"")
+            .unwrap();
+        let (entries, next) = log.read_from(offset).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, AuditOp::Repair);
+        assert!(next < log.end_offset());
+    }
+}
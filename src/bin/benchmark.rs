@@ -0,0 +1,335 @@
+//! Benchmarks the archive pipeline on synthetic files.
+//!
+//! For every requested size a file of random bytes is written to a scratch
+//! directory, committed, reconstructed, health checked, and repaired after one
+//! data shard is deleted. Each stage is timed on its own and reported as a
+//! table, JSON or CSV, so runs can be kept and compared between changes.
+//!
+//! ```text
+//! cargo run --release --bin benchmark -- --sizes 5MB,100MB,2GB --format json --output bench.json
+//! ```
+
+use blockframe::{
+    chunker::{Chunker, ChunkerBuilder},
+    config::{Config, parse_size},
+    filestore::{FileStore, models::File},
+    memstats::peak_rss,
+};
+use clap::Parser;
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
+
+/// Times commit, reconstruct, health and repair on synthetic files
+#[derive(Parser)]
+#[command(name = "benchmark")]
+struct Args {
+    /// File sizes to benchmark, e.g. 5MB,100MB,2GB.
+    #[arg(short, long, value_delimiter = ',', default_value = "5MB,100MB")]
+    sizes: Vec<String>,
+
+    /// Tiers to commit every size as. Without this the tier follows the
+    /// file size, as for `blockframe commit`.
+    #[arg(short, long, value_delimiter = ',', value_parser = clap::value_parser!(u8).range(1..=3))]
+    tiers: Vec<u8>,
+
+    /// How many times to run each workload, every run is reported.
+    #[arg(short, long, default_value_t = 1)]
+    runs: usize,
+
+    /// Where to write the synthetic files and the archive. A scratch
+    /// directory is made inside it and removed afterwards.
+    #[arg(short, long)]
+    dir: Option<PathBuf>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, default_value = "table")]
+    format: Format,
+
+    /// Write the results to this file. The commit pipeline prints progress
+    /// to stdout, so JSON and CSV are best written to a file.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Config file to take encoding settings from, found as for `blockframe`
+    /// when not given.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!(
+                "unknown format {:?}, expected table, json or csv",
+                other
+            )),
+        }
+    }
+}
+
+/// One timed stage of one run.
+#[derive(Serialize)]
+struct Sample {
+    /// Size of the synthetic file in bytes.
+    size: u64,
+    /// Tier the file was committed as.
+    tier: u8,
+    run: usize,
+    stage: &'static str,
+    seconds: f64,
+    /// File bytes per second, in MB.
+    throughput_mb_s: f64,
+    /// Bytes the archive entry takes on disk, data and parity.
+    stored_bytes: u64,
+    /// Peak heap bytes of the commit, when built with `--features mem-stats`.
+    peak_heap_bytes: Option<u64>,
+    /// Peak resident set size of the benchmark process after the stage.
+    peak_rss_bytes: Option<u64>,
+}
+
+const CHUNK: usize = 1 << 20;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let sizes = args
+        .sizes
+        .iter()
+        .map(|size| parse_size(size).map(|bytes| bytes as u64))
+        .collect::<Result<Vec<_>, _>>()?;
+    if sizes.contains(&0) {
+        return Err("sizes must be above 0 bytes".into());
+    }
+    let tiers: Vec<Option<u8>> = if args.tiers.is_empty() {
+        vec![None]
+    } else {
+        args.tiers.iter().copied().map(Some).collect()
+    };
+
+    let config = Config::load_from(args.config.as_deref())?;
+    let scratch = match &args.dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            tempfile::Builder::new()
+                .prefix("blockframe-bench")
+                .tempdir_in(dir)?
+        }
+        None => tempfile::Builder::new()
+            .prefix("blockframe-bench")
+            .tempdir()?,
+    };
+    let chunker = ChunkerBuilder::from_config(&config)?
+        .archive_dir(scratch.path().join("archive"))
+        .build()?;
+    let store = FileStore::new(chunker.archive_dir())?;
+
+    let mut samples = Vec::new();
+    for &size in &sizes {
+        for &tier in &tiers {
+            for run in 1..=args.runs.max(1) {
+                let source = scratch.path().join(format!("bench_{}.bin", size));
+                synthesize(&source, size, run as u64)?;
+                let result = bench_run(&chunker, &store, &source, tier, run, &mut samples);
+                fs::remove_file(&source)?;
+                result.map_err(|e| {
+                    format!(
+                        "{} byte file as tier {}: {}",
+                        size,
+                        tier.map_or("auto".to_string(), |t| t.to_string()),
+                        e
+                    )
+                })?;
+            }
+        }
+    }
+
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut out = BufWriter::new(out);
+    match args.format {
+        Format::Table => write_table(&mut out, &samples)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &samples)?;
+            writeln!(out)?;
+        }
+        Format::Csv => write_csv(&mut out, &samples)?,
+    }
+    out.flush()?;
+    if let Some(path) = &args.output {
+        eprintln!("wrote {} results to {:?}", samples.len(), path);
+    }
+    Ok(())
+}
+
+/// Writes `size` random bytes to `path` without holding them in memory.
+fn synthesize(path: &Path, size: u64, seed: u64) -> io::Result<()> {
+    let mut rng = StdRng::seed_from_u64(size ^ seed);
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    let mut chunk = vec![0u8; CHUNK];
+    let mut left = size;
+    while left > 0 {
+        let len = left.min(CHUNK as u64) as usize;
+        rng.fill_bytes(&mut chunk[..len]);
+        out.write_all(&chunk[..len])?;
+        left -= len as u64;
+    }
+    out.flush()
+}
+
+/// Runs every stage on `source` once, then removes its archive entry.
+fn bench_run(
+    chunker: &Chunker,
+    store: &FileStore,
+    source: &Path,
+    tier: Option<u8>,
+    run: usize,
+    samples: &mut Vec<Sample>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let size = fs::metadata(source)?.len();
+    let name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("bad file name")?
+        .to_string();
+
+    let start = Instant::now();
+    let chunked = chunker.commit_as(source, tier)?;
+    let commit = start.elapsed().as_secs_f64();
+
+    store.invalidate();
+    let file = store.find(&name)?;
+    let tier = file.manifest.tier;
+    let stored_bytes = dir_size(&chunked.file_dir)?;
+    let commit_heap = chunked.memory.map(|memory| memory.peak_heap_bytes);
+    let mut record = |stage, seconds: f64| {
+        samples.push(Sample {
+            size,
+            tier,
+            run,
+            stage,
+            seconds,
+            throughput_mb_s: size as f64 / seconds.max(f64::EPSILON) / 1_000_000.0,
+            stored_bytes,
+            peak_heap_bytes: commit_heap.filter(|_| stage == "commit"),
+            peak_rss_bytes: peak_rss(),
+        })
+    };
+    record("commit", commit);
+
+    let start = Instant::now();
+    let hash = store.reconstruct_to(&file, io::sink())?;
+    record("reconstruct", start.elapsed().as_secs_f64());
+    if hash != file.file_data.hash {
+        return Err("reconstructed file doesn't match its hash".into());
+    }
+
+    let start = Instant::now();
+    store.health_check(&file)?;
+    record("health", start.elapsed().as_secs_f64());
+
+    let lost = first_data_shard(store, &file)?;
+    fs::remove_file(&lost)?;
+    let start = Instant::now();
+    store.repair(&file)?;
+    record("repair", start.elapsed().as_secs_f64());
+    if !lost.exists() {
+        return Err(format!("repair didn't restore {:?}", lost).into());
+    }
+
+    fs::remove_dir_all(&chunked.file_dir)?;
+    store.invalidate();
+    Ok(())
+}
+
+/// The shard deleted before timing a repair.
+fn first_data_shard(store: &FileStore, file: &File) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(match file.manifest.tier {
+        1 => store.get_data_path(file)?,
+        2 => store.get_segment_path(file, 0)?,
+        _ => store.get_block_segment_path(file, 0, 0)?,
+    })
+}
+
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(total)
+}
+
+fn write_table(out: &mut impl Write, samples: &[Sample]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>14} {:>4} {:>4} {:<12} {:>10} {:>10} {:>14} {:>14}",
+        "size", "tier", "run", "stage", "seconds", "MB/s", "stored", "peak heap"
+    )?;
+    for s in samples {
+        writeln!(
+            out,
+            "{:>14} {:>4} {:>4} {:<12} {:>10.3} {:>10.1} {:>14} {:>14}",
+            s.size,
+            s.tier,
+            s.run,
+            s.stage,
+            s.seconds,
+            s.throughput_mb_s,
+            s.stored_bytes,
+            optional(s.peak_heap_bytes, "-")
+        )?;
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut impl Write, samples: &[Sample]) -> io::Result<()> {
+    writeln!(
+        out,
+        "size,tier,run,stage,seconds,throughput_mb_s,stored_bytes,peak_heap_bytes,peak_rss_bytes"
+    )?;
+    for s in samples {
+        writeln!(
+            out,
+            "{},{},{},{},{:.6},{:.3},{},{},{}",
+            s.size,
+            s.tier,
+            s.run,
+            s.stage,
+            s.seconds,
+            s.throughput_mb_s,
+            s.stored_bytes,
+            optional(s.peak_heap_bytes, ""),
+            optional(s.peak_rss_bytes, "")
+        )?;
+    }
+    Ok(())
+}
+
+fn optional(value: Option<u64>, missing: &str) -> String {
+    value.map_or(missing.to_string(), |value| value.to_string())
+}
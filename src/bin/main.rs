@@ -1,21 +1,37 @@
 use blockframe::{
-    chunker::Chunker,
-    config::Config,
-    filestore::FileStore,
+    audit::{AuditLog, AuditOp, AuditQuery},
+    checksums::ChecksumList,
+    chunker::{self, ChunkerBuilder, OnExisting, PathFilter},
+    config::{Config, LogFormat, MountConfig, ServerConfig, parse_size},
+    daemon::{DaemonOptions, run_daemon},
+    erasure::{self, RsEngine},
+    filestore::{
+        FileStore, Integrity,
+        history::HistorySummary,
+        models::HealthStatus,
+        query::SearchOptions,
+        remote_download::{DownloadMode, RemoteDownloader},
+        remote_health::RemoteHealthChecker,
+        scrub::ScrubLimits,
+        versions::PrunePolicy,
+    },
     mount::{
-        BlockframeFS,
-        source::{LocalSource, RemoteSource, SegmentSource},
+        self,
+        options::MountOptions,
+        source::{LocalSource, MultiPeerSource, RemoteSource, SegmentSource},
     },
-    serve::run_server,
+    notify::Notifier,
+    serve::{ServeOptions, TlsPaths, bind::Bind, limits::RateLimits, run_server},
+    signing::{ManifestSigner, ManifestVerifier},
+    tags::{TagFilter, parse_tag},
+    utils::{SegmentPolicy, detect_available_memory},
 };
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-use tracing::{info, warn};
-use tracing_appender::{
-    non_blocking,
-    rolling::{RollingFileAppender, Rotation},
-};
-use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tracing::{error, info, warn};
 
 /// CLI for Accessing Blockframe functions
 #[derive(Parser)]
@@ -23,161 +39,1788 @@ use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt};
 #[command(about = "erasure-coded storage with transparent file access")]
 #[command(arg_required_else_help = true)]
 struct Cli {
+    /// Config file to use instead of the discovered one.
+    ///
+    /// Without this flag, BLOCKFRAME_CONFIG, ./config.toml and
+    /// ~/.config/blockframe/config.toml are tried in that order.
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Log level or filter, overriding `logging.level`.
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<String>,
+
+    /// Directory for log files, overriding `logging.directory`. An empty
+    /// string writes no log file.
+    #[arg(long, global = true, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+
+    /// `text` or `json`, overriding `logging.format`.
+    #[arg(long, global = true, value_name = "FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Log to the systemd journal instead of stdout.
+    #[arg(long, global = true)]
+    journald: bool,
+
     /// Commands parsers for subcommands
     #[command(subcommand)]
     command: Commands,
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Commit a specific file to the archive.
-    ///
-    /// This will break the file into chunks, apply erasure coding, and
-    /// save it to the archive directory.
-    Commit {
-        /// The source file to upload.
-        #[arg(short, long)]
-        file: PathBuf,
-    },
+#[derive(Subcommand)]
+enum Commands {
+    /// Commit files to the archive.
+    ///
+    /// This will break each file into chunks, apply erasure coding, and
+    /// save it to the archive directory. Several files are committed in parallel.
+    Commit {
+        /// The source files to upload. Takes several paths, and quoted glob
+        /// patterns such as "photos/*.jpg" are expanded. A directory is
+        /// committed file by file, with everything below it.
+        #[arg(short, long, num_args = 1.., required_unless_present = "url")]
+        file: Vec<PathBuf>,
+
+        /// Download this HTTP(S) URL and commit it, hashing and encoding as
+        /// it arrives and resuming if the connection drops.
+        #[arg(long, conflicts_with_all = ["file", "explode_archives"])]
+        url: Option<String>,
+
+        /// Archive the download under this name instead of the last part of
+        /// the URL.
+        #[arg(long, requires = "url")]
+        name: Option<String>,
+
+        /// How many files to encode at once.
+        #[arg(short, long, default_value_t = chunker::DEFAULT_JOBS)]
+        jobs: usize,
+
+        /// Force tier 1, 2 or 3 instead of choosing by file size.
+        #[arg(short, long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        tier: Option<u8>,
+
+        /// Pick the segment size from the file size alone, so the same file
+        /// gets the same layout on any host.
+        #[arg(long, conflicts_with = "segment_size")]
+        deterministic: bool,
+
+        /// Use this segment size for tier 2 and 3, e.g. 8MB.
+        #[arg(long)]
+        segment_size: Option<String>,
+
+        /// What to do when a file name is already archived: version (keep
+        /// both), skip, or overwrite. Defaults to archive.on_existing.
+        #[arg(long)]
+        on_existing: Option<OnExisting>,
+
+        /// Also index the files inside each .tar or .zip, so `extract
+        /// --member` can restore one without the rest.
+        #[arg(long)]
+        explode_archives: bool,
+
+        /// Print the tier, segments, blocks and parity each file would get,
+        /// without reading or writing anything.
+        #[arg(long, conflicts_with = "url")]
+        dry_run: bool,
+
+        /// Only commit files under a directory that match one of these
+        /// globs, e.g. '*.raw'. Can be given several times.
+        #[arg(long, conflicts_with = "url")]
+        include: Vec<String>,
+
+        /// Skip files under a directory that match this glob, e.g.
+        /// 'node_modules/**', on top of its .blockframeignore. Can be given
+        /// several times.
+        #[arg(long, conflicts_with = "url")]
+        exclude: Vec<String>,
+
+        /// Write the parity shards under this directory instead of next to
+        /// the data, e.g. on another disk. Defaults to archive.parity_dir.
+        #[arg(long)]
+        parity_dir: Option<PathBuf>,
+
+        /// Read the source files past the page cache, so a large commit
+        /// doesn't evict what other programs have cached. Also set by
+        /// io.direct_commit.
+        #[arg(long, conflicts_with = "url")]
+        direct_io: bool,
+
+        /// Tag the committed files, e.g. --tag project=apollo. Repeat for
+        /// several tags.
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_tag)]
+        tag: Vec<(String, String)>,
+
+        /// Check each file against a checksum list from sha256sum or b3sum
+        /// before committing it, and fail the files that don't match or
+        /// aren't listed.
+        #[arg(long, value_name = "FILE", conflicts_with = "url")]
+        verify_against: Option<PathBuf>,
+    },
+
+    /// Pack many small files into a single archive entry.
+    ///
+    /// The files are stored back to back in one entry with an index in its
+    /// manifest, instead of a directory and four shards each. Packed files are
+    /// restored with `extract` and appear as ordinary files in a mount.
+    Pack {
+        /// Files to pack. Takes several paths and quoted glob patterns, as commit does.
+        #[arg(short, long, num_args = 1.., required = true)]
+        file: Vec<PathBuf>,
+
+        /// Name of the pack entry, e.g. scans-2024.pack.
+        #[arg(short, long)]
+        name: String,
+
+        /// Force tier 1, 2 or 3 for the pack instead of choosing by total size.
+        #[arg(short, long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        tier: Option<u8>,
+    },
+
+    /// Restore a file from the archive, including files inside a pack.
+    ///
+    /// Damaged shards are recovered from parity on the way; the archive itself
+    /// is not changed.
+    Extract {
+        /// Name of the archived or packed file.
+        name: String,
+
+        /// Where to write the file (default: reconstructed/<name>).
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        /// Restore an earlier version, by number as shown by
+        /// `list --versions` or by a prefix of its hash.
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Restore only this file from inside a tar or zip committed with
+        /// --explode-archives, by its path in the container.
+        #[arg(long)]
+        member: Option<String>,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+    },
+
+    /// Restore a byte range of an archived file.
+    ///
+    /// Only the segments overlapping the range are read, and damaged ones are
+    /// recovered from parity, so a slice of a huge file comes back quickly.
+    Fetch {
+        /// Name of the archived or packed file.
+        name: String,
+
+        /// Bytes to fetch as START-END, e.g. 100MB-200MB. Either end may be left out.
+        #[arg(short, long)]
+        range: String,
+
+        /// Where to write the slice.
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+    },
+
+    /// Download a file from a remote server, verified on this side.
+    ///
+    /// Every shard is checked against the manifest as it arrives. Damaged
+    /// ones are downloaded again on their own or recovered here from the
+    /// server's parity, and the result is checked against the original hash.
+    Get {
+        /// Name of the archived file.
+        name: String,
+
+        /// Server to download from, e.g. http://host:8080.
+        #[arg(short, long)]
+        remote: String,
+
+        /// Where to write the file (default: reconstructed/<name>).
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        /// Download every segment on its own instead of the assembled file,
+        /// so nothing relies on the server's own recovery.
+        #[arg(long)]
+        segments: bool,
+    },
+
+    /// Check a working copy against its archived version.
+    ///
+    /// Each segment of the live file is hashed and compared with the manifest,
+    /// without reading the archive. With --restore, only the segments that
+    /// differ are rewritten from the archive.
+    CheckOriginal {
+        /// The live file to check.
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Archived name, when it differs from the file's name.
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Rewrite the damaged ranges from the archive.
+        #[arg(long)]
+        restore: bool,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+    },
+
+    /// List the archived files.
+    ///
+    /// Duplicates recorded as aliases at commit are shown with the file whose
+    /// shards they share.
+    List {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// List every version of this file instead, oldest first.
+        #[arg(long, value_name = "NAME")]
+        versions: Option<String>,
+
+        /// List the files indexed inside this tar or zip instead.
+        #[arg(long, value_name = "NAME", conflicts_with = "versions")]
+        members: Option<String>,
+
+        /// Only list names matching this glob, e.g. '*.mkv', or containing this
+        /// text. Case is ignored.
+        #[arg(long, value_name = "PATTERN", conflicts_with_all = ["versions", "members"])]
+        filter: Option<String>,
+
+        /// Match --filter with case.
+        #[arg(long, requires = "filter")]
+        case_sensitive: bool,
+
+        /// Only list files with this tag, as KEY for any value or KEY=VALUE.
+        /// Repeat to require several.
+        #[arg(long, value_name = "KEY[=VALUE]", conflicts_with_all = ["versions", "members"])]
+        tag: Vec<TagFilter>,
+    },
+
+    /// Re-encode an archived file as a different tier.
+    ///
+    /// The file is restored from its verified data shards and committed again,
+    /// replacing the old shards only once the new commit succeeds.
+    Retier {
+        /// Name of the archived file.
+        name: String,
+
+        /// Tier to re-encode into.
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        to: u8,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+    },
+
+    /// Convert archive entries from the pre-tier chunk layout.
+    ///
+    /// Legacy entries are skipped by every other command until they are migrated.
+    Migrate {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// List legacy entries without converting them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Reclaim space taken by files the archive no longer refers to.
+    ///
+    /// Removes stale shards left by re-committing a file under another tier,
+    /// aliases whose original is gone and abandoned work directories. Only
+    /// healthy entries are compacted, and each is checked again afterwards.
+    Compact {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// List what would be removed without removing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Show or use the archive-wide parity across files.
+    ///
+    /// With erasure.global_parity set, every commit is also XORed into a
+    /// parity volume shared with other files. Lists the volumes and any
+    /// member whose entry is gone; --recover rebuilds and commits those again.
+    GlobalParity {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Rebuild lost entries from their volume and commit them again.
+        #[arg(long, conflicts_with = "rebuild")]
+        recover: bool,
+
+        /// Recompute stale volumes from the entries still archived.
+        #[arg(long)]
+        rebuild: bool,
+    },
+
+    /// Remove old versions of files committed again with new content.
+    ///
+    /// The latest version of every name is always kept, and so is a version
+    /// other names are aliases of.
+    Prune {
+        /// Only prune versions of this file.
+        name: Option<String>,
+
+        /// Versions to keep per name, latest included.
+        #[arg(long)]
+        keep: Option<usize>,
+
+        /// Remove versions older than this, e.g. 90d.
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Option<std::time::Duration>,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// List what would be removed without removing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Remove a file and all its versions from the archive.
+    ///
+    /// The entries are moved to the archive's .trash directory, from which
+    /// `undelete` brings them back until archive.trash_retention days pass.
+    Rm {
+        /// Name of the file to remove.
+        name: String,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Delete right away instead of moving to the trash.
+        #[arg(long)]
+        permanent: bool,
+    },
+
+    /// Bring back a file removed with `rm`, or list the trash.
+    Undelete {
+        /// Name of the file to bring back, its latest removal is restored.
+        #[arg(required_unless_present = "list")]
+        name: Option<String>,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// List what is in the trash instead.
+        #[arg(long, conflicts_with = "name")]
+        list: bool,
+    },
+
+    /// Start an HTTP server to serve the archive.
+    ///
+    /// Allows users to browse and download files via a web browser.
+    Serve {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Port to bind the server to.
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// Address to listen on instead, `host:port` or `unix:/path/to.sock`.
+        /// Sockets passed in by systemd socket activation take precedence.
+        #[arg(long, value_name = "ADDR", conflicts_with = "port")]
+        bind: Option<Bind>,
+
+        /// Accept uploads with `PUT /api/files/<name>` and commit them into
+        /// the archive. Anyone who can reach the port can then write to it.
+        #[arg(long)]
+        allow_uploads: bool,
+
+        /// Also share the archive read-only over WebDAV at `/dav`, so it can
+        /// be mapped as a network drive without WinFsp or FUSE.
+        #[arg(long)]
+        webdav: bool,
+
+        /// Refuse every request that could change the archive, such as
+        /// uploads, repairs and scrub jobs, so it can be shared widely.
+        #[arg(long, conflicts_with = "allow_uploads")]
+        read_only: bool,
+    },
+
+    /// Mount the archive as a virtual filesystem.
+    ///
+    /// This mounts the archive as a Read-Only filesystem, allowing transparent access
+    /// to files without manually restoring them.
+    Mount {
+        /// The location to mount the filesystem (e.g., /tmp/blockframe).
+        #[arg(short, long)]
+        mountpoint: Option<PathBuf>,
+        /// Path to a local archive directory.
+        #[arg(short, long, conflicts_with = "remote")]
+        archive: Option<PathBuf>,
+        /// URL of a remote blockframe server.
+        #[arg(short, long, conflicts_with = "archive")]
+        remote: Option<String>,
+        /// URLs of several blockframe servers holding the same archive.
+        /// Segment reads are spread across them and verified against the manifest.
+        #[arg(long = "peer", conflicts_with_all = ["archive", "remote"])]
+        peers: Vec<String>,
+        /// How much reads are checked against the manifest: always,
+        /// on-corruption (trust the data, verify and recover only after a read
+        /// fails) or off (fastest, for trusted local archives). Defaults to
+        /// mount.integrity.
+        #[arg(long)]
+        integrity: Option<Integrity>,
+        /// Mount option, as `mount -o` takes them, e.g. allow_other or
+        /// fsname=photos. Repeat for several; added to mount.options.
+        #[arg(short = 'o', long = "mount-opt", value_name = "OPT")]
+        mount_opts: Vec<String>,
+        /// Windows: label shown in Explorer. Defaults to mount.volume.label.
+        #[arg(long)]
+        volume_label: Option<String>,
+        /// Windows: serial number, e.g. 1A2B-3C4D, or auto to derive it from
+        /// the archive's file names. Defaults to mount.volume.serial.
+        #[arg(long)]
+        volume_serial: Option<String>,
+        /// Windows: tell apart names differing only in case.
+        #[arg(long)]
+        case_sensitive: bool,
+        /// Windows: sector size in bytes, 512 to 4096. Defaults to
+        /// mount.volume.sector_size.
+        #[arg(long)]
+        sector_size: Option<u16>,
+    },
+
+    /// Check the health of all files and attempt repairs.
+    ///
+    /// Scans all file manifests and chunks. If chunks are missing but enough
+    /// parity chunks exist, the file will be repaired.
+    Health {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Check a remote server instead, e.g. http://host:8080.
+        /// Uses the hash endpoints so no segments are downloaded, and never repairs.
+        #[arg(short, long, conflicts_with = "archive")]
+        remote: Option<String>,
+
+        /// Never write to the archive, e.g. on read-only media. Repairs are
+        /// recovered in memory and the writes they would make are listed.
+        #[arg(long, conflicts_with = "remote")]
+        read_only: bool,
+
+        /// With --read-only, write recovered shards here instead, laid out as
+        /// in the archive.
+        #[arg(long, requires = "read_only")]
+        recover_to: Option<PathBuf>,
+
+        /// Write recovered shards here instead of over the originals, laid
+        /// out as in the archive. Move them in afterwards with `promote`.
+        #[arg(long, conflicts_with_all = ["remote", "read_only"])]
+        repair_dest: Option<PathBuf>,
+
+        /// Only check, and list the shards a repair would rebuild or rewrite.
+        #[arg(long, conflicts_with_all = ["remote", "read_only", "repair_dest"])]
+        dry_run: bool,
+
+        /// Also write what the check found to this file, JUnit XML for a .xml
+        /// name and SARIF for .sarif or .json. Can be given several times.
+        #[arg(long)]
+        report: Vec<PathBuf>,
+
+        /// Only check, and repair, this archived file.
+        #[arg(short, long, conflicts_with_all = ["remote", "report"])]
+        file: Option<String>,
+
+        /// With --file, only read this data segment and the parity protecting
+        /// it. Tier 3 segments are numbered across blocks.
+        #[arg(long, requires = "file")]
+        segment: Option<usize>,
+
+        /// With --file, only read this block of a tier 3 file.
+        #[arg(long, requires = "file", conflicts_with = "segment")]
+        block: Option<usize>,
+
+        /// Only check this many random data segments of each file, with the
+        /// parity protecting them, to screen a large archive quickly.
+        #[arg(long, value_name = "N", conflicts_with_all = ["remote", "file"])]
+        sample: Option<usize>,
+
+        /// Seed for --sample, to repeat an earlier run. Random by default and
+        /// printed either way.
+        #[arg(long, requires = "sample")]
+        seed: Option<u64>,
+
+        /// Read shards past the page cache. Also set by io.direct_scrub.
+        #[arg(long, conflicts_with = "remote")]
+        direct_io: bool,
+    },
+
+    /// Move shards repaired into a --repair-dest directory into the archive.
+    ///
+    /// Each shard is checked against the hash its manifest records first.
+    /// Anything that doesn't match, or isn't a shard of an archived file, is
+    /// left where it is.
+    Promote {
+        /// Directory the repairs were written to.
+        from: PathBuf,
+
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// List what would be promoted without moving anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Check and repair the archive in resumable passes.
+    ///
+    /// Files are checked in name order and progress is saved after each one, so
+    /// a run that is stopped, or runs out of --max-duration, is picked up by the
+    /// next run where it left off.
+    Scrub {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Stop after this long, e.g. 90m or 8h. The next run resumes.
+        #[arg(long, value_parser = parse_duration)]
+        max_duration: Option<std::time::Duration>,
+
+        /// Read at most this much per second on average, e.g. 200MB.
+        #[arg(long)]
+        max_rate: Option<String>,
+
+        /// Discard the saved progress and start a new pass.
+        #[arg(long)]
+        restart: bool,
+
+        /// Never write to the archive, see `health --read-only`.
+        #[arg(long)]
+        read_only: bool,
+
+        /// With --read-only, write recovered shards and progress here instead.
+        #[arg(long, requires = "read_only")]
+        recover_to: Option<PathBuf>,
+
+        /// Write recovered shards here instead of over the originals, see
+        /// `health --repair-dest`.
+        #[arg(long, conflicts_with = "read_only")]
+        repair_dest: Option<PathBuf>,
+
+        /// Read shards past the page cache, so a pass over the archive
+        /// doesn't evict what other programs have cached. Also set by
+        /// io.direct_scrub.
+        #[arg(long)]
+        direct_io: bool,
+    },
+
+    /// Run serve, scheduled scrubbing and the watch folder in one process.
+    ///
+    /// Intended to be run under systemd or as a Windows service. Writes a PID
+    /// file, reloads the `[daemon]` config section on SIGHUP and shuts down
+    /// cleanly on SIGTERM or Ctrl+C.
+    Daemon {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Port to bind the server to.
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// Address to listen on instead, `host:port` or `unix:/path/to.sock`.
+        /// Sockets passed in by systemd socket activation take precedence.
+        #[arg(long, value_name = "ADDR", conflicts_with = "port")]
+        bind: Option<Bind>,
+
+        /// Where to write the process id.
+        #[arg(long)]
+        pid_file: Option<PathBuf>,
+
+        /// Folder to poll for new files to commit.
+        #[arg(short, long)]
+        watch: Option<PathBuf>,
+    },
+
+    /// Summarise the archive, or with --system the machine it runs on.
+    ///
+    /// The system report shows which SIMD instructions Reed-Solomon coding
+    /// uses here and measures how fast it encodes.
+    Stats {
+        /// Directory where chunks are stored.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Report the CPU, memory and Reed-Solomon engine instead.
+        #[arg(long)]
+        system: bool,
+
+        /// Break the archived bytes down by content type and file size.
+        #[arg(long, conflicts_with = "system")]
+        by_type: bool,
+    },
+
+    /// Generate an Ed25519 key pair for signing manifests.
+    ///
+    /// The secret key is written to `--out` and the public key is printed so it
+    /// can be set as `signing.public_key` on the machines that verify.
+    Keygen {
+        /// Where to write the hex secret key.
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+
+    /// Query the archive's audit log of commits and repairs.
+    Audit {
+        /// Archive directory holding `audit.log`.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Only show one operation, e.g. `commit` or `repair`.
+        #[arg(long)]
+        op: Option<AuditOp>,
+
+        /// Only show entries for this file name.
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// Only show entries at or after this time (RFC 3339 or YYYY-MM-DD).
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// Show at most this many of the most recent entries.
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// Print raw JSON lines instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the recorded health checks of one file, to tell steady
+    /// deterioration from a one-off corruption event.
+    History {
+        /// Name of the archived file.
+        name: String,
+
+        /// Archive directory to read from.
+        #[arg(short, long)]
+        archive: Option<PathBuf>,
+
+        /// Show at most this many of the most recent checks.
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// Print raw JSON lines instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Writes a secret key readable only by the owner where the platform supports it.
+fn write_secret(path: &std::path::Path, secret: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, secret.as_bytes())
+}
+
+/// Parses `90`, `45s`, `30m`, `8h` or `2d`; a bare number is seconds.
+/// How long removed files stay in the trash.
+fn trash_retention(config: &Config) -> std::time::Duration {
+    std::time::Duration::from_secs(config.archive.trash_retention.saturating_mul(24 * 60 * 60))
+}
+
+fn parse_duration(value: &str) -> Result<std::time::Duration, String> {
+    let value = value.trim();
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((value, ""), |idx| value.split_at(idx));
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("expected a duration like 30m or 8h, got '{}'", value))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 24 * 60 * 60,
+        _ => return Err(format!("unknown duration unit '{}' in '{}'", unit, value)),
+    };
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|day| day.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| format!("expected RFC 3339 or YYYY-MM-DD, got '{}'", value))
+}
+
+/// Lists what a read-only store or a repair destination kept out of the archive.
+/// `--bind`, else `[server] bind` unless `--port` was given.
+fn choose_bind(
+    bind: Option<Bind>,
+    port: Option<u16>,
+    config: &ServerConfig,
+) -> Result<Option<Bind>, String> {
+    match (bind, port) {
+        (Some(bind), _) => Ok(Some(bind)),
+        (None, Some(_)) => Ok(None),
+        (None, None) => Bind::from_config(config),
+    }
+}
+
+fn print_file_health(name: &str, report: &blockframe::filestore::models::HealthReport) {
+    println!(
+        "{}: {}, {}",
+        name,
+        format!("{:?}", report.status).to_lowercase(),
+        report.details
+    );
+    for shard in report.missing_data.iter().chain(&report.missing_parity) {
+        println!("  missing {}", shard);
+    }
+    for shard in &report.corrupt_segments {
+        println!("  corrupt {}", shard);
+    }
+}
+
+fn report_withheld_writes(store: &FileStore) {
+    let withheld = store.withheld_writes();
+    if !withheld.is_empty() {
+        let mode = if store.is_read_only() {
+            "read-only"
+        } else {
+            "repair-dest"
+        };
+        println!(
+            "{}: {} writes withheld from the archive",
+            mode,
+            withheld.len()
+        );
+        for path in withheld {
+            println!("  {}", path.display());
+        }
+    }
+}
+
+fn check_report_paths(paths: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    for path in paths {
+        blockframe::filestore::export::ReportFormat::for_path(path)?;
+    }
+    Ok(())
+}
+
+fn write_health_reports(
+    batch: &blockframe::filestore::models::BatchHealthReport,
+    archive: &str,
+    paths: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for path in paths {
+        blockframe::filestore::export::write_report(batch, archive, path)?;
+        info!(report = %path.display(), "wrote health report");
+    }
+    Ok(())
+}
+
+/// Exit codes of `health`, `scrub` and `check-original`, so scripts can branch
+/// on the outcome without parsing output. Any other failure exits with 1.
+#[derive(Clone, Copy)]
+enum Exit {
+    Healthy = 0,
+    Degraded = 10,
+    Recoverable = 20,
+    Unrecoverable = 30,
+    /// A file that could have been repaired, or a working copy that could
+    /// have been restored, wasn't.
+    RepairFailed = 40,
+}
+
+impl Exit {
+    fn code(self) -> ExitCode {
+        ExitCode::from(self as u8)
+    }
+}
+
+impl From<HealthStatus> for Exit {
+    fn from(status: HealthStatus) -> Self {
+        match status {
+            HealthStatus::Healthy => Exit::Healthy,
+            HealthStatus::Degraded => Exit::Degraded,
+            HealthStatus::Recoverable => Exit::Recoverable,
+            HealthStatus::Unrecoverable => Exit::Unrecoverable,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    // Load configuration file, falling back to defaults when none is found
+    let config = Config::load_from(cli.config.as_deref())
+        .map_err(|e| format!("Failed to load config: {}", e))?;
+    let mut logging = config.logging.clone();
+    if let Some(level) = cli.log_level.clone() {
+        logging.level = level;
+    }
+    if let Some(dir) = cli.log_dir.clone() {
+        logging.directory = dir;
+    }
+    if let Some(format) = cli.log_format {
+        logging.format = format;
+    }
+    logging.journald |= cli.journald;
+    // held until main returns, so the last lines, such as serve's shutdown
+    // summary, are flushed before the process exits
+    let _log_guard = blockframe::logging::init(&logging)?;
+    match &config.source {
+        Some(path) => info!("CONFIG | loaded {:?}", path),
+        None => info!("CONFIG | no config file found, using defaults"),
+    }
+
+    // Warn if both remote and archive are configured (could be confusing)
+    if !config.mount.default_remote.is_empty() {
+        warn!(
+            "Config has default_remote set to: {}",
+            config.mount.default_remote
+        );
+        warn!("The 'mount' command will connect to the remote server by default.");
+        warn!("Use --archive flag to override and mount local archive instead.");
+    }
+
+    erasure::set_engine(config.erasure.engine);
+    let builder = ChunkerBuilder::from_config(&config)?;
+    let tls = TlsPaths::from_config(&config.server)?;
+    let limits = RateLimits::from_config(&config.server)?;
+    let verifier = ManifestVerifier::from_config(&config.signing)?;
+
+    let mut exit = Exit::Healthy;
+    let outcome = match cli.command {
+        Commands::Commit {
+            file,
+            url,
+            name,
+            tier,
+            jobs,
+            deterministic,
+            segment_size,
+            on_existing,
+            explode_archives,
+            dry_run,
+            include,
+            exclude,
+            parity_dir,
+            direct_io,
+            tag,
+            verify_against,
+        } => {
+            let mut builder = builder
+                .explode_archives(explode_archives)
+                .tags(tag.into_iter().collect());
+            if direct_io {
+                builder = builder.direct_io(true);
+            }
+            if let Some(on_existing) = on_existing {
+                builder = builder.on_existing(on_existing);
+            }
+            if let Some(parity_dir) = parity_dir {
+                builder = builder.parity_dir(parity_dir);
+            }
+            if let Some(list) = verify_against {
+                builder = builder.verify_against(ChecksumList::from_file(&list)?);
+            }
+            if deterministic {
+                builder = builder.segment_policy(SegmentPolicy::Deterministic);
+            } else if let Some(size) = segment_size {
+                builder = builder.segment_policy(size.parse()?);
+            }
+            let chunker = builder.build()?;
+            if let Some(url) = url {
+                let chunked = chunker.commit_url(&url, name.as_deref(), tier)?;
+                println!(
+                    "committed {} ({} bytes) as {}",
+                    chunked.file_name, chunked.file_size, chunked.file_hash
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            let filter = PathFilter::new(&include, &exclude)?;
+            let paths = chunker::expand_paths_with(&file, &filter)?;
+            if dry_run {
+                let (mut data, mut parity) = (0, 0);
+                for path in &paths {
+                    let plan = chunker.plan(path, tier)?;
+                    let layout = match plan.tier {
+                        1 => "one shard".to_string(),
+                        2 => format!("{} segments of {} bytes", plan.segments, plan.segment_size),
+                        _ => format!(
+                            "{} segments of {} bytes in {} blocks",
+                            plan.segments, plan.segment_size, plan.blocks
+                        ),
+                    };
+                    println!(
+                        "{}: {} bytes, tier {}, {}, {} parity shards ({} bytes, {:.0}% overhead)",
+                        plan.file_name,
+                        plan.file_size,
+                        plan.tier,
+                        layout,
+                        plan.parity_shards,
+                        plan.parity_bytes,
+                        plan.overhead() * 100.0
+                    );
+                    data += plan.data_bytes;
+                    parity += plan.parity_bytes;
+                }
+                println!(
+                    "would commit {} files: {} bytes of data and {} of parity, nothing written",
+                    paths.len(),
+                    data,
+                    parity
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            info!(
+                "COMMIT | committing {} files, {} at a time",
+                paths.len(),
+                jobs
+            );
+            let summary = chunker.commit_many(&paths, tier, jobs)?;
+
+            println!(
+                "committed {} files ({} bytes) in {:.1}s, {} failed",
+                summary.committed.len(),
+                summary.bytes,
+                summary.elapsed.as_secs_f64(),
+                summary.failed.len()
+            );
+            for chunked in &summary.committed {
+                if let Some(memory) = &chunked.memory {
+                    println!(
+                        "  {}: peak heap {} bytes, {} allocations, peak RSS {}",
+                        chunked.file_name,
+                        memory.peak_heap_bytes,
+                        memory.allocations,
+                        memory
+                            .peak_rss_bytes
+                            .map_or("unknown".to_string(), |rss| format!("{} bytes", rss))
+                    );
+                }
+            }
+            for (path, reason) in &summary.failed {
+                println!("  {}: {}", path.display(), reason);
+            }
+            if !summary.failed.is_empty() {
+                return Err(format!(
+                    "{} of {} files failed to commit",
+                    summary.failed.len(),
+                    paths.len()
+                )
+                .into());
+            }
+            Ok(())
+        }
+
+        Commands::Pack { file, name, tier } => {
+            let paths = chunker::expand_paths(&file)?;
+            let chunked = builder.build()?.commit_pack(&paths, &name, tier)?;
+            println!(
+                "packed {} files ({} bytes) into {}",
+                paths.len(),
+                chunked.file_size,
+                chunked.file_dir.display()
+            );
+            Ok(())
+        }
+
+        Commands::Extract {
+            name,
+            out,
+            version,
+            member,
+            archive,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?.with_verifier(verifier);
+            let default_name = match &member {
+                Some(member) => Path::new(member)
+                    .file_name()
+                    .ok_or_else(|| format!("{} is not a file name", member))?,
+                None => name.as_ref(),
+            };
+            let out = out.unwrap_or_else(|| PathBuf::from("reconstructed").join(default_name));
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let writer = std::io::BufWriter::new(std::fs::File::create(&out)?);
+
+            let found = match &version {
+                Some(version) => store.find_version(&name, version),
+                None => store.find(&name),
+            };
+            let restored = match (found, &member) {
+                (Ok(file), Some(member)) => store.verify_manifest(&file).and_then(|_| {
+                    let indexed = store.find_in(&file, member)?;
+                    info!("EXTRACT | {} is a member of {}", member, file.file_name);
+                    store.restore_in(&file, indexed, writer)
+                }),
+                (Err(e), Some(_)) => Err(e),
+                (Ok(file), None) => store
+                    .verify_manifest(&file)
+                    .and_then(|_| store.reconstruct_to(&file, writer)),
+                (Err(e), None) if version.is_some() => Err(e),
+                (Err(_), None) => store.find_member(&name).and_then(|(pack, member)| {
+                    info!("EXTRACT | {} is packed in {}", name, pack.file_name);
+                    store.verify_manifest(&pack)?;
+                    store.restore_member(&pack, &member, writer)
+                }),
+            };
+            match restored {
+                Ok(hash) => {
+                    let restored = member.as_deref().unwrap_or(&name);
+                    info!("EXTRACT | {} restored to {:?} ({})", restored, out, hash);
+                    Ok(())
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_file(&out);
+                    Err(e)
+                }
+            }
+        }
+
+        Commands::Fetch {
+            name,
+            range,
+            out,
+            archive,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?.with_verifier(verifier);
+
+            // a packed file is a range of its pack
+            let (file, base, size) = match store.find(&name) {
+                Ok(file) => {
+                    let size = file.manifest.size.max(0) as u64;
+                    (file, 0, size)
+                }
+                Err(_) => {
+                    let (pack, member) = store.find_member(&name)?;
+                    info!("FETCH | {} is packed in {}", name, pack.file_name);
+                    (pack, member.offset, member.size)
+                }
+            };
+            store.verify_manifest(&file)?;
+            let range = blockframe::utils::parse_range(&range, size)?;
+
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let writer = std::io::BufWriter::new(std::fs::File::create(&out)?);
+            match store.read_range(&file, base + range.start..base + range.end, writer) {
+                Ok(written) => {
+                    println!(
+                        "wrote bytes {}..{} of {} ({} bytes) to {}",
+                        range.start,
+                        range.end,
+                        name,
+                        written,
+                        out.display()
+                    );
+                    Ok(())
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_file(&out);
+                    Err(e)
+                }
+            }
+        }
+
+        Commands::Get {
+            name,
+            remote,
+            out,
+            segments,
+        } => {
+            let downloader = RemoteDownloader::new(
+                RemoteSource::new(remote)
+                    .with_verifier(verifier)
+                    .with_config(&config.remote),
+            );
+            let mode = if segments {
+                DownloadMode::Segments
+            } else {
+                DownloadMode::Assembled
+            };
+            let out = out.unwrap_or_else(|| PathBuf::from("reconstructed").join(&name));
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let writer = std::io::BufWriter::new(std::fs::File::create(&out)?);
+            match downloader.download_to(&name, mode, writer) {
+                Ok(report) => {
+                    info!("GET | {} downloaded to {:?} ({})", name, out, report.hash);
+                    println!(
+                        "wrote {} bytes to {}, {} of {} shards fetched again, {} recovered from parity",
+                        report.bytes,
+                        out.display(),
+                        report.refetched,
+                        report.shards,
+                        report.recovered.len()
+                    );
+                    Ok(())
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_file(&out);
+                    Err(e)
+                }
+            }
+        }
+
+        Commands::CheckOriginal {
+            file,
+            name,
+            restore,
+            archive,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?.with_verifier(verifier);
+            let name = match name {
+                Some(name) => name,
+                None => file
+                    .file_name()
+                    .ok_or("--file has no file name")?
+                    .to_string_lossy()
+                    .to_string(),
+            };
+            let archived = store.find(&name)?;
+            store.verify_manifest(&archived)?;
+
+            let report = store.check_original(&archived, &file)?;
+            if report.is_intact() {
+                println!(
+                    "{} matches the archive ({} segments)",
+                    file.display(),
+                    report.shards
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            println!(
+                "{} differs from the archive: {} of {} segments ({} bytes), size {} vs {} archived",
+                file.display(),
+                report.damaged.len(),
+                report.shards,
+                report.damaged_bytes(),
+                report.live_size,
+                report.archived_size
+            );
+            for range in &report.damaged {
+                println!("  bytes {}..{}", range.start, range.end);
+            }
+            if !restore {
+                println!("working copy differs from the archive, rerun with --restore");
+                return Ok(Exit::Recoverable.code());
+            }
+            let written = match store.restore_original(&archived, &file, &report) {
+                Ok(written) => written,
+                Err(e) => {
+                    error!("CHECK | restoring {} failed: {}", file.display(), e);
+                    return Ok(Exit::RepairFailed.code());
+                }
+            };
+            println!(
+                "restored {} bytes, {} now matches the archive",
+                written,
+                file.display()
+            );
+            Ok(())
+        }
+
+        Commands::List {
+            archive,
+            members: Some(name),
+            ..
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            let file = store.find(&name)?;
+            if file.manifest.members.is_empty() {
+                return Err(format!(
+                    "{} has no member index, commit it with --explode-archives",
+                    name
+                )
+                .into());
+            }
+            for member in &file.manifest.members {
+                match member.inflated_size {
+                    Some(size) => println!(
+                        "{}  {} bytes  (deflated to {})",
+                        member.name, size, member.size
+                    ),
+                    None => println!("{}  {} bytes", member.name, member.size),
+                }
+            }
+            Ok(())
+        }
+
+        Commands::List {
+            archive,
+            versions: Some(name),
+            ..
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            let versions = store.versions(&name)?;
+            if versions.is_empty() {
+                return Err(format!("File '{}' not found", name).into());
+            }
+            for (idx, file) in versions.iter().enumerate() {
+                println!(
+                    "{}  {}  {} bytes  {}",
+                    idx + 1,
+                    &file.file_data.hash[..10],
+                    file.manifest.size,
+                    file.committed
+                        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_else(|| "unknown time".to_string())
+                );
+            }
+            Ok(())
+        }
+
+        Commands::List {
+            archive,
+            versions: None,
+            filter,
+            case_sensitive,
+            tag,
+            ..
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            let catalog = store.catalog()?;
+            // the latest version of every name
+            let files = if filter.is_some() || !tag.is_empty() {
+                let options = SearchOptions {
+                    case_sensitive,
+                    tags: tag,
+                    ..Default::default()
+                };
+                store.search(filter.as_deref().unwrap_or("*"), options)?
+            } else {
+                let mut files: Vec<_> = catalog
+                    .keys()
+                    .filter_map(|name| store.find(name).ok())
+                    .collect();
+                files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+                files
+            };
+            for file in &files {
+                let count = catalog.get(&file.file_name).map_or(1, Vec::len);
+                let mut versions = if count > 1 {
+                    format!("  ({} versions)", count)
+                } else {
+                    String::new()
+                };
+                for (key, value) in &file.manifest.tags {
+                    versions.push_str(&format!("  {}={}", key, value));
+                }
+                match &file.alias_of {
+                    Some(original) => {
+                        println!(
+                            "{}  {} bytes  -> {}{}",
+                            file.file_name, file.manifest.size, original, versions
+                        )
+                    }
+                    None => println!(
+                        "{}  {} bytes  tier {}{}",
+                        file.file_name, file.manifest.size, file.manifest.tier, versions
+                    ),
+                }
+            }
+            Ok(())
+        }
+
+        Commands::Stats {
+            archive: _,
+            system: true,
+            by_type: _,
+        } => {
+            println!(
+                "platform:      {} {}",
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            );
+            println!(
+                "cpu threads:   {}",
+                std::thread::available_parallelism().map_or(1, |n| n.get())
+            );
+            match detect_available_memory() {
+                Ok(bytes) => println!("free memory:   {} bytes", bytes),
+                Err(e) => println!("free memory:   unknown ({})", e),
+            }
+            println!("rs simd:       {}", erasure::simd_path());
+            match erasure::engine() {
+                RsEngine::Auto => println!("rs engine:     {}", erasure::active_path()),
+                RsEngine::NoSimd => {
+                    println!("rs engine:     none (nosimd forced by erasure.engine)")
+                }
+            }
+            // the shapes commits use: a full tier 3 block and a tier 1/2 segment
+            let block = erasure::encode_throughput(30, 3, 1 << 20, 8)?;
+            let segment = erasure::encode_throughput(1, 3, 8 << 20, 8)?;
+            println!("rs(30,3) encode: {:.0} MB/s", block);
+            println!("rs(1,3) encode:  {:.0} MB/s", segment);
+            Ok(())
+        }
+
+        Commands::Stats {
+            archive,
+            system: false,
+            by_type: true,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let report = FileStore::new(&archive_path)?.type_report()?;
+            let total = report
+                .types
+                .iter()
+                .map(|group| group.bytes)
+                .sum::<u64>()
+                .max(1);
+            println!(
+                "{:<32} {:>8} {:>16} {:>6}",
+                "type", "files", "bytes", "share"
+            );
+            for group in &report.types {
+                println!(
+                    "{:<32} {:>8} {:>16} {:>5.1}%",
+                    group.kind,
+                    group.files,
+                    group.bytes,
+                    group.bytes as f64 * 100.0 / total as f64
+                );
+            }
+            println!();
+            println!(
+                "{:<32} {:>8} {:>16} {:>6}",
+                "size", "files", "bytes", "share"
+            );
+            for bucket in &report.sizes {
+                println!(
+                    "{:<32} {:>8} {:>16} {:>5.1}%",
+                    bucket.kind,
+                    bucket.files,
+                    bucket.bytes,
+                    bucket.bytes as f64 * 100.0 / total as f64
+                );
+            }
+            Ok(())
+        }
 
-    /// Start an HTTP server to serve the archive.
-    ///
-    /// Allows users to browse and download files via a web browser.
-    Serve {
-        /// Directory where chunks are stored.
-        #[arg(short, long)]
-        archive: Option<PathBuf>,
+        Commands::Stats {
+            archive,
+            system: false,
+            by_type: false,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            let files = store.get_all()?;
+            let names = store.catalog()?.len();
+            let aliases = files.iter().filter(|file| file.alias_of.is_some()).count();
+            let mut tiers = [0usize; 3];
+            let mut bytes = 0u64;
+            for file in files.iter().filter(|file| file.alias_of.is_none()) {
+                if let Some(count) = (file.manifest.tier as usize)
+                    .checked_sub(1)
+                    .and_then(|idx| tiers.get_mut(idx))
+                {
+                    *count += 1;
+                }
+                bytes += file.manifest.size.max(0) as u64;
+            }
+            println!("archive:   {}", archive_path.display());
+            println!(
+                "files:     {} names, {} entries, {} of them aliases",
+                names,
+                files.len(),
+                aliases
+            );
+            println!(
+                "tiers:     {} tier 1, {} tier 2, {} tier 3",
+                tiers[0], tiers[1], tiers[2]
+            );
+            println!("data:      {} bytes", bytes);
+            Ok(())
+        }
 
-        /// Port to bind the server to.
-        #[arg(short, long)]
-        port: Option<u16>,
-    },
+        Commands::Retier { name, to, archive } => {
+            let mut builder = builder;
+            if let Some(archive) = archive {
+                builder = builder.archive_dir(archive);
+            }
+            let chunker = builder.build()?;
+            let store = FileStore::new(chunker.archive_dir())?.with_verifier(verifier);
+            let file = store.find(&name)?;
+            store.verify_manifest(&file)?;
+            store.retier(&file, &chunker, to)?;
+            Ok(())
+        }
 
-    /// Mount the archive as a virtual filesystem.
-    ///
-    /// This mounts the archive as a Read-Only filesystem, allowing transparent access
-    /// to files without manually restoring them.
-    Mount {
-        /// The location to mount the filesystem (e.g., /tmp/blockframe).
-        #[arg(short, long)]
-        mountpoint: Option<PathBuf>,
-        /// Path to a local archive directory.
-        #[arg(short, long, conflicts_with = "remote")]
-        archive: Option<PathBuf>,
-        /// URL of a remote blockframe server.
-        #[arg(short, long, conflicts_with = "archive")]
-        remote: Option<String>,
-    },
+        Commands::Migrate { archive, dry_run } => {
+            let mut builder = builder;
+            if let Some(archive) = archive {
+                builder = builder.archive_dir(archive);
+            }
+            let chunker = builder.build()?;
+            let store = FileStore::new(chunker.archive_dir())?;
+            let entries = store.legacy_entries()?;
+            info!("MIGRATE | found {} legacy entries", entries.len());
+            let mut failed = 0;
+            for dir in &entries {
+                if dry_run {
+                    println!("{}", dir.display());
+                    continue;
+                }
+                if let Err(e) = store.migrate_legacy(dir, &chunker) {
+                    error!("MIGRATE | {:?} failed: {}", dir, e);
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                return Err(
+                    format!("{} of {} entries failed to migrate", failed, entries.len()).into(),
+                );
+            }
+            Ok(())
+        }
 
-    /// Check the health of all files and attempt repairs.
-    ///
-    /// Scans all file manifests and chunks. If chunks are missing but enough
-    /// parity chunks exist, the file will be repaired.
-    Health {
-        /// Directory where chunks are stored.
-        #[arg(short, long)]
-        archive: Option<PathBuf>,
-    },
-}
+        Commands::GlobalParity {
+            archive,
+            recover,
+            rebuild,
+        } => {
+            let mut builder = builder;
+            if let Some(archive) = archive {
+                builder = builder.archive_dir(archive);
+            }
+            let chunker = builder.build()?;
+            let store = FileStore::new(chunker.archive_dir())?.with_verifier(verifier);
+            if rebuild {
+                for member in store.rebuild_global_parity()? {
+                    println!("dropped {}, no longer archived", member.name);
+                }
+            }
+            if recover {
+                for chunked in store.recover_from_global_parity(&chunker)? {
+                    println!(
+                        "recovered {} ({} bytes)",
+                        chunked.file_name, chunked.file_size
+                    );
+                }
+            }
+            let report = store.global_parity_status()?;
+            for (volume, member) in &report.missing {
+                println!("volume {}: {} is missing", volume, member.name);
+            }
+            for volume in &report.stale {
+                println!("volume {}: stale, run with --rebuild", volume);
+            }
+            for volume in &report.unrecoverable {
+                println!(
+                    "volume {}: more than one member lost, can't rebuild",
+                    volume
+                );
+            }
+            println!(
+                "{} volumes covering {} files, {} bytes of parity",
+                report.volumes, report.members, report.bytes
+            );
+            if !report.unrecoverable.is_empty() {
+                exit = Exit::Unrecoverable;
+            } else if !report.missing.is_empty() {
+                exit = Exit::Recoverable;
+            } else if !report.stale.is_empty() {
+                exit = Exit::Degraded;
+            }
+            Ok(())
+        }
 
-/// Logging initiser for listing to the logger events and rolling logging
-pub fn init_logging() {
-    // file_appender a RollingFileAppender object
-    // file_appender is used to write to the log file, however the log file will roll over to another log file
-    // when the given rotation option. Which is configured to be daily.
-    let file_appender = RollingFileAppender::new(Rotation::DAILY, "./logs", "blockframe.log");
-
-    // file_writer and _file_guard are the non-blocking feature function
-    // essentially the logging function is moved from the main thread to a dedicated background worker thread
-    // this is done to ensure logging does not interfere with main performance
-    let (file_writer, _file_guard) = non_blocking(file_appender);
-
-    // std_writer and _stdout_guard are also background worker threads
-    let (stdout_writer, _stdout_guard) = non_blocking(std::io::stdout());
-
-    // subscriber is responsible for routing where tracing events are processed
-    // it is the global sink that collects tracing events, filters them, formats them and writes them to stdout and a log file
-    let subscriber = Registry::default() // the core event router. It keeps track of spans, thier relationships and forwards events to layers
-        .with(
-            // EnvFilter is used to read a log filter string from the environment
-            // if RUST_LOG isnt set in the env then those rules will be used
-            // otherwise the fallback flags will be the default
-            // info log emits such as warn, info and error
-            // debug is enabled if its flagged in the crate
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new("info, blockframe=debug")),
-        )
-        .with(
-            // fmt::layer() is used to format the log
-            fmt::layer()
-                // sends the formatted log lines to stdout, the non-blocking stdout_writer
-                .with_writer(stdout_writer)
-                // the event's target is included in the output, e.g. the crate or module path like blockframe::mount::source
-                .with_target(true)
-                // includes the thread ID that emitted the log
-                // useful for the API calls as they are asynchronous
-                .with_thread_ids(true),
-        )
-        .with(
-            // the last format layer
-            fmt::layer()
-                // this time we use file_writter to write the logs to the log file instead of stdout
-                .with_writer(file_writer)
-                // ansi disabled color escape codes to keep logs simple plain text, no color pollution
-                .with_ansi(false),
-        );
-    // tracing::subscriber installs the subscriber globally.
-    // Ever tracing macro anywhere on the program sends events through the subscriber
-    // called twice will cause a fail
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
-
-    // TANGENT_EXPLAINATION: This is a very cool trick.
-    // Box<t> is a "smart pointer", its stored on the heap, and when it goes out of scope it is 'dropped'
-    // wrapping our non-blocking guards in a smart-pointer seems counter-intuitive
-    // however we pass our smart-pointer (Box) into a `Box::leak` which returns `&'static mut T`
-    // Box::leak consumes our smart-pointer and forgets how to free it, and hands back a reference that lives for 'static
-    // The reason we dont assign it to a variable through explicit let is done so that,
-    // we hand the _file_guard and _stdout_guard's ownership into the heap
-    // then we deliberately abandon the ability to free it. The side effect is the leak itself not the reference.
-    // What this achives is it keeps the guards and writters alive and ensures the logging threads arent randomly shut down and the logs stop working silently.
-    Box::leak(Box::new(_file_guard));
-    Box::leak(Box::new(_stdout_guard));
-}
+        Commands::Compact { archive, dry_run } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?.with_verifier(verifier);
+            let report = store.compact(dry_run)?;
+            for path in &report.removed {
+                println!("{}", path.display());
+            }
+            for (name, reason) in &report.skipped {
+                println!("skipped {}: {}", name, reason);
+            }
+            println!(
+                "{} {} paths, {} bytes",
+                if dry_run { "would remove" } else { "removed" },
+                report.removed.len(),
+                report.bytes
+            );
+            Ok(())
+        }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    init_logging();
+        Commands::Prune {
+            name,
+            keep,
+            older_than,
+            archive,
+            dry_run,
+        } => {
+            if keep.is_none() && older_than.is_none() {
+                return Err("nothing to prune by, give --keep and/or --older-than".into());
+            }
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            let policy = PrunePolicy {
+                keep_last: keep,
+                older_than,
+            };
+            let report = store.prune_versions(name.as_deref(), policy, dry_run)?;
+            for file in &report.removed {
+                println!("{}  {}", file.file_name, &file.file_data.hash[..10]);
+            }
+            for (file, reason) in &report.kept {
+                println!(
+                    "kept {}  {}: {}",
+                    file.file_name,
+                    &file.file_data.hash[..10],
+                    reason
+                );
+            }
+            println!(
+                "{} {} versions",
+                if dry_run { "would remove" } else { "removed" },
+                report.removed.len()
+            );
+            Ok(())
+        }
 
-    // Load configuration file
-    let config = Config::load().map_err(|e| {
-        format!("Failed to load config.toml: {}. Make sure config.toml exists in the current directory.", e)
-    })?;
+        Commands::Rm {
+            name,
+            archive,
+            permanent,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            let retention = trash_retention(&config);
+            let permanent = permanent || retention.is_zero();
+            let removed = if permanent {
+                store.remove_permanently(&name)?
+            } else {
+                store.trash(&name)?
+            };
+            for file in &removed {
+                println!("{}  {}", file.file_name, &file.file_data.hash[..10]);
+            }
+            println!(
+                "removed {} versions{}",
+                removed.len(),
+                if permanent { "" } else { " to the trash" }
+            );
+            store.purge_trash(retention)?;
+            Ok(())
+        }
 
-    // Warn if both remote and archive are configured (could be confusing)
-    if !config.mount.default_remote.is_empty() {
-        warn!(
-            "Config has default_remote set to: {}",
-            config.mount.default_remote
-        );
-        warn!("The 'mount' command will connect to the remote server by default.");
-        warn!("Use --archive flag to override and mount local archive instead.");
-    }
+        Commands::Undelete {
+            name,
+            archive,
+            list,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            if list {
+                for entry in store.list_trash()? {
+                    println!(
+                        "{}  {}  removed {}",
+                        entry.file_name,
+                        &entry.hash[..entry.hash.len().min(10)],
+                        entry.removed.format("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+            } else {
+                let name = name.ok_or("give a name to undelete, or --list")?;
+                for dir in store.undelete(&name)? {
+                    println!("restored {}", dir.display());
+                }
+                if config.erasure.global_parity > 0 {
+                    store.invalidate();
+                    for file in store.versions(&name)? {
+                        store.add_to_global_parity(&file, config.erasure.global_parity)?;
+                    }
+                }
+            }
+            Ok(())
+        }
 
-    let chunker = Chunker::new()?;
+        Commands::Health {
+            remote: Some(url),
+            report,
+            ..
+        } => {
+            check_report_paths(&report)?;
+            let checker = RemoteHealthChecker::new(
+                RemoteSource::new(url.clone())
+                    .with_verifier(verifier)
+                    .with_config(&config.remote),
+            );
+            let batch_report = checker.batch_health_check()?;
+            write_health_reports(&batch_report, &url, &report)?;
+            info!(
+                total_files = batch_report.total_files,
+                healthy = batch_report.healthy,
+                degraded = batch_report.degraded,
+                recoverable = batch_report.recoverable,
+                unrecoverable = batch_report.unrecoverable
+            );
+            for (filename, report) in &batch_report.reports {
+                if report.status != HealthStatus::Healthy {
+                    warn!(
+                        "REMOTE HEALTH | {}: {:?} ({})",
+                        filename, report.status, report.details
+                    );
+                }
+            }
+            exit = batch_report.worst_status().into();
+            Ok(())
+        }
 
-    match cli.command {
-        Commands::Commit { file } => {
-            // use existing Chunker
-            info!(file = ?file, "starting commit");
-            let _ = chunker.commit(&file)?;
+        Commands::Health {
+            archive,
+            read_only,
+            recover_to,
+            repair_dest,
+            dry_run,
+            file: Some(name),
+            segment,
+            block,
+            direct_io,
+            ..
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let mut store = FileStore::new(&archive_path)?
+                .with_verifier(verifier)
+                .with_direct_io(direct_io || config.io.direct_scrub);
+            if read_only {
+                store = store.read_only(recover_to);
+            } else if let Some(dest) = repair_dest {
+                store = store.with_repair_dest(dest);
+            }
+            let file = store.find(&name)?;
+            let check = |store: &FileStore| match (segment, block) {
+                (Some(idx), _) => store.health_check_segment(&file, idx),
+                (_, Some(block_id)) => store.health_check_block(&file, block_id),
+                _ => store.health_check(&file),
+            };
+            let report = check(&store)?;
+            print_file_health(&name, &report);
+            exit = report.status.into();
+            if !dry_run
+                && matches!(
+                    report.status,
+                    HealthStatus::Degraded | HealthStatus::Recoverable
+                )
+            {
+                // repair works on the whole entry, then the same region is checked again
+                match store.repair(&file) {
+                    Ok(()) => print_file_health(&format!("{} after repair", name), &check(&store)?),
+                    Err(e) => {
+                        error!("REPAIR | {} failed: {}", name, e);
+                        exit = Exit::RepairFailed;
+                    }
+                }
+            }
+            report_withheld_writes(&store);
             Ok(())
         }
 
-        Commands::Health { archive } => {
+        Commands::Health {
+            archive,
+            read_only,
+            recover_to,
+            repair_dest,
+            dry_run,
+            report,
+            sample,
+            seed,
+            direct_io,
+            ..
+        } => {
+            check_report_paths(&report)?;
             let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
-            let store = FileStore::new(&archive_path)?;
-            let batch_report = store.batch_health_check()?;
+            let archive_name = archive_path.display().to_string();
+            let mut store = FileStore::new(&archive_path)?
+                .with_verifier(verifier)
+                .with_direct_io(direct_io || config.io.direct_scrub);
+            let seed = seed.unwrap_or_else(rand::random);
+            if let Some(per_file) = sample {
+                println!("sampling {} segments per file, seed {}", per_file, seed);
+            }
+            let check = |store: &FileStore| match sample {
+                Some(per_file) => store.sample_health_check(per_file, seed),
+                None => store.batch_health_check(),
+            };
+            if dry_run {
+                let batch_report = check(&store)?;
+                write_health_reports(&batch_report, &archive_name, &report)?;
+                for (filename, report) in &batch_report.reports {
+                    if report.status == HealthStatus::Unrecoverable {
+                        println!("{}: unrecoverable, {}", filename, report.details);
+                    }
+                }
+                // in the order a repair would take them, most at risk first
+                for task in store.plan_repairs_for(&batch_report)? {
+                    let report = batch_report
+                        .reports
+                        .iter()
+                        .find(|(name, _)| *name == task.file_name)
+                        .map(|(_, report)| report)
+                        .ok_or("planned a file the check didn't report")?;
+                    println!(
+                        "{}: {:?}, margin {}, a repair would",
+                        task.file_name, task.status, task.margin
+                    );
+                    for shard in report.missing_data.iter().chain(&report.missing_parity) {
+                        println!("  rebuild {}", shard);
+                    }
+                    for shard in &report.corrupt_segments {
+                        println!("  rewrite {}", shard);
+                    }
+                }
+                println!(
+                    "checked {} files: {} healthy, {} degraded, {} recoverable, {} unrecoverable, nothing repaired",
+                    batch_report.total_files,
+                    batch_report.healthy,
+                    batch_report.degraded,
+                    batch_report.recoverable,
+                    batch_report.unrecoverable
+                );
+                return Ok(Exit::from(batch_report.worst_status()).code());
+            }
+            if read_only {
+                store = store.read_only(recover_to);
+            } else if let Some(dest) = repair_dest {
+                store = store.with_repair_dest(dest);
+            }
+            let batch_report = check(&store)?;
+            write_health_reports(&batch_report, &archive_name, &report)?;
             info!(
                 total_files = batch_report.total_files,
                 healthy = batch_report.healthy,
@@ -185,37 +1828,270 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 recoverable = batch_report.recoverable,
                 unrecoverable = batch_report.unrecoverable
             );
+            exit = batch_report.worst_status().into();
+            let notifier = Notifier::from_config(&config.notify);
+            if let Some(notifier) = &notifier
+                && let Err(e) = notifier.notify(&archive_path, &batch_report)
+            {
+                error!("NOTIFY | {}", e);
+            }
 
             // Attempt repairs on any recoverable files
             if batch_report.recoverable > 0 || batch_report.degraded > 0 {
-                info!("REPAIR | attempting repairs");
-                for (filename, report) in &batch_report.reports {
-                    if report.status != blockframe::filestore::models::HealthStatus::Healthy {
-                        info!(filename = filename, "Repairing");
-                        let file = store.find(filename)?;
-                        match store.repair(&file) {
-                            Ok(_) => info!("Repair completed"),
-                            Err(e) => info!(e = e, "Repair failed"),
+                info!("REPAIR | attempting repairs, most at risk first");
+                for task in store.plan_repairs_for(&batch_report)? {
+                    info!(filename = task.file_name, margin = task.margin, "Repairing");
+                    let file = store.find(&task.file_name)?;
+                    match store.repair(&file) {
+                        Ok(_) => info!("Repair completed"),
+                        Err(e) => {
+                            info!(e = e, "Repair failed");
+                            exit = Exit::RepairFailed;
                         }
                     }
                 }
 
                 // Re-check health after repairs
                 info!("REPAIR | post-repair health check");
-                let post_repair = store.batch_health_check()?;
+                let post_repair = check(&store)?;
                 info!(
                     "Healthy: {}/{}",
                     post_repair.healthy, post_repair.total_files
                 );
                 info!(recoverable = post_repair.recoverable, "Recoverable");
                 info!(unrecoverable = post_repair.unrecoverable, "Unrecoverable");
+                if let Some(notifier) = &notifier
+                    && let Err(e) = notifier.notify(&archive_path, &post_repair)
+                {
+                    error!("NOTIFY | {}", e);
+                }
             } else {
                 info!("REPAIR | all files healthy");
             }
+            report_withheld_writes(&store);
+            Ok(())
+        }
+
+        Commands::Promote {
+            from,
+            archive,
+            dry_run,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?.with_verifier(verifier);
+            let report = store.promote(&from, dry_run)?;
+            for path in &report.promoted {
+                println!("{}", path.display());
+            }
+            for (path, reason) in &report.rejected {
+                println!("kept {}: {}", path.display(), reason);
+            }
+            println!(
+                "{} {} shards, kept {}",
+                if dry_run { "would promote" } else { "promoted" },
+                report.promoted.len(),
+                report.rejected.len()
+            );
+            Ok(())
+        }
+
+        Commands::Scrub {
+            archive,
+            max_duration,
+            max_rate,
+            restart,
+            read_only,
+            recover_to,
+            repair_dest,
+            direct_io,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let mut store = FileStore::new(&archive_path)?
+                .with_verifier(verifier)
+                .with_direct_io(direct_io || config.io.direct_scrub);
+            if read_only {
+                store = store.read_only(recover_to);
+            } else if let Some(dest) = repair_dest {
+                store = store.with_repair_dest(dest);
+            }
+            if restart {
+                store.reset_scrub()?;
+            }
+            let limits = ScrubLimits {
+                max_duration,
+                max_rate: max_rate
+                    .map(|rate| parse_size(&rate).map(|rate| rate as u64))
+                    .transpose()?,
+            };
+            let run = store.scrub_resumable(limits)?;
+            let report = &run.report;
+            println!(
+                "checked {} files: {} healthy, {} degraded, {} recoverable, {} unrecoverable",
+                report.total_files,
+                report.healthy,
+                report.degraded,
+                report.recoverable,
+                report.unrecoverable
+            );
+            match &run.checkpoint {
+                Some(cp) if !run.complete => println!(
+                    "pass started {} is paused after {} ({} files), run again to continue",
+                    cp.started, cp.last_file, cp.checked
+                ),
+                _ => println!("pass complete"),
+            }
+            report_withheld_writes(&store);
+            if report.unrecoverable > 0 {
+                error!("SCRUB | {} files are unrecoverable", report.unrecoverable);
+            }
+            exit = report.worst_status().into();
+            Ok(())
+        }
+
+        Commands::Daemon {
+            archive,
+            port,
+            bind,
+            pid_file,
+            watch,
+        } => {
+            let mut settings = config.daemon.clone();
+            if let Some(pid_file) = pid_file {
+                settings.pid_file = pid_file;
+            }
+            let mut serve = ServeOptions::new(
+                archive.unwrap_or_else(|| config.archive.directory.clone()),
+                port.unwrap_or(config.server.default_port),
+            );
+            serve.tls = tls;
+            serve.limits = limits;
+            serve.bind = choose_bind(bind, port, &config.server)?;
+            serve.drain_timeout = std::time::Duration::from_secs(config.server.drain_timeout);
+            serve.negative_ttl = std::time::Duration::from_secs(config.server.negative_ttl);
+            let options = DaemonOptions {
+                serve,
+                chunker: builder.build()?,
+                settings,
+                config_path: cli.config.clone(),
+                watch_override: watch,
+                verifier,
+                notifier: Notifier::from_config(&config.notify),
+                trash_retention: trash_retention(&config),
+                direct_scrub: config.io.direct_scrub,
+            };
+            info!(
+                archive = options.serve.archive_path.to_str(),
+                "DAEMON | archive directory set"
+            );
+            run_daemon(options).await?;
+            Ok(())
+        }
+
+        Commands::Keygen { out } => {
+            if out.exists() {
+                return Err(format!("{} already exists", out.display()).into());
+            }
+            let signer = ManifestSigner::generate();
+            write_secret(&out, &signer.secret_key_hex())?;
+            info!("KEYGEN | secret key written to {:?}", out);
+            println!("{}", signer.public_key_hex());
+            Ok(())
+        }
+
+        Commands::Audit {
+            archive,
+            op,
+            file,
+            since,
+            limit,
+            json,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let entries = AuditLog::for_archive(&archive_path).query(&AuditQuery {
+                operation: op,
+                file_name: file,
+                since,
+                limit,
+            })?;
+            for entry in entries {
+                if json {
+                    println!("{}", serde_json::to_string(&entry)?);
+                } else {
+                    println!(
+                        "{}  {:<9} {:<30} {:<12} {}@{}  {}",
+                        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                        entry.operation,
+                        entry.file_name,
+                        entry.outcome,
+                        entry.user,
+                        entry.host,
+                        entry.details.unwrap_or_default()
+                    );
+                }
+            }
+            Ok(())
+        }
+
+        Commands::History {
+            name,
+            archive,
+            limit,
+            json,
+        } => {
+            let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
+            let store = FileStore::new(&archive_path)?;
+            store.find(&name)?;
+            let records = store.health_history(&name, limit)?;
+            if json {
+                for record in &records {
+                    println!("{}", serde_json::to_string(record)?);
+                }
+            } else if records.is_empty() {
+                println!("{}: no health checks recorded", name);
+            } else {
+                for record in &records {
+                    println!(
+                        "{}  {:<13} missing data {:<4} missing parity {:<4} corrupt {}",
+                        record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                        format!("{:?}", record.status).to_lowercase(),
+                        record.missing_data,
+                        record.missing_parity,
+                        record.corrupt
+                    );
+                }
+                let summary = HistorySummary::of(&records);
+                let trend = if summary.deteriorating {
+                    "deteriorating"
+                } else if summary.incidents == 0 {
+                    "always healthy"
+                } else if records
+                    .last()
+                    .is_some_and(|r| r.status == HealthStatus::Healthy)
+                {
+                    "healthy since the last incident"
+                } else {
+                    "unhealthy"
+                };
+                println!(
+                    "{} checks, {} unhealthy in {} incident(s), at most {} shards damaged: {}",
+                    summary.checks,
+                    summary.unhealthy,
+                    summary.incidents,
+                    summary.worst_damage,
+                    trend
+                );
+            }
             Ok(())
         }
 
-        Commands::Serve { archive, port } => {
+        Commands::Serve {
+            archive,
+            port,
+            bind,
+            allow_uploads,
+            webdav,
+            read_only,
+        } => {
             let archive_path = archive.unwrap_or_else(|| config.archive.directory.clone());
             let server_port = port.unwrap_or(config.server.default_port);
 
@@ -224,7 +2100,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "SERVE | archive directory set"
             );
             info!("CWD: {:?}", std::env::current_dir());
-            run_server(archive_path, server_port).await?;
+            let mut options = ServeOptions::new(archive_path.clone(), server_port);
+            options.tls = tls;
+            options.limits = limits;
+            options.bind = choose_bind(bind, port, &config.server)?;
+            options.drain_timeout = std::time::Duration::from_secs(config.server.drain_timeout);
+            options.negative_ttl = std::time::Duration::from_secs(config.server.negative_ttl);
+            options.webdav = webdav;
+            options.read_only = read_only;
+            if allow_uploads {
+                let chunker = builder.archive_dir(archive_path).build()?;
+                options.uploads = Some(Arc::new(chunker));
+            }
+            run_server(options).await?;
             Ok(())
         }
 
@@ -232,183 +2120,114 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             mountpoint,
             archive,
             remote,
+            peers,
+            integrity,
+            mount_opts,
+            volume_label,
+            volume_serial,
+            case_sensitive,
+            sector_size,
         } => {
             let mount_path = mountpoint.unwrap_or_else(|| config.mount.default_mountpoint.clone());
+            let mount_options = MountOptions::parse(&config.mount.options)
+                .map_err(|e| format!("mount.options: {}", e))?
+                .extend(MountOptions::parse(&mount_opts)?);
+            let integrity = integrity.unwrap_or(config.mount.integrity);
+            let mut mount_config = MountConfig {
+                integrity,
+                ..config.mount.clone()
+            };
+            if let Some(label) = volume_label {
+                mount_config.volume.label = label;
+            }
+            if let Some(serial) = volume_serial {
+                mount_config.volume.serial = serial;
+            }
+            if case_sensitive {
+                mount_config.volume.case_sensitive = true;
+            }
+            if let Some(sector_size) = sector_size {
+                mount_config.volume.sector_size = sector_size;
+            }
 
             info!("MOUNT | starting mount operation");
             info!("MOUNT | mountpoint: {:?}", mount_path);
+            info!("MOUNT | integrity: {:?}", integrity);
+            info!("MOUNT | options: {}", mount_options);
 
             // source is a smart-pointer which points to our source
             // we're using a smart-pointer as it could either be a RemoteSource or LocalSource
-            let source: Box<dyn SegmentSource> = if let Some(url) = remote {
+            let source: Box<dyn SegmentSource> = if !peers.is_empty() {
+                // several peers serving the same archive, MultiPeerSource spreads
+                // segment reads across them and falls back when one is unreachable
+                info!("MOUNT | using {} peers: {:?}", peers.len(), peers);
+                Box::new(MultiPeerSource::new(peers, verifier)?.with_config(&config.remote))
+            } else if let Some(url) = remote {
                 // If mount command is flagged with remote
                 // then we'll return a smart-pointer to a RemoteSource object
                 // RemoteSource object connects to another blockframe url which is serving
                 info!("MOUNT | using remote source: {}", url);
-                Box::new(RemoteSource::new(url))
+                Box::new(
+                    RemoteSource::new(url)
+                        .with_verifier(verifier)
+                        .with_config(&config.remote)
+                        .with_integrity(integrity),
+                )
             } else if let Some(path) = archive {
                 // If mount command is flagged with archive
                 // then we'll return a smart-pointer to a LocalSource object
                 // LocalSource is used to interface local files
                 info!("MOUNT | using local source: {:?}", path);
-                Box::new(LocalSource::new(path)?)
+                Box::new(LocalSource::new(path)?.with_verifier(verifier))
             } else if !config.mount.default_remote.is_empty() {
                 // Use default remote from config if specified
                 info!(
                     "MOUNT | using default remote source from config: {}",
                     config.mount.default_remote
                 );
-                Box::new(RemoteSource::new(config.mount.default_remote.clone()))
+                Box::new(
+                    RemoteSource::new(config.mount.default_remote.clone())
+                        .with_verifier(verifier)
+                        .with_config(&config.remote)
+                        .with_integrity(integrity),
+                )
             } else {
                 // Use default archive directory from config
                 info!(
                     "MOUNT | using default archive from config: {:?}",
                     config.archive.directory
                 );
-                Box::new(LocalSource::new(config.archive.directory)?)
+                Box::new(LocalSource::new(config.archive.directory)?.with_verifier(verifier))
             };
-            // Initalising the BlockframeFS class with the given source
-            info!("MOUNT | creating filesystem");
-            let fs = BlockframeFS::new(source)?;
+            // mounting serves the filesystem on a background thread, the
+            // process stays up until the mount goes away
+            let handle = mount::mount(
+                source,
+                &mount_path,
+                &mount_options,
+                &config.cache,
+                &mount_config,
+            )?;
 
             #[cfg(target_os = "windows")]
             {
-                // winfsp_init_or_die is called to check if WinFsp runtime is loaded and ready
-                // if WinFsp isnt installed or cant initalise, the process aborts immediately.
-                info!("MOUNT | initializing WinFsp");
-                winfsp::winfsp_init_or_die();
-
-                // importing windows specific libraries
                 use std::io::{self, Read};
-                use winfsp::host::VolumeParams;
-                // volume_params is a VolumeParams object
-                // VolumeParams is an object that configures the medata about the virtual disk being presented to windows
-                // its used to describe the shape and rules of the filesystem volume so windows knows how to interact with it
-                info!("MOUNT | configuring volume parameters");
-                let mut volume_params = VolumeParams::new();
-
-                // The `sector_size` tells windows the logical sector size of the filesystem
-                // the `sector_size` is set to 512 bytes which is the traditional disk sector size.
-                volume_params.sector_size(512);
-
-                // `sectors_per_allocation_unit` defines the allocation unit (cluster) size.
-                // with 1 sector per allocation unit, the cluster size is 512 bytes.
-                // small clusters reduce wasted space but increase metadata churn, but this is a conservative and filesystem-friendly
-                volume_params.sectors_per_allocation_unit(1);
-
-                // `volume_serial_number` is the filesystems ID. Windows uses it to recognise whether a volume is "the same" across mounts.
-                // 0 means auto assignment.
-                volume_params.volume_serial_number(0);
-
-                // `file_info_timeout` is a cache hint in miliseconds
-                // we're flagging that windows is allowed to cache the file's metadata for 1 second before asking the filesystem again
-                // 1 second is used for reducing call spam and a reasonable balance between refreshes.
-                volume_params.file_info_timeout(1000);
-
-                // `case_sensitive_search` treats 'File.txt' and 'file.txt' the same when searching
-                volume_params.case_sensitive_search(false);
-
-                // `case_preserved_names` ensures filenames sustain thier casing when displayed. Even though seraches are case-insensitive
-                // quality of life option, and its enabled to match windows-esk functionality
-                volume_params.case_preserved_names(true);
-                // `unicode_on_disk` flags if filenames are treated as unicode. This ensures multi-lingual and obscure characters dont panic the volume
-                volume_params.unicode_on_disk(true);
-                // `persistent_acls` is flagged to false as Blockframe doesnt impliment Access Control Lists (acl)
-                // ACL's are not stored persistently by Blockframe. Windows will still ask about permissions
-                // If ACL's are persisted, windows would start relying on behaviour which is not supported by Blockframe
-                // enabling would create subtle breakages such as access denied errors, explorer weirdness or files being unreadable for no obvious reason.
-                volume_params.persistent_acls(false);
-                // `post_cleanup_when_modified_only` is an optimisation.
-                // cleanup work after file handles close only happens if the file was actually modified.
-                // less overhead, fewer pointless calls.
-                volume_params.post_cleanup_when_modified_only(true);
-
-                // host is a FileSystemHost object which manages the lifetime of the mounted volume
-                // it binds the `BlockframeFS` to WinFsp using the `volume_params` that were defined.
-                info!("MOUNT | creating filesystem host");
-                let mut host = winfsp::host::FileSystemHost::new(volume_params, fs)?;
-
-                // this mounts our provided mountpoint which is the directory where the filesystem will mount
-                info!("MOUNT | mounting to: {:?}", mount_path);
-                host.mount(&mount_path)?;
-
-                // we then start our request loop.
-                // from this point on, windows explore, dir, file reads will actively be called into the filesystem
-                info!("MOUNT | starting filesystem");
-                host.start()?;
-
                 // blockframe uses stdin for exitpoint, its a crude lifetime guard
                 // it keeps the processes alive until the user presses enter, which unmounts the filesystem.
                 info!("Mounted at {:?}. Press Enter to unmount.", mount_path);
                 io::stdin()
                     .read_exact(&mut [0u8])
                     .map_err(|e| e.to_string())?;
+                handle.unmount()?;
             }
 
+            // FUSE mounts end when they are unmounted, by `fusermount -u` or
+            // with the process when `auto_unmount` is set
             #[cfg(not(target_os = "windows"))]
-            {
-                // import linux specific libraries
-                use fuser::MountOption;
-
-                // options is a list of our MountOption' options.
-                // essentially its a configure list for the FUSE kernel module.
-                // it is an enum of MountOption enums which tell the Linux Kernel how to treat this specific filesystem mount
-                // This is essentially passing a rulebook to the OS before the filesystem mounts.
-                let options = vec![
-                    // MountOption::RO stands for `Read-Only` option meaning we're blocking all write operations at the system call level
-                    // This filters out any write requests.
-                    MountOption::RO,
-                    // This is the filesystems name. Purely cosmetic.
-                    MountOption::FSName("blockframe".to_string()),
-                    // AutoUnmount is used to prevent stale mount points, with a tiny uncertainty.
-                    // its used to automatically unmount the directory if blockframe crashes or exists.
-                    MountOption::AutoUnmount,
-                    // DefaultPermissions flags standard unix permission checks (rwx). This avoids stupid permission issues.
-                    MountOption::DefaultPermissions,
-                ];
-
-                // This is the zombie mount.
-                // we start by checking if our mount point doesnt exist, as in the directory to "mount" our filesystem
-                // TANGENT_EXPLAINATION: the reason this is a zombie mount is due to the difference in how linux's filesystem works.
-                // linux doesnt have drives, it has a huge filesystem which is associated with permissions.
-                // However, with linux, if our BlockframeFS crashes for some reason, our linux kernel wont be informed properly.
-                // If blockframe crashes, it wont have time to inform the kernel, beacuse unmounting is a manual call that needs to be made.
-                // The reason we have to unmount, if blockframe crashes, and we couldnt call fusermount -u, linux will still sustain that mountpoint still belongs to a process that's not alive anymore, making that mountpoint stale.
-                if !mount_path.exists() {
-                    // if our mountpoint doesnt exist
-                    // we then attempt to check if creating out mountpoint causes an error
-                    match std::fs::create_dir_all(&mount_path) {
-                        // if there were no errors, we exit the match predicate no problems.
-                        // we created our mountpoint
-                        Ok(_) => {}
-                        // if creating the dir does cause an error, we check to see if its an AlreadyExists
-                        // meaning we still have a successful outcome
-                        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-                            warn!("Mountpoint appears to be stale. Attempting cleanup...");
-                            let _ = std::process::Command::new("fusermount") // fusermount allows non-root users to mount and unmount FUSE filesystems.
-                                .arg("-u") // -u is unmount
-                                .arg("-q") // -q (Quiet). We are calling unmount speculatively.
-                                // If the directory wasn't actually mounted (and the mkdir failed for a different reason),
-                                // fusermount would normally error out. -q suppresses that error so we don't spam logs.
-                                .arg(&mount_path) // we also pass in our given mountpoint as the directory we're trying to fix
-                                .status();
-                        }
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-
-                // and finally we mount the blockframe filesystem.
-                // with linux this is a bit different.
-                // TANGENT_EXPLAINATION: On windows our filesystem is "plugged in"
-                // where as on linux, our filesystem is "placed on-top".
-                // Linux doesnt have drives, as akin to windows E: or C:. Linux is just one filesystem with a bunch of folders.
-                // When we mount or filesystem on linux, what is happening is, blockframe creats a telephone line (a socket) to the linux kernel
-                // when the user checks to see the files, instead of seeing the physical files placed in that folder, the linux kernel intercepts `ls` request and understands that there is a process attached to that folder
-                // instead of being served the actual files in that folder, blockframe instead serves the files.
-                fuser::mount2(fs, &mount_path, &options)?;
-            }
+            handle.join()?;
 
             Ok(())
         }
-    }
+    };
+    outcome.map(|()| exit.code())
 }
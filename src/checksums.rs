@@ -0,0 +1,280 @@
+//! Checking files against a checksum list before they are committed.
+//!
+//! `commit --verify-against SHA256SUMS` reads a list written by `sha256sum`,
+//! `b3sum` or `sha256sum --tag`, hashes each file as it is committed and
+//! fails the commit when the file doesn't match its line, so a file damaged
+//! before it reached the archive isn't archived as if it were good. A file the
+//! list doesn't name fails too.
+//!
+//! SHA-256 and BLAKE3 are both 64 hex digits, so a plain `hash  name` line is
+//! taken to match either; the `--tag` form names its algorithm.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::Digests;
+
+/// Which hash a line of the list holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Blake3,
+    /// A `hash  name` line, which could be either.
+    Either,
+}
+
+/// One line of a checksum list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    /// The path as the list gives it, without a leading `./`.
+    pub name: PathBuf,
+    pub algorithm: Algorithm,
+    /// Lowercase hex.
+    pub hash: String,
+}
+
+/// The lines of a checksum file.
+#[derive(Debug, Clone)]
+pub struct ChecksumList {
+    path: PathBuf,
+    checksums: Vec<Checksum>,
+}
+
+impl ChecksumList {
+    /// Reads the list at `path`. Names in it are relative to its directory.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("reading {}: {}", path.display(), e))?;
+        Self::parse(path, &text)
+    }
+
+    /// Parses `text` as the list at `path`. Blank lines and `#` comments are
+    /// skipped.
+    ///
+    /// ```
+    /// use blockframe::checksums::{Algorithm, ChecksumList};
+    /// use std::path::Path;
+    ///
+    /// let hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    /// let text = format!("{hash}  ./abc.txt\nSHA256 (data/abc.bin) = {hash}\n");
+    /// let list = ChecksumList::parse(Path::new("SHA256SUMS"), &text).unwrap();
+    ///
+    /// assert_eq!(list.checksums()[0].name, Path::new("abc.txt"));
+    /// assert_eq!(list.checksums()[0].algorithm, Algorithm::Either);
+    /// assert_eq!(list.checksums()[1].algorithm, Algorithm::Sha256);
+    /// assert!(ChecksumList::parse(Path::new("MD5SUMS"), "d41d8cd98f00b204e9800998ecf8427e  x").is_err());
+    /// ```
+    pub fn parse(path: &Path, text: &str) -> Result<Self, String> {
+        let checksums = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                parse_line(line).map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            checksums,
+        })
+    }
+
+    /// The file the list was read from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn checksums(&self) -> &[Checksum] {
+        &self.checksums
+    }
+
+    /// The line for `file`, found by file name. When several lines share the
+    /// name, the one whose path leads to `file` from the list's directory.
+    pub fn expected(&self, file: &Path) -> Result<&Checksum, String> {
+        let named: Vec<_> = self
+            .checksums
+            .iter()
+            .filter(|checksum| checksum.name.file_name() == file.file_name())
+            .collect();
+        match named.as_slice() {
+            [] => Err(format!(
+                "{} isn't listed in {}",
+                file.display(),
+                self.path.display()
+            )),
+            [checksum] => Ok(checksum),
+            _ => {
+                let base = self.path.parent().unwrap_or(Path::new(""));
+                let wanted = fs::canonicalize(file).ok();
+                named
+                    .into_iter()
+                    .find(|checksum| fs::canonicalize(base.join(&checksum.name)).ok() == wanted)
+                    .ok_or_else(|| {
+                        format!(
+                            "{} has several lines for {} and none is its path",
+                            self.path.display(),
+                            file.display()
+                        )
+                    })
+            }
+        }
+    }
+
+    /// Fails unless `digests` of `file` match its line.
+    pub fn verify(&self, file: &Path, digests: &Digests) -> Result<(), String> {
+        let expected = self.expected(file)?;
+        let sha256 = digests.sha256.as_deref();
+        let matches = match expected.algorithm {
+            Algorithm::Sha256 => sha256 == Some(expected.hash.as_str()),
+            Algorithm::Blake3 => digests.blake3 == expected.hash,
+            Algorithm::Either => {
+                sha256 == Some(expected.hash.as_str()) || digests.blake3 == expected.hash
+            }
+        };
+        if matches {
+            return Ok(());
+        }
+        Err(format!(
+            "{} doesn't match {}: expected {}, got SHA-256 {} and BLAKE3 {}",
+            file.display(),
+            self.path.display(),
+            expected.hash,
+            sha256.unwrap_or("?"),
+            digests.blake3
+        ))
+    }
+}
+
+/// `hash  name`, `hash *name` or `ALGO (name) = hash`. A line starting with
+/// `\` has `\\` and `\n` escaped in its name, as `sha256sum` writes them.
+fn parse_line(line: &str) -> Result<Checksum, String> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (algorithm, name, hash) = match line.split_once(" (") {
+        Some((tag, rest)) if !rest.is_empty() && !tag.contains(' ') => {
+            let (name, hash) = rest
+                .rsplit_once(") = ")
+                .ok_or("expected 'ALGO (name) = hash'")?;
+            let algorithm = match tag {
+                "SHA256" => Algorithm::Sha256,
+                "BLAKE3" => Algorithm::Blake3,
+                other => return Err(format!("{} isn't supported, only SHA256 and BLAKE3", other)),
+            };
+            (algorithm, name, hash)
+        }
+        _ => {
+            let (hash, rest) = line.split_once(' ').ok_or("expected 'hash  name'")?;
+            let name = rest
+                .strip_prefix(' ')
+                .or_else(|| rest.strip_prefix('*'))
+                .ok_or("expected two spaces or ' *' between hash and name")?;
+            (Algorithm::Either, name, hash)
+        }
+    };
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "'{}' isn't a SHA-256 or BLAKE3 hash of 64 hex digits",
+            hash
+        ));
+    }
+    let name = match escaped {
+        true => unescape(name),
+        false => name.to_string(),
+    };
+    let name = name.strip_prefix("./").unwrap_or(&name);
+    if name.is_empty() {
+        return Err("no file name".to_string());
+    }
+    Ok(Checksum {
+        name: PathBuf::from(name),
+        algorithm,
+        hash: hash.to_ascii_lowercase(),
+    })
+}
+
+fn unescape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::HashSession;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn digests(data: &[u8]) -> Digests {
+        HashSession::new()
+            .with_sha256()
+            .hash_reader(data, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_lines_of_each_format_parse() {
+        let text = format!(
+            "# made by hand\n\n{h}  plain.txt\n{h} *binary.bin\nBLAKE3 (dir/tagged (1).bin) = {H}\n\\{h}  odd\\nname\\\\x\n",
+            h = ABC_SHA256,
+            H = ABC_SHA256.to_uppercase()
+        );
+        let list = ChecksumList::parse(Path::new("sums"), &text).unwrap();
+        let names: Vec<_> = list.checksums().iter().map(|c| c.name.clone()).collect();
+        assert_eq!(
+            names,
+            [
+                PathBuf::from("plain.txt"),
+                PathBuf::from("binary.bin"),
+                PathBuf::from("dir/tagged (1).bin"),
+                PathBuf::from("odd\nname\\x"),
+            ]
+        );
+        assert_eq!(list.checksums()[2].algorithm, Algorithm::Blake3);
+        assert_eq!(list.checksums()[2].hash, ABC_SHA256);
+
+        let err = ChecksumList::parse(Path::new("sums"), "\nabc  short.txt").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+        assert!(
+            ChecksumList::parse(Path::new("sums"), &format!("MD5 (x) = {}", ABC_SHA256)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify_takes_either_hash_of_a_plain_line() {
+        let abc = digests(b"abc");
+        let blake3 = abc.blake3.clone();
+        let text = format!("{}  sha.txt\n{}  b3.txt\n", ABC_SHA256, blake3);
+        let list = ChecksumList::parse(Path::new("sums"), &text).unwrap();
+        list.verify(Path::new("in/sha.txt"), &abc).unwrap();
+        list.verify(Path::new("b3.txt"), &abc).unwrap();
+
+        let err = list
+            .verify(Path::new("sha.txt"), &digests(b"abd"))
+            .unwrap_err();
+        assert!(err.contains("doesn't match"), "{}", err);
+        let err = list.verify(Path::new("other.txt"), &abc).unwrap_err();
+        assert!(err.contains("isn't listed"), "{}", err);
+
+        // a tagged line only takes its own algorithm
+        let tagged =
+            ChecksumList::parse(Path::new("sums"), &format!("BLAKE3 (x) = {}", ABC_SHA256))
+                .unwrap();
+        assert!(tagged.verify(Path::new("x"), &abc).is_err());
+    }
+}
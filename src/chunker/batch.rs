@@ -0,0 +1,120 @@
+//! Committing many files at once.
+//!
+//! Each file is still an independent `commit_as`, they just run side by side on
+//! a dedicated rayon pool so a folder of thousands of tier 1 files isn't bound
+//! to one core. The pool also bounds the memory in flight: at most `jobs` files
+//! are being encoded at any moment.
+
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::{ChunkedFile, Chunker, PathFilter};
+
+/// Files encoded at once when the caller doesn't say.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// Outcome of [`Chunker::commit_many`].
+pub struct CommitSummary {
+    pub committed: Vec<ChunkedFile>,
+    /// Paths that failed, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+    /// Total size of the committed files.
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+/// Expands glob patterns (`*`, `?`, `[...]`) and keeps plain paths as given, in
+/// order and without duplicates. A pattern that matches nothing is an error so
+/// a typo doesn't silently commit nothing. A directory is replaced by the
+/// files below it, less those its `.blockframeignore` leaves out.
+pub fn expand_paths(patterns: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    expand_paths_with(patterns, &PathFilter::default())
+}
+
+/// [`expand_paths`], with `filter` choosing the files taken from directories.
+/// Files named directly or by a glob are kept whatever it says.
+pub fn expand_paths_with(
+    patterns: &[PathBuf],
+    filter: &PathFilter,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let text = pattern.to_string_lossy();
+        if pattern.is_dir() {
+            paths.extend(filter.walk(pattern)?);
+            continue;
+        }
+        if !text.contains(['*', '?', '[']) {
+            paths.push(pattern.clone());
+            continue;
+        }
+        let matches: Vec<PathBuf> = glob::glob(&text)?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        if matches.is_empty() {
+            return Err(format!("{} matched no files", text).into());
+        }
+        paths.extend(matches);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    Ok(paths)
+}
+
+impl Chunker {
+    /// Commits every path in `paths` with up to `jobs` running at once. One
+    /// file failing doesn't stop the others; failures are collected in the
+    /// summary instead.
+    ///
+    /// # Errors
+    ///
+    /// Only fails if the worker pool cannot be created.
+    pub fn commit_many(
+        &self,
+        paths: &[PathBuf],
+        tier: Option<u8>,
+        jobs: usize,
+    ) -> Result<CommitSummary, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.max(1))
+            .build()?;
+
+        let results: Vec<(PathBuf, Result<ChunkedFile, String>)> = pool.install(|| {
+            paths
+                .par_iter()
+                .map(|path| {
+                    let result = self.commit_as(path, tier).map_err(|e| e.to_string());
+                    match &result {
+                        Ok(chunked) => {
+                            tracing::info!("COMMIT | {} -> {:?}", path.display(), chunked.file_dir)
+                        }
+                        Err(e) => tracing::error!("COMMIT | {} failed: {}", path.display(), e),
+                    }
+                    (path.clone(), result)
+                })
+                .collect()
+        });
+
+        let mut summary = CommitSummary {
+            committed: Vec::new(),
+            failed: Vec::new(),
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        };
+        for (path, result) in results {
+            match result {
+                Ok(chunked) => {
+                    summary.bytes += chunked.file_size as u64;
+                    summary.committed.push(chunked);
+                }
+                Err(e) => summary.failed.push((path, e)),
+            }
+        }
+        summary.elapsed = started.elapsed();
+        Ok(summary)
+    }
+}
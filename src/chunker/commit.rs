@@ -1,20 +1,33 @@
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use super::Chunker;
+use crate::audit::{AuditEntry, AuditLog, AuditOp};
 use crate::chunker::ChunkedFile;
+use crate::chunker::OnExisting;
+use crate::chunker::existing::parity_root;
+use crate::classify::SNIFF_LEN;
+use crate::container::{self, ContainerKind};
+use crate::direct_io::{self, DirectReader};
+use crate::erasure;
+use crate::filestore::global_parity::GLOBAL_PARITY_DIR;
+use crate::filestore::versions::PrunePolicy;
+use crate::filestore::{FileStore, remove_parity_dir};
+use crate::memstats::MemoryProbe;
 use crate::merkle_tree::{
     MerkleTree,
-    manifest::{BlockHashes, MerkleTreeStructure, SegmentHashes},
+    manifest::{BlockHashes, ManifestFile, MerkleTreeStructure, SegmentHashes},
+};
+use crate::utils::{
+    BLOCK_SEGMENTS, HashSession, blake3_hash_bytes, block_parity_shards, is_zero, write_shard,
 };
-use crate::utils::blake3_hash_bytes;
 use rayon::prelude::*;
 use tracing::info;
 
-use crate::utils::determine_segment_size;
-
 use memmap2::Mmap;
 
 impl Chunker {
@@ -22,30 +35,51 @@ impl Chunker {
     /// is treated as a single data shard with 3 parity shards. File is padded to 64-byte
     /// boundary (Reed-Solomon requirement), then 3 parity shards are generated.
     /// Creates data.dat + parity_0/1/2.dat, builds merkle tree from hashes, writes manifest.
+    /// `file_hash` is the file's BLAKE3 hash, which is also the hash of data.dat.
     pub fn commit_tiny(
         &self,
         file_path: &Path,
         file_size: usize,
         tier: u8,
+        file_hash: &str,
     ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
         info!(
             "COMMIT | (tiny) reading file from {:?} as tier {:?}",
             file_path, tier
         );
-        let file_data = fs::read(file_path)?;
-        // our tiny file needs to be round up to a multiple of 64
-        let padded_size = file_data.len().div_ceil(64) * 64;
-        let parity = self.generate_parity_segmented(&file_data)?;
+        let file_data = match self.direct_io {
+            true => direct_io::read(file_path)?,
+            false => fs::read(file_path)?,
+        };
+        if file_data.len() != file_size {
+            return Err(format!("{:?} changed size while committing", file_path).into());
+        }
 
         let file_name = file_path
             .file_name()
             .and_then(|name| name.to_str())
             .ok_or("error getting filename")?
             .to_string();
+        self.commit_tiny_bytes(file_data, file_name, tier, file_hash)
+    }
+
+    /// [`Chunker::commit_tiny`] for a file already in memory, such as one
+    /// downloaded by [`Chunker::commit_url`].
+    pub(crate) fn commit_tiny_bytes(
+        &self,
+        file_data: Vec<u8>,
+        file_name: String,
+        tier: u8,
+        file_hash: &str,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let file_size = file_data.len();
+        // our tiny file needs to be round up to a multiple of 64
+        let padded_size = file_data.len().div_ceil(64) * 64;
+        let parity = self.generate_parity_segmented(&file_data)?;
 
         info!("COMMIT | (tiny) confirming filename: {:?}", file_name);
 
-        let file_hash = blake3_hash_bytes(&file_data)?;
+        let file_hash = file_hash.to_string();
 
         info!("COMMIT | (tiny) hash: {:?} for: {:?}", file_hash, file_name);
 
@@ -70,13 +104,16 @@ impl Chunker {
         let file_dir = self.get_dir(&file_name, &file_hash)?;
         let archive_dir_check = self.check_for_archive_dir()?;
         info!("COMMIT | (tiny) archive_dir check {:?}", archive_dir_check);
+        // written into a work dir of our own, published to file_dir once complete
+        let work = self.work_dir()?;
+        let parity_work = self.parity_work_dir(&file_name)?;
         let shard_name = "data.dat";
-        let shard_path = &file_dir.join(shard_name);
+        let shard_path = &work.path().join(shard_name);
 
-        self.create_dir(&file_dir)?;
         info!("COMMIT | (tiny) writing shards to {:?}", shard_path);
-        fs::write(shard_path, file_data)?;
-        self.write_parity_chunks(&file_dir, &parity)?;
+        write_shard(shard_path, &file_data)?;
+        self.write_parity_chunks(parity_root(&work, &parity_work), &parity)?;
+        self.report_progress(&file_name, file_size as u64, file_size as u64);
 
         let merkle_tree = MerkleTree::from_hashes(vec![
             file_hash.clone(),
@@ -94,10 +131,13 @@ impl Chunker {
             file_size,
             6,
             3,
-            &file_dir,
+            work.path(),
             tier,
             padded_size as u64,
+            &file_data,
+            parity_work.as_ref().map(|p| p.path()),
         )?;
+        self.publish_split(work, parity_work, &file_dir)?;
         info!(
             "COMMIT | (tiny) {:?} commited successfully to {:?} ",
             &file_hash, &file_dir
@@ -112,19 +152,21 @@ impl Chunker {
             file_trun_hash,
             file_hash,
             merkle_tree,
-            data_shards: self.data_shards,
-            parity_shards: self.parity_shards,
+            data_shards: 1,
+            parity_shards: parity.len(),
+            memory: None,
         })
     }
 
     /// Tier 2 commit for 10MB-1GB files. Divides file into segments (1/8/32MB depending on size),
     /// each segment gets RS(1,3) encoding for independent recovery. Uses mmap for files >10MB.
-    /// Builds merkle tree from segment hashes, computes BLAKE3 hash during processing.
-    /// Each segment can lose up to 3 shards and still recover.
+    /// Builds merkle tree from segment hashes. `file_hash` is the file's BLAKE3 hash, so
+    /// each segment is only hashed once. Each segment can lose up to 3 shards and still recover.
     pub fn commit_segmented(
         &self,
         file_path: &Path,
         tier: u8,
+        file_hash: &str,
     ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
         // we open a file, so we're not reading the file into memory
         let file = File::open(file_path)?;
@@ -135,7 +177,6 @@ impl Chunker {
             "COMMIT | (segmented) reading file from {:?} as tier {:?}",
             file_path, tier
         );
-        info!("COMMIT | (segmented) file size: {} bytes", file_size);
 
         // extract the filename from the path given
         let file_name = file_path
@@ -144,7 +185,18 @@ impl Chunker {
             .ok_or("error getting filename")?
             .to_string();
 
-        info!("COMMIT | (segmented) confirming filename: {:?}", file_name);
+        let mut encoder = SegmentedCommit::new(self, file_name, file_size, tier)?;
+        if self.direct_io {
+            // a mapping would pull the file into the page cache, read it in steps instead
+            let mut reader = DirectReader::new(direct_io::open(file_path)?);
+            let mut segment = Vec::new();
+            while let Some(range) = encoder.next_range() {
+                segment.resize(range.len(), 0);
+                reader.read_exact(&mut segment)?;
+                encoder.push(&segment)?;
+            }
+            return encoder.finish(file_hash);
+        }
 
         // we using default mmap otherwise we can end up with short reads in normal manual io reads at this size.
         let mmap = unsafe { Mmap::map(&file)? };
@@ -153,168 +205,22 @@ impl Chunker {
         // our file data array is filled through the memory mapped file as a reference to the memory mapped file
         let file_data: &[u8] = mmap.as_ref();
 
-        // get an optimised segment size 1mb/8mb/32mb
-        let segment_size = determine_segment_size(file_size as u64)? as usize;
-        info!("COMMIT | (segmented) segment size: {} bytes", segment_size);
-
-        // this is the amount of segments we're going to generate
-        // its our file size
-        // file_size    = 10mb - 1000mb
-        // segment_size = 1mb/8mb/32mb
-        // max = 1_000_000_000 + 33_554_432 - 1 / 33_554_432 = 30 segments
-        // 30 segments x 3 parity shards = 90 files generated in total
-        let num_segments = file_size.div_ceil(segment_size);
-        info!(
-            "COMMIT | (segmented) total segments to create: {}",
-            num_segments
-        );
-        info!("COMMIT | (segmented) rs encoder will use 1:3 ratio per segment");
-
-        println!("Computing file hash while processing segments...");
-        let mut file_hasher = blake3::Hasher::new();
-
-        let file_hash_placeholder = "computing";
-        let file_dir = self.get_dir(&file_name, &file_hash_placeholder.to_string())?;
-        let parity_dir = &file_dir.join("parity");
-        let segments_dir = &file_dir.join("segments");
-
-        self.create_dir(&file_dir)?;
-        self.create_dir(&file_dir.join("parity"))?;
-        self.create_dir(&file_dir.join("segments"))?;
-
-        // a check and create function for our archive directory
-        let archive_dir_check = self.check_for_archive_dir()?;
-        info!(
-            "COMMIT | (segmented) archive_dir check {:?}",
-            archive_dir_check
-        );
-
-        info!(
-            "COMMIT | (segmented) writing segments to {:?}",
-            segments_dir
-        );
-
-        // empty vector for our segment hashes that are going to be generated
-        // through the numerical index loop
-        let mut segment_hashes = Vec::new();
-        let mut segments_map = HashMap::new();
-
-        // iterating by the amount of segments we need to create
-        // TODO: we know the amount of segments we need
-        // NOTE - we just need to per iteration, create our segment which we already do
-        // NOTE - instead of generating chunks, we just write our segment as a data
-        // NOTE - our `buffer` has the segment data, just write it
-        // NOTE - then generate our parity chunks per segment
-        for segment_index in 0..num_segments {
-            // our memory segment buffer
-            // our `buffer` buffer is for reading the file with a slice
-            // this is the storage buffer so that segment data is used in the code
-            let segment_data: &[u8] = {
-                // segment_index 0..num_segments:MAX(30)
-                // segment_size = 1mb/8mb/32mb
-                // start = 0..30 x 1_000_000
-                let start = segment_index * segment_size;
-                // end = (0..30 + 1) x 1_000_000
-                // we're moving megabytes at a time,
-                // moving forward by a sort of pagenation of our file
-                let end = ((segment_index + 1) * segment_size).min(file_data.len());
-                // segment_data is our chunk of data or more, our segment
-                // which will be broken up into chunks, except //NOTE we wont be doing that
-                // NOTE we're writting the segment data as soon as we get, the parity data is also being written when our segment is provided
-                &file_data[start..end]
-            };
-
-            // Hash file data as we process segments
-            file_hasher.update(segment_data);
-
-            let parity = self.generate_parity_segmented(segment_data)?;
-
-            self.write_segment(segment_index, segments_dir, segment_data)?;
-            self.write_segment_parities(segment_index, parity_dir, &parity)?;
-
-            let data_hash = blake3_hash_bytes(segment_data)?;
-            let mut parity_hashes = Vec::new();
-            for p in &parity {
-                parity_hashes.push(blake3_hash_bytes(p)?);
-            }
-
-            segments_map.insert(
-                segment_index,
-                SegmentHashes {
-                    data: data_hash.clone(),
-                    parity: parity_hashes.clone(),
-                },
-            );
-
-            let mut segment_leaves = vec![data_hash];
-            segment_leaves.extend(parity_hashes);
-            let segment_tree = MerkleTree::from_hashes(segment_leaves)?;
-            segment_hashes.push(segment_tree.root.hash_val);
+        // we're moving a segment at a time, a sort of pagenation of our file
+        while let Some(range) = encoder.next_range() {
+            encoder.push(&file_data[range])?;
         }
-
-        // Finalize hash after processing all segments
-        let file_hash = file_hasher.finalize().to_string();
-        let file_trun_hash = &file_hash[0..10].to_string();
-        println!("File hash computed: {}", file_trun_hash);
-        info!(
-            "COMMIT | (segmented) file hash: {:?} for: {:?}",
-            file_hash, file_name
-        );
-
-        // Rename directory to include actual hash
-        let final_file_dir = self.get_dir(&file_name, &file_hash)?;
-        std::fs::rename(&file_dir, &final_file_dir)?;
-        info!("COMMIT | (segmented) renamed directory to include hash");
-
-        let root_tree = MerkleTree::from_hashes(segment_hashes)?;
-        let merkle_tree_struct = MerkleTreeStructure {
-            leaves: HashMap::new(),
-            segments: segments_map,
-            blocks: HashMap::new(),
-            root: root_tree.root.hash_val.clone(),
-        };
-
-        info!(
-            "COMMIT | (segmented) writing manifest to {:?}",
-            &final_file_dir
-        );
-        self.write_manifest_struct(
-            merkle_tree_struct,
-            &file_hash,
-            &file_name,
-            file_size,
-            6,
-            3,
-            &final_file_dir,
-            tier,
-            segment_size as u64,
-        )?;
-        info!(
-            "COMMIT | (segmented) {:?} commited successfully to {:?}",
-            &file_hash, &final_file_dir
-        );
-
-        Ok(ChunkedFile {
-            file_name,
-            file_size,
-            segment_size,
-            num_segments,
-            file_dir: final_file_dir,
-            file_trun_hash: file_trun_hash.clone(),
-            file_hash,
-            merkle_tree: root_tree,
-            data_shards: self.data_shards,
-            parity_shards: self.parity_shards,
-        })
+        encoder.finish(file_hash)
     }
 
     /// Tier 3 commit for 1GB-35GB files. Divides into blocks of 30 segments each, applies RS(30,3)
     /// per block. Can lose up to 3 segments per block and still recover. Uses parallel block
     /// processing (Rayon), always mmaps, builds two-level merkle tree (file → blocks → segments).
+    /// `file_hash` is the file's BLAKE3 hash, so the file isn't read a second time to hash it.
     pub fn commit_blocked(
         &self,
         file_path: &Path,
         tier: u8,
+        file_hash: &str,
     ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
         info!(
             "COMMIT | (blocked) reading file from {:?} as tier {:?}",
@@ -339,15 +245,21 @@ impl Chunker {
 
         // our file data buffer
 
-        // we're just gonna use mmap because we'd want to for files this size
-        let mmap = Some(unsafe { Mmap::map(&file)? });
+        // we're just gonna use mmap because we'd want to for files this size,
+        // unless asked to keep the file out of the page cache
+        let direct = self
+            .direct_io
+            .then(|| direct_io::open(file_path))
+            .transpose()?;
+        let mmap = match direct {
+            Some(_) => None,
+            None => Some(unsafe { Mmap::map(&file)? }),
+        };
 
         // assigning our file data buffer to our mmap file buffer reference
-        let file_data: &[u8] = mmap
-            .as_ref()
-            .ok_or_else(|| std::io::Error::other("could not copy data into memmap"))?;
-        // using system available memory, getting the sizes of our segments
-        let segment_size = determine_segment_size(file_size as u64)? as usize;
+        let file_data: &[u8] = mmap.as_deref().unwrap_or_default();
+        // using system available memory, getting the sizes of our segments, unless configured otherwise
+        let segment_size = self.segment_policy.segment_size(file_size as u64)?;
         info!("COMMIT | (blocked) segment size: {} bytes", segment_size);
 
         // how many in total segments will be made from our file
@@ -356,13 +268,21 @@ impl Chunker {
 
         // how many blocks will be built with our segments
         // each block needs to have max 30 segments
-        let blocks = (num_segments as f64 / 30.0).ceil() as usize;
+        let blocks = num_segments.div_ceil(BLOCK_SEGMENTS);
         info!("COMMIT | (blocked) total blocks: {}", blocks);
-        info!("COMMIT | (blocked) rs encoder will use 30:3 ratio per block");
+        // a partial last block gets parity in proportion to its segments
+        let full_parity = block_parity_shards(BLOCK_SEGMENTS, self.block_parity_ratio);
+        info!(
+            "COMMIT | (blocked) rs encoder will use {}:{} ratio per full block",
+            BLOCK_SEGMENTS, full_parity
+        );
 
-        let file_hash_placeholder = "computing";
-        let file_dir = self.get_dir(&file_name, &file_hash_placeholder.to_string())?;
         let archive_dir_check = self.check_for_archive_dir()?;
+        // encode into a work dir of our own, it only gets its final name once complete
+        let work = self.work_dir()?;
+        let parity_work = self.parity_work_dir(&file_name)?;
+        let file_dir = work.path().to_path_buf();
+        let parity_blocks_dir = parity_root(&work, &parity_work).join("blocks");
         info!(
             "COMMIT | (blocked) archive_dir check {:?}",
             archive_dir_check
@@ -384,64 +304,83 @@ impl Chunker {
 
             self.create_dir(&current_block_dir)?;
             self.create_dir(&current_block_dir.join("segments"))?;
-            self.create_dir(&current_block_dir.join("parity"))?;
+            self.create_dir(&parity_blocks_dir.join(format!("block_{}/parity", block_index)))?;
             Ok(())
         });
 
+        // blocks finish in any order, so progress counts the bytes done so far
+        let bytes_done = AtomicU64::new(0);
         let block_results: Result<Vec<(String, BlockHashes)>, Box<dyn std::error::Error + Send + Sync>> = (0
             ..blocks)
             .into_par_iter()
             .map(
                 |block_index| -> Result<(String, BlockHashes), Box<dyn std::error::Error + Send + Sync>> {
+                    self.check_cancelled()?;
                     let current_block_dir = blocks_dir.join(format!("block_{}", block_index));
                     let block_segments_dir = current_block_dir.join("segments");
-                    let block_parity_dir = current_block_dir.join("parity");
+                    let block_parity_dir =
+                        parity_blocks_dir.join(format!("block_{}/parity", block_index));
 
-                    let mut block_segments_refs: Vec<&[u8]> = Vec::with_capacity(30);
+                    let mut segment_ranges = Vec::with_capacity(BLOCK_SEGMENTS);
 
-                    for segment_index in 0..30 {
-                        let global_segment = block_index * 30 + segment_index;
+                    for segment_index in 0..BLOCK_SEGMENTS {
+                        let global_segment = block_index * BLOCK_SEGMENTS + segment_index;
 
                         let segment_start = global_segment * segment_size;
-                        let segment_end =
-                            ((global_segment + 1) * segment_size).min(file_data.len());
+                        let segment_end = ((global_segment + 1) * segment_size).min(file_size);
 
-                        if segment_start >= file_data.len() {
+                        if segment_start >= file_size {
                             break;
                         }
 
-                        let segment_data = &file_data[segment_start..segment_end];
-
-                        block_segments_refs.push(segment_data);
+                        segment_ranges.push(segment_start..segment_end);
                     }
 
-                    // fan the disk writes out because serialising 30 files in a row is painful
-                    let hashed_pairs: Vec<(usize, String)> = block_segments_refs
+                    // a direct read holds the block in memory, a mapping only borrows it
+                    let read_segments: Vec<Vec<u8>> = match &direct {
+                        Some(file) => segment_ranges
+                            .iter()
+                            .map(|range| direct_io::read_at(file, range.start as u64, range.len()))
+                            .collect::<Result<_, _>>()?,
+                        None => Vec::new(),
+                    };
+                    let block_segments_refs: Vec<&[u8]> = match &direct {
+                        Some(_) => read_segments.iter().map(Vec::as_slice).collect(),
+                        None => segment_ranges
+                            .iter()
+                            .map(|range| &file_data[range.clone()])
+                            .collect(),
+                    };
+
+                    // the whole block goes to disk as one batch of writes
+                    self.write_block_segments(&block_segments_dir, &block_segments_refs)?;
+                    let segment_hashes = block_segments_refs
                         .par_iter()
-                        .enumerate()
-                        .map(
-                            |(segment_index, segment_data)| -> Result<_, std::io::Error> {
-                                self.write_segment(
-                                    segment_index,
-                                    &block_segments_dir,
-                                    segment_data,
-                                )?;
-                                let hash = blake3_hash_bytes(segment_data)?;
-                                Ok((segment_index, hash))
-                            },
-                        )
+                        .map(|segment_data| blake3_hash_bytes(segment_data))
                         .collect::<Result<Vec<_>, _>>()?;
 
-                    let mut segment_hashes = vec![String::new(); hashed_pairs.len()];
-                    for (idx, hash) in hashed_pairs {
-                        segment_hashes[idx] = hash;
-                    }
-
-                    let parity = self
-                        .generate_parity(&block_segments_refs, block_segments_refs.len(), 3)
+                    let data_shards = block_segments_refs.len();
+                    let shard_size = block_segments_refs
+                        .iter()
+                        .map(|s| s.len())
+                        .max()
+                        .unwrap_or(0)
+                        .div_ceil(64)
+                        * 64;
+                    let parity_shards = block_parity_shards(data_shards, self.block_parity_ratio);
+                    let mut parity = self
+                        .generate_parity(&block_segments_refs, data_shards, parity_shards)
                         .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
                             e.to_string().into()
                         })?;
+                    // local parity per group goes after the global shards
+                    if self.local_group > 0 {
+                        parity.extend(
+                            block_segments_refs
+                                .chunks(self.local_group)
+                                .map(|group| erasure::local_parity(group, shard_size)),
+                        );
+                    }
 
                     self.write_blocked_parities(&block_parity_dir, &parity)?;
 
@@ -450,6 +389,7 @@ impl Chunker {
                     for p in &parity {
                         parity_hashes.push(blake3_hash_bytes(p)?);
                     }
+                    let local_parity = parity_hashes.split_off(parity_shards);
 
                     // For Tier 3, the block root is the Merkle root of its segments AND parity
                     let mut block_leaves = segment_hashes.clone();
@@ -457,9 +397,28 @@ impl Chunker {
                     let block_merkle = MerkleTree::from_hashes(block_leaves)?;
                     let block_root = block_merkle.root.hash_val.to_string();
 
+                    let block_bytes: usize = block_segments_refs.iter().map(|s| s.len()).sum();
+                    let done = bytes_done.fetch_add(block_bytes as u64, Ordering::Relaxed)
+                        + block_bytes as u64;
+                    self.report_progress(&file_name, done, file_size as u64);
+
                     Ok((block_root, BlockHashes {
                         segments: segment_hashes,
                         parity: parity_hashes,
+                        data_shards: Some(data_shards),
+                        parity_shards: Some(parity_shards),
+                        shard_size: Some(shard_size as u64),
+                        segment_sizes: Some(
+                            block_segments_refs.iter().map(|s| s.len() as u64).collect(),
+                        ),
+                        local_group: (self.local_group > 0).then_some(self.local_group),
+                        local_parity,
+                        zero_segments: block_segments_refs
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, segment)| is_zero(segment))
+                            .map(|(idx, _)| idx)
+                            .collect(),
                     }))
                 },
             )
@@ -474,18 +433,14 @@ impl Chunker {
             blocks
         );
 
-        // mmap already handed us the full file, so just hash the slice directly
-        let file_hash = blake3_hash_bytes(file_data)?;
+        let file_hash = file_hash.to_string();
         let file_trun_hash = &file_hash[0..10].to_string();
-        println!("File hash computed: {}", file_trun_hash);
         info!(
             "COMMIT | (blocked) file hash: {:?} for: {:?}",
             file_hash, file_name
         );
 
         let final_file_dir = self.get_dir(&file_name, &file_hash)?;
-        std::fs::rename(&file_dir, &final_file_dir)?;
-        info!("COMMIT | (blocked) renamed directory to include hash");
 
         let root_tree = MerkleTree::from_hashes(block_root_hashes)?;
 
@@ -501,21 +456,25 @@ impl Chunker {
             root: root_tree.root.hash_val.clone(),
         };
 
-        info!(
-            "COMMIT | (blocked) writing manifest to {:?}",
-            &final_file_dir
-        );
+        let head = match &direct {
+            Some(file) => direct_io::read_at(file, 0, file_size.min(SNIFF_LEN))?,
+            None => file_data[..file_size.min(SNIFF_LEN)].to_vec(),
+        };
+        info!("COMMIT | (blocked) writing manifest to {:?}", &file_dir);
         self.write_manifest_struct(
             merkle_tree_struct,
             &file_hash,
             &file_name,
             file_size,
-            30,
-            3,
-            &final_file_dir,
+            BLOCK_SEGMENTS,
+            full_parity,
+            &file_dir,
             tier,
             segment_size as u64,
+            &head,
+            parity_work.as_ref().map(|p| p.path()),
         )?;
+        self.publish_split(work, parity_work, &final_file_dir)?;
         info!(
             "COMMIT | (blocked) {:?} commited successfully to {:?}",
             &file_hash, &final_file_dir
@@ -530,8 +489,9 @@ impl Chunker {
             file_trun_hash: file_trun_hash.clone(),
             file_hash,
             merkle_tree: root_tree,
-            data_shards: self.data_shards,
-            parity_shards: self.parity_shards,
+            data_shards: BLOCK_SEGMENTS,
+            parity_shards: full_parity,
+            memory: None,
         })
     }
 
@@ -550,6 +510,9 @@ impl Chunker {
     /// | 1 GB - 35 GB             | 3    | `commit_blocked`    | RS(30,3) per block |
     /// | > 35 GB (future)         | 4    | `commit_segmented`  | (planned expansion)|
     ///
+    /// The 25 MB and 1 GB boundaries are the defaults for `tier_1_limit` and
+    /// `tier_2_limit`, set from the `[erasure]` config section.
+    ///
     /// # Parameters
     ///
     /// * `file_path` - Path to the file to archive. File is not loaded into memory
@@ -589,32 +552,524 @@ impl Chunker {
     /// - File size is determined via metadata without reading file content
     /// - The function does not modify the original file
     /// - Archive directory is created automatically if it doesn't exist
-    /// - Duplicate files (same hash) will overwrite existing archives
+    /// - A file identical to one archived under another name is recorded as an
+    ///   alias of it instead of being encoded again, see [`crate::alias`]
+    /// - A name that is already archived is handled by [`Chunker::on_existing`]:
+    ///   by default new content is kept next to the older versions and the same
+    ///   content isn't committed again. Versions beyond [`Chunker::max_versions`]
+    ///   are pruned, oldest first
+    /// - When a signing key is configured the manifest is signed into `manifest.sig`
+    /// - Each successful commit is appended to the archive's `audit.log`
+    /// - With [`crate::chunker::ChunkerBuilder::explode_archives`] the members of
+    ///   a `.tar` or `.zip` are indexed in its manifest, see [`crate::container`]
+    /// - With [`crate::chunker::ChunkerBuilder::verify_against`] a file that
+    ///   doesn't match its checksum fails before anything is written
     pub fn commit(&self, file_path: &Path) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        self.commit_as(file_path, None)
+    }
+
+    /// Same as [`Chunker::commit`], but `tier` forces the encoding instead of
+    /// picking it from the file size. Tier 1 holds the whole file in memory, so
+    /// it is refused for files above the tier 2 limit.
+    pub fn commit_as(
+        &self,
+        file_path: &Path,
+        tier: Option<u8>,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let name = file_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or("error getting filename")?
+            .to_string();
+        let mut session = HashSession::new();
+        if let Some(token) = &self.cancel {
+            session = session.cancel_token(token.clone());
+        }
+        if self.checksums.is_some() {
+            session = session.with_sha256();
+        }
+        let digests = match self.direct_io {
+            true => {
+                let total = fs::metadata(file_path)?.len();
+                session.hash_reader(DirectReader::open(file_path)?, Some(total))?
+            }
+            false => session.hash_file(file_path)?,
+        };
+        if let Some(checksums) = &self.checksums {
+            checksums.verify(file_path, &digests)?;
+            info!("COMMIT | {} matches {}", name, checksums.path().display());
+        }
+        let hash = digests.blake3;
+        let members = match ContainerKind::of(file_path) {
+            Some(kind) if self.explode_archives => Some(container::index_members(file_path, kind)?),
+            _ => None,
+        };
+        // a new entry gets the index before it is published, one that is
+        // already archived gets it afterwards
+        let committed = match &members {
+            Some(members) => self.commit_content(&name, &hash, || {
+                self.with_members(members.clone())
+                    .encode_as(file_path, tier, &hash)
+            })?,
+            None => self.commit_content(&name, &hash, || self.encode_as(file_path, tier, &hash))?,
+        };
+        if let Some(members) = members {
+            self.record_members(&committed, members)?;
+        }
+        Ok(committed)
+    }
+
+    /// Commits the content `hash` under `name`, going by
+    /// [`Chunker::on_existing`] and recording a duplicate of another entry as
+    /// an alias. `encode` writes and publishes the entry when one is needed;
+    /// a streamed commit has already encoded it by then and only publishes.
+    /// The chunker's tags are added to whichever entry it ends up with.
+    pub(crate) fn commit_content(
+        &self,
+        name: &str,
+        hash: &str,
+        encode: impl FnOnce() -> Result<ChunkedFile, Box<dyn std::error::Error>>,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let committed = self.commit_entry(name, hash, encode)?;
+        if !self.tags.is_empty() {
+            self.record_tags(&committed)?;
+        }
+        Ok(committed)
+    }
+
+    fn commit_entry(
+        &self,
+        name: &str,
+        hash: &str,
+        encode: impl FnOnce() -> Result<ChunkedFile, Box<dyn std::error::Error>>,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let existing = self.entries_named(name);
+        let (same, others): (Vec<_>, Vec<_>) = existing.into_iter().partition(|(_, h)| h == hash);
+        match self.on_existing {
+            OnExisting::Skip if !same.is_empty() || !others.is_empty() => {
+                let (dir, _) = same.first().or(others.first()).ok_or("no entry")?;
+                info!("COMMIT | {} is already archived, skipping", name);
+                return self.existing_entry(dir, name);
+            }
+            OnExisting::Version | OnExisting::Skip if !same.is_empty() => {
+                info!("COMMIT | {} is already archived with this content", name);
+                return self.existing_entry(&same[0].0, name);
+            }
+            OnExisting::Overwrite => {
+                let committed = self.commit_replacing(name, hash, encode, &same)?;
+                self.remove_replaced(name, &others)?;
+                return Ok(committed);
+            }
+            _ => {}
+        }
+
+        let committed = match self.commit_duplicate(name, hash) {
+            Ok(Some(aliased)) => Ok(aliased),
+            Ok(None) => encode(),
+            Err(e) => Err(e),
+        };
+        let committed = match committed {
+            // a concurrent commit of the same file got there first
+            Err(e) if is_already_exists(e.as_ref()) => {
+                info!(
+                    "COMMIT | {} was committed concurrently, using that entry",
+                    name
+                );
+                return self.existing_entry(&self.get_dir(name, hash)?, name);
+            }
+            other => other?,
+        };
+
+        if self.max_versions > 0 && self.max_versions <= others.len() {
+            let policy = PrunePolicy {
+                keep_last: Some(self.max_versions),
+                older_than: None,
+            };
+            let pruned = FileStore::new(&self.archive_dir)
+                .map_err(Into::into)
+                .and_then(|store| store.prune_versions(Some(name), policy, false));
+            match pruned {
+                Ok(report) => info!(
+                    "COMMIT | pruned {} old versions of {}",
+                    report.removed.len(),
+                    name
+                ),
+                Err(e) => tracing::warn!("COMMIT | pruning old versions of {} failed: {}", name, e),
+            }
+        }
+        Ok(committed)
+    }
+
+    /// Commits `name` in place of `same`, the entry already holding this
+    /// content under this name. The old entry is moved aside first and put
+    /// back if the new commit fails.
+    fn commit_replacing(
+        &self,
+        name: &str,
+        hash: &str,
+        encode: impl FnOnce() -> Result<ChunkedFile, Box<dyn std::error::Error>>,
+        same: &[(std::path::PathBuf, String)],
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let Some((file_dir, _)) = same.first() else {
+            return match self.commit_duplicate(name, hash)? {
+                Some(aliased) => Ok(aliased),
+                None => encode(),
+            };
+        };
+
+        let aside = self.work_dir()?;
+        let previous = aside.path().join("previous");
+        fs::rename(file_dir, &previous)?;
+        let committed = match self.commit_duplicate(name, hash) {
+            Ok(Some(aliased)) => Ok(aliased),
+            Ok(None) => encode(),
+            Err(e) => Err(e),
+        };
+        if committed.is_err() && !file_dir.exists() {
+            fs::rename(&previous, file_dir)?;
+        }
+        if committed.is_ok() {
+            // the old entry goes with `aside`, but its parity may live elsewhere
+            remove_parity_dir(&previous)?;
+            info!("COMMIT | {} overwritten", name);
+        }
+        committed
+    }
+
+    /// Encodes and writes `file_path` without looking for a duplicate first.
+    /// Used where the caller needs real shards of its own, such as packs and
+    /// retiering.
+    ///
+    /// `file_hash` is the BLAKE3 hash of the file's content. Every caller has
+    /// already worked it out, so it is trusted here rather than read again,
+    /// which on tier 3 would be another pass over the whole file.
+    pub(crate) fn encode_as(
+        &self,
+        file_path: &Path,
+        tier: Option<u8>,
+        file_hash: &str,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
         // 1. Get file metadata (doesnt load file)
         let file = File::open(file_path)?;
         let file_size = file.metadata()?.len() as usize;
+        let tier = self.choose_tier(file_size, tier)?;
+
+        self.check_cancelled()?;
+        let probe = MemoryProbe::start();
+        let mut which = match tier {
+            1 => self.commit_tiny(file_path, file_size, tier, file_hash)?,
+            2 => self.commit_segmented(file_path, tier, file_hash)?,
+            _ => self.commit_blocked(file_path, tier, file_hash)?,
+        };
+        which.memory = probe.map(MemoryProbe::finish);
+        if let Some(memory) = &which.memory {
+            info!(
+                "COMMIT | {} used {} heap bytes at peak in {} allocations, peak RSS {:?}",
+                which.file_name, memory.peak_heap_bytes, memory.allocations, memory.peak_rss_bytes
+            );
+        }
 
-        const TIER_1_LIMIT: usize = 25_000_000; // 25MB
-        const TIER_2_LIMIT: usize = 1_000_000_000; // 1GB
+        self.record_commit(which, tier)
+    }
 
-        let tier: u8 = if file_size == 0 {
+    /// The tier a file of `file_size` bytes is encoded as, `tier` if forced.
+    /// Tier 1 holds the whole file in memory, so it is refused above the tier 2 limit.
+    pub(crate) fn choose_tier(
+        &self,
+        file_size: usize,
+        tier: Option<u8>,
+    ) -> Result<u8, Box<dyn std::error::Error>> {
+        if file_size == 0 {
             return Err("empty file".into());
-        } else if file_size <= TIER_1_LIMIT {
-            1
-        } else if file_size <= TIER_2_LIMIT {
-            2
-        } else {
-            3
-        };
+        }
+        Ok(match tier {
+            Some(1) if file_size > self.tier_2_limit => {
+                return Err(format!("{} bytes is too large for tier 1", file_size).into());
+            }
+            Some(tier @ 1..=3) => tier,
+            Some(tier) => return Err(format!("unknown tier {}", tier).into()),
+            None if file_size <= self.tier_1_limit => 1,
+            None if file_size <= self.tier_2_limit => 2,
+            None => 3,
+        })
+    }
 
-        let which = match tier {
-            1 => self.commit_tiny(file_path, file_size, tier)?,
-            2 => self.commit_segmented(file_path, tier)?,
-            3 => self.commit_blocked(file_path, tier)?,
-            _ => self.commit_blocked(file_path, tier)?,
-        };
+    /// Records a freshly encoded entry in the audit log and adds it to the
+    /// global parity if that is on. The entry was signed in its work dir.
+    pub(crate) fn record_commit(
+        &self,
+        which: ChunkedFile,
+        tier: u8,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        AuditLog::for_archive(&self.archive_dir).append(
+            &AuditEntry::new(AuditOp::Commit, &which.file_name)
+                .hash(&which.file_hash)
+                .details(format!("tier {}, {} bytes", tier, which.file_size)),
+        )?;
+
+        if self.global_parity > 0 {
+            // the entry is committed either way, a stale volume is rebuilt later
+            let manifest = which.file_dir.join("manifest.json");
+            let added = FileStore::new(&self.archive_dir)
+                .map_err(Into::into)
+                .and_then(|store| {
+                    let file = crate::filestore::models::File::new(
+                        which.file_name.clone(),
+                        which.file_hash.clone(),
+                        manifest.display().to_string(),
+                    )?;
+                    store.add_to_global_parity(&file, self.global_parity)
+                });
+            if let Err(e) = added {
+                tracing::warn!(
+                    "COMMIT | {} not added to global parity: {}",
+                    which.file_name,
+                    e
+                );
+            }
+        }
 
         Ok(which)
     }
+
+    /// Brings what the global parity keeps of an entry in line with its
+    /// `manifest`, after indexes or tags were added to an entry that was
+    /// already committed. The manifest is changed either way, so a failure
+    /// is only logged.
+    pub(crate) fn refresh_global_parity(&self, manifest: &ManifestFile) {
+        if !self.archive_dir.join(GLOBAL_PARITY_DIR).exists() {
+            return;
+        }
+        let refreshed = FileStore::new(&self.archive_dir)
+            .map_err(Into::into)
+            .and_then(|store| store.refresh_global_parity(manifest));
+        if let Err(e) = refreshed {
+            tracing::warn!(
+                "COMMIT | global parity of {} not refreshed: {}",
+                manifest.name,
+                e
+            );
+        }
+    }
+}
+
+fn is_already_exists(error: &(dyn std::error::Error + 'static)) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::AlreadyExists)
+}
+
+/// Tier 2 encoding, one segment at a time in file order.
+///
+/// [`Chunker::commit_segmented`] pushes slices of a mapped file, a streamed
+/// commit pushes segments as they arrive. The file's hash is only needed by
+/// [`SegmentedCommit::finish`], so a stream can hash on the way in. Segments
+/// are written into a work dir of their own, which only gets its final name
+/// once complete and is removed if the commit is dropped before then.
+pub(crate) struct SegmentedCommit<'a> {
+    chunker: &'a Chunker,
+    work: tempfile::TempDir,
+    /// Set when parity goes under [`super::ChunkerBuilder::parity_dir`].
+    parity_work: Option<tempfile::TempDir>,
+    file_name: String,
+    file_size: usize,
+    segment_size: usize,
+    tier: u8,
+    /// Bytes pushed so far.
+    done: usize,
+    segments: HashMap<usize, SegmentHashes>,
+    segment_roots: Vec<String>,
+    /// The file's first bytes, to classify it by.
+    head: Vec<u8>,
+}
+
+impl<'a> SegmentedCommit<'a> {
+    pub(crate) fn new(
+        chunker: &'a Chunker,
+        file_name: String,
+        file_size: usize,
+        tier: u8,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        info!("COMMIT | (segmented) file size: {} bytes", file_size);
+        info!("COMMIT | (segmented) confirming filename: {:?}", file_name);
+
+        // get an optimised segment size 1mb/8mb/32mb, unless configured otherwise
+        let segment_size = chunker.segment_policy.segment_size(file_size as u64)?;
+        info!("COMMIT | (segmented) segment size: {} bytes", segment_size);
+
+        // file_size    = 10mb - 1000mb
+        // segment_size = 1mb/8mb/32mb
+        // max = 1_000_000_000 + 33_554_432 - 1 / 33_554_432 = 30 segments
+        // 30 segments x 3 parity shards = 90 files generated in total
+        info!(
+            "COMMIT | (segmented) total segments to create: {}",
+            file_size.div_ceil(segment_size)
+        );
+        info!("COMMIT | (segmented) rs encoder will use 1:3 ratio per segment");
+
+        // encode into a work dir of our own, it only gets its final name once complete
+        let work = chunker.work_dir()?;
+        let parity_work = chunker.parity_work_dir(&file_name)?;
+        chunker.create_dir(&parity_root(&work, &parity_work).join("parity"))?;
+        chunker.create_dir(&work.path().join("segments"))?;
+
+        // a check and create function for our archive directory
+        let archive_dir_check = chunker.check_for_archive_dir()?;
+        info!(
+            "COMMIT | (segmented) archive_dir check {:?}",
+            archive_dir_check
+        );
+
+        Ok(Self {
+            chunker,
+            work,
+            parity_work,
+            file_name,
+            file_size,
+            segment_size,
+            tier,
+            done: 0,
+            segments: HashMap::new(),
+            segment_roots: Vec::new(),
+            head: Vec::new(),
+        })
+    }
+
+    /// Byte range of the file the next segment covers, `None` once the whole
+    /// file has been pushed.
+    pub(crate) fn next_range(&self) -> Option<std::ops::Range<usize>> {
+        (self.done < self.file_size)
+            .then(|| self.done..(self.done + self.segment_size).min(self.file_size))
+    }
+
+    /// Encodes and writes the next segment, which must span [`Self::next_range`].
+    pub(crate) fn push(&mut self, segment_data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let chunker = self.chunker;
+        chunker.check_cancelled()?;
+        let expected = self.next_range().ok_or("every segment has been pushed")?;
+        if segment_data.len() != expected.len() {
+            return Err(format!(
+                "segment at byte {} is {} bytes, expected {}",
+                expected.start,
+                segment_data.len(),
+                expected.len()
+            )
+            .into());
+        }
+        let segment_index = self.segments.len();
+        if segment_index == 0 {
+            self.head = segment_data[..segment_data.len().min(SNIFF_LEN)].to_vec();
+        }
+
+        let parity = chunker.generate_parity_segmented(segment_data)?;
+        chunker.write_segment(
+            segment_index,
+            &self.work.path().join("segments"),
+            segment_data,
+        )?;
+        chunker.write_segment_parities(
+            segment_index,
+            &parity_root(&self.work, &self.parity_work).join("parity"),
+            &parity,
+        )?;
+
+        let data_hash = blake3_hash_bytes(segment_data)?;
+        let mut parity_hashes = Vec::new();
+        for p in &parity {
+            parity_hashes.push(blake3_hash_bytes(p)?);
+        }
+
+        let mut segment_leaves = vec![data_hash.clone()];
+        segment_leaves.extend(parity_hashes.iter().cloned());
+        self.segments.insert(
+            segment_index,
+            SegmentHashes {
+                data: data_hash,
+                parity: parity_hashes,
+                zero: is_zero(segment_data),
+            },
+        );
+        let segment_tree = MerkleTree::from_hashes(segment_leaves)?;
+        self.segment_roots.push(segment_tree.root.hash_val);
+
+        self.done = expected.end;
+        chunker.report_progress(&self.file_name, self.done as u64, self.file_size as u64);
+        Ok(())
+    }
+
+    /// Writes the manifest and publishes the entry under `file_hash`, the
+    /// BLAKE3 hash of everything pushed.
+    pub(crate) fn finish(self, file_hash: &str) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let Self {
+            chunker,
+            work,
+            parity_work,
+            file_name,
+            file_size,
+            segment_size,
+            tier,
+            done,
+            segments,
+            segment_roots,
+            head,
+        } = self;
+        if done != file_size {
+            return Err(
+                format!("{} ended after {} of {} bytes", file_name, done, file_size).into(),
+            );
+        }
+
+        let file_hash = file_hash.to_string();
+        let file_trun_hash = file_hash[0..10].to_string();
+        info!(
+            "COMMIT | (segmented) file hash: {:?} for: {:?}",
+            file_hash, file_name
+        );
+
+        // Rename directory to include actual hash
+        let final_file_dir = chunker.get_dir(&file_name, &file_hash)?;
+
+        let num_segments = segment_roots.len();
+        let root_tree = MerkleTree::from_hashes(segment_roots)?;
+        let merkle_tree_struct = MerkleTreeStructure {
+            leaves: HashMap::new(),
+            segments,
+            blocks: HashMap::new(),
+            root: root_tree.root.hash_val.clone(),
+        };
+
+        info!("COMMIT | (segmented) writing manifest to {:?}", work.path());
+        chunker.write_manifest_struct(
+            merkle_tree_struct,
+            &file_hash,
+            &file_name,
+            file_size,
+            6,
+            3,
+            work.path(),
+            tier,
+            segment_size as u64,
+            &head,
+            parity_work.as_ref().map(|p| p.path()),
+        )?;
+        chunker.publish_split(work, parity_work, &final_file_dir)?;
+        info!(
+            "COMMIT | (segmented) {:?} commited successfully to {:?}",
+            &file_hash, &final_file_dir
+        );
+
+        Ok(ChunkedFile {
+            file_name,
+            file_size,
+            segment_size,
+            num_segments,
+            file_dir: final_file_dir,
+            file_trun_hash,
+            file_hash,
+            merkle_tree: root_tree,
+            data_shards: 1,
+            parity_shards: 3,
+            memory: None,
+        })
+    }
 }
@@ -0,0 +1,266 @@
+//! What a commit does when the archive already holds a file of the same name.
+//!
+//! Entries are named `{name}_{hash}`, so the same name with new content gets an
+//! entry of its own, while the same name with the same content maps to an entry
+//! that is already there. [`OnExisting`] decides between keeping every version,
+//! leaving the archive alone, and replacing what is archived under the name.
+//!
+//! Every commit encodes into a hidden `.commit-*` work directory of its own and
+//! only moves it to `{name}_{hash}` once the manifest is written. Two commits of
+//! the same file at once therefore never write into the same directory: the
+//! first to finish publishes its entry and the other finds it there and returns
+//! it instead.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tempfile::TempDir;
+
+use super::{ChunkedFile, Chunker};
+use crate::alias::{self, ALIAS_FILE, Alias};
+use crate::audit::{AuditEntry, AuditLog, AuditOp};
+use crate::filestore::{FileStore, remove_entry_dir};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::naming;
+
+/// Where a commit writes its parity: `parity` from [`Chunker::parity_work_dir`]
+/// if there is one, otherwise the work dir with the rest of the entry.
+pub(crate) fn parity_root<'a>(work: &'a TempDir, parity: &'a Option<TempDir>) -> &'a Path {
+    parity.as_ref().unwrap_or(work).path()
+}
+
+/// Prefix of the work directory a commit encodes into.
+pub const WORK_DIR_PREFIX: &str = ".commit-";
+
+/// What to do when a file is committed under a name that is already archived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExisting {
+    /// Keep every version: new content is committed next to the older entries,
+    /// content that is already archived under the name isn't committed again.
+    #[default]
+    Version,
+    /// Leave the archive as it is whenever the name is already archived.
+    Skip,
+    /// Replace everything archived under the name with the new content.
+    Overwrite,
+}
+
+impl FromStr for OnExisting {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "version" => Ok(Self::Version),
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            other => Err(format!(
+                "unknown policy {:?}, expected version, skip or overwrite",
+                other
+            )),
+        }
+    }
+}
+
+impl ChunkedFile {
+    /// Describes the archived entry in `file_dir`, named `name`, from its
+    /// manifest, or from the original's manifest for an alias.
+    pub(crate) fn from_manifest(
+        file_dir: PathBuf,
+        name: String,
+        manifest: &ManifestFile,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let tree = &manifest.merkle_tree;
+        let hashes: Vec<String> = match manifest.tier {
+            1 => {
+                let mut leaves: Vec<_> = tree.leaves.iter().collect();
+                leaves.sort();
+                leaves.into_iter().map(|(_, hash)| hash.clone()).collect()
+            }
+            2 => {
+                let mut segments: Vec<_> = tree.segments.iter().collect();
+                segments.sort_by_key(|(id, _)| **id);
+                segments
+                    .into_iter()
+                    .map(|(_, segment)| segment.data.clone())
+                    .collect()
+            }
+            _ => {
+                let mut blocks: Vec<_> = tree.blocks.iter().collect();
+                blocks.sort_by_key(|(id, _)| **id);
+                blocks
+                    .into_iter()
+                    .flat_map(|(_, block)| block.segments.iter().cloned())
+                    .collect()
+            }
+        };
+        if hashes.is_empty() {
+            return Err(format!("manifest of {:?} has no hashes", file_dir).into());
+        }
+        let hash = manifest.original_hash.clone();
+        let merkle_tree = MerkleTree::from_hashes(hashes)?;
+        Ok(ChunkedFile {
+            file_name: name,
+            file_size: manifest.size.max(0) as usize,
+            file_dir,
+            file_trun_hash: hash.chars().take(10).collect(),
+            file_hash: hash,
+            num_segments: merkle_tree.leaves.len(),
+            merkle_tree,
+            segment_size: manifest.segment_size as usize,
+            // tiers 1 and 2 record 6 data shards but encode each segment alone
+            data_shards: if manifest.tier < 3 {
+                1
+            } else {
+                manifest.erasure_coding.data_shards as usize
+            },
+            parity_shards: manifest.erasure_coding.parity_shards as usize,
+            memory: None,
+        })
+    }
+}
+
+impl Chunker {
+    /// A fresh hidden directory in the archive for one commit to encode into.
+    /// It is removed again if the commit fails before publishing it.
+    pub(crate) fn work_dir(&self) -> Result<TempDir, io::Error> {
+        fs::create_dir_all(&self.archive_dir)?;
+        tempfile::Builder::new()
+            .prefix(WORK_DIR_PREFIX)
+            .tempdir_in(&self.archive_dir)
+    }
+
+    /// A directory of its own under [`super::ChunkerBuilder::parity_dir`] for
+    /// the parity of one commit of `file_name`, `None` when parity stays in
+    /// the work dir. Like the work dir it is removed if the commit fails.
+    pub(crate) fn parity_work_dir(&self, file_name: &str) -> Result<Option<TempDir>, io::Error> {
+        let Some(parity_dir) = &self.parity_dir else {
+            return Ok(None);
+        };
+        fs::create_dir_all(parity_dir)?;
+        // the manifest records it, so it must not depend on the working directory
+        let parity_dir = std::path::absolute(parity_dir)?;
+        tempfile::Builder::new()
+            .prefix(&naming::entry_dir_name(file_name, ""))
+            .tempdir_in(parity_dir)
+            .map(Some)
+    }
+
+    /// [`Chunker::publish`] for an entry whose parity was written to `parity`,
+    /// which is kept once the entry is in place.
+    pub(crate) fn publish_split(
+        &self,
+        work: TempDir,
+        parity: Option<TempDir>,
+        file_dir: &Path,
+    ) -> Result<(), io::Error> {
+        self.publish(work, file_dir)?;
+        if let Some(parity) = parity {
+            let _ = parity.keep();
+        }
+        Ok(())
+    }
+
+    /// Moves a finished entry from `work` to `file_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] when `file_dir` is already
+    /// there, e.g. published by a concurrent commit of the same file.
+    pub(crate) fn publish(&self, work: TempDir, file_dir: &Path) -> Result<(), io::Error> {
+        let exists = || {
+            io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", file_dir),
+            )
+        };
+        if file_dir.exists() {
+            return Err(exists());
+        }
+        if let Err(e) = fs::rename(work.path(), file_dir) {
+            return Err(if file_dir.exists() { exists() } else { e });
+        }
+        // the directory has moved, leave nothing for the guard to clean up
+        let _ = work.keep();
+        Ok(())
+    }
+
+    /// Entry directories archived under `name`, aliases included, with the
+    /// hash of their content.
+    pub(crate) fn entries_named(&self, name: &str) -> Vec<(PathBuf, String)> {
+        let prefix = naming::entry_dir_name(name, "");
+        let Ok(entries) = fs::read_dir(&self.archive_dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|dir| {
+                dir.file_name()
+                    .map(|dir_name| dir_name.to_string_lossy())
+                    .is_some_and(|dir_name| dir_name.starts_with(&prefix))
+            })
+            .filter_map(|dir| {
+                if alias::is_alias(&dir) {
+                    let json = fs::read_to_string(dir.join(ALIAS_FILE)).ok()?;
+                    let alias: Alias = serde_json::from_str(&json).ok()?;
+                    (alias.name == name).then_some((dir, alias.original_hash))
+                } else {
+                    let manifest =
+                        ManifestFile::new(dir.join("manifest.json").display().to_string()).ok()?;
+                    (manifest.name == name).then_some((dir, manifest.original_hash))
+                }
+            })
+            .collect()
+    }
+
+    /// The already archived entry in `file_dir` as a [`ChunkedFile`].
+    pub(crate) fn existing_entry(
+        &self,
+        file_dir: &Path,
+        name: &str,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let manifest_dir = if alias::is_alias(file_dir) {
+            let alias: Alias =
+                serde_json::from_str(&fs::read_to_string(file_dir.join(ALIAS_FILE))?)?;
+            self.archive_dir.join(alias.target)
+        } else {
+            file_dir.to_path_buf()
+        };
+        let manifest = ManifestFile::new(manifest_dir.join("manifest.json").display().to_string())?;
+        ChunkedFile::from_manifest(file_dir.to_path_buf(), name.to_string(), &manifest)
+    }
+
+    /// Removes the entries in `dirs`, older versions replaced by an overwrite.
+    /// An entry other names are aliases of is kept, as it holds their data.
+    pub(crate) fn remove_replaced(
+        &self,
+        name: &str,
+        dirs: &[(PathBuf, String)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (dir, hash) in dirs {
+            let aliased_by = alias::aliases_of(&self.archive_dir, dir);
+            if !aliased_by.is_empty() {
+                tracing::warn!(
+                    "COMMIT | keeping {:?}, it is the original of {}",
+                    dir,
+                    aliased_by.join(", ")
+                );
+                continue;
+            }
+            FileStore::new(&self.archive_dir)?.forget_global_parity_dir(dir)?;
+            remove_entry_dir(dir)?;
+            tracing::info!("COMMIT | removed {:?}, replaced by the new {}", dir, name);
+            AuditLog::for_archive(&self.archive_dir).append(
+                &AuditEntry::new(AuditOp::Delete, name)
+                    .hash(hash)
+                    .details("replaced by overwrite"),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! Which files under a directory get committed.
+//!
+//! Used by `commit` on a directory and by the daemon's watch folder, so both
+//! pick the same files: a file is taken when it matches an include pattern, or
+//! there are none, and matches no exclude pattern and no line of the
+//! directory's `.blockframeignore`.
+//!
+//! Patterns are globs over the path relative to that directory, with `/`
+//! between components. One without a `/` is checked against the file name for
+//! includes, and against every component for excludes, so `node_modules`
+//! leaves out everything below any directory of that name. One with a `/` is
+//! checked against the whole path, where `**` crosses directories; as an
+//! exclude it also leaves out everything below a directory it matches.
+
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the per directory ignore file, which is never committed itself.
+pub const IGNORE_FILE: &str = ".blockframeignore";
+
+const MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Include and exclude globs, see the module docs.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    /// # Errors
+    ///
+    /// Fails on a pattern that isn't a valid glob.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let compile = |patterns: &[String]| -> Result<Vec<Pattern>, String> {
+            patterns
+                .iter()
+                .map(|p| {
+                    Pattern::new(p.trim_end_matches('/'))
+                        .map_err(|e| format!("bad pattern {:?}: {}", p, e))
+                })
+                .collect()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// This filter plus the patterns in `dir`'s `.blockframeignore`, if it has
+    /// one: one exclude per line, blank lines and `#` comments skipped.
+    pub fn with_ignore_file(&self, dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut filter = self.clone();
+        let text = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(filter),
+            Err(e) => return Err(e.into()),
+        };
+        let lines: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        filter.exclude.extend(Self::new(&[], &lines)?.exclude);
+        Ok(filter)
+    }
+
+    /// Whether the file at `relative`, a path below the filtered directory,
+    /// is taken.
+    pub fn matches(&self, relative: &Path) -> bool {
+        let components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let Some(name) = components.last() else {
+            return false;
+        };
+        if name == IGNORE_FILE {
+            return false;
+        }
+        let path = components.join("/");
+        let hits = |pattern: &Pattern, anywhere: bool| {
+            if pattern.as_str().contains('/') && anywhere {
+                // an excluded directory takes everything below it along
+                (1..=components.len())
+                    .any(|n| pattern.matches_with(&components[..n].join("/"), MATCH))
+            } else if pattern.as_str().contains('/') {
+                pattern.matches_with(&path, MATCH)
+            } else if anywhere {
+                components.iter().any(|c| pattern.matches_with(c, MATCH))
+            } else {
+                pattern.matches_with(name, MATCH)
+            }
+        };
+        (self.include.is_empty() || self.include.iter().any(|p| hits(p, false)))
+            && !self.exclude.iter().any(|p| hits(p, true))
+    }
+
+    /// The files below `dir` this filter and `dir`'s ignore file take, in
+    /// path order. Symlinked directories aren't followed.
+    pub fn walk(&self, dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let filter = self.with_ignore_file(dir)?;
+        let mut files = Vec::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current)? {
+                let entry = entry?;
+                let path = entry.path();
+                let kind = entry.file_type()?;
+                if kind.is_dir() {
+                    pending.push(path);
+                } else if fs::metadata(&path).is_ok_and(|meta| meta.is_file())
+                    && filter.matches(path.strip_prefix(dir)?)
+                {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_include_exclude_and_ignore_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for file in [
+            "a.raw",
+            "b.jpg",
+            "shots/c.raw",
+            "shots/tmp/d.raw",
+            "node_modules/pkg/e.raw",
+            "src/node_modules/f.raw",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"x").unwrap();
+        }
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| {
+                    f.strip_prefix(root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect()
+        };
+
+        let all = PathFilter::default().walk(root).unwrap();
+        assert_eq!(all.len(), 6);
+
+        let raw = PathFilter::new(&["*.raw".into()], &["node_modules".into()]).unwrap();
+        assert_eq!(
+            names(raw.walk(root).unwrap()),
+            ["a.raw", "shots/c.raw", "shots/tmp/d.raw"]
+        );
+        // anchored to the walked directory
+        let top = PathFilter::new(&[], &["node_modules/**".into()]).unwrap();
+        assert_eq!(top.walk(root).unwrap().len(), 5);
+
+        fs::write(root.join(IGNORE_FILE), "# scratch\n\nshots/tmp/\n*.jpg\n").unwrap();
+        assert_eq!(names(raw.walk(root).unwrap()), ["a.raw", "shots/c.raw"]);
+        assert!(PathFilter::new(&["[".into()], &[]).is_err());
+    }
+}
@@ -1,56 +1,30 @@
 use super::Chunker;
 
-use reed_solomon_simd::ReedSolomonEncoder;
 impl Chunker {
-    pub fn get_chunks(&self, file_data: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
-        let total_len = file_data.len();
-        let chunk_size = total_len.div_ceil(6); // Round up to ensure we don't create more than 6 chunks
-
-        let mut chunks = Vec::new();
-
-        for i in 0..6 {
-            let start = i * chunk_size;
-            let end = ((i + 1) * chunk_size).min(total_len);
-
-            if start < total_len {
-                chunks.push(file_data[start..end].to_vec());
-            } else {
-                // If we've exhausted the data, push empty chunks
-                chunks.push(vec![]);
-            }
-        }
-
-        Ok(chunks)
-    }
-
     pub fn generate_parity_segmented(
         &self,
         segment_data: &[u8],
     ) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
-        // create Reed-Solomon encoder
         let data_shards = 1;
         let parity_shards = 3;
         // calculate the padded size, round up to the nearest 64
         let padded_size = segment_data.len().div_ceil(64) * 64;
 
-        // initalise the encoder with the padded size
-        let mut encoder = ReedSolomonEncoder::new(data_shards, parity_shards, padded_size)?;
-
-        if segment_data.len() < padded_size {
+        let parity = if segment_data.len() < padded_size {
             // create a temporary padded vector if strict alignment is needed
             let mut padded_vec = segment_data.to_vec();
             padded_vec.resize(padded_size, 0);
-            encoder.add_original_shard(&padded_vec)?;
+            self.coder.encode(&[&padded_vec], parity_shards)?
         } else {
             // the faster path as most segments will be aligned already
-            encoder.add_original_shard(segment_data)?;
-        }
-        let result = encoder.encode()?;
-        let parity: Vec<Vec<u8>> = result.recovery_iter().map(|shard| shard.to_vec()).collect();
-
-        println!(
-            "Generated {} parity chunks from {} data chunks",
-            parity_shards, data_shards
+            self.coder.encode(&[segment_data], parity_shards)?
+        };
+
+        // called once per segment or block, so kept out of stdout
+        tracing::debug!(
+            "COMMIT | generated {} parity chunks from {} data chunks",
+            parity_shards,
+            data_shards
         );
 
         Ok(parity)
@@ -62,14 +36,17 @@ impl Chunker {
         data_shards: usize,
         parity_shards: usize,
     ) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
-        // Find max chunk size (all chunks must be the same size for RS)
+        // Find max chunk size (all chunks must be the same size for RS), rounded up to
+        // the nearest 64 like the other tiers as the encoder rejects odd shard sizes
         let max_chunk_size = segments
             .iter()
             .map(|chunk| chunk.len())
             .max()
             .ok_or_else(|| {
                 std::io::Error::new(std::io::ErrorKind::InvalidInput, "No chunks provided")
-            })?;
+            })?
+            .div_ceil(64)
+            * 64;
 
         // Pad all data chunks to max size
         let padded_chunks: Vec<Vec<u8>> = segments
@@ -81,23 +58,22 @@ impl Chunker {
             })
             .collect();
 
-        let mut encoder = ReedSolomonEncoder::new(data_shards, parity_shards, max_chunk_size)?;
-
-        // Add all data shards
-        for shard in padded_chunks.iter() {
-            encoder.add_original_shard(shard)?;
+        if padded_chunks.len() != data_shards {
+            return Err(format!(
+                "expected {} data shards, got {}",
+                data_shards,
+                padded_chunks.len()
+            )
+            .into());
         }
-
-        // Encode and get result
-        let result = encoder.encode()?;
-
-        // Extract parity shards
-        let parity_chunks: Vec<Vec<u8>> =
-            result.recovery_iter().map(|shard| shard.to_vec()).collect();
-
-        println!(
-            "Generated {} parity chunks from {} data chunks",
-            parity_shards, data_shards
+        let padded_refs: Vec<&[u8]> = padded_chunks.iter().map(Vec::as_slice).collect();
+        let parity_chunks = self.coder.encode(&padded_refs, parity_shards)?;
+
+        // called once per segment or block, so kept out of stdout
+        tracing::debug!(
+            "COMMIT | generated {} parity chunks from {} data chunks",
+            parity_shards,
+            data_shards
         );
 
         Ok(parity_chunks)
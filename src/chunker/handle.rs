@@ -0,0 +1,102 @@
+//! Committing from async code.
+//!
+//! A commit reads, hashes and encodes the whole file, which would stall a
+//! tokio worker for as long as it takes. [`Chunker::commit_async`] runs it on
+//! the blocking pool instead (tier 3 still spreads its blocks over rayon) and
+//! hands back a [`CommitHandle`] to await.
+//!
+//! The commit checks for cancellation while hashing and between segments and
+//! blocks. A cancelled commit leaves nothing behind, its work directory is
+//! removed as for any other failed commit.
+
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::task::JoinHandle;
+
+use super::{ChunkedFile, Chunker};
+use crate::utils::CancelToken;
+
+type CommitResult = Result<ChunkedFile, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A commit running in the background, see [`Chunker::commit_async`].
+///
+/// Resolves to the committed file. Dropping the handle before then cancels
+/// the commit, so a request handler that goes away takes its commit with it.
+pub struct CommitHandle {
+    task: JoinHandle<CommitResult>,
+    cancel: CancelToken,
+}
+
+impl CommitHandle {
+    /// Asks the commit to stop. It then resolves to an
+    /// [`io::ErrorKind::Interrupted`] error, unless it had already finished.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// A token that cancels this commit, for code that doesn't own the handle.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+}
+
+impl Future for CommitHandle {
+    type Output = CommitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|joined| match joined {
+                Ok(result) => result,
+                Err(e) => Err(format!("commit task failed: {}", e).into()),
+            })
+    }
+}
+
+impl Drop for CommitHandle {
+    fn drop(&mut self) {
+        if !self.task.is_finished() {
+            self.cancel.cancel();
+        }
+    }
+}
+
+impl Chunker {
+    /// Same as [`Chunker::commit_as`], run on tokio's blocking pool.
+    ///
+    /// # Panics
+    ///
+    /// Must be called from within a tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::chunker::Chunker;
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let chunker = Chunker::new()?;
+    /// let committed = chunker.commit_async("movie.mkv", None).await?;
+    /// println!("{}", committed.file_hash);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn commit_async(&self, file_path: impl Into<PathBuf>, tier: Option<u8>) -> CommitHandle {
+        let cancel = CancelToken::new();
+        let mut chunker = self.clone();
+        chunker.cancel = Some(cancel.clone());
+        let file_path = file_path.into();
+        let task = tokio::task::spawn_blocking(move || {
+            chunker
+                .commit_as(&file_path, tier)
+                .map_err(|e| match e.downcast::<io::Error>() {
+                    Ok(e) => e as Box<dyn std::error::Error + Send + Sync>,
+                    Err(e) => e.to_string().into(),
+                })
+        });
+        CommitHandle { task, cancel }
+    }
+}
@@ -5,17 +5,20 @@ use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::{
     fs::{self},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use serde_json::json;
 
+use crate::classify::classify;
 use crate::merkle_tree::MerkleTree;
 use crate::merkle_tree::manifest::MerkleTreeStructure;
+use crate::naming;
+use crate::utils::{write_shard, write_shards};
 impl Chunker {
     pub fn check_for_archive_dir(&self) -> Result<bool, Box<dyn std::error::Error>> {
-        if !Path::new("archive_directory").is_dir() {
-            self.create_dir(Path::new("archive_directory"))?;
+        if !self.archive_dir.is_dir() {
+            self.create_dir(&self.archive_dir)?;
             return Ok(false);
         }
         Ok(true)
@@ -27,13 +30,9 @@ impl Chunker {
         segment_dir: &Path,
         segment: &[u8],
     ) -> Result<(), std::io::Error> {
-        // buffering this so windows doesn't throw a tantrum mid write
+        // preallocated so segments written side by side don't interleave on disk
         let segment_file = segment_dir.join(format!("segment_{}.dat", segment_index));
-        let file = File::create(&segment_file)?;
-        let capacity = segment.len().max(8 * 1024);
-        let mut writer = BufWriter::with_capacity(capacity, file);
-        writer.write_all(segment)?;
-        writer.flush()
+        write_shard(&segment_file, segment)
     }
 
     pub fn write_parity_chunks(
@@ -47,9 +46,7 @@ impl Chunker {
             let parity_filename = format!("parity_{}.dat", index);
             let parity_path = parity_dir.join(parity_filename);
 
-            let file = File::create(&parity_path)?;
-            let mut writer = BufWriter::new(file);
-            writer.write_all(chunk)?;
+            write_shard(&parity_path, chunk)?;
             println!("wrote parity chunk {} ({} bytes)", index, chunk.len());
         }
         Ok(())
@@ -68,11 +65,7 @@ impl Chunker {
             |(index, chunk)| -> Result<(), std::io::Error> {
                 let parity_filename = format!("segment_{}_parity_{}.dat", segment_idx, index);
                 let parity_path = parity_dir.join(parity_filename);
-                let file = File::create(&parity_path)?;
-                let capacity = chunk.len().max(8 * 1024);
-                let mut writer = BufWriter::with_capacity(capacity, file);
-                writer.write_all(chunk)?;
-                writer.flush()?;
+                write_shard(&parity_path, chunk)?;
                 println!("wrote parity chunk {} ({} bytes)", index, chunk.len());
                 Ok(())
             },
@@ -80,38 +73,52 @@ impl Chunker {
         Ok(())
     }
 
+    /// Writes the segments of a tier 3 block in one batch, see
+    /// [`crate::utils::write_shards`].
+    pub fn write_block_segments(
+        &self,
+        segment_dir: &Path,
+        segments: &[&[u8]],
+    ) -> Result<(), std::io::Error> {
+        let shards: Vec<(PathBuf, &[u8])> = segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| (segment_dir.join(format!("segment_{}.dat", index)), *segment))
+            .collect();
+        write_shards(&shards)
+    }
+
     pub fn write_blocked_parities(
         &self,
         parity_dir: &Path,
         parity: &[Vec<u8>],
     ) -> Result<(), std::io::Error> {
-        // TIER 2
+        // TIER 3
 
-        // these parity files are independent so just spray them in parallel
-        parity.par_iter().enumerate().try_for_each(
-            |(index, chunk)| -> Result<(), std::io::Error> {
+        // these parity files are independent, so they go out as one batch
+        let shards: Vec<(PathBuf, &[u8])> = parity
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
                 let parity_filename = format!("block_parity_{}.dat", index);
-                let parity_path = parity_dir.join(parity_filename);
-                let file = File::create(&parity_path)?;
-                let capacity = chunk.len().max(8 * 1024);
-                let mut writer = BufWriter::with_capacity(capacity, file);
-                writer.write_all(chunk)?;
-                writer.flush()?;
-                println!("wrote parity chunk {} ({} bytes)", index, chunk.len());
-                Ok(())
-            },
-        )?;
+                (parity_dir.join(parity_filename), chunk.as_slice())
+            })
+            .collect();
+        write_shards(&shards)?;
+        for (index, chunk) in parity.iter().enumerate() {
+            println!("wrote parity chunk {} ({} bytes)", index, chunk.len());
+        }
         Ok(())
     }
 
     pub fn get_dir(
         &self,
-        file_name: &String,
-        file_hash: &String,
+        file_name: &str,
+        file_hash: &str,
     ) -> Result<std::path::PathBuf, std::io::Error> {
-        let path = format!("archive_directory/{}_{}", file_name, file_hash);
-        let dir = Path::new(&path);
-        Ok(dir.to_path_buf())
+        Ok(self
+            .archive_dir
+            .join(naming::entry_dir_name(file_name, file_hash)))
     }
 
     pub fn create_dir(&self, file_dir: &Path) -> Result<bool, Box<dyn std::error::Error>> {
@@ -123,6 +130,7 @@ impl Chunker {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn write_manifest(
         &self,
         merkle_tree: &MerkleTree,
@@ -134,34 +142,33 @@ impl Chunker {
         file_dir: &Path,
         tier: u8,
         segment_size: u64,
+        head: &[u8],
+        parity_dir: Option<&Path>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let now: DateTime<Utc> = Utc::now();
         let mk_tree = merkle_tree.get_json()?;
-        let manifest = json!({
+        let mut manifest = json!({
             "original_hash": file_hash,
             "name": file_name,
             "size": file_size,
             "time_of_creation":  now.to_string(),
             "erasure_coding": {
-                "type": "reed-solomon",
+                "type": self.coder.name(),
                 "data_shards": data_shards,
                 "parity_shards": parity_shards,
             },
             "merkle_tree": mk_tree,
             "tier": tier,
             "segment_size":segment_size,
-        })
-        .to_string()
-        .into_bytes();
-
-        let manifest_path = file_dir.join("manifest.json");
-        let file = File::create(manifest_path)?;
-        let mut writer = BufWriter::new(file);
-        writer.write_all(&manifest)?;
-        writer.flush()?;
-        Ok(())
+            "content": classify(file_name, head),
+        });
+        if let Some(parity_dir) = parity_dir {
+            manifest["parity_dir"] = json!(parity_dir.display().to_string());
+        }
+        self.save_manifest(manifest, file_dir)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn write_manifest_struct(
         &self,
         merkle_tree_struct: MerkleTreeStructure,
@@ -173,31 +180,63 @@ impl Chunker {
         file_dir: &Path,
         tier: u8,
         segment_size: u64,
+        head: &[u8],
+        parity_dir: Option<&Path>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let now: DateTime<Utc> = Utc::now();
 
-        let manifest = json!({
+        let mut manifest = json!({
             "original_hash": file_hash,
             "name": file_name,
             "size": file_size,
             "time_of_creation":  now.to_string(),
             "erasure_coding": {
-                "type": "reed-solomon",
+                "type": self.coder.name(),
                 "data_shards": data_shards,
                 "parity_shards": parity_shards,
             },
             "merkle_tree": merkle_tree_struct,
             "tier": tier,
             "segment_size":segment_size,
-        })
-        .to_string()
-        .into_bytes();
+            "segment_policy": self.segment_policy.name(),
+            "content": classify(file_name, head),
+        });
+        if let Some(parity_dir) = parity_dir {
+            manifest["parity_dir"] = json!(parity_dir.display().to_string());
+        }
+        self.save_manifest(manifest, file_dir)
+    }
+
+    /// Writes `manifest` with the chunker's tags, pack and member index into
+    /// `file_dir`, the work dir of an entry that isn't published yet, and
+    /// signs it there when a key is configured, so the entry never appears
+    /// under its name unsigned or without its index.
+    fn save_manifest(
+        &self,
+        mut manifest: serde_json::Value,
+        file_dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.tags.is_empty() {
+            manifest["tags"] = json!(self.tags);
+        }
+        if !self.pack.is_empty() {
+            manifest["pack"] = json!(self.pack);
+        }
+        if !self.members.is_empty() {
+            manifest["members"] = json!(self.members);
+        }
+        let manifest = manifest.to_string().into_bytes();
 
         let manifest_path = file_dir.join("manifest.json");
         let file = File::create(manifest_path)?;
         let mut writer = BufWriter::new(file);
         writer.write_all(&manifest)?;
         writer.flush()?;
+        drop(writer);
+
+        if let Some(signer) = &self.signer {
+            signer.sign_dir(file_dir)?;
+        }
         Ok(())
     }
 }
@@ -5,25 +5,69 @@
 //! self-healing repair without requiring the original file.
 //! File chunking and Reed-Solomon erasure coding for self-healing archival storage.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::checksums::ChecksumList;
+use crate::config::{Config, parse_size};
+use crate::erasure::{self, ErasureCoder};
+use crate::memstats::MemoryUsage;
 use crate::merkle_tree::MerkleTree;
-/// Builder and configuration object. Chunker class is used for setting up the paramerters for a chunking operation.
-/// Most fields are Option as those bits of data arent static.
-/// The fields which arent option, they're hardcoded
+use crate::merkle_tree::manifest::{ContainerMember, PackMember};
+use crate::signing::ManifestSigner;
+use crate::tags::Tags;
+use crate::utils::{BLOCK_SEGMENTS, CancelToken, DEFAULT_BLOCK_PARITY_RATIO, SegmentPolicy};
+
+pub use batch::{CommitSummary, DEFAULT_JOBS, expand_paths, expand_paths_with};
+pub use existing::{OnExisting, WORK_DIR_PREFIX};
+pub use filter::{IGNORE_FILE, PathFilter};
+pub use handle::CommitHandle;
+pub use plan::CommitPlan;
+/// Commits files into an archive. Made with [`Chunker::builder`], or
+/// [`Chunker::from_config`] for the settings in `config.toml`, and not changed
+/// afterwards, so one chunker can commit from several threads at once.
+#[derive(Clone)]
 pub struct Chunker {
-    pub file_name: Option<String>,
-    pub file_size: Option<usize>,
-    pub file_dir: Option<PathBuf>,
-    pub file_trun_hash: Option<String>,
-    pub file_hash: Option<String>,
-    pub merkle_tree: Option<MerkleTree>,
-    pub committed: Option<bool>,
-    pub segment_size: Option<usize>,
-    pub num_segments: Option<usize>,
-    pub data_shards: usize,
-    pub parity_shards: usize,
+    archive_dir: PathBuf,
+    tier_1_limit: usize,
+    tier_2_limit: usize,
+    on_existing: OnExisting,
+    max_versions: usize,
+    segment_policy: SegmentPolicy,
+    block_parity_ratio: f64,
+    local_group: usize,
+    coder: &'static dyn ErasureCoder,
+    parity_dir: Option<PathBuf>,
+    global_parity: usize,
+    signer: Option<ManifestSigner>,
+    progress: Option<ProgressSink>,
+    explode_archives: bool,
+    /// Read source files past the page cache, see [`crate::direct_io`].
+    direct_io: bool,
+    /// Added to the manifest of each committed entry, see [`crate::tags`].
+    tags: Tags,
+    /// Checked before each file is committed, see [`crate::checksums`].
+    checksums: Option<Arc<ChecksumList>>,
+    /// Set on the copy [`Chunker::commit_async`] runs.
+    cancel: Option<CancelToken>,
+    /// Set on the copy [`Chunker::commit_pack`] encodes its stream with.
+    pack: Vec<PackMember>,
+    /// Set on the copy a container is encoded with, see [`crate::container`].
+    members: Vec<ContainerMember>,
+}
+
+impl std::fmt::Debug for Chunker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chunker")
+            .field("archive_dir", &self.archive_dir)
+            .field("tier_limits", &self.tier_limits())
+            .field("on_existing", &self.on_existing)
+            .field("segment_policy", &self.segment_policy)
+            .field("coder", &self.coder)
+            .finish_non_exhaustive()
+    }
 }
+
 /// Chunker Result struct.
 /// In contrast to Chunker, all fields are determined to be filled.
 /// Used to return the processed data.
@@ -36,45 +80,417 @@ pub struct ChunkedFile {
     pub merkle_tree: MerkleTree,
     pub segment_size: usize,
     pub num_segments: usize,
+    /// Data shards per Reed-Solomon set: 1 for tiers 1 and 2, which encode
+    /// each segment on its own, and the segments of a full block for tier 3.
     pub data_shards: usize,
+    /// Parity shards per Reed-Solomon set, for a full block on tier 3.
     pub parity_shards: usize,
+    /// Memory the encoding used, when built with the `mem-stats` feature.
+    /// `None` for a file that was already archived, see [`crate::memstats`].
+    pub memory: Option<MemoryUsage>,
+}
+
+/// How far the commit of one file has got, passed to the sink set with
+/// [`ChunkerBuilder::on_progress`].
+#[derive(Debug, Clone, Copy)]
+pub struct CommitProgress<'a> {
+    /// Name of the file being committed.
+    pub file_name: &'a str,
+    /// Bytes of the file encoded and written so far.
+    pub bytes_done: u64,
+    /// Size of the file.
+    pub total_bytes: u64,
+}
+
+/// Receives [`CommitProgress`] as a commit goes. Tier 3 blocks are encoded
+/// in parallel, so it is called from several threads.
+pub type ProgressSink = Arc<dyn Fn(CommitProgress<'_>) + Send + Sync>;
+
+/// Settings for a [`Chunker`], checked when it is built.
+///
+/// # Examples
+///
+/// ```
+/// # use blockframe::chunker::{Chunker, OnExisting};
+/// # use blockframe::utils::SegmentPolicy;
+/// let chunker = Chunker::builder()
+///     .archive_dir("archive_directory")
+///     .tier_limits(10_000_000, 500_000_000)
+///     .segment_policy(SegmentPolicy::Fixed(1 << 20))
+///     .on_existing(OnExisting::Skip)
+///     .build()
+///     .unwrap();
+/// assert_eq!(chunker.tier_limits(), (10_000_000, 500_000_000));
+///
+/// assert!(Chunker::builder().tier_limits(2, 1).build().is_err());
+/// ```
+pub struct ChunkerBuilder {
+    archive_dir: PathBuf,
+    tier_1_limit: usize,
+    tier_2_limit: usize,
+    on_existing: OnExisting,
+    max_versions: usize,
+    segment_policy: SegmentPolicy,
+    block_parity_ratio: f64,
+    local_group: usize,
+    coder: &'static dyn ErasureCoder,
+    parity_dir: Option<PathBuf>,
+    global_parity: usize,
+    signer: Option<ManifestSigner>,
+    progress: Option<ProgressSink>,
+    explode_archives: bool,
+    direct_io: bool,
+    tags: Tags,
+    checksums: Option<Arc<ChecksumList>>,
+}
+
+impl Default for ChunkerBuilder {
+    fn default() -> Self {
+        Self {
+            archive_dir: PathBuf::from("archive_directory"),
+            tier_1_limit: 25_000_000,
+            tier_2_limit: 1_000_000_000,
+            on_existing: OnExisting::Version,
+            max_versions: 0,
+            segment_policy: SegmentPolicy::Adaptive,
+            block_parity_ratio: DEFAULT_BLOCK_PARITY_RATIO,
+            local_group: 0,
+            coder: &erasure::ReedSolomon,
+            parity_dir: None,
+            global_parity: 0,
+            signer: None,
+            progress: None,
+            explode_archives: false,
+            direct_io: false,
+            tags: Tags::new(),
+            checksums: None,
+        }
+    }
+}
+
+impl ChunkerBuilder {
+    /// The settings in `config`: archive directory, tier limits, segment
+    /// size, block, local and global parity, erasure code, parity directory,
+    /// signing key and direct IO.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let tier_1_limit = parse_size(&config.erasure.tier_1_max)
+            .map_err(|e| format!("erasure.tier_1_max: {}", e))?;
+        let tier_2_limit = parse_size(&config.erasure.tier_2_max)
+            .map_err(|e| format!("erasure.tier_2_max: {}", e))?;
+        let segment_policy = config
+            .erasure
+            .segment_size
+            .parse()
+            .map_err(|e| format!("erasure.segment_size: {}", e))?;
+        let coder =
+            erasure::coder(&config.erasure.codec).map_err(|e| format!("erasure.codec: {}", e))?;
+        let signer =
+            ManifestSigner::from_config(&config.signing).map_err(|e| format!("signing: {}", e))?;
+        Ok(Self {
+            archive_dir: config.archive.directory.clone(),
+            tier_1_limit,
+            tier_2_limit,
+            on_existing: config.archive.on_existing,
+            max_versions: config.archive.max_versions,
+            segment_policy,
+            block_parity_ratio: config.erasure.block_parity_ratio,
+            local_group: config.erasure.local_group,
+            coder,
+            parity_dir: config.archive.parity_dir.clone(),
+            global_parity: config.erasure.global_parity,
+            signer,
+            progress: None,
+            explode_archives: false,
+            direct_io: config.io.direct_commit,
+            tags: Tags::new(),
+            checksums: None,
+        })
+    }
+
+    /// Archive the committed files are written into, `archive_directory`
+    /// by default.
+    pub fn archive_dir(mut self, archive_dir: impl Into<PathBuf>) -> Self {
+        self.archive_dir = archive_dir.into();
+        self
+    }
+
+    /// Largest file sizes committed as tier 1 and as tier 2, anything bigger
+    /// is tier 3. 25 MB and 1 GB by default.
+    pub fn tier_limits(mut self, tier_1: usize, tier_2: usize) -> Self {
+        self.tier_1_limit = tier_1;
+        self.tier_2_limit = tier_2;
+        self
+    }
+
+    /// How tier 2 and 3 commits choose their segment size.
+    pub fn segment_policy(mut self, segment_policy: SegmentPolicy) -> Self {
+        self.segment_policy = segment_policy;
+        self
+    }
+
+    /// Parity shards per data shard of each tier 3 block, above 0 and at
+    /// most 1.
+    pub fn block_parity_ratio(mut self, ratio: f64) -> Self {
+        self.block_parity_ratio = ratio;
+        self
+    }
+
+    /// Also give every `group` segments of a tier 3 block a local parity
+    /// shard, so one lost segment is rebuilt from its group alone. 0, the
+    /// default, for none.
+    pub fn local_parity_group(mut self, group: usize) -> Self {
+        self.local_group = group;
+        self
+    }
+
+    /// Also XOR each committed file into an archive-wide parity volume of up
+    /// to `width` files, so a lost entry can be rebuilt from the others, see
+    /// [`crate::filestore::global_parity`]. 0, the default, for none.
+    pub fn global_parity(mut self, width: usize) -> Self {
+        self.global_parity = width;
+        self
+    }
+
+    /// Erasure code the parity is written with, [`erasure::ReedSolomon`] by
+    /// default. Recovery reads it back from each manifest.
+    pub fn erasure_coder(mut self, coder: &'static dyn ErasureCoder) -> Self {
+        self.coder = coder;
+        self
+    }
+
+    /// Writes each entry's parity shards into a directory of its own under
+    /// `parity_dir` instead of next to the data, e.g. on another disk. The
+    /// manifest records where, so reads and repairs find them.
+    pub fn parity_dir(mut self, parity_dir: impl Into<PathBuf>) -> Self {
+        self.parity_dir = Some(parity_dir.into());
+        self
+    }
+
+    /// What a commit does when its file name is already archived.
+    pub fn on_existing(mut self, on_existing: OnExisting) -> Self {
+        self.on_existing = on_existing;
+        self
+    }
+
+    /// Versions kept per file name when new content is committed, 0 for all.
+    pub fn max_versions(mut self, max_versions: usize) -> Self {
+        self.max_versions = max_versions;
+        self
+    }
+
+    /// Signs each manifest at commit time.
+    pub fn signer(mut self, signer: ManifestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Also index the files inside each `.tar` or `.zip` committed, so one
+    /// can be restored without the rest, see [`crate::container`].
+    pub fn explode_archives(mut self, explode_archives: bool) -> Self {
+        self.explode_archives = explode_archives;
+        self
+    }
+
+    /// Reads the files being committed past the page cache, so committing
+    /// large files doesn't evict what other programs have cached. Off by
+    /// default, see [`crate::direct_io`].
+    pub fn direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    /// Tags each committed entry with `tags`, see [`crate::tags`].
+    pub fn tags(mut self, tags: Tags) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Fails the commit of any file that doesn't match its line in
+    /// `checksums`, see [`crate::checksums`].
+    pub fn verify_against(mut self, checksums: ChecksumList) -> Self {
+        self.checksums = Some(Arc::new(checksums));
+        self
+    }
+
+    /// Calls `progress` as each file's commit goes, see [`CommitProgress`].
+    pub fn on_progress(
+        mut self,
+        progress: impl Fn(CommitProgress<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Checks the settings and makes the [`Chunker`].
+    pub fn build(self) -> Result<Chunker, String> {
+        if self.tier_1_limit > self.tier_2_limit {
+            return Err("erasure.tier_1_max must not exceed erasure.tier_2_max".to_string());
+        }
+        if !(self.block_parity_ratio > 0.0 && self.block_parity_ratio <= 1.0) {
+            return Err("erasure.block_parity_ratio must be above 0 and at most 1".to_string());
+        }
+        if self.local_group > BLOCK_SEGMENTS {
+            return Err(format!(
+                "erasure.local_group must be at most {}, the segments of a block",
+                BLOCK_SEGMENTS
+            ));
+        }
+        Ok(Chunker {
+            archive_dir: self.archive_dir,
+            tier_1_limit: self.tier_1_limit,
+            tier_2_limit: self.tier_2_limit,
+            on_existing: self.on_existing,
+            max_versions: self.max_versions,
+            segment_policy: self.segment_policy,
+            block_parity_ratio: self.block_parity_ratio,
+            local_group: self.local_group,
+            coder: self.coder,
+            parity_dir: self.parity_dir,
+            global_parity: self.global_parity,
+            signer: self.signer,
+            progress: self.progress,
+            explode_archives: self.explode_archives,
+            direct_io: self.direct_io,
+            tags: self.tags,
+            checksums: self.checksums,
+            cancel: None,
+            pack: Vec::new(),
+            members: Vec::new(),
+        })
+    }
 }
 
 impl Chunker {
-    /// Creates a new [`Chunker`] instance with default shard counts suitable for
-    /// Reed-Solomon encoding.
-    /// Chunker is initalised so all of the chunker specific functions are accessable withint the class or through a Chunker instance.
+    /// Creates a [`Chunker`] with the default settings, writing into
+    /// `archive_directory`.
     ///
     /// # Examples
     ///
     /// ```
     /// # use blockframe::chunker::Chunker;
     /// let chunker = Chunker::new().unwrap();
-    /// assert_eq!(chunker.data_shards, 6);
-    /// assert_eq!(chunker.parity_shards, 3);
+    /// assert_eq!(chunker.tier_limits(), (25_000_000, 1_000_000_000));
     /// ```
     pub fn new() -> Result<Self, String> {
-        const DATA_SHARDS: usize = 6;
-        const PARITY_SHARDS: usize = 3;
-        Ok(Chunker {
-            file_name: None,
-            file_size: None,
-            segment_size: None,
-            num_segments: None,
-            file_dir: None,
-            file_trun_hash: None,
-            file_hash: None,
-            merkle_tree: None,
-            committed: Some(false),
-            data_shards: DATA_SHARDS,
-            parity_shards: PARITY_SHARDS,
-        })
+        ChunkerBuilder::default().build()
+    }
+
+    /// Starts a [`ChunkerBuilder`] from the default settings.
+    pub fn builder() -> ChunkerBuilder {
+        ChunkerBuilder::default()
+    }
+
+    /// Creates a [`Chunker`] that writes into the configured archive directory
+    /// and uses the configured tier thresholds and signing key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use blockframe::{chunker::Chunker, config::Config};
+    /// let chunker = Chunker::from_config(&Config::default()).unwrap();
+    /// assert_eq!(chunker.tier_limits().0, 25_000_000);
+    /// ```
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        ChunkerBuilder::from_config(config)?.build()
+    }
+
+    /// Archive the committed files are written into.
+    pub fn archive_dir(&self) -> &Path {
+        &self.archive_dir
+    }
+
+    /// Largest file sizes committed as tier 1 and as tier 2.
+    pub fn tier_limits(&self) -> (usize, usize) {
+        (self.tier_1_limit, self.tier_2_limit)
+    }
+
+    /// What a commit does when its file name is already archived.
+    pub fn on_existing(&self) -> OnExisting {
+        self.on_existing
+    }
+
+    /// Versions kept per file name, 0 for all.
+    pub fn max_versions(&self) -> usize {
+        self.max_versions
+    }
+
+    /// How tier 2 and 3 commits choose their segment size.
+    pub fn segment_policy(&self) -> SegmentPolicy {
+        self.segment_policy
+    }
+
+    /// Parity shards per data shard of each tier 3 block.
+    pub fn block_parity_ratio(&self) -> f64 {
+        self.block_parity_ratio
+    }
+
+    /// The key manifests are signed with, if any.
+    pub fn signer(&self) -> Option<&ManifestSigner> {
+        self.signer.as_ref()
+    }
+
+    /// Tags each committed entry gets.
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    /// A copy that writes `pack` into the manifest of each entry it encodes,
+    /// so the index is in place, and signed, before the entry is published.
+    pub(crate) fn with_pack(&self, pack: Vec<PackMember>) -> Self {
+        let mut chunker = self.clone();
+        chunker.pack = pack;
+        chunker
+    }
+
+    /// [`Chunker::with_pack`] for the member index of a container.
+    pub(crate) fn with_members(&self, members: Vec<ContainerMember>) -> Self {
+        let mut chunker = self.clone();
+        chunker.members = members;
+        chunker
+    }
+
+    /// A copy that adds `tags` to its own, e.g. those an entry had before it
+    /// was lost.
+    pub(crate) fn with_tags(&self, tags: &Tags) -> Self {
+        let mut chunker = self.clone();
+        chunker.tags.extend(tags.clone());
+        chunker
+    }
+
+    /// Passes progress on the commit of `file_name` to the progress sink.
+    fn report_progress(&self, file_name: &str, bytes_done: u64, total_bytes: u64) {
+        if let Some(progress) = &self.progress {
+            progress(CommitProgress {
+                file_name,
+                bytes_done,
+                total_bytes,
+            });
+        }
+    }
+
+    /// Fails with [`std::io::ErrorKind::Interrupted`] once the commit has
+    /// been cancelled through its [`CommitHandle`].
+    fn check_cancelled(&self) -> Result<(), std::io::Error> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "commit cancelled",
+            )),
+            _ => Ok(()),
+        }
     }
 }
 
+mod batch;
 mod commit;
+mod existing;
+mod filter;
 mod generate;
+mod handle;
 mod io;
+mod plan;
+#[cfg(feature = "remote")]
+mod url;
 
 #[cfg(test)]
 mod tests;
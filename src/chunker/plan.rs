@@ -0,0 +1,122 @@
+//! What a commit would write, worked out without reading or writing the file.
+
+use std::path::Path;
+
+use super::Chunker;
+use crate::utils::{BLOCK_SEGMENTS, block_parity_shards};
+
+/// The layout [`Chunker::plan`] works out for a file: its tier, how it would be
+/// split, and the bytes its shards would take on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitPlan {
+    pub file_name: String,
+    pub file_size: u64,
+    pub tier: u8,
+    /// 0 on tier 1, where the whole file is one shard.
+    pub segment_size: u64,
+    /// 0 on tier 1.
+    pub segments: usize,
+    /// Tier 3 blocks, 0 on the other tiers.
+    pub blocks: usize,
+    /// Parity shards written in total.
+    pub parity_shards: usize,
+    /// Bytes of the data shards, the size of the file.
+    pub data_bytes: u64,
+    /// Bytes of the parity shards, padding included.
+    pub parity_bytes: u64,
+}
+
+impl CommitPlan {
+    /// Parity bytes per byte of the file, 3.0 for tiers 1 and 2.
+    pub fn overhead(&self) -> f64 {
+        self.parity_bytes as f64 / self.data_bytes.max(1) as f64
+    }
+}
+
+/// Shards are padded to a multiple of 64 bytes before encoding.
+fn padded(len: u64) -> u64 {
+    len.div_ceil(64) * 64
+}
+
+impl Chunker {
+    /// The layout [`Chunker::commit_as`] would give the file at `file_path`,
+    /// from its size alone. Nothing is read or written, and whether the
+    /// file is already archived isn't checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use blockframe::chunker::Chunker;
+    /// # use blockframe::utils::SegmentPolicy;
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("video.bin");
+    /// std::fs::File::create(&path).unwrap().set_len(1_000_000).unwrap();
+    ///
+    /// let chunker = Chunker::builder()
+    ///     .archive_dir(dir.path().join("archive"))
+    ///     .segment_policy(SegmentPolicy::Fixed(256 * 1024))
+    ///     .build()
+    ///     .unwrap();
+    /// let plan = chunker.plan(&path, Some(2)).unwrap();
+    /// assert_eq!((plan.tier, plan.segments, plan.parity_shards), (2, 4, 12));
+    /// assert!(!dir.path().join("archive").exists());
+    /// ```
+    pub fn plan(
+        &self,
+        file_path: &Path,
+        tier: Option<u8>,
+    ) -> Result<CommitPlan, Box<dyn std::error::Error>> {
+        let file_name = file_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or("error getting filename")?
+            .to_string();
+        let file_size = std::fs::metadata(file_path)?.len();
+        let tier = self.choose_tier(file_size as usize, tier)?;
+
+        let mut plan = CommitPlan {
+            file_name,
+            file_size,
+            tier,
+            segment_size: 0,
+            segments: 0,
+            blocks: 0,
+            parity_shards: 3,
+            data_bytes: file_size,
+            parity_bytes: 3 * padded(file_size),
+        };
+        if tier == 1 {
+            return Ok(plan);
+        }
+
+        let segment_size = self.segment_policy.segment_size(file_size)?.max(1) as u64;
+        let segments = file_size.div_ceil(segment_size) as usize;
+        plan.segment_size = segment_size;
+        plan.segments = segments;
+        if tier == 2 {
+            // each segment has 3 parity shards of its own size, the last may be short
+            let last = file_size - (segments.max(1) as u64 - 1) * segment_size;
+            plan.parity_shards = 3 * segments;
+            plan.parity_bytes =
+                3 * (segments.saturating_sub(1) as u64 * padded(segment_size) + padded(last));
+            return Ok(plan);
+        }
+
+        // tier 3 parity shards are the size of the block's largest segment
+        plan.blocks = segments.div_ceil(BLOCK_SEGMENTS);
+        plan.parity_shards = 0;
+        plan.parity_bytes = 0;
+        for block in 0..plan.blocks {
+            let start = (block * BLOCK_SEGMENTS) as u64 * segment_size;
+            let block_segments = (segments - block * BLOCK_SEGMENTS).min(BLOCK_SEGMENTS);
+            let shard_size = padded(segment_size.min(file_size - start));
+            let mut parity = block_parity_shards(block_segments, self.block_parity_ratio);
+            if self.local_group > 0 {
+                parity += block_segments.div_ceil(self.local_group);
+            }
+            plan.parity_shards += parity;
+            plan.parity_bytes += parity as u64 * shard_size;
+        }
+        Ok(plan)
+    }
+}
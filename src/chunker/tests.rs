@@ -5,10 +5,15 @@
 //! - Reed-Solomon encoding correctness
 //! - Manifest generation
 //! - File hash computation
+//! - Zero segments stored as holes
+//! - Verification against a checksum list
+//! - Signing before an entry is published
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use super::super::*;
+    use crate::utils::{SegmentPolicy, blake3_hash_bytes};
     use std::fs::{self, File};
     use std::io::Write;
     use std::path::{Path, PathBuf};
@@ -26,11 +31,16 @@ mod tests {
         path
     }
 
-    /// Helper: Create a chunker instance with test archive directory
-    fn setup_chunker(temp_dir: &Path) -> Chunker {
+    /// Helper: Start a chunker builder with test archive directory
+    fn setup_builder(temp_dir: &Path) -> ChunkerBuilder {
         let archive_dir = temp_dir.join("archive_directory");
         fs::create_dir_all(&archive_dir).unwrap();
-        Chunker::new().unwrap()
+        Chunker::builder().archive_dir(archive_dir)
+    }
+
+    /// Helper: Create a chunker instance with test archive directory
+    fn setup_chunker(temp_dir: &Path) -> Chunker {
+        setup_builder(temp_dir).build().unwrap()
     }
 
     #[test]
@@ -45,8 +55,8 @@ mod tests {
         assert!(result.is_ok());
 
         let chunked = result.unwrap();
-        // Chunker is initialized with 6 data shards, 3 parity - this is the default
-        assert_eq!(chunked.data_shards, 6);
+        // the whole file is one data shard with 3 parity shards
+        assert_eq!(chunked.data_shards, 1);
         assert_eq!(chunked.parity_shards, 3);
     }
 
@@ -67,7 +77,7 @@ mod tests {
 
         let chunked = result.unwrap();
         assert!(chunked.num_segments > 0);
-        assert_eq!(chunked.data_shards, 6);
+        assert_eq!(chunked.data_shards, 1);
         assert_eq!(chunked.parity_shards, 3);
     }
 
@@ -203,4 +213,478 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_commit_many_collects_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_chunker(temp_dir.path());
+
+        let inputs = temp_dir.path().join("inputs");
+        fs::create_dir_all(&inputs).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            create_test_file(&inputs, name, 1000);
+        }
+        let mut paths = expand_paths(&[
+            inputs.join("*.txt"),
+            inputs.join("a.txt"), // duplicate of a glob match
+        ])
+        .unwrap();
+        assert_eq!(paths.len(), 3);
+        paths.push(inputs.join("missing.txt"));
+
+        let summary = chunker.commit_many(&paths, None, 2).unwrap();
+        assert_eq!(summary.committed.len(), 3);
+        assert_eq!(summary.bytes, 3000);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, inputs.join("missing.txt"));
+
+        assert!(expand_paths(&[inputs.join("*.bin")]).is_err());
+
+        // a directory stands for the files below it
+        assert_eq!(
+            expand_paths(std::slice::from_ref(&inputs)).unwrap().len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_segment_policy_is_recorded_and_checked() {
+        use crate::filestore::{FileStore, models::HealthStatus};
+        use crate::merkle_tree::manifest::ManifestFile;
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(256 * 1024))
+            .build()
+            .unwrap();
+        let path = create_test_file(temp_dir.path(), "fixed.bin", 1_000_000);
+        let fixed = chunker.commit_as(&path, Some(2)).unwrap();
+        assert_eq!(fixed.segment_size, 256 * 1024);
+        assert_eq!(fixed.num_segments, 4);
+
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Deterministic)
+            .build()
+            .unwrap();
+        let path = create_test_file(temp_dir.path(), "deterministic.bin", 2_000_000);
+        let chunked = chunker.commit_as(&path, Some(2)).unwrap();
+        assert_eq!(chunked.segment_size, 1024 * 1024);
+
+        let manifest_path = chunked.file_dir.join("manifest.json");
+        let manifest = ManifestFile::new(manifest_path.display().to_string()).unwrap();
+        assert_eq!(manifest.segment_policy.as_deref(), Some("deterministic"));
+        assert!(manifest.check_segment_size().is_ok());
+
+        // a deterministic manifest with a host dependent size is rejected
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        json["segment_size"] = serde_json::json!(8 * 1024 * 1024);
+        fs::write(&manifest_path, json.to_string()).unwrap();
+
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let file = store.find(&"deterministic.bin".to_string()).unwrap();
+        let report = store.health_check(&file).unwrap();
+        assert_eq!(report.status, HealthStatus::Unrecoverable);
+    }
+
+    #[test]
+    fn test_manifests_verify_with_blockframe_core() {
+        use blockframe_core::manifest::{Manifest, Shard};
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(64 * 1024))
+            .build()
+            .unwrap();
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let path = temp_dir.path().join("core.bin");
+
+        for (tier, shard, shard_path) in [
+            (1, Shard::Tiny, "data.dat"),
+            (2, Shard::Segment(3), "segments/segment_3.dat"),
+            (
+                3,
+                Shard::Block(0, 2),
+                "blocks/block_0/segments/segment_2.dat",
+            ),
+        ] {
+            fs::write(&path, &data).unwrap();
+            let chunked = chunker.commit_as(&path, Some(tier)).unwrap();
+            let json = fs::read(chunked.file_dir.join("manifest.json")).unwrap();
+            let manifest = Manifest::parse(&json).unwrap();
+            let root = manifest.computed_root().unwrap();
+            assert_eq!(root, manifest.merkle_tree.root, "tier {}", tier);
+
+            let bytes = fs::read(chunked.file_dir.join(shard_path)).unwrap();
+            assert!(manifest.verify_shard(shard, &bytes, &root).is_ok());
+            assert!(manifest.verify_shard(shard, &bytes[1..], &root).is_err());
+
+            fs::remove_dir_all(&chunked.file_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_plan_matches_what_commit_writes() {
+        fn shard_bytes(dir: &Path, parity: bool) -> u64 {
+            let mut total = 0;
+            for entry in fs::read_dir(dir).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    total += shard_bytes(&path, parity);
+                } else if path.extension().is_some_and(|ext| ext == "dat")
+                    && path.to_string_lossy().contains("parity") == parity
+                {
+                    total += entry.metadata().unwrap().len();
+                }
+            }
+            total
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(20_000))
+            .tier_limits(50_000, 500_000)
+            .build()
+            .unwrap();
+        for (tier, size) in [(1, 1_000), (2, 100_001), (3, 1_000_001)] {
+            let path = create_test_file(temp_dir.path(), &format!("plan{}.bin", tier), size);
+            let plan = chunker.plan(&path, Some(tier)).unwrap();
+            assert!(chunker.entries_named(&plan.file_name).is_empty());
+
+            let chunked = chunker.commit_as(&path, Some(tier)).unwrap();
+            assert_eq!(plan.tier, tier);
+            assert_eq!(plan.segments, chunked.num_segments, "tier {}", tier);
+            assert_eq!(plan.data_bytes, shard_bytes(&chunked.file_dir, false));
+            assert_eq!(plan.parity_bytes, shard_bytes(&chunked.file_dir, true));
+        }
+        let path = create_test_file(temp_dir.path(), "big.bin", 1_000_001);
+        assert_eq!(chunker.plan(&path, None).unwrap().blocks, 2);
+    }
+
+    #[test]
+    fn test_commit_records_content_class() {
+        use crate::filestore::FileStore;
+        use crate::merkle_tree::manifest::ManifestFile;
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(20_000))
+            .tier_limits(50_000, 500_000)
+            .build()
+            .unwrap();
+        let png = |name: &str, size: usize| {
+            let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+            data.resize(size, name.as_bytes()[0]);
+            let path = temp_dir.path().join(name);
+            fs::write(&path, data).unwrap();
+            path
+        };
+        for (tier, name, size) in [
+            (1, "a.PNG", 1_000),
+            (2, "b.png", 100_001),
+            (3, "c", 1_000_001),
+        ] {
+            let chunked = chunker.commit_as(&png(name, size), Some(tier)).unwrap();
+            let manifest =
+                ManifestFile::new(chunked.file_dir.join("manifest.json").display().to_string())
+                    .unwrap();
+            let content = manifest.content.unwrap();
+            assert_eq!(
+                content.detected.as_deref(),
+                Some("image/png"),
+                "tier {}",
+                tier
+            );
+        }
+        let path = create_test_file(temp_dir.path(), "notes.bin", 10);
+        fs::write(&path, [0, 1, 2]).unwrap();
+        chunker.commit(&path).unwrap();
+
+        let report = FileStore::new(chunker.archive_dir())
+            .unwrap()
+            .type_report()
+            .unwrap();
+        let kinds: Vec<_> = report
+            .types
+            .iter()
+            .map(|group| (group.kind.as_str(), group.files))
+            .collect();
+        assert_eq!(kinds, [("image/png", 3), (".bin", 1)]);
+        let files: Vec<_> = report.sizes.iter().map(|bucket| bucket.files).collect();
+        assert_eq!(files, [2, 2, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_on_existing_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_chunker(temp_dir.path());
+        let path = temp_dir.path().join("notes.txt");
+        let entries = |chunker: &Chunker| chunker.entries_named("notes.txt").len();
+
+        // version: the same content again is not a new entry, new content is
+        fs::write(&path, b"first").unwrap();
+        let first = chunker.commit(&path).unwrap();
+        let again = chunker.commit(&path).unwrap();
+        assert_eq!(first.file_dir, again.file_dir);
+        assert_eq!(entries(&chunker), 1);
+        fs::write(&path, b"second").unwrap();
+        chunker.commit(&path).unwrap();
+        assert_eq!(entries(&chunker), 2);
+
+        // skip: nothing changes
+        let chunker = setup_builder(temp_dir.path())
+            .on_existing(OnExisting::Skip)
+            .build()
+            .unwrap();
+        fs::write(&path, b"third").unwrap();
+        let skipped = chunker.commit(&path).unwrap();
+        assert_ne!(skipped.file_hash, blake3_hash_bytes(b"third").unwrap());
+        assert_eq!(entries(&chunker), 2);
+
+        // overwrite: only the new content is left
+        let chunker = setup_builder(temp_dir.path())
+            .on_existing(OnExisting::Overwrite)
+            .build()
+            .unwrap();
+        let replaced = chunker.commit(&path).unwrap();
+        assert_eq!(replaced.file_hash, blake3_hash_bytes(b"third").unwrap());
+        assert_eq!(entries(&chunker), 1);
+        let again = chunker.commit(&path).unwrap();
+        assert_eq!(again.file_dir, replaced.file_dir);
+        assert!(again.file_dir.join("manifest.json").is_file());
+        assert_eq!(entries(&chunker), 1);
+
+        // a version that is the original of an alias is kept
+        let copy = temp_dir.path().join("copy.txt");
+        fs::write(&copy, b"third").unwrap();
+        chunker.commit(&copy).unwrap();
+        fs::write(&path, b"fourth").unwrap();
+        chunker.commit(&path).unwrap();
+        assert_eq!(entries(&chunker), 2);
+        assert!(replaced.file_dir.join("manifest.json").is_file());
+    }
+
+    #[test]
+    fn test_concurrent_commits_of_the_same_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(64 * 1024))
+            .build()
+            .unwrap();
+        let path = create_test_file(temp_dir.path(), "shared.bin", 2_000_000);
+
+        let committed: Vec<ChunkedFile> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| chunker.commit_as(&path, Some(2)).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(
+            committed
+                .iter()
+                .all(|c| c.file_dir == committed[0].file_dir)
+        );
+        assert_eq!(committed[0].num_segments, 31);
+        assert_eq!(chunker.entries_named("shared.bin").len(), 1);
+
+        let leftovers = fs::read_dir(chunker.archive_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with('.'))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn test_progress_reaches_file_size() {
+        use std::sync::{Arc, Mutex};
+
+        let temp_dir = TempDir::new().unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(64 * 1024))
+            .on_progress(move |progress| {
+                sink.lock()
+                    .unwrap()
+                    .push((progress.bytes_done, progress.total_bytes))
+            })
+            .build()
+            .unwrap();
+
+        for (tier, name) in [(1, "first.bin"), (2, "second.bin"), (3, "third.bin")] {
+            seen.lock().unwrap().clear();
+            let path = create_test_file(temp_dir.path(), name, 3_000_000);
+            chunker.commit_as(&path, Some(tier)).unwrap();
+            let mut seen = seen.lock().unwrap().clone();
+            seen.sort();
+            assert!(!seen.is_empty(), "tier {}", tier);
+            assert!(seen.iter().all(|&(_, total)| total == 3_000_000));
+            assert_eq!(seen.last().unwrap().0, 3_000_000, "tier {}", tier);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_commit_async_commits_and_cancels() {
+        use crate::utils::CancelToken;
+        use std::sync::{Arc, Mutex};
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_chunker(temp_dir.path());
+        let path = create_test_file(temp_dir.path(), "async.bin", 500_000);
+        let committed = chunker.commit_async(&path, None).await.unwrap();
+        assert!(committed.file_dir.exists());
+
+        // the first progress report waits for the handle's token, then cancels
+        let token: Arc<Mutex<Option<CancelToken>>> = Arc::default();
+        let sink = Arc::clone(&token);
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(64 * 1024))
+            .on_progress(move |_| {
+                loop {
+                    if let Some(token) = sink.lock().unwrap().as_ref() {
+                        token.cancel();
+                        return;
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+            })
+            .build()
+            .unwrap();
+        let path = create_test_file(temp_dir.path(), "cancelled.bin", 1_000_000);
+        let handle = chunker.commit_async(&path, Some(2));
+        *token.lock().unwrap() = Some(handle.cancel_token());
+        let err = handle.await.err().unwrap();
+        let err = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+
+        // nothing is left of the cancelled commit
+        let entries: Vec<_> = fs::read_dir(chunker.archive_dir())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("cancelled.bin") || name.starts_with('.'))
+            .collect();
+        assert!(entries.is_empty(), "{:?}", entries);
+    }
+
+    #[test]
+    fn test_zero_segments_are_marked_and_stored_as_holes() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .segment_policy(SegmentPolicy::Fixed(64 * 1024))
+            .build()
+            .unwrap();
+        let store = crate::filestore::FileStore::new(chunker.archive_dir()).unwrap();
+        // one segment of data, then three of zeros and a short data tail
+        let mut data = vec![7u8; 64 * 1024];
+        data.resize(4 * 64 * 1024, 0);
+        data.extend_from_slice(b"tail");
+        let path = temp_dir.path().join("disk.img");
+        fs::write(&path, &data).unwrap();
+
+        for tier in [2, 3] {
+            chunker.commit_as(&path, Some(tier)).unwrap();
+            let file = store.refresh().unwrap().pop().unwrap();
+            let entry = Path::new(&file.file_data.path)
+                .parent()
+                .unwrap()
+                .to_path_buf();
+            let tree = &file.manifest.merkle_tree;
+            let (zero_segment, zeros) = match tier {
+                2 => {
+                    let zeros: Vec<usize> = (0..tree.segments.len())
+                        .filter(|idx| tree.segments[idx].zero)
+                        .collect();
+                    (entry.join("segments/segment_2.dat"), zeros)
+                }
+                _ => (
+                    entry.join("blocks/block_0/segments/segment_2.dat"),
+                    tree.blocks[&0].zero_segments.clone(),
+                ),
+            };
+            assert_eq!(zeros, [1, 2, 3]);
+            assert_eq!(fs::metadata(&zero_segment).unwrap().len(), 64 * 1024);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                assert!(fs::metadata(&zero_segment).unwrap().blocks() < 8);
+            }
+
+            // marked segments read as zeros without their file
+            fs::remove_file(&zero_segment).unwrap();
+            let mut out = Vec::new();
+            store.reconstruct_to(&file, &mut out).unwrap();
+            assert_eq!(out, data);
+            store.repair(&file).unwrap();
+            assert_eq!(fs::read(&zero_segment).unwrap(), vec![0; 64 * 1024]);
+
+            fs::remove_dir_all(entry).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_against_fails_files_that_dont_match() {
+        use crate::checksums::ChecksumList;
+        use crate::utils::HashSession;
+
+        let temp_dir = TempDir::new().unwrap();
+        let good = create_test_file(temp_dir.path(), "good.bin", 10_000);
+        let bad = create_test_file(temp_dir.path(), "bad.bin", 10_000);
+        let unlisted = create_test_file(temp_dir.path(), "unlisted.bin", 10_000);
+        let sha256 = |path: &Path| HashSession::new().with_sha256().hash_file(path).unwrap();
+        let list = temp_dir.path().join("SHA256SUMS");
+        fs::write(
+            &list,
+            format!(
+                "{}  good.bin\n{}  ./bad.bin\n",
+                sha256(&good).sha256.unwrap(),
+                sha256(&good).sha256.unwrap()
+            ),
+        )
+        .unwrap();
+        let chunker = setup_builder(temp_dir.path())
+            .verify_against(ChecksumList::from_file(&list).unwrap())
+            .build()
+            .unwrap();
+
+        chunker.commit(&good).unwrap();
+        let err = chunker.commit(&bad).err().unwrap().to_string();
+        assert!(err.contains("doesn't match"), "{}", err);
+        let err = chunker.commit(&unlisted).err().unwrap().to_string();
+        assert!(err.contains("isn't listed"), "{}", err);
+
+        // nothing was written for the files that failed
+        let entries: Vec<_> = fs::read_dir(chunker.archive_dir())
+            .unwrap()
+            .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+            .filter(|name| name.contains(".bin"))
+            .collect();
+        assert_eq!(entries.len(), 1, "{:?}", entries);
+        assert!(entries[0].starts_with("good.bin"));
+    }
+
+    #[test]
+    fn test_entries_are_signed_before_they_are_published() {
+        use crate::merkle_tree::manifest::ManifestFile;
+        use crate::signing::{ManifestSigner, read_signature};
+
+        let temp_dir = TempDir::new().unwrap();
+        let signer = ManifestSigner::generate();
+        let verifier = signer.verifier(true);
+        let chunker = setup_builder(temp_dir.path())
+            .signer(signer)
+            .build()
+            .unwrap();
+
+        for (name, size, tier) in [("tiny.bin", 10_000, 1), ("segmented.bin", 3_000_000, 2)] {
+            let path = create_test_file(temp_dir.path(), name, size);
+            let chunked = chunker.commit_as(&path, Some(tier)).unwrap();
+            let manifest_path = chunked.file_dir.join("manifest.json");
+            let manifest = ManifestFile::new(manifest_path.display().to_string()).unwrap();
+            verifier
+                .verify(&manifest, read_signature(&manifest_path).as_deref())
+                .unwrap();
+        }
+    }
 }
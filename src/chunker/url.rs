@@ -0,0 +1,397 @@
+//! Committing a file straight from an HTTP(S) URL.
+//!
+//! The body is hashed as it arrives, and where the tier allows it is encoded
+//! as it arrives too. A tier 1 file is held in memory like any tier 1 commit,
+//! and a tier 2 file is cut into segments on the way in, so neither is written
+//! out whole before encoding. Tier 3 encodes blocks of segments in parallel
+//! from a mapped file, so a file that large, or one whose size the server
+//! doesn't send, is spooled into a work dir in the archive first.
+//!
+//! A download that drops part way is picked up where it stopped with a
+//! `Range` request, provided the server takes them and can tell whether the
+//! object changed since (`If-Range`). Otherwise it fails rather than splice
+//! two versions of the object together.
+
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+
+use blake3::Hasher;
+use percent_encoding::percent_decode_str;
+use tracing::{info, warn};
+
+use super::commit::SegmentedCommit;
+use super::{ChunkedFile, Chunker, OnExisting};
+
+/// Times a download picks up again after its connection drops.
+const MAX_RESUMES: usize = 5;
+
+/// Bytes read from the connection at a time when spooling.
+const SPOOL_BUFFER: usize = 1 << 20;
+
+impl Chunker {
+    /// Downloads `url` and commits it as `name`, or as the last segment of
+    /// the URL's path. `tier` forces the encoding as in [`Chunker::commit_as`],
+    /// and everything else about the commit (duplicates, versions, signing,
+    /// the audit log) is as for a local file.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::chunker::Chunker;
+    ///
+    /// let chunker = Chunker::new()?;
+    /// let committed = chunker.commit_url("https://example.com/big.iso", None, None)?;
+    /// println!("{} committed as {}", committed.file_name, committed.file_hash);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn commit_url(
+        &self,
+        url: &str,
+        name: Option<&str>,
+        tier: Option<u8>,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => name_from_url(url)?,
+        };
+        if name.starts_with('.') || std::path::Path::new(&name).file_name() != Some(name.as_ref()) {
+            return Err(format!("invalid file name {:?}", name).into());
+        }
+        // nothing is downloaded for a name that would be skipped anyway
+        if self.on_existing == OnExisting::Skip
+            && let Some((dir, _)) = self.entries_named(&name).first()
+        {
+            info!("COMMIT | {} is already archived, skipping", name);
+            return self.existing_entry(dir, &name);
+        }
+
+        let mut body = UrlReader::open(url)?;
+        let size = body.len().map(|len| len as usize);
+        let streamed_tier = match size {
+            Some(size) => Some(self.choose_tier(size, tier)?),
+            None => None,
+        };
+        info!(
+            "COMMIT | downloading {} as {} ({})",
+            url,
+            name,
+            size.map_or("size unknown".to_string(), |size| format!("{} bytes", size))
+        );
+
+        match (size, streamed_tier) {
+            (Some(size), Some(1)) => {
+                let mut data = Vec::with_capacity(size);
+                self.read_body(&mut body, &name, size as u64, &mut data)?;
+                let hash = blake3::hash(&data).to_string();
+                self.commit_content(&name, &hash, || {
+                    let committed = self.commit_tiny_bytes(data, name.clone(), 1, &hash)?;
+                    self.record_commit(committed, 1)
+                })
+            }
+            (Some(size), Some(2)) => {
+                let mut encoder = SegmentedCommit::new(self, name.clone(), size, 2)?;
+                let mut hasher = Hasher::new();
+                let mut segment = Vec::new();
+                while let Some(range) = encoder.next_range() {
+                    segment.resize(range.len(), 0);
+                    body.read_exact(&mut segment)?;
+                    hasher.update(&segment);
+                    encoder.push(&segment)?;
+                }
+                let hash = hasher.finalize().to_string();
+                self.commit_content(&name, &hash, || {
+                    let committed = encoder.finish(&hash)?;
+                    self.record_commit(committed, 2)
+                })
+            }
+            _ => {
+                let work = self.work_dir()?;
+                let path = work.path().join(&name);
+                let mut spool = BufWriter::new(fs::File::create(&path)?);
+                let received =
+                    self.read_body(&mut body, &name, size.unwrap_or(0) as u64, &mut spool)?;
+                spool.flush()?;
+                drop(spool);
+                info!(
+                    "COMMIT | spooled {} bytes of {} to {:?}",
+                    received, name, path
+                );
+                // the hash was taken on the way in, so the spool isn't read twice
+                let hash = body.hash();
+                self.commit_content(&name, &hash, || self.encode_as(&path, tier, &hash))
+            }
+        }
+    }
+
+    /// Copies the rest of `body` into `out`, checking for cancellation and
+    /// reporting progress against `total` as it goes. Returns the bytes copied.
+    fn read_body<W: Write>(
+        &self,
+        body: &mut UrlReader,
+        name: &str,
+        total: u64,
+        out: &mut W,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut buffer = vec![0u8; SPOOL_BUFFER];
+        let mut received = 0u64;
+        loop {
+            self.check_cancelled()?;
+            let read = body.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(received);
+            }
+            out.write_all(&buffer[..read])?;
+            received += read as u64;
+            self.report_progress(name, received, total.max(received));
+        }
+    }
+}
+
+/// The last segment of `url`'s path, percent-decoded.
+fn name_from_url(url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+    let last = path
+        .split_once('/')
+        .map(|(_, path)| path.rsplit('/').next().unwrap_or(""))
+        .unwrap_or("");
+    let name = percent_decode_str(last).decode_utf8()?.to_string();
+    if name.is_empty() {
+        return Err(format!("{} doesn't end in a file name, give one with --name", url).into());
+    }
+    Ok(name)
+}
+
+/// The body of a GET of a URL, resumed with `Range` requests when the
+/// connection drops, and hashed as it is read.
+struct UrlReader {
+    agent: ureq::Agent,
+    url: String,
+    /// `Content-Length` of the first response.
+    len: Option<u64>,
+    /// Strong `ETag` or `Last-Modified` of the first response, sent as
+    /// `If-Range` so a changed object isn't resumed.
+    validator: Option<String>,
+    /// Whether the server said it takes byte ranges.
+    ranges: bool,
+    body: Option<ureq::BodyReader<'static>>,
+    received: u64,
+    resumes: usize,
+    hasher: Hasher,
+}
+
+impl UrlReader {
+    /// Starts the download. Error statuses fail here.
+    fn open(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let agent = ureq::agent();
+        // compressed transfers would make the offsets in a Range meaningless
+        let response = agent
+            .get(url)
+            .header("Accept-Encoding", "identity")
+            .call()?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let len = header("Content-Length").and_then(|len| len.parse().ok());
+        let ranges = header("Accept-Ranges").is_some_and(|ranges| ranges == "bytes");
+        let validator = header("ETag")
+            .filter(|etag| !etag.starts_with("W/"))
+            .or_else(|| header("Last-Modified"));
+        Ok(Self {
+            agent,
+            url: url.to_string(),
+            len,
+            validator,
+            ranges,
+            body: Some(response.into_body().into_reader()),
+            received: 0,
+            resumes: 0,
+            hasher: Hasher::new(),
+        })
+    }
+
+    /// Size of the object, if the server sent it.
+    fn len(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// BLAKE3 hash of everything read so far.
+    fn hash(&self) -> String {
+        self.hasher.finalize().to_string()
+    }
+
+    /// Asks for the rest of the object from `received` on.
+    fn resume(&mut self, cause: io::Error) -> io::Result<()> {
+        let (true, Some(validator)) = (self.ranges, &self.validator) else {
+            return Err(io::Error::new(
+                cause.kind(),
+                format!(
+                    "{} dropped after {} bytes and the server can't resume it: {}",
+                    self.url, self.received, cause
+                ),
+            ));
+        };
+        if self.resumes == MAX_RESUMES {
+            return Err(cause);
+        }
+        self.resumes += 1;
+        warn!(
+            "COMMIT | {} dropped after {} bytes, resuming: {}",
+            self.url, self.received, cause
+        );
+        let response = self
+            .agent
+            .get(&self.url)
+            .header("Accept-Encoding", "identity")
+            .header("Range", format!("bytes={}-", self.received))
+            .header("If-Range", validator)
+            .call()
+            .map_err(io::Error::other)?;
+        // a full 200 means the object changed since, or the range was ignored
+        if response.status() != 206 {
+            return Err(io::Error::other(format!(
+                "{} changed while it was downloading",
+                self.url
+            )));
+        }
+        self.body = Some(response.into_body().into_reader());
+        Ok(())
+    }
+}
+
+impl Read for UrlReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(body) = self.body.as_mut() else {
+                return Ok(0);
+            };
+            let cause = match body.read(buf) {
+                Ok(0) if self.len.is_some_and(|len| self.received < len) => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("connection closed at byte {}", self.received),
+                ),
+                Ok(read) => {
+                    self.hasher.update(&buf[..read]);
+                    self.received += read as u64;
+                    if read == 0 {
+                        self.body = None;
+                    }
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => e,
+            };
+            self.body = None;
+            self.resume(cause)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    /// Serves `data` with ranges and an ETag. The first response hangs up
+    /// half way through the body.
+    fn serve(data: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/files/big%20file.bin?x=1",
+            listener.local_addr().unwrap()
+        );
+        std::thread::spawn(move || {
+            let mut dropped = false;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut start = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(range) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        start = range.trim().trim_end_matches('-').parse().unwrap();
+                    }
+                }
+                let body = &data[start..];
+                let status = if start > 0 {
+                    "206 Partial Content"
+                } else {
+                    "200 OK"
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let sent = if dropped { body.len() } else { body.len() / 2 };
+                dropped = true;
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body[..sent]);
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_name_from_url() {
+        assert_eq!(
+            name_from_url("https://example.com/isos/big.iso").unwrap(),
+            "big.iso"
+        );
+        assert_eq!(
+            name_from_url("http://h/a%20b.txt?dl=1#top").unwrap(),
+            "a b.txt"
+        );
+        assert!(name_from_url("https://example.com/").is_err());
+        assert!(name_from_url("https://example.com").is_err());
+    }
+
+    #[test]
+    fn test_commit_url_streams_and_resumes() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .tier_limits(50_000, 1_000_000)
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+
+        // tier 1, tier 2 and the spooled tier 3, each dropped once on the way
+        for (size, tier) in [(30_000u32, 1), (200_000, 2), (1_100_000, 3)] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let committed = chunker
+                .commit_url(&serve(data.clone()), None, None)
+                .unwrap();
+            assert_eq!(committed.file_name, "big file.bin");
+            assert_eq!(committed.file_size, data.len());
+            assert_eq!(committed.file_hash, blake3::hash(&data).to_string());
+
+            let store = crate::filestore::FileStore::new(chunker.archive_dir()).unwrap();
+            let file = store
+                .find_version("big file.bin", &committed.file_hash[..10])
+                .unwrap();
+            assert_eq!(file.manifest.tier, tier);
+            let mut restored = Vec::new();
+            store.reconstruct_to(&file, &mut restored).unwrap();
+            assert_eq!(restored, data);
+        }
+
+        // nothing but the entries is left in the archive
+        let hidden = fs::read_dir(chunker.archive_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with('.'))
+            .count();
+        assert_eq!(hidden, 0);
+    }
+}
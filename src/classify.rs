@@ -0,0 +1,184 @@
+//! What kind of file an archive entry holds.
+//!
+//! Each commit records the name's extension and the type the first bytes of
+//! the file show in the manifest's `content`, so `blockframe stats --by-type`
+//! can say what is taking the space without reading any shards. Entries
+//! committed before that are grouped by extension alone.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::filestore::FileStore;
+use crate::merkle_tree::manifest::{ContentClass, ManifestFile};
+
+/// Bytes [`sniff`] looks at, enough for a tar header.
+pub const SNIFF_LEN: usize = 512;
+
+/// Upper bounds of the [`TypeReport::sizes`] buckets, the last one is open.
+pub const SIZE_BUCKETS: [u64; 5] = [64 << 10, 1 << 20, 16 << 20, 256 << 20, 4 << 30];
+
+/// The class of a file called `name` whose first bytes are `head`. Only the
+/// first [`SNIFF_LEN`] bytes are looked at.
+///
+/// ```
+/// # use blockframe::classify::classify;
+/// let class = classify("Scan.PDF", b"%PDF-1.7");
+/// assert_eq!(class.extension.as_deref(), Some("pdf"));
+/// assert_eq!(class.detected.as_deref(), Some("application/pdf"));
+/// ```
+pub fn classify(name: &str, head: &[u8]) -> ContentClass {
+    let extension = Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let detected = sniff(&head[..head.len().min(SNIFF_LEN)])
+        .map(|kind| kind.split(';').next().unwrap_or(kind).to_string());
+    ContentClass {
+        extension,
+        detected,
+    }
+}
+
+/// The group [`FileStore::type_report`] counts an entry in: the detected
+/// type, else `.` and the extension, else `unknown`.
+pub fn kind_of(manifest: &ManifestFile) -> String {
+    let class = match &manifest.content {
+        Some(class) => class.clone(),
+        None => classify(&manifest.name, &[]),
+    };
+    match (class.detected, class.extension) {
+        (Some(detected), _) => detected,
+        (None, Some(extension)) => format!(".{}", extension),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+/// The type of a file starting with `head`, by its signature.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\x00", "application/x-xz"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+        (b"SQLite format 3\x00", "application/vnd.sqlite3"),
+        (b"\x7fELF", "application/x-executable"),
+    ];
+    if let Some((_, kind)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(kind);
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") {
+        match &head[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            b"AVI " => return Some("video/x-msvideo"),
+            _ => {}
+        }
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+    if head.len() >= 262 && &head[257..262] == b"ustar" {
+        return Some("application/x-tar");
+    }
+    // text if it decodes, allowing for a character cut off at the end
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    text.filter(|text| !text.is_empty() && !text.contains('\0'))
+        .map(|_| "text/plain; charset=utf-8")
+}
+
+/// Entries and bytes of one group in a [`TypeReport`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    pub kind: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// What [`FileStore::type_report`] found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeReport {
+    /// Largest first.
+    pub types: Vec<TypeStats>,
+    /// One per [`SIZE_BUCKETS`] bound plus one for larger files, `kind`
+    /// naming the bucket.
+    pub sizes: Vec<TypeStats>,
+}
+
+impl FileStore {
+    /// Archived bytes by content type and by file size. Aliases are left
+    /// out, their bytes are counted once under the entry they point at.
+    pub fn type_report(&self) -> Result<TypeReport, Box<dyn std::error::Error>> {
+        let mut types: BTreeMap<String, TypeStats> = BTreeMap::new();
+        let mut sizes: Vec<TypeStats> = SIZE_BUCKETS
+            .iter()
+            .map(|bound| format!("< {}", human_size(*bound)))
+            .chain([format!(">= {}", human_size(SIZE_BUCKETS[4]))])
+            .map(|kind| TypeStats {
+                kind,
+                ..TypeStats::default()
+            })
+            .collect();
+        for file in self.get_all()? {
+            if file.alias_of.is_some() {
+                continue;
+            }
+            let bytes = file.manifest.size.max(0) as u64;
+            let kind = kind_of(&file.manifest);
+            let group = types.entry(kind.clone()).or_insert_with(|| TypeStats {
+                kind,
+                ..TypeStats::default()
+            });
+            group.files += 1;
+            group.bytes += bytes;
+            let bucket = SIZE_BUCKETS
+                .iter()
+                .position(|bound| bytes < *bound)
+                .unwrap_or(SIZE_BUCKETS.len());
+            sizes[bucket].files += 1;
+            sizes[bucket].bytes += bytes;
+        }
+        let mut types: Vec<TypeStats> = types.into_values().collect();
+        types.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.kind.cmp(&b.kind)));
+        Ok(TypeReport { types, sizes })
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{}GB", b >> 30),
+        b if b >= 1 << 20 => format!("{}MB", b >> 20),
+        b => format!("{}KB", b >> 10),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_by_extension_and_signature() {
+        let class = classify("photo", b"\x89PNG\r\n\x1a\n....");
+        assert_eq!(class.extension, None);
+        assert_eq!(class.detected.as_deref(), Some("image/png"));
+        // parameters are dropped
+        let class = classify("notes.TXT", b"hello");
+        assert_eq!(class.extension.as_deref(), Some("txt"));
+        assert_eq!(class.detected.as_deref(), Some("text/plain"));
+        assert_eq!(classify("blob", &[0, 1, 255]), ContentClass::default());
+        assert_eq!(human_size(SIZE_BUCKETS[1]), "1MB");
+    }
+}
@@ -1,52 +1,650 @@
 use serde::Deserialize;
 use std::{
-    fs,
+    env, fs,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
-#[derive(Debug, Deserialize)]
+use crate::chunker::OnExisting;
+use crate::erasure::RsEngine;
+use crate::filestore::Integrity;
+use crate::filestore::models::HealthStatus;
+use crate::mount::options::DEFAULT_MOUNT_OPTIONS;
+use crate::utils::DEFAULT_BLOCK_PARITY_RATIO;
+
+/// Environment variable pointing at a config file, checked after `--config`.
+pub const CONFIG_ENV: &str = "BLOCKFRAME_CONFIG";
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
 pub struct Config {
     pub archive: ArchiveConfig,
     pub mount: MountConfig,
+    pub remote: RemoteConfig,
     pub cache: CacheConfig,
+    pub erasure: ErasureConfig,
+    pub io: IoConfig,
     pub server: ServerConfig,
     pub logging: LoggingConfig,
+    pub daemon: DaemonConfig,
+    pub signing: SigningConfig,
+    pub notify: NotifyConfig,
+    /// The file this config was read from, `None` when running on defaults.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct ArchiveConfig {
     pub directory: PathBuf,
+    /// What a commit does when its file name is already archived.
+    pub on_existing: OnExisting,
+    /// Versions kept per file name, older ones are pruned at commit time. 0
+    /// keeps every version.
+    pub max_versions: usize,
+    /// Directory new commits write their parity shards under, to keep them
+    /// on another disk than the data. Unset keeps parity in each entry.
+    pub parity_dir: Option<PathBuf>,
+    /// Days `rm` keeps removed files in the trash before they are purged. 0
+    /// removes them right away.
+    pub trash_retention: u64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("archive_directory"),
+            on_existing: OnExisting::Version,
+            max_versions: 0,
+            parity_dir: None,
+            trash_retention: 30,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct MountConfig {
     pub default_mountpoint: PathBuf,
     pub default_remote: String,
+    /// Seconds before the mounted file list is re-read from the archive. The
+    /// refresh happens on the next directory listing or unknown lookup; 0 keeps
+    /// the list from mount time.
+    pub refresh_interval: u64,
+    /// How much mounted reads are checked against the manifest: `always`,
+    /// `on-corruption` (only after a read fails) or `off`.
+    pub integrity: Integrity,
+    /// Threads serving reads on Linux, so one slow segment fetch doesn't
+    /// hold up reads of other segments. WinFsp runs its own threads.
+    pub read_threads: usize,
+    /// Seconds the kernel (or WinFsp) may keep file names and attributes
+    /// before asking again. Longer makes big listings cheaper to repeat, but
+    /// files committed or removed take that long more to show.
+    pub attr_ttl: u64,
+    /// Seconds a name that isn't archived is answered as missing without
+    /// refreshing the file list, 0 to look every time.
+    pub negative_ttl: u64,
+    /// File the inode numbers of mounted files are kept in. Numbers come from
+    /// the file hashes either way, this keeps the ones that collided stable
+    /// across remounts.
+    pub inode_map: Option<PathBuf>,
+    /// Options the mount is made with, as `mount -o` takes them, see
+    /// [`crate::mount::options`]. `--mount-opt` adds to them.
+    pub options: Vec<String>,
+    /// How a Windows mount presents itself, `[mount.volume]`.
+    pub volume: VolumeConfig,
+}
+
+impl Default for MountConfig {
+    fn default() -> Self {
+        Self {
+            default_mountpoint: PathBuf::from("./mnt/blockframe"),
+            default_remote: String::new(),
+            refresh_interval: 10,
+            integrity: Integrity::Always,
+            read_threads: 8,
+            attr_ttl: 1,
+            negative_ttl: 5,
+            inode_map: None,
+            options: DEFAULT_MOUNT_OPTIONS.map(String::from).to_vec(),
+            volume: VolumeConfig::default(),
+        }
+    }
+}
+
+/// The WinFsp volume a Windows mount shows, see [`crate::mount::volume`].
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct VolumeConfig {
+    /// Label shown in Explorer, up to 32 characters.
+    pub label: String,
+    /// Serial number as 8 hex digits, e.g. `1A2B-3C4D`, or `auto` for one
+    /// derived from the archived file names, so remounting the same archive
+    /// gives Windows the same volume.
+    pub serial: String,
+    /// Whether `File.txt` and `file.txt` are different files. Names keep
+    /// their case either way.
+    pub case_sensitive: bool,
+    /// Logical sector size in bytes, a power of two from 512 to 4096.
+    pub sector_size: u16,
+    /// Sectors per allocation unit, a power of two.
+    pub sectors_per_allocation_unit: u16,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for VolumeConfig {
+    fn default() -> Self {
+        Self {
+            label: "BlockframeFS".to_string(),
+            serial: "auto".to_string(),
+            case_sensitive: false,
+            sector_size: 512,
+            sectors_per_allocation_unit: 1,
+        }
+    }
+}
+
+/// How remote sources talk to a `blockframe serve` instance, for mounts and
+/// remote health checks.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Seconds to wait for a connection. 0 waits as long as the OS does.
+    pub connect_timeout: u64,
+    /// Seconds to wait for a response, and again for its body. 0 waits forever.
+    pub read_timeout: u64,
+    /// Times a request that failed to connect, timed out or got a 5xx is
+    /// tried again.
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubled for each one after.
+    pub retry_backoff_ms: u64,
+    /// Failed attempts in a row after which the server is treated as down.
+    /// 0 never gives up on it.
+    pub breaker_threshold: u32,
+    /// Seconds requests to a server treated as down fail straight away before
+    /// one is let through to see whether it is back.
+    pub breaker_cooldown: u64,
+    /// Directory the file list and manifests of remote servers are kept in,
+    /// revalidated by ETag and used while a server is unreachable. Unset
+    /// keeps them in memory only.
+    pub manifest_cache: Option<PathBuf>,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 5,
+            read_timeout: 30,
+            retries: 2,
+            retry_backoff_ms: 250,
+            breaker_threshold: 5,
+            breaker_cooldown: 30,
+            manifest_cache: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct CacheConfig {
     pub max_segments: usize,
     pub max_size: String,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_segments: 200,
+            max_size: "1GB".to_string(),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Cache limit in bytes, falling back to 1GB when `max_size` doesn't parse.
+    pub fn max_bytes(&self) -> u64 {
+        parse_size(&self.max_size).unwrap_or(1_000_000_000) as u64
+    }
+}
+
+/// Size thresholds used to pick a tier at commit time. The RS geometry of each
+/// tier is fixed: RS(1,3) for tiers 1 and 2, RS(30,3) per block for tier 3.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ErasureConfig {
+    /// Largest file committed as tier 1 (whole file RS(1,3)).
+    pub tier_1_max: String,
+    /// Largest file committed as tier 2 (per segment RS(1,3)).
+    pub tier_2_max: String,
+    /// Segment size of tier 2 and 3 commits: `adaptive` to size by free
+    /// memory, `deterministic` to size by file size alone, or a fixed size.
+    pub segment_size: String,
+    /// Parity shards per data shard of each tier 3 block, rounded up, so a
+    /// partial last block gets fewer than a full one.
+    pub block_parity_ratio: f64,
+    /// Segments per local parity group of each tier 3 block, 0 for no local
+    /// parity.
+    pub local_group: usize,
+    /// Files per archive-wide parity volume, 0 for no global parity, see
+    /// [`crate::filestore::global_parity`].
+    pub global_parity: usize,
+    /// Reed-Solomon engine: `auto` for the fastest the CPU supports, or
+    /// `nosimd` to force the portable one, see [`crate::erasure`].
+    pub engine: RsEngine,
+    /// Erasure code new commits are written with, see
+    /// [`crate::erasure::coder`]. Entries keep the one they were written with.
+    pub codec: String,
+}
+
+impl Default for ErasureConfig {
+    fn default() -> Self {
+        Self {
+            tier_1_max: "25MB".to_string(),
+            tier_2_max: "1GB".to_string(),
+            segment_size: "adaptive".to_string(),
+            block_parity_ratio: DEFAULT_BLOCK_PARITY_RATIO,
+            local_group: 0,
+            global_parity: 0,
+            engine: RsEngine::Auto,
+            codec: "reed-solomon".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct ServerConfig {
     pub default_port: u16,
+    /// Where to listen instead of every interface on `default_port`, either
+    /// `host:port` or `unix:/path/to.sock`. Empty for the default.
+    pub bind: String,
+    /// PEM certificate chain. Serving switches to HTTPS when both TLS paths are set.
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key matching `tls_cert`.
+    pub tls_key: Option<PathBuf>,
+    /// Requests per second allowed from one client IP, 0 for no limit.
+    pub rate_limit: f64,
+    /// Requests a client can make back to back before `rate_limit` applies.
+    pub rate_burst: u32,
+    /// Bytes per second sent and received across all clients, e.g. "200MB".
+    /// Empty or 0 for no limit.
+    pub max_bandwidth: String,
+    /// Bytes per second sent to and received from one client IP.
+    pub max_client_bandwidth: String,
+    /// Seconds in-flight requests get to finish when the server shuts down.
+    pub drain_timeout: u64,
+    /// Seconds a file name that wasn't found is answered as missing without
+    /// rescanning the archive, 0 to look every time. Uploads clear it.
+    pub negative_ttl: u64,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            default_port: 8080,
+            bind: String::new(),
+            tls_cert: None,
+            tls_key: None,
+            rate_limit: 0.0,
+            rate_burst: 20,
+            max_bandwidth: String::new(),
+            max_client_bandwidth: String::new(),
+            drain_timeout: 30,
+            negative_ttl: 5,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct LoggingConfig {
     pub level: String,
+    /// Directory log files are written to, empty for no log file.
+    pub directory: PathBuf,
+    pub rotation: LogRotation,
+    /// Size a log file may reach before the next is started, with
+    /// `rotation = "size"`.
+    pub max_size: String,
+    /// Log files kept, the current one included. 0 keeps them all.
+    pub max_files: usize,
+    pub format: LogFormat,
+    /// Send events to the systemd journal instead of stdout.
+    pub journald: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            directory: PathBuf::from("logs"),
+            rotation: LogRotation::Daily,
+            max_size: "100MB".to_string(),
+            max_files: 0,
+            format: LogFormat::Text,
+            journald: false,
+        }
+    }
+}
+
+/// When the log file is closed and a new one started.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    /// Once the file reaches `max_size`.
+    Size,
+    /// A single file that grows forever.
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "minutely" => Ok(Self::Minutely),
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            "size" => Ok(Self::Size),
+            "never" => Ok(Self::Never),
+            other => Err(format!(
+                "unknown rotation {:?}, expected minutely, hourly, daily, size or never",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per event.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format {:?}, expected text or json",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Where the daemon records its process id.
+    pub pid_file: PathBuf,
+    /// Seconds between scheduled health check and repair passes.
+    pub scrub_interval: u64,
+    /// Seconds between sampled passes, which check `sample_segments` random
+    /// segments of each file. 0 turns them off.
+    pub sample_interval: u64,
+    pub sample_segments: usize,
+    /// Folder polled for new files to commit. Empty disables watching.
+    pub watch_directory: PathBuf,
+    /// Seconds between polls of the watch folder.
+    pub watch_interval: u64,
+    /// Globs a watched file has to match one of to be committed, all when
+    /// empty. See [`crate::chunker::PathFilter`].
+    pub watch_include: Vec<String>,
+    /// Globs of watched files to leave alone, on top of the folder's
+    /// `.blockframeignore`.
+    pub watch_exclude: Vec<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            pid_file: PathBuf::from("blockframe.pid"),
+            scrub_interval: 24 * 60 * 60,
+            sample_interval: 0,
+            sample_segments: 4,
+            watch_directory: PathBuf::new(),
+            watch_interval: 30,
+            watch_include: Vec::new(),
+            watch_exclude: Vec::new(),
+        }
+    }
+}
+
+/// Which operations read past the page cache, see [`crate::direct_io`]. Off
+/// by default.
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct IoConfig {
+    /// Read source files past the cache when committing them.
+    pub direct_commit: bool,
+    /// Read shards past the cache in health checks, scrubs and repairs.
+    pub direct_scrub: bool,
+}
+
+/// Manifest signing, see [`crate::signing`]. Everything is off by default.
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct SigningConfig {
+    /// Hex Ed25519 secret key used to sign manifests at commit time.
+    /// `BLOCKFRAME_SIGNING_KEY` takes precedence over this file.
+    pub key_file: Option<PathBuf>,
+    /// Hex Ed25519 public key manifests are verified against. Defaults to the
+    /// public half of the signing key.
+    pub public_key: Option<String>,
+    /// Treat unsigned manifests as invalid instead of logging a warning.
+    pub require: bool,
+}
+
+/// Health notifications, see [`crate::notify`]. Off unless a webhook or an
+/// email address is set.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// URL that receives a POST when files become unhealthy. Empty disables.
+    pub webhook_url: String,
+    /// Payload shape expected by the webhook.
+    pub webhook_format: WebhookFormat,
+    /// Addresses mailed through `sendmail` on the same events. Empty disables.
+    pub email_to: Vec<String>,
+    pub email_from: String,
+    /// `sendmail` compatible binary, called with `-t`.
+    pub sendmail: PathBuf,
+    /// Least severe status that triggers a notification.
+    pub min_status: HealthStatus,
+    /// Hold notifications until at least this many files have changed state.
+    pub min_files: usize,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: String::new(),
+            webhook_format: WebhookFormat::Generic,
+            email_to: Vec::new(),
+            email_from: "blockframe@localhost".to_string(),
+            sendmail: PathBuf::from("/usr/sbin/sendmail"),
+            min_status: HealthStatus::Recoverable,
+            min_files: 1,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// JSON summary with one entry per file.
+    Generic,
+    /// `{"text": ...}` for Slack incoming webhooks.
+    Slack,
+    /// `{"content": ...}` for Discord webhooks.
+    Discord,
 }
 
 impl Config {
+    /// Loads the config using the default discovery order, see [`Config::load_from`].
     pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let config_str = fs::read_to_string(Path::new("config.toml"))?;
-        let config: Config = toml::from_str(&config_str).map_err(|e| e.to_string())?;
+        Self::load_from(None)
+    }
+
+    /// Loads the config file and applies `BLOCKFRAME_*` environment overrides.
+    ///
+    /// The file is picked in this order:
+    /// 1. `explicit` (the `--config` flag), which must exist
+    /// 2. the `BLOCKFRAME_CONFIG` environment variable, which must exist
+    /// 3. `config.toml` in the current directory
+    /// 4. the user config dir, e.g. `~/.config/blockframe/config.toml`
+    ///
+    /// If none of the implicit locations exist the built-in defaults are used.
+    pub fn load_from(explicit: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = match explicit {
+            Some(path) => Some(path.to_path_buf()),
+            None => env::var_os(CONFIG_ENV)
+                .map(PathBuf::from)
+                .or_else(|| Self::candidates().into_iter().find(|p| p.is_file())),
+        };
+
+        let mut config = match path {
+            Some(path) => {
+                let config_str = fs::read_to_string(&path)
+                    .map_err(|e| format!("cannot read config {}: {}", path.display(), e))?;
+                let mut config: Config = toml::from_str(&config_str)
+                    .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+                config.source = Some(path);
+                config
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env_overrides(|key| env::var(key).ok())?;
         Ok(config)
     }
+
+    /// Implicit config locations, in the order they are tried.
+    pub fn candidates() -> Vec<PathBuf> {
+        let mut candidates = vec![PathBuf::from("config.toml")];
+        if let Some(dir) = user_config_dir() {
+            candidates.push(dir.join("blockframe").join("config.toml"));
+        }
+        candidates
+    }
+
+    /// Applies `BLOCKFRAME_*` overrides on top of the file values. `lookup` is
+    /// `std::env::var` in practice and a map in tests.
+    pub fn apply_env_overrides<F>(&mut self, lookup: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(v) = lookup("BLOCKFRAME_ARCHIVE") {
+            self.archive.directory = PathBuf::from(v);
+        }
+        if let Some(v) = lookup("BLOCKFRAME_ON_EXISTING") {
+            self.archive.on_existing = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_ON_EXISTING: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_MAX_VERSIONS") {
+            self.archive.max_versions = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_MAX_VERSIONS: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_TRASH_RETENTION") {
+            self.archive.trash_retention = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_TRASH_RETENTION: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_MOUNTPOINT") {
+            self.mount.default_mountpoint = PathBuf::from(v);
+        }
+        if let Some(v) = lookup("BLOCKFRAME_REMOTE") {
+            self.mount.default_remote = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_MOUNT_OPTIONS") {
+            self.mount.options = v.split(',').map(|o| o.trim().to_string()).collect();
+        }
+        if let Some(v) = lookup("BLOCKFRAME_CACHE_MAX_SEGMENTS") {
+            self.cache.max_segments = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_CACHE_MAX_SEGMENTS: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_CACHE_MAX_SIZE") {
+            self.cache.max_size = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_TIER_1_MAX") {
+            self.erasure.tier_1_max = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_TIER_2_MAX") {
+            self.erasure.tier_2_max = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_SEGMENT_SIZE") {
+            self.erasure.segment_size = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_BLOCK_PARITY_RATIO") {
+            self.erasure.block_parity_ratio = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_BLOCK_PARITY_RATIO: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_RS_ENGINE") {
+            self.erasure.engine = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_RS_ENGINE: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_PORT") {
+            self.server.default_port = v.parse().map_err(|e| format!("BLOCKFRAME_PORT: {}", e))?;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_BIND") {
+            self.server.bind = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_TLS_CERT") {
+            self.server.tls_cert = Some(PathBuf::from(v));
+        }
+        if let Some(v) = lookup("BLOCKFRAME_TLS_KEY") {
+            self.server.tls_key = Some(PathBuf::from(v));
+        }
+        if let Some(v) = lookup("BLOCKFRAME_SIGNING_KEY_FILE") {
+            self.signing.key_file = Some(PathBuf::from(v));
+        }
+        if let Some(v) = lookup("BLOCKFRAME_PUBLIC_KEY") {
+            self.signing.public_key = Some(v);
+        }
+        if let Some(v) = lookup("BLOCKFRAME_WEBHOOK_URL") {
+            self.notify.webhook_url = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_LOG_LEVEL") {
+            self.logging.level = v;
+        }
+        if let Some(v) = lookup("BLOCKFRAME_LOG_DIR") {
+            self.logging.directory = PathBuf::from(v);
+        }
+        if let Some(v) = lookup("BLOCKFRAME_LOG_FORMAT") {
+            self.logging.format = v
+                .parse()
+                .map_err(|e| format!("BLOCKFRAME_LOG_FORMAT: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME`, `~/.config` on Unix or `%APPDATA%` on Windows.
+fn user_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+    }
 }
+
 /// Parse data unit strings for actual rust size integer
 /// Support for GB, MB and KB
 pub fn parse_size(size_str: &str) -> Result<usize, Box<dyn std::error::Error>> {
@@ -87,6 +685,7 @@ pub fn parse_size(size_str: &str) -> Result<usize, Box<dyn std::error::Error>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     /// very simple assert test
     /// checking to see if our strings match the actual byte values that rust needs
@@ -96,4 +695,52 @@ mod tests {
         assert_eq!(parse_size("500MB").unwrap(), 500_000_000);
         assert_eq!(parse_size("1024").unwrap(), 1024);
     }
+
+    /// a config file only needs the sections it wants to change
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: Config = toml::from_str("[server]\ndefault_port = 9000\n").unwrap();
+        assert_eq!(config.server.default_port, 9000);
+        assert_eq!(config.archive.directory, PathBuf::from("archive_directory"));
+        assert_eq!(config.erasure.tier_1_max, "25MB");
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars: HashMap<&str, &str> = HashMap::from([
+            ("BLOCKFRAME_ARCHIVE", "/srv/archive"),
+            ("BLOCKFRAME_PORT", "9443"),
+            ("BLOCKFRAME_LOG_LEVEL", "debug"),
+            ("BLOCKFRAME_RS_ENGINE", "NoSIMD"),
+            ("BLOCKFRAME_LOG_FORMAT", "json"),
+        ]);
+        let mut config = Config::default();
+        config
+            .apply_env_overrides(|key| vars.get(key).map(|v| v.to_string()))
+            .unwrap();
+
+        assert_eq!(config.archive.directory, PathBuf::from("/srv/archive"));
+        assert_eq!(config.server.default_port, 9443);
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.erasure.engine, RsEngine::NoSimd);
+        assert_eq!(config.logging.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn test_logging_section() {
+        let config: Config =
+            toml::from_str("[logging]\nrotation = \"size\"\nmax_size = \"10MB\"\nmax_files = 5\n")
+                .unwrap();
+        assert_eq!(config.logging.rotation, LogRotation::Size);
+        assert_eq!(config.logging.max_files, 5);
+        assert_eq!(config.logging.level, "info");
+        assert_eq!(config.logging.directory, PathBuf::from("logs"));
+        assert!("weekly".parse::<LogRotation>().is_err());
+    }
+
+    #[test]
+    fn test_explicit_missing_config_fails() {
+        let result = Config::load_from(Some(Path::new("does/not/exist.toml")));
+        assert!(result.is_err());
+    }
 }
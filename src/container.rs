@@ -0,0 +1,657 @@
+//! Indexing the files inside a committed tar or zip.
+//!
+//! Committing with [`ChunkerBuilder::explode_archives`] still stores a `.tar`
+//! or `.zip` as one ordinary entry, byte for byte, but also records where each
+//! file inside it sits in the manifest's `members` list, with that file's own
+//! BLAKE3 hash. One member is then restored with [`FileStore::restore_in`],
+//! which reads only the shards under its bytes through
+//! [`FileStore::read_range`] instead of restoring and unpacking the whole
+//! container. Deflated zip members are inflated on the way out.
+//!
+//! The index is read from the source file before anything is encoded, so a
+//! malformed container fails the commit without touching the archive. Members
+//! that can't be read back on their own (directories, links, encrypted or
+//! unusually compressed zip entries) are left out of it.
+//!
+//! [`ChunkerBuilder::explode_archives`]: crate::chunker::ChunkerBuilder::explode_archives
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use flate2::Crc;
+use flate2::read::DeflateDecoder;
+
+use crate::alias::{self, Alias};
+use crate::chunker::{ChunkedFile, Chunker};
+use crate::filestore::FileStore;
+use crate::filestore::models::File;
+use crate::merkle_tree::manifest::{ContainerMember, ManifestFile};
+use crate::signing;
+
+/// Kinds of container whose members can be indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+    Tar,
+    Zip,
+}
+
+impl ContainerKind {
+    /// The kind `path` is by its extension, `None` for anything else.
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "tar" => Some(Self::Tar),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the member index of the container at `path`.
+///
+/// # Errors
+///
+/// Fails if the container is truncated or its headers don't parse, or if a
+/// zip member doesn't match its CRC.
+pub fn index_members(
+    path: &Path,
+    kind: ContainerKind,
+) -> Result<Vec<ContainerMember>, Box<dyn std::error::Error>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let members = match kind {
+        ContainerKind::Tar => index_tar(&mut file, len)?,
+        ContainerKind::Zip => index_zip(&mut file, len)?,
+    };
+    // a name stored twice is the later copy when unpacked, so only that one is kept
+    let mut last = HashMap::new();
+    for (idx, member) in members.iter().enumerate() {
+        last.insert(member.name.clone(), idx);
+    }
+    Ok(members
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, member)| last[&member.name] == *idx)
+        .map(|(_, member)| member)
+        .collect())
+}
+
+const TAR_BLOCK: u64 = 512;
+
+fn index_tar(
+    file: &mut fs::File,
+    len: u64,
+) -> Result<Vec<ContainerMember>, Box<dyn std::error::Error>> {
+    let mut members = Vec::new();
+    let mut offset = 0u64;
+    // set by a GNU long name or a pax header for the entry after it
+    let mut next_name: Option<String> = None;
+    let mut next_size: Option<u64> = None;
+    let mut header = [0u8; TAR_BLOCK as usize];
+    while offset + TAR_BLOCK <= len {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(members);
+        }
+        let checksum = tar_number(&header[148..156])?;
+        let sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    b as u64
+                }
+            })
+            .sum();
+        if checksum != sum {
+            return Err(format!("tar header at byte {} has a bad checksum", offset).into());
+        }
+
+        let data = offset + TAR_BLOCK;
+        let size = next_size.take().unwrap_or(tar_number(&header[124..136])?);
+        if data + size > len {
+            return Err(format!("tar entry at byte {} runs past the end", offset).into());
+        }
+        match header[156] {
+            b'L' => next_name = Some(tar_string(&read_at(file, data, size)?)),
+            b'x' => {
+                let records = read_at(file, data, size)?;
+                for (key, value) in pax_records(&records) {
+                    match key {
+                        "path" => next_name = Some(value.to_string()),
+                        "size" => next_size = Some(value.parse()?),
+                        _ => {}
+                    }
+                }
+            }
+            b'0' | b'\0' | b'7' => {
+                let name = next_name.take().unwrap_or_else(|| {
+                    let name = tar_string(&header[0..100]);
+                    let prefix = tar_string(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                file.seek(SeekFrom::Start(data))?;
+                let hash = hash_reader(&mut (&mut *file).take(size))?;
+                members.push(ContainerMember {
+                    name,
+                    offset: data,
+                    size,
+                    inflated_size: None,
+                    hash,
+                });
+            }
+            _ => next_name = None,
+        }
+        offset = data + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+    }
+    Err("tar ends without its end-of-archive blocks".into())
+}
+
+/// A NUL-terminated header field.
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+/// An octal header field, or a base-256 one for sizes past 8 GB.
+fn tar_number(field: &[u8]) -> Result<u64, Box<dyn std::error::Error>> {
+    if field[0] & 0x80 != 0 {
+        let mut value = (field[0] & 0x7f) as u64;
+        for &b in &field[1..] {
+            value = value.checked_mul(256).ok_or("tar size overflows")? | b as u64;
+        }
+        return Ok(value);
+    }
+    let text = tar_string(field);
+    let text = text.trim_matches([' ', '\0']);
+    if text.is_empty() {
+        return Ok(0);
+    }
+    Ok(u64::from_str_radix(text, 8)?)
+}
+
+/// The `key=value` records of a pax extended header.
+fn pax_records(records: &[u8]) -> Vec<(&str, &str)> {
+    let mut out = Vec::new();
+    let mut rest = records;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|len| len.parse::<usize>().ok())
+            .filter(|&len| len > space && len <= rest.len())
+        else {
+            break;
+        };
+        if let Ok(record) = std::str::from_utf8(&rest[space + 1..len])
+            && let Some((key, value)) = record.trim_end_matches('\n').split_once('=')
+        {
+            out.push((key, value));
+        }
+        rest = &rest[len..];
+    }
+    out
+}
+
+const ZIP_EOCD: u32 = 0x0605_4b50;
+const ZIP64_EOCD: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP_CENTRAL: u32 = 0x0201_4b50;
+const ZIP_LOCAL: u32 = 0x0403_4b50;
+
+fn index_zip(
+    file: &mut fs::File,
+    len: u64,
+) -> Result<Vec<ContainerMember>, Box<dyn std::error::Error>> {
+    // the end of central directory record sits before a comment of up to 64 KB
+    let tail_len = len.min(22 + 0xffff);
+    let tail = read_at(file, len - tail_len, tail_len)?;
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| le32(&tail, i) == ZIP_EOCD)
+        .ok_or("not a zip file, no end of central directory")?;
+    let mut entries = le16(&tail, eocd + 10) as u64;
+    let mut cd_size = le32(&tail, eocd + 12) as u64;
+    let mut cd_offset = le32(&tail, eocd + 16) as u64;
+    if (entries == 0xffff || cd_size == 0xffff_ffff || cd_offset == 0xffff_ffff)
+        && eocd >= 20
+        && le32(&tail, eocd - 20) == ZIP64_LOCATOR
+    {
+        let zip64 = read_at(file, le64(&tail, eocd - 20 + 8), 56)?;
+        if le32(&zip64, 0) != ZIP64_EOCD {
+            return Err("zip64 end of central directory is missing".into());
+        }
+        entries = le64(&zip64, 32);
+        cd_size = le64(&zip64, 40);
+        cd_offset = le64(&zip64, 48);
+    }
+    if cd_offset + cd_size > len {
+        return Err("zip central directory runs past the end".into());
+    }
+
+    let directory = read_at(file, cd_offset, cd_size)?;
+    let mut members = Vec::new();
+    let mut at = 0usize;
+    for _ in 0..entries {
+        if at + 46 > directory.len() || le32(&directory, at) != ZIP_CENTRAL {
+            return Err("zip central directory is truncated".into());
+        }
+        let flags = le16(&directory, at + 8);
+        let method = le16(&directory, at + 10);
+        let crc = le32(&directory, at + 16);
+        let mut size = le32(&directory, at + 20) as u64;
+        let mut inflated = le32(&directory, at + 24) as u64;
+        let name_len = le16(&directory, at + 28) as usize;
+        let extra_len = le16(&directory, at + 30) as usize;
+        let comment_len = le16(&directory, at + 32) as usize;
+        let mut local = le32(&directory, at + 42) as u64;
+        let next = at + 46 + name_len + extra_len + comment_len;
+        if next > directory.len() {
+            return Err("zip central directory is truncated".into());
+        }
+        let name = String::from_utf8_lossy(&directory[at + 46..at + 46 + name_len]).to_string();
+
+        // zip64 sizes and offset, in this order, for the fields that overflowed
+        let mut extra = &directory[at + 46 + name_len..at + 46 + name_len + extra_len];
+        while extra.len() >= 4 {
+            let (id, field_len) = (le16(extra, 0), le16(extra, 2) as usize);
+            let field = &extra[4..(4 + field_len).min(extra.len())];
+            if id == 0x0001 {
+                let mut values = field.chunks_exact(8).map(|v| le64(v, 0));
+                if inflated == 0xffff_ffff {
+                    inflated = values.next().ok_or("zip64 field is short")?;
+                }
+                if size == 0xffff_ffff {
+                    size = values.next().ok_or("zip64 field is short")?;
+                }
+                if local == 0xffff_ffff {
+                    local = values.next().ok_or("zip64 field is short")?;
+                }
+            }
+            extra = &extra[(4 + field_len).min(extra.len())..];
+        }
+        at = next;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 || (method != 0 && method != 8) {
+            tracing::warn!(
+                "CONTAINER | leaving {} out of the index, it is encrypted or compressed with method {}",
+                name,
+                method
+            );
+            continue;
+        }
+        let header = read_at(file, local, 30)?;
+        if le32(&header, 0) != ZIP_LOCAL {
+            return Err(format!("zip local header of {} is missing", name).into());
+        }
+        let data = local + 30 + le16(&header, 26) as u64 + le16(&header, 28) as u64;
+        if data + size > len {
+            return Err(format!("zip member {} runs past the end", name).into());
+        }
+
+        file.seek(SeekFrom::Start(data))?;
+        let stored = (&mut *file).take(size);
+        let inner: Box<dyn Read + '_> = match method {
+            8 => Box::new(DeflateDecoder::new(stored)),
+            _ => Box::new(stored),
+        };
+        let mut check = CheckedRead {
+            inner,
+            crc: Crc::new(),
+            hasher: blake3::Hasher::new(),
+        };
+        let read = io::copy(&mut check, &mut io::sink())?;
+        if read != inflated || check.crc.sum() != crc {
+            return Err(format!("zip member {} doesn't match its CRC", name).into());
+        }
+        members.push(ContainerMember {
+            name,
+            offset: data,
+            size,
+            inflated_size: (method == 8).then_some(inflated),
+            hash: check.hasher.finalize().to_string(),
+        });
+    }
+    Ok(members)
+}
+
+fn le16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn read_at(file: &mut fs::File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = vec![0u8; len as usize];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn hash_reader<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_string())
+}
+
+/// Passes reads through, keeping a CRC-32 and BLAKE3 of the bytes.
+struct CheckedRead<R: Read> {
+    inner: R,
+    crc: Crc,
+    hasher: blake3::Hasher,
+}
+
+impl<R: Read> Read for CheckedRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc.update(&buf[..read]);
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl Chunker {
+    /// Writes `members` into the manifest of the committed `chunked` and signs
+    /// it again so the index is covered. A newly encoded entry already has
+    /// them, so this only changes an entry that was archived before. An alias
+    /// has no manifest of its own, so the index goes to the entry it points
+    /// at, which holds the same bytes.
+    pub(crate) fn record_members(
+        &self,
+        chunked: &ChunkedFile,
+        members: Vec<ContainerMember>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file_dir = chunked.file_dir.clone();
+        if alias::is_alias(&file_dir) {
+            let alias: Alias =
+                serde_json::from_str(&fs::read_to_string(file_dir.join(alias::ALIAS_FILE))?)?;
+            file_dir = self.archive_dir().join(alias.target);
+        }
+        let manifest_path = file_dir.join("manifest.json");
+        let mut manifest = ManifestFile::new(manifest_path.display().to_string())?;
+        if manifest.members == members {
+            return Ok(());
+        }
+        tracing::info!(
+            "CONTAINER | indexed {} members of {}",
+            members.len(),
+            chunked.file_name
+        );
+        manifest.members = members;
+        signing::replace_manifest(&file_dir, &manifest, self.signer())?;
+        self.refresh_global_parity(&manifest);
+        Ok(())
+    }
+}
+
+impl FileStore {
+    /// The member called `member` of the container `file`.
+    pub fn find_in<'a>(
+        &self,
+        file: &'a File,
+        member: &str,
+    ) -> Result<&'a ContainerMember, Box<dyn std::error::Error>> {
+        if file.manifest.members.is_empty() {
+            return Err(format!(
+                "{} has no member index, commit it with --explode-archives",
+                file.file_name
+            )
+            .into());
+        }
+        file.manifest
+            .members
+            .iter()
+            .find(|m| m.name == member)
+            .ok_or_else(|| format!("{} is not in {}", member, file.file_name).into())
+    }
+
+    /// Streams one member of a container into `out` and returns its hash.
+    /// Only the shards under the member's bytes are read, through
+    /// [`FileStore::read_range`], so damaged ones are recovered from parity
+    /// on the way.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the range can't be restored, doesn't inflate, or doesn't
+    /// match the hash in the index.
+    pub fn restore_in<W: Write>(
+        &self,
+        file: &File,
+        member: &ContainerMember,
+        out: W,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let range = member.offset..member.offset + member.size;
+        let mut out = HashingWriter {
+            inner: out,
+            hasher: blake3::Hasher::new(),
+            written: 0,
+        };
+        let written = match member.inflated_size {
+            Some(_) => {
+                let mut inflate = flate2::write::DeflateDecoder::new(&mut out);
+                self.read_range(file, range, &mut inflate)?;
+                inflate.finish()?;
+                out.written
+            }
+            None => self.read_range(file, range, &mut out)?,
+        };
+        out.flush()?;
+
+        let hash = out.hasher.finalize().to_string();
+        if hash != member.hash || written != member.inflated_size.unwrap_or(member.size) {
+            return Err(format!("{} does not match its indexed hash", member.name).into());
+        }
+        Ok(hash)
+    }
+}
+
+/// Passes writes through, keeping a BLAKE3 of the bytes.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: blake3::Hasher,
+    written: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
+    use tempfile::TempDir;
+
+    fn tar_header(name: &str, size: usize, kind: u8) -> Vec<u8> {
+        let mut header = vec![0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header
+    }
+
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for (name, data) in entries {
+            if name.len() > 100 {
+                let long = format!("{}\0", name);
+                tar.extend(tar_header("././@LongLink", long.len(), b'L'));
+                tar.extend(long.as_bytes());
+                tar.resize(tar.len().div_ceil(512) * 512, 0);
+            }
+            tar.extend(tar_header(&name[..name.len().min(100)], data.len(), b'0'));
+            tar.extend(*data);
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+        tar.extend([0u8; 1024]);
+        tar
+    }
+
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for (name, data, deflate) in entries {
+            let mut crc = Crc::new();
+            crc.update(data);
+            let stored = if *deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+            let method: u16 = if *deflate { 8 } else { 0 };
+            let offset = zip.len() as u32;
+            let mut common = Vec::new();
+            common.extend(20u16.to_le_bytes());
+            common.extend(0u16.to_le_bytes());
+            common.extend(method.to_le_bytes());
+            common.extend([0u8; 4]);
+            common.extend(crc.sum().to_le_bytes());
+            common.extend((stored.len() as u32).to_le_bytes());
+            common.extend((data.len() as u32).to_le_bytes());
+            common.extend((name.len() as u16).to_le_bytes());
+            common.extend(0u16.to_le_bytes());
+
+            zip.extend(ZIP_LOCAL.to_le_bytes());
+            zip.extend(&common);
+            zip.extend(name.as_bytes());
+            zip.extend(&stored);
+
+            central.extend(ZIP_CENTRAL.to_le_bytes());
+            central.extend(20u16.to_le_bytes());
+            central.extend(&common);
+            // comment length, disk, internal and external attributes
+            central.extend([0u8; 10]);
+            central.extend(offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+        let cd_offset = zip.len() as u32;
+        zip.extend(&central);
+        zip.extend(ZIP_EOCD.to_le_bytes());
+        zip.extend([0u8; 4]);
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((central.len() as u32).to_le_bytes());
+        zip.extend(cd_offset.to_le_bytes());
+        zip.extend(0u16.to_le_bytes());
+        zip
+    }
+
+    #[test]
+    fn test_members_restore_from_their_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .tier_limits(10_000, 1_000_000)
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .explode_archives(true)
+            .build()
+            .unwrap();
+
+        let big: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let text = b"hello from inside the container\n".repeat(100);
+        let long_name = format!("{}/deep.txt", "d".repeat(120));
+        let tar_path = temp_dir.path().join("bundle.tar");
+        fs::write(
+            &tar_path,
+            tar(&[
+                ("notes/a.txt", &text),
+                ("big.bin", &big),
+                (&long_name, b"x"),
+                ("empty", b""),
+            ]),
+        )
+        .unwrap();
+        let zip_path = temp_dir.path().join("bundle.ZIP");
+        fs::write(
+            &zip_path,
+            zip(&[("a.txt", &text, true), ("big.bin", &big, false)]),
+        )
+        .unwrap();
+
+        chunker.commit_as(&tar_path, None).unwrap();
+        chunker.commit_as(&zip_path, None).unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+        for (container, names) in [
+            (
+                "bundle.tar",
+                vec!["notes/a.txt", "big.bin", long_name.as_str(), "empty"],
+            ),
+            ("bundle.ZIP", vec!["a.txt", "big.bin"]),
+        ] {
+            let file = store.find(&container.to_string()).unwrap();
+            assert_eq!(file.manifest.tier, 2);
+            assert_eq!(file.manifest.members.len(), names.len());
+            for name in names {
+                let member = store.find_in(&file, name).unwrap();
+                let mut restored = Vec::new();
+                store.restore_in(&file, member, &mut restored).unwrap();
+                let expected: &[u8] = match name {
+                    "big.bin" => &big,
+                    "empty" => b"",
+                    n if n.ends_with("a.txt") => &text,
+                    _ => b"x",
+                };
+                assert_eq!(restored, expected, "{} of {}", name, container);
+            }
+            assert!(store.find_in(&file, "missing").is_err());
+        }
+        let zip_file = store.find(&"bundle.ZIP".to_string()).unwrap();
+        assert!(zip_file.manifest.members[0].inflated_size.is_some());
+
+        // a container without its end blocks fails before anything is written
+        let bad = temp_dir.path().join("cut.tar");
+        fs::write(&bad, &tar(&[("a", &text)])[..1024]).unwrap();
+        assert!(chunker.commit_as(&bad, None).is_err());
+        assert!(store.find(&"cut.tar".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_container_kind() {
+        assert_eq!(
+            ContainerKind::of(Path::new("a/b.tar")),
+            Some(ContainerKind::Tar)
+        );
+        assert_eq!(
+            ContainerKind::of(Path::new("b.Zip")),
+            Some(ContainerKind::Zip)
+        );
+        assert_eq!(ContainerKind::of(Path::new("b.tar.gz")), None);
+        assert_eq!(ContainerKind::of(Path::new("tar")), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Long-running daemon that combines `serve`, scheduled scrubbing and an
+//! optional watch folder in a single process.
+//!
+//! The daemon writes a PID file on start and removes it on exit, and won't
+//! start while the process named in an existing one is running. On Windows,
+//! where that can't be checked, a PID file left by a crash has to be removed
+//! by hand. On Unix,
+//! `SIGHUP` reloads the `[daemon]` section of `config.toml` and `SIGTERM`/`SIGINT`
+//! trigger a clean shutdown. On Windows, Ctrl+C (or a service stop) does the same.
+
+use parking_lot::RwLock;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::chunker::Chunker;
+use crate::config::{Config, DaemonConfig};
+use crate::filestore::FileStore;
+use crate::notify::Notifier;
+use crate::serve::{ServeOptions, run_server_with_store};
+use crate::signing::ManifestVerifier;
+
+mod watcher;
+
+/// Settings for [`run_daemon`] that are fixed for the lifetime of the process.
+pub struct DaemonOptions {
+    pub serve: ServeOptions,
+    /// Chunker used for files picked up from the watch folder.
+    pub chunker: Chunker,
+    pub settings: DaemonConfig,
+    /// Config file passed with `--config`, re-read on reload.
+    pub config_path: Option<PathBuf>,
+    /// Watch folder given on the command line, kept across config reloads.
+    pub watch_override: Option<PathBuf>,
+    /// Manifest signature check applied by the scheduled scrub.
+    pub verifier: Option<ManifestVerifier>,
+    /// Told about files that become unhealthy during a scrub.
+    pub notifier: Option<Notifier>,
+    /// Trashed files older than this are purged after each full scrub.
+    pub trash_retention: Duration,
+    /// Scrubs read shards past the page cache, see [`FileStore::with_direct_io`].
+    pub direct_scrub: bool,
+}
+
+/// Runs the server, scrub scheduler and watch folder until a shutdown signal arrives.
+pub async fn run_daemon(options: DaemonOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let pid_file = options.settings.pid_file.clone();
+    write_pid_file(&pid_file)?;
+    info!("DAEMON | started with pid {}", std::process::id());
+
+    let mut initial = options.settings;
+    if let Some(watch) = &options.watch_override {
+        initial.watch_directory = watch.clone();
+    }
+    let settings = Arc::new(RwLock::new(initial));
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    // one store for the server, scrubber and watch folder so they share its cache
+    let store = Arc::new(
+        FileStore::new(&options.serve.archive_path)?
+            .with_verifier(options.verifier)
+            .with_direct_io(options.direct_scrub)
+            .with_negative_ttl(options.serve.negative_ttl),
+    );
+    let server = {
+        let mut rx = shutdown_rx.clone();
+        let serve = options.serve;
+        let store = store.clone();
+        tokio::spawn(async move {
+            let stop = async move {
+                let _ = rx.wait_for(|stop| *stop).await;
+            };
+            if let Err(e) = run_server_with_store(serve, store, stop).await {
+                error!("DAEMON | server exited with error: {}", e);
+            }
+        })
+    };
+
+    let scrubber = tokio::spawn(scrub_loop(
+        store.clone(),
+        options.notifier.clone(),
+        settings.clone(),
+        Pass::Full,
+        options.trash_retention,
+        shutdown_rx.clone(),
+    ));
+    let sampler = tokio::spawn(scrub_loop(
+        store.clone(),
+        options.notifier,
+        settings.clone(),
+        Pass::Sampled,
+        options.trash_retention,
+        shutdown_rx.clone(),
+    ));
+    let watcher = tokio::spawn(watcher::watch_loop(
+        Arc::new(options.chunker),
+        store,
+        settings.clone(),
+        shutdown_rx.clone(),
+    ));
+
+    let reload = Reload {
+        config_path: options.config_path,
+        watch_override: options.watch_override,
+    };
+    wait_for_shutdown(settings, reload).await;
+    info!("DAEMON | shutting down");
+    let _ = shutdown_tx.send(true);
+
+    let _ = tokio::join!(server, scrubber, sampler, watcher);
+
+    if let Err(e) = fs::remove_file(&pid_file) {
+        warn!("DAEMON | could not remove pid file {:?}: {}", pid_file, e);
+    }
+    info!("DAEMON | stopped");
+    Ok(())
+}
+
+/// Claims `pid_file` for this process. Refuses while the process it names is
+/// still running, so two daemons never scrub and repair one archive at once,
+/// and replaces it once that process is gone.
+fn write_pid_file(pid_file: &Path) -> Result<(), io::Error> {
+    match fs::read_to_string(pid_file) {
+        Ok(contents) => {
+            match contents.trim().parse::<u32>() {
+                Ok(pid) if pid != std::process::id() && process_running(pid) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "a daemon is already running with pid {} (from {:?}), remove the file if it isn't",
+                            pid, pid_file
+                        ),
+                    ));
+                }
+                Ok(pid) => warn!(
+                    "DAEMON | replacing pid file {:?}, process {} is gone",
+                    pid_file, pid
+                ),
+                Err(_) => warn!(
+                    "DAEMON | replacing pid file {:?}, it holds no pid",
+                    pid_file
+                ),
+            }
+            fs::remove_file(pid_file)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    // a daemon starting at the same time gets AlreadyExists here
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(pid_file)?;
+    writeln!(file, "{}", std::process::id())
+}
+
+/// Whether a process with `pid` exists. Signal 0 only checks that it could be
+/// signalled; `EPERM` means it exists under another user.
+#[cfg(unix)]
+fn process_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid == 0 {
+        return false;
+    }
+    // SAFETY: signal 0 sends nothing
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a portable check, any recorded pid is taken to be running.
+#[cfg(not(unix))]
+fn process_running(_pid: u32) -> bool {
+    true
+}
+
+/// What a reload needs to know to reproduce the start-up settings.
+struct Reload {
+    config_path: Option<PathBuf>,
+    watch_override: Option<PathBuf>,
+}
+
+/// Re-reads the `[daemon]` section of the config file. Archive path, port and
+/// PID file are bound at start-up and need a restart to change.
+fn reload_settings(settings: &RwLock<DaemonConfig>, reload: &Reload) {
+    match Config::load_from(reload.config_path.as_deref()) {
+        Ok(cfg) => {
+            let mut reloaded = cfg.daemon;
+            let mut current = settings.write();
+            reloaded.pid_file = current.pid_file.clone();
+            if let Some(watch) = &reload.watch_override {
+                reloaded.watch_directory = watch.clone();
+            }
+            info!("DAEMON | reloaded config: {:?}", reloaded);
+            *current = reloaded;
+        }
+        Err(e) => error!("DAEMON | config reload failed, keeping old settings: {}", e),
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown(settings: Arc<RwLock<DaemonConfig>>, reload: Reload) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let (Ok(mut hangup), Ok(mut terminate), Ok(mut interrupt)) = (
+        signal(SignalKind::hangup()),
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        error!("DAEMON | could not install signal handlers, falling back to ctrl-c");
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => reload_settings(&settings, &reload),
+            _ = terminate.recv() => return,
+            _ = interrupt.recv() => return,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown(_settings: Arc<RwLock<DaemonConfig>>, _reload: Reload) {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Sleeps for `duration` or until shutdown is requested. Returns `true` on shutdown.
+async fn sleep_or_shutdown(duration: Duration, shutdown: &mut watch::Receiver<bool>) -> bool {
+    let stopped = tokio::select! {
+        _ = tokio::time::sleep(duration) => false,
+        _ = shutdown.wait_for(|stop| *stop) => true,
+    };
+    stopped || *shutdown.borrow()
+}
+
+/// Which check a [`scrub_loop`] runs before repairing.
+#[derive(Clone, Copy, PartialEq)]
+enum Pass {
+    /// Every shard, every `scrub_interval`.
+    Full,
+    /// `sample_segments` random segments per file, every `sample_interval`.
+    Sampled,
+}
+
+async fn scrub_loop(
+    store: Arc<FileStore>,
+    notifier: Option<Notifier>,
+    settings: Arc<RwLock<DaemonConfig>>,
+    pass: Pass,
+    trash_retention: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        let (interval, per_file) = {
+            let settings = settings.read();
+            match pass {
+                Pass::Full => (settings.scrub_interval.max(1), 0),
+                Pass::Sampled => (settings.sample_interval, settings.sample_segments),
+            }
+        };
+        // sampling is off, look again in a minute in case a reload turned it on
+        if interval == 0 {
+            if sleep_or_shutdown(Duration::from_secs(60), &mut shutdown).await {
+                return;
+            }
+            continue;
+        }
+        if sleep_or_shutdown(Duration::from_secs(interval), &mut shutdown).await {
+            return;
+        }
+
+        let label = match pass {
+            Pass::Full => "scrub",
+            Pass::Sampled => "sampled scrub",
+        };
+        info!("DAEMON | scheduled {} of {:?}", label, store.store_path);
+        let store = store.clone();
+        let notifier = notifier.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<String, String> {
+            // manifests may have been edited in place, which the cache can't see
+            store.invalidate();
+            let notify = |report: &_| {
+                if let Some(notifier) = &notifier
+                    && let Err(e) = notifier.notify(&store.store_path, report)
+                {
+                    error!("DAEMON | health notification failed: {}", e);
+                }
+            };
+            let report = match pass {
+                Pass::Full => store.scrub_with(notify),
+                Pass::Sampled => store.scrub_sampled_with(per_file, rand::random(), notify),
+            }
+            .map_err(|e| e.to_string())?;
+            if pass == Pass::Full
+                && let Err(e) = store.purge_trash(trash_retention)
+            {
+                error!("DAEMON | purging the trash failed: {}", e);
+            }
+            Ok(format!(
+                "{}/{} healthy, {} recoverable, {} unrecoverable",
+                report.healthy, report.total_files, report.recoverable, report.unrecoverable
+            ))
+        })
+        .await;
+
+        match result {
+            Ok(Ok(summary)) => info!("DAEMON | {} finished: {}", label, summary),
+            Ok(Err(e)) => error!("DAEMON | {} failed: {}", label, e),
+            Err(e) => error!("DAEMON | {} task panicked: {}", label, e),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pid_file_is_only_replaced_when_stale() {
+        let temp_dir = TempDir::new().unwrap();
+        let pid_file = temp_dir.path().join("blockframe.pid");
+        let mut running = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        fs::write(&pid_file, format!("{}\n", running.id())).unwrap();
+
+        let err = write_pid_file(&pid_file).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(&pid_file).unwrap().trim(),
+            running.id().to_string()
+        );
+
+        running.kill().unwrap();
+        running.wait().unwrap();
+        write_pid_file(&pid_file).unwrap();
+        assert_eq!(
+            fs::read_to_string(&pid_file).unwrap().trim(),
+            std::process::id().to_string()
+        );
+
+        fs::write(&pid_file, "not a pid").unwrap();
+        write_pid_file(&pid_file).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Watch folder support for the daemon.
+//!
+//! There is no filesystem notification dependency, the folder is polled. A file
+//! is committed once its size has stayed the same across two polls, so files
+//! that are still being copied in are left alone. Committed files are moved into
+//! `committed/` inside the watch folder; failures are moved into `failed/`.
+
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use super::sleep_or_shutdown;
+use crate::chunker::{Chunker, PathFilter};
+use crate::config::DaemonConfig;
+use crate::filestore::FileStore;
+
+pub(super) async fn watch_loop(
+    chunker: Arc<Chunker>,
+    store: Arc<FileStore>,
+    settings: Arc<RwLock<DaemonConfig>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    // file -> size seen on the previous poll
+    let mut pending: HashMap<PathBuf, u64> = HashMap::new();
+
+    loop {
+        let (watch_dir, interval, include, exclude) = {
+            let s = settings.read();
+            (
+                s.watch_directory.clone(),
+                Duration::from_secs(s.watch_interval.max(1)),
+                s.watch_include.clone(),
+                s.watch_exclude.clone(),
+            )
+        };
+
+        if sleep_or_shutdown(interval, &mut shutdown).await {
+            return;
+        }
+
+        if watch_dir.as_os_str().is_empty() {
+            pending.clear();
+            continue;
+        }
+
+        // read again each poll, so edits to the ignore file apply without a restart
+        // the error as a string, a boxed one would make this future !Send
+        let filter = PathFilter::new(&include, &exclude)
+            .and_then(|filter| filter.with_ignore_file(&watch_dir))
+            .map_err(|e| e.to_string());
+        let filter = match filter {
+            Ok(filter) => filter,
+            Err(e) => {
+                error!("DAEMON | watch folder filter: {}", e);
+                continue;
+            }
+        };
+        let ready = match stable_files(&watch_dir, &filter, &mut pending) {
+            Ok(ready) => ready,
+            Err(e) => {
+                error!("DAEMON | cannot read watch folder {:?}: {}", watch_dir, e);
+                continue;
+            }
+        };
+
+        for file in ready {
+            pending.remove(&file);
+            let dir = watch_dir.clone();
+            let chunker = chunker.clone();
+            let store = store.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                commit_and_move(&chunker, &dir, &file);
+                store.invalidate();
+            })
+            .await;
+            if let Err(e) = result {
+                error!("DAEMON | watch commit task panicked: {}", e);
+            }
+        }
+    }
+}
+
+/// Records the current size of every regular file in `watch_dir` that `filter`
+/// takes and returns the ones whose size matches the previous poll.
+fn stable_files(
+    watch_dir: &Path,
+    filter: &PathFilter,
+    pending: &mut HashMap<PathBuf, u64>,
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut ready = Vec::new();
+    let mut seen = HashMap::new();
+
+    for entry in fs::read_dir(watch_dir)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if !meta.is_file() || meta.len() == 0 || !filter.matches(Path::new(&entry.file_name())) {
+            continue;
+        }
+        if pending.get(&path) == Some(&meta.len()) {
+            ready.push(path.clone());
+        }
+        seen.insert(path, meta.len());
+    }
+
+    *pending = seen;
+    Ok(ready)
+}
+
+fn commit_and_move(chunker: &Chunker, watch_dir: &Path, file: &Path) {
+    let outcome = chunker.commit(file);
+    let target_dir = match &outcome {
+        Ok(chunked) => {
+            info!(
+                "DAEMON | committed {:?} as {}",
+                file, chunked.file_trun_hash
+            );
+            watch_dir.join("committed")
+        }
+        Err(e) => {
+            error!("DAEMON | commit of {:?} failed: {}", file, e);
+            watch_dir.join("failed")
+        }
+    };
+
+    let Some(name) = file.file_name() else {
+        return;
+    };
+    if let Err(e) =
+        fs::create_dir_all(&target_dir).and_then(|_| fs::rename(file, target_dir.join(name)))
+    {
+        error!(
+            "DAEMON | could not move {:?} to {:?}: {}",
+            file, target_dir, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_ready_after_size_stops_changing() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("incoming.bin");
+        let mut pending = HashMap::new();
+        let filter = PathFilter::default();
+
+        fs::write(&file, vec![1u8; 10]).unwrap();
+        assert!(
+            stable_files(temp_dir.path(), &filter, &mut pending)
+                .unwrap()
+                .is_empty()
+        );
+
+        // still being written
+        fs::write(&file, vec![1u8; 20]).unwrap();
+        assert!(
+            stable_files(temp_dir.path(), &filter, &mut pending)
+                .unwrap()
+                .is_empty()
+        );
+
+        // unchanged since last poll
+        assert_eq!(
+            stable_files(temp_dir.path(), &filter, &mut pending).unwrap(),
+            vec![file]
+        );
+
+        // files the filter leaves out are never ready
+        let filter = PathFilter::new(&[], &["*.bin".into()]).unwrap();
+        stable_files(temp_dir.path(), &filter, &mut pending).unwrap();
+        assert!(
+            stable_files(temp_dir.path(), &filter, &mut pending)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
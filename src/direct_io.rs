@@ -0,0 +1,259 @@
+//! Reads that bypass the page cache.
+//!
+//! A scrub or a commit reads every byte it touches exactly once, and going
+//! through the page cache that pushes out whatever else the machine had cached,
+//! such as a database's working set. [`open`] opens a file with `O_DIRECT` on
+//! Linux and `FILE_FLAG_NO_BUFFERING` on Windows. Both need reads into buffers
+//! aligned to [`ALIGN`], at aligned offsets and of aligned lengths, which
+//! [`DirectReader`] and [`read_at`] take care of.
+//!
+//! Elsewhere, and on filesystems that refuse direct IO, files are opened and
+//! read through the cache as usual, so callers don't have to check.
+
+use std::alloc::{self, Layout};
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::ptr::NonNull;
+
+/// Alignment of buffers, offsets and lengths of direct reads, a multiple of
+/// the logical block size of any disk in use.
+pub const ALIGN: usize = 4096;
+
+/// Bytes a [`DirectReader`] reads per step.
+const READ_SIZE: usize = 1024 * 1024;
+
+/// Opens `path` for reading past the page cache, or through it where direct IO
+/// isn't available.
+pub fn open(path: &Path) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::fs::OpenOptions;
+        use std::os::unix::fs::OpenOptionsExt;
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)
+        {
+            Ok(file) => return Ok(file),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                tracing::debug!("DIRECT IO | not supported for {:?}, reading cached", path);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::fs::OpenOptions;
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+        const ERROR_INVALID_PARAMETER: i32 = 87;
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_NO_BUFFERING)
+            .open(path)
+        {
+            Ok(file) => return Ok(file),
+            Err(e) if e.raw_os_error() == Some(ERROR_INVALID_PARAMETER) => {
+                tracing::debug!("DIRECT IO | not supported for {:?}, reading cached", path);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    File::open(path)
+}
+
+/// Reads all of `path` past the page cache, see [`open`].
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let mut reader = DirectReader::open(path)?;
+    let mut data = Vec::with_capacity(reader.file.metadata()?.len() as usize);
+    reader.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Reads `len` bytes at `offset` of a file opened with [`open`]. The read is
+/// widened to aligned bounds and cut back, so any offset and length work.
+pub fn read_at(file: &File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let start = offset - offset % ALIGN as u64;
+    let skip = (offset - start) as usize;
+    let mut buf = AlignedBuf::new((skip + len).div_ceil(ALIGN) * ALIGN);
+    let filled = fill(&mut buf, |chunk, done| {
+        positioned_read(file, chunk, start + done as u64)
+    })?;
+    if filled < skip + len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("{} bytes at {} run past the end of the file", len, offset),
+        ));
+    }
+    Ok(buf[skip..skip + len].to_vec())
+}
+
+/// Sequential reader over a file opened with [`open`], reading aligned steps
+/// into a buffer of its own.
+pub struct DirectReader {
+    file: File,
+    buf: AlignedBuf,
+    pos: usize,
+    filled: usize,
+    eof: bool,
+}
+
+impl DirectReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(open(path)?))
+    }
+
+    /// Reads `file` from its current position, which must be aligned.
+    pub fn new(file: File) -> Self {
+        Self {
+            file,
+            buf: AlignedBuf::new(READ_SIZE),
+            pos: 0,
+            filled: 0,
+            eof: false,
+        }
+    }
+}
+
+impl Read for DirectReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.filled {
+            if self.eof || out.is_empty() {
+                return Ok(0);
+            }
+            let file = &mut self.file;
+            self.filled = fill(&mut self.buf, |chunk, _| file.read(chunk))?;
+            self.pos = 0;
+            self.eof = self.filled < self.buf.len();
+        }
+        let count = out.len().min(self.filled - self.pos);
+        out[..count].copy_from_slice(&self.buf[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+/// Fills `buf` with `read(chunk, bytes so far)` until it is full or the file
+/// ends, and returns the bytes read. A direct read only comes back short of
+/// an aligned length at the end of the file, and reading on from an
+/// unaligned position would fail, so that ends it too.
+fn fill(
+    buf: &mut [u8],
+    mut read: impl FnMut(&mut [u8], usize) -> io::Result<usize>,
+) -> io::Result<usize> {
+    let mut done = 0;
+    while done < buf.len() {
+        match read(&mut buf[done..], done) {
+            Ok(0) => break,
+            Ok(n) => {
+                done += n;
+                if n % ALIGN != 0 {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(done)
+}
+
+#[cfg(unix)]
+fn positioned_read(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn positioned_read(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn positioned_read(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::io::{Seek, SeekFrom};
+    let mut file = file;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+/// Zeroed heap buffer aligned to [`ALIGN`].
+pub(crate) struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the buffer is owned like a Vec<u8>
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    pub(crate) fn new(len: usize) -> Self {
+        if len == 0 {
+            return Self {
+                ptr: NonNull::dangling(),
+                len,
+            };
+        }
+        let layout = Self::layout(len);
+        // SAFETY: `layout` has a non-zero size
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        match NonNull::new(ptr) {
+            Some(ptr) => Self { ptr, len },
+            None => alloc::handle_alloc_error(layout),
+        }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len, ALIGN).expect("buffer size overflows")
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `ptr` holds `len` initialised bytes, or is dangling for 0
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as for `deref`, and `&mut self` makes the borrow unique
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: allocated in `new` with this layout
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_match_cached_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..3 * READ_SIZE + 1234).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        assert_eq!(read(&path).unwrap(), data);
+        let file = open(&path).unwrap();
+        assert_eq!(read_at(&file, 5000, 10_000).unwrap(), data[5000..15_000]);
+        let end = data.len() as u64 - 100;
+        assert_eq!(read_at(&file, end, 100).unwrap(), data[end as usize..]);
+        assert!(read_at(&file, end, 101).is_err());
+
+        std::fs::write(&path, b"").unwrap();
+        assert!(read(&path).unwrap().is_empty());
+    }
+}
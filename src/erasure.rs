@@ -0,0 +1,393 @@
+//! Reed-Solomon encoding and decoding, and which implementation runs it.
+//!
+//! `reed_solomon_simd` picks the fastest engine the CPU supports when an
+//! encoder or decoder is made: AVX2 or SSSE3 on x86, Neon on ARM, and a
+//! portable one without SIMD otherwise. Throughput differs a lot between
+//! them, so [`simd_path`] reports the pick and `blockframe stats --system`
+//! shows it next to a measured encode speed.
+//!
+//! Every encode and decode in the crate goes through [`encode`] and
+//! [`decode`], so [`set_engine`] can force the portable engine for the whole
+//! process (`erasure.engine = "nosimd"`). The engines produce identical
+//! shards, which makes the portable one a reference when a SIMD path is
+//! suspected of a fault.
+//!
+//! Which code writes the parity is an [`ErasureCoder`], chosen per archive
+//! with `erasure.codec`. Its name is recorded as the manifest's
+//! `erasure_coding.type`, and recovery looks the coder up by that name with
+//! [`coder`], so an entry is always decoded with the code it was written
+//! with. [`ReedSolomon`] is the default; builds with the `rs-galois` feature
+//! also have [`GaloisReedSolomon`], whose parity differs from it.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use reed_solomon_simd::engine::{DefaultEngine, Engine, NoSimd};
+use reed_solomon_simd::rate::{DefaultRateDecoder, DefaultRateEncoder, RateDecoder, RateEncoder};
+use serde::Deserialize;
+
+/// Which Reed-Solomon engine encodes and decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RsEngine {
+    /// The fastest one the CPU supports, see [`simd_path`].
+    #[default]
+    Auto,
+    /// The portable engine, without SIMD.
+    NoSimd,
+}
+
+impl FromStr for RsEngine {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "nosimd" => Ok(Self::NoSimd),
+            other => Err(format!(
+                "unknown engine {:?}, expected auto or nosimd",
+                other
+            )),
+        }
+    }
+}
+
+static FORCE_NOSIMD: AtomicBool = AtomicBool::new(false);
+
+/// Uses `engine` for every encode and decode from now on.
+pub fn set_engine(engine: RsEngine) {
+    FORCE_NOSIMD.store(engine == RsEngine::NoSimd, Ordering::Relaxed);
+}
+
+/// The engine set with [`set_engine`], [`RsEngine::Auto`] by default.
+pub fn engine() -> RsEngine {
+    if FORCE_NOSIMD.load(Ordering::Relaxed) {
+        RsEngine::NoSimd
+    } else {
+        RsEngine::Auto
+    }
+}
+
+/// The SIMD instructions [`RsEngine::Auto`] uses on this CPU: `avx2`,
+/// `ssse3`, `neon`, or `none` for the portable engine. Checked the same way
+/// and in the same order as `reed_solomon_simd` does.
+pub fn simd_path() -> &'static str {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            return "avx2";
+        }
+        if std::is_x86_feature_detected!("ssse3") {
+            return "ssse3";
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return "neon";
+        }
+    }
+    "none"
+}
+
+/// The engine encodes and decodes run on: [`simd_path`], or `none` when
+/// [`RsEngine::NoSimd`] is forced.
+pub fn active_path() -> &'static str {
+    match engine() {
+        RsEngine::Auto => simd_path(),
+        RsEngine::NoSimd => "none",
+    }
+}
+
+/// Parity shards for `originals`, which must all be the same even length.
+pub fn encode<T: AsRef<[u8]>>(
+    originals: &[T],
+    parity_shards: usize,
+) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    match engine() {
+        RsEngine::Auto => encode_with(DefaultEngine::new(), originals, parity_shards),
+        RsEngine::NoSimd => encode_with(NoSimd::new(), originals, parity_shards),
+    }
+}
+
+fn encode_with<E: Engine, T: AsRef<[u8]>>(
+    engine: E,
+    originals: &[T],
+    parity_shards: usize,
+) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    let shard_bytes = originals
+        .first()
+        .map(|shard| shard.as_ref().len())
+        .ok_or("no shards to encode")?;
+    let mut encoder =
+        DefaultRateEncoder::new(originals.len(), parity_shards, shard_bytes, engine, None)?;
+    for shard in originals {
+        encoder.add_original_shard(shard)?;
+    }
+    let result = encoder.encode()?;
+    Ok(result.recovery_iter().map(|shard| shard.to_vec()).collect())
+}
+
+/// Restores the missing data shards of a set. `originals` and `parity` hold
+/// every shard position, `None` where a shard is lost; all present shards
+/// must be `shard_bytes` long. Returns the restored shards by position.
+pub fn decode(
+    originals: &[Option<&[u8]>],
+    parity: &[Option<&[u8]>],
+    shard_bytes: usize,
+) -> Result<HashMap<usize, Vec<u8>>, Box<dyn std::error::Error>> {
+    match engine() {
+        RsEngine::Auto => decode_with(DefaultEngine::new(), originals, parity, shard_bytes),
+        RsEngine::NoSimd => decode_with(NoSimd::new(), originals, parity, shard_bytes),
+    }
+}
+
+fn decode_with<E: Engine>(
+    engine: E,
+    originals: &[Option<&[u8]>],
+    parity: &[Option<&[u8]>],
+    shard_bytes: usize,
+) -> Result<HashMap<usize, Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut decoder =
+        DefaultRateDecoder::new(originals.len(), parity.len(), shard_bytes, engine, None)?;
+    for (idx, shard) in originals.iter().enumerate() {
+        if let Some(shard) = shard {
+            decoder.add_original_shard(idx, shard)?;
+        }
+    }
+    for (idx, shard) in parity.iter().enumerate() {
+        if let Some(shard) = shard {
+            decoder.add_recovery_shard(idx, shard)?;
+        }
+    }
+    let result = decoder.decode()?;
+    Ok(result
+        .restored_original_iter()
+        .map(|(idx, shard)| (idx, shard.to_vec()))
+        .collect())
+}
+
+/// An erasure code shards are written and recovered with.
+pub trait ErasureCoder: Send + Sync {
+    /// Recorded as the manifest's `erasure_coding.type`.
+    fn name(&self) -> &'static str;
+
+    /// Parity shards for `originals`, which must all be the same length, a
+    /// multiple of 64.
+    fn encode(
+        &self,
+        originals: &[&[u8]],
+        parity_shards: usize,
+    ) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>>;
+
+    /// Restores the missing data shards of a set, as [`decode`] does.
+    fn decode(
+        &self,
+        originals: &[Option<&[u8]>],
+        parity: &[Option<&[u8]>],
+        shard_bytes: usize,
+    ) -> Result<HashMap<usize, Vec<u8>>, Box<dyn std::error::Error>>;
+}
+
+impl std::fmt::Debug for dyn ErasureCoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Reed-Solomon over GF(2^16) with `reed_solomon_simd`, on the engine
+/// [`set_engine`] picks. Every archive written before codecs could be chosen
+/// uses it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReedSolomon;
+
+impl ErasureCoder for ReedSolomon {
+    fn name(&self) -> &'static str {
+        "reed-solomon"
+    }
+
+    fn encode(
+        &self,
+        originals: &[&[u8]],
+        parity_shards: usize,
+    ) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+        encode(originals, parity_shards)
+    }
+
+    fn decode(
+        &self,
+        originals: &[Option<&[u8]>],
+        parity: &[Option<&[u8]>],
+        shard_bytes: usize,
+    ) -> Result<HashMap<usize, Vec<u8>>, Box<dyn std::error::Error>> {
+        decode(originals, parity, shard_bytes)
+    }
+}
+
+/// Reed-Solomon over GF(2^8) with `reed_solomon_erasure`, at most 256 shards
+/// per set. Its parity is not interchangeable with [`ReedSolomon`]'s.
+#[cfg(feature = "rs-galois")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GaloisReedSolomon;
+
+#[cfg(feature = "rs-galois")]
+impl ErasureCoder for GaloisReedSolomon {
+    fn name(&self) -> &'static str {
+        "reed-solomon-galois"
+    }
+
+    fn encode(
+        &self,
+        originals: &[&[u8]],
+        parity_shards: usize,
+    ) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+        let shard_bytes = originals
+            .first()
+            .map(|shard| shard.len())
+            .ok_or("no shards to encode")?;
+        let codec =
+            reed_solomon_erasure::galois_8::ReedSolomon::new(originals.len(), parity_shards)?;
+        let mut parity = vec![vec![0u8; shard_bytes]; parity_shards];
+        codec.encode_sep(originals, &mut parity)?;
+        Ok(parity)
+    }
+
+    fn decode(
+        &self,
+        originals: &[Option<&[u8]>],
+        parity: &[Option<&[u8]>],
+        shard_bytes: usize,
+    ) -> Result<HashMap<usize, Vec<u8>>, Box<dyn std::error::Error>> {
+        let codec =
+            reed_solomon_erasure::galois_8::ReedSolomon::new(originals.len(), parity.len())?;
+        let mut shards: Vec<Option<Vec<u8>>> = originals
+            .iter()
+            .chain(parity)
+            .map(|shard| shard.map(<[u8]>::to_vec))
+            .collect();
+        if shards
+            .iter()
+            .flatten()
+            .any(|shard| shard.len() != shard_bytes)
+        {
+            return Err(format!("every shard must be {} bytes", shard_bytes).into());
+        }
+        codec.reconstruct_data(&mut shards)?;
+        Ok(originals
+            .iter()
+            .zip(shards)
+            .enumerate()
+            .filter(|(_, (original, _))| original.is_none())
+            .filter_map(|(idx, (_, shard))| Some((idx, shard?)))
+            .collect())
+    }
+}
+
+/// The coder an entry whose manifest records `name` as its
+/// `erasure_coding.type` was written with.
+///
+/// ```
+/// # use blockframe::erasure::coder;
+/// assert_eq!(coder("reed-solomon").unwrap().name(), "reed-solomon");
+/// assert!(coder("fountain").is_err());
+/// ```
+pub fn coder(name: &str) -> Result<&'static dyn ErasureCoder, String> {
+    match name.trim().to_lowercase().as_str() {
+        // manifests written by hand and in older docs spell it this way
+        "reed-solomon" | "reed_solomon" => Ok(&ReedSolomon),
+        #[cfg(feature = "rs-galois")]
+        "reed-solomon-galois" => Ok(&GaloisReedSolomon),
+        #[cfg(not(feature = "rs-galois"))]
+        "reed-solomon-galois" => {
+            Err("reed-solomon-galois needs a build with the rs-galois feature".to_string())
+        }
+        other => Err(format!(
+            "unknown erasure code {:?}, expected reed-solomon or reed-solomon-galois",
+            other
+        )),
+    }
+}
+
+/// The local parity of a group of tier 3 segments: their XOR, each padded
+/// with zeros to `shard_bytes`. One lost segment of the group is the XOR of
+/// this with the others, so repairing it reads the group instead of the
+/// whole block.
+///
+/// ```
+/// # use blockframe::erasure::local_parity;
+/// let parity = local_parity(&[b"ab", b"c"], 2);
+/// assert_eq!(local_parity(&[&parity, b"c"], 2), b"ab");
+/// ```
+pub fn local_parity(segments: &[&[u8]], shard_bytes: usize) -> Vec<u8> {
+    let mut parity = vec![0u8; shard_bytes];
+    for segment in segments {
+        for (out, byte) in parity.iter_mut().zip(segment.iter()) {
+            *out ^= byte;
+        }
+    }
+    parity
+}
+
+/// Encodes `rounds` sets of `data_shards` shards of `shard_bytes` with the
+/// current engine and returns the data throughput in MB/s.
+pub fn encode_throughput(
+    data_shards: usize,
+    parity_shards: usize,
+    shard_bytes: usize,
+    rounds: usize,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    let originals: Vec<Vec<u8>> = (0..data_shards)
+        .map(|i| (0..shard_bytes).map(|j| (i * 31 + j) as u8).collect())
+        .collect();
+    let rounds = rounds.max(1);
+    let start = Instant::now();
+    for _ in 0..rounds {
+        std::hint::black_box(encode(&originals, parity_shards)?);
+    }
+    let seconds = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    Ok((data_shards * shard_bytes * rounds) as f64 / seconds / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engines_agree() {
+        let originals: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i * 17 + 3; 256]).collect();
+        let simd = encode_with(DefaultEngine::new(), &originals, 2).unwrap();
+        let portable = encode_with(NoSimd::new(), &originals, 2).unwrap();
+        assert_eq!(simd, portable);
+
+        // either engine restores what the other encoded
+        let mut lost: Vec<Option<&[u8]>> = originals.iter().map(|s| Some(s.as_slice())).collect();
+        lost[1] = None;
+        lost[4] = None;
+        let parity: Vec<Option<&[u8]>> = simd.iter().map(|s| Some(s.as_slice())).collect();
+        let restored = decode_with(NoSimd::new(), &lost, &parity, 256).unwrap();
+        assert_eq!(restored[&1], originals[1]);
+        assert_eq!(restored[&4], originals[4]);
+        assert!(["avx2", "ssse3", "neon", "none"].contains(&simd_path()));
+    }
+
+    #[cfg(feature = "rs-galois")]
+    #[test]
+    fn test_galois_round_trip() {
+        let originals: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i * 29 + 1; 128]).collect();
+        let refs: Vec<&[u8]> = originals.iter().map(Vec::as_slice).collect();
+        let galois = coder("reed-solomon-galois").unwrap();
+        let parity = galois.encode(&refs, 2).unwrap();
+        assert_ne!(parity, ReedSolomon.encode(&refs, 2).unwrap());
+
+        let mut lost: Vec<Option<&[u8]>> = refs.iter().copied().map(Some).collect();
+        lost[0] = None;
+        lost[2] = None;
+        let parity: Vec<Option<&[u8]>> = parity.iter().map(|s| Some(s.as_slice())).collect();
+        let restored = galois.decode(&lost, &parity, 128).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[&0], originals[0]);
+        assert_eq!(restored[&2], originals[2]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Removing dead space from the archive.
+//!
+//! Shards are never rewritten in place, so space is only lost to files nothing
+//! refers to any more:
+//!
+//! - shards left in an entry when the same file was committed again under the
+//!   same name but a different tier,
+//! - alias entries whose original is gone,
+//! - work directories of an interrupted commit, pack, retier or migrate, and
+//!   the `{name}_computing` directory older versions left behind when a tier 2
+//!   or 3 commit was interrupted.
+//!
+//! Only healthy entries are compacted, so a damaged entry keeps everything
+//! until it has been repaired. Dead shards are first moved aside into a hidden
+//! directory and the entry is checked again; if it no longer passes they are
+//! moved back. Work directories are left alone until they are an hour old so a
+//! commit running alongside isn't cut short.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::FileStore;
+use crate::alias;
+use crate::audit::{AuditEntry, AuditOp};
+use crate::chunker::WORK_DIR_PREFIX;
+use crate::filestore::models::{File, HealthStatus};
+use crate::signing::SIGNATURE_FILE;
+
+/// Prefix of the work directories `serve` receives uploads into.
+pub const UPLOAD_DIR_PREFIX: &str = ".upload-";
+
+/// Prefixes of the hidden work directories commands create in the archive.
+const WORK_DIR_PREFIXES: [&str; 6] = [
+    WORK_DIR_PREFIX,
+    UPLOAD_DIR_PREFIX,
+    ".pack-",
+    ".retier-",
+    ".migrate-",
+    ".compact-",
+];
+
+/// Age after which an abandoned work directory is considered dead.
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Outcome of [`FileStore::compact`].
+#[derive(Debug, Default)]
+pub struct CompactReport {
+    /// Files and directories removed, or that would be on a dry run.
+    pub removed: Vec<PathBuf>,
+    /// Bytes freed.
+    pub bytes: u64,
+    /// Entries left alone, with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+impl FileStore {
+    /// Removes unreferenced shards, dangling aliases and abandoned work
+    /// directories. With `dry_run` the report lists what would be removed
+    /// without touching anything.
+    pub fn compact(&self, dry_run: bool) -> Result<CompactReport, Box<dyn std::error::Error>> {
+        if !dry_run {
+            self.ensure_writable("compact")?;
+        }
+        let mut report = CompactReport::default();
+
+        for file in self.get_all()? {
+            if file.alias_of.is_some() {
+                continue;
+            }
+            if let Err(e) = self.compact_entry(&file, dry_run, &mut report) {
+                tracing::warn!("COMPACT | skipping {}: {}", file.file_name, e);
+                report.skipped.push((file.file_name.clone(), e.to_string()));
+            }
+        }
+
+        for dir in self.dead_dirs()? {
+            let bytes = disk_usage(&dir);
+            tracing::info!("COMPACT | removing {:?} ({} bytes)", dir, bytes);
+            if !dry_run {
+                fs::remove_dir_all(&dir)?;
+                self.audit(
+                    &AuditEntry::new(AuditOp::Delete, &dir_name(&dir))
+                        .details(format!("compact: removed dead directory ({} bytes)", bytes)),
+                )?;
+            }
+            report.bytes += bytes;
+            report.removed.push(dir);
+        }
+
+        if !dry_run {
+            self.invalidate();
+        }
+        Ok(report)
+    }
+
+    /// Drops the files in `file`'s entry directory that its manifest doesn't
+    /// refer to.
+    fn compact_entry(
+        &self,
+        file: &File,
+        dry_run: bool,
+        report: &mut CompactReport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file_dir = Path::new(&file.file_data.path)
+            .parent()
+            .ok_or("No parent directory found")?
+            .to_path_buf();
+        let expected = self.referenced_files(file)?;
+        let mut dead = Vec::new();
+        unreferenced(&file_dir, &expected, &mut dead)?;
+        if dead.is_empty() {
+            return Ok(());
+        }
+
+        let status = self.health_check(file)?.status;
+        if status != HealthStatus::Healthy {
+            return Err(format!("entry is {:?}, repair it first", status).into());
+        }
+        let bytes: u64 = dead.iter().map(|path| disk_usage(path)).sum();
+        if dry_run {
+            report.bytes += bytes;
+            report.removed.extend(dead);
+            return Ok(());
+        }
+
+        // move aside first so a surprise can be undone
+        let trash = tempfile::Builder::new()
+            .prefix(".compact-")
+            .tempdir_in(&self.store_path)?;
+        let mut moved = Vec::with_capacity(dead.len());
+        for (idx, path) in dead.iter().enumerate() {
+            let aside = trash.path().join(idx.to_string());
+            fs::rename(path, &aside)?;
+            moved.push((path.clone(), aside));
+        }
+        let status = self.health_check(file)?.status;
+        if status != HealthStatus::Healthy {
+            for (path, aside) in &moved {
+                fs::rename(aside, path)?;
+            }
+            return Err(format!("entry became {:?} without the removed files", status).into());
+        }
+        remove_empty_dirs(&file_dir);
+
+        self.audit(
+            &AuditEntry::new(AuditOp::Delete, &file.file_name)
+                .hash(&file.manifest.original_hash)
+                .details(format!(
+                    "compact: removed {} unreferenced files ({} bytes)",
+                    dead.len(),
+                    bytes
+                )),
+        )?;
+        tracing::info!(
+            "COMPACT | {} freed {} bytes in {} files",
+            file.file_name,
+            bytes,
+            dead.len()
+        );
+        report.bytes += bytes;
+        report.removed.extend(dead);
+        Ok(())
+    }
+
+    /// Every path `file`'s manifest refers to: the manifest, its signature,
+    /// data shards and parity.
+    fn referenced_files(
+        &self,
+        file: &File,
+    ) -> Result<HashSet<PathBuf>, Box<dyn std::error::Error>> {
+        let manifest_path = PathBuf::from(&file.file_data.path);
+        let mut expected: HashSet<PathBuf> = self
+            .data_shards(file)?
+            .into_iter()
+            .map(|shard| shard.path)
+            .chain(
+                self.parity_shards(file)?
+                    .into_iter()
+                    .map(|shard| shard.path),
+            )
+            .collect();
+        if let Some(file_dir) = manifest_path.parent() {
+            expected.insert(file_dir.join(SIGNATURE_FILE));
+        }
+        expected.insert(manifest_path);
+        Ok(expected)
+    }
+
+    /// Top level directories nothing can use: dangling aliases, and stale work
+    /// or placeholder directories.
+    fn dead_dirs(&self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let mut dead = Vec::new();
+        for entry in fs::read_dir(&self.store_path)? {
+            let dir = entry?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            let name = dir_name(&dir);
+            let is_work_dir = WORK_DIR_PREFIXES.iter().any(|p| name.starts_with(p))
+                || (name.ends_with("_computing") && !dir.join("manifest.json").exists());
+
+            if alias::is_alias(&dir) {
+                if let Err(e) = self.resolve_alias(&dir) {
+                    tracing::debug!("COMPACT | {} is a dangling alias: {}", name, e);
+                    dead.push(dir);
+                }
+            } else if is_work_dir && is_stale(&dir) {
+                dead.push(dir);
+            }
+        }
+        Ok(dead)
+    }
+}
+
+fn dir_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age >= STALE_AFTER)
+}
+
+/// Collects the files under `dir` that aren't in `expected`.
+fn unreferenced(
+    dir: &Path,
+    expected: &HashSet<PathBuf>,
+    dead: &mut Vec<PathBuf>,
+) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            unreferenced(&path, expected, dead)?;
+        } else if !expected.contains(&path) {
+            dead.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Removes directories under `dir` left empty, deepest first.
+fn remove_empty_dirs(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            remove_empty_dirs(&path);
+            // only succeeds when empty
+            let _ = fs::remove_dir(&path);
+        }
+    }
+}
+
+/// Bytes used by a file, or everything under a directory.
+fn disk_usage(path: &Path) -> u64 {
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| disk_usage(&entry.path()))
+            .sum(),
+        Err(_) => fs::metadata(path).map(|meta| meta.len()).unwrap_or(0),
+    }
+}
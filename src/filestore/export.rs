@@ -0,0 +1,230 @@
+//! Health results in formats CI dashboards and compliance tools read.
+//!
+//! `health --report` writes a [`BatchHealthReport`] as JUnit XML, one test
+//! case per file, or as SARIF 2.1.0, one result per file that isn't healthy.
+//! The format follows the report's file name: `.xml` for JUnit, `.sarif` or
+//! `.json` for SARIF.
+
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::filestore::models::{BatchHealthReport, HealthReport, HealthStatus};
+
+/// A format [`write_report`] can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Junit,
+    Sarif,
+}
+
+impl ReportFormat {
+    /// The format a report written to `path` should have, by its extension.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use blockframe::filestore::export::ReportFormat;
+    /// # use std::path::Path;
+    /// assert_eq!(ReportFormat::for_path(Path::new("junit.xml")), Ok(ReportFormat::Junit));
+    /// assert_eq!(ReportFormat::for_path(Path::new("out/health.sarif")), Ok(ReportFormat::Sarif));
+    /// assert!(ReportFormat::for_path(Path::new("report.txt")).is_err());
+    /// ```
+    pub fn for_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("xml") => Ok(ReportFormat::Junit),
+            Some("sarif") | Some("json") => Ok(ReportFormat::Sarif),
+            _ => Err(format!(
+                "can't tell the report format of {}, name it .xml for JUnit or .sarif/.json for SARIF",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Writes `batch`, the health of `archive`, to `path` in the format its
+/// name calls for.
+pub fn write_report(
+    batch: &BatchHealthReport,
+    archive: &str,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = match ReportFormat::for_path(path)? {
+        ReportFormat::Junit => junit(batch, archive),
+        ReportFormat::Sarif => serde_json::to_string_pretty(&sarif(batch, archive))?,
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// One `<testsuite>` named after `archive` with a `<testcase>` per file.
+/// Degraded and recoverable files are failures, unrecoverable ones errors.
+pub fn junit(batch: &BatchHealthReport, archive: &str) -> String {
+    let failures = batch.degraded + batch.recoverable;
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"blockframe health\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+        batch.total_files, failures, batch.unrecoverable
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" timestamp=\"{}\">\n",
+        escape(archive),
+        batch.total_files,
+        failures,
+        batch.unrecoverable,
+        Utc::now().format("%Y-%m-%dT%H:%M:%S")
+    ));
+    for (name, report) in &batch.reports {
+        let open = format!(
+            "    <testcase classname=\"{}\" name=\"{}\"",
+            escape(archive),
+            escape(name)
+        );
+        let element = match report.status {
+            HealthStatus::Healthy => {
+                xml.push_str(&open);
+                xml.push_str("/>\n");
+                continue;
+            }
+            HealthStatus::Unrecoverable => "error",
+            HealthStatus::Degraded | HealthStatus::Recoverable => "failure",
+        };
+        xml.push_str(&open);
+        xml.push_str(">\n");
+        xml.push_str(&format!(
+            "      <{} type=\"{}\" message=\"{}\">{}</{}>\n",
+            element,
+            status_name(report.status),
+            escape(&report.details),
+            escape(&shard_lines(report).join("\n")),
+            element
+        ));
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// A SARIF 2.1.0 log with a result per file that isn't healthy: a warning
+/// for degraded and recoverable files, an error for unrecoverable ones.
+pub fn sarif(batch: &BatchHealthReport, archive: &str) -> Value {
+    let rules: Vec<Value> = [
+        (
+            HealthStatus::Degraded,
+            "Data is intact but parity is missing",
+        ),
+        (
+            HealthStatus::Recoverable,
+            "Data is damaged and can be repaired from parity",
+        ),
+        (
+            HealthStatus::Unrecoverable,
+            "Data is damaged beyond what its parity can repair",
+        ),
+    ]
+    .iter()
+    .map(|(status, description)| {
+        json!({
+            "id": status_name(*status),
+            "shortDescription": { "text": description },
+            "defaultConfiguration": { "level": level(*status) },
+        })
+    })
+    .collect();
+
+    let results: Vec<Value> = batch
+        .reports
+        .iter()
+        .filter(|(_, report)| report.status != HealthStatus::Healthy)
+        .map(|(name, report)| {
+            let mut text = format!("{}: {}", name, report.details);
+            let shards = shard_lines(report);
+            if !shards.is_empty() {
+                text.push_str(&format!(" ({})", shards.join(", ")));
+            }
+            json!({
+                "ruleId": status_name(report.status),
+                "level": level(report.status),
+                "message": { "text": text },
+                "locations": [{
+                    "logicalLocations": [{
+                        "name": name,
+                        "fullyQualifiedName": format!("{}/{}", archive, name),
+                        "kind": "resource",
+                    }],
+                }],
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "blockframe",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn status_name(status: HealthStatus) -> &'static str {
+    match status {
+        HealthStatus::Healthy => "healthy",
+        HealthStatus::Degraded => "degraded",
+        HealthStatus::Recoverable => "recoverable",
+        HealthStatus::Unrecoverable => "unrecoverable",
+    }
+}
+
+fn level(status: HealthStatus) -> &'static str {
+    match status {
+        HealthStatus::Healthy => "none",
+        HealthStatus::Degraded | HealthStatus::Recoverable => "warning",
+        HealthStatus::Unrecoverable => "error",
+    }
+}
+
+/// The shards a report lists as missing or corrupt, one per line.
+fn shard_lines(report: &HealthReport) -> Vec<String> {
+    let missing = report
+        .missing_data
+        .iter()
+        .chain(&report.missing_parity)
+        .map(|shard| format!("missing {}", shard));
+    let corrupt = report
+        .corrupt_segments
+        .iter()
+        .map(|shard| format!("corrupt {}", shard));
+    missing.chain(corrupt).collect()
+}
+
+/// Escapes text for an XML attribute or element.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in XML 1.0 at all
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,497 @@
+//! Archive-wide parity across files.
+//!
+//! Per-file parity can't help once a whole entry directory is gone, manifest
+//! and all. With `erasure.global_parity` set, every committed file is also
+//! XORed into a parity volume shared with up to that many other files, kept in
+//! `.global-parity/` inside the archive next to an `index.json` listing each
+//! volume's members. Like a RAID 5 across files, one lost member per volume is
+//! rebuilt from the volume and the other members, then committed again.
+//!
+//! Volumes are updated in place as files are committed and pruned: the XOR of
+//! a pruned file is taken back out, so it isn't counted as lost. A volume is
+//! marked stale while it is being changed, and a stale volume is never used
+//! for recovery until [`FileStore::rebuild_global_parity`] has recomputed it.
+//! Updates are serialised within a process, so only one process should commit
+//! to an archive with global parity at a time.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::FileStore;
+use crate::chunker::{ChunkedFile, Chunker};
+use crate::filestore::models::File;
+use crate::merkle_tree::manifest::{ContainerMember, ManifestFile, PackMember};
+use crate::tags::Tags;
+use crate::utils::hash_file_streaming;
+
+/// Directory inside the archive holding the volumes and their index.
+pub const GLOBAL_PARITY_DIR: &str = ".global-parity";
+
+const INDEX_FILE: &str = "index.json";
+
+/// Bytes XORed at a time.
+const XOR_CHUNK: usize = 1 << 20;
+
+/// Guards the index and volumes of every archive in this process.
+static UPDATE: Mutex<()> = Mutex::new(());
+
+/// The volumes of an archive, as saved in `index.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GlobalParityIndex {
+    pub volumes: Vec<ParityVolume>,
+    /// Members per volume the last commit asked for, 0 in an index saved
+    /// before it was recorded.
+    #[serde(default)]
+    pub width: usize,
+}
+
+/// One parity volume: the XOR of its members' contents, each starting at byte 0.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParityVolume {
+    pub members: Vec<VolumeMember>,
+    /// Set while the volume is being changed, cleared once it is consistent.
+    #[serde(default)]
+    pub stale: bool,
+}
+
+/// A file XORed into a volume, with what it takes to commit it again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeMember {
+    pub name: String,
+    pub hash: String,
+    pub size: u64,
+    pub tier: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pack: Vec<PackMember>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<ContainerMember>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: Tags,
+}
+
+/// Where the archive's global parity stands, from [`FileStore::global_parity_status`].
+#[derive(Debug, Default)]
+pub struct GlobalParityReport {
+    pub volumes: usize,
+    /// Files covered, over all volumes.
+    pub members: usize,
+    /// Bytes of parity on disk.
+    pub bytes: u64,
+    /// Members no longer archived, by volume.
+    pub missing: Vec<(usize, VolumeMember)>,
+    /// Volumes that can't be used until rebuilt.
+    pub stale: Vec<usize>,
+    /// Volumes missing more members than their parity can rebuild.
+    pub unrecoverable: Vec<usize>,
+}
+
+impl FileStore {
+    fn global_parity_dir(&self) -> PathBuf {
+        self.store_path.join(GLOBAL_PARITY_DIR)
+    }
+
+    fn volume_path(&self, volume: usize) -> PathBuf {
+        self.global_parity_dir()
+            .join(format!("volume_{}.dat", volume))
+    }
+
+    /// The saved index, empty if the archive has no global parity yet.
+    pub fn global_parity_index(&self) -> Result<GlobalParityIndex, Box<dyn std::error::Error>> {
+        match fs::read_to_string(self.global_parity_dir().join(INDEX_FILE)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(GlobalParityIndex::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_global_parity_index(
+        &self,
+        index: &GlobalParityIndex,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = self.global_parity_dir();
+        fs::create_dir_all(&dir)?;
+        let staged = dir.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&staged, serde_json::to_string_pretty(index)?)?;
+        fs::rename(staged, dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    /// XORs `file` into the last volume, or a new one once that has `width`
+    /// members. Content already covered by a volume isn't added again.
+    pub fn add_to_global_parity(
+        &self,
+        file: &File,
+        width: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let _guard = UPDATE.lock();
+        let mut index = self.global_parity_index()?;
+        let hash = &file.manifest.original_hash;
+        if index
+            .volumes
+            .iter()
+            .any(|v| v.members.iter().any(|m| m.hash == *hash))
+        {
+            return Ok(());
+        }
+        let volume = match index.volumes.last() {
+            Some(last) if last.members.len() < width.max(1) => index.volumes.len() - 1,
+            _ => {
+                index.volumes.push(ParityVolume::default());
+                index.volumes.len() - 1
+            }
+        };
+
+        index.volumes[volume].stale = true;
+        index.width = width;
+        self.save_global_parity_index(&index)?;
+        xor_into(&self.volume_path(volume), self.open_reader(file)?, u64::MAX)?;
+        index.volumes[volume].members.push(VolumeMember {
+            name: file.file_name.clone(),
+            hash: hash.clone(),
+            size: file.manifest.size.max(0) as u64,
+            tier: file.manifest.tier,
+            pack: file.manifest.pack.clone(),
+            members: file.manifest.members.clone(),
+            tags: file.manifest.tags.clone(),
+        });
+        index.volumes[volume].stale = false;
+        self.save_global_parity_index(&index)?;
+        tracing::info!(
+            "GLOBAL PARITY | added {} to volume {}",
+            file.file_name,
+            volume
+        );
+        Ok(())
+    }
+
+    /// Updates the indexes and tags kept for the content of `manifest` to
+    /// those in it, which were added after the content went into a volume.
+    pub(crate) fn refresh_global_parity(
+        &self,
+        manifest: &ManifestFile,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let _guard = UPDATE.lock();
+        let mut index = self.global_parity_index()?;
+        let mut changed = false;
+        for member in index
+            .volumes
+            .iter_mut()
+            .flat_map(|v| v.members.iter_mut())
+            .filter(|m| m.hash == manifest.original_hash)
+        {
+            member.pack = manifest.pack.clone();
+            member.members = manifest.members.clone();
+            member.tags = manifest.tags.clone();
+            changed = true;
+        }
+        if changed {
+            self.save_global_parity_index(&index)?;
+        }
+        Ok(())
+    }
+
+    /// Takes `file` back out of its volume before its entry is removed, unless
+    /// another entry still holds the same content. If it can't be read the
+    /// volume is left stale.
+    pub fn forget_global_parity(&self, file: &File) -> Result<(), Box<dyn std::error::Error>> {
+        // an alias holds no content of its own
+        if file.alias_of.is_some() {
+            return Ok(());
+        }
+        let _guard = UPDATE.lock();
+        let mut index = self.global_parity_index()?;
+        let hash = &file.manifest.original_hash;
+        let Some((volume, position)) = index.volumes.iter().enumerate().find_map(|(v, vol)| {
+            vol.members
+                .iter()
+                .position(|m| m.hash == *hash)
+                .map(|p| (v, p))
+        }) else {
+            return Ok(());
+        };
+        let this_manifest = Path::new(&file.file_data.path);
+        let held_elsewhere = self.get_all()?.iter().any(|other| {
+            other.alias_of.is_none()
+                && other.manifest.original_hash == *hash
+                && Path::new(&other.file_data.path) != this_manifest
+        });
+        if held_elsewhere {
+            return Ok(());
+        }
+
+        index.volumes[volume].stale = true;
+        self.save_global_parity_index(&index)?;
+        let path = self.volume_path(volume);
+        if let Err(e) = self
+            .open_reader(file)
+            .and_then(|reader| xor_into(&path, reader, u64::MAX).map_err(Into::into))
+        {
+            tracing::warn!(
+                "GLOBAL PARITY | volume {} is stale, {} could not be taken out: {}",
+                volume,
+                file.file_name,
+                e
+            );
+            return Ok(());
+        }
+        let member = index.volumes[volume].members.remove(position);
+        // the tail past the remaining members is all zeros now
+        let longest = index.volumes[volume]
+            .members
+            .iter()
+            .map(|m| m.size)
+            .max()
+            .unwrap_or(0);
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(longest)?;
+        index.volumes[volume].stale = false;
+        self.save_global_parity_index(&index)?;
+        tracing::info!(
+            "GLOBAL PARITY | took {} out of volume {}",
+            member.name,
+            volume
+        );
+        Ok(())
+    }
+
+    /// Puts an undeleted `file` back into the volumes, which
+    /// [`FileStore::trash`] took it out of, at the width the index records or
+    /// that of the widest volume in an older index. Does nothing in an archive
+    /// without global parity.
+    pub(crate) fn restore_global_parity(
+        &self,
+        file: &File,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if file.alias_of.is_some() || !self.global_parity_dir().exists() {
+            return Ok(());
+        }
+        let index = self.global_parity_index()?;
+        let width = match index.width {
+            0 => index
+                .volumes
+                .iter()
+                .map(|v| v.members.len())
+                .max()
+                .unwrap_or(0),
+            width => width,
+        };
+        self.add_to_global_parity(file, width)
+    }
+
+    /// [`FileStore::forget_global_parity`] for the entry in `dir`.
+    pub(crate) fn forget_global_parity_dir(
+        &self,
+        dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.global_parity_dir().exists() {
+            return Ok(());
+        }
+        let manifest = dir.join("manifest.json");
+        match self
+            .get_all()?
+            .into_iter()
+            .find(|f| f.alias_of.is_none() && Path::new(&f.file_data.path) == manifest)
+        {
+            Some(file) => self.forget_global_parity(&file),
+            None => Ok(()),
+        }
+    }
+
+    /// Archived content by hash, for finding the members of a volume.
+    fn entries_by_hash(&self) -> Result<HashMap<String, File>, Box<dyn std::error::Error>> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|file| file.alias_of.is_none())
+            .map(|file| (file.manifest.original_hash.clone(), file))
+            .collect())
+    }
+
+    /// Which members are gone and whether their volumes can bring them back.
+    pub fn global_parity_status(&self) -> Result<GlobalParityReport, Box<dyn std::error::Error>> {
+        let index = self.global_parity_index()?;
+        let archived = self.entries_by_hash()?;
+        let mut report = GlobalParityReport {
+            volumes: index.volumes.len(),
+            ..Default::default()
+        };
+        for (volume, vol) in index.volumes.iter().enumerate() {
+            report.members += vol.members.len();
+            report.bytes += fs::metadata(self.volume_path(volume)).map_or(0, |m| m.len());
+            let missing: Vec<_> = vol
+                .members
+                .iter()
+                .filter(|m| !archived.contains_key(&m.hash))
+                .cloned()
+                .collect();
+            if vol.stale {
+                report.stale.push(volume);
+            } else if missing.len() > 1 {
+                report.unrecoverable.push(volume);
+            }
+            report
+                .missing
+                .extend(missing.into_iter().map(|m| (volume, m)));
+        }
+        Ok(report)
+    }
+
+    /// Rebuilds every member that is no longer archived from its volume and
+    /// commits it again with `chunker`. Returns the entries committed; members
+    /// of stale volumes, or of volumes missing more than one, are skipped.
+    pub fn recover_from_global_parity(
+        &self,
+        chunker: &Chunker,
+    ) -> Result<Vec<ChunkedFile>, Box<dyn std::error::Error>> {
+        self.ensure_writable("global parity recovery")?;
+        let report = self.global_parity_status()?;
+        let index = self.global_parity_index()?;
+        let archived = self.entries_by_hash()?;
+        let mut recovered = Vec::new();
+        for (volume, member) in &report.missing {
+            if report.stale.contains(volume) || report.unrecoverable.contains(volume) {
+                tracing::warn!(
+                    "GLOBAL PARITY | can't recover {}, volume {} is {}",
+                    member.name,
+                    volume,
+                    if report.stale.contains(volume) {
+                        "stale"
+                    } else {
+                        "missing other members too"
+                    }
+                );
+                continue;
+            }
+
+            let work = tempfile::Builder::new()
+                .prefix(".global-parity-")
+                .tempdir_in(&self.store_path)?;
+            let out = work.path().join(&member.name);
+            {
+                let mut restored = fs::File::create(&out)?;
+                io::copy(
+                    &mut fs::File::open(self.volume_path(*volume))?.take(member.size),
+                    &mut restored,
+                )?;
+                restored.set_len(member.size)?;
+            }
+            for other in &index.volumes[*volume].members {
+                if other.hash == member.hash {
+                    continue;
+                }
+                let file = archived
+                    .get(&other.hash)
+                    .ok_or_else(|| format!("{} is no longer archived", other.name))?;
+                xor_into(&out, self.open_reader(file)?, member.size)?;
+            }
+            if hash_file_streaming(&out)? != member.hash {
+                return Err(format!(
+                    "{} rebuilt from volume {} doesn't match its hash",
+                    member.name, volume
+                )
+                .into());
+            }
+
+            // committed with its indexes and tags, so they are in the manifest
+            // before the entry is published
+            let chunked = chunker
+                .with_pack(member.pack.clone())
+                .with_members(member.members.clone())
+                .with_tags(&member.tags)
+                .encode_as(&out, Some(member.tier), &member.hash)?;
+            tracing::info!(
+                "GLOBAL PARITY | recovered {} from volume {}",
+                member.name,
+                volume
+            );
+            recovered.push(chunked);
+        }
+        self.invalidate();
+        Ok(recovered)
+    }
+
+    /// Recomputes stale volumes from the members still archived, dropping
+    /// those that aren't, which a stale volume can't bring back. Returns the
+    /// members dropped.
+    pub fn rebuild_global_parity(&self) -> Result<Vec<VolumeMember>, Box<dyn std::error::Error>> {
+        self.ensure_writable("global parity rebuild")?;
+        let _guard = UPDATE.lock();
+        let mut index = self.global_parity_index()?;
+        let archived = self.entries_by_hash()?;
+        let mut dropped = Vec::new();
+        for (volume, vol) in index.volumes.iter_mut().enumerate() {
+            if !vol.stale {
+                continue;
+            }
+            let path = self.volume_path(volume);
+            let staged = path.with_extension("rebuild");
+            fs::File::create(&staged)?;
+            let (kept, gone): (Vec<_>, Vec<_>) = vol
+                .members
+                .drain(..)
+                .partition(|m| archived.contains_key(&m.hash));
+            for member in &kept {
+                xor_into(
+                    &staged,
+                    self.open_reader(&archived[&member.hash])?,
+                    u64::MAX,
+                )?;
+            }
+            fs::rename(&staged, &path)?;
+            tracing::info!(
+                "GLOBAL PARITY | rebuilt volume {} from {} members",
+                volume,
+                kept.len()
+            );
+            vol.members = kept;
+            vol.stale = false;
+            dropped.extend(gone);
+        }
+        self.save_global_parity_index(&index)?;
+        Ok(dropped)
+    }
+}
+
+/// XORs the first `limit` bytes of `content` into the file at `path` from its
+/// start, growing it as needed. Returns the bytes XORed.
+fn xor_into(path: &Path, mut content: impl Read, limit: u64) -> io::Result<u64> {
+    let mut volume = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let mut incoming = vec![0u8; XOR_CHUNK];
+    let mut existing = vec![0u8; XOR_CHUNK];
+    let mut offset = 0u64;
+    while offset < limit {
+        let want = (limit - offset).min(XOR_CHUNK as u64) as usize;
+        let read = content.read(&mut incoming[..want])?;
+        if read == 0 {
+            break;
+        }
+        volume.seek(SeekFrom::Start(offset))?;
+        let mut have = 0;
+        while have < read {
+            match volume.read(&mut existing[have..read])? {
+                0 => break,
+                n => have += n,
+            }
+        }
+        existing[have..read].fill(0);
+        for (e, i) in existing[..read].iter_mut().zip(&incoming[..read]) {
+            *e ^= i;
+        }
+        volume.seek(SeekFrom::Start(offset))?;
+        volume.write_all(&existing[..read])?;
+        offset += read as u64;
+    }
+    volume.flush()?;
+    Ok(offset)
+}
@@ -1,16 +1,19 @@
 // use reed_solomon_simd::ReedSolomonEncoder;
 use std::{
-    fs,
+    collections::BTreeMap,
     path::{Path, PathBuf},
 };
 
 use crate::{
+    audit::{AuditEntry, AuditOp},
+    erasure,
     filestore::models::{BatchHealthReport, File, HealthReport, HealthStatus},
+    merkle_tree::manifest::{BlockHashes, SegmentHashes},
     utils::blake3_hash_bytes,
 };
-use reed_solomon_simd::ReedSolomonDecoder;
 
 use super::FileStore;
+use super::recovery::{ShardKind, expected_shard};
 
 impl FileStore {
     /// Performs health checks on all files in the archive directory.
@@ -43,7 +46,12 @@ impl FileStore {
     /// println!("Healthy: {}/{}", batch_report.healthy, batch_report.total_files);
     /// ```
     pub fn batch_health_check(&self) -> Result<BatchHealthReport, Box<dyn std::error::Error>> {
-        let files = self.get_all()?;
+        // aliases share their original's shards, checking them again adds nothing
+        let files: Vec<File> = self
+            .get_all()?
+            .into_iter()
+            .filter(|file| file.alias_of.is_none())
+            .collect();
         let mut reports = Vec::new();
         let mut healthy = 0;
         let mut degraded = 0;
@@ -62,6 +70,7 @@ impl FileStore {
 
             reports.push((file.file_name.clone(), report));
         }
+        self.try_record_health(reports.iter().map(|(name, report)| (name.as_str(), report)));
 
         Ok(BatchHealthReport {
             total_files: files.len(),
@@ -73,6 +82,71 @@ impl FileStore {
         })
     }
 
+    /// Runs a batch health check, repairs every file that isn't healthy, most
+    /// at risk first, and returns a fresh report taken after the repairs.
+    ///
+    /// Individual repair failures are logged and don't stop the pass, they show
+    /// up in the returned report instead.
+    pub fn scrub(&self) -> Result<BatchHealthReport, Box<dyn std::error::Error>> {
+        self.scrub_with(|_| {})
+    }
+
+    /// Same as [`FileStore::scrub`], but hands every report to `on_report`: the
+    /// one taken before repairing and, if anything was repaired, the one after.
+    pub fn scrub_with<F>(
+        &self,
+        on_report: F,
+    ) -> Result<BatchHealthReport, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&BatchHealthReport),
+    {
+        self.scrub_using(Self::batch_health_check, on_report)
+    }
+
+    /// Same as [`FileStore::scrub_with`], with both checks sampled as in
+    /// [`FileStore::sample_health_check`]. A damaged file is still repaired
+    /// as a whole.
+    pub fn scrub_sampled_with<F>(
+        &self,
+        per_file: usize,
+        seed: u64,
+        on_report: F,
+    ) -> Result<BatchHealthReport, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&BatchHealthReport),
+    {
+        self.scrub_using(|store| store.sample_health_check(per_file, seed), on_report)
+    }
+
+    fn scrub_using<C, F>(
+        &self,
+        check: C,
+        mut on_report: F,
+    ) -> Result<BatchHealthReport, Box<dyn std::error::Error>>
+    where
+        C: Fn(&Self) -> Result<BatchHealthReport, Box<dyn std::error::Error>>,
+        F: FnMut(&BatchHealthReport),
+    {
+        let batch_report = check(self)?;
+        on_report(&batch_report);
+        if batch_report.healthy == batch_report.total_files {
+            return Ok(batch_report);
+        }
+
+        // most at risk first, see planner.rs
+        for task in self.plan_repairs_for(&batch_report)? {
+            let file = self.find(&task.file_name)?;
+            match self.repair(&file) {
+                Ok(_) => tracing::info!("SCRUB | repaired {}", task.file_name),
+                Err(e) => tracing::error!("SCRUB | repair of {} failed: {}", task.file_name, e),
+            }
+        }
+
+        let post_repair = check(self)?;
+        on_report(&post_repair);
+        Ok(post_repair)
+    }
+
     /// Checks the health of a single file by verifying data integrity and parity availability.
     ///
     /// Routes to the appropriate tier-specific health check based on the file's tier.
@@ -91,6 +165,9 @@ impl FileStore {
     /// - Whether the file is recoverable
     /// - Human-readable details
     ///
+    /// When the store has a verifier, a manifest with a bad or (if required)
+    /// missing signature is reported as unrecoverable.
+    ///
     /// # Errors
     ///
     /// Returns an error if:
@@ -112,14 +189,155 @@ impl FileStore {
         &self,
         file_obj: &File,
     ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        if let Some(report) = self.untrusted(file_obj) {
+            return Ok(report);
+        }
+
         match file_obj.manifest.tier {
             1 => self.health_check_tiny(file_obj),
-            2 => self.health_check_segment(file_obj),
-            3 => self.health_check_block(file_obj),
+            2 => self.health_check_segmented(file_obj),
+            3 => self.health_check_blocked(file_obj),
             _ => Err("unknown file".into()),
         }
     }
 
+    /// Checks one data segment of a file and the parity protecting it,
+    /// without hashing the rest of the entry, e.g. after a bad sector.
+    ///
+    /// `idx` counts data segments from the start of the file: a tier 1 file
+    /// has only segment 0, and tier 3 segments are numbered across blocks,
+    /// segment 30 being the first of block 1 with the default geometry. A tier
+    /// 3 segment is checked with its block's parity and classified as that
+    /// block would be.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use blockframe::filestore::FileStore;
+    /// let store = FileStore::new(Path::new("archive_directory")).unwrap();
+    /// let file = store.find(&"disk.img".to_string()).unwrap();
+    /// let health = store.health_check_segment(&file, 1_042).unwrap();
+    /// println!("Status: {:?}", health.status);
+    /// ```
+    pub fn health_check_segment(
+        &self,
+        file_obj: &File,
+        idx: usize,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        if let Some(report) = self.untrusted(file_obj) {
+            return Ok(report);
+        }
+        match file_obj.manifest.tier {
+            1 if idx == 0 => self.health_check_tiny(file_obj),
+            1 => Err(format!(
+                "{} is a tier 1 file, it only has segment 0",
+                file_obj.file_name
+            )
+            .into()),
+            2 => {
+                let info = file_obj
+                    .manifest
+                    .merkle_tree
+                    .segments
+                    .get(&idx)
+                    .ok_or_else(|| format!("{} has no segment {}", file_obj.file_name, idx))?;
+                let parity_shards = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
+                let mut found = Shards::default();
+                let healthy = self.check_segment(file_obj, idx, info, &mut found)?;
+                let (status, recoverable) = classify_segmented(
+                    found.missing_data.len() + found.corrupt_segments.len(),
+                    parity_shards,
+                    found.missing_parity.is_empty(),
+                );
+                let details = format!(
+                    "segment {}: {}, {}/{} parity shards missing or corrupt",
+                    idx,
+                    if healthy { "valid" } else { "corrupt/missing" },
+                    found.missing_parity.len(),
+                    parity_shards
+                );
+                Ok(found.into_report(status, recoverable, details))
+            }
+            3 => {
+                let mut blocks: Vec<_> = file_obj.manifest.merkle_tree.blocks.iter().collect();
+                blocks.sort_by_key(|(id, _)| **id);
+                let mut first = 0;
+                for (block_id, block) in blocks {
+                    let data_shards = block.geometry().0;
+                    if idx < first + data_shards {
+                        let mut found = Shards::default();
+                        let block_status = self.check_block(
+                            file_obj,
+                            *block_id,
+                            block,
+                            Some(idx - first),
+                            &mut found,
+                        )?;
+                        return Ok(blocked_report(
+                            1,
+                            [block_status],
+                            found,
+                            format!(
+                                "segment {} (block {} segment {})",
+                                idx,
+                                block_id,
+                                idx - first
+                            ),
+                        ));
+                    }
+                    first += data_shards;
+                }
+                Err(format!("{} has no segment {}", file_obj.file_name, idx).into())
+            }
+            _ => Err("unknown file".into()),
+        }
+    }
+
+    /// Checks one block of a tier 3 file, its segments and its parity,
+    /// without hashing the other blocks.
+    pub fn health_check_block(
+        &self,
+        file_obj: &File,
+        block_id: usize,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        if let Some(report) = self.untrusted(file_obj) {
+            return Ok(report);
+        }
+        if file_obj.manifest.tier != 3 {
+            return Err(format!(
+                "{} is a tier {} file, only tier 3 files have blocks",
+                file_obj.file_name, file_obj.manifest.tier
+            )
+            .into());
+        }
+        let block = file_obj
+            .manifest
+            .merkle_tree
+            .blocks
+            .get(&block_id)
+            .ok_or_else(|| format!("{} has no block {}", file_obj.file_name, block_id))?;
+        let mut found = Shards::default();
+        let block_status = self.check_block(file_obj, block_id, block, None, &mut found)?;
+        Ok(blocked_report(
+            1,
+            [block_status],
+            found,
+            format!("block {}", block_id),
+        ))
+    }
+
+    /// An unrecoverable report when the manifest fails its signature or
+    /// geometry check, as it can't be trusted to check or repair against.
+    fn untrusted(&self, file_obj: &File) -> Option<HealthReport> {
+        let verified = self
+            .verify_manifest(file_obj)
+            .and_then(|_| Ok(file_obj.manifest.check_segment_size()?));
+        let e = verified.err()?;
+        tracing::error!("HEALTH | {}", e);
+        Some(Shards::default().into_report(HealthStatus::Unrecoverable, false, e.to_string()))
+    }
+
     /// Health check for Tier 1 (tiny) files using RS(1,3) encoding.
     ///
     /// Verifies the integrity of `data.dat` by comparing its hash against the manifest.
@@ -148,7 +366,7 @@ impl FileStore {
         let mut data_valid = false;
 
         if data_exists {
-            match fs::read(&data_path) {
+            match self.read_for_check(&data_path) {
                 Ok(data) => match blake3_hash_bytes(&data) {
                     Ok(hash) => {
                         if hash == file_obj.file_data.hash {
@@ -168,7 +386,7 @@ impl FileStore {
         // Check parity files
         let mut parity_count = 0;
         for i in 0..3 {
-            let parity_path = file_dir.join(format!("parity_{}.dat", i));
+            let parity_path = self.get_parity_path_t1(file_obj, i)?;
             if parity_path.exists() {
                 parity_count += 1;
             } else {
@@ -177,15 +395,7 @@ impl FileStore {
         }
 
         // Determine status
-        let (status, recoverable) = if data_valid && parity_count == 3 {
-            (HealthStatus::Healthy, true)
-        } else if data_valid && parity_count > 0 {
-            (HealthStatus::Degraded, true)
-        } else if !data_valid && parity_count > 0 {
-            (HealthStatus::Recoverable, true)
-        } else {
-            (HealthStatus::Unrecoverable, false)
-        };
+        let (status, recoverable) = classify_tiny(data_valid, parity_count);
 
         let details = format!(
             "Data: {}, Parity: {}/3",
@@ -217,224 +427,227 @@ impl FileStore {
     /// - **Recoverable**: Missing/corrupt segments ≤ parity shards available (≤3)
     /// - **Degraded**: Some parity missing but all data segments healthy
     /// - **Unrecoverable**: Too many segments lost to recover
-    fn health_check_segment(
+    fn health_check_segmented(
         &self,
         file_obj: &File,
     ) -> Result<HealthReport, Box<dyn std::error::Error>> {
-        let file_folder_path = Path::new(&file_obj.file_data.path)
-            .parent()
-            .ok_or("No parent directory found")?;
-
-        let segments_path = file_folder_path.join("segments");
-        let parity_path = file_folder_path.join("parity");
-
         let segments_map = &file_obj.manifest.merkle_tree.segments;
         let parity_shards = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
 
-        let mut missing_data = Vec::new();
-        let mut missing_parity = Vec::new();
-        let mut corrupt_segments = Vec::new();
+        let mut found = Shards::default();
         let mut total_segments = 0;
         let mut healthy_segments = 0;
 
         for (idx, segment_info) in segments_map {
             total_segments += 1;
-            let current_segment = segments_path.join(format!("segment_{}.dat", idx));
-
-            // Check segment data
-            let segment_data = match fs::read(&current_segment) {
-                Ok(data) => data,
-                Err(_) => {
-                    missing_data.push(format!("segment_{}.dat", idx));
-                    continue;
-                }
-            };
-
-            // Verify Data Hash
-            if let Ok(actual) = blake3_hash_bytes(&segment_data) {
-                if actual != segment_info.data {
-                    corrupt_segments.push(format!("segment_{}.dat", idx));
-                } else {
-                    healthy_segments += 1;
-                }
-            }
-
-            // Check parity files
-            for parity_idx in 0..parity_shards {
-                let parity_file =
-                    parity_path.join(format!("segment_{}_parity_{}.dat", idx, parity_idx));
-
-                match fs::read(&parity_file) {
-                    Ok(chunk) => {
-                        // Verify Parity Hash
-                        if let Some(expected) = segment_info.parity.get(parity_idx)
-                            && let Ok(actual) = blake3_hash_bytes(&chunk)
-                            && actual != *expected
-                        {
-                            missing_parity.push(format!(
-                                "segment_{}_parity_{}.dat (CORRUPT)",
-                                idx, parity_idx
-                            ));
-                        }
-                    }
-                    Err(_) => {
-                        missing_parity.push(format!("segment_{}_parity_{}.dat", idx, parity_idx));
-                    }
-                }
+            if self.check_segment(file_obj, *idx, segment_info, &mut found)? {
+                healthy_segments += 1;
             }
         }
 
         // Determine status
-        let missing_count = missing_data.len();
-        let corrupt_count = corrupt_segments.len();
-        let (status, recoverable) = if missing_count == 0 && corrupt_count == 0 {
-            (HealthStatus::Healthy, true)
-        } else if missing_count + corrupt_count <= parity_shards {
-            (HealthStatus::Recoverable, true)
-        } else if missing_parity.is_empty() {
-            (HealthStatus::Degraded, true)
-        } else {
-            (HealthStatus::Unrecoverable, false)
-        };
+        let missing_count = found.missing_data.len();
+        let corrupt_count = found.corrupt_segments.len();
+        let (status, recoverable) = classify_segmented(
+            missing_count + corrupt_count,
+            parity_shards,
+            found.missing_parity.is_empty(),
+        );
 
         let details = format!(
             "{}/{} segments healthy, {} missing, {} corrupt",
             healthy_segments, total_segments, missing_count, corrupt_count
         );
 
-        Ok(HealthReport {
-            status,
-            missing_data,
-            missing_parity,
-            corrupt_segments,
-            recoverable,
-            details,
-        })
+        Ok(found.into_report(status, recoverable, details))
     }
 
-    /// Health check for Tier 3 (blocked) files using per-block RS(30,3) encoding.
-    ///
-    /// Scans all blocks, checks segment availability within each block, and verifies
-    /// that block-level parity files exist. Each block can tolerate up to 3 missing segments.
-    ///
-    /// # Status Logic
-    /// - **Healthy**: All blocks have all segments + parity
-    /// - **Recoverable**: Some blocks have ≤3 missing segments with parity available
-    /// - **Degraded**: No missing segments but some parity missing
-    /// - **Unrecoverable**: Any block has >3 missing segments
-    fn health_check_block(
+    /// Hashes one tier 2 segment and its parity against the manifest, adding
+    /// whatever is missing or corrupt to `found`. True if the data is intact.
+    /// The parity of a missing segment isn't looked at.
+    fn check_segment(
         &self,
         file_obj: &File,
-    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        idx: usize,
+        segment_info: &SegmentHashes,
+        found: &mut Shards,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
         let file_folder_path = Path::new(&file_obj.file_data.path)
             .parent()
             .ok_or("No parent directory found")?;
+        let current_segment = file_folder_path.join(format!("segments/segment_{}.dat", idx));
+        let parity_shards = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
 
-        let blocks_path = file_folder_path.join("blocks");
-
-        let block_dirs: Vec<_> = fs::read_dir(&blocks_path)?
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().is_dir())
-            .collect();
+        // Check segment data
+        let segment_data = match self.read_for_check(&current_segment) {
+            Ok(data) => data,
+            Err(_) => {
+                found.missing_data.push(format!("segment_{}.dat", idx));
+                return Ok(false);
+            }
+        };
 
-        let parity_shards = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
-        let data_shards = file_obj.manifest.erasure_coding.data_shards.max(0) as usize;
+        // Verify Data Hash
+        let mut healthy = false;
+        if let Ok(actual) = blake3_hash_bytes(&segment_data) {
+            if actual != segment_info.data {
+                found.corrupt_segments.push(format!("segment_{}.dat", idx));
+            } else {
+                healthy = true;
+            }
+        }
 
-        let mut missing_data = Vec::new();
-        let mut missing_parity = Vec::new();
-        let corrupt_segments = Vec::new();
-        let mut total_blocks = 0;
-        let mut healthy_blocks = 0;
-        let mut recoverable_blocks = 0;
-        let mut unrecoverable_blocks = 0;
-
-        for block_entry in block_dirs {
-            total_blocks += 1;
-            let block_dir = block_entry.path();
-            let block_name = block_dir
-                .file_name()
-                .and_then(|n| n.to_str())
-                .ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::NotFound, "Block name not found")
-                })?;
-            let segments_dir = block_dir.join("segments"); // Corrected: Use block_dir
-            let parity_dir = block_dir.join("parity");
-
-            // Count existing segments
-            let existing_segments: Vec<_> = fs::read_dir(&segments_dir)?
-                .filter_map(|e| e.ok())
-                .filter_map(|e| {
-                    // Avoid borrowing from a temporary PathBuf returned by `e.path()`
-                    // by converting the file name into an owned String.
-                    if let Ok(name) = e.file_name().into_string() {
-                        if name.starts_with("segment_") && name.ends_with(".dat") {
-                            Some(e)
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
+        // Check parity files
+        for parity_idx in 0..parity_shards {
+            let parity_file = self.get_parity_path_t2(file_obj, idx, parity_idx)?;
+
+            match self.read_for_check(&parity_file) {
+                Ok(chunk) => {
+                    // Verify Parity Hash
+                    if let Some(expected) = segment_info.parity.get(parity_idx)
+                        && let Ok(actual) = blake3_hash_bytes(&chunk)
+                        && actual != *expected
+                    {
+                        found.missing_parity.push(format!(
+                            "segment_{}_parity_{}.dat (CORRUPT)",
+                            idx, parity_idx
+                        ));
                     }
-                })
-                .collect();
-
-            let segment_count = existing_segments.len().min(data_shards);
-
-            // Check which segments are missing
-            let mut missing_in_block = 0;
-            for seg_idx in 0..segment_count {
-                let seg_path = segments_dir.join(format!("segment_{}.dat", seg_idx));
-                if !seg_path.exists() {
-                    missing_data.push(format!("{}/segment_{}.dat", block_name, seg_idx));
-                    missing_in_block += 1;
                 }
-            }
-
-            // Check parity files
-            let mut parity_count = 0;
-            for parity_idx in 0..parity_shards {
-                let parity_path = parity_dir.join(format!("block_parity_{}.dat", parity_idx));
-                if parity_path.exists() {
-                    parity_count += 1;
-                } else {
-                    missing_parity.push(format!("{}/block_parity_{}.dat", block_name, parity_idx));
+                Err(_) => {
+                    found
+                        .missing_parity
+                        .push(format!("segment_{}_parity_{}.dat", idx, parity_idx));
                 }
             }
+        }
+        Ok(healthy)
+    }
 
-            // Classify block health
-            if missing_in_block == 0 && parity_count == parity_shards {
-                healthy_blocks += 1;
-            } else if missing_in_block <= parity_shards && parity_count == parity_shards {
-                recoverable_blocks += 1;
-            } else {
-                unrecoverable_blocks += 1;
-            }
+    /// Health check for Tier 3 (blocked) files using per-block RS encoding, RS(30,3)
+    /// for a full block by default and fewer parity shards for a partial one.
+    ///
+    /// Scans all blocks and hashes every segment and block-level parity file
+    /// against the manifest; a corrupt segment counts as missing. Each block can
+    /// tolerate as many missing segments as it has parity shards, as recorded in
+    /// the manifest.
+    ///
+    /// # Status Logic
+    /// - **Healthy**: All blocks have all segments + parity
+    /// - **Recoverable**: Some blocks have no more missing segments than parity shards
+    /// - **Degraded**: No missing segments but some parity missing
+    /// - **Unrecoverable**: Any block has more missing segments than parity shards
+    fn health_check_blocked(
+        &self,
+        file_obj: &File,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        // the manifest says which shards each block should have, a lost file or
+        // a whole lost block directory counts as missing
+        let blocks: BTreeMap<_, _> = file_obj.manifest.merkle_tree.blocks.iter().collect();
+        if blocks.is_empty() {
+            return Err(format!("manifest for {} has no block hashes", file_obj.file_name).into());
         }
 
-        // Determine overall status
-        let (status, recoverable) = if healthy_blocks == total_blocks {
-            (HealthStatus::Healthy, true)
-        } else if unrecoverable_blocks == 0 && recoverable_blocks > 0 {
-            (HealthStatus::Recoverable, true)
-        } else if unrecoverable_blocks == 0 && !missing_parity.is_empty() {
-            (HealthStatus::Degraded, true)
-        } else {
-            (HealthStatus::Unrecoverable, false)
+        let mut found = Shards::default();
+        let mut statuses = Vec::with_capacity(blocks.len());
+        for (block_id, block) in &blocks {
+            statuses.push(self.check_block(file_obj, **block_id, block, None, &mut found)?);
+        }
+        Ok(blocked_report(blocks.len(), statuses, found, String::new()))
+    }
+
+    /// Hashes the segments and parity of one tier 3 block against the
+    /// manifest, adding whatever is missing or corrupt to `found`, and
+    /// classifies the block as healthy, recoverable or unrecoverable. With
+    /// `only_segment` just that segment of the block is read, with all of its
+    /// parity.
+    fn check_block(
+        &self,
+        file_obj: &File,
+        block_id: usize,
+        block: &BlockHashes,
+        only_segment: Option<usize>,
+        found: &mut Shards,
+    ) -> Result<HealthStatus, Box<dyn std::error::Error>> {
+        let file_folder_path = Path::new(&file_obj.file_data.path)
+            .parent()
+            .ok_or("No parent directory found")?;
+        let block_dir = file_folder_path.join(format!("blocks/block_{}", block_id));
+        let (data_shards, parity_shards) = block.geometry();
+
+        // a segment that is gone or doesn't match its hash needs recovering
+        let mut missing_in_block = 0;
+        let segments = match only_segment {
+            Some(seg_idx) => seg_idx..seg_idx + 1,
+            None => 0..data_shards,
         };
+        let segments: Vec<usize> = segments.collect();
+        let seg_paths: Vec<PathBuf> = segments
+            .iter()
+            .map(|seg_idx| block_dir.join(format!("segments/segment_{}.dat", seg_idx)))
+            .collect();
+        self.read_each_for_check(&seg_paths, |index, read| {
+            let seg_idx = segments[index];
+            let Ok(segment_data) = read else {
+                found
+                    .missing_data
+                    .push(format!("block_{}/segment_{}.dat", block_id, seg_idx));
+                missing_in_block += 1;
+                return;
+            };
+            if let Some(expected) = block.segments.get(seg_idx)
+                && let Ok(actual) = blake3_hash_bytes(&segment_data)
+                && actual != *expected
+            {
+                found
+                    .corrupt_segments
+                    .push(format!("block_{}/segment_{}.dat", block_id, seg_idx));
+                missing_in_block += 1;
+            }
+        });
 
-        let details = format!(
-            "{}/{} blocks healthy, {} recoverable, {} unrecoverable",
-            healthy_blocks, total_blocks, recoverable_blocks, unrecoverable_blocks
-        );
+        // Check parity files, local parity after the global shards
+        let parity_paths = (0..parity_shards + block.local_parity.len())
+            .map(|parity_idx| self.get_parity_path_t3(file_obj, block_id, parity_idx))
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        let mut parity_count = 0;
+        // lost local parity costs no redundancy, but repair should rewrite it
+        let mut local_lost = 0;
+        self.read_each_for_check(&parity_paths, |parity_idx, read| {
+            let expected = match parity_idx < parity_shards {
+                true => block.parity.get(parity_idx),
+                false => block.local_parity.get(parity_idx - parity_shards),
+            };
+            let state = match read {
+                Ok(chunk)
+                    if expected.is_none_or(|expected| {
+                        blake3_hash_bytes(&chunk).is_ok_and(|h| h == *expected)
+                    }) =>
+                {
+                    if parity_idx < parity_shards {
+                        parity_count += 1;
+                    }
+                    return;
+                }
+                Ok(_) => " (CORRUPT)",
+                Err(_) => "",
+            };
+            found.missing_parity.push(format!(
+                "block_{}/block_parity_{}.dat{}",
+                block_id, parity_idx, state
+            ));
+            if parity_idx >= parity_shards {
+                local_lost += 1;
+            }
+        });
 
-        Ok(HealthReport {
-            status,
-            missing_data,
-            missing_parity,
-            corrupt_segments,
-            recoverable,
-            details,
-        })
+        // Classify block health
+        Ok(
+            match classify_block(missing_in_block, parity_count, parity_shards) {
+                HealthStatus::Healthy if local_lost > 0 => HealthStatus::Recoverable,
+                status => status,
+            },
+        )
     }
 
     /// Automatically repairs a file by recovering corrupted or missing data.
@@ -458,6 +671,12 @@ impl FileStore {
     /// - Required parity files are missing
     /// - File I/O fails during recovery
     ///
+    /// Every attempt that needed repair, successful or not, is appended to the
+    /// archive's `audit.log`.
+    ///
+    /// Repairs through one store run one at a time. A file repaired while
+    /// this call waited is found healthy and left alone.
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -468,67 +687,60 @@ impl FileStore {
     /// store.repair(&file).expect("Repair failed");
     /// ```
     pub fn repair(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
+        let _repairing = self.repairing.lock();
         let health = self.health_check(file_obj)?;
 
         if !health.recoverable {
-            return Err(format!("File is unrecoverable: {}", health.details).into());
+            let reason = format!("File is unrecoverable: {}", health.details);
+            self.audit(
+                &AuditEntry::new(AuditOp::Repair, &file_obj.file_name)
+                    .hash(&file_obj.file_data.hash)
+                    .failed(&reason),
+            )?;
+            return Err(reason.into());
         }
 
         if health.status == HealthStatus::Healthy {
             return Ok(()); // Nothing to repair
         }
 
-        match file_obj.manifest.tier {
+        let result = match file_obj.manifest.tier {
             1 => self.repair_tiny(file_obj),
             2 => self.repair_segment(file_obj),
             3 => self.repair_blocked(file_obj),
             _ => Err("unknown tier".into()),
+        };
+
+        let mut entry = AuditEntry::new(AuditOp::Repair, &file_obj.file_name)
+            .hash(&file_obj.file_data.hash)
+            .details(health.details);
+        if let Err(e) = &result {
+            entry = entry.failed(e);
         }
+        self.audit(&entry)?;
+
+        result
     }
 
     /// Repairs Tier 1 (tiny) files by reconstructing data.dat from parity files.
     ///
-    /// Uses Reed-Solomon decoder with RS(1,3) configuration to recover the original
-    /// data file from any available parity shards. Supports recovery even when data.dat
-    /// is completely missing.
-    ///
-    /// # Note
-    /// Recovered data may include padding (rounded up to multiple of 64 bytes).
+    /// Uses the RS(1,3) decoder in [`recovery`](super::recovery) to recover the original data file
+    /// from any parity shard that decodes to the manifest hash. Supports recovery even
+    /// when data.dat is completely missing.
     pub fn repair_tiny(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
-        let file_dir = Path::new(&file_obj.file_data.path)
-            .parent()
-            .ok_or("No parent directory found")?;
-
-        let data_path = file_dir.join("data.dat");
+        let data_path = self.get_data_path(file_obj)?;
 
         // Check if data exists and is valid
         if data_path.exists() {
-            let data = fs::read(&data_path)?;
+            let data = self.read_for_check(&data_path)?;
             if blake3_hash_bytes(&data)? == file_obj.file_data.hash {
                 return Ok(());
             }
         }
 
-        // Data is missing or corrupt, use Reed-Solomon decoder
-        let shard_size = file_obj.manifest.segment_size as usize;
-        let mut decoder = ReedSolomonDecoder::new(1, 3, shard_size)?;
-
-        // Add all available parity shards
-        for i in 0..3 {
-            let parity_path = file_dir.join(format!("parity_{}.dat", i));
-            if let Ok(parity) = fs::read(&parity_path) {
-                decoder.add_recovery_shard(i, parity)?;
-            }
-        }
-
-        // Decode to recover original data
-        let result = decoder.decode()?;
-        let recovered = result
-            .restored_original(0)
-            .ok_or("Failed to restore original data")?;
-
-        // Write recovered data (may have padding, but that's okay)
-        fs::write(&data_path, recovered)?;
+        // Data is missing or corrupt, decode it from parity
+        let recovered = self.recover_shard(file_obj, ShardKind::Tiny)?;
+        self.write_archive_file(&data_path, &recovered)?;
         println!("Recovered data.dat using Reed-Solomon decoder");
 
         Ok(())
@@ -536,93 +748,26 @@ impl FileStore {
 
     /// Repairs Tier 2 (segmented) files by reconstructing missing or corrupt segments.
     ///
-    /// Scans all segments, identifies those that are missing or fail hash verification,
-    /// then uses per-segment RS(1,3) decoding to reconstruct them from parity files.
+    /// Checks every segment against its manifest hash, then uses per-segment RS(1,3)
+    /// decoding from [`recovery`](super::recovery) to reconstruct the ones that fail from parity files.
     /// Each segment is independently recoverable.
     pub fn repair_segment(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
-        let mut corrupt_segments: Vec<(usize, PathBuf)> = Vec::new();
-
-        let file_folder_path = Path::new(&file_obj.file_data.path)
-            .parent()
-            .ok_or("No parent directory found")?;
-
-        let segments_path = file_folder_path.join("segments");
-        let parity_path = file_folder_path.join("parity");
-
-        let leafs = &file_obj.manifest.merkle_tree.leaves;
-        let parity_shards = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
-
-        for idx in 0..leafs.len() {
-            let current_segment = segments_path.join(format!("segment_{}.dat", idx));
-            let segment_data = match fs::read(&current_segment) {
-                Ok(data) => data,
-                Err(_) => {
-                    corrupt_segments.push((idx, current_segment));
-                    continue;
-                }
-            };
-
-            let mut parity_chunks = Vec::with_capacity(parity_shards);
-            let mut parity_missing = false;
-            for parity_idx in 0..parity_shards {
-                let parity_file =
-                    parity_path.join(format!("segment_{}_parity_{}.dat", idx, parity_idx));
-                match fs::read(&parity_file) {
-                    Ok(chunk) => parity_chunks.push(chunk),
-                    Err(_) => {
-                        parity_missing = true;
-                        break;
-                    }
-                }
-            }
-
-            if parity_missing {
-                corrupt_segments.push((idx, current_segment));
+        let segments = &file_obj.manifest.merkle_tree.segments;
+        for idx in 0..segments.len() {
+            let kind = ShardKind::Segment(idx);
+            let (hash, _) = expected_shard(&file_obj.manifest, kind)?;
+            let segment_path = self.get_segment_path(file_obj, idx)?;
+            let intact = self
+                .read_for_check(&segment_path)
+                .ok()
+                .is_some_and(|data| blake3_hash_bytes(&data).is_ok_and(|h| h == hash));
+            if intact {
                 continue;
             }
 
-            let computed_hash = self.hash_segment_with_parity(&segment_data, &parity_chunks)?;
-            let leaf_hash = leafs
-                .get(&(idx as i32))
-                .ok_or("manifest leaf missing for segment index")?;
-
-            if computed_hash != *leaf_hash {
-                corrupt_segments.push((idx, current_segment));
-            }
-        }
-
-        if corrupt_segments.is_empty() {
-            return Ok(());
-        }
-
-        for (segment_idx, corrupt_path) in corrupt_segments {
-            let parity_chunks: Vec<Vec<u8>> = (0..parity_shards)
-                .map(|parity_idx| {
-                    fs::read(
-                        parity_path
-                            .join(format!("segment_{}_parity_{}.dat", segment_idx, parity_idx)),
-                    )
-                })
-                .collect::<Result<_, _>>()?;
-
-            let shard_len = parity_chunks
-                .first()
-                .map(|chunk| chunk.len())
-                .ok_or_else(|| format!("Parity chunks are empty for segment {}", segment_idx))?;
-
-            let mut recovery_decoder = ReedSolomonDecoder::new(1, parity_shards, shard_len)?;
-
-            for (parity_idx, chunk) in parity_chunks.into_iter().enumerate() {
-                recovery_decoder.add_recovery_shard(parity_idx, chunk)?;
-            }
-
-            let recovered_segment = recovery_decoder
-                .decode()?
-                .restored_original(0)
-                .ok_or("unable to restore original segment")?
-                .to_vec();
-
-            fs::write(corrupt_path, &recovered_segment)?;
+            let recovered = self.recover_shard(file_obj, kind)?;
+            self.write_archive_file(&segment_path, &recovered)?;
+            println!("Recovered segment {}", idx);
         }
 
         Ok(())
@@ -630,137 +775,237 @@ impl FileStore {
 
     /// Repairs corrupt or missing segments in Tier 3 (blocked) archives.
     ///
-    /// Tier 3 uses RS(30,3): 30 data segments + 3 parity shards per block.
-    /// Each block lives in `blocks/block_N/` with:
+    /// Tier 3 encodes each block on its own: up to 30 data segments plus parity
+    /// in proportion, 3 shards for a full block by default. Each block lives in
+    /// `blocks/block_N/` with:
     ///   - `segments/segment_X.dat` (X = 0..segment_count, max 30)
-    ///   - `parity/block_parity_Y.dat` (Y = 0..2)
+    ///   - `parity/block_parity_Y.dat` (Y = 0..parity_count)
     ///
     /// Recovery strategy:
-    /// 1. For each block, identify missing or corrupt segments
-    /// 2. If <= 3 segments are missing, use RS decoder to reconstruct
+    /// 1. For each block, identify segments that are missing or fail their manifest hash
+    /// 2. If no more segments are lost than the block has parity shards, decode each
+    ///    one with the block's geometry from the manifest through [`recovery`](super::recovery)
     /// 3. Write recovered segments back to disk
     pub fn repair_blocked(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
-        let file_folder_path = Path::new(&file_obj.file_data.path)
-            .parent()
-            .ok_or("No parent directory found")?;
-
-        let blocks_path = file_folder_path.join("blocks");
+        let blocks: BTreeMap<_, _> = file_obj.manifest.merkle_tree.blocks.iter().collect();
 
-        // Determine how many blocks exist
-        let block_dirs: Vec<_> = fs::read_dir(&blocks_path)?
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().is_dir())
-            .collect();
-
-        let segment_size = file_obj.manifest.segment_size as usize;
-        let parity_shards = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
-        let data_shards = file_obj.manifest.erasure_coding.data_shards.max(0) as usize;
-
-        for block_entry in block_dirs {
-            let block_dir = block_entry.path();
-            let segments_dir = block_dir.join("segments");
-            let parity_dir = block_dir.join("parity");
-
-            // Count how many segment files actually exist in this block
-            let existing_segments: Vec<_> = fs::read_dir(&segments_dir)?
-                .filter_map(|e| e.ok())
-                .filter(|e| {
-                    e.path()
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .map(|s| s.starts_with("segment_") && s.ends_with(".dat"))
-                        .unwrap_or_else(|| false)
-                })
-                .collect();
-
-            let segment_count = existing_segments.len().min(data_shards);
+        for (&block_id, block) in blocks {
+            let (segment_count, parity_shards) = block.geometry();
 
             // Identify missing or corrupt segments
-            let mut missing_indices: Vec<usize> = Vec::new();
-            let mut valid_segments: Vec<(usize, Vec<u8>)> = Vec::new();
-
-            for seg_idx in 0..segment_count {
-                let seg_path = segments_dir.join(format!("segment_{}.dat", seg_idx));
-                match fs::read(&seg_path) {
-                    Ok(data) => {
-                        // TODO: optionally verify hash against stored merkle leaf
-                        valid_segments.push((seg_idx, data));
-                    }
-                    Err(_) => {
-                        missing_indices.push(seg_idx);
-                    }
+            let mut lost: Vec<usize> = Vec::new();
+            for (seg_idx, hash) in block.segments.iter().enumerate().take(segment_count) {
+                let seg_path = self.get_block_segment_path(file_obj, block_id, seg_idx)?;
+                let intact = self
+                    .read_for_check(&seg_path)
+                    .ok()
+                    .is_some_and(|data| blake3_hash_bytes(&data).is_ok_and(|h| h == *hash));
+                if !intact {
+                    lost.push(seg_idx);
                 }
             }
 
-            // Also check for segments that exist but might be corrupt
-            // For now we trust that if the file exists it's valid
-
-            if missing_indices.is_empty() {
-                // Block is healthy
+            if lost.is_empty() {
+                self.repair_local_parity(file_obj, block_id, block)?;
                 continue;
             }
 
-            if missing_indices.len() > parity_shards {
+            if lost.len() > parity_shards {
                 return Err(format!(
-                    "Block {:?} has {} missing segments but only {} parity shards - unrecoverable",
-                    block_dir,
-                    missing_indices.len(),
+                    "Block {} has {} missing segments but only {} parity shards - unrecoverable",
+                    block_id,
+                    lost.len(),
                     parity_shards
                 )
                 .into());
             }
 
-            // Read parity shards
-            let mut parity_data: Vec<Vec<u8>> = Vec::with_capacity(parity_shards);
-            for parity_idx in 0..parity_shards {
-                let parity_path = parity_dir.join(format!("block_parity_{}.dat", parity_idx));
-                let data = fs::read(&parity_path).map_err(|e| {
-                    format!(
-                        "Failed to read parity {} in {:?}: {}",
-                        parity_idx, block_dir, e
-                    )
-                })?;
-                parity_data.push(data);
+            for seg_idx in lost {
+                let recovered =
+                    self.recover_shard(file_obj, ShardKind::Block(block_id, seg_idx))?;
+                let seg_path = self.get_block_segment_path(file_obj, block_id, seg_idx)?;
+                self.write_archive_file(&seg_path, &recovered)?;
+                println!("Recovered segment {} in block {}", seg_idx, block_id);
             }
+            self.repair_local_parity(file_obj, block_id, block)?;
+        }
 
-            // Determine shard size (all shards in a block are same size)
-            let shard_size = parity_data
-                .first()
-                .map(|p| p.len())
-                .unwrap_or_else(|| segment_size);
-
-            // Create decoder
-            let mut decoder = ReedSolomonDecoder::new(segment_count, parity_shards, shard_size)?;
-
-            // Add all valid original shards
-            for (idx, data) in &valid_segments {
-                decoder.add_original_shard(*idx, data)?;
-            }
+        Ok(())
+    }
 
-            // Add all parity shards
-            for (parity_idx, data) in parity_data.iter().enumerate() {
-                decoder.add_recovery_shard(parity_idx, data)?;
+    /// Rewrites the local parity shards of a block whose segments are intact
+    /// that are missing or fail their hash.
+    fn repair_local_parity(
+        &self,
+        file_obj: &File,
+        block_id: usize,
+        block: &BlockHashes,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (segment_count, parity_shards) = block.geometry();
+        let Some(group_size) = block.local_group.filter(|&size| size > 0) else {
+            return Ok(());
+        };
+        for (group, expected) in block.local_parity.iter().enumerate() {
+            let parity_path = self.get_parity_path_t3(file_obj, block_id, parity_shards + group)?;
+            let intact = self
+                .read_for_check(&parity_path)
+                .ok()
+                .is_some_and(|data| blake3_hash_bytes(&data).is_ok_and(|h| h == *expected));
+            if intact {
+                continue;
             }
-
-            // Decode and recover
-            let result = decoder.decode()?;
-
-            // Write recovered segments back to disk
-            for missing_idx in missing_indices {
-                let recovered = result
-                    .restored_original(missing_idx)
-                    .ok_or_else(|| format!("Failed to restore segment {}", missing_idx))?;
-
-                let seg_path = segments_dir.join(format!("segment_{}.dat", missing_idx));
-                fs::write(&seg_path, recovered)?;
-                println!(
-                    "Recovered segment {} in block {:?}",
-                    missing_idx,
-                    block_dir.file_name().unwrap_or_default()
+            let start = group * group_size;
+            let mut segments = Vec::new();
+            for seg_idx in start..(start + group_size).min(segment_count) {
+                segments.push(
+                    self.read_for_check(
+                        &self.get_block_segment_path(file_obj, block_id, seg_idx)?,
+                    )?,
                 );
             }
+            let refs: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+            let shard_size = block
+                .shard_size
+                .map(|size| size as usize)
+                .unwrap_or_else(|| {
+                    refs.iter().map(|s| s.len()).max().unwrap_or(0).div_ceil(64) * 64
+                });
+            let parity = erasure::local_parity(&refs, shard_size);
+            if blake3_hash_bytes(&parity)? != *expected {
+                return Err(format!(
+                    "local parity {} of block {} doesn't match the manifest once rebuilt",
+                    group, block_id
+                )
+                .into());
+            }
+            self.write_archive_file(&parity_path, &parity)?;
+            println!("Recovered local parity {} in block {}", group, block_id);
         }
-
         Ok(())
     }
 }
+
+// Status rules shared by the local checks above and the remote checker in
+// `remote_health`, so both classify the same damage the same way.
+
+/// Tier 1: `data_valid` is whether data.dat matches the manifest hash.
+/// Shards a check found missing or corrupt, in the lists a [`HealthReport`]
+/// keeps them in.
+#[derive(Default)]
+struct Shards {
+    missing_data: Vec<String>,
+    missing_parity: Vec<String>,
+    corrupt_segments: Vec<String>,
+}
+
+impl Shards {
+    fn into_report(self, status: HealthStatus, recoverable: bool, details: String) -> HealthReport {
+        HealthReport {
+            status,
+            missing_data: self.missing_data,
+            missing_parity: self.missing_parity,
+            corrupt_segments: self.corrupt_segments,
+            recoverable,
+            details,
+        }
+    }
+}
+
+/// Tier 3 report from the status of each block checked. `label`, when not
+/// empty, says which part of the file was checked.
+fn blocked_report(
+    total_blocks: usize,
+    statuses: impl IntoIterator<Item = HealthStatus>,
+    found: Shards,
+    label: String,
+) -> HealthReport {
+    let (mut healthy_blocks, mut recoverable_blocks, mut unrecoverable_blocks) = (0, 0, 0);
+    for status in statuses {
+        match status {
+            HealthStatus::Healthy => healthy_blocks += 1,
+            HealthStatus::Recoverable => recoverable_blocks += 1,
+            _ => unrecoverable_blocks += 1,
+        }
+    }
+
+    // Determine overall status
+    let (status, recoverable) = classify_blocked(
+        total_blocks,
+        healthy_blocks,
+        recoverable_blocks,
+        unrecoverable_blocks,
+        found.missing_parity.is_empty(),
+    );
+
+    let mut details = format!(
+        "{}/{} blocks healthy, {} recoverable, {} unrecoverable",
+        healthy_blocks, total_blocks, recoverable_blocks, unrecoverable_blocks
+    );
+    if !label.is_empty() {
+        details = format!("{}: {}", label, details);
+    }
+    found.into_report(status, recoverable, details)
+}
+
+pub(crate) fn classify_tiny(data_valid: bool, parity_count: usize) -> (HealthStatus, bool) {
+    if data_valid && parity_count == 3 {
+        (HealthStatus::Healthy, true)
+    } else if data_valid && parity_count > 0 {
+        (HealthStatus::Degraded, true)
+    } else if !data_valid && parity_count > 0 {
+        (HealthStatus::Recoverable, true)
+    } else {
+        (HealthStatus::Unrecoverable, false)
+    }
+}
+
+/// Tier 2: `lost_segments` counts missing plus corrupt data segments.
+pub(crate) fn classify_segmented(
+    lost_segments: usize,
+    parity_shards: usize,
+    parity_intact: bool,
+) -> (HealthStatus, bool) {
+    if lost_segments == 0 {
+        (HealthStatus::Healthy, true)
+    } else if lost_segments <= parity_shards {
+        (HealthStatus::Recoverable, true)
+    } else if parity_intact {
+        (HealthStatus::Degraded, true)
+    } else {
+        (HealthStatus::Unrecoverable, false)
+    }
+}
+
+/// Tier 3, one block: healthy, recoverable or unrecoverable.
+pub(crate) fn classify_block(
+    missing_in_block: usize,
+    parity_count: usize,
+    parity_shards: usize,
+) -> HealthStatus {
+    if missing_in_block == 0 && parity_count == parity_shards {
+        HealthStatus::Healthy
+    } else if missing_in_block <= parity_shards && parity_count == parity_shards {
+        HealthStatus::Recoverable
+    } else {
+        HealthStatus::Unrecoverable
+    }
+}
+
+/// Tier 3, whole file from the per-block counts.
+pub(crate) fn classify_blocked(
+    total_blocks: usize,
+    healthy_blocks: usize,
+    recoverable_blocks: usize,
+    unrecoverable_blocks: usize,
+    parity_intact: bool,
+) -> (HealthStatus, bool) {
+    if healthy_blocks == total_blocks {
+        (HealthStatus::Healthy, true)
+    } else if unrecoverable_blocks == 0 && recoverable_blocks > 0 {
+        (HealthStatus::Recoverable, true)
+    } else if unrecoverable_blocks == 0 && !parity_intact {
+        (HealthStatus::Degraded, true)
+    } else {
+        (HealthStatus::Unrecoverable, false)
+    }
+}
@@ -44,4 +44,59 @@ mod tests {
         let debug_str = format!("{:?}", status);
         assert!(debug_str.contains("Degraded"));
     }
+
+    #[test]
+    fn test_health_reports_export_as_junit_and_sarif() {
+        use super::super::export::{junit, sarif};
+        use super::super::models::HealthReport;
+
+        let report = |status, data: &[&str], details: &str| HealthReport {
+            status,
+            missing_data: data.iter().map(|s| s.to_string()).collect(),
+            missing_parity: vec![],
+            corrupt_segments: vec![],
+            recoverable: status != HealthStatus::Unrecoverable,
+            details: details.to_string(),
+        };
+        let batch = BatchHealthReport {
+            total_files: 3,
+            healthy: 1,
+            degraded: 0,
+            recoverable: 1,
+            unrecoverable: 1,
+            reports: vec![
+                ("ok.bin".into(), report(HealthStatus::Healthy, &[], "fine")),
+                (
+                    "a&b.bin".into(),
+                    report(
+                        HealthStatus::Recoverable,
+                        &["segment_2"],
+                        "1 missing <data>",
+                    ),
+                ),
+                (
+                    "gone.bin".into(),
+                    report(HealthStatus::Unrecoverable, &["data.dat"], "too much lost"),
+                ),
+            ],
+        };
+
+        let xml = junit(&batch, "archive");
+        assert!(xml.contains("tests=\"3\" failures=\"1\" errors=\"1\""));
+        assert!(xml.contains("name=\"ok.bin\"/>"));
+        assert!(xml.contains("name=\"a&amp;b.bin\""));
+        assert!(xml.contains("message=\"1 missing &lt;data&gt;\">missing segment_2</failure>"));
+        assert!(xml.contains("<error type=\"unrecoverable\""));
+
+        let log = sarif(&batch, "archive");
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["level"], "warning");
+        assert_eq!(results[0]["ruleId"], "recoverable");
+        assert_eq!(results[1]["level"], "error");
+        assert_eq!(
+            results[1]["locations"][0]["logicalLocations"][0]["fullyQualifiedName"],
+            "archive/gone.bin"
+        );
+    }
 }
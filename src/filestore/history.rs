@@ -0,0 +1,165 @@
+//! Health check outcomes over time.
+//!
+//! Every batch health check, scrub and API health check appends one JSON line
+//! per file to `.health-history.jsonl` in the archive, with the time, status and
+//! how many shards were missing or corrupt. [`FileStore::health_history`] reads
+//! them back for one file and [`HistorySummary`] tells a one-off corruption
+//! event from a file that keeps getting worse.
+//!
+//! Read-only stores record nothing.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use super::FileStore;
+use super::models::{HealthReport, HealthStatus};
+
+pub const HISTORY_FILE: &str = ".health-history.jsonl";
+
+/// One file's outcome in one health check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthRecord {
+    pub timestamp: DateTime<Utc>,
+    pub file_name: String,
+    pub status: HealthStatus,
+    pub missing_data: usize,
+    pub missing_parity: usize,
+    pub corrupt: usize,
+}
+
+impl HealthRecord {
+    pub fn new(file_name: &str, report: &HealthReport) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            file_name: file_name.to_string(),
+            status: report.status,
+            missing_data: report.missing_data.len(),
+            missing_parity: report.missing_parity.len(),
+            corrupt: report.corrupt_segments.len(),
+        }
+    }
+
+    /// Shards missing or corrupt.
+    pub fn damaged(&self) -> usize {
+        self.missing_data + self.missing_parity + self.corrupt
+    }
+}
+
+/// What a file's history adds up to, see [`HistorySummary::of`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HistorySummary {
+    pub checks: usize,
+    /// Checks that found the file anything but healthy.
+    pub unhealthy: usize,
+    /// Separate runs of unhealthy checks, with a healthy check between them.
+    pub incidents: usize,
+    /// Most shards found damaged in a single check.
+    pub worst_damage: usize,
+    /// The latest incident is still going on and found more damage with
+    /// each check than the one before.
+    pub deteriorating: bool,
+}
+
+impl HistorySummary {
+    /// Summarizes `records`, oldest first.
+    pub fn of(records: &[HealthRecord]) -> Self {
+        let mut summary = HistorySummary {
+            checks: records.len(),
+            ..Default::default()
+        };
+        let mut run: Vec<usize> = Vec::new();
+        for record in records {
+            if record.status == HealthStatus::Healthy {
+                run.clear();
+                continue;
+            }
+            if run.is_empty() {
+                summary.incidents += 1;
+            }
+            summary.unhealthy += 1;
+            summary.worst_damage = summary.worst_damage.max(record.damaged());
+            run.push(record.damaged());
+        }
+        summary.deteriorating = run.len() > 1 && run.windows(2).all(|pair| pair[1] > pair[0]);
+        summary
+    }
+}
+
+impl FileStore {
+    fn history_path(&self) -> PathBuf {
+        self.store_path.join(HISTORY_FILE)
+    }
+
+    /// Appends an outcome for each `(name, report)` to the health history.
+    /// Does nothing on a read-only store.
+    pub fn record_health<'a, I>(&self, reports: I) -> Result<(), Box<dyn std::error::Error>>
+    where
+        I: IntoIterator<Item = (&'a str, &'a HealthReport)>,
+    {
+        if self.is_read_only() {
+            return Ok(());
+        }
+        let mut lines = Vec::new();
+        for (name, report) in reports {
+            serde_json::to_writer(&mut lines, &HealthRecord::new(name, report))?;
+            lines.push(b'\n');
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+        // one write on an O_APPEND handle, like the audit log, so concurrent
+        // checks don't interleave lines
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.history_path())?
+            .write_all(&lines)?;
+        Ok(())
+    }
+
+    /// Same as [`FileStore::record_health`], logging a failure instead of
+    /// returning it, for callers whose check shouldn't fail over it.
+    pub(crate) fn try_record_health<'a, I>(&self, reports: I)
+    where
+        I: IntoIterator<Item = (&'a str, &'a HealthReport)>,
+    {
+        if let Err(e) = self.record_health(reports) {
+            tracing::warn!("HISTORY | couldn't record health outcomes: {}", e);
+        }
+    }
+
+    /// Recorded outcomes for `file_name`, oldest first. `limit` keeps only
+    /// the most recent ones.
+    pub fn health_history(
+        &self,
+        file_name: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<HealthRecord>, Box<dyn std::error::Error>> {
+        let file = match fs::File::open(self.history_path()) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<HealthRecord>(&line) {
+                Ok(record) if record.file_name == file_name => records.push(record),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("HISTORY | skipping malformed line: {}", e),
+            }
+        }
+        if let Some(limit) = limit
+            && records.len() > limit
+        {
+            records.drain(..records.len() - limit);
+        }
+        Ok(records)
+    }
+}
@@ -0,0 +1,104 @@
+//! Lookups by name and content hash.
+//!
+//! Every scan of the archive builds a [`FileIndex`] next to the file list, so
+//! [`FileStore::find`], [`FileStore::find_all`] and [`FileStore::find_by_hash`]
+//! answer from a hash map instead of walking every manifest. The index lives
+//! and goes stale with the cached file list.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use super::FileStore;
+use super::versions::version_order;
+use crate::filestore::models::File;
+
+/// The file list of one scan, with the positions of the entries under each
+/// name and of each content hash.
+pub(crate) struct FileIndex {
+    files: Vec<File>,
+    /// Entries of a name, oldest version first.
+    by_name: HashMap<String, Vec<usize>>,
+    /// Entries holding a content, originals before aliases.
+    by_hash: HashMap<String, Vec<usize>>,
+}
+
+impl FileIndex {
+    pub(crate) fn new(files: Vec<File>) -> Self {
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, file) in files.iter().enumerate() {
+            by_name.entry(file.file_name.clone()).or_default().push(i);
+            by_hash
+                .entry(file.file_data.hash.clone())
+                .or_default()
+                .push(i);
+        }
+        for entries in by_name.values_mut() {
+            entries.sort_by(|&a, &b| version_order(&files[a], &files[b]));
+        }
+        for entries in by_hash.values_mut() {
+            entries.sort_by_key(|&i| (files[i].alias_of.is_some(), files[i].committed));
+        }
+        Self {
+            files,
+            by_name,
+            by_hash,
+        }
+    }
+
+    /// Entries under `name`, oldest first.
+    pub(crate) fn named(&self, name: &str) -> impl Iterator<Item = &File> {
+        self.lookup(&self.by_name, name)
+    }
+
+    /// Entries holding the content `hash`, originals first.
+    pub(crate) fn hashed(&self, hash: &str) -> impl Iterator<Item = &File> {
+        self.lookup(&self.by_hash, hash)
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        map: &'a HashMap<String, Vec<usize>>,
+        key: &str,
+    ) -> impl Iterator<Item = &'a File> {
+        map.get(key).into_iter().flatten().map(|&i| &self.files[i])
+    }
+}
+
+impl Deref for FileIndex {
+    type Target = Vec<File>;
+
+    fn deref(&self) -> &Vec<File> {
+        &self.files
+    }
+}
+
+impl FileStore {
+    /// Every entry under `name`, each version and alias, oldest first. Empty
+    /// if the name isn't archived. [`FileStore::find`] returns the last one.
+    pub fn find_all(&self, name: &str) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        Ok(self.files()?.named(name).cloned().collect())
+    }
+
+    /// The entry holding the content with the SHA-256 `hash`. When several
+    /// names share it, the original the others are aliases of is returned.
+    ///
+    /// ```no_run
+    /// # use blockframe::filestore::FileStore;
+    /// # use std::path::Path;
+    /// # let store = FileStore::new(Path::new("archive_directory"))?;
+    /// # let hash = "0000";
+    /// let file = store.find_by_hash(hash)?;
+    /// println!("{} has that content", file.file_name);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn find_by_hash(&self, hash: &str) -> Result<File, Box<dyn std::error::Error>> {
+        let hash = hash.to_ascii_lowercase();
+        self.files()?.hashed(&hash).next().cloned().ok_or_else(|| {
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No file with hash {} is archived", hash),
+            )) as Box<dyn std::error::Error>
+        })
+    }
+}
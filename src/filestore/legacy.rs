@@ -0,0 +1,134 @@
+//! Migration for archives written before tiers existed.
+//!
+//! The first on-disk layout split every segment into six chunks:
+//! `segments/segment_N/chunks/chunk_0..5.dat` with `segments/segment_N/parity/parity_0..2.dat`,
+//! and its manifest had no `tier` field. Nothing else in the crate reads that
+//! layout any more. `get_all` skips these entries, and `migrate_legacy` rebuilds
+//! the original file from the chunks and commits it again in the current format.
+
+use serde_json::Value;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::FileStore;
+use crate::chunker::{ChunkedFile, Chunker};
+
+/// Chunks per segment in the legacy layout.
+const LEGACY_CHUNKS: usize = 6;
+
+/// True when `file_dir` holds an entry in the pre-tier layout.
+pub fn is_legacy(file_dir: &Path) -> bool {
+    if file_dir.join("segments/segment_0/chunks").is_dir() {
+        return true;
+    }
+    fs::read_to_string(file_dir.join("manifest.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+        .is_some_and(|manifest| manifest.get("tier").is_none())
+}
+
+impl FileStore {
+    /// Archive entries still in the legacy layout.
+    pub fn legacy_entries(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self
+            .all_files()?
+            .into_iter()
+            .filter_map(|manifest| manifest.parent().map(Path::to_path_buf))
+            .filter(|dir| is_legacy(dir))
+            .collect())
+    }
+
+    /// Rebuilds a legacy entry from its chunks and commits it with `chunker`,
+    /// whose `archive_dir` must be this store. The legacy directory is only
+    /// removed once the new commit succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Fails without touching the archive if a chunk is missing or the rebuilt
+    /// file doesn't match the size or hash recorded in the legacy manifest.
+    pub fn migrate_legacy(
+        &self,
+        file_dir: &Path,
+        chunker: &Chunker,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        self.ensure_writable("migrate")?;
+        let manifest: Value =
+            serde_json::from_str(&fs::read_to_string(file_dir.join("manifest.json"))?)?;
+        let name = manifest
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("legacy manifest has no name")?
+            .to_string();
+
+        let work = tempfile::Builder::new()
+            .prefix(".migrate-")
+            .tempdir_in(&self.store_path)?;
+        let original = work.path().join(&name);
+        tracing::info!("MIGRATE | rebuilding {} from legacy chunks", name);
+
+        let mut out = BufWriter::new(fs::File::create(&original)?);
+        let mut hasher = blake3::Hasher::new();
+        let mut written = 0u64;
+        for segment in legacy_segments(file_dir)? {
+            for idx in 0..LEGACY_CHUNKS {
+                let chunk = fs::read(segment.join("chunks").join(format!("chunk_{}.dat", idx)))?;
+                hasher.update(&chunk);
+                out.write_all(&chunk)?;
+                written += chunk.len() as u64;
+            }
+        }
+        out.flush()?;
+        drop(out);
+
+        if let Some(size) = manifest.get("size").and_then(Value::as_u64)
+            && size != written
+        {
+            return Err(format!("{} rebuilt to {} bytes, expected {}", name, written, size).into());
+        }
+        if let Some(hash) = manifest.get("original_hash").and_then(Value::as_str)
+            && hash != hasher.finalize().to_string()
+        {
+            return Err(format!("{} does not match its original hash", name).into());
+        }
+
+        let previous = work.path().join("previous");
+        fs::rename(file_dir, &previous)?;
+        let committed = chunker.commit(&original);
+        self.invalidate();
+        let chunked = match committed {
+            Ok(chunked) => chunked,
+            Err(e) => {
+                tracing::error!("MIGRATE | commit of {} failed: {}", name, e);
+                if file_dir.exists() {
+                    fs::remove_dir_all(file_dir)?;
+                }
+                fs::rename(&previous, file_dir)?;
+                return Err(e);
+            }
+        };
+
+        tracing::info!("MIGRATE | {} migrated to {:?}", name, chunked.file_dir);
+        Ok(chunked)
+    }
+}
+
+/// `segments/segment_N` directories sorted by N.
+fn legacy_segments(file_dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut segments: Vec<(usize, PathBuf)> = fs::read_dir(file_dir.join("segments"))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let idx = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("segment_")?
+                .parse()
+                .ok()?;
+            Some((idx, path))
+        })
+        .collect();
+    segments.sort_by_key(|(idx, _)| *idx);
+    Ok(segments.into_iter().map(|(_, path)| path).collect())
+}
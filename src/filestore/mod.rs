@@ -1,14 +1,45 @@
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use parking_lot::{Mutex, RwLock};
+use std::fs;
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
-use crate::filestore::models::File;
-use crate::merkle_tree::MerkleTree;
+use crate::alias;
+use crate::filestore::index::FileIndex;
+use crate::filestore::models::{File, FileData, parse_time};
 use crate::merkle_tree::manifest::ManifestFile;
+use crate::negative_cache::NegativeCache;
+use crate::signing::{ManifestVerifier, read_signature};
 
+pub mod compact;
+pub mod export;
+pub mod global_parity;
 pub mod health;
+pub mod history;
+mod index;
+pub mod legacy;
 pub mod models;
+pub mod original;
+pub mod planner;
+pub mod query;
+pub mod reader;
 pub mod recovery;
+#[cfg(feature = "remote")]
+pub mod remote_download;
+#[cfg(feature = "remote")]
+pub mod remote_health;
+mod restore;
+mod retier;
+pub mod sample;
+pub mod scrub;
+pub mod spool;
+pub mod trash;
+pub mod versions;
+
+pub use reader::{FileReader, Integrity};
+pub use restore::{DataShard, ParitySet, ParityShard};
 
 #[cfg(test)]
 mod health_tests;
@@ -22,8 +53,34 @@ mod tests;
 /// - Finding specific files by name
 /// - Reconstructing original files from erasure-coded shards
 /// - Health checking and repair operations
+///
+/// The parsed file list is cached, so one store can be shared behind an `Arc`
+/// by the server, scrubber and watch folder without rescanning on every call.
 pub struct FileStore {
     pub store_path: PathBuf,
+    /// Checks manifest signatures during health checks and local mounts.
+    pub verifier: Option<ManifestVerifier>,
+    /// Set by [`FileStore::read_only`] or [`FileStore::with_repair_dest`].
+    spool: Option<spool::Spool>,
+    /// Set by [`FileStore::with_direct_io`].
+    direct_io: bool,
+    /// Names [`FileStore::find`] just missed, see [`FileStore::with_negative_ttl`].
+    missing: NegativeCache,
+    cache: RwLock<Option<FileCache>>,
+    /// Bumped by [`FileStore::invalidate`], so a scan it overlapped isn't cached.
+    invalidations: AtomicU64,
+    /// Set once the archive has been scanned, and kept through invalidation.
+    scanned: AtomicBool,
+    /// Held by [`FileStore::repair`], so scrubs sharing the store, like the
+    /// daemon's full and sampled passes, never repair one file at once.
+    repairing: Mutex<()>,
+}
+
+/// The result of the last archive scan.
+struct FileCache {
+    /// Modification time of the archive directory when the scan started.
+    modified: SystemTime,
+    files: Arc<FileIndex>,
 }
 
 impl FileStore {
@@ -50,9 +107,98 @@ impl FileStore {
     pub fn new(store_path: &Path) -> Result<Self, std::io::Error> {
         Ok(FileStore {
             store_path: store_path.to_path_buf(),
+            verifier: None,
+            spool: None,
+            direct_io: false,
+            missing: NegativeCache::new(Duration::ZERO),
+            cache: RwLock::new(None),
+            invalidations: AtomicU64::new(0),
+            scanned: AtomicBool::new(false),
+            repairing: Mutex::new(()),
         })
     }
 
+    /// Verifies manifest signatures with `verifier`, see [`FileStore::verify_manifest`].
+    pub fn with_verifier(mut self, verifier: Option<ManifestVerifier>) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    /// Reads shards past the page cache in health checks, scrubs and repairs,
+    /// so checking the whole archive doesn't evict what other programs have
+    /// cached, see [`crate::direct_io`].
+    pub fn with_direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    /// Has [`FileStore::find`] answer a name it didn't find for `ttl` without
+    /// looking again, so clients probing for `.DS_Store` and the like don't
+    /// each rescan an archive that keeps changing. [`FileStore::invalidate`]
+    /// forgets them. Off by default.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.missing = NegativeCache::new(ttl);
+        self
+    }
+
+    /// Reads a shard for a health check or repair, see
+    /// [`FileStore::with_direct_io`].
+    pub(crate) fn read_for_check(&self, path: &Path) -> Result<Vec<u8>, std::io::Error> {
+        match self.direct_io {
+            true => crate::direct_io::read(path),
+            false => fs::read(path),
+        }
+    }
+
+    /// Reads each of `paths` as [`FileStore::read_for_check`] does and passes
+    /// it to `visit` with its index, in io_uring batches when built with the
+    /// `uring` feature on Linux.
+    pub(crate) fn read_each_for_check(
+        &self,
+        paths: &[PathBuf],
+        mut visit: impl FnMut(usize, Result<Vec<u8>, std::io::Error>),
+    ) {
+        // a ring that fails part way leaves the rest to be read one by one
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        let visited = {
+            let mut visited = vec![false; paths.len()];
+            let batched = crate::uring::read_each(paths, self.direct_io, |index, read| {
+                visited[index] = true;
+                visit(index, read)
+            });
+            match batched {
+                Ok(()) => return,
+                Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {}
+                Err(e) => tracing::warn!("URING | batch read failed ({}), reading one by one", e),
+            }
+            visited
+        };
+        #[cfg(not(all(feature = "uring", target_os = "linux")))]
+        let visited = vec![false; paths.len()];
+        for (index, path) in paths.iter().enumerate() {
+            if !visited[index] {
+                visit(index, self.read_for_check(path));
+            }
+        }
+    }
+
+    /// Checks the file's manifest against its `manifest.sig`. Always passes when
+    /// no verifier is set.
+    pub fn verify_manifest(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.verifier {
+            Some(verifier) => verifier.verify(
+                &file_obj.manifest,
+                read_signature(Path::new(&file_obj.file_data.path)).as_deref(),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// The hex signature stored next to the file's manifest, if it was signed.
+    pub fn manifest_signature(&self, file_obj: &File) -> Option<String> {
+        read_signature(Path::new(&file_obj.file_data.path))
+    }
+
     /// Retrieves a list of all files in the archive.
     ///
     /// This function scans all subdirectories in the archive, reads each `manifest.json`,
@@ -65,8 +211,9 @@ impl FileStore {
     ///
     /// # Performance
     ///
-    /// - Reads all manifest.json files in the archive
-    /// - For large archives (1000+ files), consider caching this result
+    /// The first call reads every manifest.json in the archive. Later calls reuse
+    /// that scan until an entry is added to or removed from the archive directory
+    /// (its modification time changes) or [`FileStore::invalidate`] is called.
     ///
     /// # Example
     ///
@@ -79,20 +226,93 @@ impl FileStore {
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn get_all(&self) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        Ok(self.files()?.to_vec())
+    }
+
+    /// Drops the cached file list so the next lookup rescans the archive. Call
+    /// this after changing the archive outside of `FileStore`, e.g. a commit.
+    pub fn invalidate(&self) {
+        let mut cache = self.cache.write();
+        *cache = None;
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+        // under the lock, so a name `find` is still recording is cleared too
+        self.missing.clear();
+    }
+
+    /// Rescans the archive now and returns the fresh file list.
+    pub fn refresh(&self) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        self.invalidate();
+        self.get_all()
+    }
+
+    /// Whether the archive has been scanned at least once, so listings are
+    /// served from the cache. Doesn't touch the archive.
+    pub fn is_loaded(&self) -> bool {
+        self.scanned.load(Ordering::Relaxed)
+    }
+
+    /// The cached file list, rescanning if it is missing or the archive
+    /// directory changed since.
+    fn files(&self) -> Result<Arc<FileIndex>, Box<dyn std::error::Error>> {
+        let invalidations = self.invalidations.load(Ordering::Relaxed);
+        // read before scanning so a change during the scan triggers another one
+        let modified = fs::metadata(&self.store_path)?.modified()?;
+        if let Some(cache) = self.cache.read().as_ref()
+            && cache.modified == modified
+        {
+            return Ok(cache.files.clone());
+        }
+
+        let files = Arc::new(FileIndex::new(self.scan()?));
+        let mut cache = self.cache.write();
+        // a scan an invalidation overlapped may have missed what it was for
+        if self.invalidations.load(Ordering::Relaxed) == invalidations {
+            *cache = Some(FileCache {
+                modified,
+                files: files.clone(),
+            });
+            self.missing.clear();
+        }
+        drop(cache);
+        self.scanned.store(true, Ordering::Relaxed);
+        Ok(files)
+    }
+
+    fn scan(&self) -> Result<Vec<File>, Box<dyn std::error::Error>> {
         let mut file_list: Vec<File> = Vec::new();
 
         let manifests = self.all_files()?;
         tracing::info!("FILESTORE | scanning {} manifests", manifests.len());
 
         for path in manifests.iter() {
+            if path.parent().is_some_and(legacy::is_legacy) {
+                tracing::warn!(
+                    "FILESTORE | skipping legacy entry {:?}, run `blockframe migrate`",
+                    path.parent()
+                );
+                continue;
+            }
+            if !path.exists()
+                && let Some(file_dir) = path.parent()
+                && alias::is_alias(file_dir)
+            {
+                match self.resolve_alias(file_dir) {
+                    Ok(file) => file_list.push(file),
+                    Err(e) => tracing::warn!("FILESTORE | skipping alias {:?}: {}", file_dir, e),
+                }
+                continue;
+            }
             let manifest: ManifestFile = ManifestFile::new(path.display().to_string())?;
-            let file_entry = File::new(
-                manifest.name,
-                manifest.original_hash.to_string(),
-                path.display().to_string(),
-            )?;
-
-            file_list.push(file_entry);
+            file_list.push(File {
+                file_name: manifest.name.clone(),
+                file_data: FileData::new(
+                    manifest.original_hash.clone(),
+                    path.display().to_string(),
+                ),
+                committed: parse_time(&manifest.time_of_creation),
+                manifest,
+                alias_of: None,
+            });
         }
 
         tracing::info!("FILESTORE | found {} files in archive", file_list.len());
@@ -103,6 +323,8 @@ impl FileStore {
         let all_dirs = fs::read_dir(&self.store_path)?;
         let manifests: Vec<PathBuf> = all_dirs
             .filter_map(|entry| entry.ok())
+            // skip loose files such as audit.log and hidden work dirs
+            .filter(|f| f.path().is_dir() && !f.file_name().to_string_lossy().starts_with('.'))
             .map(|f| f.path().join("manifest.json"))
             .collect();
         Ok(manifests)
@@ -110,8 +332,9 @@ impl FileStore {
 
     /// Finds a specific file in the archive by its original filename.
     ///
-    /// This function searches through all archived files and returns the first
-    /// match with the specified filename.
+    /// Looks the name up in the index of the cached file list. When the name
+    /// has several versions the latest is returned, see
+    /// [`FileStore::find_all`] for all of them.
     ///
     /// # Parameters
     ///
@@ -134,233 +357,55 @@ impl FileStore {
     /// ```
     pub fn find(&self, filename: &String) -> Result<File, Box<dyn std::error::Error>> {
         tracing::debug!("FILESTORE | searching for file: {}", filename);
-        let files = &self.get_all()?;
-
-        for file in files {
-            if file.file_name == *filename {
-                tracing::info!(
-                    "FILESTORE | found file: {} (hash: {})",
-                    filename,
-                    &file.file_data.hash[..10]
-                );
-                return Ok(file.clone().to_owned());
-            }
+        if self.missing.contains(filename) {
+            return Err(not_found(filename));
+        }
+        let files = self.files()?;
+
+        if let Some(file) = files.named(filename).last() {
+            tracing::debug!(
+                "FILESTORE | found file: {} (hash: {})",
+                filename,
+                &file.file_data.hash[..10]
+            );
+            return Ok(file.clone());
         }
         tracing::warn!("FILESTORE | file not found: {}", filename);
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("File '{}' not found", filename),
-        )))
+        // only remembered while the list it was missing from is the cached
+        // one. A commit that invalidated it meanwhile, like the daemon's watch
+        // folder does, would otherwise be answered as missing for the whole
+        // TTL. The read lock keeps an invalidation from clearing the names
+        // before this one is in
+        let cache = self.cache.read();
+        if cache
+            .as_ref()
+            .is_some_and(|cache| Arc::ptr_eq(&cache.files, &files))
+        {
+            self.missing.insert(filename);
+        }
+        drop(cache);
+        Err(not_found(filename))
     }
 
-    pub fn segment_reconstruct(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
-        tracing::info!(
-            "FILESTORE | reconstructing segmented file: {}",
-            file_obj.file_name
-        );
-        // okay so we have a flat array of all of the chunks in order, we just need to append 1 by 1
+    /// Restores `file_obj` to `reconstructed/{file_name}` in the working
+    /// directory. See `reconstruct_to` for restoring into any other writer.
+    pub fn reconstruct(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
+        tracing::info!("FILESTORE | reconstructing: {}", file_obj.file_name);
         let reconstruct_path = Path::new("reconstructed");
-
         fs::create_dir_all(reconstruct_path)?;
 
-        let file_name = file_obj.file_name.clone();
+        let out = BufWriter::new(fs::File::create(
+            reconstruct_path.join(&file_obj.file_name),
+        )?);
+        self.reconstruct_to(file_obj, out)?;
 
-        let chunks = self.get_chunks_paths(file_obj)?;
-        tracing::info!("FILESTORE | reconstructing from {} chunks", chunks.len());
-
-        let mut file_being_reconstructed = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(reconstruct_path.join(&file_name))?;
-
-        for chunk in chunks {
-            let chunk_file = fs::read(chunk)?;
-
-            file_being_reconstructed.write_all(&chunk_file)?;
-        }
-
-        tracing::info!("FILESTORE | successfully reconstructed: {}", file_name);
-        Ok(())
-    }
-
-    pub fn tiny_reconstruct(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
         tracing::info!(
-            "FILESTORE | reconstructing tiny file: {}",
+            "FILESTORE | successfully reconstructed: {}",
             file_obj.file_name
         );
-        // okay so we have a flat array of all of the chunks in order, we just need to append 1 by 1
-        let reconstruct_path = Path::new("reconstructed");
-        fs::create_dir_all(reconstruct_path)?;
-        let file_name = file_obj.file_name.clone();
-
-        let file_path = Path::new(&file_obj.file_data.path)
-            .parent()
-            .ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "Could not get parent directory",
-                )
-            })?
-            .join("data.dat");
-
-        fs::write(reconstruct_path.join(&file_name), fs::read(file_path)?)?;
-        tracing::info!("FILESTORE | successfully reconstructed: {}", file_name);
         Ok(())
     }
 
-    pub fn reconstruct(&self, file_obj: &File) -> Result<(), Box<dyn std::error::Error>> {
-        let tier: u8 = match file_obj.manifest.size {
-            0..=10_000_000 => 1,
-            _ => 2,
-        };
-
-        match tier {
-            1 => self.tiny_reconstruct(file_obj)?,
-            _ => self.segment_reconstruct(file_obj)?,
-        };
-
-        Ok(())
-    }
-
-    pub fn get_chunks_paths(
-        &self,
-        file_obj: &File,
-    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
-        let segments_folder = &self.get_segments_paths(file_obj)?;
-
-        let mut all_chunks: Vec<PathBuf> = Vec::new();
-        for segment in segments_folder {
-            for i in 0..6 {
-                let chunk_path = segment
-                    .clone()
-                    .join("chunks")
-                    .join(format!("chunk_{:?}.dat", i));
-
-                all_chunks.push(chunk_path);
-            }
-        }
-        Ok(all_chunks)
-    }
-
-    pub fn get_parity_paths(
-        &self,
-        file_obj: &File,
-    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
-        let segments_folder = &self.get_segments_paths(file_obj)?;
-        let mut all_paraties: Vec<PathBuf> = Vec::new();
-        for segment in segments_folder {
-            for i in 0..3 {
-                let parity_path = segment
-                    .clone()
-                    .join("parity")
-                    .join(format!("parity_{:?}.dat", i));
-                all_paraties.push(parity_path);
-            }
-        }
-        Ok(all_paraties)
-    }
-
-    pub fn get_segments_paths(
-        &self,
-        file_obj: &File,
-    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
-        let file_dir: PathBuf = Path::new(&file_obj.file_data.path)
-            .parent()
-            .ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "Could not get parent directory",
-                )
-            })?
-            .to_path_buf();
-        let file_dir = file_dir.join("segments");
-
-        let mut segments_folder: Vec<PathBuf> = fs::read_dir(file_dir)?
-            .filter_map(|entry| entry.ok())
-            .map(|f| f.path())
-            .collect();
-
-        segments_folder.sort_by_key(|path| {
-            path.file_stem()
-                .and_then(|folder| folder.to_str())
-                .and_then(|folder| folder.split("_").last())
-                .and_then(|index| index.parse::<usize>().ok()) // This returns Option<usize>
-                .unwrap_or(0) // Provide a default value if parsing fails
-        });
-
-        Ok(segments_folder)
-    }
-
-    pub fn read_segment(&self, path: PathBuf) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
-        // gather all the chunks from the path
-        // and gather all of the
-        let mut chunk_data: Vec<Vec<u8>> = Vec::new();
-        let mut parity_data: Vec<Vec<u8>> = Vec::new();
-        let chunk_path = path.join("chunks");
-        let parity_path = path.join("parity");
-        for idx in 0..6 {
-            chunk_data.push(fs::read(chunk_path.join(format!("chunk_{idx}.dat")))?);
-        }
-
-        for idx in 0..3 {
-            parity_data.push(fs::read(parity_path.join(format!("parity_{idx}.dat")))?);
-        }
-
-        let combined: Vec<Vec<u8>> = chunk_data
-            .iter()
-            .chain(parity_data.iter())
-            .cloned()
-            .collect();
-        Ok(combined)
-    }
-
-    pub fn segment_hash(
-        &self,
-        combined_data: Vec<Vec<u8>>,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let segment_tree = MerkleTree::new(combined_data)?;
-        Ok(segment_tree.get_root()?.to_string())
-    }
-
-    pub fn get_size(&self, file_obj: &File) -> Result<u64, Box<dyn std::error::Error>> {
-        let mut file_size: u64 = 0;
-        let segments = &self.get_segments_paths(file_obj)?;
-        for segment in segments {
-            for i in 0..6 {
-                let chunk_path = segment
-                    .clone()
-                    .join("chunks")
-                    .join(format!("chunk_{:?}.dat", i));
-                println!("chunk_path: {:?}", chunk_path);
-
-                file_size += fs::File::open(chunk_path)?.metadata()?.len() as u64
-            }
-            for i in 0..3 {
-                let parity_path = segment
-                    .clone()
-                    .join("parity")
-                    .join(format!("parity_{:?}.dat", i));
-
-                println!("parity_path: {:?}", parity_path);
-                file_size += fs::File::open(parity_path)?.metadata()?.len() as u64
-            }
-        }
-
-        Ok(file_size)
-    }
-    fn hash_segment_with_parity(
-        &self,
-        segment_data: &[u8],
-        parity: &[Vec<u8>],
-    ) -> Result<String, std::io::Error> {
-        let combined: Vec<Vec<u8>> = std::iter::once(segment_data.to_vec())
-            .chain(parity.iter().cloned())
-            .collect();
-        let segment_tree = MerkleTree::new(combined)?;
-
-        Ok(segment_tree.get_root()?.to_string())
-    }
-
     /// Get path to segment for Tier 1
     pub fn get_data_path(&self, file: &File) -> Result<PathBuf, std::io::Error> {
         let file_dir = Path::new(&file.file_data.path).parent().ok_or_else(|| {
@@ -409,19 +454,30 @@ impl FileStore {
             .join(format!("segment_{}.dat", segment_id)))
     }
 
-    /// Get path to parity file
-    pub fn get_parity_path_t1(
-        &self,
-        file: &File,
-        parity_id: usize,
-    ) -> Result<PathBuf, std::io::Error> {
+    /// Directory the entry's parity files are laid out under: the one its
+    /// manifest records if they were written elsewhere, otherwise the entry's.
+    pub fn parity_root(&self, file: &File) -> Result<PathBuf, std::io::Error> {
+        if let Some(parity_dir) = &file.manifest.parity_dir {
+            return Ok(PathBuf::from(parity_dir));
+        }
         let file_dir = Path::new(&file.file_data.path).parent().ok_or_else(|| {
             std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "file path has no parent directory",
             )
         })?;
-        Ok(file_dir.join(format!("parity_{}.dat", parity_id)))
+        Ok(file_dir.to_path_buf())
+    }
+
+    /// Get path to parity file
+    pub fn get_parity_path_t1(
+        &self,
+        file: &File,
+        parity_id: usize,
+    ) -> Result<PathBuf, std::io::Error> {
+        Ok(self
+            .parity_root(file)?
+            .join(format!("parity_{}.dat", parity_id)))
     }
 
     /// Get path to parity file
@@ -431,13 +487,8 @@ impl FileStore {
         segment_id: usize,
         parity_id: usize,
     ) -> Result<PathBuf, std::io::Error> {
-        let file_dir = Path::new(&file.file_data.path).parent().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "file path has no parent directory",
-            )
-        })?;
-        Ok(file_dir
+        Ok(self
+            .parity_root(file)?
             .join("parity")
             .join(format!("segment_{}_parity_{}.dat", segment_id, parity_id)))
     }
@@ -449,16 +500,36 @@ impl FileStore {
         block_id: usize,
         parity_id: usize,
     ) -> Result<PathBuf, std::io::Error> {
-        let file_dir = Path::new(&file.file_data.path).parent().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "file path has no parent directory",
-            )
-        })?;
-        Ok(file_dir
+        Ok(self
+            .parity_root(file)?
             .join("blocks")
             .join(format!("block_{}", block_id))
             .join("parity")
             .join(format!("block_parity_{}.dat", parity_id)))
     }
 }
+
+fn not_found(filename: &str) -> Box<dyn std::error::Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("File '{}' not found", filename),
+    ))
+}
+
+/// Removes the entry directory `dir`, and the parity directory its manifest
+/// records if the parity was written elsewhere.
+pub(crate) fn remove_entry_dir(dir: &Path) -> Result<(), std::io::Error> {
+    remove_parity_dir(dir)?;
+    fs::remove_dir_all(dir)
+}
+
+/// Removes the parity directory the manifest in `dir` records, if any.
+pub(crate) fn remove_parity_dir(dir: &Path) -> Result<(), std::io::Error> {
+    let parity_dir = ManifestFile::new(dir.join("manifest.json").display().to_string())
+        .ok()
+        .and_then(|manifest| manifest.parity_dir);
+    match parity_dir {
+        Some(parity_dir) if Path::new(&parity_dir).exists() => fs::remove_dir_all(parity_dir),
+        _ => Ok(()),
+    }
+}
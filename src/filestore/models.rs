@@ -1,5 +1,5 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
 
 use crate::merkle_tree::manifest::ManifestFile;
 /// Manifest File Structures
@@ -16,6 +16,12 @@ pub struct File {
     pub file_name: String,
     pub file_data: FileData,
     pub manifest: ManifestFile,
+    /// Name of the archived file this one is a duplicate of. An alias has no
+    /// shards of its own, `file_data` and `manifest` are the original's.
+    pub alias_of: Option<String>,
+    /// When this name was committed with this content, from the manifest or
+    /// the alias. `None` if the recorded time doesn't parse.
+    pub committed: Option<DateTime<Utc>>,
 }
 
 impl FileData {
@@ -35,35 +41,39 @@ impl File {
         Ok(File {
             file_name,
             file_data,
+            committed: parse_time(&manifest.time_of_creation),
             manifest,
+            alias_of: None,
         })
     }
 }
 
-/// Segment Directory Structures
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ChunksAndParity {
-    pub chunks: Vec<PathBuf>,
-    pub parity: Vec<PathBuf>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct IDXDSegments {
-    pub idxd_segments: Vec<ChunksAndParity>,
-}
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Segments {
-    pub segments: Vec<IDXDSegments>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CommitedFile {
-    pub manifest: ManifestFile,
-    pub segments: Segments,
+/// Parses a commit time as written to manifests (`2025-01-31 12:00:00.5 UTC`)
+/// or alias files (RFC 3339).
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::filestore::models::parse_time;
+///
+/// let manifest = parse_time("2025-01-31 12:00:00.500 UTC").unwrap();
+/// let alias = parse_time("2025-01-31T12:00:01+00:00").unwrap();
+/// assert!(manifest < alias);
+/// assert!(parse_time("yesterday").is_none());
+/// ```
+pub fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(text.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|time| time.and_utc())
 }
 
-#[derive(Debug, PartialEq)]
+/// Ordered from best to worst, so `status >= HealthStatus::Recoverable` means
+/// "needs attention".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum HealthStatus {
     Healthy,
     Degraded,
@@ -90,3 +100,33 @@ pub struct BatchHealthReport {
     pub unrecoverable: usize,
     pub reports: Vec<(String, HealthReport)>,
 }
+
+impl BatchHealthReport {
+    /// Status of the worst off file, `Healthy` for an empty archive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use blockframe::filestore::models::{BatchHealthReport, HealthStatus};
+    /// let report = BatchHealthReport {
+    ///     total_files: 3,
+    ///     healthy: 2,
+    ///     degraded: 1,
+    ///     recoverable: 0,
+    ///     unrecoverable: 0,
+    ///     reports: Vec::new(),
+    /// };
+    /// assert_eq!(report.worst_status(), HealthStatus::Degraded);
+    /// ```
+    pub fn worst_status(&self) -> HealthStatus {
+        if self.unrecoverable > 0 {
+            HealthStatus::Unrecoverable
+        } else if self.recoverable > 0 {
+            HealthStatus::Recoverable
+        } else if self.degraded > 0 {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Healthy
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Checking a working copy against the archive.
+//!
+//! When the archive is the backup of a file that is still in use, the live file
+//! can be checked shard by shard: each range of it is hashed and compared with
+//! the data shard hash the manifest holds for that range, without reading the
+//! archive at all. Ranges that differ can then be rewritten from the archive
+//! alone, recovering the archived shards from parity where needed, instead of
+//! copying the whole file back.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use super::FileStore;
+use crate::audit::{AuditEntry, AuditOp};
+use crate::filestore::models::File;
+use crate::utils::blake3_hash_bytes;
+
+/// Outcome of comparing a live file with its archived copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalReport {
+    /// Data shards compared.
+    pub shards: usize,
+    /// Byte ranges of the live file that differ from the archive, one per
+    /// differing shard.
+    pub damaged: Vec<Range<u64>>,
+    pub live_size: u64,
+    pub archived_size: u64,
+}
+
+impl OriginalReport {
+    pub fn is_intact(&self) -> bool {
+        self.damaged.is_empty() && self.live_size == self.archived_size
+    }
+
+    /// Bytes covered by the damaged ranges.
+    pub fn damaged_bytes(&self) -> u64 {
+        self.damaged
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
+}
+
+impl FileStore {
+    /// Hashes `live` range by range and compares it with the shard hashes in
+    /// `file_obj`'s manifest. A live file shorter than the archived one has its
+    /// missing tail reported as damaged.
+    pub fn check_original(
+        &self,
+        file_obj: &File,
+        live: &Path,
+    ) -> Result<OriginalReport, Box<dyn std::error::Error>> {
+        let mut file = fs::File::open(live)?;
+        let live_size = file.metadata()?.len();
+        let shards = self.data_shards(file_obj)?;
+
+        let mut damaged = Vec::new();
+        let mut offset = 0u64;
+        for shard in &shards {
+            let range = offset..offset + shard.len as u64;
+            offset = range.end;
+
+            let mut bytes = Vec::with_capacity(shard.len);
+            file.seek(SeekFrom::Start(range.start))?;
+            (&mut file).take(shard.len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != shard.len || blake3_hash_bytes(&bytes)? != shard.hash {
+                tracing::debug!("ORIGINAL | {:?} differs at {:?}", live, range);
+                damaged.push(range);
+            }
+        }
+
+        Ok(OriginalReport {
+            shards: shards.len(),
+            damaged,
+            live_size,
+            archived_size: offset,
+        })
+    }
+
+    /// Rewrites the damaged ranges in `report` from the archive and trims or
+    /// extends `live` to the archived size, then checks it again. Returns the
+    /// number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an archived shard can't be recovered, or the live file still
+    /// differs afterwards, e.g. because it changed in between.
+    pub fn restore_original(
+        &self,
+        file_obj: &File,
+        live: &Path,
+        report: &OriginalReport,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut file = OpenOptions::new().write(true).open(live)?;
+        let mut written = 0u64;
+        let mut offset = 0u64;
+        for shard in self.data_shards(file_obj)? {
+            let range = offset..offset + shard.len as u64;
+            offset = range.end;
+            if !report.damaged.contains(&range) {
+                continue;
+            }
+
+            let bytes = self.read_shard(file_obj, &shard, false)?;
+            file.seek(SeekFrom::Start(range.start))?;
+            file.write_all(&bytes)?;
+            written += bytes.len() as u64;
+        }
+        file.set_len(offset)?;
+        file.sync_all()?;
+        drop(file);
+
+        let after = self.check_original(file_obj, live)?;
+        if !after.is_intact() {
+            return Err(format!(
+                "{:?} still differs from the archive in {} ranges",
+                live,
+                after.damaged.len()
+            )
+            .into());
+        }
+
+        self.audit(
+            &AuditEntry::new(AuditOp::Repair, &file_obj.file_name)
+                .hash(&file_obj.manifest.original_hash)
+                .details(format!(
+                    "restored {} bytes of {} in place",
+                    written,
+                    live.display()
+                )),
+        )?;
+        tracing::info!(
+            "ORIGINAL | restored {} bytes of {:?} from {}",
+            written,
+            live,
+            file_obj.file_name
+        );
+        Ok(written)
+    }
+}
@@ -0,0 +1,146 @@
+//! Orders repairs by how close each file is to data loss.
+//!
+//! A file's risk is set by its weakest stripe: a tier 1 file, a tier 2
+//! segment with its parity, or a tier 3 block. The margin of a stripe is how
+//! many more shards it can lose and still be rebuilt, its intact parity minus
+//! its lost data. [`FileStore::plan_repairs`] puts the files with the smallest
+//! margin first, so when many files are damaged the ones a single further
+//! failure would destroy are repaired before the ones missing a parity shard.
+
+use std::collections::HashMap;
+
+use super::FileStore;
+use super::models::{BatchHealthReport, File, HealthReport, HealthStatus};
+
+/// One file to repair, see [`FileStore::plan_repairs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairTask {
+    pub file_name: String,
+    pub status: HealthStatus,
+    /// Further shard losses the weakest stripe survives. Negative when that
+    /// stripe has lost more data than its parity covers.
+    pub margin: i64,
+    /// Data shards missing or corrupt, across the file.
+    pub lost_data: usize,
+    /// Parity shards missing or corrupt, across the file.
+    pub lost_parity: usize,
+}
+
+impl FileStore {
+    /// Health checks every file and returns the damaged ones that can be
+    /// repaired, most at risk first. Unrecoverable files are left out.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::filestore::FileStore;
+    /// use std::path::Path;
+    ///
+    /// let store = FileStore::new(Path::new("archive_directory"))?;
+    /// for task in store.plan_repairs()? {
+    ///     let file = store.find(&task.file_name)?;
+    ///     store.repair(&file)?;
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn plan_repairs(&self) -> Result<Vec<RepairTask>, Box<dyn std::error::Error>> {
+        let batch = self.batch_health_check()?;
+        self.plan_repairs_for(&batch)
+    }
+
+    /// Same as [`FileStore::plan_repairs`], from a check already made.
+    pub fn plan_repairs_for(
+        &self,
+        batch: &BatchHealthReport,
+    ) -> Result<Vec<RepairTask>, Box<dyn std::error::Error>> {
+        let mut tasks = Vec::new();
+        for (name, report) in &batch.reports {
+            if matches!(
+                report.status,
+                HealthStatus::Healthy | HealthStatus::Unrecoverable
+            ) {
+                continue;
+            }
+            let file = self.find(name)?;
+            tasks.push(RepairTask {
+                file_name: name.clone(),
+                status: report.status,
+                margin: margin(&file, report),
+                lost_data: report.missing_data.len() + report.corrupt_segments.len(),
+                lost_parity: report.missing_parity.len(),
+            });
+        }
+        // ties go to the file with more damage, then by name so plans are stable
+        tasks.sort_by(|a, b| {
+            a.margin
+                .cmp(&b.margin)
+                .then((b.lost_data + b.lost_parity).cmp(&(a.lost_data + a.lost_parity)))
+                .then_with(|| a.file_name.cmp(&b.file_name))
+        });
+        Ok(tasks)
+    }
+}
+
+/// Lost data and lost parity shards of one stripe.
+#[derive(Default)]
+struct StripeLoss {
+    data: i64,
+    parity: i64,
+}
+
+/// Margin of `file`'s weakest stripe going by the shards `report` lists.
+fn margin(file: &File, report: &HealthReport) -> i64 {
+    let parity_of = |stripe: &str| -> i64 {
+        match file.manifest.tier {
+            3 => stripe
+                .strip_prefix("block_")
+                .and_then(|id| id.parse::<usize>().ok())
+                .and_then(|id| file.manifest.merkle_tree.blocks.get(&id))
+                .map_or(0, |block| block.geometry().1 as i64),
+            _ => file.manifest.erasure_coding.parity_shards.max(0) as i64,
+        }
+    };
+
+    let mut stripes: HashMap<String, StripeLoss> = HashMap::new();
+    for shard in report.missing_data.iter().chain(&report.corrupt_segments) {
+        stripes.entry(stripe_of(shard)).or_default().data += 1;
+    }
+    for shard in &report.missing_parity {
+        let stripe = stripe_of(shard);
+        // tier 3 local parity past the block's own parity costs no redundancy
+        if let Some(idx) = parity_index(shard)
+            && file.manifest.tier == 3
+            && idx as i64 >= parity_of(&stripe)
+        {
+            continue;
+        }
+        stripes.entry(stripe).or_default().parity += 1;
+    }
+
+    stripes
+        .iter()
+        .map(|(stripe, loss)| parity_of(stripe) - loss.parity - loss.data)
+        .min()
+        .unwrap_or_else(|| parity_of(""))
+}
+
+/// The stripe a shard name from a health report belongs to: `block_N` for
+/// tier 3, `segment_N` for tier 2 and the whole file for tier 1.
+fn stripe_of(shard: &str) -> String {
+    if let Some((block, _)) = shard.split_once('/') {
+        return block.to_string();
+    }
+    match shard.strip_prefix("segment_") {
+        Some(rest) => {
+            let idx: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            format!("segment_{}", idx)
+        }
+        None => String::new(),
+    }
+}
+
+/// `P` from a `..._parity_P.dat` shard name.
+fn parity_index(shard: &str) -> Option<usize> {
+    let (_, rest) = shard.rsplit_once("parity_")?;
+    rest.split('.').next()?.parse().ok()
+}
@@ -0,0 +1,244 @@
+//! Filtered, sorted pages of the archive listing.
+//!
+//! Listing 50k files to show the first hundred is wasteful, so a
+//! [`FileQuery`] is run against the cached file list of the store: entries
+//! are filtered and sorted by reference and only the requested page is
+//! cloned. Nothing touches the archive directory unless the cache is stale.
+//!
+//! [`FileStore::search`] is the simpler way in for finding a name without
+//! knowing it exactly: a glob or a plain piece of the name, ignoring case.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use super::FileStore;
+use crate::filestore::models::File;
+use crate::tags::TagFilter;
+
+/// What [`FileQuery`] orders by. Ties are broken by name, then content hash,
+/// so a page is the same on every request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Tier,
+    /// Commit time, files whose time doesn't parse come first.
+    Committed,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "size" => Ok(Self::Size),
+            "tier" => Ok(Self::Tier),
+            "committed" | "time" => Ok(Self::Committed),
+            other => Err(format!(
+                "unknown sort key '{}', expected name, size, tier or committed",
+                other
+            )),
+        }
+    }
+}
+
+/// Which files [`FileStore::query`] returns. The default matches every file,
+/// sorted by name, with no limit.
+#[derive(Debug, Clone, Default)]
+pub struct FileQuery {
+    /// Keep names containing this, ignoring case.
+    pub name: Option<String>,
+    /// Keep names matching this glob pattern.
+    pub glob: Option<glob::Pattern>,
+    /// Match `glob` ignoring case, `name` always does.
+    pub ignore_case: bool,
+    /// Keep files of this tier.
+    pub tier: Option<u8>,
+    /// Keep files matching every one of these tag filters.
+    pub tags: Vec<TagFilter>,
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Matching files to skip before the page starts.
+    pub offset: usize,
+    /// Most files to return, all of them if unset.
+    pub limit: Option<usize>,
+}
+
+impl FileQuery {
+    /// Keeps names containing `needle`, ignoring case.
+    pub fn with_name(mut self, needle: impl Into<String>) -> Self {
+        self.name = Some(needle.into());
+        self
+    }
+
+    /// Keeps names matching `pattern`, e.g. `photos/*.jpg`.
+    pub fn with_glob(mut self, pattern: &str) -> Result<Self, String> {
+        let pattern = glob::Pattern::new(pattern)
+            .map_err(|e| format!("invalid glob '{}': {}", pattern, e))?;
+        self.glob = Some(pattern);
+        Ok(self)
+    }
+
+    /// Matches the glob ignoring case, so `*.mkv` also keeps `MOVIE.MKV`.
+    pub fn ignoring_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+
+    pub fn with_tier(mut self, tier: u8) -> Self {
+        self.tier = Some(tier);
+        self
+    }
+
+    /// Keeps files with the tag, see [`TagFilter`]. Each call narrows further.
+    pub fn with_tag(mut self, tag: TagFilter) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    pub fn with_sort(mut self, sort: SortKey, descending: bool) -> Self {
+        self.sort = sort;
+        self.descending = descending;
+        self
+    }
+
+    /// Returns at most `limit` files, after skipping `offset`.
+    pub fn with_page(mut self, offset: usize, limit: Option<usize>) -> Self {
+        self.offset = offset;
+        self.limit = limit;
+        self
+    }
+
+    fn matches(&self, file: &File, needle: Option<&str>) -> bool {
+        if let Some(needle) = needle
+            && !file.file_name.to_lowercase().contains(needle)
+        {
+            return false;
+        }
+        let options = glob::MatchOptions {
+            case_sensitive: !self.ignore_case,
+            ..Default::default()
+        };
+        if let Some(pattern) = &self.glob
+            && !pattern.matches_with(&file.file_name, options)
+        {
+            return false;
+        }
+        if !self.tags.iter().all(|tag| tag.matches(&file.manifest.tags)) {
+            return false;
+        }
+        self.tier.is_none_or(|tier| file.manifest.tier == tier)
+    }
+
+    fn compare(&self, a: &File, b: &File) -> Ordering {
+        let order = match self.sort {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.manifest.size.cmp(&b.manifest.size),
+            SortKey::Tier => a.manifest.tier.cmp(&b.manifest.tier),
+            SortKey::Committed => a.committed.cmp(&b.committed),
+        }
+        .then_with(|| a.file_name.cmp(&b.file_name))
+        .then_with(|| a.file_data.hash.cmp(&b.file_data.hash));
+        if self.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+}
+
+/// How [`FileStore::search`] matches.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Match case exactly instead of ignoring it.
+    pub case_sensitive: bool,
+    /// Return every version of a matching name instead of the latest only.
+    pub all_versions: bool,
+    /// Only return files matching every one of these tag filters.
+    pub tags: Vec<TagFilter>,
+}
+
+impl FileStore {
+    /// Files whose name matches `pattern`, sorted by name. A pattern with `*`,
+    /// `?` or `[` is a glob matched against the whole name, anything else
+    /// matches names containing it.
+    ///
+    /// ```no_run
+    /// # use blockframe::filestore::FileStore;
+    /// # use blockframe::filestore::query::SearchOptions;
+    /// # use std::path::Path;
+    /// # let store = FileStore::new(Path::new("archive_directory"))?;
+    /// for file in store.search("*.mkv", SearchOptions::default())? {
+    ///     println!("{}", file.file_name);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn search(
+        &self,
+        pattern: &str,
+        options: SearchOptions,
+    ) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        let glob = match pattern.contains(['*', '?', '[']) {
+            true => pattern.to_string(),
+            false => format!("*{}*", glob::Pattern::escape(pattern)),
+        };
+        let mut query = FileQuery::default().with_glob(&glob)?;
+        query.ignore_case = !options.case_sensitive;
+        query.tags = options.tags;
+
+        let files = self.files()?;
+        let mut found: Vec<&File> = files
+            .iter()
+            .filter(|file| query.matches(file, None))
+            .filter(|file| {
+                options.all_versions
+                    || files
+                        .named(&file.file_name)
+                        .last()
+                        .is_some_and(|latest| std::ptr::eq(latest, *file))
+            })
+            .collect();
+        found.sort_by(|a, b| query.compare(a, b));
+        Ok(found.into_iter().cloned().collect())
+    }
+
+    /// The page of files `query` selects, with the number of files that
+    /// matched before paging.
+    ///
+    /// ```no_run
+    /// # use blockframe::filestore::FileStore;
+    /// # use blockframe::filestore::query::{FileQuery, SortKey};
+    /// # use std::path::Path;
+    /// # let store = FileStore::new(Path::new("archive_directory"))?;
+    /// let query = FileQuery::default()
+    ///     .with_glob("*.jpg")?
+    ///     .with_sort(SortKey::Size, true)
+    ///     .with_page(0, Some(10));
+    /// let (total, largest) = store.query(&query)?;
+    /// println!("{} of {} photos", largest.len(), total);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn query(
+        &self,
+        query: &FileQuery,
+    ) -> Result<(usize, Vec<File>), Box<dyn std::error::Error>> {
+        let files = self.files()?;
+        let needle = query.name.as_ref().map(|name| name.to_lowercase());
+        let mut matched: Vec<&File> = files
+            .iter()
+            .filter(|file| query.matches(file, needle.as_deref()))
+            .collect();
+        matched.sort_by(|a, b| query.compare(a, b));
+
+        let total = matched.len();
+        let page = matched
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        Ok((total, page))
+    }
+}
@@ -0,0 +1,308 @@
+//! Reading an archived file as a stream.
+//!
+//! [`FileStore::open_reader`] returns a [`FileReader`], which is `Read + Seek`
+//! over the original bytes of a file. It loads one data shard at a time, when
+//! a read first reaches it, so seeking to the middle of a tier 3 file only
+//! touches the segments around that point. Each shard is checked against its
+//! manifest hash and recovered from parity in memory if it is missing or
+//! corrupt, as in [`FileStore::reconstruct_to`]. Nothing on disk is changed
+//! unless the reader is made with [`FileReader::heal_on_read`], which a local
+//! mount uses to repair shards as they are read. [`FileReader::with_integrity`]
+//! trades the hash checks for speed on archives that are trusted.
+//!
+//! Restores, range reads, the server's download route and local mounts all
+//! read through it.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::FileStore;
+use super::restore::DataShard;
+use crate::filestore::models::File;
+
+/// How much a read checks the shards it loads, set for mounts with
+/// `--integrity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Integrity {
+    /// Check every shard against its manifest hash as it is loaded.
+    #[default]
+    Always,
+    /// Trust shards as they are, and only fall back on the checked read,
+    /// recovering from parity, when one can't be read or comes up short.
+    OnCorruption,
+    /// Never check: a shard that can't be read is an error.
+    Off,
+}
+
+impl FromStr for Integrity {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "on-corruption" => Ok(Self::OnCorruption),
+            "off" => Ok(Self::Off),
+            other => Err(format!(
+                "unknown integrity policy {:?}, expected always, on-corruption or off",
+                other
+            )),
+        }
+    }
+}
+
+/// `Read + Seek` over the original bytes of an archived file, see the
+/// [module docs](self).
+///
+/// # Example
+///
+/// ```no_run
+/// use blockframe::filestore::FileStore;
+/// use std::io::{Read, Seek, SeekFrom};
+/// use std::path::Path;
+///
+/// let store = FileStore::new(Path::new("archive_directory"))?;
+/// let file = store.find(&"movie.mkv".to_string())?;
+/// let mut reader = store.open_reader(&file)?;
+/// reader.seek(SeekFrom::Start(1 << 30))?;
+/// let mut buf = vec![0u8; 4096];
+/// reader.read_exact(&mut buf)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct FileReader {
+    store: FileStore,
+    file: File,
+    shards: Vec<DataShard>,
+    size: u64,
+    pos: u64,
+    /// The shard last read, by index into `shards`.
+    current: Option<(usize, Vec<u8>)>,
+    heal: bool,
+    integrity: Integrity,
+}
+
+impl FileStore {
+    /// Opens `file_obj` for reading, see [`FileReader`]. Only the manifest is
+    /// read until the first read.
+    pub fn open_reader(&self, file_obj: &File) -> Result<FileReader, Box<dyn std::error::Error>> {
+        let shards = self.data_shards(file_obj)?;
+        let size = shards
+            .last()
+            .map_or(0, |shard| shard.offset + shard.len as u64);
+        Ok(FileReader {
+            // the shard paths all come from the file itself, so the reader
+            // needs no more of the store than its path
+            store: FileStore::new(&self.store_path)?,
+            file: file_obj.clone(),
+            shards,
+            size,
+            pos: 0,
+            current: None,
+            heal: false,
+            integrity: Integrity::Always,
+        })
+    }
+}
+
+impl FileReader {
+    /// Writes shards recovered from parity back over the missing or corrupt
+    /// ones, so the next read finds them intact.
+    pub fn heal_on_read(mut self) -> Self {
+        self.heal = true;
+        self
+    }
+
+    /// Checks shards as `integrity` says, [`Integrity::Always`] by default.
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// Size of the original file in bytes.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The archived file being read.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// The data shards of the file in order, with their offsets.
+    pub fn shards(&self) -> &[DataShard] {
+        &self.shards
+    }
+
+    /// Bytes of the shard at `index`, loaded if it isn't the current one.
+    fn shard(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self.current.as_ref().is_none_or(|(at, _)| *at != index) {
+            let shard = &self.shards[index];
+            let unchecked = || {
+                fs::read(&shard.path)
+                    .ok()
+                    .filter(|bytes| bytes.len() >= shard.len)
+            };
+            let bytes = match self.integrity {
+                _ if shard.zero => Some(vec![0; shard.len]),
+                Integrity::Always => None,
+                Integrity::OnCorruption => unchecked(),
+                Integrity::Off => Some(unchecked().ok_or_else(|| {
+                    io::Error::other(format!("{}: missing or short", shard.path.display()))
+                })?),
+            };
+            let bytes = match bytes {
+                Some(mut bytes) => {
+                    bytes.truncate(shard.len);
+                    bytes
+                }
+                None => self
+                    .store
+                    .read_shard(&self.file, shard, self.heal)
+                    .map_err(|e| io::Error::other(format!("{}: {}", shard.path.display(), e)))?,
+            };
+            self.current = Some((index, bytes));
+        }
+        Ok(self.current.as_ref().map_or(&[], |(_, bytes)| bytes))
+    }
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.size {
+            return Ok(0);
+        }
+        let pos = self.pos;
+        let index = self.shards.partition_point(|shard| shard.offset <= pos) - 1;
+        let from = (pos - self.shards[index].offset) as usize;
+        let bytes = self.shard(index)?;
+        let count = buf.len().min(bytes.len().saturating_sub(from));
+        buf[..count].copy_from_slice(&bytes[from..from + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for FileReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start of the file",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunker;
+    use crate::utils::SegmentPolicy;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reader_seeks_across_shards() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("seek.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+
+        for tier in [1, 2, 3] {
+            let chunked = chunker.commit_as(&source, Some(tier)).unwrap();
+            store.invalidate();
+            let file = store.find(&"seek.bin".to_string()).unwrap();
+            // lose the second data shard, reads recover it
+            if tier > 1 {
+                fs::remove_file(&store.data_shards(&file).unwrap()[1].path).unwrap();
+            }
+
+            let mut reader = store.open_reader(&file).unwrap();
+            assert_eq!(reader.len(), data.len() as u64);
+            let mut all = Vec::new();
+            reader.read_to_end(&mut all).unwrap();
+            assert!(all == data, "tier {}", tier);
+
+            // a read across the boundary of the first two shards
+            let mut buf = vec![0u8; 100];
+            reader.seek(SeekFrom::Start(65_500)).unwrap();
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, data[65_500..65_600], "tier {}", tier);
+
+            reader.seek(SeekFrom::End(-10)).unwrap();
+            assert_eq!(reader.read(&mut buf).unwrap(), 10);
+            assert_eq!(buf[..10], data[data.len() - 10..]);
+            assert_eq!(reader.read(&mut buf).unwrap(), 0);
+            assert!(reader.seek(SeekFrom::Current(-300_000)).is_err());
+
+            // healing puts the lost shard back
+            if tier > 1 {
+                let lost = &store.data_shards(&file).unwrap()[1].path;
+                assert!(!lost.exists());
+                let mut reader = store.open_reader(&file).unwrap().heal_on_read();
+                reader.read_to_end(&mut Vec::new()).unwrap();
+                assert!(lost.exists(), "tier {}", tier);
+            }
+
+            fs::remove_dir_all(&chunked.file_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reader_integrity_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("trust.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let chunker = Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        chunker.commit_as(&source, Some(2)).unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let file = store.find(&"trust.bin".to_string()).unwrap();
+        let shard = store.data_shards(&file).unwrap()[0].path.clone();
+        let read_all = |integrity: Integrity| {
+            let mut reader = store.open_reader(&file).unwrap().with_integrity(integrity);
+            let mut all = Vec::new();
+            reader.read_to_end(&mut all).map(|_| all)
+        };
+
+        // a flipped bit is only caught when shards are checked
+        let mut bytes = fs::read(&shard).unwrap();
+        bytes[0] ^= 0xff;
+        fs::write(&shard, &bytes).unwrap();
+        assert!(read_all(Integrity::Always).unwrap() == data);
+        assert!(read_all(Integrity::OnCorruption).unwrap()[0] == data[0] ^ 0xff);
+        assert!(read_all(Integrity::Off).unwrap()[0] == data[0] ^ 0xff);
+
+        // a missing shard is recovered unless checks are off
+        fs::remove_file(&shard).unwrap();
+        assert!(read_all(Integrity::OnCorruption).unwrap() == data);
+        assert!(read_all(Integrity::Off).is_err());
+
+        assert_eq!("on-corruption".parse(), Ok(Integrity::OnCorruption));
+        assert!("sometimes".parse::<Integrity>().is_err());
+    }
+}
@@ -1,15 +1,317 @@
-/// Segment-level Reed-Solomon recovery utilities.
+//! Segment-level Reed-Solomon recovery utilities.
+//!
+//! Every decode in the crate goes through this module: streaming restores and
+//! `check-original` in the filestore, `repair` in health.rs, and both
+//! filesystem implementations (Unix FUSE, Windows WinFSP).
+//!
+//! [`recover_shard`] is the tier-aware entry point. Given a manifest and which
+//! data shard to rebuild, it reads the parity (and for tier 3 the rest of the
+//! block) through the caller's closures, so it works the same on a local
+//! archive and a remote source, leaves out shards that fail their manifest
+//! hash, decodes with the geometry the manifest records, and checks the result
+//! against the shard's hash. The `recover_segment_*` functions underneath are
+//! the bare decoders. A tier 3 segment whose block has local parity is
+//! rebuilt from its group first, see [`erasure::local_parity`], reading a few
+//! segments instead of the whole block. `recover_shard` decodes with the erasure code the
+//! manifest's `erasure_coding.type` names, the bare decoders with the default
+//! [`erasure::ReedSolomon`] unless given one.
+//!
+//! The key difference from health.rs repair methods:
+//! - These operate on individual segments in-memory
+//! - Designed for on-the-fly recovery during reads
+//! - Return recovered data directly without writing to disk
+//! - Caller decides whether to cache or persist
+use crate::erasure::{self, ErasureCoder};
+use crate::merkle_tree::manifest::{BlockHashes, ManifestFile};
+use crate::utils::{BLOCK_SEGMENTS, blake3_hash_bytes};
+
+/// Where a data shard sits in the archive layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardKind {
+    /// Tier 1 `data.dat`.
+    Tiny,
+    /// Tier 2 segment N.
+    Segment(usize),
+    /// Tier 3 segment N of block B, as `Block(B, N)`.
+    Block(usize, usize),
+}
+
+/// The BLAKE3 hash and unpadded length of data shard `kind`, from the manifest.
+pub fn expected_shard(
+    manifest: &ManifestFile,
+    kind: ShardKind,
+) -> Result<(String, usize), Box<dyn std::error::Error>> {
+    let tree = &manifest.merkle_tree;
+    let size = manifest.size.max(0) as usize;
+    let segment_size = manifest.segment_size as usize;
+    // the last segment of the file is the only short one
+    let segment_len = |global: usize| segment_size.min(size.saturating_sub(global * segment_size));
+
+    match kind {
+        ShardKind::Tiny => {
+            let hash = tree.leaves.get(&0).ok_or("manifest is missing leaf 0")?;
+            Ok((hash.clone(), size))
+        }
+        ShardKind::Segment(idx) => {
+            let hashes = tree
+                .segments
+                .get(&idx)
+                .ok_or_else(|| format!("manifest is missing segment {}", idx))?;
+            Ok((hashes.data.clone(), segment_len(idx)))
+        }
+        ShardKind::Block(block_id, seg_idx) => {
+            let block = tree
+                .blocks
+                .get(&block_id)
+                .ok_or_else(|| format!("manifest is missing block {}", block_id))?;
+            let hash = block
+                .segments
+                .get(seg_idx)
+                .ok_or_else(|| format!("block {} has no segment {}", block_id, seg_idx))?;
+            let len = block
+                .segment_len(seg_idx)
+                .unwrap_or_else(|| segment_len(block_id * BLOCK_SEGMENTS + seg_idx));
+            Ok((hash.clone(), len))
+        }
+    }
+}
+
+/// Whether the manifest marks data shard `kind` as all zeros, so it can be
+/// read without touching the disk.
+pub fn is_zero_shard(manifest: &ManifestFile, kind: ShardKind) -> bool {
+    let tree = &manifest.merkle_tree;
+    match kind {
+        ShardKind::Tiny => false,
+        ShardKind::Segment(idx) => tree.segments.get(&idx).is_some_and(|hashes| hashes.zero),
+        ShardKind::Block(block_id, seg_idx) => tree
+            .blocks
+            .get(&block_id)
+            .is_some_and(|block| block.zero_segments.contains(&seg_idx)),
+    }
+}
+
+/// BLAKE3 hash of `len` zero bytes, which a shard the manifest marks as all
+/// zeros must be recorded with.
+pub fn zeros_hash(len: usize) -> String {
+    let zeros = [0u8; 64 * 1024];
+    let mut hasher = blake3::Hasher::new();
+    let mut left = len;
+    while left > 0 {
+        let chunk = left.min(zeros.len());
+        hasher.update(&zeros[..chunk]);
+        left -= chunk;
+    }
+    hasher.finalize().to_string()
+}
+
+/// Data shards of the file `manifest` describes, in file order.
+pub fn data_shard_kinds(
+    manifest: &ManifestFile,
+) -> Result<Vec<ShardKind>, Box<dyn std::error::Error>> {
+    let tree = &manifest.merkle_tree;
+    match manifest.tier {
+        1 => Ok(vec![ShardKind::Tiny]),
+        2 => Ok((0..tree.segments.len()).map(ShardKind::Segment).collect()),
+        3 => {
+            let mut kinds = Vec::new();
+            for block_id in 0..tree.blocks.len() {
+                let block = tree
+                    .blocks
+                    .get(&block_id)
+                    .ok_or_else(|| format!("manifest is missing block {}", block_id))?;
+                kinds.extend(
+                    (0..block.segments.len()).map(|seg_idx| ShardKind::Block(block_id, seg_idx)),
+                );
+            }
+            Ok(kinds)
+        }
+        _ => Err("unknown tier".into()),
+    }
+}
+
+/// Rebuilds data shard `kind` of the file `manifest` describes and checks it
+/// against its manifest hash. Returns the unpadded shard.
 ///
-/// This module provides low-level recovery functions that can be used by both
-/// filesystem implementations (Unix FUSE, Windows WinFSP) and the batch repair
-/// operations in health.rs.
+/// `read_parity(p)` reads parity shard `p` of the shard's parity set (the
+/// file's for tier 1, the segment's for tier 2, the block's for tier 3) and
+/// `read_segment(n)` reads segment `n` of the same tier 3 block; both return
+/// `None` for a shard that can't be read. Shards whose hash the manifest
+/// records and that don't match it are left out.
 ///
-/// The key difference from health.rs repair methods:
-/// - These operate on individual segments in-memory
-/// - Designed for on-the-fly recovery during reads
-/// - Return recovered data directly without writing to disk
-/// - Caller decides whether to cache or persist
-use reed_solomon_simd::ReedSolomonDecoder;
+/// # Example
+///
+/// ```no_run
+/// use blockframe::filestore::recovery::{ShardKind, recover_shard};
+/// # let manifest: blockframe::merkle_tree::manifest::ManifestFile = todo!();
+///
+/// let dir = std::path::Path::new("archive_directory/movie.mkv_abc/blocks/block_0");
+/// let segment = recover_shard(
+///     &manifest,
+///     ShardKind::Block(0, 5),
+///     |n| std::fs::read(dir.join(format!("segments/segment_{}.dat", n))).ok(),
+///     |p| std::fs::read(dir.join(format!("parity/block_parity_{}.dat", p))).ok(),
+/// )
+/// .unwrap();
+/// ```
+pub fn recover_shard(
+    manifest: &ManifestFile,
+    kind: ShardKind,
+    mut read_segment: impl FnMut(usize) -> Option<Vec<u8>>,
+    mut read_parity: impl FnMut(usize) -> Option<Vec<u8>>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (hash, len) = expected_shard(manifest, kind)?;
+    let coder = erasure::coder(&manifest.erasure_coding.r#type)?;
+    let matches = |bytes: &[u8], hash: &str| blake3_hash_bytes(bytes).is_ok_and(|h| h == hash);
+
+    let recovered = match kind {
+        ShardKind::Tiny | ShardKind::Segment(_) => {
+            let parity_hashes = match kind {
+                ShardKind::Segment(idx) => manifest.merkle_tree.segments[&idx].parity.as_slice(),
+                _ => &[],
+            };
+            let parity_count = parity_hashes
+                .len()
+                .max(manifest.erasure_coding.parity_shards.max(0) as usize);
+
+            // with one data shard every parity shard holds all of it, so try them
+            // in turn: tier 1 records no parity hashes to rule out a bad one
+            let mut recovered = None;
+            for parity_id in 0..parity_count {
+                let Some(parity) = read_parity(parity_id) else {
+                    continue;
+                };
+                if parity_hashes
+                    .get(parity_id)
+                    .is_some_and(|hash| !matches(&parity, hash))
+                {
+                    continue;
+                }
+                let mut slots = vec![None; parity_count];
+                slots[parity_id] = Some(parity);
+                if let Ok(data) = recover_single_with(coder, slots, Some(len))
+                    && matches(&data, &hash)
+                {
+                    recovered = Some(data);
+                    break;
+                }
+            }
+            recovered.ok_or_else(|| format!("no parity shard could recover {:?}", kind))?
+        }
+        ShardKind::Block(block_id, seg_idx) => {
+            let block = &manifest.merkle_tree.blocks[&block_id];
+            let (data_shards, parity_shards) = block.geometry();
+            if let Some(recovered) =
+                recover_from_group(block, seg_idx, &mut read_segment, &mut read_parity)
+                    .filter(|data| data.len() >= len)
+                    .map(|mut data| {
+                        data.truncate(len);
+                        data
+                    })
+                    .filter(|data| matches(data, &hash))
+            {
+                return Ok(recovered);
+            }
+            let segments = (0..data_shards)
+                .map(|idx| {
+                    if idx == seg_idx {
+                        return None;
+                    }
+                    read_segment(idx).filter(|bytes| matches(bytes, &block.segments[idx]))
+                })
+                .collect();
+            let parity = (0..parity_shards)
+                .map(|parity_id| {
+                    read_parity(parity_id).filter(|bytes| {
+                        block
+                            .parity
+                            .get(parity_id)
+                            .is_none_or(|hash| matches(bytes, hash))
+                    })
+                })
+                .collect();
+            recover_block_with(coder, segments, parity, seg_idx, Some(len))?
+        }
+    };
+
+    if !matches(&recovered, &hash) {
+        return Err(format!("recovery of {:?} failed verification", kind).into());
+    }
+    Ok(recovered)
+}
+
+/// Segment `seg_idx` of `block` from its local parity group, reading only
+/// the group: the XOR of the group's parity with its other segments. `None`
+/// when the block has no local parity or a shard of the group can't be read
+/// or fails its hash, the caller then decodes with the global parity.
+fn recover_from_group(
+    block: &BlockHashes,
+    seg_idx: usize,
+    read_segment: &mut impl FnMut(usize) -> Option<Vec<u8>>,
+    read_parity: &mut impl FnMut(usize) -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let (group, members) = block.local_group_of(seg_idx)?;
+    let (_, global) = block.geometry();
+    let hash_ok = |bytes: &[u8], hash: Option<&String>| {
+        hash.is_some_and(|hash| blake3_hash_bytes(bytes).is_ok_and(|h| h == *hash))
+    };
+    let parity = read_parity(global + group)
+        .filter(|bytes| hash_ok(bytes, block.local_parity.get(group)))?;
+    let mut shards = vec![parity];
+    for idx in members.filter(|&idx| idx != seg_idx) {
+        shards.push(read_segment(idx).filter(|bytes| hash_ok(bytes, block.segments.get(idx)))?);
+    }
+    let shard_size = shards[0].len();
+    let refs: Vec<&[u8]> = shards.iter().map(Vec::as_slice).collect();
+    Some(erasure::local_parity(&refs, shard_size))
+}
+
+/// Recovers the data shard of an RS(1,n) set, as used by Tier 1 and Tier 2,
+/// from whichever parity shards are available (`None` for lost ones).
+pub fn recover_single(
+    parity_shards: Vec<Option<Vec<u8>>>,
+    expected_size: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    recover_single_with(&erasure::ReedSolomon, parity_shards, expected_size)
+}
+
+/// [`recover_single`] with the erasure code the set was written with.
+pub fn recover_single_with(
+    coder: &dyn ErasureCoder,
+    parity_shards: Vec<Option<Vec<u8>>>,
+    expected_size: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let shard_size = parity_shards
+        .iter()
+        .flatten()
+        .map(|shard| shard.len())
+        .next()
+        .ok_or("No parity shard available")?;
+
+    // Verify all shards are same size
+    if !parity_shards
+        .iter()
+        .flatten()
+        .all(|s| s.len() == shard_size)
+    {
+        return Err("All parity shards must be the same size".into());
+    }
+
+    // only the parity is available, the data shard is missing/corrupt
+    let parity: Vec<Option<&[u8]>> = parity_shards.iter().map(|s| s.as_deref()).collect();
+    let mut recovered = coder
+        .decode(&[None], &parity, shard_size)?
+        .remove(&0)
+        .ok_or("Recovery failed")?;
+
+    // Truncate if needed (padding removal)
+    if let Some(size) = expected_size
+        && recovered.len() > size
+    {
+        recovered.truncate(size);
+    }
+
+    Ok(recovered)
+}
 
 /// Recovers a single segment using Reed-Solomon RS(1,3) decoding.
 ///
@@ -47,47 +349,26 @@ pub fn recover_segment_rs13(
     if parity_shards.len() != 3 {
         return Err("Exactly 3 parity shards required for RS(1,3)".into());
     }
-
-    let shard_size = parity_shards[0].len();
-
-    // Verify all shards are same size
-    if !parity_shards.iter().all(|s| s.len() == shard_size) {
-        return Err("All parity shards must be the same size".into());
-    }
-
-    let mut decoder = ReedSolomonDecoder::new(1, 3, shard_size)?;
-
-    // Add all 3 parity shards (data shard is missing/corrupt)
-    decoder.add_recovery_shard(0, &parity_shards[0])?;
-    decoder.add_recovery_shard(1, &parity_shards[1])?;
-    decoder.add_recovery_shard(2, &parity_shards[2])?;
-
-    let result = decoder.decode()?;
-    let mut recovered = result
-        .restored_original(0)
-        .ok_or("Recovery failed")?
-        .to_vec();
-
-    // Truncate if needed (Tier 1 padding removal)
-    if let Some(size) = expected_size
-        && recovered.len() > size
-    {
-        recovered.truncate(size);
-    }
-
-    Ok(recovered)
+    recover_single(parity_shards.into_iter().map(Some).collect(), expected_size)
 }
 
-/// Recovers a segment from a Tier 3 block using RS(30,3) decoding.
+/// Recovers a segment from a Tier 3 block using RS(30,3) decoding, or the
+/// smaller geometry of a partial block.
+///
+/// Tier 3 uses block-level parity: up to 30 segments per block, 3 parity shards for a full
+/// block and fewer for a partial one. This means you need the valid segments in the block +
+/// the block parity to recover one segment.
 ///
-/// Tier 3 uses block-level parity: 30 segments per block, 3 parity shards for the entire block.
-/// This means you need ALL valid segments in the block + the block parity to recover one segment.
+/// Segments were padded to the block's longest one for encoding, which is also the length of
+/// each parity shard. Shorter valid segments are padded the same way here, and the recovered
+/// segment is cut back to `expected_size` when given, its length recorded in the manifest.
 ///
 /// # Parameters
 ///
-/// * `valid_segments` - Up to 30 valid segments from the block (missing segments = None)
-/// * `block_parity` - The 3 block-level parity shards
+/// * `valid_segments` - One slot per segment of the block, up to 30 (missing segments = None)
+/// * `block_parity` - The block-level parity shards, 3 for a full block
 /// * `target_index` - Index of the segment to recover (0-29 within the block)
+/// * `expected_size` - Optional size to truncate to (the unpadded segment length)
 ///
 /// # Returns
 ///
@@ -113,61 +394,110 @@ pub fn recover_segment_rs13(
 ///     vec![0u8; 32 * 1024 * 1024],
 /// ];
 ///
-/// let recovered = recover_segment_rs30_3(segments, parity, 5).unwrap();
+/// let recovered = recover_segment_rs30_3(segments, parity, 5, None).unwrap();
 /// ```
 pub fn recover_segment_rs30_3(
     valid_segments: Vec<Option<Vec<u8>>>,
     block_parity: Vec<Vec<u8>>,
     target_index: usize,
+    expected_size: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    recover_block(
+        valid_segments,
+        block_parity.into_iter().map(Some).collect(),
+        target_index,
+        expected_size,
+    )
+}
+
+/// Like [`recover_segment_rs30_3`], with lost parity shards as `None` too.
+pub fn recover_block(
+    valid_segments: Vec<Option<Vec<u8>>>,
+    block_parity: Vec<Option<Vec<u8>>>,
+    target_index: usize,
+    expected_size: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    recover_block_with(
+        &erasure::ReedSolomon,
+        valid_segments,
+        block_parity,
+        target_index,
+        expected_size,
+    )
+}
+
+/// [`recover_block`] with the erasure code the block was written with.
+pub fn recover_block_with(
+    coder: &dyn ErasureCoder,
+    valid_segments: Vec<Option<Vec<u8>>>,
+    block_parity: Vec<Option<Vec<u8>>>,
+    target_index: usize,
+    expected_size: Option<usize>,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    if valid_segments.len() != 30 {
-        return Err("Exactly 30 segment slots required for RS(30,3)".into());
+    let data_shards = valid_segments.len();
+    let parity_shards = block_parity.len();
+    if !(1..=BLOCK_SEGMENTS).contains(&data_shards) {
+        return Err("Between 1 and 30 segment slots required for a block".into());
     }
 
-    if block_parity.len() != 3 {
-        return Err("Exactly 3 block parity shards required for RS(30,3)".into());
+    if parity_shards == 0 {
+        return Err("At least one block parity shard required".into());
     }
 
-    if target_index >= 30 {
-        return Err("Target index must be 0-29".into());
+    if target_index >= data_shards {
+        return Err(format!("Target index must be 0-{}", data_shards - 1).into());
     }
 
-    // Count missing segments
+    // Count missing segments against the parity that is left
     let missing_count = valid_segments.iter().filter(|s| s.is_none()).count();
-    if missing_count > 3 {
+    let available_parity = block_parity.iter().flatten().count();
+    if missing_count > available_parity {
         return Err(format!(
-            "Too many missing segments: {} (max 3 for RS(30,3))",
-            missing_count
+            "Too many missing segments: {} (max {} for RS({},{}) with {} parity shards left)",
+            missing_count, parity_shards, data_shards, parity_shards, available_parity
         )
         .into());
     }
 
-    // Get shard size from first valid segment or parity
-    let shard_size = valid_segments
+    // every shard was padded to the parity length when encoding
+    let shard_size = block_parity
         .iter()
-        .find_map(|s| s.as_ref().map(|v| v.len()))
-        .or_else(|| block_parity.first().map(|p| p.len()))
-        .ok_or("Cannot determine shard size")?;
-
-    let mut decoder = ReedSolomonDecoder::new(30, 3, shard_size)?;
+        .flatten()
+        .map(|p| p.len())
+        .next()
+        .ok_or("No block parity shard available")?;
+    if !block_parity.iter().flatten().all(|p| p.len() == shard_size) {
+        return Err("All block parity shards must be the same size".into());
+    }
 
-    // Add valid data segments
-    for (idx, segment) in valid_segments.iter().enumerate() {
-        if let Some(data) = segment {
-            decoder.add_original_shard(idx, data)?;
-        }
+    // Pad valid data segments to the shard length
+    let mut padded = Vec::with_capacity(valid_segments.len());
+    for (idx, segment) in valid_segments.into_iter().enumerate() {
+        padded.push(match segment {
+            Some(mut data) => {
+                if data.len() > shard_size {
+                    return Err(format!("Segment {} is longer than the block's shards", idx).into());
+                }
+                data.resize(shard_size, 0);
+                Some(data)
+            }
+            None => None,
+        });
     }
+    let originals: Vec<Option<&[u8]>> = padded.iter().map(|s| s.as_deref()).collect();
+    let parity: Vec<Option<&[u8]>> = block_parity.iter().map(|s| s.as_deref()).collect();
 
-    // Add block parity shards
-    decoder.add_recovery_shard(0, &block_parity[0])?;
-    decoder.add_recovery_shard(1, &block_parity[1])?;
-    decoder.add_recovery_shard(2, &block_parity[2])?;
+    let mut recovered = coder
+        .decode(&originals, &parity, shard_size)?
+        .remove(&target_index)
+        .ok_or("Failed to restore target segment")?;
 
-    let result = decoder.decode()?;
-    let recovered = result
-        .restored_original(target_index)
-        .ok_or("Failed to restore target segment")?
-        .to_vec();
+    // Truncate if needed (padding removal)
+    if let Some(size) = expected_size
+        && recovered.len() > size
+    {
+        recovered.truncate(size);
+    }
 
     Ok(recovered)
 }
@@ -206,12 +536,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_recover_rs30_3_short_last_segment() {
+        // a partial block of 4 segments and 1 parity, the last segment short
+        let segments: Vec<Vec<u8>> = (0..4u8)
+            .map(|i| vec![i + 1; if i == 3 { 100 } else { 128 }])
+            .collect();
+        let padded: Vec<Vec<u8>> = segments
+            .iter()
+            .map(|segment| {
+                let mut padded = segment.clone();
+                padded.resize(128, 0);
+                padded
+            })
+            .collect();
+        let parity = crate::erasure::encode(&padded, 1).unwrap();
+
+        // the valid short segment is padded, the lost one is cut back
+        let mut valid: Vec<Option<Vec<u8>>> = segments.iter().cloned().map(Some).collect();
+        valid[0] = None;
+        let recovered = recover_segment_rs30_3(valid, parity.clone(), 0, Some(128)).unwrap();
+        assert_eq!(recovered, segments[0]);
+
+        let mut valid: Vec<Option<Vec<u8>>> = segments.iter().cloned().map(Some).collect();
+        valid[3] = None;
+        let recovered = recover_segment_rs30_3(valid, parity, 3, Some(100)).unwrap();
+        assert_eq!(recovered, segments[3]);
+    }
+
     #[test]
     fn test_recover_rs30_3_too_many_missing() {
         let segments = vec![None; 30]; // All missing
         let parity = vec![vec![0u8; 1024], vec![0u8; 1024], vec![0u8; 1024]];
 
-        let result = recover_segment_rs30_3(segments, parity, 0);
+        let result = recover_segment_rs30_3(segments, parity, 0, None);
         assert!(result.is_err());
         assert!(
             result
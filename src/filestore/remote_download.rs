@@ -0,0 +1,265 @@
+//! Verified downloads from a remote `blockframe serve` instance.
+//!
+//! The server is trusted for nothing but bytes. Every data shard is checked
+//! against the file's manifest, whose signature is checked too when the source
+//! has a verifier. A shard that doesn't match is downloaded again on its own
+//! and, failing that, rebuilt here from the server's parity. The whole output
+//! is then checked against the manifest's `original_hash`.
+
+use blake3::Hasher;
+use std::io::{Read, Write};
+
+use super::recovery::{ShardKind, data_shard_kinds, expected_shard, recover_shard};
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::mount::source::{RemoteSource, SegmentSource};
+use crate::utils::blake3_hash_bytes;
+
+/// How the bytes are fetched. Either way each shard is checked on arrival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownloadMode {
+    /// One stream of the file as the server assembles it, with the shard
+    /// endpoints only used for shards that arrive damaged.
+    #[default]
+    Assembled,
+    /// Every data shard on its own, so nothing goes through the server's
+    /// own recovery.
+    Segments,
+}
+
+/// What a download took.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadReport {
+    /// BLAKE3 hash of the bytes written, the manifest's `original_hash`.
+    pub hash: String,
+    pub bytes: u64,
+    /// Data shards in the file.
+    pub shards: usize,
+    /// Shards the assembled stream got wrong that were fetched again on their own.
+    pub refetched: usize,
+    /// Shards rebuilt here from parity.
+    pub recovered: Vec<ShardKind>,
+}
+
+pub struct RemoteDownloader {
+    source: RemoteSource,
+}
+
+impl RemoteDownloader {
+    /// Downloads from the archive served by `source`. Give the source a
+    /// verifier to have the manifest's signature checked as well.
+    pub fn new(source: RemoteSource) -> Self {
+        Self { source }
+    }
+
+    /// Writes the original bytes of `filename` to `out`, shard by shard in
+    /// file order.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the manifest can't be fetched or verified, if a shard can be
+    /// neither downloaded intact nor recovered, or if the output doesn't hash
+    /// to the manifest's `original_hash`. Bytes written before the error have
+    /// already reached `out`.
+    pub fn download_to<W: Write>(
+        &self,
+        filename: &str,
+        mode: DownloadMode,
+        mut out: W,
+    ) -> Result<DownloadReport, Box<dyn std::error::Error>> {
+        let manifest = self.source.get_manifest(filename)?;
+        let kinds = data_shard_kinds(&manifest)?;
+        let mut stream = match mode {
+            DownloadMode::Assembled => Some(self.source.open_download(filename)?),
+            DownloadMode::Segments => None,
+        };
+        let mut report = DownloadReport {
+            shards: kinds.len(),
+            ..Default::default()
+        };
+        let mut hasher = Hasher::new();
+
+        for kind in kinds {
+            let (hash, len) = expected_shard(&manifest, kind)?;
+            let streamed = match stream.as_mut() {
+                Some(reader) => {
+                    let mut bytes = vec![0u8; len];
+                    match reader.read_exact(&mut bytes) {
+                        Ok(()) => Some(bytes),
+                        Err(e) => {
+                            tracing::warn!(
+                                "REMOTE GET | download of {} broke off at {:?}: {}, fetching the rest by shard",
+                                filename,
+                                kind,
+                                e
+                            );
+                            stream = None;
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+            let bytes = match streamed {
+                Some(bytes) if blake3_hash_bytes(&bytes)? == hash => bytes,
+                streamed => {
+                    if streamed.is_some() {
+                        tracing::warn!(
+                            "REMOTE GET | {:?} of {} arrived damaged, fetching it on its own",
+                            kind,
+                            filename
+                        );
+                        report.refetched += 1;
+                    }
+                    self.fetch_shard(filename, &manifest, kind, &hash, &mut report)?
+                }
+            };
+            hasher.update(&bytes);
+            out.write_all(&bytes)?;
+            report.bytes += bytes.len() as u64;
+        }
+        out.flush()?;
+
+        report.hash = hasher.finalize().to_string();
+        if report.hash != manifest.original_hash {
+            return Err(format!("downloaded {} does not match its original hash", filename).into());
+        }
+        Ok(report)
+    }
+
+    /// Data shard `kind` from its own endpoint, or recovered from parity when
+    /// it can't be downloaded intact.
+    fn fetch_shard(
+        &self,
+        filename: &str,
+        manifest: &ManifestFile,
+        kind: ShardKind,
+        hash: &str,
+        report: &mut DownloadReport,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (segment_id, block_id) = match kind {
+            ShardKind::Tiny => (0, None),
+            ShardKind::Segment(idx) => (idx, None),
+            ShardKind::Block(block_id, seg_idx) => (seg_idx, Some(block_id)),
+        };
+        let read = match kind {
+            ShardKind::Tiny => self.source.read_data(filename),
+            ShardKind::Segment(idx) => self.source.read_segment(filename, idx),
+            ShardKind::Block(block_id, seg_idx) => {
+                self.source.read_block_segment(filename, block_id, seg_idx)
+            }
+        };
+        match read {
+            Ok(bytes) if blake3_hash_bytes(&bytes)? == hash => return Ok(bytes),
+            Ok(_) => tracing::warn!(
+                "REMOTE GET | {:?} of {} doesn't match its manifest, recovering from parity",
+                kind,
+                filename
+            ),
+            Err(e) => tracing::warn!(
+                "REMOTE GET | {:?} of {}: {}, recovering from parity",
+                kind,
+                filename,
+                e
+            ),
+        }
+
+        let recovered = recover_shard(
+            manifest,
+            kind,
+            |seg_idx| {
+                let block_id = block_id?;
+                self.source
+                    .read_block_segment(filename, block_id, seg_idx)
+                    .ok()
+            },
+            |parity_id| {
+                self.source
+                    .read_parity(filename, segment_id, parity_id, block_id)
+                    .ok()
+            },
+        )?;
+        tracing::info!("REMOTE GET | recovered {:?} of {}", kind, filename);
+        report.recovered.push(kind);
+        Ok(recovered)
+    }
+}
+
+#[cfg(all(test, feature = "serve"))]
+mod tests {
+    use super::*;
+    use crate::serve::{ServeOptions, bind::Bind, run_server_until};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_downloads_verify_and_recover_client_side() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("archive_directory");
+        let source = temp_dir.path().join("remote.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let chunked = crate::chunker::Chunker::builder()
+            .archive_dir(archive.clone())
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap()
+            .commit_as(&source, Some(2))
+            .unwrap();
+        // damaged on the server
+        let segment = chunked.file_dir.join("segments/segment_1.dat");
+        let mut bytes = fs::read(&segment).unwrap();
+        bytes[10] ^= 0xff;
+        fs::write(&segment, bytes).unwrap();
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut options = ServeOptions::new(archive, port);
+        options.bind = Some(Bind::Tcp(format!("127.0.0.1:{}", port)));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            run_server_until(options, async {
+                let _ = stopped.await;
+            })
+            .await
+            .map_err(|e| e.to_string())
+        });
+
+        let url = format!("http://127.0.0.1:{}", port);
+        let (assembled, segments) = tokio::task::spawn_blocking(move || {
+            let downloader = RemoteDownloader::new(RemoteSource::new(url));
+            // the server may still be starting up
+            let mut assembled = Vec::new();
+            let report = (0..50)
+                .find_map(|_| {
+                    assembled.clear();
+                    let report = downloader
+                        .download_to("remote.bin", DownloadMode::Assembled, &mut assembled)
+                        .ok();
+                    if report.is_none() {
+                        std::thread::sleep(std::time::Duration::from_millis(100));
+                    }
+                    report
+                })
+                .unwrap();
+            assert_eq!(assembled, data);
+            assert_eq!(report.shards, 4);
+
+            let mut segments = Vec::new();
+            let report = downloader
+                .download_to("remote.bin", DownloadMode::Segments, &mut segments)
+                .unwrap();
+            assert_eq!(report.recovered, [ShardKind::Segment(1)]);
+            assert_eq!(report.bytes, data.len() as u64);
+            (assembled, segments)
+        })
+        .await
+        .unwrap();
+        assert_eq!(assembled, segments);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+//! Health checks against a remote `blockframe serve` instance.
+//!
+//! Only the manifest and the `/hash` endpoints are used, so checking a remote
+//! archive moves 64 hex characters per shard instead of the shards themselves.
+//! Status classification uses the same rules as the local checks. Because the
+//! server returns real hashes, tier 3 segments and parity are verified rather
+//! than only tested for presence.
+
+use std::collections::BTreeMap;
+
+use super::health::{classify_block, classify_blocked, classify_segmented, classify_tiny};
+use crate::filestore::models::{BatchHealthReport, HealthReport, HealthStatus};
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::mount::source::{RemoteSource, SegmentSource};
+
+pub struct RemoteHealthChecker {
+    source: RemoteSource,
+}
+
+impl RemoteHealthChecker {
+    /// Checks the archive served by `source`. Give the source a verifier to have
+    /// manifest signatures checked as well.
+    pub fn new(source: RemoteSource) -> Self {
+        Self { source }
+    }
+
+    /// Remote equivalent of [`FileStore::batch_health_check`](super::FileStore::batch_health_check).
+    pub fn batch_health_check(&self) -> Result<BatchHealthReport, Box<dyn std::error::Error>> {
+        let files = self.source.list_files()?;
+        tracing::info!(
+            "REMOTE HEALTH | checking {} files on {}",
+            files.len(),
+            self.source.base_url()
+        );
+
+        let mut report = BatchHealthReport {
+            total_files: files.len(),
+            healthy: 0,
+            degraded: 0,
+            recoverable: 0,
+            unrecoverable: 0,
+            reports: Vec::with_capacity(files.len()),
+        };
+
+        for filename in files {
+            let file_report = self.health_check(&filename)?;
+            match file_report.status {
+                HealthStatus::Healthy => report.healthy += 1,
+                HealthStatus::Degraded => report.degraded += 1,
+                HealthStatus::Recoverable => report.recoverable += 1,
+                HealthStatus::Unrecoverable => report.unrecoverable += 1,
+            }
+            report.reports.push((filename, file_report));
+        }
+
+        Ok(report)
+    }
+
+    /// Checks a single file. A manifest that can't be fetched or fails its
+    /// signature is reported as unrecoverable, network errors while hashing
+    /// shards are returned as errors.
+    pub fn health_check(&self, filename: &str) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        let manifest = match self.source.get_manifest(filename) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                tracing::error!("REMOTE HEALTH | manifest for {}: {}", filename, e);
+                return Ok(HealthReport {
+                    status: HealthStatus::Unrecoverable,
+                    missing_data: Vec::new(),
+                    missing_parity: Vec::new(),
+                    corrupt_segments: Vec::new(),
+                    recoverable: false,
+                    details: e.to_string(),
+                });
+            }
+        };
+
+        match manifest.tier {
+            1 => self.check_tiny(filename, &manifest),
+            2 => self.check_segment(filename, &manifest),
+            3 => self.check_block(filename, &manifest),
+            _ => Err("unknown file".into()),
+        }
+    }
+
+    fn check_tiny(
+        &self,
+        filename: &str,
+        manifest: &ManifestFile,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        let mut missing_data = Vec::new();
+        let mut missing_parity = Vec::new();
+        let mut corrupt_segments = Vec::new();
+
+        let data_valid = match self.source.data_hash(filename)? {
+            Some(hash) if hash == manifest.original_hash => true,
+            Some(_) => {
+                corrupt_segments.push("data.dat".to_string());
+                false
+            }
+            None => {
+                missing_data.push("data.dat".to_string());
+                false
+            }
+        };
+
+        // leaves are [data, parity_0, parity_1, parity_2]
+        let mut parity_count = 0;
+        for i in 0..3 {
+            match self.source.parity_hash(filename, 0, i, None)? {
+                Some(hash) => {
+                    parity_count += 1;
+                    if manifest
+                        .merkle_tree
+                        .leaves
+                        .get(&(i as i32 + 1))
+                        .is_some_and(|expected| *expected != hash)
+                    {
+                        missing_parity.push(format!("parity_{}.dat (CORRUPT)", i));
+                    }
+                }
+                None => missing_parity.push(format!("parity_{}.dat", i)),
+            }
+        }
+
+        let (status, recoverable) = classify_tiny(data_valid, parity_count);
+        let details = format!(
+            "Data: {}, Parity: {}/3",
+            if data_valid {
+                "valid"
+            } else {
+                "corrupt/missing"
+            },
+            parity_count
+        );
+
+        Ok(HealthReport {
+            status,
+            missing_data,
+            missing_parity,
+            corrupt_segments,
+            recoverable,
+            details,
+        })
+    }
+
+    fn check_segment(
+        &self,
+        filename: &str,
+        manifest: &ManifestFile,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        let parity_shards = manifest.erasure_coding.parity_shards.max(0) as usize;
+        let segments: BTreeMap<_, _> = manifest.merkle_tree.segments.iter().collect();
+
+        let mut missing_data = Vec::new();
+        let mut missing_parity = Vec::new();
+        let mut corrupt_segments = Vec::new();
+        let mut healthy_segments = 0;
+
+        for (idx, segment_info) in &segments {
+            match self.source.segment_hash(filename, **idx)? {
+                Some(hash) if hash == segment_info.data => healthy_segments += 1,
+                Some(_) => corrupt_segments.push(format!("segment_{}.dat", idx)),
+                None => {
+                    missing_data.push(format!("segment_{}.dat", idx));
+                    continue;
+                }
+            }
+
+            for parity_idx in 0..parity_shards {
+                match self.source.parity_hash(filename, **idx, parity_idx, None)? {
+                    Some(hash) => {
+                        if segment_info
+                            .parity
+                            .get(parity_idx)
+                            .is_some_and(|expected| *expected != hash)
+                        {
+                            missing_parity.push(format!(
+                                "segment_{}_parity_{}.dat (CORRUPT)",
+                                idx, parity_idx
+                            ));
+                        }
+                    }
+                    None => {
+                        missing_parity.push(format!("segment_{}_parity_{}.dat", idx, parity_idx))
+                    }
+                }
+            }
+        }
+
+        let missing_count = missing_data.len();
+        let corrupt_count = corrupt_segments.len();
+        let (status, recoverable) = classify_segmented(
+            missing_count + corrupt_count,
+            parity_shards,
+            missing_parity.is_empty(),
+        );
+        let details = format!(
+            "{}/{} segments healthy, {} missing, {} corrupt",
+            healthy_segments,
+            segments.len(),
+            missing_count,
+            corrupt_count
+        );
+
+        Ok(HealthReport {
+            status,
+            missing_data,
+            missing_parity,
+            corrupt_segments,
+            recoverable,
+            details,
+        })
+    }
+
+    fn check_block(
+        &self,
+        filename: &str,
+        manifest: &ManifestFile,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        if manifest.merkle_tree.blocks.is_empty() {
+            return Err(format!("manifest for {} has no block hashes", filename).into());
+        }
+        let blocks: BTreeMap<_, _> = manifest.merkle_tree.blocks.iter().collect();
+
+        let mut missing_data = Vec::new();
+        let mut missing_parity = Vec::new();
+        let mut corrupt_segments = Vec::new();
+        let mut healthy_blocks = 0;
+        let mut recoverable_blocks = 0;
+        let mut unrecoverable_blocks = 0;
+
+        for (block_id, block) in &blocks {
+            let block_id = **block_id;
+            let (data_shards, parity_shards) = block.geometry();
+            let mut missing_in_block = 0;
+            for (seg_idx, expected) in block.segments.iter().take(data_shards).enumerate() {
+                match self
+                    .source
+                    .block_segment_hash(filename, block_id, seg_idx)?
+                {
+                    Some(hash) if hash == *expected => {}
+                    Some(_) => {
+                        corrupt_segments
+                            .push(format!("block_{}/segment_{}.dat", block_id, seg_idx));
+                        missing_in_block += 1;
+                    }
+                    None => {
+                        missing_data.push(format!("block_{}/segment_{}.dat", block_id, seg_idx));
+                        missing_in_block += 1;
+                    }
+                }
+            }
+
+            // only parity that matches its hash can be used for recovery
+            let mut parity_count = 0;
+            for parity_idx in 0..parity_shards {
+                match self
+                    .source
+                    .parity_hash(filename, 0, parity_idx, Some(block_id))?
+                {
+                    Some(hash) if block.parity.get(parity_idx) == Some(&hash) => parity_count += 1,
+                    Some(_) => missing_parity.push(format!(
+                        "block_{}/block_parity_{}.dat (CORRUPT)",
+                        block_id, parity_idx
+                    )),
+                    None => missing_parity.push(format!(
+                        "block_{}/block_parity_{}.dat",
+                        block_id, parity_idx
+                    )),
+                }
+            }
+
+            let mut local_lost = 0;
+            for (local_idx, expected) in block.local_parity.iter().enumerate() {
+                let parity_idx = parity_shards + local_idx;
+                let state =
+                    match self
+                        .source
+                        .parity_hash(filename, 0, parity_idx, Some(block_id))?
+                    {
+                        Some(hash) if hash == *expected => continue,
+                        Some(_) => " (CORRUPT)",
+                        None => "",
+                    };
+                missing_parity.push(format!(
+                    "block_{}/block_parity_{}.dat{}",
+                    block_id, parity_idx, state
+                ));
+                local_lost += 1;
+            }
+
+            match classify_block(missing_in_block, parity_count, parity_shards) {
+                HealthStatus::Healthy if local_lost > 0 => recoverable_blocks += 1,
+                HealthStatus::Healthy => healthy_blocks += 1,
+                HealthStatus::Recoverable => recoverable_blocks += 1,
+                _ => unrecoverable_blocks += 1,
+            }
+        }
+
+        let (status, recoverable) = classify_blocked(
+            blocks.len(),
+            healthy_blocks,
+            recoverable_blocks,
+            unrecoverable_blocks,
+            missing_parity.is_empty(),
+        );
+        let details = format!(
+            "{}/{} blocks healthy, {} recoverable, {} unrecoverable",
+            healthy_blocks,
+            blocks.len(),
+            recoverable_blocks,
+            unrecoverable_blocks
+        );
+
+        Ok(HealthReport {
+            status,
+            missing_data,
+            missing_parity,
+            corrupt_segments,
+            recoverable,
+            details,
+        })
+    }
+}
@@ -0,0 +1,349 @@
+//! Streaming restores.
+//!
+//! `reconstruct_to` walks the data shards of a file in order and writes them to
+//! any `Write`, so a restore can go straight into a tar stream, an upload or an
+//! HTTP body without staging the whole file. Only one shard is held in memory at
+//! a time, except when a tier 3 segment has to be rebuilt from its block.
+//!
+//! `read_range` does the same for a slice of the file, touching only the shards
+//! the slice overlaps. Both read through a [`FileReader`](super::reader::FileReader).
+//!
+//! Every shard is checked against its manifest hash before it is written. A
+//! missing or corrupt shard is recovered from parity in memory; nothing on disk
+//! is changed, that is what `repair` is for.
+
+use blake3::Hasher;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use super::FileStore;
+use super::recovery::{
+    self, ShardKind, data_shard_kinds, expected_shard, is_zero_shard, zeros_hash,
+};
+use crate::filestore::models::File;
+use crate::utils::blake3_hash_bytes;
+
+/// One data shard of an archived file: `data.dat` on tier 1, a segment on
+/// tiers 2 and 3. Listed in file order by [`FileStore::data_shards`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataShard {
+    pub kind: ShardKind,
+    pub path: PathBuf,
+    /// BLAKE3 hash the manifest records for the shard.
+    pub hash: String,
+    /// Offset of the shard's first byte in the original file.
+    pub offset: u64,
+    /// Bytes of the original file held by this shard.
+    pub len: usize,
+    /// All zeros by the manifest, which records the hash of zeros for it,
+    /// read without touching the disk.
+    pub zero: bool,
+}
+
+/// The data a parity shard protects: the whole file on tier 1, one segment on
+/// tier 2, one block of segments on tier 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParitySet {
+    File,
+    Segment(usize),
+    Block(usize),
+}
+
+/// One parity shard of an archived file. Listed by set, then parity id, by
+/// [`FileStore::parity_shards`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParityShard {
+    pub set: ParitySet,
+    pub parity_id: usize,
+    pub path: PathBuf,
+    /// BLAKE3 hash the manifest records for the shard. Tier 1 manifests don't
+    /// record parity hashes.
+    pub hash: Option<String>,
+}
+
+impl FileStore {
+    /// Streams the original bytes of `file_obj` into `out` and returns their
+    /// BLAKE3 hash.
+    ///
+    /// Shards that are missing or fail their hash check are recovered from
+    /// parity on the fly.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a shard cannot be recovered, or if the restored bytes do not hash
+    /// to the manifest's `original_hash`. Bytes written before the error have
+    /// already reached `out`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::filestore::FileStore;
+    /// use std::path::Path;
+    ///
+    /// let store = FileStore::new(Path::new("archive_directory")).unwrap();
+    /// let file = store.find(&"movie.mkv".to_string()).unwrap();
+    /// store.reconstruct_to(&file, std::io::stdout().lock()).unwrap();
+    /// ```
+    pub fn reconstruct_to<W: Write>(
+        &self,
+        file_obj: &File,
+        mut out: W,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut hasher = Hasher::new();
+        let mut reader = self.open_reader(file_obj)?;
+        io::copy(&mut reader, &mut HashingWriter::new(&mut out, &mut hasher))?;
+        out.flush()?;
+
+        let hash = hasher.finalize().to_string();
+        if hash != file_obj.manifest.original_hash {
+            return Err(format!(
+                "restored {} does not match its original hash",
+                file_obj.file_name
+            )
+            .into());
+        }
+        Ok(hash)
+    }
+
+    /// Streams bytes `range` of `file_obj` into `out`, reading only the data
+    /// shards that overlap it. Damaged shards are recovered from parity as in
+    /// [`FileStore::reconstruct_to`]; each shard is checked against its own
+    /// hash since the whole-file hash can't be. Returns the bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the range reaches past the end of the file or a shard cannot
+    /// be recovered.
+    pub fn read_range<W: Write>(
+        &self,
+        file_obj: &File,
+        range: Range<u64>,
+        mut out: W,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let size = file_obj.manifest.size.max(0) as u64;
+        if range.start > range.end || range.end > size {
+            return Err(format!(
+                "range {}..{} is outside {} ({} bytes)",
+                range.start, range.end, file_obj.file_name, size
+            )
+            .into());
+        }
+
+        let mut reader = self.open_reader(file_obj)?;
+        reader.seek(SeekFrom::Start(range.start))?;
+        let written = io::copy(&mut reader.take(range.end - range.start), &mut out)?;
+        out.flush()?;
+        Ok(written)
+    }
+
+    /// The bytes of `shard`, checked against its hash and recovered from
+    /// parity in memory when missing or corrupt. With `heal` a recovered shard
+    /// is also written back over the damaged one.
+    pub(super) fn read_shard(
+        &self,
+        file_obj: &File,
+        shard: &DataShard,
+        heal: bool,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if shard.zero {
+            return Ok(vec![0; shard.len]);
+        }
+        let mut bytes = match fs::read(&shard.path) {
+            Ok(bytes) if blake3_hash_bytes(&bytes)? == shard.hash => bytes,
+            _ => {
+                tracing::warn!(
+                    "RESTORE | {:?} is missing or corrupt, recovering from parity",
+                    shard.path
+                );
+                let recovered = self.recover_shard(file_obj, shard.kind)?;
+                if heal {
+                    self.write_archive_file(&shard.path, &recovered)?;
+                    tracing::info!("RESTORE | rewrote {:?} from parity", shard.path);
+                }
+                recovered
+            }
+        };
+        bytes.truncate(shard.len);
+        Ok(bytes)
+    }
+
+    /// Data shards of a file in file order, from the manifest rather than a
+    /// directory listing. Nothing is read; see [`FileStore::open_reader`] to
+    /// read through them.
+    pub fn data_shards(
+        &self,
+        file_obj: &File,
+    ) -> Result<Vec<DataShard>, Box<dyn std::error::Error>> {
+        let kinds = data_shard_kinds(&file_obj.manifest)?;
+
+        let mut offset = 0u64;
+        // shards of one file are mostly the same length
+        let mut zero_hashes = HashMap::new();
+        kinds
+            .into_iter()
+            .map(|kind| {
+                let (hash, len) = expected_shard(&file_obj.manifest, kind)?;
+                let start = offset;
+                offset += len as u64;
+                // a zero flag whose hash disagrees, from a bug or an edited
+                // manifest, would hide real data, so that shard is read as any other
+                let zero = is_zero_shard(&file_obj.manifest, kind) && {
+                    let zeros = zero_hashes.entry(len).or_insert_with(|| zeros_hash(len));
+                    if *zeros != hash {
+                        tracing::warn!(
+                            "RESTORE | {} marks {:?} as zeros, but not with their hash",
+                            file_obj.file_name,
+                            kind
+                        );
+                    }
+                    *zeros == hash
+                };
+                let path = match kind {
+                    ShardKind::Tiny => self.get_data_path(file_obj)?,
+                    ShardKind::Segment(idx) => self.get_segment_path(file_obj, idx)?,
+                    ShardKind::Block(block_id, seg_idx) => {
+                        self.get_block_segment_path(file_obj, block_id, seg_idx)?
+                    }
+                };
+                Ok(DataShard {
+                    kind,
+                    path,
+                    hash,
+                    offset: start,
+                    len,
+                    zero,
+                })
+            })
+            .collect()
+    }
+
+    /// Parity shards of a file, from the manifest rather than a directory
+    /// listing. Nothing is read or checked, so listed shards may be missing.
+    pub fn parity_shards(
+        &self,
+        file_obj: &File,
+    ) -> Result<Vec<ParityShard>, Box<dyn std::error::Error>> {
+        let tree = &file_obj.manifest.merkle_tree;
+        let mut shards = Vec::new();
+        match file_obj.manifest.tier {
+            1 => {
+                let parity_count = file_obj.manifest.erasure_coding.parity_shards.max(0) as usize;
+                for parity_id in 0..parity_count {
+                    shards.push(ParityShard {
+                        set: ParitySet::File,
+                        parity_id,
+                        path: self.get_parity_path_t1(file_obj, parity_id)?,
+                        hash: None,
+                    });
+                }
+            }
+            2 => {
+                for segment_id in 0..tree.segments.len() {
+                    let hashes = tree
+                        .segments
+                        .get(&segment_id)
+                        .ok_or_else(|| format!("manifest is missing segment {}", segment_id))?;
+                    for (parity_id, hash) in hashes.parity.iter().enumerate() {
+                        shards.push(ParityShard {
+                            set: ParitySet::Segment(segment_id),
+                            parity_id,
+                            path: self.get_parity_path_t2(file_obj, segment_id, parity_id)?,
+                            hash: Some(hash.clone()),
+                        });
+                    }
+                }
+            }
+            3 => {
+                for block_id in 0..tree.blocks.len() {
+                    let block = tree
+                        .blocks
+                        .get(&block_id)
+                        .ok_or_else(|| format!("manifest is missing block {}", block_id))?;
+                    // local parity, if any, is numbered after the global shards
+                    let (_, global) = block.geometry();
+                    let hashes = block
+                        .parity
+                        .iter()
+                        .enumerate()
+                        .chain((global..).zip(&block.local_parity));
+                    for (parity_id, hash) in hashes {
+                        shards.push(ParityShard {
+                            set: ParitySet::Block(block_id),
+                            parity_id,
+                            path: self.get_parity_path_t3(file_obj, block_id, parity_id)?,
+                            hash: Some(hash.clone()),
+                        });
+                    }
+                }
+            }
+            _ => return Err("unknown tier".into()),
+        }
+        Ok(shards)
+    }
+
+    /// Rebuilds one data shard in memory from the archive's parity, checked
+    /// against the manifest, see [`recovery::recover_shard`].
+    pub(super) fn recover_shard(
+        &self,
+        file_obj: &File,
+        kind: ShardKind,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        recovery::recover_shard(
+            &file_obj.manifest,
+            kind,
+            |seg_idx| match kind {
+                ShardKind::Block(block_id, _) => self
+                    .get_block_segment_path(file_obj, block_id, seg_idx)
+                    .and_then(fs::read)
+                    .ok(),
+                _ => None,
+            },
+            |parity_id| {
+                self.parity_path(file_obj, kind, parity_id)
+                    .and_then(fs::read)
+                    .ok()
+            },
+        )
+    }
+
+    /// Path of parity shard `parity_id` in the parity set of `kind`.
+    pub(super) fn parity_path(
+        &self,
+        file_obj: &File,
+        kind: ShardKind,
+        parity_id: usize,
+    ) -> Result<PathBuf, std::io::Error> {
+        match kind {
+            ShardKind::Tiny => self.get_parity_path_t1(file_obj, parity_id),
+            ShardKind::Segment(idx) => self.get_parity_path_t2(file_obj, idx, parity_id),
+            ShardKind::Block(block_id, _) => self.get_parity_path_t3(file_obj, block_id, parity_id),
+        }
+    }
+}
+
+/// Passes writes on to `out` and hashes them.
+struct HashingWriter<'a, W> {
+    out: W,
+    hasher: &'a mut Hasher,
+}
+
+impl<'a, W: Write> HashingWriter<'a, W> {
+    fn new(out: W, hasher: &'a mut Hasher) -> Self {
+        Self { out, hasher }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
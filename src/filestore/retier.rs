@@ -0,0 +1,91 @@
+//! Re-encoding an archived file under a different tier.
+//!
+//! The original bytes are restored with `reconstruct_to`, which checks them
+//! against the manifest's `original_hash`, and committed again with the requested tier. The
+//! old directory is kept aside until the new commit succeeds and is put back if
+//! it fails, so a failed retier leaves the archive as it was.
+
+use std::fs;
+use std::path::Path;
+
+use super::{FileStore, remove_entry_dir, remove_parity_dir};
+use crate::audit::{AuditEntry, AuditOp};
+use crate::chunker::{ChunkedFile, Chunker};
+use crate::filestore::models::File;
+
+impl FileStore {
+    /// Re-encodes `file_obj` as `tier` using `chunker`, whose `archive_dir` must
+    /// be this store.
+    ///
+    /// # Errors
+    ///
+    /// Fails without touching the archive if the file is already at `tier`, is an
+    /// alias, or its original bytes cannot be restored.
+    pub fn retier(
+        &self,
+        file_obj: &File,
+        chunker: &Chunker,
+        tier: u8,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        self.ensure_writable("retier")?;
+        if let Some(original) = &file_obj.alias_of {
+            return Err(format!(
+                "{} is an alias of {}, retier that instead",
+                file_obj.file_name, original
+            )
+            .into());
+        }
+        let from = file_obj.manifest.tier;
+        if from == tier {
+            return Err(format!("{} is already tier {}", file_obj.file_name, tier).into());
+        }
+        let file_dir = Path::new(&file_obj.file_data.path)
+            .parent()
+            .ok_or("No parent directory found")?
+            .to_path_buf();
+
+        // hidden work dir inside the archive so the renames below stay on one filesystem
+        let work = tempfile::Builder::new()
+            .prefix(".retier-")
+            .tempdir_in(&self.store_path)?;
+        let original = work.path().join(&file_obj.file_name);
+        tracing::info!(
+            "RETIER | restoring {} from tier {} shards",
+            file_obj.file_name,
+            from
+        );
+        let hash = self.reconstruct_to(file_obj, fs::File::create(&original)?)?;
+
+        let previous = work.path().join("previous");
+        fs::rename(&file_dir, &previous)?;
+        let committed = chunker.encode_as(&original, Some(tier), &hash);
+        self.invalidate();
+        let chunked = match committed {
+            Ok(chunked) => chunked,
+            Err(e) => {
+                tracing::error!("RETIER | commit as tier {} failed: {}", tier, e);
+                if file_dir.exists() {
+                    remove_entry_dir(&file_dir)?;
+                }
+                fs::rename(&previous, &file_dir)?;
+                return Err(e);
+            }
+        };
+
+        // the old shards go with `work`, but their parity may live elsewhere
+        remove_parity_dir(&previous)?;
+
+        self.audit(
+            &AuditEntry::new(AuditOp::Retier, &file_obj.file_name)
+                .hash(&chunked.file_hash)
+                .details(format!("tier {} -> {}", from, tier)),
+        )?;
+        tracing::info!(
+            "RETIER | {} moved from tier {} to tier {}",
+            file_obj.file_name,
+            from,
+            tier
+        );
+        Ok(chunked)
+    }
+}
@@ -0,0 +1,145 @@
+//! Sampled verification for archives too large to rehash often.
+//!
+//! A sampled check reads a few random data segments of every file, each with
+//! the parity protecting it (see [`FileStore::health_check_segment`]), instead
+//! of every shard. It can't prove a file healthy, but run often it finds
+//! spreading damage early, while a full check runs on a longer schedule.
+//!
+//! The segments picked follow from a seed and each file's hash, so a run can
+//! be repeated by passing the seed it logged. Sampled outcomes aren't added to
+//! the health history, as a sample missing a known bad segment would read as
+//! the file having recovered.
+
+use rand::{SeedableRng, rngs::StdRng, seq::index};
+
+use super::FileStore;
+use super::models::{BatchHealthReport, File, HealthReport, HealthStatus};
+
+impl FileStore {
+    /// Checks `per_file` random data segments of every file, all of a file
+    /// with fewer. Aliases are skipped, as in
+    /// [`FileStore::batch_health_check`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::filestore::FileStore;
+    /// use std::path::Path;
+    ///
+    /// let store = FileStore::new(Path::new("archive_directory"))?;
+    /// let seed = rand::random();
+    /// let report = store.sample_health_check(4, seed)?;
+    /// println!("seed {}: {:?}", seed, report.worst_status());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn sample_health_check(
+        &self,
+        per_file: usize,
+        seed: u64,
+    ) -> Result<BatchHealthReport, Box<dyn std::error::Error>> {
+        tracing::info!(
+            "SAMPLE | checking {} segments per file, seed {}",
+            per_file,
+            seed
+        );
+        let mut batch = BatchHealthReport {
+            total_files: 0,
+            healthy: 0,
+            degraded: 0,
+            recoverable: 0,
+            unrecoverable: 0,
+            reports: Vec::new(),
+        };
+        for file in self
+            .get_all()?
+            .iter()
+            .filter(|file| file.alias_of.is_none())
+        {
+            let report = self.sample_file(file, per_file, seed)?;
+            match report.status {
+                HealthStatus::Healthy => batch.healthy += 1,
+                HealthStatus::Degraded => batch.degraded += 1,
+                HealthStatus::Recoverable => batch.recoverable += 1,
+                HealthStatus::Unrecoverable => batch.unrecoverable += 1,
+            }
+            batch.total_files += 1;
+            batch.reports.push((file.file_name.clone(), report));
+        }
+        Ok(batch)
+    }
+
+    /// The segments of `file` picked for `seed`, and their checks merged into
+    /// one report.
+    fn sample_file(
+        &self,
+        file: &File,
+        per_file: usize,
+        seed: u64,
+    ) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        let segments = segment_indexes(file);
+        let hash_bits = u64::from_str_radix(file.file_data.hash.get(..16).unwrap_or("0"), 16)
+            .unwrap_or_default();
+        let mut rng = StdRng::seed_from_u64(seed ^ hash_bits);
+        let mut picked: Vec<usize> =
+            index::sample(&mut rng, segments.len(), per_file.min(segments.len()))
+                .into_iter()
+                .map(|i| segments[i])
+                .collect();
+        picked.sort_unstable();
+        tracing::debug!("SAMPLE | {} segments {:?}", file.file_name, picked);
+
+        let mut merged = HealthReport {
+            status: HealthStatus::Healthy,
+            missing_data: Vec::new(),
+            missing_parity: Vec::new(),
+            corrupt_segments: Vec::new(),
+            recoverable: true,
+            details: String::new(),
+        };
+        let mut worst_details = String::new();
+        for idx in &picked {
+            let report = self.health_check_segment(file, *idx)?;
+            if report.status > merged.status {
+                merged.status = report.status;
+                worst_details = report.details;
+            }
+            merged.recoverable &= report.recoverable;
+            // segments of one tier 3 block share its parity, list it once
+            for (from, into) in [
+                (report.missing_data, &mut merged.missing_data),
+                (report.missing_parity, &mut merged.missing_parity),
+                (report.corrupt_segments, &mut merged.corrupt_segments),
+            ] {
+                for shard in from {
+                    if !into.contains(&shard) {
+                        into.push(shard);
+                    }
+                }
+            }
+        }
+        merged.details = format!(
+            "sampled {} of {} segments {:?}",
+            picked.len(),
+            segments.len(),
+            picked
+        );
+        if !worst_details.is_empty() {
+            merged.details = format!("{}, {}", merged.details, worst_details);
+        }
+        Ok(merged)
+    }
+}
+
+/// Every index [`FileStore::health_check_segment`] accepts for `file`.
+fn segment_indexes(file: &File) -> Vec<usize> {
+    let tree = &file.manifest.merkle_tree;
+    match file.manifest.tier {
+        2 => {
+            let mut indexes: Vec<usize> = tree.segments.keys().copied().collect();
+            indexes.sort_unstable();
+            indexes
+        }
+        3 => (0..tree.blocks.values().map(|b| b.geometry().0).sum()).collect(),
+        _ => vec![0],
+    }
+}
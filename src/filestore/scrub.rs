@@ -0,0 +1,210 @@
+//! Resumable scrubbing for archives too big to check in one go.
+//!
+//! [`FileStore::scrub`] checks everything and repairs what it finds in a single
+//! pass. A resumable scrub walks the files in name order instead, one at a
+//! time, and records the last file it finished in `.scrub-checkpoint.json`
+//! inside the archive. When it is stopped, by its time budget or by being
+//! killed, the next run carries on after that file. The checkpoint is removed
+//! once a pass completes, so the run after that starts from the top again.
+//!
+//! Checkpoints are per file: a file interrupted halfway is checked again from
+//! its start. Files committed behind the checkpoint mid-pass are picked up by
+//! the following pass.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::FileStore;
+use crate::filestore::models::{BatchHealthReport, HealthReport, HealthStatus};
+
+pub const CHECKPOINT_FILE: &str = ".scrub-checkpoint.json";
+
+/// Limits for [`FileStore::scrub_resumable`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScrubLimits {
+    /// Stop starting new files after this long.
+    pub max_duration: Option<Duration>,
+    /// Average bytes per second to stay under, by the archived size of the
+    /// files checked.
+    pub max_rate: Option<u64>,
+}
+
+/// Progress of a pass, as saved between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubCheckpoint {
+    /// When the pass started.
+    pub started: DateTime<Utc>,
+    /// Last file checked, and repaired if needed.
+    pub last_file: String,
+    /// Files checked in the pass so far, over all runs.
+    pub checked: usize,
+}
+
+/// Outcome of one run of [`FileStore::scrub_resumable`].
+pub struct ScrubRun {
+    /// Files checked in this run, with their status after any repair.
+    pub report: BatchHealthReport,
+    /// Whether this run finished the pass.
+    pub complete: bool,
+    /// Where the pass stands, `None` once it is complete.
+    pub checkpoint: Option<ScrubCheckpoint>,
+}
+
+impl FileStore {
+    /// The archive's checkpoint, or the recovery directory's for a read-only
+    /// store. A read-only store without one only keeps progress in memory.
+    fn checkpoint_path(&self) -> Option<PathBuf> {
+        self.metadata_target(&self.store_path.join(CHECKPOINT_FILE))
+    }
+
+    /// The saved checkpoint of an unfinished pass, if any.
+    pub fn scrub_checkpoint(&self) -> Option<ScrubCheckpoint> {
+        let json = fs::read_to_string(self.checkpoint_path()?).ok()?;
+        serde_json::from_str(&json)
+            .inspect_err(|e| tracing::warn!("SCRUB | ignoring unreadable checkpoint: {}", e))
+            .ok()
+    }
+
+    /// Forgets an unfinished pass so the next run starts from the first file.
+    pub fn reset_scrub(&self) -> Result<(), std::io::Error> {
+        let Some(path) = self.checkpoint_path() else {
+            return Ok(());
+        };
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks and repairs files in name order from the saved checkpoint, until
+    /// the pass is done or `limits` say to stop. The checkpoint is saved after
+    /// every file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the archive can't be listed or the checkpoint can't be saved.
+    /// A file that fails to check or repair is logged and reported as
+    /// unrecoverable rather than stopping the run.
+    pub fn scrub_resumable(
+        &self,
+        limits: ScrubLimits,
+    ) -> Result<ScrubRun, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let mut checkpoint = self.scrub_checkpoint();
+        match &checkpoint {
+            Some(cp) => tracing::info!(
+                "SCRUB | resuming pass from {} after {} ({} files done)",
+                cp.started,
+                cp.last_file,
+                cp.checked
+            ),
+            None => tracing::info!("SCRUB | starting a new pass"),
+        }
+
+        let mut files: Vec<_> = self
+            .get_all()?
+            .into_iter()
+            .filter(|file| file.alias_of.is_none())
+            .filter(|file| {
+                checkpoint
+                    .as_ref()
+                    .is_none_or(|cp| file.file_name > cp.last_file)
+            })
+            .collect();
+        files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        let mut report = BatchHealthReport {
+            total_files: 0,
+            healthy: 0,
+            degraded: 0,
+            recoverable: 0,
+            unrecoverable: 0,
+            reports: Vec::new(),
+        };
+        let mut bytes = 0u64;
+        for file in &files {
+            if limits
+                .max_duration
+                .is_some_and(|max| started.elapsed() >= max)
+            {
+                tracing::info!(
+                    "SCRUB | time budget used after {} files, stopping",
+                    report.total_files
+                );
+                return Ok(ScrubRun {
+                    report,
+                    complete: false,
+                    checkpoint,
+                });
+            }
+
+            let health = match self.health_check(file) {
+                Ok(health) if health.status != HealthStatus::Healthy => {
+                    match self.repair(file) {
+                        Ok(_) => tracing::info!("SCRUB | repaired {}", file.file_name),
+                        Err(e) => {
+                            tracing::error!("SCRUB | repair of {} failed: {}", file.file_name, e)
+                        }
+                    }
+                    self.health_check(file)
+                }
+                other => other,
+            };
+            let health = health.unwrap_or_else(|e| {
+                tracing::error!("SCRUB | checking {} failed: {}", file.file_name, e);
+                HealthReport {
+                    status: HealthStatus::Unrecoverable,
+                    missing_data: Vec::new(),
+                    missing_parity: Vec::new(),
+                    corrupt_segments: Vec::new(),
+                    recoverable: false,
+                    details: e.to_string(),
+                }
+            });
+            match health.status {
+                HealthStatus::Healthy => report.healthy += 1,
+                HealthStatus::Degraded => report.degraded += 1,
+                HealthStatus::Recoverable => report.recoverable += 1,
+                HealthStatus::Unrecoverable => report.unrecoverable += 1,
+            }
+            report.total_files += 1;
+            self.try_record_health([(file.file_name.as_str(), &health)]);
+            report.reports.push((file.file_name.clone(), health));
+
+            let cp = checkpoint.get_or_insert_with(|| ScrubCheckpoint {
+                started: Utc::now(),
+                last_file: String::new(),
+                checked: 0,
+            });
+            cp.last_file = file.file_name.clone();
+            cp.checked += 1;
+            self.write_metadata_file(
+                &self.store_path.join(CHECKPOINT_FILE),
+                serde_json::to_string_pretty(cp)?.as_bytes(),
+            )?;
+
+            // stay under the rate by sleeping off whatever we're ahead of it
+            bytes += file.manifest.size.max(0) as u64;
+            if let Some(rate) = limits.max_rate.filter(|rate| *rate > 0) {
+                let due = Duration::from_secs_f64(bytes as f64 / rate as f64);
+                if let Some(ahead) = due.checked_sub(started.elapsed()) {
+                    std::thread::sleep(ahead);
+                }
+            }
+        }
+
+        self.reset_scrub()?;
+        tracing::info!(
+            "SCRUB | pass complete, {} files checked in this run",
+            report.total_files
+        );
+        Ok(ScrubRun {
+            report,
+            complete: true,
+            checkpoint: None,
+        })
+    }
+}
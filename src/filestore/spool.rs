@@ -0,0 +1,313 @@
+//! Keeping repairs out of the archive.
+//!
+//! A DVD or a locked snapshot can still be checked and restored from, but a
+//! repair that tries to write a recovered shard back fails halfway with an IO
+//! error. A store made with [`FileStore::read_only`] never writes under its
+//! archive directory. Shards are recovered in memory as usual and either
+//! written to a separate recovery directory, at the same relative paths, or
+//! dropped. Commands that rewrite the archive itself (retier, compact, prune
+//! and migrate) fail up front instead.
+//!
+//! A store made with [`FileStore::with_repair_dest`] is writable but sends
+//! recovered shards to a spool directory the same way, so they can be looked
+//! at before they replace anything. [`FileStore::promote`] then checks each
+//! spooled shard against its manifest and moves it into the archive.
+//!
+//! Either way every shard write kept out of the archive is listed by
+//! [`FileStore::withheld_writes`].
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::FileStore;
+use super::scrub::CHECKPOINT_FILE;
+use crate::audit::{AUDIT_FILE, AuditEntry, AuditLog, AuditOp};
+use crate::utils::{blake3_hash_bytes, replace_shard};
+
+/// Where a store sends the writes it keeps out of the archive.
+pub(super) struct Spool {
+    dir: Option<PathBuf>,
+    read_only: bool,
+    withheld: Mutex<Vec<PathBuf>>,
+}
+
+/// Outcome of [`FileStore::promote`].
+#[derive(Debug, Default)]
+pub struct PromoteReport {
+    /// Archive paths replaced by their spooled shard, or that would be on a
+    /// dry run.
+    pub promoted: Vec<PathBuf>,
+    /// Spooled files left where they are, with the reason.
+    pub rejected: Vec<(PathBuf, String)>,
+}
+
+impl FileStore {
+    /// Never writes under `store_path`. With `recovery_dir`, recovered shards,
+    /// the audit log and scrub checkpoints go there instead, laid out as in
+    /// the archive; without it they are only kept in memory.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::filestore::FileStore;
+    /// use std::path::{Path, PathBuf};
+    ///
+    /// let store = FileStore::new(Path::new("/mnt/dvd/archive"))?
+    ///     .read_only(Some(PathBuf::from("recovered")));
+    /// let file = store.find(&"movie.mkv".to_string())?;
+    /// store.repair(&file)?;
+    /// for path in store.withheld_writes() {
+    ///     println!("would have written {}", path.display());
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_only(mut self, recovery_dir: Option<PathBuf>) -> Self {
+        self.spool = Some(Spool {
+            dir: recovery_dir,
+            read_only: true,
+            withheld: Mutex::new(Vec::new()),
+        });
+        self
+    }
+
+    /// Writes recovered shards under `repair_dest` rather than over the
+    /// originals, laid out as in the archive. The audit log and scrub
+    /// checkpoints stay in the archive. See [`FileStore::promote`].
+    pub fn with_repair_dest(mut self, repair_dest: PathBuf) -> Self {
+        self.spool = Some(Spool {
+            dir: Some(repair_dest),
+            read_only: false,
+            withheld: Mutex::new(Vec::new()),
+        });
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.spool.as_ref().is_some_and(|spool| spool.read_only)
+    }
+
+    /// Archive paths kept out of the archive by a read-only store or a repair
+    /// destination, in the order they were first held back. Always empty for
+    /// a store that writes in place.
+    pub fn withheld_writes(&self) -> Vec<PathBuf> {
+        self.spool
+            .as_ref()
+            .map(|spool| spool.withheld.lock().clone())
+            .unwrap_or_default()
+    }
+
+    /// Fails with a message naming `action` if the store is read-only.
+    pub(crate) fn ensure_writable(&self, action: &str) -> Result<(), String> {
+        match self.is_read_only() {
+            true => Err(format!(
+                "{} is read-only, {} needs to write to it",
+                self.store_path.display(),
+                action
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Where a shard write to `path` in the archive actually lands: `path`
+    /// itself, its place in the recovery or spool directory, or `None` if a
+    /// read-only store has nowhere to put it.
+    pub(crate) fn write_target(&self, path: &Path) -> Option<PathBuf> {
+        let Some(spool) = &self.spool else {
+            return Some(path.to_path_buf());
+        };
+        let relative = path.strip_prefix(&self.store_path).ok()?;
+        spool.dir.as_ref().map(|dir| dir.join(relative))
+    }
+
+    /// Like [`FileStore::write_target`] for the archive's own bookkeeping,
+    /// which only a read-only store moves elsewhere.
+    pub(crate) fn metadata_target(&self, path: &Path) -> Option<PathBuf> {
+        match self.is_read_only() {
+            true => self.write_target(path),
+            false => Some(path.to_path_buf()),
+        }
+    }
+
+    /// Writes shard `bytes` to `path` in the archive, creating its directory,
+    /// or to the recovery or spool directory.
+    pub(crate) fn write_archive_file(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+    ) -> Result<(), std::io::Error> {
+        self.withhold(path);
+        let Some(target) = self.write_target(path) else {
+            tracing::info!("READ-ONLY | not writing {:?}", path);
+            return Ok(());
+        };
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        replace_shard(&target, bytes)?;
+        if target != path {
+            tracing::info!("SPOOL | wrote {:?} to {:?}", path, target);
+        }
+        Ok(())
+    }
+
+    /// Saves archive bookkeeping such as the scrub checkpoint, see
+    /// [`FileStore::metadata_target`].
+    pub(crate) fn write_metadata_file(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+    ) -> Result<(), std::io::Error> {
+        if self.is_read_only() {
+            self.withhold(path);
+        }
+        match self.metadata_target(path) {
+            Some(target) => write_file(&target, bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends `entry` to the archive's audit log, or the recovery
+    /// directory's for a read-only store.
+    pub(crate) fn audit(&self, entry: &AuditEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let log = AuditLog::for_archive(&self.store_path);
+        if !self.is_read_only() {
+            return log.append(entry);
+        }
+        self.withhold(log.path());
+        match self
+            .metadata_target(log.path())
+            .as_deref()
+            .and_then(Path::parent)
+        {
+            Some(dir) => AuditLog::for_archive(dir).append(entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the shards a repair left in `spool_dir` into the archive. Each
+    /// one must be a data or parity shard some manifest expects at the same
+    /// relative path and match the hash recorded for it; anything else is
+    /// left in the spool and reported. With `dry_run` nothing is moved.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blockframe::filestore::FileStore;
+    /// use std::path::Path;
+    ///
+    /// let store = FileStore::new(Path::new("archive_directory"))?;
+    /// let report = store.promote(Path::new("repairs"), false)?;
+    /// for (path, reason) in &report.rejected {
+    ///     println!("kept {}: {}", path.display(), reason);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn promote(
+        &self,
+        spool_dir: &Path,
+        dry_run: bool,
+    ) -> Result<PromoteReport, Box<dyn std::error::Error>> {
+        if !dry_run {
+            self.ensure_writable("promote")?;
+        }
+
+        // archive path -> (file name, manifest hash) for every shard
+        let mut expected: HashMap<PathBuf, (String, Option<String>)> = HashMap::new();
+        for file in self.get_all()? {
+            if file.alias_of.is_some() {
+                continue;
+            }
+            for shard in self.data_shards(&file)? {
+                expected.insert(shard.path, (file.file_name.clone(), Some(shard.hash)));
+            }
+            for shard in self.parity_shards(&file)? {
+                expected.insert(shard.path, (file.file_name.clone(), shard.hash));
+            }
+        }
+
+        let mut spooled = Vec::new();
+        spooled_files(spool_dir, &mut spooled)?;
+        spooled.sort();
+
+        let mut report = PromoteReport::default();
+        for source in spooled {
+            let relative = source.strip_prefix(spool_dir)?;
+            if relative == Path::new(AUDIT_FILE) || relative == Path::new(CHECKPOINT_FILE) {
+                continue;
+            }
+            let target = self.store_path.join(relative);
+            let reject = match expected.get(&target) {
+                None => Some("not a shard of any archived file".to_string()),
+                Some((_, None)) => Some("the manifest has no hash to check it against".to_string()),
+                Some((_, Some(hash))) => match blake3_hash_bytes(&fs::read(&source)?)? {
+                    actual if actual == *hash => None,
+                    _ => Some("does not match its manifest hash".to_string()),
+                },
+            };
+            if let Some(reason) = reject {
+                tracing::warn!("PROMOTE | keeping {:?}: {}", source, reason);
+                report.rejected.push((source, reason));
+                continue;
+            }
+            if !dry_run {
+                promote_file(&source, &target)?;
+                let (file_name, _) = &expected[&target];
+                self.audit(
+                    &AuditEntry::new(AuditOp::Repair, file_name).details(format!(
+                        "promoted {} from {}",
+                        relative.display(),
+                        spool_dir.display()
+                    )),
+                )?;
+                tracing::info!("PROMOTE | {:?} -> {:?}", source, target);
+            }
+            report.promoted.push(target);
+        }
+        Ok(report)
+    }
+
+    fn withhold(&self, path: &Path) {
+        if let Some(spool) = &self.spool {
+            let mut withheld = spool.withheld.lock();
+            if !withheld.iter().any(|p| p == path) {
+                withheld.push(path.to_path_buf());
+            }
+        }
+    }
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, bytes)
+}
+
+/// Copies `source` next to `target`, renames it over `target` so a crash
+/// never leaves a half written shard, then removes `source`.
+fn promote_file(source: &Path, target: &Path) -> Result<(), std::io::Error> {
+    let mut staged = target.as_os_str().to_owned();
+    staged.push(".promote");
+    let staged = PathBuf::from(staged);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, &staged)?;
+    fs::rename(&staged, target)?;
+    fs::remove_file(source)
+}
+
+/// Collects every file under `dir`.
+fn spooled_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            spooled_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
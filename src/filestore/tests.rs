@@ -2,12 +2,36 @@
 //!
 //! Tests cover:
 //! - Finding files by name
+//! - Remembering names that weren't found, but not past a commit that
+//!   raced the lookup
+//! - Looking entries up by content hash
+//! - Searching names by glob or text
+//! - Tagging entries and filtering on tags
 //! - Listing all files
 //! - File reconstruction
+//! - Reading a byte range
+//! - Zero segments only read as zeros when their hash says so
+//! - Re-tiering
+//! - Checking and restoring a working copy in place
+//! - Migrating legacy entries
+//! - Compacting dead space
+//! - Resumable scrubbing
+//! - Parity kept under a separate directory
+//! - Archive-wide parity across files, kept through an undelete, with the
+//!   indexes and tags of what it recovers
+//! - Health history
+//! - Repair planning
+//! - Concurrent repairs of one file
+//! - Checking a single segment or block
+//! - Sampled checks
+//! - Removing to the trash and undeleting
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use super::super::*;
+    use crate::filestore::models::{HealthReport, HealthStatus};
+    use std::collections::HashMap;
     use std::fs;
     use std::path::{Path, PathBuf};
     use tempfile::TempDir;
@@ -99,6 +123,83 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_find_remembers_missing_names_until_invalidated() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = setup_test_archive(temp_dir.path());
+        let store = FileStore::new(&archive_dir)
+            .unwrap()
+            .with_negative_ttl(std::time::Duration::from_secs(60));
+        assert!(store.find(&"late.txt".to_string()).is_err());
+
+        let entry = archive_dir.join("late.txt_def456");
+        fs::create_dir_all(&entry).unwrap();
+        let manifest = fs::read_to_string(archive_dir.join("test.txt_abc123/manifest.json"))
+            .unwrap()
+            .replace("test.txt", "late.txt")
+            .replace("abc123", "def456");
+        fs::write(entry.join("manifest.json"), manifest).unwrap();
+        fs::write(entry.join("data.dat"), vec![0u8; 1000]).unwrap();
+
+        // still answered from the negative cache
+        assert!(store.find(&"late.txt".to_string()).is_err());
+        store.invalidate();
+        assert_eq!(
+            store.find(&"late.txt".to_string()).unwrap().file_name,
+            "late.txt"
+        );
+    }
+
+    #[test]
+    fn test_miss_racing_a_commit_is_not_remembered() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = setup_test_archive(temp_dir.path());
+        let store = FileStore::new(&archive_dir)
+            .unwrap()
+            .with_negative_ttl(std::time::Duration::from_secs(60));
+        let manifest = fs::read_to_string(archive_dir.join("test.txt_abc123/manifest.json"))
+            .unwrap()
+            .replace("test.txt", "late.txt")
+            .replace("abc123", "def456");
+        // enough entries that a scan takes a while
+        for i in 0..200 {
+            let entry = archive_dir.join(format!("filler{i}.txt_abc123"));
+            fs::create_dir_all(&entry).unwrap();
+            fs::copy(
+                archive_dir.join("test.txt_abc123/manifest.json"),
+                entry.join("manifest.json"),
+            )
+            .unwrap();
+        }
+        let work_dir = temp_dir.path().join("late.txt_def456");
+        fs::create_dir_all(&work_dir).unwrap();
+        fs::write(work_dir.join("data.dat"), vec![0u8; 1000]).unwrap();
+        fs::write(work_dir.join("manifest.json"), &manifest).unwrap();
+        let done = std::sync::atomic::AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            // a client polling for the file, each lookup rescanning as it
+            // would on an archive that keeps changing
+            scope.spawn(|| {
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    store.invalidate();
+                    let _ = store.find(&"late.txt".to_string());
+                }
+            });
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            // publishes and invalidates the way the daemon's watcher does,
+            // while a lookup is most likely scanning
+            fs::rename(&work_dir, archive_dir.join("late.txt_def456")).unwrap();
+            store.invalidate();
+            done.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+
+        assert_eq!(
+            store.find(&"late.txt".to_string()).unwrap().file_name,
+            "late.txt"
+        );
+    }
+
     #[test]
     fn test_get_all_empty_archive() {
         let temp_dir = TempDir::new().unwrap();
@@ -123,4 +224,1577 @@ mod tests {
         assert_eq!(manifests.iter().clone().len(), 1);
         assert!(&manifests.unwrap()[0].ends_with("manifest.json"));
     }
+
+    #[test]
+    fn test_retier_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let source = temp_dir.path().join("retier.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        chunker.commit(&source).unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+
+        for tier in [2, 3, 1] {
+            let file = store.find(&"retier.bin".to_string()).unwrap();
+            store.retier(&file, &chunker, tier).unwrap();
+
+            let file = store.find(&"retier.bin".to_string()).unwrap();
+            assert_eq!(file.manifest.tier, tier);
+            let mut restored = Vec::new();
+            let hash = store.reconstruct_to(&file, &mut restored).unwrap();
+            assert_eq!(hash, file.file_data.hash);
+            assert_eq!(restored, data);
+        }
+
+        let file = store.find(&"retier.bin".to_string()).unwrap();
+        assert!(store.retier(&file, &chunker, 1).is_err());
+        assert_eq!(store.get_all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_reconstruct_to_recovers_corrupt_shards() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let source = temp_dir.path().join("restore.bin");
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 241) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+
+        for tier in [1, 2, 3] {
+            let chunked = chunker.commit_as(&source, Some(tier)).unwrap();
+            let file_dir = Path::new(&chunked.file_dir);
+            let damaged = match tier {
+                1 => file_dir.join("data.dat"),
+                2 => file_dir.join("segments/segment_0.dat"),
+                _ => file_dir.join("blocks/block_0/segments/segment_0.dat"),
+            };
+            let mut bytes = fs::read(&damaged).unwrap();
+            bytes[0] ^= 0xff;
+            fs::write(&damaged, bytes).unwrap();
+
+            let file = store.find(&"restore.bin".to_string()).unwrap();
+            let mut restored = Vec::new();
+            let hash = store.reconstruct_to(&file, &mut restored).unwrap();
+            assert_eq!(hash, file.file_data.hash);
+            assert_eq!(restored, data);
+
+            fs::remove_dir_all(file_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_partial_block_parity_scales_with_fill() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("blocks.bin");
+        // 30 segments in block 0, 6 in block 1 with the last one short
+        let data: Vec<u8> = (0..35 * 65_536 + 1000u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(3)).unwrap();
+        let block_1 = chunked.file_dir.join("blocks/block_1");
+
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let file = store.find(&"blocks.bin".to_string()).unwrap();
+        let blocks = &file.manifest.merkle_tree.blocks;
+        assert_eq!(blocks[&0].geometry(), (30, 3));
+        assert_eq!(blocks[&1].geometry(), (6, 1));
+        assert_eq!(blocks[&1].segment_len(4), Some(65_536));
+        assert_eq!(blocks[&1].segment_len(5), Some(1000));
+        assert!(!block_1.join("parity/block_parity_1.dat").exists());
+        let parity = store.parity_shards(&file).unwrap();
+        let sets: Vec<_> = parity.iter().map(|p| (p.set, p.parity_id)).collect();
+        assert_eq!(
+            sets,
+            [
+                (ParitySet::Block(0), 0),
+                (ParitySet::Block(0), 1),
+                (ParitySet::Block(0), 2),
+                (ParitySet::Block(1), 0),
+            ]
+        );
+        for shard in &parity {
+            let bytes = fs::read(&shard.path).unwrap();
+            let hash = crate::utils::blake3_hash_bytes(&bytes).unwrap();
+            assert_eq!(shard.hash.as_deref(), Some(hash.as_str()));
+        }
+        assert_eq!(
+            store.health_check(&file).unwrap().status,
+            HealthStatus::Healthy
+        );
+
+        // one lost segment is within the partial block's single parity shard
+        let last = block_1.join("segments/segment_5.dat");
+        fs::remove_file(&last).unwrap();
+        let report = store.health_check(&file).unwrap();
+        assert_eq!(report.status, HealthStatus::Recoverable);
+        store.repair(&file).unwrap();
+        assert_eq!(fs::read(&last).unwrap(), data[35 * 65_536..]);
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, data);
+
+        // two are not
+        fs::remove_file(block_1.join("segments/segment_0.dat")).unwrap();
+        fs::remove_file(&last).unwrap();
+        let report = store.health_check(&file).unwrap();
+        assert_eq!(report.status, HealthStatus::Unrecoverable);
+    }
+
+    #[test]
+    fn test_block_health_goes_by_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("listing.bin");
+        let data: Vec<u8> = (0..40 * 65_536u32).map(|i| (i % 239) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(3)).unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let file = store.find(&"listing.bin".to_string()).unwrap();
+        assert_eq!(
+            file.manifest.merkle_tree.blocks[&0].shard_size,
+            Some(65_536)
+        );
+
+        // the highest numbered segment can't be told missing from a listing
+        let last = chunked
+            .file_dir
+            .join("blocks/block_0/segments/segment_29.dat");
+        fs::remove_file(&last).unwrap();
+        let report = store.health_check(&file).unwrap();
+        assert_eq!(report.status, HealthStatus::Recoverable);
+        assert_eq!(report.missing_data, ["block_0/segment_29.dat"]);
+        store.repair(&file).unwrap();
+        assert_eq!(fs::read(&last).unwrap(), data[29 * 65_536..30 * 65_536]);
+
+        // nor can a block whose directory is gone
+        fs::remove_dir_all(chunked.file_dir.join("blocks/block_1")).unwrap();
+        let report = store.health_check(&file).unwrap();
+        assert_eq!(report.status, HealthStatus::Unrecoverable);
+        assert_eq!(report.missing_data.len(), 10);
+    }
+
+    #[test]
+    fn test_repair_every_tier() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("repair.bin");
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 233) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+
+        for tier in [1, 2, 3] {
+            let chunked = chunker.commit_as(&source, Some(tier)).unwrap();
+            let dir = chunked.file_dir.clone();
+            // a lost data shard, plus a bad parity shard where there's one to spare
+            let (lost, bad_parity) = match tier {
+                1 => (dir.join("data.dat"), Some(dir.join("parity_0.dat"))),
+                2 => (
+                    dir.join("segments/segment_4.dat"),
+                    Some(dir.join("parity/segment_4_parity_1.dat")),
+                ),
+                _ => (dir.join("blocks/block_0/segments/segment_4.dat"), None),
+            };
+            fs::remove_file(&lost).unwrap();
+            if let Some(parity) = bad_parity {
+                fs::write(&parity, vec![7u8; fs::read(&parity).unwrap().len()]).unwrap();
+            }
+
+            let file = store.find(&"repair.bin".to_string()).unwrap();
+            store.repair(&file).unwrap();
+            assert!(lost.exists(), "tier {} left {:?} missing", tier, lost);
+            let mut restored = Vec::new();
+            store.reconstruct_to(&file, &mut restored).unwrap();
+            assert_eq!(restored, data);
+
+            fs::remove_dir_all(&dir).unwrap();
+            store.invalidate();
+        }
+    }
+
+    #[test]
+    fn test_repair_decodes_with_the_manifests_codec() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("coded.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 241) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let builder = || {
+            crate::chunker::Chunker::builder()
+                .archive_dir(temp_dir.path().join("archive_directory"))
+                .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+        };
+        let store = FileStore::new(&temp_dir.path().join("archive_directory")).unwrap();
+
+        let chunked = builder()
+            .build()
+            .unwrap()
+            .commit_as(&source, Some(2))
+            .unwrap();
+        let manifest_path = chunked.file_dir.join("manifest.json");
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        assert!(manifest.contains(r#""type":"reed-solomon""#));
+        fs::write(
+            &manifest_path,
+            manifest.replace(r#""type":"reed-solomon""#, r#""type":"fountain""#),
+        )
+        .unwrap();
+        fs::remove_file(chunked.file_dir.join("segments/segment_1.dat")).unwrap();
+        let file = store.find(&"coded.bin".to_string()).unwrap();
+        let err = store.repair(&file).unwrap_err().to_string();
+        assert!(err.contains("unknown erasure code"), "{}", err);
+        fs::remove_dir_all(&chunked.file_dir).unwrap();
+        store.invalidate();
+
+        #[cfg(feature = "rs-galois")]
+        for tier in [1, 2, 3] {
+            let chunker = builder()
+                .erasure_coder(&crate::erasure::GaloisReedSolomon)
+                .build()
+                .unwrap();
+            let dir = chunker.commit_as(&source, Some(tier)).unwrap().file_dir;
+            let lost = match tier {
+                1 => dir.join("data.dat"),
+                2 => dir.join("segments/segment_1.dat"),
+                _ => dir.join("blocks/block_0/segments/segment_1.dat"),
+            };
+            fs::remove_file(&lost).unwrap();
+            let file = store.find(&"coded.bin".to_string()).unwrap();
+            assert_eq!(file.manifest.erasure_coding.r#type, "reed-solomon-galois");
+            store.repair(&file).unwrap();
+            assert!(lost.exists(), "tier {} left {:?} missing", tier, lost);
+            let mut restored = Vec::new();
+            store.reconstruct_to(&file, &mut restored).unwrap();
+            assert_eq!(restored, data);
+            fs::remove_dir_all(&dir).unwrap();
+            store.invalidate();
+        }
+    }
+
+    #[test]
+    fn test_local_parity_repairs_from_the_group() {
+        use crate::filestore::recovery::{ShardKind, recover_shard};
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("lrc.bin");
+        let data: Vec<u8> = (0..40_000u32).map(|i| (i % 239) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(1_000))
+            .local_parity_group(6)
+            .build()
+            .unwrap();
+        let plan = chunker.plan(&source, Some(3)).unwrap();
+        let dir = chunker.commit_as(&source, Some(3)).unwrap().file_dir;
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let file = store.find(&"lrc.bin".to_string()).unwrap();
+        let block = &file.manifest.merkle_tree.blocks[&0];
+        assert_eq!((block.local_group, block.local_parity.len()), (Some(6), 5));
+        let parity_files = fs::read_dir(dir.join("blocks/block_0/parity"))
+            .unwrap()
+            .count();
+        assert_eq!(parity_files, 3 + 5);
+        assert_eq!(plan.parity_shards, 3 + 5 + 1 + 2);
+
+        // segment 8 comes back from segments 6, 7, 9, 10, 11 and local parity 1
+        let block_dir = dir.join("blocks/block_0");
+        let mut segment_reads = Vec::new();
+        let mut parity_reads = Vec::new();
+        let recovered = recover_shard(
+            &file.manifest,
+            ShardKind::Block(0, 8),
+            |n| {
+                segment_reads.push(n);
+                fs::read(block_dir.join(format!("segments/segment_{}.dat", n))).ok()
+            },
+            |p| {
+                parity_reads.push(p);
+                fs::read(block_dir.join(format!("parity/block_parity_{}.dat", p))).ok()
+            },
+        )
+        .unwrap();
+        assert_eq!(recovered, data[8_000..9_000]);
+        assert_eq!(segment_reads, [6, 7, 9, 10, 11]);
+        assert_eq!(parity_reads, [4]);
+
+        // a lost local parity shard is rebuilt by repair, next to the segment
+        fs::remove_file(block_dir.join("segments/segment_8.dat")).unwrap();
+        fs::remove_file(block_dir.join("parity/block_parity_5.dat")).unwrap();
+        let report = store.health_check(&file).unwrap();
+        assert_eq!(report.status, HealthStatus::Recoverable);
+        store.repair(&file).unwrap();
+        assert!(block_dir.join("parity/block_parity_5.dat").exists());
+        assert_eq!(
+            store.health_check(&file).unwrap().status,
+            HealthStatus::Healthy
+        );
+
+        // without the global parity a lost segment still comes back
+        for p in 0..3 {
+            fs::remove_file(block_dir.join(format!("parity/block_parity_{}.dat", p))).unwrap();
+        }
+        fs::remove_file(block_dir.join("segments/segment_8.dat")).unwrap();
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, data);
+    }
+
+    #[test]
+    fn test_parity_dir_keeps_parity_apart_from_the_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let parity_root = temp_dir.path().join("other_disk");
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(1_000))
+            .block_parity_ratio(0.5)
+            .parity_dir(&parity_root)
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+
+        for tier in 1..=3u8 {
+            let name = format!("split_{}.bin", tier);
+            let source = temp_dir.path().join(&name);
+            let data: Vec<u8> = (0..5_500u32)
+                .map(|i| (i * tier as u32 % 251) as u8)
+                .collect();
+            fs::write(&source, &data).unwrap();
+            let dir = chunker.commit_as(&source, Some(tier)).unwrap().file_dir;
+            let file = store.find(&name).unwrap();
+
+            let parity_dir = PathBuf::from(file.manifest.parity_dir.as_ref().unwrap());
+            assert!(parity_dir.starts_with(std::path::absolute(&parity_root).unwrap()));
+            let shards = store.parity_shards(&file).unwrap();
+            assert!(
+                shards
+                    .iter()
+                    .all(|s| s.path.starts_with(&parity_dir) && s.path.exists())
+            );
+            assert!(!dir.join("parity").exists() && !dir.join("parity_0.dat").exists());
+            assert!(!dir.join("blocks/block_0/parity").exists());
+
+            // repair finds the parity on its own disk
+            let data_shard = store.data_shards(&file).unwrap().remove(0).path;
+            fs::remove_file(&data_shard).unwrap();
+            assert_eq!(
+                store.health_check(&file).unwrap().status,
+                HealthStatus::Recoverable
+            );
+            store.repair(&file).unwrap();
+            assert!(data_shard.exists());
+            let mut restored = Vec::new();
+            store.reconstruct_to(&file, &mut restored).unwrap();
+            assert_eq!(restored, data);
+
+            // and the health check looks for it there
+            fs::remove_file(&shards[0].path).unwrap();
+            let report = store.health_check(&file).unwrap();
+            assert_eq!(report.missing_parity.len(), 1);
+
+            // retiering writes new parity and removes the old
+            if tier == 2 {
+                store.retier(&file, &chunker, 3).unwrap();
+                assert!(!parity_dir.exists());
+                let file = store.find(&name).unwrap();
+                let moved = file.manifest.parity_dir.as_ref().unwrap();
+                assert!(Path::new(moved).join("blocks/block_0/parity").exists());
+            }
+        }
+    }
+
+    #[test]
+    fn test_global_parity_recovers_a_lost_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let builder = || {
+            crate::chunker::Chunker::builder()
+                .archive_dir(temp_dir.path().join("archive_directory"))
+                .segment_policy(crate::utils::SegmentPolicy::Fixed(1_000))
+                .block_parity_ratio(0.5)
+                .global_parity(3)
+        };
+        let chunker = builder().build().unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let mut contents = Vec::new();
+        for (name, len, tier) in [
+            ("a.bin", 3_000, 1),
+            ("b.bin", 5_500, 2),
+            ("c.bin", 7_000, 3),
+            ("d.bin", 900, 1),
+        ] {
+            let source = temp_dir.path().join(name);
+            let data: Vec<u8> = (0..len).map(|i| (i * len % 253) as u8).collect();
+            fs::write(&source, &data).unwrap();
+            chunker.commit_as(&source, Some(tier)).unwrap();
+            contents.push((name.to_string(), data));
+        }
+        let report = store.global_parity_status().unwrap();
+        assert_eq!((report.volumes, report.members), (2, 4));
+        assert!(report.missing.is_empty());
+
+        // a whole entry directory goes, and comes back from its volume
+        let lost = store.find(&"b.bin".to_string()).unwrap();
+        fs::remove_dir_all(Path::new(&lost.file_data.path).parent().unwrap()).unwrap();
+        store.invalidate();
+        assert_eq!(store.global_parity_status().unwrap().missing.len(), 1);
+        let recovered = store.recover_from_global_parity(&chunker).unwrap();
+        assert_eq!(recovered.len(), 1);
+        let file = store.find(&"b.bin".to_string()).unwrap();
+        assert_eq!(file.manifest.tier, 2);
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, contents[1].1);
+        assert!(store.global_parity_status().unwrap().missing.is_empty());
+
+        // overwriting a.bin takes the old content out of its volume
+        let overwrite = builder()
+            .on_existing(crate::chunker::OnExisting::Overwrite)
+            .build()
+            .unwrap();
+        fs::write(temp_dir.path().join("a.bin"), b"new content").unwrap();
+        overwrite.commit(&temp_dir.path().join("a.bin")).unwrap();
+        store.invalidate();
+        let index = store.global_parity_index().unwrap();
+        assert_eq!(index.volumes[0].members.len(), 2);
+        assert_eq!(index.volumes[1].members.len(), 2);
+        assert!(store.global_parity_status().unwrap().missing.is_empty());
+
+        // two lost members of one volume are more than it can rebuild
+        for name in ["b.bin", "c.bin"] {
+            let file = store.find(&name.to_string()).unwrap();
+            fs::remove_dir_all(Path::new(&file.file_data.path).parent().unwrap()).unwrap();
+        }
+        store.invalidate();
+        let report = store.global_parity_status().unwrap();
+        assert_eq!(report.unrecoverable, [0]);
+        assert!(
+            store
+                .recover_from_global_parity(&chunker)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_undeleted_entry_is_back_in_global_parity() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .global_parity(2)
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let mut contents = HashMap::new();
+        for (name, len) in [("a.bin", 3_000), ("b.bin", 5_500), ("c.bin", 900)] {
+            let source = temp_dir.path().join(name);
+            let data: Vec<u8> = (0..len).map(|i| (i * len % 251) as u8).collect();
+            fs::write(&source, &data).unwrap();
+            chunker.commit(&source).unwrap();
+            contents.insert(name, data);
+        }
+
+        store.trash("b.bin").unwrap();
+        assert_eq!(store.global_parity_status().unwrap().members, 2);
+        store.undelete("b.bin").unwrap();
+        let report = store.global_parity_status().unwrap();
+        assert_eq!((report.members, report.volumes), (3, 2));
+
+        // lost after the undelete, it comes back from its volume
+        let file = store.find(&"b.bin".to_string()).unwrap();
+        fs::remove_dir_all(Path::new(&file.file_data.path).parent().unwrap()).unwrap();
+        store.invalidate();
+        assert_eq!(store.recover_from_global_parity(&chunker).unwrap().len(), 1);
+        let file = store.find(&"b.bin".to_string()).unwrap();
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, contents["b.bin"]);
+    }
+
+    #[test]
+    fn test_recovered_pack_keeps_its_index_and_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let builder = || {
+            crate::chunker::Chunker::builder()
+                .archive_dir(temp_dir.path().join("archive_directory"))
+                .global_parity(2)
+        };
+        let tags = |pairs: &[(&str, &str)]| -> crate::tags::Tags {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let chunker = builder()
+            .tags(tags(&[("project", "apollo")]))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let mut paths = Vec::new();
+        for (name, len) in [("a.txt", 700), ("b.txt", 1_300)] {
+            let path = temp_dir.path().join(name);
+            let data: Vec<u8> = (0..len).map(|i| (i * len % 251) as u8).collect();
+            fs::write(&path, data).unwrap();
+            paths.push(path);
+        }
+        chunker.commit_pack(&paths, "docs.pack", None).unwrap();
+        let other = temp_dir.path().join("other.bin");
+        fs::write(&other, vec![7u8; 2_000]).unwrap();
+        chunker.commit(&other).unwrap();
+        // tagged again once it is already in a volume
+        builder()
+            .tags(tags(&[("owner", "ops")]))
+            .build()
+            .unwrap()
+            .commit(&other)
+            .unwrap();
+
+        for name in ["docs.pack", "other.bin"] {
+            let file = store.find(&name.to_string()).unwrap();
+            fs::remove_dir_all(Path::new(&file.file_data.path).parent().unwrap()).unwrap();
+            store.invalidate();
+            assert_eq!(store.recover_from_global_parity(&chunker).unwrap().len(), 1);
+        }
+
+        let (pack, member) = store.find_member("b.txt").unwrap();
+        assert_eq!(pack.file_name, "docs.pack");
+        assert_eq!(pack.manifest.tags, tags(&[("project", "apollo")]));
+        let mut restored = Vec::new();
+        store.restore_member(&pack, &member, &mut restored).unwrap();
+        assert_eq!(restored, fs::read(&paths[1]).unwrap());
+        let other = store.find(&"other.bin".to_string()).unwrap();
+        assert_eq!(
+            other.manifest.tags,
+            tags(&[("owner", "ops"), ("project", "apollo")])
+        );
+    }
+
+    #[test]
+    fn test_concurrent_repairs_of_one_file_repair_it_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("shared.bin");
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 241) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(2)).unwrap();
+        fs::remove_file(chunked.file_dir.join("segments/segment_1.dat")).unwrap();
+
+        // as the daemon's full and sampled scrubs sharing one store
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let file = store.find(&"shared.bin".to_string()).unwrap();
+        std::thread::scope(|scope| {
+            let repairs: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| store.repair(&file).map_err(|e| e.to_string())))
+                .collect();
+            for repair in repairs {
+                repair.join().unwrap().unwrap();
+            }
+        });
+
+        let log = fs::read_to_string(chunker.archive_dir().join(crate::audit::AUDIT_FILE)).unwrap();
+        let repairs: Vec<crate::audit::AuditEntry> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .filter(|entry: &crate::audit::AuditEntry| entry.operation.as_str() == "repair")
+            .collect();
+        assert_eq!(repairs.len(), 1, "{}", log);
+        assert_eq!(repairs[0].outcome, "ok");
+        assert_eq!(
+            store.health_check(&file).unwrap().status,
+            HealthStatus::Healthy
+        );
+    }
+
+    #[test]
+    fn test_wrong_zero_flag_does_not_hide_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(64 * 1024))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let mut data = vec![7u8; 64 * 1024];
+        data.resize(2 * 64 * 1024, 0);
+        data.extend_from_slice(b"tail");
+        let path = temp_dir.path().join("disk.img");
+        fs::write(&path, &data).unwrap();
+
+        for tier in [2, 3] {
+            let chunked = chunker.commit_as(&path, Some(tier)).unwrap();
+            // the data segment marked as zeros, as by an unsigned edit
+            let manifest_path = chunked.file_dir.join("manifest.json");
+            let mut json: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+            match tier {
+                2 => json["merkle_tree"]["segments"]["0"]["zero"] = true.into(),
+                _ => {
+                    json["merkle_tree"]["blocks"]["0"]["zero_segments"] = serde_json::json!([0, 1])
+                }
+            }
+            fs::write(&manifest_path, json.to_string()).unwrap();
+
+            let file = store.refresh().unwrap().pop().unwrap();
+            let shards = store.data_shards(&file).unwrap();
+            assert_eq!(
+                shards.iter().map(|shard| shard.zero).collect::<Vec<_>>(),
+                [false, true, false]
+            );
+            let mut out = Vec::new();
+            store.reconstruct_to(&file, &mut out).unwrap();
+            assert_eq!(out, data);
+
+            fs::remove_dir_all(&chunked.file_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_only_repair_leaves_archive_untouched() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("dvd.bin");
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 229) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(2)).unwrap();
+        let lost = chunked.file_dir.join("segments/segment_3.dat");
+        fs::remove_file(&lost).unwrap();
+        let audit_log = chunker.archive_dir().join(crate::audit::AUDIT_FILE);
+        let audit_before = fs::read(&audit_log).unwrap();
+
+        // recovered in memory only, the writes are listed instead
+        let store = FileStore::new(chunker.archive_dir())
+            .unwrap()
+            .read_only(None);
+        let file = store.find(&"dvd.bin".to_string()).unwrap();
+        store.repair(&file).unwrap();
+        assert!(!lost.exists());
+        assert_eq!(store.withheld_writes(), [lost.clone(), audit_log.clone()]);
+        assert_eq!(fs::read(&audit_log).unwrap(), audit_before);
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, data);
+        assert!(store.compact(false).is_err());
+        assert!(store.compact(true).is_ok());
+
+        // or written to a recovery directory at the same relative path
+        let recovery_dir = temp_dir.path().join("recovered");
+        let store = FileStore::new(chunker.archive_dir())
+            .unwrap()
+            .read_only(Some(recovery_dir.clone()));
+        store.repair(&file).unwrap();
+        assert!(!lost.exists());
+        let relative = lost.strip_prefix(chunker.archive_dir()).unwrap();
+        assert_eq!(
+            fs::read(recovery_dir.join(relative)).unwrap(),
+            data[3 * 65_536..4 * 65_536]
+        );
+        assert!(recovery_dir.join(crate::audit::AUDIT_FILE).exists());
+    }
+
+    #[test]
+    fn test_repair_dest_then_promote() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("spooled.bin");
+        let data: Vec<u8> = (0..40 * 65_536u32).map(|i| (i % 227) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(3)).unwrap();
+        let lost = chunked
+            .file_dir
+            .join("blocks/block_1/segments/segment_2.dat");
+        fs::remove_file(&lost).unwrap();
+
+        let spool = temp_dir.path().join("repairs");
+        let store = FileStore::new(chunker.archive_dir())
+            .unwrap()
+            .with_repair_dest(spool.clone());
+        let file = store.find(&"spooled.bin".to_string()).unwrap();
+        store.repair(&file).unwrap();
+        assert!(!lost.exists());
+        assert_eq!(store.withheld_writes(), std::slice::from_ref(&lost));
+        let spooled = spool.join(lost.strip_prefix(chunker.archive_dir()).unwrap());
+        assert!(spooled.exists());
+
+        // a stray file and a tampered copy are kept back
+        fs::write(spool.join("stray.bin"), b"stray").unwrap();
+        let tampered_path = chunked
+            .file_dir
+            .join("blocks/block_0/segments/segment_0.dat");
+        let tampered = spool.join(tampered_path.strip_prefix(chunker.archive_dir()).unwrap());
+        fs::create_dir_all(tampered.parent().unwrap()).unwrap();
+        fs::write(&tampered, b"not the segment").unwrap();
+
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let report = store.promote(&spool, true).unwrap();
+        assert_eq!(report.promoted, std::slice::from_ref(&lost));
+        assert_eq!(report.rejected.len(), 2);
+        assert!(!lost.exists());
+
+        let report = store.promote(&spool, false).unwrap();
+        assert_eq!(report.promoted, std::slice::from_ref(&lost));
+        assert!(!spooled.exists());
+        assert!(tampered.exists());
+        assert_eq!(fs::read(&tampered_path).unwrap(), data[..65_536]);
+        assert_eq!(
+            store.health_check(&file).unwrap().status,
+            HealthStatus::Healthy
+        );
+    }
+
+    #[test]
+    fn test_read_range_reads_only_overlapping_shards() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let source = temp_dir.path().join("range.bin");
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 227) as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+
+        for tier in [1, 2, 3] {
+            let chunked = chunker.commit_as(&source, Some(tier)).unwrap();
+            let file = store.find(&"range.bin".to_string()).unwrap();
+
+            let mut slice = Vec::new();
+            let written = store.read_range(&file, 1000..5000, &mut slice).unwrap();
+            assert_eq!(written, 4000);
+            assert_eq!(slice, data[1000..5000]);
+
+            // a damaged shard in the range is recovered from parity
+            let damaged = match tier {
+                1 => chunked.file_dir.join("data.dat"),
+                2 => chunked.file_dir.join("segments/segment_0.dat"),
+                _ => chunked
+                    .file_dir
+                    .join("blocks/block_0/segments/segment_0.dat"),
+            };
+            fs::remove_file(&damaged).unwrap();
+            let mut slice = Vec::new();
+            store
+                .read_range(&file, 149_000..150_000, &mut slice)
+                .unwrap();
+            assert_eq!(slice, data[149_000..]);
+
+            assert!(store.read_range(&file, 0..150_001, Vec::new()).is_err());
+            fs::remove_dir_all(&chunked.file_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_and_restore_original_in_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let live = temp_dir.path().join("working.bin");
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 233) as u8).collect();
+        fs::write(&live, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+
+        for tier in [1, 2, 3] {
+            let chunked = chunker.commit_as(&live, Some(tier)).unwrap();
+            let file = store.find(&"working.bin".to_string()).unwrap();
+            assert!(store.check_original(&file, &live).unwrap().is_intact());
+
+            // damage the working copy and, for good measure, the archived shard too
+            let mut bytes = data.clone();
+            bytes[70_000] ^= 0xff;
+            fs::write(&live, &bytes).unwrap();
+            let shard = match tier {
+                1 => chunked.file_dir.join("data.dat"),
+                2 => chunked.file_dir.join("segments/segment_0.dat"),
+                _ => chunked
+                    .file_dir
+                    .join("blocks/block_0/segments/segment_0.dat"),
+            };
+            let mut archived = fs::read(&shard).unwrap();
+            archived[0] ^= 0xff;
+            fs::write(&shard, archived).unwrap();
+
+            let report = store.check_original(&file, &live).unwrap();
+            assert_eq!(report.damaged, vec![0..data.len() as u64]);
+            store.restore_original(&file, &live, &report).unwrap();
+            assert_eq!(fs::read(&live).unwrap(), data);
+
+            // a truncated copy is reported by size and grown back
+            fs::write(&live, &data[..1000]).unwrap();
+            let report = store.check_original(&file, &live).unwrap();
+            assert_eq!(report.live_size, 1000);
+            assert!(!report.is_intact());
+            store.restore_original(&file, &live, &report).unwrap();
+            assert_eq!(fs::read(&live).unwrap(), data);
+
+            fs::remove_dir_all(&chunked.file_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compact_removes_stale_shards_and_dangling_aliases() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 211) as u8).collect();
+        let source = temp_dir.path().join("kept.bin");
+        fs::write(&source, &data).unwrap();
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+
+        // segments left behind by a tier 2 encoding re-encoded in place as
+        // tier 1, as recommitting the same file used to do
+        let chunked = chunker.commit_as(&source, Some(1)).unwrap();
+        for dir in ["segments", "parity"] {
+            fs::create_dir_all(chunked.file_dir.join(dir)).unwrap();
+        }
+        fs::write(chunked.file_dir.join("segments/segment_0.dat"), &data).unwrap();
+        fs::write(
+            chunked.file_dir.join("parity/segment_0_parity_0.dat"),
+            &data,
+        )
+        .unwrap();
+
+        // an alias of a file that is then removed
+        let other = temp_dir.path().join("gone.bin");
+        fs::write(&other, b"gone").unwrap();
+        let gone = chunker.commit(&other).unwrap();
+        let copy = temp_dir.path().join("copy.bin");
+        fs::write(&copy, b"gone").unwrap();
+        let alias = chunker.commit(&copy).unwrap();
+        fs::remove_dir_all(&gone.file_dir).unwrap();
+
+        let preview = store.compact(true).unwrap();
+        assert!(preview.removed.contains(&alias.file_dir));
+        assert!(chunked.file_dir.join("segments").exists());
+
+        let report = store.compact(false).unwrap();
+        assert_eq!(report.removed.len(), preview.removed.len());
+        assert!(report.bytes > 0);
+        assert!(!chunked.file_dir.join("segments").exists());
+        assert!(!chunked.file_dir.join("parity").exists());
+        assert!(!alias.file_dir.exists());
+
+        let file = store.find(&"kept.bin".to_string()).unwrap();
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, data);
+        assert!(store.compact(false).unwrap().removed.is_empty());
+    }
+
+    #[test]
+    fn test_resumable_scrub_continues_after_checkpoint() {
+        use crate::filestore::scrub::{ScrubCheckpoint, ScrubLimits};
+
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let mut dirs = Vec::new();
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, name.repeat(512)).unwrap();
+            dirs.push(chunker.commit(&path).unwrap().file_dir);
+        }
+        let store = FileStore::new(&archive_dir).unwrap();
+
+        // out of time before the first file: nothing checked, nothing saved
+        let run = store
+            .scrub_resumable(ScrubLimits {
+                max_duration: Some(std::time::Duration::ZERO),
+                max_rate: None,
+            })
+            .unwrap();
+        assert!(!run.complete);
+        assert_eq!(run.report.total_files, 0);
+        assert!(store.scrub_checkpoint().is_none());
+
+        // a pass interrupted after b.bin only has c.bin left, which gets repaired
+        let checkpoint = ScrubCheckpoint {
+            started: chrono::Utc::now(),
+            last_file: "b.bin".to_string(),
+            checked: 2,
+        };
+        fs::write(
+            archive_dir.join(crate::filestore::scrub::CHECKPOINT_FILE),
+            serde_json::to_string(&checkpoint).unwrap(),
+        )
+        .unwrap();
+        let data_path = dirs[2].join("data.dat");
+        let mut bytes = fs::read(&data_path).unwrap();
+        bytes[0] ^= 0xff;
+        fs::write(&data_path, bytes).unwrap();
+
+        let run = store.scrub_resumable(ScrubLimits::default()).unwrap();
+        assert!(run.complete);
+        assert_eq!(run.report.total_files, 1);
+        assert_eq!(run.report.reports[0].0, "c.bin");
+        assert_eq!(run.report.healthy, 1);
+        assert_eq!(
+            fs::read(&data_path).unwrap(),
+            "c.bin".repeat(512).as_bytes()
+        );
+        assert!(store.scrub_checkpoint().is_none());
+
+        // the next pass starts over
+        let run = store.scrub_resumable(ScrubLimits::default()).unwrap();
+        assert_eq!(run.report.total_files, 3);
+    }
+
+    #[test]
+    fn test_migrate_legacy_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 239) as u8).collect();
+
+        // pre-tier layout: six chunks per segment and no tier in the manifest
+        let legacy_dir = archive_dir.join("legacy.bin_0123");
+        let chunks_dir = legacy_dir.join("segments/segment_0/chunks");
+        fs::create_dir_all(&chunks_dir).unwrap();
+        for (idx, chunk) in data.chunks(data.len().div_ceil(6)).enumerate() {
+            fs::write(chunks_dir.join(format!("chunk_{}.dat", idx)), chunk).unwrap();
+        }
+        let manifest = serde_json::json!({
+            "name": "legacy.bin",
+            "original_hash": crate::utils::blake3_hash_bytes(&data).unwrap(),
+            "size": data.len(),
+        });
+        fs::write(legacy_dir.join("manifest.json"), manifest.to_string()).unwrap();
+
+        let store = FileStore::new(&archive_dir).unwrap();
+        assert!(store.get_all().unwrap().is_empty());
+        assert_eq!(store.legacy_entries().unwrap(), vec![legacy_dir.clone()]);
+
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        store.migrate_legacy(&legacy_dir, &chunker).unwrap();
+
+        assert!(store.legacy_entries().unwrap().is_empty());
+        let file = store.find(&"legacy.bin".to_string()).unwrap();
+        let mut restored = Vec::new();
+        store.reconstruct_to(&file, &mut restored).unwrap();
+        assert_eq!(restored, data);
+    }
+
+    #[test]
+    fn test_file_list_cache_invalidation() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = setup_test_archive(temp_dir.path());
+        let store = FileStore::new(&archive_dir).unwrap();
+        assert_eq!(store.get_all().unwrap().len(), 1);
+
+        // editing a manifest in place doesn't touch the archive directory
+        let manifest_path = archive_dir.join("test.txt_abc123/manifest.json");
+        let edited = fs::read_to_string(&manifest_path)
+            .unwrap()
+            .replace("\"size\": 1000", "\"size\": 2000");
+        fs::write(&manifest_path, edited).unwrap();
+        assert_eq!(store.get_all().unwrap()[0].manifest.size, 1000);
+        store.invalidate();
+        assert_eq!(store.get_all().unwrap()[0].manifest.size, 2000);
+
+        // new entries change the directory's mtime, but that can be too coarse to
+        // rely on here
+        let copy = archive_dir.join("copy.txt_def456");
+        fs::create_dir_all(&copy).unwrap();
+        fs::write(
+            copy.join("manifest.json"),
+            fs::read_to_string(&manifest_path)
+                .unwrap()
+                .replace("test.txt", "copy.txt"),
+        )
+        .unwrap();
+        assert_eq!(store.refresh().unwrap().len(), 2);
+        assert!(store.find(&"copy.txt".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_find_all_and_find_by_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        let notes = temp_dir.path().join("notes.txt");
+        let mut hashes = Vec::new();
+        for content in ["one", "two"] {
+            fs::write(&notes, content).unwrap();
+            hashes.push(chunker.commit(&notes).unwrap().file_hash);
+        }
+        let copy = temp_dir.path().join("copy.txt");
+        fs::write(&copy, "two").unwrap();
+        chunker.commit(&copy).unwrap();
+
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let all = store.find_all("notes.txt").unwrap();
+        let all_hashes: Vec<&str> = all.iter().map(|f| f.file_data.hash.as_str()).collect();
+        assert_eq!(all_hashes, [hashes[0].as_str(), hashes[1].as_str()]);
+        assert_eq!(
+            store.find(&"notes.txt".to_string()).unwrap().file_data.hash,
+            hashes[1]
+        );
+        assert!(store.find_all("missing.txt").unwrap().is_empty());
+
+        // the original comes before the alias sharing its content
+        let found = store.find_by_hash(&hashes[1].to_uppercase()).unwrap();
+        assert_eq!(found.file_name, "notes.txt");
+        assert!(found.alias_of.is_none());
+        let copy = &store.find_all("copy.txt").unwrap()[0];
+        assert_eq!(copy.alias_of.as_deref(), Some("notes.txt"));
+        assert_eq!(
+            store.find_by_hash(&hashes[0]).unwrap().file_name,
+            "notes.txt"
+        );
+        assert!(store.find_by_hash(&"0".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_versions_of_a_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        for content in ["one", "two", "three"] {
+            fs::write(&path, content).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let read = |file: &File| {
+            let mut out = Vec::new();
+            store.reconstruct_to(file, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let versions = store.versions("notes.txt").unwrap();
+        let contents: Vec<String> = versions.iter().map(read).collect();
+        assert_eq!(contents, ["one", "two", "three"]);
+        assert_eq!(
+            read(&store.find(&"notes.txt".to_string()).unwrap()),
+            "three"
+        );
+        assert_eq!(read(&store.find_version("notes.txt", "1").unwrap()), "one");
+        let hash = &versions[1].file_data.hash[..8];
+        assert_eq!(read(&store.find_version("notes.txt", hash).unwrap()), "two");
+        assert!(store.find_version("notes.txt", "4").is_err());
+
+        let policy = versions::PrunePolicy {
+            keep_last: Some(2),
+            older_than: None,
+        };
+        let report = store.prune_versions(None, policy, true).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(store.versions("notes.txt").unwrap().len(), 3);
+        store.prune_versions(None, policy, false).unwrap();
+        let contents: Vec<String> = store
+            .versions("notes.txt")
+            .unwrap()
+            .iter()
+            .map(read)
+            .collect();
+        assert_eq!(contents, ["two", "three"]);
+
+        // the latest is never pruned, whatever the policy
+        let policy = versions::PrunePolicy {
+            keep_last: Some(0),
+            older_than: Some(std::time::Duration::ZERO),
+        };
+        store
+            .prune_versions(Some("notes.txt"), policy, false)
+            .unwrap();
+        assert_eq!(store.versions("notes.txt").unwrap().len(), 1);
+
+        // max_versions prunes as part of the commit
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .max_versions(2)
+            .build()
+            .unwrap();
+        for content in ["four", "five", "six"] {
+            fs::write(&path, content).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+        store.invalidate();
+        let contents: Vec<String> = store
+            .versions("notes.txt")
+            .unwrap()
+            .iter()
+            .map(read)
+            .collect();
+        assert_eq!(contents, ["five", "six"]);
+    }
+
+    #[test]
+    fn test_query_filters_sorts_and_pages() {
+        use crate::filestore::query::{FileQuery, SortKey};
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        for (name, size) in [("b.jpg", 30), ("A.JPG", 10), ("c.txt", 20), ("d.jpg", 40)] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, vec![b'x'; size]).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let names = |query: &FileQuery| -> (usize, Vec<String>) {
+            let (total, page) = store.query(query).unwrap();
+            (total, page.into_iter().map(|file| file.file_name).collect())
+        };
+
+        let (total, all) = names(&FileQuery::default());
+        assert_eq!(total, 4);
+        assert_eq!(all, ["A.JPG", "b.jpg", "c.txt", "d.jpg"]);
+
+        // glob matches case-sensitively, the substring filter doesn't
+        let photos = FileQuery::default().with_glob("*.jpg").unwrap();
+        assert_eq!(names(&photos).1, ["b.jpg", "d.jpg"]);
+        let photos = FileQuery::default().with_name("JPG");
+        assert_eq!(names(&photos).0, 3);
+
+        let largest = photos.with_sort(SortKey::Size, true).with_page(1, Some(1));
+        assert_eq!(names(&largest), (3, vec!["b.jpg".to_string()]));
+
+        assert_eq!(names(&FileQuery::default().with_tier(2)).0, 0);
+        assert_eq!(
+            names(&FileQuery::default().with_page(10, None)),
+            (4, vec![])
+        );
+        assert!(FileQuery::default().with_glob("[").is_err());
+        assert!("bogus".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn test_search_ignores_case_and_takes_globs() {
+        use crate::filestore::query::{FileQuery, SearchOptions};
+
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        for (name, content) in [
+            ("Movie.MKV", "a"),
+            ("clip.mkv", "b"),
+            ("notes.txt", "c"),
+            ("notes.txt", "d"),
+        ] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let search = |pattern: &str, options: SearchOptions| -> Vec<String> {
+            let found = store.search(pattern, options).unwrap();
+            found.into_iter().map(|file| file.file_name).collect()
+        };
+
+        assert_eq!(
+            search("*.mkv", SearchOptions::default()),
+            ["Movie.MKV", "clip.mkv"]
+        );
+        let exact = SearchOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(search("*.mkv", exact.clone()), ["clip.mkv"]);
+        assert_eq!(search("MOVIE", SearchOptions::default()), ["Movie.MKV"]);
+        assert!(search("MOVIE", exact).is_empty());
+
+        // the latest version only, unless asked for all
+        let notes = store.search("notes", SearchOptions::default()).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(
+            notes[0].file_data.hash,
+            store.find(&"notes.txt".to_string()).unwrap().file_data.hash
+        );
+        let all = SearchOptions {
+            all_versions: true,
+            ..Default::default()
+        };
+        assert_eq!(search("notes", all).len(), 2);
+        assert!(store.search("[", SearchOptions::default()).is_err());
+
+        let query = FileQuery::default().with_glob("*.MKV").unwrap();
+        assert_eq!(store.query(&query).unwrap().0, 1);
+        assert_eq!(store.query(&query.ignoring_case()).unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_tags_are_recorded_and_filtered() {
+        use crate::filestore::query::{FileQuery, SearchOptions};
+        use crate::tags::{TagFilter, Tags};
+
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive_directory");
+        let tagged = |tags: &[(&str, &str)]| {
+            let tags: Tags = tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            crate::chunker::Chunker::builder()
+                .archive_dir(&archive_dir)
+                .tags(tags)
+                .build()
+                .unwrap()
+        };
+        let commit = |chunker: &crate::chunker::Chunker, name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            chunker.commit(&path).unwrap()
+        };
+        commit(&tagged(&[("project", "apollo")]), "a.log", "one");
+        commit(&tagged(&[("project", "gemini")]), "b.log", "two");
+        commit(&tagged(&[]), "c.log", "three");
+        // tagging the same content again adds to its tags
+        commit(&tagged(&[("owner", "ops")]), "a.log", "one");
+        // an alias tags the entry holding its bytes
+        commit(&tagged(&[("copy", "yes")]), "b-copy.log", "two");
+
+        let store = FileStore::new(&archive_dir).unwrap();
+        let a = store.find(&"a.log".to_string()).unwrap();
+        assert_eq!(a.manifest.tags.len(), 2);
+        assert_eq!(
+            store.find(&"b.log".to_string()).unwrap().manifest.tags["copy"],
+            "yes"
+        );
+
+        let names = |query: FileQuery| -> Vec<String> {
+            let (_, files) = store.query(&query).unwrap();
+            files.into_iter().map(|file| file.file_name).collect()
+        };
+        let filter = |s: &str| s.parse::<TagFilter>().unwrap();
+        assert_eq!(
+            names(FileQuery::default().with_tag(filter("project"))),
+            ["a.log", "b-copy.log", "b.log"]
+        );
+        assert_eq!(
+            names(
+                FileQuery::default()
+                    .with_tag(filter("project=apollo"))
+                    .with_tag(filter("owner=ops"))
+            ),
+            ["a.log"]
+        );
+        let options = SearchOptions {
+            tags: vec![filter("project=gemini")],
+            ..Default::default()
+        };
+        let found = store.search("b.log", options).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].file_name, "b.log");
+    }
+
+    #[test]
+    fn test_health_history_records_every_check() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        let source = temp_dir.path().join("notes.txt");
+        fs::write(&source, vec![7u8; 4_000]).unwrap();
+        chunker.commit(&source).unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let name = "notes.txt".to_string();
+
+        store.batch_health_check().unwrap();
+        let file = store.find(&name).unwrap();
+        let entry = Path::new(&file.file_data.path).parent().unwrap();
+        fs::remove_file(entry.join("data.dat")).unwrap();
+        store.batch_health_check().unwrap();
+        store.repair(&file).unwrap();
+        store.batch_health_check().unwrap();
+        // a read-only store checks without recording
+        FileStore::new(chunker.archive_dir())
+            .unwrap()
+            .read_only(None)
+            .batch_health_check()
+            .unwrap();
+
+        let history = store.health_history(&name, None).unwrap();
+        let statuses: Vec<HealthStatus> = history.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                HealthStatus::Healthy,
+                HealthStatus::Recoverable,
+                HealthStatus::Healthy
+            ]
+        );
+        assert_eq!(history[1].missing_data, 1);
+        assert_eq!(store.health_history(&name, Some(1)).unwrap().len(), 1);
+        assert!(store.health_history("other.txt", None).unwrap().is_empty());
+
+        let summary = history::HistorySummary::of(&history);
+        assert_eq!(
+            (summary.checks, summary.unhealthy, summary.incidents),
+            (3, 1, 1)
+        );
+        assert!(!summary.deteriorating);
+
+        // more damage with each check while the incident lasts
+        let mut worsening = history[1..2].to_vec();
+        worsening.push(history::HealthRecord {
+            corrupt: 2,
+            ..history[1].clone()
+        });
+        assert!(history::HistorySummary::of(&worsening).deteriorating);
+    }
+
+    #[test]
+    fn test_plan_repairs_puts_the_files_closest_to_loss_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(1_000))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let mut entries = HashMap::new();
+        for (name, tier) in [("a.bin", 1), ("b.bin", 1), ("c.bin", 1), ("d.bin", 2)] {
+            let source = temp_dir.path().join(name);
+            fs::write(&source, vec![name.as_bytes()[0]; 3_000]).unwrap();
+            chunker.commit_as(&source, Some(tier)).unwrap();
+            let file = store.find(&name.to_string()).unwrap();
+            let dir = Path::new(&file.file_data.path)
+                .parent()
+                .unwrap()
+                .to_path_buf();
+            entries.insert(name, dir);
+        }
+        let remove = |name: &str, shards: &[&str]| {
+            for shard in shards {
+                fs::remove_file(entries[name].join(shard)).unwrap();
+            }
+        };
+        // one parity shard short, two to spare
+        remove("a.bin", &["parity_0.dat"]);
+        // data and two parity shards gone, one more loss and it's lost
+        remove("b.bin", &["data.dat", "parity_0.dat", "parity_1.dat"]);
+        // nothing left to repair from
+        remove(
+            "c.bin",
+            &["data.dat", "parity_0.dat", "parity_1.dat", "parity_2.dat"],
+        );
+        // one segment corrupt and one of its parity shards gone
+        fs::write(entries["d.bin"].join("segments/segment_1.dat"), b"garbage").unwrap();
+        remove("d.bin", &["parity/segment_1_parity_2.dat"]);
+
+        let plan = store.plan_repairs().unwrap();
+        let order: Vec<(&str, i64)> = plan
+            .iter()
+            .map(|task| (task.file_name.as_str(), task.margin))
+            .collect();
+        assert_eq!(order, [("b.bin", 0), ("d.bin", 1), ("a.bin", 2)]);
+        assert_eq!((plan[0].lost_data, plan[0].lost_parity), (1, 2));
+    }
+
+    #[test]
+    fn test_health_check_of_one_segment_or_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(1_000))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let mut files = Vec::new();
+        for (name, len, tier) in [("a.bin", 900, 1), ("b.bin", 3_000, 2), ("c.bin", 7_000, 3)] {
+            let source = temp_dir.path().join(name);
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            fs::write(&source, data).unwrap();
+            chunker.commit_as(&source, Some(tier)).unwrap();
+            files.push(store.find(&name.to_string()).unwrap());
+        }
+        let entry = |file: &File| {
+            Path::new(&file.file_data.path)
+                .parent()
+                .unwrap()
+                .to_path_buf()
+        };
+        fs::write(entry(&files[1]).join("segments/segment_1.dat"), b"bad").unwrap();
+        fs::write(
+            entry(&files[2]).join("blocks/block_0/segments/segment_5.dat"),
+            b"bad",
+        )
+        .unwrap();
+
+        let status = |report: HealthReport| report.status;
+        assert_eq!(
+            status(store.health_check_segment(&files[0], 0).unwrap()),
+            HealthStatus::Healthy
+        );
+        assert!(store.health_check_segment(&files[0], 1).is_err());
+
+        assert_eq!(
+            status(store.health_check_segment(&files[1], 0).unwrap()),
+            HealthStatus::Healthy
+        );
+        let report = store.health_check_segment(&files[1], 1).unwrap();
+        assert_eq!(report.status, HealthStatus::Recoverable);
+        assert_eq!(report.corrupt_segments, ["segment_1.dat"]);
+        assert!(store.health_check_segment(&files[1], 3).is_err());
+        assert!(store.health_check_block(&files[1], 0).is_err());
+
+        assert_eq!(
+            status(store.health_check_segment(&files[2], 2).unwrap()),
+            HealthStatus::Healthy
+        );
+        let report = store.health_check_segment(&files[2], 5).unwrap();
+        assert_eq!(report.status, HealthStatus::Recoverable);
+        assert_eq!(report.corrupt_segments, ["block_0/segment_5.dat"]);
+        assert_eq!(
+            status(store.health_check_block(&files[2], 0).unwrap()),
+            HealthStatus::Recoverable
+        );
+        assert!(store.health_check_block(&files[2], 1).is_err());
+        assert!(store.health_check_segment(&files[2], 7).is_err());
+    }
+
+    #[test]
+    fn test_sampled_health_check_is_repeatable() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(1_000))
+            .build()
+            .unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        let source = temp_dir.path().join("big.bin");
+        let data: Vec<u8> = (0..12_000).map(|i| (i % 241) as u8).collect();
+        fs::write(&source, data).unwrap();
+        chunker.commit_as(&source, Some(3)).unwrap();
+
+        let details = |per_file, seed| {
+            let batch = store.sample_health_check(per_file, seed).unwrap();
+            assert_eq!((batch.total_files, batch.healthy), (1, 1));
+            batch.reports[0].1.details.clone()
+        };
+        assert_eq!(details(3, 7), details(3, 7));
+        assert!(details(3, 7).starts_with("sampled 3 of 12 segments"));
+        assert!(details(50, 7).starts_with("sampled 12 of 12 segments"));
+
+        let file = store.find(&"big.bin".to_string()).unwrap();
+        let entry = Path::new(&file.file_data.path).parent().unwrap();
+        fs::write(entry.join("blocks/block_0/segments/segment_4.dat"), b"bad").unwrap();
+        let batch = store.sample_health_check(12, 1).unwrap();
+        assert_eq!(batch.recoverable, 1);
+        assert_eq!(
+            batch.reports[0].1.corrupt_segments,
+            ["block_0/segment_4.dat"]
+        );
+
+        let repaired = store.scrub_sampled_with(12, 1, |_| {}).unwrap();
+        assert_eq!(repaired.healthy, 1);
+    }
+
+    #[test]
+    fn test_trash_undelete_and_purge() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        for content in ["one", "two"] {
+            fs::write(&path, content).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+        let other = temp_dir.path().join("other.txt");
+        fs::write(&other, "other").unwrap();
+        chunker.commit(&other).unwrap();
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+
+        assert_eq!(store.trash("notes.txt").unwrap().len(), 2);
+        assert!(store.versions("notes.txt").unwrap().is_empty());
+        assert_eq!(store.get_all().unwrap().len(), 1);
+        let trashed = store.list_trash().unwrap();
+        assert_eq!(trashed.len(), 2);
+        assert!(trashed.iter().all(|entry| entry.file_name == "notes.txt"));
+        assert!(store.trash("notes.txt").is_err());
+
+        // nothing is old enough yet
+        assert!(
+            store
+                .purge_trash(std::time::Duration::from_secs(3600))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(store.undelete("notes.txt").unwrap().len(), 2);
+        let latest = store.find(&"notes.txt".to_string()).unwrap();
+        let mut out = Vec::new();
+        store.reconstruct_to(&latest, &mut out).unwrap();
+        assert_eq!(out, b"two");
+        assert!(store.list_trash().unwrap().is_empty());
+        assert!(store.undelete("notes.txt").is_err());
+
+        store.trash("other.txt").unwrap();
+        store.remove_permanently("notes.txt").unwrap();
+        assert!(store.get_all().unwrap().is_empty());
+        assert_eq!(store.list_trash().unwrap().len(), 1);
+        let purged = store.purge_trash(std::time::Duration::ZERO).unwrap();
+        assert_eq!(purged[0].file_name, "other.txt");
+        assert!(store.list_trash().unwrap().is_empty());
+        assert!(store.undelete("other.txt").is_err());
+    }
+
+    #[test]
+    fn test_filestore_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FileStore>();
+    }
 }
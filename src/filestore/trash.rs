@@ -0,0 +1,266 @@
+//! Removing files by moving them to the archive's trash first.
+//!
+//! [`FileStore::trash`] moves every entry of a name into
+//! `.trash/<timestamp>/` inside the archive, where the scan doesn't see it, and
+//! [`FileStore::undelete`] moves the latest removal of a name back.
+//! [`FileStore::purge_trash`] deletes removals older than the retention window
+//! for good. Parity written to a separate `parity_dir` stays where it is until
+//! the purge, the trashed manifest still points at it. Global parity is
+//! updated both ways: a removed entry is taken out of its volume and an
+//! undeleted one is added back.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use super::{FileStore, remove_entry_dir};
+use crate::alias::{self, ALIAS_FILE, Alias};
+use crate::audit::{AuditEntry, AuditOp};
+use crate::filestore::models::File;
+use crate::merkle_tree::manifest::ManifestFile;
+
+pub const TRASH_DIR: &str = ".trash";
+
+/// Names of removal directories, a suffix is added when two share a time.
+const REMOVAL_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// One entry in the trash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedEntry {
+    pub file_name: String,
+    pub hash: String,
+    /// When it was removed.
+    pub removed: DateTime<Utc>,
+    /// Its entry directory in the trash.
+    pub path: PathBuf,
+}
+
+impl FileStore {
+    /// Moves every version of `name` to the trash and returns them. Fails
+    /// without removing anything if a version is the original of an alias
+    /// under another name.
+    pub fn trash(&self, name: &str) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        self.remove_name(name, false)
+    }
+
+    /// Deletes every version of `name` without going through the trash.
+    pub fn remove_permanently(&self, name: &str) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        self.remove_name(name, true)
+    }
+
+    fn remove_name(
+        &self,
+        name: &str,
+        permanent: bool,
+    ) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        self.ensure_writable("rm")?;
+        let versions = self.versions(name)?;
+        if versions.is_empty() {
+            return Err(format!("File '{}' not found", name).into());
+        }
+        for file in &versions {
+            let aliased_by = alias::aliases_of(&self.store_path, &self.entry_dir(file));
+            if !aliased_by.is_empty() {
+                return Err(format!(
+                    "{} is the original of {}, remove those first",
+                    name,
+                    aliased_by.join(", ")
+                )
+                .into());
+            }
+        }
+
+        let removal = match permanent {
+            true => None,
+            false => Some(self.new_removal_dir()?),
+        };
+        for file in &versions {
+            let dir = self.entry_dir(file);
+            self.forget_global_parity(file)?;
+            let details = match &removal {
+                Some(removal) => {
+                    let target = removal.join(dir.file_name().ok_or("entry has no directory")?);
+                    fs::rename(&dir, &target)?;
+                    format!("moved to {}", target.display())
+                }
+                None => {
+                    remove_entry_dir(&dir)?;
+                    "removed permanently".to_string()
+                }
+            };
+            self.audit(
+                &AuditEntry::new(AuditOp::Delete, name)
+                    .hash(&file.file_data.hash)
+                    .details(details),
+            )?;
+        }
+        tracing::info!("TRASH | removed {} versions of {}", versions.len(), name);
+        self.invalidate();
+        Ok(versions)
+    }
+
+    /// Moves the entries of the latest removal of `name` back into the
+    /// archive and returns their directories. Fails if an entry with the same
+    /// content was committed since.
+    pub fn undelete(&self, name: &str) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        self.ensure_writable("undelete")?;
+        let trashed = self.list_trash()?;
+        let Some(latest) = trashed
+            .iter()
+            .filter(|entry| entry.file_name == name)
+            .map(|entry| entry.removed)
+            .max()
+        else {
+            return Err(format!("'{}' is not in the trash", name).into());
+        };
+        let entries: Vec<&TrashedEntry> = trashed
+            .iter()
+            .filter(|entry| entry.file_name == name && entry.removed == latest)
+            .collect();
+        for entry in &entries {
+            let target = self.restore_target(entry)?;
+            if target.exists() {
+                return Err(format!(
+                    "{} is archived again as {}, remove it before undeleting",
+                    name,
+                    target.display()
+                )
+                .into());
+            }
+        }
+
+        let mut restored = Vec::new();
+        for entry in entries {
+            let target = self.restore_target(entry)?;
+            fs::rename(&entry.path, &target)?;
+            if let Some(removal) = entry.path.parent() {
+                // fails while other names removed at the same time are still in it
+                let _ = fs::remove_dir(removal);
+            }
+            self.audit(
+                &AuditEntry::new(AuditOp::Undelete, name)
+                    .hash(&entry.hash)
+                    .details(format!("restored from {}", entry.path.display())),
+            )?;
+            restored.push(target);
+        }
+        tracing::info!("TRASH | restored {} entries of {}", restored.len(), name);
+        self.invalidate();
+
+        // the removal took them out of the global parity, put them back
+        for file in self.find_all(name)? {
+            let dir = self.entry_dir(&file);
+            if !restored.contains(&dir) {
+                continue;
+            }
+            // the entry is back either way, as after a commit that fails to add it
+            if let Err(e) = self.restore_global_parity(&file) {
+                tracing::warn!("TRASH | {} not added back to global parity: {}", name, e);
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Everything in the trash, oldest removal first.
+    pub fn list_trash(&self) -> Result<Vec<TrashedEntry>, Box<dyn std::error::Error>> {
+        let mut trashed = Vec::new();
+        let removals = match fs::read_dir(self.trash_dir()) {
+            Ok(removals) => removals,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(trashed),
+            Err(e) => return Err(e.into()),
+        };
+        for removal in removals.filter_map(|entry| entry.ok()) {
+            let Some(removed) = removal_time(&removal.path()) else {
+                tracing::warn!("TRASH | skipping unknown directory {:?}", removal.path());
+                continue;
+            };
+            for entry in fs::read_dir(removal.path())?.filter_map(|entry| entry.ok()) {
+                match describe_entry(&entry.path()) {
+                    Some((file_name, hash)) => trashed.push(TrashedEntry {
+                        file_name,
+                        hash,
+                        removed,
+                        path: entry.path(),
+                    }),
+                    None => tracing::warn!("TRASH | skipping unreadable {:?}", entry.path()),
+                }
+            }
+        }
+        trashed.sort_by(|a, b| a.removed.cmp(&b.removed).then(a.path.cmp(&b.path)));
+        Ok(trashed)
+    }
+
+    /// Deletes what was moved to the trash longer than `retention` ago and
+    /// returns it.
+    pub fn purge_trash(
+        &self,
+        retention: Duration,
+    ) -> Result<Vec<TrashedEntry>, Box<dyn std::error::Error>> {
+        self.ensure_writable("purging the trash")?;
+        let cutoff = Utc::now() - chrono::Duration::from_std(retention)?;
+        let expired: Vec<TrashedEntry> = self
+            .list_trash()?
+            .into_iter()
+            .filter(|entry| entry.removed <= cutoff)
+            .collect();
+        for entry in &expired {
+            remove_entry_dir(&entry.path)?;
+            if let Some(removal) = entry.path.parent() {
+                let _ = fs::remove_dir(removal);
+            }
+        }
+        if !expired.is_empty() {
+            tracing::info!("TRASH | purged {} entries", expired.len());
+        }
+        Ok(expired)
+    }
+
+    fn trash_dir(&self) -> PathBuf {
+        self.store_path.join(TRASH_DIR)
+    }
+
+    /// A new, empty directory in the trash named after the current time.
+    fn new_removal_dir(&self) -> Result<PathBuf, std::io::Error> {
+        let stamp = Utc::now().format(REMOVAL_TIME_FORMAT).to_string();
+        fs::create_dir_all(self.trash_dir())?;
+        let mut dir = self.trash_dir().join(&stamp);
+        let mut n = 1;
+        loop {
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(dir),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    dir = self.trash_dir().join(format!("{}-{}", stamp, n));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn restore_target(&self, entry: &TrashedEntry) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let dir_name = entry.path.file_name().ok_or("trashed entry has no name")?;
+        Ok(self.store_path.join(dir_name))
+    }
+}
+
+/// When the removal in `dir` was made, from its name.
+fn removal_time(dir: &Path) -> Option<DateTime<Utc>> {
+    let name = dir.file_name()?.to_str()?;
+    // drop the suffix of a removal that shared its time with another
+    let stamp = name.split_once('-').map_or(name, |(stamp, _)| stamp);
+    NaiveDateTime::parse_from_str(stamp, REMOVAL_TIME_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Name and hash of the entry in `dir`, from its manifest or alias file.
+fn describe_entry(dir: &Path) -> Option<(String, String)> {
+    if let Ok(manifest) = ManifestFile::new(dir.join("manifest.json").display().to_string()) {
+        return Some((manifest.name, manifest.original_hash));
+    }
+    let alias: Alias =
+        serde_json::from_str(&fs::read_to_string(dir.join(ALIAS_FILE)).ok()?).ok()?;
+    Some((alias.name, alias.original_hash))
+}
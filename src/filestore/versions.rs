@@ -0,0 +1,211 @@
+//! Earlier versions of a file name.
+//!
+//! Committing a name again with new content keeps the old entry next to the new
+//! one (see [`crate::chunker::OnExisting`]), each in its own `{name}_{hash}`
+//! directory. The catalog of versions is read from the entries themselves:
+//! every entry under a name is one version, ordered by commit time, so there is
+//! no index to fall out of step with the archive. Version 1 is the oldest one
+//! still archived and the highest number is the latest, which is what
+//! [`FileStore::find`] returns. Pruning removes old versions, so the numbers
+//! of the ones after it move down; a version can also be named by a prefix of
+//! its hash, which doesn't change.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Utc;
+
+use super::{FileStore, remove_entry_dir};
+use crate::alias;
+use crate::audit::{AuditEntry, AuditOp};
+use crate::filestore::models::File;
+use crate::naming;
+
+/// Which old versions [`FileStore::prune_versions`] removes. A version is
+/// removed when it breaks any rule that is set; the latest version of a name
+/// is always kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrunePolicy {
+    /// Keep at most this many versions per name, latest included.
+    pub keep_last: Option<usize>,
+    /// Remove versions committed longer ago than this.
+    pub older_than: Option<Duration>,
+}
+
+/// Outcome of [`FileStore::prune_versions`].
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    /// Versions removed, or that would be on a dry run.
+    pub removed: Vec<File>,
+    /// Versions the policy selected but that were kept, with the reason.
+    pub kept: Vec<(File, String)>,
+}
+
+impl FileStore {
+    /// Every archived name with the content hashes of its versions, oldest
+    /// first.
+    pub fn catalog(&self) -> Result<BTreeMap<String, Vec<String>>, Box<dyn std::error::Error>> {
+        let mut catalog: BTreeMap<String, Vec<File>> = BTreeMap::new();
+        for file in self.get_all()? {
+            catalog
+                .entry(file.file_name.clone())
+                .or_default()
+                .push(file);
+        }
+        Ok(catalog
+            .into_iter()
+            .map(|(name, mut files)| {
+                sort_versions(&mut files);
+                let hashes = files.into_iter().map(|file| file.file_data.hash).collect();
+                (name, hashes)
+            })
+            .collect())
+    }
+
+    /// The versions of `name`, oldest first. Empty if the name isn't archived.
+    pub fn versions(&self, name: &str) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        self.find_all(name)
+    }
+
+    /// One version of `name`, by its number counting from 1 for the oldest, or
+    /// by a prefix of its hash.
+    pub fn find_version(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<File, Box<dyn std::error::Error>> {
+        let versions = self.versions(name)?;
+        if versions.is_empty() {
+            return Err(format!("File '{}' not found", name).into());
+        }
+        let found = match version.parse::<usize>() {
+            Ok(number) if number >= 1 && number <= versions.len() => {
+                Some(versions[number - 1].clone())
+            }
+            _ => {
+                let mut matching = versions
+                    .iter()
+                    .filter(|file| file.file_data.hash.starts_with(version));
+                match (matching.next(), matching.next()) {
+                    (Some(file), None) => Some(file.clone()),
+                    (Some(_), Some(_)) => {
+                        return Err(
+                            format!("version {:?} of {} is ambiguous", version, name).into()
+                        );
+                    }
+                    _ => None,
+                }
+            }
+        };
+        found.ok_or_else(|| {
+            format!(
+                "{} has no version {:?}, it has versions 1 to {}",
+                name,
+                version,
+                versions.len()
+            )
+            .into()
+        })
+    }
+
+    /// Removes the old versions `policy` selects, of `name` or of every name.
+    /// A version other names are aliases of is kept, as it holds their data.
+    /// With `dry_run` nothing is removed.
+    pub fn prune_versions(
+        &self,
+        name: Option<&str>,
+        policy: PrunePolicy,
+        dry_run: bool,
+    ) -> Result<PruneReport, Box<dyn std::error::Error>> {
+        if !dry_run {
+            self.ensure_writable("prune")?;
+        }
+        let names: Vec<String> = match name {
+            Some(name) => vec![name.to_string()],
+            None => self.catalog()?.into_keys().collect(),
+        };
+        let cutoff = policy
+            .older_than
+            .map(chrono::Duration::from_std)
+            .transpose()?
+            .map(|age| Utc::now() - age);
+
+        let mut report = PruneReport::default();
+        for name in names {
+            let versions = self.versions(&name)?;
+            let count = versions.len();
+            for (idx, file) in versions.into_iter().enumerate() {
+                // newest first: 0 is the latest
+                let age_rank = count - 1 - idx;
+                if age_rank == 0 {
+                    continue;
+                }
+                let beyond_last = policy.keep_last.is_some_and(|keep| age_rank >= keep.max(1));
+                let too_old =
+                    cutoff.is_some_and(|cutoff| file.committed.is_none_or(|t| t < cutoff));
+                if !(beyond_last || too_old) {
+                    continue;
+                }
+
+                let dir = self.entry_dir(&file);
+                let aliased_by = alias::aliases_of(&self.store_path, &dir);
+                if !aliased_by.is_empty() {
+                    report.kept.push((
+                        file,
+                        format!("it is the original of {}", aliased_by.join(", ")),
+                    ));
+                    continue;
+                }
+                if !dry_run {
+                    self.forget_global_parity(&file)?;
+                    remove_entry_dir(&dir)?;
+                    self.audit(
+                        &AuditEntry::new(AuditOp::Delete, &name)
+                            .hash(&file.file_data.hash)
+                            .details(format!("pruned version {} of {}", idx + 1, count)),
+                    )?;
+                    tracing::info!("VERSIONS | pruned version {} of {}", idx + 1, name);
+                }
+                report.removed.push(file);
+            }
+        }
+        if !dry_run && !report.removed.is_empty() {
+            self.invalidate();
+        }
+        Ok(report)
+    }
+
+    /// The entry directory of `file`. An alias reads from its original's
+    /// directory but lives in one of its own.
+    pub(crate) fn entry_dir(&self, file: &File) -> PathBuf {
+        if file.alias_of.is_none()
+            && let Some(dir) = Path::new(&file.file_data.path).parent()
+        {
+            return dir.to_path_buf();
+        }
+        let dir = self.store_path.join(naming::entry_dir_name(
+            &file.file_name,
+            &file.file_data.hash,
+        ));
+        if dir.exists() {
+            dir
+        } else {
+            // named before entry directories were made portable
+            self.store_path
+                .join(format!("{}_{}", file.file_name, file.file_data.hash))
+        }
+    }
+}
+
+/// Oldest first, by commit time and then hash so the order is stable.
+fn sort_versions(files: &mut [File]) {
+    files.sort_by(version_order);
+}
+
+/// Orders versions of a name by commit time, oldest first.
+pub(crate) fn version_order(a: &File, b: &File) -> std::cmp::Ordering {
+    a.committed
+        .cmp(&b.committed)
+        .then_with(|| a.file_data.hash.cmp(&b.file_data.hash))
+}
@@ -1,8 +1,31 @@
+pub mod alias;
+pub mod audit;
+pub mod checksums;
 pub mod chunker;
+pub mod classify;
 pub mod config;
+pub mod container;
+#[cfg(all(feature = "serve", feature = "remote"))]
+pub mod daemon;
+pub mod direct_io;
+pub mod erasure;
 pub mod filestore;
+#[cfg(feature = "logging")]
+pub mod logging;
+pub mod memstats;
 pub mod merkle_tree;
 pub mod mount;
+pub mod naming;
+pub mod negative_cache;
+#[cfg(feature = "remote")]
+pub mod notify;
+pub mod pack;
+pub mod prelude;
+#[cfg(feature = "serve")]
 pub mod serve;
+pub mod signing;
+pub mod tags;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring;
 
 pub mod utils;
@@ -0,0 +1,212 @@
+//! Installs the global tracing subscriber from the `[logging]` config section.
+//!
+//! Events go to stdout, or to the systemd journal with `journald = true`, and
+//! to a log file in `directory`. The file is rotated by time with
+//! `tracing-appender`, or by size with [`SizeRotatingFile`], and the oldest
+//! files are removed once there are more than `max_files`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, prelude::*};
+
+use crate::config::{LogFormat, LogRotation, LoggingConfig, parse_size};
+
+/// Name of the log file, rotated files get a date or number appended.
+pub const LOG_FILE_NAME: &str = "blockframe.log";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Keeps the background writers running. Dropping it flushes what is still
+/// queued, so hold it until the process is about to exit.
+#[must_use = "logging stops when the guard is dropped"]
+pub struct LogGuard {
+    _guards: Vec<WorkerGuard>,
+}
+
+/// Installs the subscriber described by `config`. `RUST_LOG`, when set,
+/// takes precedence over `config.level`. Fails if a subscriber is already
+/// installed.
+pub fn init(config: &LoggingConfig) -> Result<LogGuard, Box<dyn std::error::Error>> {
+    let mut guards = Vec::new();
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+
+    if config.journald {
+        layers.push(journald_layer()?);
+    } else {
+        let (writer, guard) = non_blocking(io::stdout());
+        guards.push(guard);
+        layers.push(match config.format {
+            LogFormat::Text => fmt::layer()
+                .with_writer(writer)
+                .with_target(true)
+                .with_thread_ids(true)
+                .boxed(),
+            LogFormat::Json => fmt::layer()
+                .json()
+                .with_writer(writer)
+                .with_thread_ids(true)
+                .boxed(),
+        });
+    }
+
+    if !config.directory.as_os_str().is_empty() {
+        let (writer, guard) = match config.rotation {
+            LogRotation::Size => non_blocking(SizeRotatingFile::new(
+                &config.directory,
+                parse_size(&config.max_size)? as u64,
+                config.max_files,
+            )?),
+            rotation => {
+                let rotation = match rotation {
+                    LogRotation::Minutely => Rotation::MINUTELY,
+                    LogRotation::Hourly => Rotation::HOURLY,
+                    LogRotation::Daily => Rotation::DAILY,
+                    _ => Rotation::NEVER,
+                };
+                let mut builder = RollingFileAppender::builder()
+                    .rotation(rotation)
+                    .filename_prefix(LOG_FILE_NAME);
+                if config.max_files > 0 {
+                    builder = builder.max_log_files(config.max_files);
+                }
+                non_blocking(builder.build(&config.directory)?)
+            }
+        };
+        guards.push(guard);
+        layers.push(match config.format {
+            LogFormat::Text => fmt::layer().with_writer(writer).with_ansi(false).boxed(),
+            LogFormat::Json => fmt::layer().json().with_writer(writer).boxed(),
+        });
+    }
+
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+    tracing::subscriber::set_global_default(Registry::default().with(layers).with(filter))?;
+    Ok(LogGuard { _guards: guards })
+}
+
+#[cfg(unix)]
+fn journald_layer() -> Result<BoxedLayer, Box<dyn std::error::Error>> {
+    let layer = tracing_journald::layer()
+        .map_err(|e| format!("can't connect to the systemd journal: {}", e))?;
+    Ok(layer.boxed())
+}
+
+#[cfg(not(unix))]
+fn journald_layer() -> Result<BoxedLayer, Box<dyn std::error::Error>> {
+    Err("logging.journald is only available on Linux".into())
+}
+
+/// `blockframe.log` in a directory, moved to `blockframe.log.1` once it
+/// reaches `max_size`, with older files shifted up one number.
+pub struct SizeRotatingFile {
+    directory: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeRotatingFile {
+    /// Opens the log file in `directory` for appending, creating both if
+    /// needed. `max_files` counts the current file, 0 keeps every file.
+    pub fn new(directory: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(LOG_FILE_NAME);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            max_size: max_size.max(1),
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn numbered(&self, n: usize) -> PathBuf {
+        self.directory.join(format!("{}.{}", LOG_FILE_NAME, n))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let mut last = 0;
+        while self.numbered(last + 1).exists() {
+            last += 1;
+        }
+        let keep = match self.max_files {
+            0 => usize::MAX,
+            n => n - 1,
+        };
+        for n in (1..=last).rev() {
+            if n >= keep {
+                fs::remove_file(self.numbered(n))?;
+            } else {
+                fs::rename(self.numbered(n), self.numbered(n + 1))?;
+            }
+        }
+        let path = self.directory.join(LOG_FILE_NAME);
+        if keep == 0 {
+            fs::remove_file(&path)?;
+        } else {
+            fs::rename(&path, self.numbered(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_rotation_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = SizeRotatingFile::new(dir.path(), 10, 3).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read(LOG_FILE_NAME), "fourth\n");
+        assert_eq!(read("blockframe.log.1"), "third\n");
+        assert_eq!(read("blockframe.log.2"), "second\n");
+        assert!(!dir.path().join("blockframe.log.3").exists());
+    }
+
+    #[test]
+    fn test_size_rotation_continues_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(LOG_FILE_NAME), "0123456789").unwrap();
+        let mut log = SizeRotatingFile::new(dir.path(), 10, 0).unwrap();
+        log.write_all(b"next\n").unwrap();
+        log.flush().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("blockframe.log.1")).unwrap(),
+            "0123456789"
+        );
+    }
+}
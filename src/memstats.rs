@@ -0,0 +1,201 @@
+//! Memory used while committing a file.
+//!
+//! Built with the `mem-stats` feature, the crate installs a global allocator
+//! that counts allocations on top of the system one, and each commit reports
+//! what it allocated in [`ChunkedFile::memory`](crate::chunker::ChunkedFile):
+//! the peak of live heap bytes, how many allocations it made, and the process'
+//! peak resident set size. That shows how a segment size or tier behaves on a
+//! machine with little memory before relying on it there.
+//!
+//! Without the feature nothing is counted and `memory` is `None`. The heap
+//! figures are process wide, so with several commits in flight, as in
+//! [`Chunker::commit_many`](crate::chunker::Chunker::commit_many) with more
+//! than one job, each commit's figures include the others.
+
+use serde::Serialize;
+
+/// Memory a commit used, see the [module docs](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    /// Most heap bytes live at once during the commit, above what was live
+    /// when it started.
+    pub peak_heap_bytes: u64,
+    /// Allocations made during the commit.
+    pub allocations: u64,
+    /// Bytes allocated during the commit, counting memory that was freed and
+    /// allocated again.
+    pub allocated_bytes: u64,
+    /// Highest resident set size of the process so far, where the platform
+    /// reports it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Measures the memory used between [`MemoryProbe::start`] and
+/// [`MemoryProbe::finish`].
+pub struct MemoryProbe {
+    #[cfg(feature = "mem-stats")]
+    start: counting::Snapshot,
+}
+
+impl MemoryProbe {
+    /// Starts measuring. `None` when the crate is built without `mem-stats`.
+    pub fn start() -> Option<Self> {
+        #[cfg(feature = "mem-stats")]
+        {
+            Some(Self {
+                start: counting::Snapshot::take_and_reset_peak(),
+            })
+        }
+        #[cfg(not(feature = "mem-stats"))]
+        {
+            None
+        }
+    }
+
+    /// What was used since [`MemoryProbe::start`].
+    pub fn finish(self) -> MemoryUsage {
+        #[cfg(feature = "mem-stats")]
+        {
+            let end = counting::Snapshot::take();
+            MemoryUsage {
+                peak_heap_bytes: end.peak.saturating_sub(self.start.current),
+                allocations: end.allocations - self.start.allocations,
+                allocated_bytes: end.allocated - self.start.allocated,
+                peak_rss_bytes: peak_rss(),
+            }
+        }
+        #[cfg(not(feature = "mem-stats"))]
+        {
+            MemoryUsage {
+                peak_rss_bytes: peak_rss(),
+                ..MemoryUsage::default()
+            }
+        }
+    }
+}
+
+/// Highest resident set size of this process so far.
+#[cfg(unix)]
+pub fn peak_rss() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes into the struct it is given
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+        usage.assume_init()
+    };
+    let max = u64::try_from(usage.ru_maxrss).ok()?;
+    // kilobytes everywhere but macOS, which reports bytes
+    if cfg!(target_os = "macos") {
+        Some(max)
+    } else {
+        Some(max * 1024)
+    }
+}
+
+/// Highest resident set size of this process so far.
+#[cfg(not(unix))]
+pub fn peak_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(feature = "mem-stats")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+    /// The system allocator, counting what goes through it.
+    struct Counting;
+
+    static CURRENT: AtomicU64 = AtomicU64::new(0);
+    static PEAK: AtomicU64 = AtomicU64::new(0);
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+    static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    fn grow(bytes: u64) {
+        let now = CURRENT.fetch_add(bytes, Relaxed) + bytes;
+        PEAK.fetch_max(now, Relaxed);
+        ALLOCATIONS.fetch_add(1, Relaxed);
+        ALLOCATED.fetch_add(bytes, Relaxed);
+    }
+
+    // SAFETY: every call is passed straight to `System`, only counters are
+    // updated around it
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                grow(layout.size() as u64);
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc_zeroed(layout) };
+            if !ptr.is_null() {
+                grow(layout.size() as u64);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            CURRENT.fetch_sub(layout.size() as u64, Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new = unsafe { System.realloc(ptr, layout, new_size) };
+            if !new.is_null() {
+                CURRENT.fetch_sub(layout.size() as u64, Relaxed);
+                grow(new_size as u64);
+            }
+            new
+        }
+    }
+
+    pub(super) struct Snapshot {
+        pub current: u64,
+        pub peak: u64,
+        pub allocations: u64,
+        pub allocated: u64,
+    }
+
+    impl Snapshot {
+        pub fn take() -> Self {
+            Self {
+                current: CURRENT.load(Relaxed),
+                peak: PEAK.load(Relaxed),
+                allocations: ALLOCATIONS.load(Relaxed),
+                allocated: ALLOCATED.load(Relaxed),
+            }
+        }
+
+        /// Starts a new peak from what is live now.
+        pub fn take_and_reset_peak() -> Self {
+            PEAK.store(CURRENT.load(Relaxed), Relaxed);
+            Self::take()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mem-stats"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_sees_allocations() {
+        // other tests commit at the same time, so only the running totals
+        // are certain to include this buffer
+        let probe = MemoryProbe::start().unwrap();
+        let buffer = std::hint::black_box(vec![1u8; 4 << 20]);
+        let usage = probe.finish();
+        drop(buffer);
+        assert!(usage.allocations >= 1, "{:?}", usage);
+        assert!(usage.allocated_bytes >= 4 << 20, "{:?}", usage);
+        assert_eq!(usage.peak_rss_bytes.is_some(), cfg!(unix));
+    }
+}
@@ -1,18 +1,93 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+};
 
-use crate::{merkle_tree::MerkleTree, utils::blake3_hash_bytes};
+use crate::{
+    merkle_tree::MerkleTree,
+    utils::{blake3_hash_bytes, deterministic_segment_size},
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SegmentHashes {
     pub data: String,
     pub parity: Vec<String>,
+    /// The segment is all zeros, kept on disk as a hole, see
+    /// [`crate::utils::write_shard`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub zero: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BlockHashes {
     pub segments: Vec<String>,
     pub parity: Vec<String>,
+    /// Data shards the block was encoded with, see [`BlockHashes::geometry`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_shards: Option<usize>,
+    /// Parity shards the block was encoded with, fewer for a partial block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parity_shards: Option<usize>,
+    /// Bytes every shard of the block was padded to for encoding, the length
+    /// of its longest segment rounded up to 64 and of each parity shard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard_size: Option<u64>,
+    /// Length of each segment before padding, recovered segments are cut back
+    /// to this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment_sizes: Option<Vec<u64>>,
+    /// Segments per local parity group, for a block committed with local
+    /// parity, see [`crate::erasure::local_parity`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_group: Option<usize>,
+    /// Hash of each group's local parity shard. They are stored after the
+    /// global ones, local parity `g` as parity shard `parity_shards + g`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub local_parity: Vec<String>,
+    /// Segments of the block that are all zeros, kept on disk as holes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zero_segments: Vec<usize>,
+}
+
+impl BlockHashes {
+    /// `(data, parity)` shard counts to decode the block with. Blocks written
+    /// before the counts were recorded fall back to the number of hashes.
+    pub fn geometry(&self) -> (usize, usize) {
+        (
+            self.data_shards.unwrap_or(self.segments.len()),
+            self.parity_shards.unwrap_or(self.parity.len()),
+        )
+    }
+
+    /// Hash of parity shard `parity_id`, global or, past the global ones, local.
+    pub fn parity_hash(&self, parity_id: usize) -> Option<&String> {
+        let (_, global) = self.geometry();
+        match parity_id.checked_sub(global) {
+            Some(local) => self.local_parity.get(local),
+            None => self.parity.get(parity_id),
+        }
+    }
+
+    /// The local group segment `idx` is in, with the range of segments it
+    /// covers, if the block has local parity.
+    pub fn local_group_of(&self, idx: usize) -> Option<(usize, std::ops::Range<usize>)> {
+        let size = self.local_group.filter(|&size| size > 0)?;
+        let group = idx / size;
+        if group >= self.local_parity.len() {
+            return None;
+        }
+        let (data_shards, _) = self.geometry();
+        Some((group, group * size..((group + 1) * size).min(data_shards)))
+    }
+
+    /// Unpadded length of segment `idx`, if the manifest recorded it.
+    pub fn segment_len(&self, idx: usize) -> Option<usize> {
+        self.segment_sizes
+            .as_ref()?
+            .get(idx)
+            .map(|&len| len as usize)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -32,6 +107,45 @@ pub struct MerkleTreeStructure {
     pub blocks: HashMap<usize, BlockHashes>,
     pub root: String,
 }
+/// One file stored inside a pack entry, see [`crate::pack`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PackMember {
+    pub name: String,
+    /// Byte offset of the file within the pack's original data.
+    pub offset: u64,
+    pub size: u64,
+    /// BLAKE3 of the file's own bytes.
+    pub hash: String,
+}
+
+/// One file inside a committed tar or zip, see [`crate::container`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ContainerMember {
+    /// Path of the file inside the container.
+    pub name: String,
+    /// Byte offset of the member's stored bytes within the container.
+    pub offset: u64,
+    /// Bytes stored in the container, compressed for a deflated zip member.
+    pub size: u64,
+    /// Size once inflated, for a zip member stored deflated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inflated_size: Option<u64>,
+    /// BLAKE3 of the member's own bytes, after inflating.
+    pub hash: String,
+}
+
+/// What kind of file an entry holds, worked out at commit time, see
+/// [`crate::classify`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ContentClass {
+    /// The name's extension, lowercased.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+    /// The type the file's first bytes show, e.g. `image/png`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ManifestFile {
     pub erasure_coding: ErasureCoding,
@@ -42,6 +156,30 @@ pub struct ManifestFile {
     pub time_of_creation: String,
     pub tier: u8,
     pub segment_size: u64,
+    /// How `segment_size` was chosen, see [`crate::utils::SegmentPolicy`]. Missing for tier 1
+    /// and for entries committed before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment_policy: Option<String>,
+    /// Files packed into this entry. Empty, and left out of the JSON, for
+    /// ordinary entries.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pack: Vec<PackMember>,
+    /// Files inside a tar or zip committed with its members indexed. Empty,
+    /// and left out of the JSON, otherwise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<ContainerMember>,
+    /// Missing for entries committed before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<ContentClass>,
+    /// Directory holding the parity shards when they were written outside the
+    /// entry, see [`crate::chunker::ChunkerBuilder::parity_dir`]. Missing when
+    /// they sit next to the data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parity_dir: Option<String>,
+    /// Key/value tags given at commit time, see [`crate::tags`]. Empty, and
+    /// left out of the JSON, when there are none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
 }
 
 impl ManifestFile {
@@ -66,6 +204,21 @@ impl ManifestFile {
             return Ok(false);
         }
 
+        // a block's recorded shard counts must match its hashes
+        for block in self.merkle_tree.blocks.values() {
+            if block.geometry() != (block.segments.len(), block.parity.len()) {
+                return Ok(false);
+            }
+            if let Some(sizes) = &block.segment_sizes
+                && (sizes.len() != block.segments.len()
+                    || block
+                        .shard_size
+                        .is_some_and(|max| sizes.iter().any(|&s| s > max)))
+            {
+                return Ok(false);
+            }
+        }
+
         // check each leaf hash
         for hash in self.merkle_tree.leaves.values() {
             if !Self::is_valid_hash(hash)? {
@@ -86,6 +239,23 @@ impl ManifestFile {
         Ok(true)
     }
 
+    /// Checks that a manifest recorded as `deterministic` has the segment size
+    /// its file size calls for, so it matches a commit of the same file on any
+    /// other host. Other policies depend on the committing host and pass.
+    pub fn check_segment_size(&self) -> Result<(), String> {
+        if self.segment_policy.as_deref() != Some("deterministic") {
+            return Ok(());
+        }
+        let expected = deterministic_segment_size(self.size.max(0) as u64) as u64;
+        if self.segment_size != expected {
+            return Err(format!(
+                "manifest records a deterministic segment size of {} bytes, but {} bytes calls for {}",
+                self.segment_size, self.size, expected
+            ));
+        }
+        Ok(())
+    }
+
     /// Checks whether the supplied string is a 64-character hexadecimal hash.
     ///
     /// # Examples
@@ -99,7 +269,7 @@ impl ManifestFile {
     /// # }
     /// ```
     pub fn is_valid_hash(hash: &str) -> Result<bool, std::io::Error> {
-        Ok(hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        Ok(blockframe_core::merkle::is_hash(hash))
     }
 
     /// Verifies that the hashes in the manifest match a collection of chunk
@@ -124,12 +294,20 @@ impl ManifestFile {
     ///     size: 10,
     ///     tier: 1,
     ///     segment_size: 0,
+    ///     segment_policy: None,
     ///     time_of_creation: "2024-01-01T00:00:00Z".to_string(),
     ///     erasure_coding: ErasureCoding { r#type: "reed_solomon".to_string(), data_shards: 1, parity_shards: 3 },
     ///     merkle_tree: MerkleTreeStructure {
     ///         leaves,
+    ///         segments: HashMap::new(),
+    ///         blocks: HashMap::new(),
     ///         root: tree.get_root()?.to_string(),
     ///     },
+    ///     pack: Vec::new(),
+    ///     members: Vec::new(),
+    ///     content: None,
+    ///     parity_dir: None,
+    ///     tags: Default::default(),
     /// };
     /// assert!(manifest.verify_against_chunks(&chunks)?);
     /// # Ok(())
@@ -1,4 +1,5 @@
 use crate::{merkle_tree::node::Node, utils::blake3_hash_bytes};
+use blockframe_core::merkle::{parent_hash, verify_indexed_proof};
 use serde_json::{self, Value, json};
 
 #[derive(Debug)]
@@ -89,10 +90,7 @@ impl MerkleTree {
                 nodes[i].clone()
             };
 
-            let combined_hashes = format!("{}{}", left.hash_val, right.hash_val)
-                .as_bytes()
-                .to_vec();
-            let combined = blake3_hash_bytes(&combined_hashes)?;
+            let combined = parent_hash(&left.hash_val, &right.hash_val);
             let parent = Node::with_children(combined, Some(Box::new(left)), Some(Box::new(right)));
             new_level.push(parent);
         }
@@ -134,14 +132,8 @@ impl MerkleTree {
             for i in (0..level.len()).step_by(2) {
                 let left = level[i].clone();
                 let right = level[i + 1].clone();
-                let combined_hashes = format!("{}{}", left.hash_val, right.hash_val)
-                    .as_bytes()
-                    .to_vec();
-
-                let parent_hash = blake3_hash_bytes(&combined_hashes)?;
-
                 let parent = Node::with_children(
-                    parent_hash,
+                    parent_hash(&left.hash_val, &right.hash_val),
                     Some(Box::new(left.clone())),
                     Some(Box::new(right.clone())),
                 );
@@ -180,24 +172,7 @@ impl MerkleTree {
         proof: &[String],
         root_hash: String,
     ) -> Result<bool, std::io::Error> {
-        let mut current_hash = blake3_hash_bytes(chunk)?;
-        let mut chunk_index = chunk_index;
-        for sibling_hash in proof {
-            if chunk_index.is_multiple_of(2) {
-                let combined_hashes = format!("{}{}", current_hash, sibling_hash)
-                    .as_bytes()
-                    .to_vec();
-                current_hash = blake3_hash_bytes(&combined_hashes)?;
-            } else {
-                let combined_hashes_else = format!("{}{}", sibling_hash, current_hash)
-                    .as_bytes()
-                    .to_vec();
-                current_hash = blake3_hash_bytes(&combined_hashes_else)?;
-            }
-
-            chunk_index /= 2;
-        }
-        Ok(current_hash == root_hash)
+        Ok(verify_indexed_proof(chunk, chunk_index, proof, &root_hash))
     }
 
     /// Returns the root hash of the Merkle tree as a string slice.
@@ -1,12 +1,21 @@
 use moka::sync::Cache;
-use std::sync::Arc;
+use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::time::Duration;
 
+use bytes::Bytes;
+
+/// handle -> (cache key, segment)
+type PinnedSegments = HashMap<u64, (String, Bytes)>;
+
 pub struct SegmentCache {
     // Moka handles thread safety, eviction, and weighing internally.
     // No manual byte tracking, no manual eviction loops, just works.
-    cache: Cache<String, Arc<Vec<u8>>>,
+    cache: Cache<String, Bytes>,
     max_bytes: u64,
+    // The segment each open handle is reading, kept outside moka so eviction
+    // under memory pressure can't pull it out from under a sequential read.
+    pinned: Mutex<PinnedSegments>,
 }
 
 #[derive(Debug)]
@@ -14,6 +23,8 @@ pub struct CacheStats {
     pub items: u64,
     pub bytes: u64,
     pub max_bytes: u64,
+    /// Segments held by open handles, counted once per handle.
+    pub pinned: u64,
 }
 
 impl SegmentCache {
@@ -27,37 +38,79 @@ impl SegmentCache {
         // W-TinyLFU cache that evicts based on SIZE (bytes) and FREQUENCY.
         // The weigher tells moka how "heavy" each item is.
         let cache = Cache::builder()
-            .weigher(|_key: &String, value: &Arc<Vec<u8>>| -> u32 {
+            .weigher(|_key: &String, value: &Bytes| -> u32 {
                 // Each segment's weight = its size in bytes
                 value.len().try_into().unwrap_or(u32::MAX)
             })
             .max_capacity(max_bytes)
             // TTL prevents stale data if files change on disk
             .time_to_live(Duration::from_secs(60 * 60)) // 1 hour
+            .support_invalidation_closures()
             .build();
 
-        Self { cache, max_bytes }
+        Self {
+            cache,
+            max_bytes,
+            pinned: Mutex::new(HashMap::new()),
+        }
     }
 
-    /// Zero-copy getter. Returns Arc clone (cheap), no data copy.
-    pub fn get(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+    /// Zero-copy getter. Returns a `Bytes` clone (cheap), no data copy.
+    pub fn get(&self, key: &str) -> Option<Bytes> {
         // Moka's get() automatically promotes frequently accessed items.
         // Unlike LRU, one-hit wonders don't pollute the cache.
-        self.cache.get(key)
+        self.cache.get(key).or_else(|| {
+            self.pinned
+                .lock()
+                .values()
+                .find(|(pinned_key, _)| pinned_key == key)
+                .map(|(_, data)| data.clone())
+        })
+    }
+
+    /// Keeps `data` available under `key` for as long as `handle` is reading it.
+    /// Each handle pins one segment; pinning another replaces it.
+    pub fn pin(&self, handle: u64, key: &str, data: Bytes) {
+        let mut pinned = self.pinned.lock();
+        if pinned.get(&handle).is_some_and(|(k, _)| k == key) {
+            return;
+        }
+        pinned.insert(handle, (key.to_string(), data));
+    }
+
+    /// Releases whatever `handle` had pinned, called when the handle closes.
+    pub fn unpin(&self, handle: u64) {
+        self.pinned.lock().remove(&handle);
     }
 
-    pub fn put(&self, key: String, value: Arc<Vec<u8>>) {
+    pub fn put(&self, key: String, value: Bytes) {
         // No manual eviction loop needed. Moka uses W-TinyLFU to decide
         // what stays based on access frequency and recency.
         // Streaming segments (accessed once) won't evict hot metadata.
         self.cache.insert(key, value);
     }
 
+    /// Drops every cached segment of `filename`, e.g. after it left the archive.
+    pub fn invalidate_file(&self, filename: &str) {
+        // keys are "<filename>:<segment>" or "<filename>:block<b>:seg<s>"
+        let prefix = format!("{}:", filename);
+        self.pinned
+            .lock()
+            .retain(|_, (key, _)| !key.starts_with(&prefix));
+        if let Err(e) = self
+            .cache
+            .invalidate_entries_if(move |key, _| key.starts_with(&prefix))
+        {
+            tracing::warn!("CACHE | could not evict {}: {}", filename, e);
+        }
+    }
+
     pub fn stats(&self) -> CacheStats {
         CacheStats {
             items: self.cache.entry_count(),
             bytes: self.cache.weighted_size(),
             max_bytes: self.max_bytes,
+            pinned: self.pinned.lock().len() as u64,
         }
     }
 
@@ -66,21 +119,28 @@ impl SegmentCache {
         filename: &str,
         segment_id: usize,
         fetch: F,
-    ) -> Result<Arc<Vec<u8>>, Box<dyn std::error::Error>>
+    ) -> Result<Bytes, Box<dyn std::error::Error>>
     where
-        F: FnOnce() -> Result<Vec<u8>, Box<dyn std::error::Error>>,
+        F: FnOnce() -> Result<Bytes, Box<dyn std::error::Error>>,
     {
-        let key = format!("{}:{}", filename, segment_id);
+        self.get_or_load(&format!("{}:{}", filename, segment_id), fetch)
+    }
 
-        // Check cache first
-        if let Some(data) = self.cache.get(&key) {
+    /// Returns the segment under `key`, fetching and inserting it on a miss.
+    /// Concurrent misses on the same key wait for the first fetch instead of
+    /// repeating it, so parallel reads into one segment download it once.
+    /// A failed fetch is returned to every reader waiting on it.
+    pub fn get_or_load<F>(&self, key: &str, fetch: F) -> Result<Bytes, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<Bytes, Box<dyn std::error::Error>>,
+    {
+        if let Some(data) = self.get(key) {
             return Ok(data);
         }
-
-        // Cache miss - fetch and insert
-        let data = Arc::new(fetch()?);
-        self.cache.insert(key, data.clone());
-        Ok(data)
+        // moka wants an error it can share between the waiting readers
+        self.cache
+            .try_get_with(key.to_string(), || fetch().map_err(|e| e.to_string()))
+            .map_err(|e| e.as_str().into())
     }
 }
 #[cfg(test)]
@@ -92,13 +152,13 @@ mod tests {
         let cache = SegmentCache::new_with_limits(100); // 100 byte limit
 
         // Insert 50 byte segment
-        cache.put("seg1".to_string(), Arc::new(vec![0u8; 50]));
+        cache.put("seg1".to_string(), Bytes::from(vec![0u8; 50]));
 
         // Insert another 50 byte segment
-        cache.put("seg2".to_string(), Arc::new(vec![0u8; 50]));
+        cache.put("seg2".to_string(), Bytes::from(vec![0u8; 50]));
 
         // Insert 60 byte segment - W-TinyLFU decides what to evict
-        cache.put("seg3".to_string(), Arc::new(vec![0u8; 60]));
+        cache.put("seg3".to_string(), Bytes::from(vec![0u8; 60]));
 
         // Give moka time to process evictions (async internally)
         std::thread::sleep(std::time::Duration::from_millis(100));
@@ -112,14 +172,14 @@ mod tests {
         let cache = SegmentCache::new_with_limits(100);
 
         // Insert hot segment and access it multiple times
-        cache.put("hot".to_string(), Arc::new(vec![0u8; 40]));
+        cache.put("hot".to_string(), Bytes::from(vec![0u8; 40]));
         for _ in 0..10 {
             cache.get("hot");
         }
 
         // Insert one-hit wonder segments (like streaming video)
-        cache.put("cold1".to_string(), Arc::new(vec![0u8; 40]));
-        cache.put("cold2".to_string(), Arc::new(vec![0u8; 40]));
+        cache.put("cold1".to_string(), Bytes::from(vec![0u8; 40]));
+        cache.put("cold2".to_string(), Bytes::from(vec![0u8; 40]));
 
         std::thread::sleep(std::time::Duration::from_millis(100));
         cache.cache.run_pending_tasks();
@@ -127,4 +187,45 @@ mod tests {
         // W-TinyLFU should keep the frequently accessed "hot" item
         assert!(cache.get("hot").is_some());
     }
+
+    #[test]
+    fn test_pinned_segment_survives_eviction() {
+        let cache = SegmentCache::new_with_limits(100);
+        let segment = Bytes::from(vec![7u8; 60]);
+        cache.put("movie:0".to_string(), segment.clone());
+        cache.pin(1, "movie:0", segment);
+
+        // a burst of other reads far beyond the limit
+        for i in 0..50 {
+            cache.put(format!("other:{}", i), Bytes::from(vec![0u8; 60]));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        cache.cache.run_pending_tasks();
+
+        assert!(cache.stats().bytes <= 100);
+        assert_eq!(cache.get("movie:0").unwrap()[0], 7);
+
+        cache.unpin(1);
+        cache.cache.invalidate("movie:0");
+        assert!(cache.get("movie:0").is_none());
+        assert_eq!(cache.stats().pinned, 0);
+    }
+
+    #[test]
+    fn test_pin_is_per_handle() {
+        let cache = SegmentCache::new_with_limits(100);
+        cache.pin(1, "a:0", Bytes::from(vec![1u8; 10]));
+        cache.pin(2, "b:0", Bytes::from(vec![2u8; 10]));
+        // moving handle 1 on releases its previous segment
+        cache.pin(1, "a:1", Bytes::from(vec![3u8; 10]));
+
+        assert!(cache.get("a:0").is_none());
+        assert!(cache.get("a:1").is_some());
+        cache.unpin(1);
+        assert!(cache.get("a:1").is_none());
+        assert!(cache.get("b:0").is_some());
+
+        cache.invalidate_file("b");
+        assert!(cache.get("b:0").is_none());
+    }
 }
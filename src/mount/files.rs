@@ -0,0 +1,453 @@
+//! The mounted file list, shared by the Unix and Windows backends.
+//!
+//! The archive can change while it is mounted, so the list is refreshed from the
+//! source whenever it is older than the configured interval and the kernel asks
+//! for the directory (readdir, or a lookup of a name we don't know yet).
+//!
+//! A file's inode is derived from its hash and name (see [`super::inodes`]), so
+//! it keeps the same one across refreshes and remounts, and a removed file's
+//! inode simply stops resolving.
+//!
+//! Archive entries and mounted files aren't always one to one. A pack entry
+//! (see [`crate::pack`]) is shown as the files inside it rather than as the pack,
+//! so every name resolves to a [`Location`]: the entry to read from, and where
+//! in it the file starts.
+//!
+//! On Windows, names with characters it doesn't allow in a file name are shown
+//! escaped, see [`crate::naming::windows_name`]. A case-insensitive volume (see
+//! [`crate::mount::volume`]) also finds a file by its name in any case, and
+//! of two names differing only in case shows the first.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::inodes::InodeMap;
+use super::source::SegmentSource;
+use crate::config::MountConfig;
+use crate::filestore::models::parse_time;
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::naming;
+
+/// Sizes reported for the mounted volume. Used space is the logical size of
+/// the archived files, free space is what is left on the archive's disk, and
+/// the total is the two together so `df` adds up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeStats {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    pub files: u64,
+}
+
+/// Where a mounted file's bytes live.
+pub struct Location<'a> {
+    /// Archive entry holding the bytes, the file itself unless it is packed.
+    pub entry: &'a str,
+    /// Shared with the table, so a read can hold on to it unlocked.
+    pub manifest: &'a Arc<ManifestFile>,
+    /// Offset of the file within the entry.
+    pub offset: u64,
+    pub size: u64,
+    /// When the entry was committed, shown as every time of the file.
+    pub committed: SystemTime,
+}
+
+struct Placement {
+    entry: String,
+    offset: u64,
+    size: u64,
+    committed: SystemTime,
+}
+
+pub struct FileTable {
+    inode_to_filename: HashMap<u64, String>,
+    // sorted so directory listings page through a stable order
+    filename_to_inode: BTreeMap<String, u64>,
+    inodes: InodeMap,
+    // mounted name -> where it lives
+    placements: HashMap<String, Placement>,
+    // archive entry -> manifest, shared with reads in flight
+    manifests: HashMap<String, Arc<ManifestFile>>,
+    // newest commit time, shown as the root directory's times
+    modified: SystemTime,
+    refresh_interval: Option<Duration>,
+    refreshed_at: Option<Instant>,
+    // lowercased name -> mounted name, when names are looked up in any case
+    folded: Option<HashMap<String, String>>,
+}
+
+impl FileTable {
+    /// `refresh_interval` of zero disables refreshing after the first load.
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            inode_to_filename: HashMap::new(),
+            filename_to_inode: BTreeMap::new(),
+            inodes: InodeMap::new(),
+            placements: HashMap::new(),
+            manifests: HashMap::new(),
+            modified: SystemTime::UNIX_EPOCH,
+            refresh_interval: (!refresh_interval.is_zero()).then_some(refresh_interval),
+            refreshed_at: None,
+            folded: None,
+        }
+    }
+
+    /// A table refreshed and numbered as `[mount]` says. On Windows names
+    /// are looked up in any case unless `mount.volume.case_sensitive` is set.
+    pub fn from_config(mount_config: &MountConfig) -> Self {
+        let table = Self::new(Duration::from_secs(mount_config.refresh_interval))
+            .with_case_folding(cfg!(windows) && !mount_config.volume.case_sensitive);
+        match &mount_config.inode_map {
+            Some(path) => table.with_inode_map(path),
+            None => table,
+        }
+    }
+
+    /// Keeps the inode numbers in `path`, so files whose numbers collided get
+    /// the same ones on the next mount.
+    pub fn with_inode_map(mut self, path: &Path) -> Self {
+        self.inodes = InodeMap::load(path);
+        self
+    }
+
+    /// Looks names up in any case, for a case-insensitive volume.
+    pub fn with_case_folding(mut self, fold: bool) -> Self {
+        self.folded = fold.then(HashMap::new);
+        self
+    }
+
+    /// Syncs the table with `source`. New entries get an inode per file and
+    /// their manifest, entries gone from the source are dropped. Returns
+    /// the removed entry names so the caller can evict their cached segments.
+    pub fn refresh(
+        &mut self,
+        source: &dyn SegmentSource,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let entries = source.list_files()?;
+        self.refreshed_at = Some(Instant::now());
+        let current: HashSet<&String> = entries.iter().collect();
+
+        let removed: Vec<String> = self
+            .manifests
+            .keys()
+            .filter(|entry| !current.contains(entry))
+            .cloned()
+            .collect();
+        for entry in &removed {
+            self.manifests.remove(entry);
+            let names: Vec<String> = self
+                .placements
+                .iter()
+                .filter(|(_, placement)| placement.entry == *entry)
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in names {
+                if let Some(folded) = &mut self.folded {
+                    folded.remove(&name.to_lowercase());
+                }
+                self.placements.remove(&name);
+                if let Some(inode) = self.filename_to_inode.remove(&name) {
+                    self.inode_to_filename.remove(&inode);
+                }
+            }
+            tracing::info!("MOUNT | {} was removed from the archive", entry);
+        }
+
+        for entry in entries {
+            if self.manifests.contains_key(&entry) {
+                continue;
+            }
+            // an entry whose manifest can't be read yet is picked up on a later refresh
+            let manifest = match source.get_manifest(&entry) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    tracing::warn!("MOUNT | skipping {}: {}", entry, e);
+                    continue;
+                }
+            };
+
+            let files: Vec<(String, u64, u64)> = if manifest.pack.is_empty() {
+                vec![(entry.clone(), 0, manifest.size.max(0) as u64)]
+            } else {
+                manifest
+                    .pack
+                    .iter()
+                    .map(|member| (member.name.clone(), member.offset, member.size))
+                    .collect()
+            };
+            // manifests without a readable time show as 1970, like before
+            let committed = parse_time(&manifest.time_of_creation)
+                .map_or(SystemTime::UNIX_EPOCH, SystemTime::from);
+            for (name, offset, size) in files {
+                // names Windows can't show, e.g. with `:`, are escaped in the mount
+                let name = if cfg!(windows) {
+                    naming::windows_name(&name)
+                } else {
+                    name
+                };
+                let hidden = match &self.folded {
+                    Some(folded) => folded.contains_key(&name.to_lowercase()),
+                    None => self.filename_to_inode.contains_key(&name),
+                };
+                if hidden {
+                    tracing::warn!(
+                        "MOUNT | {} in {} hides an existing file, skipping",
+                        name,
+                        entry
+                    );
+                    continue;
+                }
+                if let Some(folded) = &mut self.folded {
+                    folded.insert(name.to_lowercase(), name.clone());
+                }
+                let inode = self.inodes.assign(&manifest, &name);
+                self.inode_to_filename.insert(inode, name.clone());
+                self.filename_to_inode.insert(name.clone(), inode);
+                self.placements.insert(
+                    name,
+                    Placement {
+                        entry: entry.clone(),
+                        offset,
+                        size,
+                        committed,
+                    },
+                );
+            }
+            self.manifests.insert(entry, Arc::new(manifest));
+        }
+        self.inodes
+            .retain(|inode| self.inode_to_filename.contains_key(&inode));
+        self.modified = self
+            .placements
+            .values()
+            .map(|p| p.committed)
+            .max()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        self.inodes.save();
+        Ok(removed)
+    }
+
+    /// Same as [`refresh`](Self::refresh) but only when the last one is older
+    /// than the refresh interval. Returns no names when nothing was done.
+    pub fn refresh_if_stale(
+        &mut self,
+        source: &dyn SegmentSource,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let stale = match (self.refreshed_at, self.refresh_interval) {
+            (None, _) => true,
+            (Some(at), Some(interval)) => at.elapsed() >= interval,
+            (Some(_), None) => false,
+        };
+        if stale {
+            self.refresh(source)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn filename(&self, inode: u64) -> Option<&str> {
+        self.inode_to_filename.get(&inode).map(String::as_str)
+    }
+
+    pub fn inode(&self, filename: &str) -> Option<u64> {
+        self.filename_to_inode.get(self.resolve(filename)).copied()
+    }
+
+    /// The mounted name `filename` stands for: itself, or on a
+    /// case-insensitive volume the name differing only in case.
+    pub fn resolve<'a>(&'a self, filename: &'a str) -> &'a str {
+        match &self.folded {
+            Some(folded) if !self.placements.contains_key(filename) => folded
+                .get(&filename.to_lowercase())
+                .map_or(filename, String::as_str),
+            _ => filename,
+        }
+    }
+
+    /// Where the mounted file `filename` reads from.
+    pub fn locate(&self, filename: &str) -> Option<Location<'_>> {
+        let placement = self.placements.get(self.resolve(filename))?;
+        let (entry, manifest) = self.manifests.get_key_value(&placement.entry)?;
+        Some(Location {
+            entry,
+            manifest,
+            offset: placement.offset,
+            size: placement.size,
+            committed: placement.committed,
+        })
+    }
+
+    /// When the newest mounted file was committed.
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    /// Volume sizes given the free space reported by the source, if any.
+    pub fn volume_stats(&self, free_bytes: Option<u64>) -> VolumeStats {
+        let used: u64 = self.placements.values().map(|p| p.size).sum();
+        let free_bytes = free_bytes.unwrap_or(0);
+        VolumeStats {
+            total_bytes: used + free_bytes,
+            free_bytes,
+            files: self.placements.len() as u64,
+        }
+    }
+
+    /// `(filename, inode)` pairs sorted by name.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.filename_to_inode
+            .iter()
+            .map(|(name, inode)| (name.as_str(), *inode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunker;
+    use crate::mount::source::LocalSource;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_refresh_keeps_inodes_and_drops_removed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+
+        let commit = |name: &str| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, name.repeat(100)).unwrap();
+            chunker.commit(&path).unwrap()
+        };
+        let first = commit("first.txt");
+
+        let source = LocalSource::new(archive_dir.clone()).unwrap();
+        let mut table = FileTable::new(Duration::from_secs(3600));
+        assert!(table.refresh_if_stale(&source).unwrap().is_empty());
+        let first_inode = table.inode("first.txt").unwrap();
+
+        // within the interval nothing is re-read
+        commit("second.txt");
+        table.refresh_if_stale(&source).unwrap();
+        assert_eq!(table.inode("second.txt"), None);
+
+        table.refresh(&source).unwrap();
+        assert_eq!(table.inode("first.txt"), Some(first_inode));
+        let second_inode = table.inode("second.txt").unwrap();
+        assert_ne!(second_inode, first_inode);
+
+        fs::remove_dir_all(&first.file_dir).unwrap();
+        assert_eq!(table.refresh(&source).unwrap(), vec!["first.txt"]);
+        assert_eq!(table.filename(first_inode), None);
+        assert!(table.locate("first.txt").is_none());
+        assert_eq!(
+            table.entries().collect::<Vec<_>>(),
+            vec![("second.txt", second_inode)]
+        );
+
+        let stats = table.volume_stats(Some(4096));
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.total_bytes, "second.txt".len() as u64 * 100 + 4096);
+    }
+
+    #[test]
+    fn test_inodes_survive_a_remount() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        for name in ["b.txt", "a.txt"] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, name.repeat(10)).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+
+        let source = LocalSource::new(archive_dir).unwrap();
+        let map = temp_dir.path().join("inodes.json");
+        let mount = || {
+            let mut table = FileTable::new(Duration::ZERO).with_inode_map(&map);
+            table.refresh(&source).unwrap();
+            table
+                .entries()
+                .map(|(name, inode)| (name.to_string(), inode))
+                .collect::<Vec<_>>()
+        };
+        let first = mount();
+        assert_eq!(first.len(), 2);
+        assert_eq!(mount(), first);
+
+        // the numbers come from the files, not from the order they were listed in
+        let mut unmapped = FileTable::new(Duration::ZERO);
+        unmapped.refresh(&source).unwrap();
+        assert_eq!(unmapped.inode("a.txt"), Some(first[0].1));
+        assert!(map.exists());
+    }
+
+    #[test]
+    fn test_pack_members_are_listed_instead_of_the_pack() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+
+        let paths: Vec<_> = ["x.txt", "y.txt"]
+            .iter()
+            .map(|name| {
+                let path = temp_dir.path().join(name);
+                fs::write(&path, name.repeat(10)).unwrap();
+                path
+            })
+            .collect();
+        chunker.commit_pack(&paths, "notes.pack", None).unwrap();
+
+        let source = LocalSource::new(archive_dir).unwrap();
+        let mut table = FileTable::new(Duration::ZERO);
+        table.refresh(&source).unwrap();
+
+        let names: Vec<&str> = table.entries().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["x.txt", "y.txt"]);
+        let y = table.locate("y.txt").unwrap();
+        assert_eq!((y.entry, y.offset, y.size), ("notes.pack", 50, 50));
+        assert!(table.locate("notes.pack").is_none());
+        assert_eq!(table.volume_stats(None).files, 2);
+    }
+
+    #[test]
+    fn test_case_folding_finds_names_in_any_case() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        for name in ["Notes.txt", "notes.TXT"] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, name.repeat(10)).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+
+        let source = LocalSource::new(archive_dir).unwrap();
+        let mut exact = FileTable::new(Duration::ZERO);
+        exact.refresh(&source).unwrap();
+        assert_eq!(exact.entries().count(), 2);
+        assert!(exact.locate("NOTES.txt").is_none());
+
+        let mut folded = FileTable::new(Duration::ZERO).with_case_folding(true);
+        folded.refresh(&source).unwrap();
+        let names: Vec<&str> = folded.entries().map(|(name, _)| name).collect();
+        assert_eq!(names.len(), 1);
+        let shown = names[0].to_string();
+        assert_eq!(folded.resolve("NOTES.txt"), shown);
+        assert_eq!(folded.inode("nOtEs.TxT"), folded.inode(&shown));
+        assert!(folded.locate("NOTES.TXT").is_some());
+    }
+}
@@ -1,93 +1,159 @@
 use super::cache::SegmentCache;
-use super::source::SegmentSource;
+use super::files::FileTable;
+use super::source::{SegmentSource, checked_read};
+use bytes::Bytes;
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyDirectoryPlus,
+    ReplyEntry, ReplyStatfs, ReplyXattr, Request,
 };
+use parking_lot::{Mutex, RwLock};
 use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::io::{Read, Seek, SeekFrom};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, SystemTime};
 use tracing::error;
 
-use crate::config::{Config, parse_size};
+use crate::config::{CacheConfig, MountConfig};
+use crate::filestore::recovery::{self, ShardKind};
+use crate::filestore::{FileReader, Integrity};
 use crate::merkle_tree::manifest::ManifestFile;
+use crate::negative_cache::NegativeCache;
+use crate::tags::Tags;
+use crate::utils::BLOCK_SEGMENTS;
 
-const TTL: Duration = Duration::from_secs(1);
+/// Block size reported to the kernel, matching `blksize` in file attributes.
+const BLOCK_SIZE: u64 = 512;
 
+/// Namespace tags are shown in as extended attributes, `user.<key>`.
+const XATTR_PREFIX: &str = "user.";
+
+/// Error for an extended attribute a file doesn't have.
+#[cfg(target_os = "linux")]
+const NO_XATTR: libc::c_int = libc::ENODATA;
+#[cfg(not(target_os = "linux"))]
+const NO_XATTR: libc::c_int = libc::ENOATTR;
+
+/// The FUSE side of a mount.
+///
+/// fuser hands requests over one at a time on its session thread, so reads are
+/// passed on to a pool of `mount.read_threads` workers that reply when they
+/// are done. One slow segment fetch then only holds up the reads waiting on
+/// that segment, and parallel reads into the same segment share one fetch
+/// through [`SegmentCache::get_or_load`]. Metadata requests are answered on
+/// the session thread, they only touch the file table.
 pub struct BlockframeFS {
+    shared: Arc<Shared>,
+    workers: rayon::ThreadPool,
+}
+
+/// State the session thread and the read workers share.
+struct Shared {
     source: Box<dyn SegmentSource>,
     cache: SegmentCache,
 
-    // Inode mappings
-    inode_to_filename: HashMap<u64, String>,
-    filename_to_inode: HashMap<String, u64>,
-    next_inode: u64,
+    // inode mappings and cached manifests
+    files: RwLock<FileTable>,
 
-    // Cached manifests
-    manifests: HashMap<String, ManifestFile>,
-
-    // open file handles (fh -> (filename, cursor position))
-    open_files: HashMap<u64, (String, u64)>,
-    next_fh: u64,
+    // open file handles (fh -> filename)
+    open_files: Mutex<HashMap<u64, String>>,
+    // readers of open files, when the source has the archive on hand. Each is
+    // locked by the worker reading through it
+    readers: Mutex<HashMap<u64, Arc<Mutex<FileReader>>>>,
+    next_fh: AtomicU64,
+    // how much reads are checked against the manifest
+    integrity: Integrity,
+    // how long the kernel may keep names and attributes before asking again
+    attr_ttl: Duration,
+    // names recently looked up and not found, answered without a refresh
+    missing: NegativeCache,
 
     uid: u32,
     gid: u32,
 }
 
+/// A read handed to a worker, with what it needs from the file table.
+struct ReadRequest {
+    fh: u64,
+    entry: String,
+    manifest: Arc<ManifestFile>,
+    offset: u64,
+    size: usize,
+}
+
 impl BlockframeFS {
-    pub fn new(source: Box<dyn SegmentSource>) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(
+        source: Box<dyn SegmentSource>,
+        cache_config: &CacheConfig,
+        mount_config: &MountConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let uid = unsafe { libc::getuid() };
         let gid = unsafe { libc::getgid() };
 
-        let (_max_segments, max_bytes) = match Config::load() {
-            Ok(cfg) => (
-                cfg.cache.max_segments,
-                parse_size(&cfg.cache.max_size).unwrap_or(1_000_000_000),
-            ),
-            Err(e) => {
-                error!("Failed to load config: {}. Using defaults.", e);
-                (100, 1_000_000_000)
-            }
-        };
-
-        // Convert to u64 for moka
-        let max_bytes_u64 = max_bytes as u64;
+        let max_bytes_u64 = cache_config.max_bytes();
 
-        let mut fs = Self {
+        let shared = Shared {
             source,
             cache: SegmentCache::new_with_limits(max_bytes_u64),
-            inode_to_filename: HashMap::new(),
-            filename_to_inode: HashMap::new(),
-            next_inode: 2, // 1 is root
-            manifests: HashMap::new(),
-            open_files: HashMap::new(),
-            next_fh: 1,
+            files: RwLock::new(FileTable::from_config(mount_config)),
+            open_files: Mutex::new(HashMap::new()),
+            readers: Mutex::new(HashMap::new()),
+            next_fh: AtomicU64::new(1),
+            integrity: mount_config.integrity,
+            attr_ttl: Duration::from_secs(mount_config.attr_ttl),
+            missing: NegativeCache::new(Duration::from_secs(mount_config.negative_ttl)),
             uid,
             gid,
         };
 
         // initialise file list
-        fs.refresh_files()?;
-        Ok(fs)
-    }
-
-    fn refresh_files(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let files = self.source.list_files()?;
-        for filename in files {
-            if !self.filename_to_inode.contains_key(&filename) {
-                let inode = self.next_inode;
-                self.next_inode += 1;
-                self.inode_to_filename.insert(inode, filename.clone());
-                self.filename_to_inode.insert(filename.clone(), inode);
-
-                // cache manifest
-                if let Ok(manifest) = self.source.get_manifest(&filename) {
-                    self.manifests.insert(filename, manifest);
-                }
-            }
-        }
+        shared.refresh_files()?;
+
+        let workers = rayon::ThreadPoolBuilder::new()
+            .num_threads(mount_config.read_threads.max(1))
+            .thread_name(|i| format!("blockframe-read-{}", i))
+            // the reply is dropped with the panicking read, which answers EIO
+            .panic_handler(|_| error!("MOUNT | a read worker panicked"))
+            .build()?;
+        Ok(Self {
+            shared: Arc::new(shared),
+            workers,
+        })
+    }
+
+    /// Runs `request` on a worker and hands the outcome to `done` there.
+    fn dispatch<F>(&self, request: ReadRequest, done: F)
+    where
+        F: FnOnce(Result<Bytes, Box<dyn std::error::Error>>) + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        self.workers.spawn(move || done(shared.read(&request)));
+    }
+}
+
+impl Shared {
+    fn refresh_files(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let removed = self.files.write().refresh(self.source.as_ref())?;
+        self.evict(&removed);
         Ok(())
     }
+
+    /// Picks up archive changes once the file list is older than the refresh
+    /// interval. A failed refresh keeps serving the current list.
+    fn refresh_if_stale(&self) {
+        let refreshed = self.files.write().refresh_if_stale(self.source.as_ref());
+        match refreshed {
+            Ok(removed) => self.evict(&removed),
+            Err(e) => error!("MOUNT | file list refresh failed: {}", e),
+        }
+    }
+
+    fn evict(&self, removed: &[String]) {
+        for filename in removed {
+            self.cache.invalidate_file(filename);
+        }
+    }
     fn recover_segment(
         &self,
         filename: &str,
@@ -97,71 +163,69 @@ impl BlockframeFS {
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         println!("Recovering segment {} for {}", segment_id, filename);
 
-        // Fetch parity shards
-        let parity_shards: Vec<Vec<u8>> = (0..3)
-            .map(|i| self.source.read_parity(filename, segment_id, i, block_id))
-            .collect::<Result<Vec<_>, _>>()?;
-
-        // Use shared recovery logic from filestore
-        let expected_size = if manifest.tier == 1 {
-            Some(manifest.size as usize)
-        } else {
-            None
+        // tier 3 addresses segments within their block
+        let kind = match (manifest.tier, block_id) {
+            (1, _) => ShardKind::Tiny,
+            (3, Some(block_id)) => ShardKind::Block(block_id, segment_id % BLOCK_SEGMENTS),
+            (3, None) => return Err("Block ID required for Tier 3 recovery".into()),
+            _ => ShardKind::Segment(segment_id),
         };
 
-        let recovered =
-            crate::filestore::recovery::recover_segment_rs13(parity_shards, expected_size)?;
-
-        // Verify recovered data
-        let expected_hash = if manifest.tier == 2 {
-            &manifest
-                .merkle_tree
-                .segments
-                .get(&segment_id)
-                .ok_or("Missing segment info in manifest")?
-                .data
-        } else if manifest.tier == 3 {
-            let block_id = block_id.ok_or("Block ID required for Tier 3 recovery")?;
-            let seg_idx = segment_id % 30;
-            manifest
-                .merkle_tree
-                .blocks
-                .get(&block_id)
-                .ok_or("Missing block info")?
-                .segments
-                .get(seg_idx)
-                .ok_or("Missing segment hash")?
-        } else {
-            // Tier 1
-            manifest
-                .merkle_tree
-                .leaves
-                .get(&(segment_id as i32))
-                .ok_or("Missing leaf hash")?
-        };
-
-        let actual_hash = crate::utils::blake3_hash_bytes(&recovered)?;
-        if actual_hash != *expected_hash {
-            return Err("Recovery verification failed".into());
-        }
+        // Use shared recovery logic from filestore, it checks the manifest hash
+        let recovered = recovery::recover_shard(
+            manifest,
+            kind,
+            |seg_idx| {
+                let block_id = block_id?;
+                self.source
+                    .read_block_segment(filename, block_id, seg_idx)
+                    .ok()
+            },
+            |parity_id| {
+                self.source
+                    .read_parity(filename, segment_id, parity_id, block_id)
+                    .ok()
+            },
+        )?;
 
+        let write_id = match kind {
+            ShardKind::Block(_, seg_idx) => seg_idx,
+            _ => segment_id,
+        };
         self.source
-            .write_parity(filename, segment_id, block_id, &recovered)?;
+            .write_parity(filename, write_id, block_id, &recovered)?;
         Ok(recovered)
     }
 
+    /// Tags of the file with `ino`, none for the root. `None` if there is no
+    /// such file.
+    fn tags(&self, ino: u64) -> Option<Tags> {
+        if ino == 1 {
+            return Some(Tags::new());
+        }
+        let files = self.files.read();
+        let location = files.locate(files.filename(ino)?)?;
+        Some(location.manifest.tags.clone())
+    }
+
     fn get_file_attr(&self, filename: &str) -> Option<FileAttr> {
-        let manifest = self.manifests.get(filename)?;
-        let inode = *self.filename_to_inode.get(filename)?;
+        let files = self.files.read();
+        let location = files.locate(filename)?;
+        let inode = files.inode(filename)?;
+        Some(self.file_attr(inode, location.size, location.committed))
+    }
 
-        Some(FileAttr {
+    /// Every time of a file is its commit time, so a re-export (Samba, NFS)
+    /// sees the same times on each stat and can tell a recommitted file changed.
+    fn file_attr(&self, inode: u64, size: u64, committed: SystemTime) -> FileAttr {
+        FileAttr {
             ino: inode,
-            size: manifest.size as u64,
-            blocks: (manifest.size as u64 + 511) / 512,
-            atime: SystemTime::UNIX_EPOCH,
-            mtime: SystemTime::UNIX_EPOCH,
-            ctime: SystemTime::UNIX_EPOCH,
-            crtime: SystemTime::UNIX_EPOCH,
+            size,
+            blocks: size.div_ceil(512),
+            atime: committed,
+            mtime: committed,
+            ctime: committed,
+            crtime: committed,
             kind: FileType::RegularFile,
             perm: 0o444, // READ ONLY
             nlink: 1,
@@ -170,71 +234,140 @@ impl BlockframeFS {
             rdev: 0,
             blksize: 512,
             flags: 0,
-        })
+        }
     }
 
-    fn read_bytes(
-        &mut self,
-        filename: &str,
-        segment_size: u64,
-        tier: u8,
-        offset: u64,
-        size: usize,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    /// The root's times are those of the newest file in it.
+    fn root_attr(&self, modified: SystemTime) -> FileAttr {
+        FileAttr {
+            ino: 1,
+            size: 0,
+            blocks: 0,
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: modified,
+            kind: FileType::Directory,
+            perm: 0o755,
+            nlink: 2,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+
+    /// Hands the root listing from `offset` on to `add` as `(next offset,
+    /// name, attributes)` until it returns true for a full reply buffer. The
+    /// table is locked once for the whole page.
+    fn list(&self, offset: i64, mut add: impl FnMut(i64, &str, &FileAttr) -> bool) {
+        // only refresh at the start of a listing so later pages line up
+        if offset == 0 {
+            self.refresh_if_stale();
+        }
+        let files = self.files.read();
+        let root = self.root_attr(files.modified());
+        let dots = [(".", root), ("..", root)].into_iter();
+        let entries = files.entries().filter_map(|(filename, inode)| {
+            let location = files.locate(filename)?;
+            Some((
+                filename,
+                self.file_attr(inode, location.size, location.committed),
+            ))
+        });
+        for (i, (name, attr)) in dots.chain(entries).enumerate().skip(offset as usize) {
+            if add((i + 1) as i64, name, &attr) {
+                break;
+            }
+        }
+    }
+
+    /// Serves a read on a worker thread, through the handle's reader if it
+    /// has one and from the segment cache otherwise.
+    fn read(&self, request: &ReadRequest) -> Result<Bytes, Box<dyn std::error::Error>> {
+        let reader = self.readers.lock().get(&request.fh).cloned();
+        if let Some(reader) = reader {
+            let mut reader = reader.lock();
+            let mut data = Vec::with_capacity(request.size);
+            reader.seek(SeekFrom::Start(request.offset))?;
+            reader
+                .by_ref()
+                .take(request.size as u64)
+                .read_to_end(&mut data)?;
+            return Ok(data.into());
+        }
+        self.read_bytes(request)
+    }
+
+    /// A read inside one segment is a slice of the cached segment, only one
+    /// that spans segments is copied together.
+    fn read_bytes(&self, request: &ReadRequest) -> Result<Bytes, Box<dyn std::error::Error>> {
+        let ReadRequest {
+            fh,
+            entry: filename,
+            manifest,
+            offset,
+            size,
+        } = request;
+        let (fh, offset, size) = (*fh, *offset, *size);
+        let segment_size = manifest.segment_size;
+        let tier = manifest.tier;
+
         // tier 1: whole file is one segment
         if tier == 1 {
-            let mut data = self
-                .cache
-                .get_or_fetch(filename, 0, || self.source.read_data(filename))?
-                .to_vec();
-
-            // Verify integrity for Tier 1
-            if let Some(manifest) = self.manifests.get(filename) {
-                if let Some(expected_hash) = manifest.merkle_tree.leaves.get(&0) {
-                    let actual_hash = crate::utils::blake3_hash_bytes(&data)?;
-                    if actual_hash != *expected_hash {
+            let expected_hash = manifest.merkle_tree.leaves.get(&0).map(String::as_str);
+            let data = self.cache.get_or_fetch(filename, 0, || {
+                let data = checked_read(
+                    self.source.read_data_bytes(filename),
+                    expected_hash,
+                    self.integrity,
+                )?;
+                match data {
+                    Some(data) => Ok(data),
+                    None => {
                         error!(
                             "Data corruption detected for {} (Tier 1). Attempting recovery...",
                             filename
                         );
-                        data = self.recover_segment(filename, manifest, 0, None)?;
+                        self.recover_segment(filename, manifest, 0, None)
+                            .map(Bytes::from)
                     }
                 }
-            }
+            })?;
+            self.cache.pin(fh, &format!("{}:0", filename), data.clone());
 
             let start = offset as usize;
             let end = std::cmp::min(start + size, data.len());
-            return Ok(data[start..end].to_vec());
+            return Ok(data.slice(start..end));
         }
         // tier 2 and 3: segmented
-        let mut result = Vec::with_capacity(size);
+        let mut result: Option<Vec<u8>> = None;
         let mut remaining = size;
         let mut current_offset = offset;
 
         while remaining > 0 {
             let segment_id = (current_offset / segment_size) as usize;
-            let offset_in_segment = (current_offset & segment_size) as usize;
+            let offset_in_segment = (current_offset % segment_size) as usize;
 
-            let manifest = self
-                .manifests
-                .get(&filename.to_string())
-                .ok_or("file not found in manifests hashtable line: 184 read_bytes")?;
+            let cache_key = if tier == 3 {
+                format!(
+                    "{}:block{}:seg{}",
+                    filename,
+                    segment_id / 30,
+                    segment_id % 30
+                )
+            } else {
+                format!("{}:{}", filename, segment_id)
+            };
 
-            // PERFORMANCE: Use get_or_fetch_verified to only verify on cache miss
+            // PERFORMANCE: only verify on cache miss, and only as far as the integrity policy asks.
+            // A miss is fetched once however many readers are waiting on it
             let segment_data = if tier == 3 {
                 let block_id = segment_id / 30;
                 let segment_in_block = segment_id % 30;
-                let cache_key = format!("{}:block{}:seg{}", filename, block_id, segment_in_block);
-
-                // Check cache first (no verification needed)
-                if let Some(cached) = self.cache.get(&cache_key) {
-                    cached
-                } else {
-                    // Cache miss - fetch and verify
-                    let data =
-                        self.source
-                            .read_block_segment(filename, block_id, segment_in_block)?;
 
+                self.cache.get_or_load(&cache_key, || {
                     let seg_idx = segment_id % 30;
                     let expected_hash = manifest
                         .merkle_tree
@@ -243,31 +376,27 @@ impl BlockframeFS {
                         .and_then(|b| b.segments.get(seg_idx))
                         .ok_or(format!("Hash not found for segment {}", segment_id))?;
 
-                    let actual_hash = crate::utils::blake3_hash_bytes(&data)?;
-                    let verified_data = if actual_hash != *expected_hash {
-                        error!(
-                            "Corruption in {} segment {}. Recovering...",
-                            filename, segment_id
-                        );
-                        self.recover_segment(filename, manifest, segment_id, Some(block_id))?
-                    } else {
-                        data
-                    };
-
-                    let arc_data = Arc::new(verified_data);
-                    self.cache.put(cache_key, arc_data.clone());
-                    arc_data
-                }
-            } else {
-                let cache_key = format!("{}:{}", filename, segment_id);
-
-                // Check cache first (no verification needed)
-                if let Some(cached) = self.cache.get(&cache_key) {
-                    cached
-                } else {
-                    // Cache miss - fetch and verify
-                    let data = self.source.read_segment(filename, segment_id)?;
+                    let data = checked_read(
+                        self.source
+                            .read_block_segment_bytes(filename, block_id, segment_in_block),
+                        Some(expected_hash),
+                        self.integrity,
+                    )?;
 
+                    match data {
+                        Some(data) => Ok(data),
+                        None => {
+                            error!(
+                                "Corruption in {} segment {}. Recovering...",
+                                filename, segment_id
+                            );
+                            self.recover_segment(filename, manifest, segment_id, Some(block_id))
+                                .map(Bytes::from)
+                        }
+                    }
+                })?
+            } else {
+                self.cache.get_or_load(&cache_key, || {
                     let expected_hash = manifest
                         .merkle_tree
                         .segments
@@ -275,33 +404,43 @@ impl BlockframeFS {
                         .map(|s| &s.data)
                         .ok_or(format!("Hash not found for segment {}", segment_id))?;
 
-                    let actual_hash = crate::utils::blake3_hash_bytes(&data)?;
-                    let verified_data = if actual_hash != *expected_hash {
-                        error!(
-                            "Corruption in {} segment {}. Recovering...",
-                            filename, segment_id
-                        );
-                        self.recover_segment(filename, manifest, segment_id, None)?
-                    } else {
-                        data
-                    };
-
-                    let arc_data = Arc::new(verified_data);
-                    self.cache.put(cache_key, arc_data.clone());
-                    arc_data
-                }
+                    let data = checked_read(
+                        self.source.read_segment_bytes(filename, segment_id),
+                        Some(expected_hash),
+                        self.integrity,
+                    )?;
+
+                    match data {
+                        Some(data) => Ok(data),
+                        None => {
+                            error!(
+                                "Corruption in {} segment {}. Recovering...",
+                                filename, segment_id
+                            );
+                            self.recover_segment(filename, manifest, segment_id, None)
+                                .map(Bytes::from)
+                        }
+                    }
+                })?
             };
+            // hold the segment this handle is reading so eviction can't drop it mid-read
+            self.cache.pin(fh, &cache_key, segment_data.clone());
 
             // calculate how much we can read from this segment
             let available = segment_data.len() - offset_in_segment;
             let to_read = std::cmp::min(remaining, available);
 
-            // append to result
-            result.extend_from_slice(&segment_data[offset_in_segment..offset_in_segment + to_read]);
+            let piece = segment_data.slice(offset_in_segment..offset_in_segment + to_read);
+            if to_read == size {
+                return Ok(piece);
+            }
+            result
+                .get_or_insert_with(|| Vec::with_capacity(size))
+                .extend_from_slice(&piece);
             remaining -= to_read;
             current_offset += to_read as u64;
         }
-        Ok(result)
+        Ok(result.unwrap_or_default().into())
     }
 }
 
@@ -310,8 +449,15 @@ impl Filesystem for BlockframeFS {
     fn init(
         &mut self,
         _req: &Request<'_>,
-        _config: &mut fuser::KernelConfig,
+        config: &mut fuser::KernelConfig,
     ) -> Result<(), libc::c_int> {
+        // listings come with attributes, so `ls -l` doesn't look up every file
+        // on its own. AUTO lets the kernel drop back to plain readdir when the
+        // attributes aren't used
+        use fuser::consts::{FUSE_DO_READDIRPLUS, FUSE_READDIRPLUS_AUTO};
+        if let Err(missing) = config.add_capabilities(FUSE_DO_READDIRPLUS | FUSE_READDIRPLUS_AUTO) {
+            tracing::debug!("MOUNT | kernel lacks readdirplus flags {:#x}", missing);
+        }
         println!("Blockframe filesystem mounted");
 
         Ok(())
@@ -319,34 +465,48 @@ impl Filesystem for BlockframeFS {
 
     /// get attributes of an inode
     fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        let ttl = self.shared.attr_ttl;
         if ino == 1 {
             // root directory
-            let attr = FileAttr {
-                ino: 1,
-                size: 0,
-                blocks: 0,
-                atime: SystemTime::UNIX_EPOCH,
-                mtime: SystemTime::UNIX_EPOCH,
-                ctime: SystemTime::UNIX_EPOCH,
-                crtime: SystemTime::UNIX_EPOCH,
-                kind: FileType::Directory,
-                perm: 0o755,
-                nlink: 2,
-                uid: self.uid,
-                gid: self.gid,
-                rdev: 0,
-                blksize: 512,
-                flags: 0,
-            };
-            reply.attr(&TTL, &attr);
-        } else if let Some(filename) = self.inode_to_filename.get(&ino) {
-            if let Some(attr) = self.get_file_attr(filename) {
-                reply.attr(&TTL, &attr);
-            } else {
-                reply.error(libc::ENOENT);
-            }
-        } else {
+            let modified = self.shared.files.read().modified();
+            reply.attr(&ttl, &self.shared.root_attr(modified));
+            return;
+        }
+        let filename = self.shared.files.read().filename(ino).map(str::to_string);
+        match filename.and_then(|filename| self.shared.get_file_attr(&filename)) {
+            Some(attr) => reply.attr(&ttl, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    /// A tag of a file, asked for as `user.<key>`
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let Some(tags) = self.shared.tags(ino) else {
             reply.error(libc::ENOENT);
+            return;
+        };
+        let value = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(XATTR_PREFIX))
+            .and_then(|key| tags.get(key));
+        match value {
+            Some(value) => reply_xattr(reply, size, value.as_bytes()),
+            None => reply.error(NO_XATTR),
+        }
+    }
+
+    /// The tags of a file as attribute names
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        match self.shared.tags(ino) {
+            Some(tags) => reply_xattr(reply, size, &xattr_names(&tags)),
+            None => reply.error(libc::ENOENT),
         }
     }
 
@@ -357,9 +517,18 @@ impl Filesystem for BlockframeFS {
             return;
         }
         let filename = name.to_string_lossy().to_string();
-        if let Some(attr) = self.get_file_attr(&filename) {
-            reply.entry(&TTL, &attr, 0);
+        if self.shared.files.read().inode(&filename).is_none() {
+            if self.shared.missing.contains(&filename) {
+                reply.error(libc::ENOENT);
+                return;
+            }
+            // may have been committed since the last refresh
+            self.shared.refresh_if_stale();
+        }
+        if let Some(attr) = self.shared.get_file_attr(&filename) {
+            reply.entry(&self.shared.attr_ttl, &attr, 0);
         } else {
+            self.shared.missing.insert(&filename);
             reply.error(libc::ENOENT);
         }
     }
@@ -377,31 +546,90 @@ impl Filesystem for BlockframeFS {
             reply.error(libc::ENOENT);
             return;
         }
-        let entries: Vec<_> = vec![
-            (1, FileType::Directory, "."),
-            (1, FileType::Directory, ".."),
-        ];
-
-        let mut full_entries = entries;
-        for (filename, inode) in &self.filename_to_inode {
-            full_entries.push((*inode, FileType::RegularFile, filename.as_str()));
-        }
+        self.shared.list(offset, |next, name, attr| {
+            reply.add(attr.ino, next, attr.kind, name)
+        });
+        reply.ok();
+    }
 
-        for (i, (ion, kind, name)) in full_entries.iter().enumerate().skip(offset as usize) {
-            if reply.add(*ion, (i + 1) as i64, *kind, name) {
-                break;
-            }
+    /// Read directory entries along with their attributes, so the kernel
+    /// doesn't follow up with a lookup per file
+    fn readdirplus(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectoryPlus,
+    ) {
+        if ino != 1 {
+            reply.error(libc::ENOENT);
+            return;
         }
+        let ttl = self.shared.attr_ttl;
+        self.shared.list(offset, |next, name, attr| {
+            reply.add(attr.ino, next, name, &ttl, attr, 0)
+        });
         reply.ok();
     }
 
+    /// Report volume capacity for `df`
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let free_space = self.shared.source.free_space();
+        let stats = self.shared.files.read().volume_stats(free_space);
+        let free_blocks = stats.free_bytes / BLOCK_SIZE;
+        reply.statfs(
+            stats.total_bytes.div_ceil(BLOCK_SIZE),
+            free_blocks,
+            free_blocks,
+            stats.files,
+            0,
+            BLOCK_SIZE as u32,
+            255,
+            BLOCK_SIZE as u32,
+        );
+    }
+
     /// Open a file
-    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-        if let Some(filename) = self.inode_to_filename.get(&ino).cloned() {
-            let fh = self.next_fh;
-            self.next_fh += 1;
-            self.open_files.insert(fh, (filename, 0));
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        if ino == 1 {
+            reply.error(libc::EISDIR);
+            return;
+        }
+        // the kernel already refuses these on a read-only mount, but a
+        // re-exporting server may not go through the same checks
+        if let Some(errno) = open_error(flags) {
+            reply.error(errno);
+            return;
+        }
+        let located = {
+            let files = self.shared.files.read();
+            files.filename(ino).map(|filename| {
+                let entry = files.locate(filename).map(|l| l.entry.to_string());
+                (filename.to_string(), entry)
+            })
+        };
+        if let Some((filename, entry)) = located {
+            let fh = self.shared.next_fh.fetch_add(1, Ordering::Relaxed);
+            match entry.and_then(|entry| self.shared.source.open_reader(&entry)) {
+                Some(Ok(reader)) => {
+                    let reader = reader.with_integrity(self.shared.integrity);
+                    self.shared
+                        .readers
+                        .lock()
+                        .insert(fh, Arc::new(Mutex::new(reader)));
+                }
+                Some(Err(e)) => {
+                    error!("Open error: {}", e);
+                    reply.error(libc::EIO);
+                    return;
+                }
+                None => {}
+            }
+            self.shared.open_files.lock().insert(fh, filename);
             reply.opened(fh, 0);
+        } else {
+            reply.error(libc::ENOENT);
         }
     }
 
@@ -417,7 +645,7 @@ impl Filesystem for BlockframeFS {
         _lock_owner: Option<u64>,
         reply: ReplyData,
     ) {
-        let (filename, _) = match self.open_files.get(&fh) {
+        let filename = match self.shared.open_files.lock().get(&fh) {
             Some(f) => f.clone(),
             None => {
                 reply.error(libc::EBADF);
@@ -425,8 +653,15 @@ impl Filesystem for BlockframeFS {
             }
         };
 
-        let (file_size, segment_size, tier) = match self.manifests.get(&filename) {
-            Some(m) => (m.size as u64, m.segment_size as u64, m.tier as u8),
+        // packed files read from their pack, starting at their offset in it
+        let located = {
+            let files = self.shared.files.read();
+            files
+                .locate(&filename)
+                .map(|l| (l.entry.to_string(), l.offset, l.size, l.manifest.clone()))
+        };
+        let (entry, base, file_size, manifest) = match located {
+            Some(located) => located,
             None => {
                 reply.error(libc::ENOENT);
                 return;
@@ -445,14 +680,21 @@ impl Filesystem for BlockframeFS {
         // calculate actual read size
         let actual_size = std::cmp::min(size, file_size - offset);
 
-        // read segment(s) and slice
-        match self.read_bytes(&filename, segment_size, tier, offset, actual_size as usize) {
+        // read segment(s) and slice on a worker, the session moves on to the next request
+        let request = ReadRequest {
+            fh,
+            entry,
+            manifest,
+            offset: base + offset,
+            size: actual_size as usize,
+        };
+        self.dispatch(request, move |read| match read {
             Ok(data) => reply.data(&data),
             Err(e) => {
                 error!("Read error: {}", e);
                 reply.error(libc::EIO);
             }
-        }
+        });
     }
 
     /// Release (close) a file
@@ -466,7 +708,385 @@ impl Filesystem for BlockframeFS {
         _flush: bool,
         reply: fuser::ReplyEmpty,
     ) {
-        self.open_files.remove(&fh);
+        self.shared.open_files.lock().remove(&fh);
+        self.shared.readers.lock().remove(&fh);
+        self.shared.cache.unpin(fh);
         reply.ok();
     }
+
+    /// Nothing is buffered, so a flush only checks the handle is ours. Samba
+    /// flushes on every close and treats an error as a failed close.
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        if self.shared.open_files.lock().contains_key(&fh) {
+            reply.ok();
+        } else {
+            reply.error(libc::EBADF);
+        }
+    }
+
+    /// Permission check for `access(2)`, which file servers use to decide
+    /// what to offer a client before opening anything.
+    fn access(&mut self, _req: &Request<'_>, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+        let kind = if ino == 1 {
+            FileType::Directory
+        } else if self.shared.files.read().filename(ino).is_some() {
+            FileType::RegularFile
+        } else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        match access_error(kind, mask) {
+            Some(errno) => reply.error(errno),
+            None => reply.ok(),
+        }
+    }
+
+    /// Only the root is a directory. Listings are stateless, so no handle is
+    /// kept.
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
+        if ino == 1 {
+            reply.opened(0, 0);
+        } else if self.shared.files.read().filename(ino).is_some() {
+            reply.error(libc::ENOTDIR);
+        } else {
+            reply.error(libc::ENOENT);
+        }
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _flags: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+}
+
+/// Answers an attribute request with `value`, or its length when the caller
+/// asks with a `size` of 0 to size its buffer.
+fn reply_xattr(reply: ReplyXattr, size: u32, value: &[u8]) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(value);
+    }
+}
+
+/// `tags` as `listxattr` returns them, each name followed by a NUL.
+fn xattr_names(tags: &Tags) -> Vec<u8> {
+    let mut names = Vec::new();
+    for key in tags.keys() {
+        names.extend_from_slice(XATTR_PREFIX.as_bytes());
+        names.extend_from_slice(key.as_bytes());
+        names.push(0);
+    }
+    names
+}
+
+/// Why an `open` with `flags` is refused, if it is. Anything but a plain
+/// read-only open would change the file.
+fn open_error(flags: i32) -> Option<libc::c_int> {
+    let writes = flags & libc::O_ACCMODE != libc::O_RDONLY;
+    let changes = flags & (libc::O_TRUNC | libc::O_APPEND | libc::O_CREAT) != 0;
+    (writes || changes).then_some(libc::EROFS)
+}
+
+/// Why `access` with `mask` fails for an entry of `kind`, if it does. Writing
+/// is never allowed, and files can't be executed.
+fn access_error(kind: FileType, mask: i32) -> Option<libc::c_int> {
+    if mask & libc::W_OK != 0 {
+        Some(libc::EROFS)
+    } else if mask & libc::X_OK != 0 && kind == FileType::RegularFile {
+        Some(libc::EACCES)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunker;
+    use crate::mount::source::LocalSource;
+    use crate::utils::SegmentPolicy;
+    use std::fs;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc;
+    use tempfile::TempDir;
+
+    /// How many segment reads were sent and how many ran at once.
+    #[derive(Default)]
+    struct Counters {
+        reads: AtomicUsize,
+        in_flight: AtomicUsize,
+        most_in_flight: AtomicUsize,
+    }
+
+    /// A local archive whose segment reads take a while.
+    struct SlowSource {
+        inner: LocalSource,
+        counters: Arc<Counters>,
+    }
+
+    impl SegmentSource for SlowSource {
+        fn list_files(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            self.inner.list_files()
+        }
+        fn get_manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
+            self.inner.get_manifest(filename)
+        }
+        fn read_segment(
+            &self,
+            filename: &str,
+            segment_id: usize,
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            let counters = &self.counters;
+            counters.reads.fetch_add(1, Ordering::SeqCst);
+            let now = counters.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            counters.most_in_flight.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(100));
+            counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.inner.read_segment(filename, segment_id)
+        }
+        fn read_block_segment(
+            &self,
+            filename: &str,
+            block_id: usize,
+            segment_id: usize,
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            self.inner
+                .read_block_segment(filename, block_id, segment_id)
+        }
+        fn read_parity(
+            &self,
+            filename: &str,
+            segment_id: usize,
+            parity_id: usize,
+            block_id: Option<usize>,
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            self.inner
+                .read_parity(filename, segment_id, parity_id, block_id)
+        }
+        fn write_parity(
+            &self,
+            filename: &str,
+            segment_id: usize,
+            block_id: Option<usize>,
+            recovered_bytes: &[u8],
+        ) -> Result<bool, Box<dyn std::error::Error>> {
+            self.inner
+                .write_parity(filename, segment_id, block_id, recovered_bytes)
+        }
+        fn read_data(&self, filename: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            self.inner.read_data(filename)
+        }
+    }
+
+    #[test]
+    fn test_reads_run_in_parallel_and_share_fetches() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("parallel.bin");
+        let data: Vec<u8> = (0..4 * 65_536u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let chunker = Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        chunker.commit_as(&source, Some(2)).unwrap();
+
+        let counters = Arc::new(Counters::default());
+        let source = SlowSource {
+            inner: LocalSource::new(chunker.archive_dir().to_path_buf()).unwrap(),
+            counters: counters.clone(),
+        };
+        let mount_config = MountConfig {
+            read_threads: 4,
+            ..MountConfig::default()
+        };
+        let mounted =
+            BlockframeFS::new(Box::new(source), &CacheConfig::default(), &mount_config).unwrap();
+        let manifest = mounted
+            .shared
+            .files
+            .read()
+            .locate("parallel.bin")
+            .unwrap()
+            .manifest
+            .clone();
+
+        let read_all = |offsets: &[u64]| {
+            let (sender, results) = mpsc::channel();
+            for (fh, &offset) in offsets.iter().enumerate() {
+                let request = ReadRequest {
+                    fh: fh as u64,
+                    entry: "parallel.bin".to_string(),
+                    manifest: manifest.clone(),
+                    offset,
+                    size: 4096,
+                };
+                let sender = sender.clone();
+                mounted.dispatch(request, move |read| {
+                    sender
+                        .send((offset, read.map_err(|e| e.to_string())))
+                        .unwrap()
+                });
+            }
+            for _ in offsets {
+                let (offset, read) = results.recv().unwrap();
+                let offset = offset as usize;
+                assert_eq!(read.unwrap(), data[offset..offset + 4096]);
+            }
+        };
+
+        // a slow segment doesn't hold up the others
+        read_all(&[0, 65_536, 131_072, 196_608]);
+        assert_eq!(counters.reads.load(Ordering::SeqCst), 4);
+        assert!(counters.most_in_flight.load(Ordering::SeqCst) > 1);
+
+        // readers of one segment share its fetch
+        mounted.shared.cache.invalidate_file("parallel.bin");
+        read_all(&[0, 4096, 8192, 12_288]);
+        assert_eq!(counters.reads.load(Ordering::SeqCst), 5);
+
+        // a read inside a segment is a slice of the cached one, one across two is copied
+        let request = |offset: u64, size: usize| ReadRequest {
+            fh: 9,
+            entry: "parallel.bin".to_string(),
+            manifest: manifest.clone(),
+            offset,
+            size,
+        };
+        let cached = mounted.shared.cache.get("parallel.bin:0").unwrap();
+        let inside = mounted.shared.read(&request(4096, 4096)).unwrap();
+        assert_eq!(inside.as_ptr(), cached[4096..].as_ptr());
+        let across = mounted.shared.read(&request(65_436, 200)).unwrap();
+        assert_eq!(across, data[65_436..65_636]);
+    }
+
+    #[test]
+    fn test_listing_pages_carry_sizes() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .build()
+            .unwrap();
+        for (name, size) in [("a.txt", 10), ("b.txt", 20), ("c.txt", 30)] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, vec![b'x'; size]).unwrap();
+            chunker.commit(&path).unwrap();
+        }
+        let source = LocalSource::new(chunker.archive_dir().to_path_buf()).unwrap();
+        let mounted = BlockframeFS::new(
+            Box::new(source),
+            &CacheConfig::default(),
+            &MountConfig::default(),
+        )
+        .unwrap();
+
+        // a reply buffer with room for three entries, then the rest from where it stopped
+        let mut times = Vec::new();
+        let mut page = |offset| {
+            let mut entries = Vec::new();
+            mounted.shared.list(offset, |next, name, attr| {
+                let size = (attr.kind == FileType::RegularFile).then_some(attr.size);
+                entries.push((next, name.to_string(), size));
+                // files show their commit time, the root that of the newest file
+                times.push((attr.kind, attr.mtime));
+                entries.len() == 3
+            });
+            entries
+        };
+        let first = page(0);
+        assert_eq!(
+            first.iter().map(|e| e.1.as_str()).collect::<Vec<_>>(),
+            vec![".", "..", "a.txt"]
+        );
+        assert_eq!(first[2].2, Some(10));
+        let rest = page(first[2].0);
+        assert_eq!(
+            rest,
+            vec![
+                (4, "b.txt".to_string(), Some(20)),
+                (5, "c.txt".to_string(), Some(30))
+            ]
+        );
+        assert!(page(5).is_empty());
+
+        let newest = times.iter().map(|(_, mtime)| *mtime).max().unwrap();
+        assert!(newest > SystemTime::UNIX_EPOCH);
+        assert!(
+            times
+                .iter()
+                .all(|(kind, mtime)| *kind == FileType::RegularFile || *mtime == newest)
+        );
+    }
+
+    #[test]
+    fn test_tags_show_as_xattrs() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunker = Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .tags(Tags::from([
+                ("project".to_string(), "apollo".to_string()),
+                ("owner".to_string(), "ops".to_string()),
+            ]))
+            .build()
+            .unwrap();
+        let path = temp_dir.path().join("flight.log");
+        fs::write(&path, b"liftoff").unwrap();
+        chunker.commit(&path).unwrap();
+        let source = LocalSource::new(chunker.archive_dir().to_path_buf()).unwrap();
+        let mounted = BlockframeFS::new(
+            Box::new(source),
+            &CacheConfig::default(),
+            &MountConfig::default(),
+        )
+        .unwrap();
+
+        let inode = mounted.shared.files.read().inode("flight.log").unwrap();
+        let tags = mounted.shared.tags(inode).unwrap();
+        assert_eq!(tags.get("project").map(String::as_str), Some("apollo"));
+        assert_eq!(xattr_names(&tags), b"user.owner\0user.project\0");
+        assert!(mounted.shared.tags(1).unwrap().is_empty());
+        assert!(mounted.shared.tags(inode + 1000).is_none());
+    }
+
+    #[test]
+    fn test_writes_are_refused() {
+        assert_eq!(open_error(libc::O_RDONLY), None);
+        assert_eq!(open_error(libc::O_RDONLY | libc::O_NONBLOCK), None);
+        assert_eq!(open_error(libc::O_RDWR), Some(libc::EROFS));
+        assert_eq!(
+            open_error(libc::O_RDONLY | libc::O_TRUNC),
+            Some(libc::EROFS)
+        );
+
+        assert_eq!(access_error(FileType::RegularFile, libc::R_OK), None);
+        assert_eq!(access_error(FileType::RegularFile, libc::F_OK), None);
+        assert_eq!(
+            access_error(FileType::RegularFile, libc::W_OK),
+            Some(libc::EROFS)
+        );
+        assert_eq!(
+            access_error(FileType::RegularFile, libc::X_OK),
+            Some(libc::EACCES)
+        );
+        assert_eq!(
+            access_error(FileType::Directory, libc::R_OK | libc::X_OK),
+            None
+        );
+    }
 }
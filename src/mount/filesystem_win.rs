@@ -14,20 +14,32 @@ use winfsp::filesystem::{
 };
 use winfsp::{FspError, Result, U16CStr, U16CString};
 
-use std::collections::HashMap;
 use std::ffi::c_void;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 use super::cache::SegmentCache;
-use super::source::SegmentSource;
-use crate::config::{Config, parse_size};
+use super::files::FileTable;
+use super::source::{SegmentSource, checked_read};
+use crate::config::{CacheConfig, MountConfig};
+use crate::filestore::recovery::{self, ShardKind};
+use crate::filestore::{FileReader, Integrity};
 use crate::merkle_tree::manifest::ManifestFile;
+use crate::negative_cache::NegativeCache;
+use crate::utils::BLOCK_SEGMENTS;
+use bytes::Bytes;
 
 // File context for open files
 pub struct BlockframeFileContext {
     filename: String,
+    // identifies the handle for cache pinning, 0 for the root directory
+    handle: u64,
     _cursor: u64,
     dir_buffer: Option<DirBuffer>,
+    // reads the file when the source has the archive on hand
+    reader: Option<Mutex<FileReader>>,
 }
 
 // Main filesystem structure
@@ -35,38 +47,36 @@ pub struct BlockframeFileContext {
 // some of these source functions might be called multiple times
 pub struct BlockframeFS {
     inner: Arc<Mutex<BlockframeFSInner>>,
+    next_handle: AtomicU64,
+    // label shown in Explorer, from mount.volume.label
+    volume_label: String,
 }
 
 // Inner filesystem state
 struct BlockframeFSInner {
     source: Box<dyn SegmentSource>,
     cache: SegmentCache,
-    inode_to_filename: HashMap<u64, String>,
-    filename_to_inode: HashMap<String, u64>,
-    next_inode: u64,
-    manifests: HashMap<String, ManifestFile>,
+    files: FileTable,
+    // how much reads are checked against the manifest
+    integrity: Integrity,
+    // names recently looked up and not found, answered without a refresh
+    missing: NegativeCache,
 }
 
 impl BlockframeFS {
-    pub fn new(source: Box<dyn SegmentSource>) -> Result<Self> {
-        let (_max_segments, max_bytes) = match Config::load() {
-            Ok(cfg) => (
-                cfg.cache.max_segments,
-                parse_size(&cfg.cache.max_size).unwrap_or(1_000_000_000),
-            ),
-            Err(_) => (10_000, 1_000_000_000),
-        };
-
-        // Convert to u64 for moka
-        let max_bytes_u64 = max_bytes as u64;
+    pub fn new(
+        source: Box<dyn SegmentSource>,
+        cache_config: &CacheConfig,
+        mount_config: &MountConfig,
+    ) -> Result<Self> {
+        let max_bytes_u64 = cache_config.max_bytes();
 
         let mut inner = BlockframeFSInner {
             source,
             cache: SegmentCache::new_with_limits(max_bytes_u64),
-            inode_to_filename: HashMap::new(),
-            filename_to_inode: HashMap::new(),
-            next_inode: 2, // 1 is root
-            manifests: HashMap::new(),
+            files: FileTable::from_config(mount_config),
+            integrity: mount_config.integrity,
+            missing: NegativeCache::new(Duration::from_secs(mount_config.negative_ttl)),
         };
 
         // Initialize file list
@@ -74,46 +84,73 @@ impl BlockframeFS {
 
         Ok(Self {
             inner: Arc::new(Mutex::new(inner)),
+            next_handle: AtomicU64::new(1),
+            volume_label: mount_config.volume.label.clone(),
         })
     }
 }
 
 impl BlockframeFSInner {
     fn refresh_files(&mut self) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        let files = self.source.list_files()?;
-        for filename in files {
-            if !self.filename_to_inode.contains_key(&filename) {
-                let inode = self.next_inode;
-                self.next_inode += 1;
-                self.inode_to_filename.insert(inode, filename.clone());
-                self.filename_to_inode.insert(filename.clone(), inode);
-
-                if let Ok(manifest) = self.source.get_manifest(&filename) {
-                    self.manifests.insert(filename, manifest);
-                }
-            }
-        }
+        let removed = self.files.refresh(self.source.as_ref())?;
+        self.evict(&removed);
         Ok(())
     }
 
+    /// Picks up archive changes once the file list is older than the refresh
+    /// interval. A failed refresh keeps serving the current list.
+    fn refresh_if_stale(&mut self) {
+        match self.files.refresh_if_stale(self.source.as_ref()) {
+            Ok(removed) => self.evict(&removed),
+            Err(e) => tracing::error!("MOUNT | file list refresh failed: {}", e),
+        }
+    }
+
+    fn evict(&self, removed: &[String]) {
+        for filename in removed {
+            self.cache.invalidate_file(filename);
+        }
+    }
+
     fn get_file_info(&self, filename: &str) -> Option<FileInfo> {
-        let manifest = self.manifests.get(filename)?;
+        let location = self.files.locate(filename)?;
+        let size = location.size;
+        // every time of a file is its commit time
+        let committed = filetime(location.committed);
 
         Some(FileInfo {
             file_attributes: FILE_ATTRIBUTE_READONLY.0,
             reparse_tag: 0,
-            allocation_size: ((manifest.size as u64).div_ceil(512) * 512),
-            file_size: manifest.size as u64,
-            creation_time: 0,
-            last_access_time: 0,
-            last_write_time: 0,
-            change_time: 0, // TODO: Get from manifest
-            index_number: *self.filename_to_inode.get(filename).unwrap_or(&0),
+            allocation_size: size.div_ceil(512) * 512,
+            file_size: size,
+            creation_time: committed,
+            last_access_time: committed,
+            last_write_time: committed,
+            change_time: committed,
+            index_number: self.files.inode(filename).unwrap_or(0),
             hard_links: 1,
             ea_size: 0,
         })
     }
 
+    /// The root's times are those of the newest file in it.
+    fn root_info(&self) -> FileInfo {
+        let modified = filetime(self.files.modified());
+        FileInfo {
+            file_attributes: FILE_ATTRIBUTE_DIRECTORY.0,
+            allocation_size: 0,
+            file_size: 0,
+            creation_time: modified,
+            last_access_time: modified,
+            last_write_time: modified,
+            change_time: modified,
+            index_number: 1,
+            reparse_tag: 0,
+            hard_links: 1,
+            ea_size: 0,
+        }
+    }
+
     fn recover_segment(
         &self,
         filename: &str,
@@ -124,87 +161,70 @@ impl BlockframeFSInner {
         use tracing::info;
         info!("Recovering segment {} for {}", segment_id, filename);
 
-        // Fetch parity shards
-        let parity_shards: Vec<Vec<u8>> = (0..3)
-            .map(|i| self.source.read_parity(filename, segment_id, i, block_id))
-            .collect::<std::result::Result<Vec<_>, Box<dyn std::error::Error>>>()?;
-
-        // Use shared recovery logic from filestore
-        let expected_size = if manifest.tier == 1 {
-            Some(manifest.size as usize)
-        } else {
-            None
+        // tier 3 addresses segments within their block
+        let kind = match (manifest.tier, block_id) {
+            (1, _) => ShardKind::Tiny,
+            (3, Some(block_id)) => ShardKind::Block(block_id, segment_id % BLOCK_SEGMENTS),
+            (3, None) => return Err("Block ID required for Tier 3 recovery".into()),
+            _ => ShardKind::Segment(segment_id),
         };
 
-        let recovered =
-            crate::filestore::recovery::recover_segment_rs13(parity_shards, expected_size)?;
-
-        // Verify recovered data
-        let expected_hash = if manifest.tier == 2 {
-            &manifest
-                .merkle_tree
-                .segments
-                .get(&segment_id)
-                .ok_or("Missing segment info in manifest")?
-                .data
-        } else if manifest.tier == 3 {
-            let block_id = block_id.ok_or("Block ID required for Tier 3 recovery")?;
-            let seg_idx = segment_id % 30;
-            manifest
-                .merkle_tree
-                .blocks
-                .get(&block_id)
-                .ok_or("Missing block info")?
-                .segments
-                .get(seg_idx)
-                .ok_or("Missing segment hash")?
-        } else {
-            // Tier 1
-            manifest
-                .merkle_tree
-                .leaves
-                .get(&(segment_id as i32))
-                .ok_or("Missing leaf hash")?
+        // Use shared recovery logic from filestore, it checks the manifest hash
+        let recovered = recovery::recover_shard(
+            manifest,
+            kind,
+            |seg_idx| {
+                let block_id = block_id?;
+                self.source
+                    .read_block_segment(filename, block_id, seg_idx)
+                    .ok()
+            },
+            |parity_id| {
+                self.source
+                    .read_parity(filename, segment_id, parity_id, block_id)
+                    .ok()
+            },
+        )?;
+
+        let write_id = match kind {
+            ShardKind::Block(_, seg_idx) => seg_idx,
+            _ => segment_id,
         };
-
-        let actual_hash = crate::utils::blake3_hash_bytes(&recovered)?;
-        if actual_hash != *expected_hash {
-            return Err("Recovery verification failed".into());
-        }
-
         self.source
-            .write_parity(filename, segment_id, block_id, &recovered)?;
+            .write_parity(filename, write_id, block_id, &recovered)?;
         Ok(recovered)
     }
 
     fn read_from_source(
         &mut self,
+        handle: u64,
         filename: &str,
         segment_index: usize,
         tier: u8,
         manifest: &ManifestFile,
-    ) -> std::result::Result<Arc<Vec<u8>>, Box<dyn std::error::Error>> {
+    ) -> std::result::Result<Bytes, Box<dyn std::error::Error>> {
         let cache_key = format!("{}:{}", filename, segment_index);
 
         // PERFORMANCE: Return cached data immediately without verification
         // The cache only contains previously verified data
         if let Some(segment) = self.cache.get(&cache_key) {
+            self.cache.pin(handle, &cache_key, segment.clone());
             return Ok(segment);
         }
 
         // Read from disk
         let segment_data = match tier {
-            1 => self.source.read_data(filename),
-            2 => self.source.read_segment(filename, segment_index),
+            1 => self.source.read_data_bytes(filename),
+            2 => self.source.read_segment_bytes(filename, segment_index),
             3 => {
                 let block_size = 30;
                 let block_index = segment_index / block_size;
                 let segment_in_block = segment_index % block_size;
                 self.source
-                    .read_block_segment(filename, block_index, segment_in_block)
+                    .read_block_segment_bytes(filename, block_index, segment_in_block)
             }
             _ => Err("Unsupported tier".into()),
-        }?;
+        };
 
         // ONLY verify hash on first read from disk (not on cached reads)
         let expected_hash_opt = if tier == 1 {
@@ -226,11 +246,16 @@ impl BlockframeFSInner {
                 .and_then(|b| b.segments.get(seg_idx))
         };
 
-        // Verify integrity and recover if corrupted
-        let verified_data = if let Some(expected_hash) = expected_hash_opt {
-            let actual_hash = crate::utils::blake3_hash_bytes(&segment_data)?;
-
-            if actual_hash != *expected_hash {
+        // Verify integrity as far as the policy asks and recover if corrupted,
+        // a shard a remote source couldn't verify comes back as None
+        let segment_data = checked_read(
+            segment_data,
+            expected_hash_opt.map(String::as_str),
+            self.integrity,
+        )?;
+        let verified_data = match segment_data {
+            Some(data) => data,
+            None => {
                 use tracing::error;
                 error!(
                     "Corruption detected in {} segment {} (Tier {}). Recovering...",
@@ -243,15 +268,14 @@ impl BlockframeFSInner {
                     None
                 };
                 self.recover_segment(filename, manifest, segment_index, block_id)?
-            } else {
-                segment_data
+                    .into()
             }
-        } else {
-            segment_data
         };
 
-        let segment = Arc::new(verified_data);
-        self.cache.put(cache_key, segment.clone());
+        let segment = verified_data;
+        self.cache.put(cache_key.clone(), segment.clone());
+        // hold the segment this handle is reading so eviction can't drop it mid-read
+        self.cache.pin(handle, &cache_key, segment.clone());
         Ok(segment)
     }
 }
@@ -260,9 +284,17 @@ impl FileSystemContext for BlockframeFS {
     type FileContext = BlockframeFileContext;
 
     fn get_volume_info(&self, volume_info: &mut VolumeInfo) -> Result<()> {
-        volume_info.total_size = 1024 * 1024 * 1024 * 1024;
-        volume_info.free_size = 1024 * 1024 * 1024 * 1024;
-        volume_info.set_volume_label("BlockframeFS");
+        // same numbers as statfs on unix: archived bytes plus the archive disk's free space
+        let stats = {
+            let inner = self
+                .inner
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            inner.files.volume_stats(inner.source.free_space())
+        };
+        volume_info.total_size = stats.total_bytes;
+        volume_info.free_size = stats.free_bytes;
+        volume_info.set_volume_label(&self.volume_label);
         Ok(())
     }
 
@@ -282,19 +314,27 @@ impl FileSystemContext for BlockframeFS {
             });
         }
 
-        let inner = self
+        let mut inner = self
             .inner
             .lock()
             .unwrap_or_else(|poisoned| poisoned.into_inner());
         let clean_name = filename.trim_start_matches('\\');
+        if inner.files.locate(clean_name).is_none() {
+            if inner.missing.contains(clean_name) {
+                return Err(FspError::NTSTATUS(-1073741772)); // STATUS_OBJECT_NAME_NOT_FOUND
+            }
+            // may have been committed since the last refresh
+            inner.refresh_if_stale();
+        }
 
-        if inner.manifests.contains_key(clean_name) {
+        if inner.files.locate(clean_name).is_some() {
             Ok(FileSecurity {
                 attributes: FILE_ATTRIBUTE_READONLY.0,
                 reparse: false,
                 sz_security_descriptor: 0,
             })
         } else {
+            inner.missing.insert(clean_name);
             Err(FspError::NTSTATUS(-1073741772)) // STATUS_OBJECT_NAME_NOT_FOUND
         }
     }
@@ -310,23 +350,18 @@ impl FileSystemContext for BlockframeFS {
 
         // Handle root directory
         if filename == "\\" {
-            let info: &mut FileInfo = file_info.as_mut();
-            info.file_attributes = FILE_ATTRIBUTE_DIRECTORY.0;
-            info.allocation_size = 0;
-            info.file_size = 0;
-            info.creation_time = 0;
-            info.last_access_time = 0;
-            info.last_write_time = 0;
-            info.change_time = 0;
-            info.index_number = 1;
-            info.reparse_tag = 0;
-            info.hard_links = 1;
-            info.ea_size = 0;
+            let inner = self
+                .inner
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            *file_info.as_mut() = inner.root_info();
 
             return Ok(Self::FileContext {
                 filename: "\\".to_string(),
+                handle: 0,
                 _cursor: 0,
                 dir_buffer: Some(DirBuffer::new()),
+                reader: None,
             });
         }
 
@@ -338,18 +373,33 @@ impl FileSystemContext for BlockframeFS {
 
         if let Some(info) = inner.get_file_info(clean_name) {
             *file_info.as_mut() = info;
+            let entry = inner.files.locate(clean_name).map(|l| l.entry.to_string());
+            let reader = match entry.and_then(|entry| inner.source.open_reader(&entry)) {
+                Some(Ok(reader)) => Some(Mutex::new(reader.with_integrity(inner.integrity))),
+                Some(Err(_)) => return Err(FspError::NTSTATUS(-1073741772)),
+                None => None,
+            };
             Ok(BlockframeFileContext {
                 filename: clean_name.to_string(),
+                handle: self.next_handle.fetch_add(1, Ordering::Relaxed),
                 _cursor: 0,
                 dir_buffer: None,
+                reader,
             })
         } else {
             Err(FspError::NTSTATUS(-1073741772))
         }
     }
 
-    fn close(&self, _context: Self::FileContext) {
-        // Nothing to clean up for read-only filesystem
+    fn close(&self, context: Self::FileContext) {
+        // read-only, so the only thing to release is the pinned segment
+        if context.handle != 0 {
+            self.inner
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .cache
+                .unpin(context.handle);
+        }
     }
 
     fn read(
@@ -358,24 +408,36 @@ impl FileSystemContext for BlockframeFS {
         buffer: &mut [u8],
         offset: u64,
     ) -> Result<u32> {
-        let manifest = {
+        // packed files read from their pack, starting at their offset in it
+        let (entry, base, file_size, manifest) = {
             let inner = self
                 .inner
                 .lock()
                 .unwrap_or_else(|poisoned| poisoned.into_inner());
-            match inner.manifests.get(&file_context.filename) {
-                Some(manifest) => manifest.clone(),
+            match inner.files.locate(&file_context.filename) {
+                Some(l) => (l.entry.to_string(), l.offset, l.size, l.manifest.clone()),
                 None => return Err(FspError::NTSTATUS(-1073741772)),
             }
         };
 
-        if offset >= manifest.size as u64 {
+        if offset >= file_size {
             return Ok(0);
         }
 
-        let bytes_to_read = (manifest.size as u64 - offset).min(buffer.len() as u64) as usize;
+        let bytes_to_read = (file_size - offset).min(buffer.len() as u64) as usize;
+
+        if let Some(reader) = &file_context.reader {
+            let mut reader = reader
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            reader
+                .seek(SeekFrom::Start(base + offset))
+                .and_then(|_| reader.read_exact(&mut buffer[..bytes_to_read]))
+                .map_err(|_| FspError::NTSTATUS(-1073741772))?;
+            return Ok(bytes_to_read as u32);
+        }
         let mut bytes_read = 0;
-        let mut current_offset = offset;
+        let mut current_offset = base + offset;
 
         while bytes_read < bytes_to_read {
             let segment_size = manifest.segment_size;
@@ -391,7 +453,8 @@ impl FileSystemContext for BlockframeFS {
 
                 let segment_data = inner
                     .read_from_source(
-                        &file_context.filename,
+                        file_context.handle,
+                        &entry,
                         segment_index,
                         manifest.tier,
                         &manifest,
@@ -431,13 +494,15 @@ impl FileSystemContext for BlockframeFS {
             .expect("dir_buffer must be initialized before use");
 
         if let Ok(dir_buffer_lock) = dir_buffer.acquire(marker.is_none(), None) {
-            let inner = self
+            let mut inner = self
                 .inner
                 .lock()
                 .unwrap_or_else(|poisoned| poisoned.into_inner());
+            // the buffer is only filled at the start of a listing, so later pages line up
+            inner.refresh_if_stale();
 
             let mut dir_info: DirInfo = DirInfo::new();
-            for filename in inner.manifests.keys() {
+            for (filename, _) in inner.files.entries() {
                 if let Some(file_info) = inner.get_file_info(filename) {
                     dir_info.reset();
                     let file_name_u16 = match U16CString::from_str(filename) {
@@ -472,19 +537,7 @@ impl FileSystemContext for BlockframeFS {
             .unwrap_or_else(|poisoned| poisoned.into_inner());
 
         if file_context.filename == "\\" {
-            *file_info = FileInfo {
-                file_attributes: FILE_ATTRIBUTE_DIRECTORY.0,
-                allocation_size: 0,
-                file_size: 0,
-                creation_time: 0,
-                last_access_time: 0,
-                last_write_time: 0,
-                change_time: 0,
-                index_number: 1,
-                reparse_tag: 0,
-                hard_links: 1,
-                ea_size: 0,
-            };
+            *file_info = inner.root_info();
         } else if let Some(info) = inner.get_file_info(&file_context.filename) {
             *file_info = info;
         } else {
@@ -494,3 +547,12 @@ impl FileSystemContext for BlockframeFS {
         Ok(())
     }
 }
+
+/// `time` as a Windows FILETIME, 100ns ticks since 1601.
+fn filetime(time: SystemTime) -> u64 {
+    const UNIX_EPOCH_TICKS: u64 = 116_444_736_000_000_000;
+    let since_unix = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH_TICKS + (since_unix.as_nanos() / 100) as u64
+}
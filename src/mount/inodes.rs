@@ -0,0 +1,179 @@
+//! Inode numbers for mounted files that stay the same across remounts.
+//!
+//! Tools like rsync and backup software remember inode numbers between runs,
+//! so numbering files in listing order each mount makes them see every file
+//! as new. Instead a file's inode is derived from its content hash and name.
+//! Two files whose numbers collide get the next free one, which would depend
+//! on the order they were seen, so with a map file configured every assigned
+//! number is kept on disk and handed out again on the next mount.
+//!
+//! The map only holds files that are in the archive. A removed file's number
+//! is dropped with it, and comes back the same if the file is committed again.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::merkle_tree::manifest::ManifestFile;
+
+// below 2^63, for tools that store inodes as signed numbers
+const INODE_MASK: u64 = u64::MAX >> 1;
+
+pub struct InodeMap {
+    by_identity: BTreeMap<String, u64>,
+    by_inode: HashMap<u64, String>,
+    path: Option<PathBuf>,
+    changed: bool,
+}
+
+impl InodeMap {
+    /// A map kept in memory only, numbers are still derived from the hashes.
+    pub fn new() -> Self {
+        Self {
+            by_identity: BTreeMap::new(),
+            by_inode: HashMap::new(),
+            path: None,
+            changed: false,
+        }
+    }
+
+    /// A map loaded from, and saved to, `path`. A missing or unreadable file
+    /// starts an empty map.
+    pub fn load(path: &Path) -> Self {
+        let mut map = Self::new();
+        map.path = Some(path.to_path_buf());
+        let saved: BTreeMap<String, u64> = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("MOUNT | ignoring unreadable inode map {:?}: {}", path, e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        for (identity, inode) in saved {
+            // a hand-edited map could hand one number to two files
+            if inode > 1 && !map.by_inode.contains_key(&inode) {
+                map.by_inode.insert(inode, identity.clone());
+                map.by_identity.insert(identity, inode);
+            }
+        }
+        map
+    }
+
+    /// The inode of `name`, a file in the entry described by `manifest`.
+    pub fn assign(&mut self, manifest: &ManifestFile, name: &str) -> u64 {
+        let identity = format!("{}/{}", manifest.original_hash, name);
+        if let Some(inode) = self.by_identity.get(&identity) {
+            return *inode;
+        }
+
+        let hash = blake3::hash(identity.as_bytes());
+        let mut inode = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap()) & INODE_MASK;
+        // 0 is invalid and 1 is the root
+        while inode <= 1 || self.by_inode.contains_key(&inode) {
+            inode = inode.wrapping_add(1) & INODE_MASK;
+        }
+        self.by_inode.insert(inode, identity.clone());
+        self.by_identity.insert(identity, inode);
+        self.changed = true;
+        inode
+    }
+
+    /// Forgets every inode `keep` returns false for.
+    pub fn retain(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        let before = self.by_inode.len();
+        self.by_inode.retain(|inode, _| keep(*inode));
+        if self.by_inode.len() != before {
+            self.by_identity
+                .retain(|_, inode| self.by_inode.contains_key(inode));
+            self.changed = true;
+        }
+    }
+
+    /// Writes the map if it has a file and changed since the last save. The
+    /// map is only an aid, failures are logged and otherwise ignored.
+    pub fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if !self.changed {
+            return;
+        }
+        let write = || -> Result<(), Box<dyn std::error::Error>> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // written next to it and renamed over, so a crash never leaves half a map
+            let staged = path.with_extension("tmp");
+            fs::write(&staged, serde_json::to_vec_pretty(&self.by_identity)?)?;
+            fs::rename(&staged, path)?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => self.changed = false,
+            Err(e) => tracing::warn!("MOUNT | cannot write inode map {:?}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::manifest::{ErasureCoding, MerkleTreeStructure};
+    use tempfile::TempDir;
+
+    fn manifest(hash: &str) -> ManifestFile {
+        ManifestFile {
+            erasure_coding: ErasureCoding {
+                data_shards: 1,
+                parity_shards: 3,
+                r#type: "RS".to_string(),
+            },
+            merkle_tree: MerkleTreeStructure {
+                leaves: HashMap::new(),
+                segments: HashMap::new(),
+                blocks: HashMap::new(),
+                root: String::new(),
+            },
+            name: "a.txt".to_string(),
+            original_hash: hash.to_string(),
+            size: 0,
+            time_of_creation: "2025-01-01".to_string(),
+            tier: 1,
+            segment_size: 64,
+            segment_policy: None,
+            pack: Vec::new(),
+            members: Vec::new(),
+            content: None,
+            parity_dir: None,
+            tags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_inodes_are_derived_and_collisions_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("inodes.json");
+        let (a, b) = (manifest("aaaa"), manifest("bbbb"));
+
+        // the same file gets the same number without any map
+        let derived = InodeMap::new().assign(&a, "a.txt");
+        assert_eq!(InodeMap::new().assign(&a, "a.txt"), derived);
+        assert!(derived > 1 && derived <= INODE_MASK);
+        assert_ne!(InodeMap::new().assign(&b, "a.txt"), derived);
+
+        // another file already holds that number, so this one moves past it
+        fs::write(&path, format!(r#"{{"other/file": {}}}"#, derived)).unwrap();
+        let mut map = InodeMap::load(&path);
+        let moved = map.assign(&a, "a.txt");
+        assert_ne!(moved, derived);
+        map.save();
+
+        // and keeps the moved number after a remount, with the other file gone
+        let mut map = InodeMap::load(&path);
+        map.retain(|inode| inode == moved);
+        map.save();
+        assert_eq!(InodeMap::load(&path).assign(&a, "a.txt"), moved);
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("other/file"));
+    }
+}
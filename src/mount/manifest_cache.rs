@@ -0,0 +1,117 @@
+//! On-disk copies of a remote archive's file list and manifests.
+//!
+//! A [`RemoteSource`](super::source::RemoteSource) with a cache directory
+//! keeps every file list and manifest response it gets, with the server's
+//! ETag. Later requests send the ETag along and a `304 Not Modified` is
+//! answered from the copy. When the server can't be reached at all the copy
+//! is used as it is, so a remote mount keeps listing its files, and serving
+//! the segments it has cached, through a short outage.
+//!
+//! Each server gets its own directory, named after a hash of its URL:
+//!
+//! ```text
+//! <cache dir>/<url hash>/files.json
+//! <cache dir>/<url hash>/manifests/<file name hash>.json
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A response body as the server last sent it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub body: String,
+}
+
+pub struct ManifestCache {
+    dir: PathBuf,
+}
+
+impl ManifestCache {
+    /// Cache for the server at `base_url`, kept under `cache_dir`.
+    pub fn new(cache_dir: &Path, base_url: &str) -> Self {
+        Self {
+            dir: cache_dir.join(&key(base_url)[..16]),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn file_list(&self) -> Option<CachedResponse> {
+        load(&self.dir.join("files.json"))
+    }
+
+    pub fn store_file_list(&self, response: &CachedResponse) {
+        store(&self.dir.join("files.json"), response);
+    }
+
+    pub fn manifest(&self, filename: &str) -> Option<CachedResponse> {
+        load(&self.manifest_path(filename))
+    }
+
+    pub fn store_manifest(&self, filename: &str, response: &CachedResponse) {
+        store(&self.manifest_path(filename), response);
+    }
+
+    /// Drops the manifests of files no longer in `current`.
+    pub fn retain_manifests<'a>(&self, current: impl IntoIterator<Item = &'a str>) {
+        let keep: HashSet<String> = current
+            .into_iter()
+            .map(|name| format!("{}.json", key(name)))
+            .collect();
+        let Ok(entries) = fs::read_dir(self.dir.join("manifests")) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if !keep.contains(entry.file_name().to_string_lossy().as_ref())
+                && let Err(e) = fs::remove_file(entry.path())
+            {
+                tracing::warn!("MANIFEST CACHE | cannot remove {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+
+    fn manifest_path(&self, filename: &str) -> PathBuf {
+        self.dir
+            .join("manifests")
+            .join(format!("{}.json", key(filename)))
+    }
+}
+
+/// File names and URLs can hold anything, their hashes are safe to use as paths.
+fn key(name: &str) -> String {
+    blake3::hash(name.as_bytes()).to_hex().to_string()
+}
+
+fn load(path: &Path) -> Option<CachedResponse> {
+    let bytes = fs::read(path).ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(response) => Some(response),
+        Err(e) => {
+            tracing::warn!("MANIFEST CACHE | ignoring unreadable {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Writes next to `path` and renames over it, so a crash never leaves half a
+/// file. The cache is only an aid, failures are logged and otherwise ignored.
+fn store(path: &Path, response: &CachedResponse) {
+    let write = || -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let staged = path.with_extension("json.tmp");
+        fs::write(&staged, serde_json::to_vec(response)?)?;
+        fs::rename(&staged, path)?;
+        Ok(())
+    };
+    if let Err(e) = write() {
+        tracing::warn!("MANIFEST CACHE | cannot write {:?}: {}", path, e);
+    }
+}
@@ -1,12 +1,28 @@
+#[cfg(feature = "mount")]
 pub mod cache;
+#[cfg(feature = "mount")]
+mod files;
+#[cfg(feature = "mount")]
+mod inodes;
+#[cfg(feature = "remote")]
+pub mod manifest_cache;
+pub mod options;
+#[cfg(feature = "remote")]
+mod remote;
+#[cfg(feature = "mount")]
+pub mod session;
 pub mod source;
+pub mod volume;
 
-#[cfg(unix)]
+#[cfg(feature = "mount")]
+pub use session::{MountHandle, Unmounter, mount, mount_local};
+
+#[cfg(all(unix, feature = "mount"))]
 mod filesystem_unix;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "mount"))]
 pub use filesystem_unix::BlockframeFS;
 
-#[cfg(windows)]
+#[cfg(all(windows, feature = "mount"))]
 mod filesystem_win;
-#[cfg(windows)]
+#[cfg(all(windows, feature = "mount"))]
 pub use filesystem_win::BlockframeFS;
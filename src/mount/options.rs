@@ -0,0 +1,326 @@
+//! Mount options passed through from `--mount-opt` and `mount.options`.
+//!
+//! Options are written the way `mount -o` takes them, one per entry, and
+//! mapped onto fuser's `MountOption`s on Unix and onto WinFsp's
+//! `VolumeParams` on Windows. The mount is always read-only, so `rw` is
+//! refused and `ro` is implied. Options that only mean something on the
+//! other platform are skipped with a warning, so one config can be shared.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Options used when `mount.options` isn't set.
+pub const DEFAULT_MOUNT_OPTIONS: [&str; 2] = ["auto_unmount", "default_permissions"];
+
+/// Filesystem name shown in `mount` output and by Windows, unless `fsname=` is given.
+pub const DEFAULT_FSNAME: &str = "blockframe";
+
+/// One option, as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MountOpt {
+    /// Name of the filesystem, on both platforms.
+    FsName(String),
+    /// Unix: let other users read the mount.
+    AllowOther,
+    /// Unix: let root read the mount as well as the user who mounted it.
+    AllowRoot,
+    /// Unix: unmount when the process exits, even if it crashed.
+    AutoUnmount,
+    /// Unix: have the kernel check the usual permission bits.
+    DefaultPermissions,
+    /// Unix: type shown after `fuse.` in `mount` output.
+    Subtype(String),
+    /// Unix: `nosuid`, `noexec`, `noatime`, `nodev` and their opposites,
+    /// `sync`, `async` and `dirsync`.
+    Flag(String),
+    /// Windows: UNC prefix that makes the volume a network share, e.g.
+    /// `\blockframe\archive`.
+    Prefix(String),
+    /// Windows: ask the OS to close files as soon as their handles are.
+    FlushAndPurgeOnCleanup,
+    /// Windows: milliseconds before a pending request times out, from 1 to 10 minutes.
+    IrpTimeout(u32),
+    /// Unix: anything else, handed to FUSE as it is.
+    Custom(String),
+}
+
+const FLAGS: [&str; 11] = [
+    "dev", "nodev", "suid", "nosuid", "exec", "noexec", "atime", "noatime", "sync", "async",
+    "dirsync",
+];
+
+impl FromStr for MountOpt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, given) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (s, None),
+        };
+        let value = |what: &str| {
+            given
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+                .ok_or_else(|| format!("mount option {} needs a {}", key, what))
+        };
+        Ok(match key {
+            "" => return Err("empty mount option".to_string()),
+            "rw" => return Err("the mount is read-only, rw can't be set".to_string()),
+            "ro" => Self::Flag("ro".to_string()),
+            "fsname" => Self::FsName(value("name")?),
+            "subtype" => Self::Subtype(value("name")?),
+            "allow_other" => Self::AllowOther,
+            "allow_root" => Self::AllowRoot,
+            "auto_unmount" => Self::AutoUnmount,
+            "default_permissions" => Self::DefaultPermissions,
+            "prefix" => Self::Prefix(value("UNC prefix")?),
+            "flush_and_purge_on_cleanup" => Self::FlushAndPurgeOnCleanup,
+            "irp_timeout" => {
+                let ms: u32 = value("number of milliseconds")?
+                    .parse()
+                    .map_err(|e| format!("mount option irp_timeout: {}", e))?;
+                if !(60_000..=600_000).contains(&ms) {
+                    return Err("mount option irp_timeout must be 60000 to 600000 ms".to_string());
+                }
+                Self::IrpTimeout(ms)
+            }
+            flag if FLAGS.contains(&flag) && given.is_none() => Self::Flag(flag.to_string()),
+            _ => Self::Custom(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for MountOpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FsName(name) => write!(f, "fsname={}", name),
+            Self::AllowOther => f.write_str("allow_other"),
+            Self::AllowRoot => f.write_str("allow_root"),
+            Self::AutoUnmount => f.write_str("auto_unmount"),
+            Self::DefaultPermissions => f.write_str("default_permissions"),
+            Self::Subtype(name) => write!(f, "subtype={}", name),
+            Self::Flag(flag) | Self::Custom(flag) => f.write_str(flag),
+            Self::Prefix(prefix) => write!(f, "prefix={}", prefix),
+            Self::FlushAndPurgeOnCleanup => f.write_str("flush_and_purge_on_cleanup"),
+            Self::IrpTimeout(ms) => write!(f, "irp_timeout={}", ms),
+        }
+    }
+}
+
+impl MountOpt {
+    /// Whether the option only applies to WinFsp volumes.
+    pub fn windows_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Prefix(_) | Self::FlushAndPurgeOnCleanup | Self::IrpTimeout(_)
+        )
+    }
+
+    /// Whether the option only applies to FUSE mounts.
+    pub fn unix_only(&self) -> bool {
+        !self.windows_only() && !matches!(self, Self::FsName(_))
+    }
+}
+
+/// The options a mount is made with.
+///
+/// # Examples
+///
+/// ```
+/// # use blockframe::mount::options::MountOptions;
+/// let options = MountOptions::parse(["auto_unmount", "fsname=photos", "allow_other"]).unwrap();
+/// assert_eq!(options.fsname(), "photos");
+/// assert!(MountOptions::parse(["rw"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountOptions {
+    options: Vec<MountOpt>,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        Self::parse(DEFAULT_MOUNT_OPTIONS).expect("default mount options parse")
+    }
+}
+
+impl MountOptions {
+    /// Parses each option, also taking comma-separated lists as `mount -o` does.
+    pub fn parse<I, S>(options: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut parsed = Vec::new();
+        for option in options {
+            for option in option.as_ref().split(',').map(str::trim) {
+                let option: MountOpt = option.parse()?;
+                if !parsed.contains(&option) {
+                    parsed.push(option);
+                }
+            }
+        }
+        Ok(Self { options: parsed })
+    }
+
+    /// Adds `more` after these options.
+    pub fn extend(mut self, more: MountOptions) -> Self {
+        for option in more.options {
+            if !self.options.contains(&option) {
+                self.options.push(option);
+            }
+        }
+        self
+    }
+
+    pub fn options(&self) -> &[MountOpt] {
+        &self.options
+    }
+
+    /// The last `fsname=` given, or [`DEFAULT_FSNAME`].
+    pub fn fsname(&self) -> &str {
+        self.options
+            .iter()
+            .rev()
+            .find_map(|option| match option {
+                MountOpt::FsName(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_FSNAME)
+    }
+
+    /// The options as fuser takes them, read-only and named first.
+    #[cfg(all(unix, feature = "mount"))]
+    pub fn fuser_options(&self) -> Vec<fuser::MountOption> {
+        use fuser::MountOption;
+
+        let mut fuser_options = vec![
+            MountOption::RO,
+            MountOption::FSName(self.fsname().to_string()),
+        ];
+        for option in &self.options {
+            let mapped = match option {
+                MountOpt::FsName(_) => continue,
+                MountOpt::AllowOther => MountOption::AllowOther,
+                MountOpt::AllowRoot => MountOption::AllowRoot,
+                MountOpt::AutoUnmount => MountOption::AutoUnmount,
+                MountOpt::DefaultPermissions => MountOption::DefaultPermissions,
+                MountOpt::Subtype(name) => MountOption::Subtype(name.clone()),
+                MountOpt::Flag(flag) => match flag.as_str() {
+                    "ro" => continue,
+                    "dev" => MountOption::Dev,
+                    "nodev" => MountOption::NoDev,
+                    "suid" => MountOption::Suid,
+                    "nosuid" => MountOption::NoSuid,
+                    "exec" => MountOption::Exec,
+                    "noexec" => MountOption::NoExec,
+                    "atime" => MountOption::Atime,
+                    "noatime" => MountOption::NoAtime,
+                    "sync" => MountOption::Sync,
+                    "async" => MountOption::Async,
+                    _ => MountOption::DirSync,
+                },
+                MountOpt::Custom(option) => MountOption::CUSTOM(option.clone()),
+                windows => {
+                    tracing::warn!("MOUNT | ignoring {}, it only applies on Windows", windows);
+                    continue;
+                }
+            };
+            if !fuser_options.contains(&mapped) {
+                fuser_options.push(mapped);
+            }
+        }
+        fuser_options
+    }
+
+    /// Sets the volume parameters these options stand for.
+    #[cfg(all(windows, feature = "mount"))]
+    pub fn apply_to(&self, params: &mut winfsp::host::VolumeParams) {
+        params.filesystem_name(self.fsname());
+        for option in &self.options {
+            match option {
+                MountOpt::FsName(_) => {}
+                MountOpt::Prefix(prefix) => {
+                    params.prefix(prefix);
+                }
+                MountOpt::FlushAndPurgeOnCleanup => {
+                    params.flush_and_purge_on_cleanup(true);
+                }
+                MountOpt::IrpTimeout(ms) => {
+                    params.irp_timeout(*ms);
+                }
+                // the defaults have nothing to do on WinFsp, which unmounts
+                // with the process and has no permission bits to check
+                MountOpt::AutoUnmount | MountOpt::DefaultPermissions => {}
+                MountOpt::Flag(flag) if flag == "ro" => {}
+                unix => tracing::warn!("MOUNT | ignoring {}, it only applies to FUSE", unix),
+            }
+        }
+    }
+}
+
+impl fmt::Display for MountOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options: Vec<String> = self.options.iter().map(ToString::to_string).collect();
+        f.write_str(&options.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mount_options() {
+        let options = MountOptions::parse([
+            "allow_other,noatime",
+            "fsname=a",
+            "fsname=b",
+            "max_read=65536",
+        ])
+        .unwrap();
+        assert_eq!(options.fsname(), "b");
+        assert_eq!(
+            options.options()[..2],
+            [MountOpt::AllowOther, MountOpt::Flag("noatime".to_string())]
+        );
+        assert_eq!(
+            options.options().last(),
+            Some(&MountOpt::Custom("max_read=65536".to_string()))
+        );
+        assert_eq!(
+            MountOptions::default().to_string(),
+            "auto_unmount,default_permissions"
+        );
+        assert_eq!(MountOptions::default().fsname(), DEFAULT_FSNAME);
+
+        for bad in ["rw", "fsname=", "irp_timeout=5", "prefix", ""] {
+            assert!(MountOptions::parse([bad]).is_err(), "{}", bad);
+        }
+        let windows = MountOptions::parse(["prefix=\\bf\\archive", "irp_timeout=60000"]).unwrap();
+        assert!(windows.options().iter().all(MountOpt::windows_only));
+        assert!(MountOpt::AllowOther.unix_only());
+        assert!(!MountOpt::FsName("x".to_string()).unix_only());
+    }
+
+    #[cfg(all(unix, feature = "mount"))]
+    #[test]
+    fn test_fuser_options() {
+        use fuser::MountOption;
+
+        let options = MountOptions::default()
+            .extend(
+                MountOptions::parse(["allow_other", "ro", "prefix=\\x\\y", "fsname=pics"]).unwrap(),
+            )
+            .fuser_options();
+        assert_eq!(
+            options,
+            [
+                MountOption::RO,
+                MountOption::FSName("pics".to_string()),
+                MountOption::AutoUnmount,
+                MountOption::DefaultPermissions,
+                MountOption::AllowOther,
+            ]
+        );
+    }
+}
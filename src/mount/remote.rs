@@ -0,0 +1,1142 @@
+//! Sources reading from `blockframe serve` over HTTP: one server with
+//! [`RemoteSource`], or several holding the same archive with
+//! [`MultiPeerSource`].
+
+use super::manifest_cache::{CachedResponse, ManifestCache};
+use super::source::SegmentSource;
+use crate::config::RemoteConfig;
+use crate::filestore::Integrity;
+use crate::filestore::recovery::{ShardKind, expected_shard};
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::signing::ManifestVerifier;
+use crate::utils::{REQUEST_ID_HEADER, blake3_hash_bytes};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// NEW: Match server's FileInfo response
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FileInfoResponse {
+    name: String,
+    size: i64,
+    tier: u8,
+}
+
+// NEW: Match server's manifest response wrapper
+#[derive(Debug, Deserialize)]
+struct ManifestResponse {
+    manifest: ManifestFile,
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+// Match server's ShardHash response
+#[derive(Debug, Deserialize)]
+struct ShardHashResponse {
+    hash: String,
+}
+
+/// Times a shard download picks up again after its connection drops.
+const MAX_RESUMES: usize = 3;
+
+/// A shard a [`RemoteSource`] downloaded twice that still doesn't match its
+/// manifest hash. The shard is damaged on the server, so callers recover it
+/// from parity as they would a corrupt local shard.
+#[derive(Debug)]
+pub struct ShardMismatch {
+    pub shard: String,
+}
+
+impl fmt::Display for ShardMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} does not match its manifest hash", self.shard)
+    }
+}
+
+impl std::error::Error for ShardMismatch {}
+
+/// Returned straight away by a [`RemoteSource`] whose server failed too many
+/// times in a row, until its cooldown runs out.
+#[derive(Debug)]
+pub struct RemoteUnavailable {
+    pub base_url: String,
+    pub retry_in: Duration,
+}
+
+impl fmt::Display for RemoteUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} keeps failing, trying it again in {}s",
+            self.base_url,
+            self.retry_in.as_secs_f32().ceil()
+        )
+    }
+}
+
+impl std::error::Error for RemoteUnavailable {}
+
+/// Counts failed attempts against one server. Once `threshold` fail in a row
+/// it opens: requests fail without being sent until `cooldown` has passed.
+/// The first request after that goes through, and if it fails too the
+/// breaker opens again at once.
+struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl Breaker {
+    fn new(config: &RemoteConfig) -> Self {
+        Self {
+            threshold: config.breaker_threshold,
+            cooldown: Duration::from_secs(config.breaker_cooldown),
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Time left before requests are let through again, `None` when closed.
+    fn open_for(&self) -> Option<Duration> {
+        let state = self.state.lock();
+        let remaining = state.open_until?.saturating_duration_since(Instant::now());
+        (!remaining.is_zero()).then_some(remaining)
+    }
+
+    fn success(&self) {
+        *self.state.lock() = BreakerState::default();
+    }
+
+    fn failure(&self, base_url: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock();
+        state.failures += 1;
+        if state.failures >= self.threshold {
+            if state.open_until.is_none() {
+                tracing::error!(
+                    "REMOTE | {} failed {} times in a row, failing fast for {}s",
+                    base_url,
+                    state.failures,
+                    self.cooldown.as_secs()
+                );
+            }
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+/// Whether a failed request is worth sending again: the server couldn't be
+/// reached, was too slow, or reported a problem on its side.
+fn is_transient(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::StatusCode(status) => *status >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::Protocol(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether `err` means the server couldn't be reached, as opposed to it
+/// answering with an error.
+fn is_unreachable(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.is::<RemoteUnavailable>()
+        || err
+            .downcast_ref::<ureq::Error>()
+            .is_some_and(|e| is_transient(e) && !matches!(e, ureq::Error::StatusCode(_)))
+}
+
+/// Whether a read failed with a [`ShardMismatch`].
+pub fn is_shard_mismatch(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.is::<ShardMismatch>()
+}
+
+/// A read that failed with a [`ShardMismatch`] as `None`, to be recovered
+/// like a shard that arrived corrupt.
+pub fn unless_mismatched(
+    read: Result<Vec<u8>, Box<dyn std::error::Error>>,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    match read {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if is_shard_mismatch(e.as_ref()) => {
+            tracing::error!("MOUNT | {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Hash of a parity shard, addressed the same way as [`SegmentSource::read_parity`].
+fn expected_parity(
+    manifest: &ManifestFile,
+    segment_id: usize,
+    parity_id: usize,
+    block_id: Option<usize>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match manifest.tier {
+        // tier 1 leaves are [data, parity_0, parity_1, parity_2]
+        1 => manifest
+            .merkle_tree
+            .leaves
+            .get(&(parity_id as i32 + 1))
+            .cloned(),
+        2 => manifest
+            .merkle_tree
+            .segments
+            .get(&segment_id)
+            .and_then(|s| s.parity.get(parity_id))
+            .cloned(),
+        3 => {
+            let block_id = block_id.ok_or("block_id is required for tier 3 parity reads")?;
+            manifest
+                .merkle_tree
+                .blocks
+                .get(&block_id)
+                .and_then(|b| b.parity_hash(parity_id))
+                .cloned()
+        }
+        _ => return Err("unknown tier".into()),
+    }
+    .ok_or_else(|| {
+        format!(
+            "Hash not found for parity {} of segment {}",
+            parity_id, segment_id
+        )
+        .into()
+    })
+}
+
+/// An HTTP agent with the timeouts of `config`, 0 meaning none.
+fn agent_for(config: &RemoteConfig) -> ureq::Agent {
+    let seconds = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+    ureq::Agent::config_builder()
+        .timeout_connect(seconds(config.connect_timeout))
+        .timeout_recv_response(seconds(config.read_timeout))
+        .timeout_recv_body(seconds(config.read_timeout))
+        .build()
+        .into()
+}
+
+/// `url` asking the server to skip the first `offset` bytes.
+fn with_offset(url: &str, offset: usize) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}offset={}", url, separator, offset)
+}
+
+/// Reads from a `blockframe serve` instance over HTTP.
+///
+/// Requests time out, transient failures are retried with exponential backoff,
+/// and a server that keeps failing is given up on for a while so callers get
+/// a [`RemoteUnavailable`] error at once instead of waiting on it, see
+/// [`RemoteConfig`].
+///
+/// Shard downloads that drop part way resume from the bytes already received,
+/// and every shard is checked against its manifest hash before it is returned.
+/// One that doesn't match is downloaded again from scratch with a freshly
+/// fetched manifest, then reported as a [`ShardMismatch`].
+///
+/// With a [`RemoteConfig::manifest_cache`] directory the file list and
+/// manifests are also kept on disk, see [`ManifestCache`].
+pub struct RemoteSource {
+    base_url: String,
+    agent: ureq::Agent,
+    verifier: Option<ManifestVerifier>,
+    /// Manifests fetched so far, refreshed by every [`SegmentSource::get_manifest`].
+    manifests: RwLock<HashMap<String, ManifestFile>>,
+    retries: u32,
+    backoff: Duration,
+    breaker: Breaker,
+    offline: Option<ManifestCache>,
+    /// Whether shards are checked against the manifest as they arrive.
+    verify: bool,
+}
+
+impl RemoteSource {
+    /// Talks to `base_url` with the default [`RemoteConfig`].
+    pub fn new(base_url: String) -> Self {
+        let config = RemoteConfig::default();
+        Self {
+            base_url,
+            agent: agent_for(&config),
+            verifier: None,
+            manifests: RwLock::new(HashMap::new()),
+            retries: config.retries,
+            backoff: Duration::from_millis(config.retry_backoff_ms),
+            breaker: Breaker::new(&config),
+            offline: None,
+            verify: true,
+        }
+    }
+
+    /// Uses the timeouts, retries, breaker and manifest cache settings of `config`.
+    pub fn with_config(mut self, config: &RemoteConfig) -> Self {
+        self.agent = agent_for(config);
+        self.retries = config.retries;
+        self.backoff = Duration::from_millis(config.retry_backoff_ms);
+        self.breaker = Breaker::new(config);
+        self.offline = config
+            .manifest_cache
+            .as_deref()
+            .map(|dir| ManifestCache::new(dir, &self.base_url));
+        self
+    }
+
+    /// Rejects manifests whose signature from the server doesn't verify.
+    pub fn with_verifier(mut self, verifier: Option<ManifestVerifier>) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    /// Only checks shards against the manifest under [`Integrity::Always`],
+    /// otherwise they are returned as downloaded.
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.verify = integrity == Integrity::Always;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Hash of tier 1 `data.dat` as computed by the server, `None` if it is missing.
+    pub fn data_hash(&self, filename: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.fetch_hash(&format!("{}/api/files/{}/hash", self.base_url, filename))
+    }
+
+    pub fn segment_hash(
+        &self,
+        filename: &str,
+        segment_id: usize,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.fetch_hash(&format!(
+            "{}/api/files/{}/segment/{}/hash",
+            self.base_url, filename, segment_id
+        ))
+    }
+
+    pub fn block_segment_hash(
+        &self,
+        filename: &str,
+        block_id: usize,
+        segment_id: usize,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.fetch_hash(&format!(
+            "{}/api/files/{}/block/{}/segment/{}/hash",
+            self.base_url, filename, block_id, segment_id
+        ))
+    }
+
+    /// Parity hash, addressed the same way as [`SegmentSource::read_parity`].
+    pub fn parity_hash(
+        &self,
+        filename: &str,
+        segment_id: usize,
+        parity_id: usize,
+        block_id: Option<usize>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let url = match block_id {
+            Some(bid) => format!(
+                "{}/api/files/{}/parity/hash?block_id={}&segment_id={}&parity_id={}",
+                self.base_url, filename, bid, segment_id, parity_id
+            ),
+            None => format!(
+                "{}/api/files/{}/parity/hash?segment_id={}&parity_id={}",
+                self.base_url, filename, segment_id, parity_id
+            ),
+        };
+        self.fetch_hash(&url)
+    }
+
+    /// A reader over the whole of `filename` as the server assembles it,
+    /// recovered server side but not checked here. Nothing is resumed, a
+    /// dropped connection ends the read with an error.
+    pub fn open_download(
+        &self,
+        filename: &str,
+    ) -> Result<impl Read + Send + use<>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/files/{}/download", self.base_url, filename);
+        Ok(self.call(&url)?.into_body().into_reader())
+    }
+
+    fn fetch_hash(&self, url: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        match self.call(url) {
+            Ok(mut response) => {
+                let shard: ShardHashResponse = response.body_mut().read_json()?;
+                Ok(Some(shard.hash))
+            }
+            Err(e) if matches!(e.downcast_ref(), Some(ureq::Error::StatusCode(404))) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn call(
+        &self,
+        url: &str,
+    ) -> Result<ureq::http::Response<ureq::Body>, Box<dyn std::error::Error>> {
+        self.call_with(url, None)
+    }
+
+    /// GETs `url`, retrying transient failures with backoff unless the
+    /// breaker has given up on the server. With `etag` the server may answer
+    /// `304 Not Modified` instead. Every attempt carries the same request ID,
+    /// which the server logs with the request.
+    fn call_with(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+    ) -> Result<ureq::http::Response<ureq::Body>, Box<dyn std::error::Error>> {
+        let id = format!("{:016x}", rand::random::<u64>());
+        let mut attempt = 0;
+        loop {
+            if let Some(retry_in) = self.breaker.open_for() {
+                return Err(Box::new(RemoteUnavailable {
+                    base_url: self.base_url.clone(),
+                    retry_in,
+                }));
+            }
+            let mut request = self.agent.get(url).header(REQUEST_ID_HEADER, &id);
+            if let Some(etag) = etag {
+                request = request.header("If-None-Match", etag);
+            }
+            let err = match request.call() {
+                Ok(response) => {
+                    self.breaker.success();
+                    return Ok(response);
+                }
+                Err(err) if !is_transient(&err) => {
+                    // the server answered, it just didn't like the request
+                    if matches!(err, ureq::Error::StatusCode(_)) {
+                        self.breaker.success();
+                    }
+                    return Err(err.into());
+                }
+                Err(err) => err,
+            };
+            self.breaker.failure(&self.base_url);
+            if attempt == self.retries {
+                return Err(err.into());
+            }
+            let delay = self.backoff * 2u32.saturating_pow(attempt);
+            tracing::warn!(
+                "REMOTE | {} failed: {}, retrying in {}ms (request {})",
+                url,
+                err,
+                delay.as_millis(),
+                id
+            );
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    fn manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
+        if let Some(manifest) = self.manifests.read().get(filename) {
+            return Ok(manifest.clone());
+        }
+        self.get_manifest(filename)
+    }
+
+    /// GETs the JSON at `url`, revalidating `cached` with its ETag and falling
+    /// back on it while the server is unreachable. The flag is set when the
+    /// body is new from the server and worth caching.
+    fn revalidate(
+        &self,
+        url: &str,
+        cached: Option<CachedResponse>,
+    ) -> Result<(CachedResponse, bool), Box<dyn std::error::Error>> {
+        let etag = cached.as_ref().and_then(|c| c.etag.as_deref());
+        match self.call_with(url, etag) {
+            Ok(response) if response.status() == 304 => cached
+                .map(|cached| (cached, false))
+                .ok_or_else(|| format!("{} answered 304 without being asked", url).into()),
+            Ok(mut response) => {
+                let etag = response
+                    .headers()
+                    .get("ETag")
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                let body = response.body_mut().with_config().read_to_string()?;
+                Ok((CachedResponse { etag, body }, true))
+            }
+            Err(e)
+                if is_unreachable(e.as_ref())
+                    && let Some(cached) = cached =>
+            {
+                tracing::warn!(
+                    "REMOTE | {} is unreachable, using the cached copy: {}",
+                    url,
+                    e
+                );
+                Ok((cached, false))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Downloads the shard at `url` and checks it against the hash `expected`
+    /// picks out of the file's manifest.
+    fn fetch_verified<F>(
+        &self,
+        filename: &str,
+        url: &str,
+        what: &str,
+        expected: F,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+    where
+        F: Fn(&ManifestFile) -> Result<String, Box<dyn std::error::Error>>,
+    {
+        if !self.verify {
+            return self.download(url);
+        }
+        let mut manifest = self.manifest(filename)?;
+        for attempt in 0..2 {
+            let bytes = self.download(url)?;
+            if blake3_hash_bytes(&bytes)? == expected(&manifest)? {
+                return Ok(bytes);
+            }
+            tracing::warn!(
+                "REMOTE | {} from {} failed verification",
+                what,
+                self.base_url
+            );
+            // a resumed download may have spanned a recommit of the file, in
+            // which case the cached manifest is out of date as well
+            if attempt == 0 {
+                manifest = self.get_manifest(filename)?;
+            }
+        }
+        Err(Box::new(ShardMismatch {
+            shard: format!("{} from {}", what, self.base_url),
+        }))
+    }
+
+    /// Downloads `url`, asking for the rest with `?offset=` when the
+    /// connection drops part way. Error statuses are returned as they are.
+    fn download(&self, url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut bytes = Vec::new();
+        let mut resumes = 0;
+        loop {
+            let request = match bytes.len() {
+                0 => url.to_string(),
+                received => with_offset(url, received),
+            };
+            // read_to_end keeps what arrived before an error
+            let failure = match self
+                .call(&request)?
+                .body_mut()
+                .with_config()
+                .reader()
+                .read_to_end(&mut bytes)
+            {
+                Ok(_) => return Ok(bytes),
+                Err(e) => e,
+            };
+            self.breaker.failure(&self.base_url);
+            if resumes == MAX_RESUMES {
+                return Err(failure.into());
+            }
+            resumes += 1;
+            tracing::warn!(
+                "REMOTE | {} dropped after {} bytes, resuming: {}",
+                url,
+                bytes.len(),
+                failure
+            );
+        }
+    }
+}
+
+impl SegmentSource for RemoteSource {
+    fn list_files(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/files", self.base_url);
+        let cache = self.offline.as_ref();
+        let (response, fresh) = self.revalidate(&url, cache.and_then(ManifestCache::file_list))?;
+        let files: Vec<FileInfoResponse> = serde_json::from_str(&response.body)?;
+        if fresh && let Some(cache) = cache {
+            cache.store_file_list(&response);
+            cache.retain_manifests(files.iter().map(|f| f.name.as_str()));
+        }
+        Ok(files.into_iter().map(|f| f.name).collect())
+    }
+
+    fn get_manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/files/{}/manifest", self.base_url, filename);
+        let cache = self.offline.as_ref();
+        let (body, fresh) =
+            self.revalidate(&url, cache.and_then(|cache| cache.manifest(filename)))?;
+        let response: ManifestResponse = serde_json::from_str(&body.body)?;
+        // cached copies are checked again, the key may have changed since
+        if let Some(verifier) = &self.verifier {
+            verifier.verify(&response.manifest, response.signature.as_deref())?;
+        }
+        if fresh && let Some(cache) = cache {
+            cache.store_manifest(filename, &body);
+        }
+        self.manifests
+            .write()
+            .insert(filename.to_string(), response.manifest.clone());
+        Ok(response.manifest)
+    }
+
+    fn read_segment(
+        &self,
+        filename: &str,
+        segment_id: usize,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let url = format!(
+            "{}/api/files/{}/segment/{}",
+            self.base_url, filename, segment_id
+        );
+        self.fetch_verified(
+            filename,
+            &url,
+            &format!("{} segment {}", filename, segment_id),
+            |manifest| Ok(expected_shard(manifest, ShardKind::Segment(segment_id))?.0),
+        )
+    }
+
+    fn read_block_segment(
+        &self,
+        filename: &str,
+        block_id: usize,
+        segment_id: usize,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let url = format!(
+            "{}/api/files/{}/block/{}/segment/{}",
+            self.base_url, filename, block_id, segment_id
+        );
+        self.fetch_verified(
+            filename,
+            &url,
+            &format!("{} block {} segment {}", filename, block_id, segment_id),
+            |manifest| Ok(expected_shard(manifest, ShardKind::Block(block_id, segment_id))?.0),
+        )
+    }
+
+    fn read_parity(
+        &self,
+        filename: &str,
+        segment_id: usize,
+        parity_id: usize,
+        block_id: Option<usize>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let url = if let Some(bid) = block_id {
+            format!(
+                "{}/api/files/{}/parity?block_id={}&segment_id={}&parity_id={}",
+                self.base_url, filename, bid, segment_id, parity_id
+            )
+        } else {
+            format!(
+                "{}/api/files/{}/parity?segment_id={}&parity_id={}",
+                self.base_url, filename, segment_id, parity_id
+            )
+        };
+        self.fetch_verified(
+            filename,
+            &url,
+            &format!(
+                "{} parity {} of segment {}",
+                filename, parity_id, segment_id
+            ),
+            |manifest| expected_parity(manifest, segment_id, parity_id, block_id),
+        )
+    }
+
+    fn write_parity(
+        &self,
+        filename: &str,
+        segment_id: usize,
+        block_id: Option<usize>,
+        _recovered_bytes: &[u8],
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let url = format!(
+            "{}/api/files/{}/parity?block_id={}&segment_id={}",
+            self.base_url,
+            filename,
+            block_id.unwrap_or(0),
+            segment_id,
+        );
+        self.call(&url)?;
+        Ok(true)
+    }
+
+    fn read_data(&self, filename: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/files/{}", self.base_url, filename);
+        self.fetch_verified(filename, &url, &format!("{} data", filename), |manifest| {
+            Ok(expected_shard(manifest, ShardKind::Tiny)?.0)
+        })
+    }
+}
+
+/// Spreads segment reads across several blockframe servers.
+///
+/// Each request starts at the next peer in round-robin order so load is shared,
+/// and falls through to the remaining peers when one is down or returns bytes
+/// that don't match the manifest. Nothing reaches the filesystem layer unless
+/// its BLAKE3 hash matches the manifest entry for that shard.
+pub struct MultiPeerSource {
+    peers: Vec<RemoteSource>,
+    next_peer: AtomicUsize,
+    manifests: RwLock<HashMap<String, ManifestFile>>,
+}
+
+impl MultiPeerSource {
+    /// Every peer verifies manifest signatures with `verifier` when set, so a peer
+    /// serving a tampered manifest is skipped like one that is down.
+    pub fn new(
+        base_urls: Vec<String>,
+        verifier: Option<ManifestVerifier>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if base_urls.is_empty() {
+            return Err("at least one peer url is required".into());
+        }
+        let peers = base_urls
+            .into_iter()
+            .map(|url| RemoteSource::new(url).with_verifier(verifier.clone()))
+            .collect();
+        Ok(Self {
+            peers,
+            next_peer: AtomicUsize::new(0),
+            manifests: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Gives every peer the timeouts, retries and breaker settings of `config`.
+    /// Each peer keeps its own breaker, so one that is down is skipped quickly.
+    pub fn with_config(mut self, config: &RemoteConfig) -> Self {
+        self.peers = self
+            .peers
+            .into_iter()
+            .map(|peer| peer.with_config(config))
+            .collect();
+        self
+    }
+
+    /// Peer indices in the order a single request should try them.
+    fn peer_order(&self) -> impl Iterator<Item = usize> + '_ {
+        let start = self.next_peer.fetch_add(1, Ordering::Relaxed) % self.peers.len();
+        (0..self.peers.len()).map(move |i| (start + i) % self.peers.len())
+    }
+
+    fn manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
+        if let Some(manifest) = self.manifests.read().get(filename) {
+            return Ok(manifest.clone());
+        }
+        let manifest = self.get_manifest(filename)?;
+        self.manifests
+            .write()
+            .insert(filename.to_string(), manifest.clone());
+        Ok(manifest)
+    }
+
+    /// Runs `fetch` against each peer in turn until one returns bytes whose hash
+    /// matches `expected_hash`.
+    fn fetch_verified<F>(
+        &self,
+        what: &str,
+        expected_hash: &str,
+        fetch: F,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+    where
+        F: Fn(&RemoteSource) -> Result<Vec<u8>, Box<dyn std::error::Error>>,
+    {
+        let mut last_err: Box<dyn std::error::Error> = "no peers tried".into();
+        for idx in self.peer_order() {
+            let peer = &self.peers[idx];
+            match fetch(peer) {
+                Ok(bytes) => {
+                    if blake3_hash_bytes(&bytes)? == expected_hash {
+                        return Ok(bytes);
+                    }
+                    tracing::warn!(
+                        "MULTIPEER | {} from {} failed verification",
+                        what,
+                        peer.base_url
+                    );
+                    last_err =
+                        format!("{} from {} failed verification", what, peer.base_url).into();
+                }
+                Err(e) => {
+                    tracing::warn!("MULTIPEER | {} from {} failed: {}", what, peer.base_url, e);
+                    last_err = e;
+                }
+            }
+        }
+        Err(format!("all peers failed for {}: {}", what, last_err).into())
+    }
+
+    /// Runs `fetch` against each peer in turn until one succeeds. Used for
+    /// metadata that has nothing in the manifest to verify against.
+    fn fetch_any<T, F>(&self, what: &str, fetch: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: Fn(&RemoteSource) -> Result<T, Box<dyn std::error::Error>>,
+    {
+        let mut last_err: Box<dyn std::error::Error> = "no peers tried".into();
+        for idx in self.peer_order() {
+            let peer = &self.peers[idx];
+            match fetch(peer) {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    tracing::warn!("MULTIPEER | {} from {} failed: {}", what, peer.base_url, e);
+                    last_err = e;
+                }
+            }
+        }
+        Err(format!("all peers failed for {}: {}", what, last_err).into())
+    }
+}
+
+impl SegmentSource for MultiPeerSource {
+    fn list_files(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.fetch_any("file list", |peer| peer.list_files())
+    }
+
+    fn get_manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
+        self.fetch_any(&format!("manifest for {}", filename), |peer| {
+            peer.get_manifest(filename)
+        })
+    }
+
+    fn read_segment(
+        &self,
+        filename: &str,
+        segment_id: usize,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let manifest = self.manifest(filename)?;
+        let expected = manifest
+            .merkle_tree
+            .segments
+            .get(&segment_id)
+            .map(|s| s.data.clone())
+            .ok_or(format!("Hash not found for segment {}", segment_id))?;
+        self.fetch_verified(
+            &format!("{} segment {}", filename, segment_id),
+            &expected,
+            |peer| peer.read_segment(filename, segment_id),
+        )
+    }
+
+    fn read_block_segment(
+        &self,
+        filename: &str,
+        block_id: usize,
+        segment_id: usize,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let manifest = self.manifest(filename)?;
+        let expected = manifest
+            .merkle_tree
+            .blocks
+            .get(&block_id)
+            .and_then(|b| b.segments.get(segment_id))
+            .cloned()
+            .ok_or(format!(
+                "Hash not found for block {} segment {}",
+                block_id, segment_id
+            ))?;
+        self.fetch_verified(
+            &format!("{} block {} segment {}", filename, block_id, segment_id),
+            &expected,
+            |peer| peer.read_block_segment(filename, block_id, segment_id),
+        )
+    }
+
+    fn read_parity(
+        &self,
+        filename: &str,
+        segment_id: usize,
+        parity_id: usize,
+        block_id: Option<usize>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let manifest = self.manifest(filename)?;
+        let expected = expected_parity(&manifest, segment_id, parity_id, block_id)?;
+        self.fetch_verified(
+            &format!(
+                "{} parity {} of segment {}",
+                filename, parity_id, segment_id
+            ),
+            &expected,
+            |peer| peer.read_parity(filename, segment_id, parity_id, block_id),
+        )
+    }
+
+    fn write_parity(
+        &self,
+        filename: &str,
+        segment_id: usize,
+        block_id: Option<usize>,
+        recovered_bytes: &[u8],
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // let every reachable peer know, a single healthy peer is enough
+        let mut any = false;
+        for peer in &self.peers {
+            match peer.write_parity(filename, segment_id, block_id, recovered_bytes) {
+                Ok(ok) => any |= ok,
+                Err(e) => {
+                    tracing::warn!(
+                        "MULTIPEER | write_parity to {} failed: {}",
+                        peer.base_url,
+                        e
+                    )
+                }
+            }
+        }
+        Ok(any)
+    }
+
+    fn read_data(&self, filename: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let manifest = self.manifest(filename)?;
+        self.fetch_verified(
+            &format!("{} data", filename),
+            &manifest.original_hash,
+            |peer| peer.read_data(filename),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use tempfile::TempDir;
+
+    /// Serves the manifest and segment 0 of `file_dir`, noting the offset of
+    /// every segment request. The first download of the segment hangs up half
+    /// way; with `corrupt` every copy is damaged.
+    fn serve(
+        file_dir: &Path,
+        corrupt: Arc<AtomicBool>,
+        offsets: Arc<parking_lot::Mutex<Vec<usize>>>,
+    ) -> String {
+        let manifest = fs::read_to_string(file_dir.join("manifest.json")).unwrap();
+        let manifest = format!(r#"{{"manifest": {}, "signature": null}}"#, manifest);
+        let segment = fs::read(file_dir.join("segments/segment_0.dat")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let mut dropped = false;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+
+                let mut body = match path.contains("/manifest") {
+                    true => manifest.clone().into_bytes(),
+                    false => segment.clone(),
+                };
+                if !path.contains("/manifest") && corrupt.load(Ordering::SeqCst) {
+                    body[0] ^= 0xff;
+                }
+                let offset = path
+                    .split("offset=")
+                    .nth(1)
+                    .map_or(0, |n| n.parse::<usize>().unwrap());
+                if path.contains("/segment/") {
+                    offsets.lock().push(offset);
+                }
+                let body = &body[offset..];
+                let sent = match path.contains("/segment/") && !dropped {
+                    true => {
+                        dropped = true;
+                        body.len() / 2
+                    }
+                    false => body.len(),
+                };
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body[..sent]);
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_remote_segment_resumes_and_verifies() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("remote.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(2)).unwrap();
+
+        let corrupt = Arc::new(AtomicBool::new(false));
+        let offsets = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let remote = RemoteSource::new(serve(&chunked.file_dir, corrupt.clone(), offsets.clone()));
+
+        // the first download drops half way and picks up from the offset
+        let segment = remote.read_segment("remote.bin", 0).unwrap();
+        assert_eq!(segment, data[..65_536]);
+        assert_eq!(*offsets.lock(), [0, 32_768]);
+
+        // damaged on the server: tried twice, then reported for recovery
+        corrupt.store(true, Ordering::SeqCst);
+        let err = remote.read_segment("remote.bin", 0).unwrap_err();
+        assert!(is_shard_mismatch(err.as_ref()), "{}", err);
+        assert_eq!(
+            unless_mismatched(remote.read_segment("remote.bin", 0)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_remote_retries_times_out_and_fails_fast() {
+        let config = RemoteConfig {
+            connect_timeout: 1,
+            read_timeout: 1,
+            retries: 1,
+            retry_backoff_ms: 1,
+            breaker_threshold: 2,
+            breaker_cooldown: 60,
+            manifest_cache: None,
+        };
+
+        // a 503 is retried, the second attempt gets the list
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let reply = match i {
+                    0 => {
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    }
+                    _ => "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]",
+                };
+                let _ = stream.write_all(reply.as_bytes());
+            }
+        });
+        let remote = RemoteSource::new(url).with_config(&config);
+        assert!(remote.list_files().unwrap().is_empty());
+
+        // a server that accepts and never answers times out
+        let hung = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", hung.local_addr().unwrap());
+        let remote = RemoteSource::new(url).with_config(&config);
+        let started = Instant::now();
+        let err = remote.list_files().unwrap_err();
+        assert!(!err.is::<RemoteUnavailable>(), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        // both attempts failed, which opens the breaker: the next call isn't sent
+        let started = Instant::now();
+        let err = remote.get_manifest("anything").unwrap_err();
+        assert!(err.is::<RemoteUnavailable>(), "{}", err);
+        assert!(started.elapsed() < Duration::from_millis(100));
+        drop(hung);
+    }
+
+    #[test]
+    fn test_remote_manifest_cache_revalidates_and_serves_offline() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("cached.bin");
+        fs::write(&source, vec![7u8; 100_000]).unwrap();
+        let chunker = crate::chunker::Chunker::builder()
+            .archive_dir(temp_dir.path().join("archive_directory"))
+            .segment_policy(crate::utils::SegmentPolicy::Fixed(65_536))
+            .build()
+            .unwrap();
+        let chunked = chunker.commit_as(&source, Some(2)).unwrap();
+        let manifest = fs::read_to_string(chunked.file_dir.join("manifest.json")).unwrap();
+        let manifest = format!(r#"{{"manifest": {}, "signature": null}}"#, manifest);
+        let files = r#"[{"name": "cached.bin", "size": 100000, "tier": 2}]"#.to_string();
+
+        // answers with an ETag and 304s a request that sends it back, and
+        // hangs up without a word once `down` is set
+        let down = Arc::new(AtomicBool::new(false));
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (server_down, server_304) = (down.clone(), not_modified.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut revalidating = false;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    revalidating |= header.to_ascii_lowercase().starts_with("if-none-match");
+                }
+                if server_down.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let body = match request.contains("/manifest") {
+                    true => &manifest,
+                    false => &files,
+                };
+                let reply = match revalidating {
+                    true => {
+                        server_304.fetch_add(1, Ordering::SeqCst);
+                        "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                    false => format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                };
+                let _ = stream.write_all(reply.as_bytes());
+            }
+        });
+
+        let config = RemoteConfig {
+            retries: 0,
+            breaker_threshold: 0,
+            manifest_cache: Some(temp_dir.path().join("remote_cache")),
+            ..RemoteConfig::default()
+        };
+        let remote = RemoteSource::new(url.clone()).with_config(&config);
+        assert_eq!(remote.list_files().unwrap(), ["cached.bin"]);
+        let original = remote.get_manifest("cached.bin").unwrap();
+
+        // the second round only revalidates
+        assert_eq!(remote.list_files().unwrap(), ["cached.bin"]);
+        remote.get_manifest("cached.bin").unwrap();
+        assert_eq!(not_modified.load(Ordering::SeqCst), 2);
+
+        // a fresh mount of the same server gets by on the disk copies alone
+        down.store(true, Ordering::SeqCst);
+        let restarted = RemoteSource::new(url).with_config(&config);
+        assert_eq!(restarted.list_files().unwrap(), ["cached.bin"]);
+        let offline = restarted.get_manifest("cached.bin").unwrap();
+        assert_eq!(offline.original_hash, original.original_hash);
+
+        // files the server didn't list aren't made up
+        assert!(restarted.get_manifest("other.bin").is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! Mounting from a library, without the `blockframe` binary.
+//!
+//! [`mount`] mounts any [`SegmentSource`] and [`mount_local`] an archive
+//! directory. Either serves the filesystem on a background thread and hands
+//! back a [`MountHandle`], so the caller carries on while it is mounted and
+//! unmounts it when done. `blockframe mount` is [`mount`] followed by a wait.
+//!
+//! ```no_run
+//! # use blockframe::mount::options::MountOptions;
+//! # use blockframe::mount::session::mount_local;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let handle = mount_local("archive", "/mnt/blockframe", MountOptions::default())?;
+//! // read files under /mnt/blockframe...
+//! handle.unmount()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use super::BlockframeFS;
+use super::options::MountOptions;
+use super::source::{LocalSource, SegmentSource};
+use crate::config::{CacheConfig, MountConfig};
+
+/// Mounts the archive directory `archive` at `mountpoint`, with the default
+/// `[cache]` and `[mount]` settings.
+pub fn mount_local(
+    archive: impl Into<PathBuf>,
+    mountpoint: impl AsRef<Path>,
+    options: MountOptions,
+) -> Result<MountHandle, Box<dyn std::error::Error>> {
+    let source = LocalSource::new(archive.into())?;
+    mount(
+        Box::new(source),
+        mountpoint,
+        &options,
+        &CacheConfig::default(),
+        &MountConfig::default(),
+    )
+}
+
+/// Mounts `source` at `mountpoint` and starts serving it on a background
+/// thread. Returns once the mount is in place.
+pub fn mount(
+    source: Box<dyn SegmentSource>,
+    mountpoint: impl AsRef<Path>,
+    options: &MountOptions,
+    cache_config: &CacheConfig,
+    mount_config: &MountConfig,
+) -> Result<MountHandle, Box<dyn std::error::Error>> {
+    let mountpoint = mountpoint.as_ref();
+    mount_config.volume.validate()?;
+    #[cfg(windows)]
+    {
+        windows_mount(source, mountpoint, options, cache_config, mount_config)
+    }
+    #[cfg(unix)]
+    {
+        unix_mount(source, mountpoint, options, cache_config, mount_config)
+    }
+}
+
+/// A mounted archive. Dropping it unmounts the archive too.
+pub struct MountHandle {
+    mountpoint: PathBuf,
+    unmounter: Unmounter,
+    serving: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl MountHandle {
+    pub fn mountpoint(&self) -> &Path {
+        &self.mountpoint
+    }
+
+    /// Something to unmount with from another thread, e.g. while this one
+    /// is in [`MountHandle::join`].
+    pub fn unmounter(&self) -> Unmounter {
+        self.unmounter.clone()
+    }
+
+    /// Unmounts and waits for the filesystem to stop.
+    pub fn unmount(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.unmounter.unmount()?;
+        self.join()
+    }
+
+    /// Waits until the filesystem is unmounted, by an [`Unmounter`] or on
+    /// Unix also from outside, e.g. by `fusermount -u`.
+    pub fn join(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.wait()
+    }
+
+    fn wait(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(serving) = self.serving.take() {
+            serving.join().map_err(|_| "mount thread panicked")??;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MountHandle {
+    fn drop(&mut self) {
+        if self.serving.is_some() {
+            let _ = self.unmounter.unmount();
+            let _ = self.wait();
+        }
+    }
+}
+
+/// Unmounts the archive of a [`MountHandle`], from any thread.
+#[derive(Clone)]
+pub struct Unmounter {
+    #[cfg(unix)]
+    session: Arc<parking_lot::Mutex<fuser::SessionUnmounter>>,
+    #[cfg(windows)]
+    requested: Arc<(parking_lot::Mutex<bool>, parking_lot::Condvar)>,
+}
+
+impl Unmounter {
+    pub fn unmount(&self) -> Result<(), Box<dyn std::error::Error>> {
+        #[cfg(unix)]
+        self.session.lock().unmount()?;
+        #[cfg(windows)]
+        {
+            let (requested, changed) = &*self.requested;
+            *requested.lock() = true;
+            changed.notify_all();
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn wait(&self) {
+        let (requested, changed) = &*self.requested;
+        let mut requested = requested.lock();
+        while !*requested {
+            changed.wait(&mut requested);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unix_mount(
+    source: Box<dyn SegmentSource>,
+    mountpoint: &Path,
+    options: &MountOptions,
+    cache_config: &CacheConfig,
+    mount_config: &MountConfig,
+) -> Result<MountHandle, Box<dyn std::error::Error>> {
+    tracing::info!("MOUNT | creating filesystem");
+    let fs = BlockframeFS::new(source, cache_config, mount_config)?;
+
+    // the options are a rulebook handed to the FUSE kernel module
+    // before the filesystem mounts. It is always read-only (`ro`),
+    // blocking writes at the system call level, and named `fsname`.
+    // By default it also unmounts by itself if blockframe crashes
+    // or exits (`auto_unmount`), and has the kernel do the usual
+    // rwx permission checks (`default_permissions`).
+    let fuser_options = options.fuser_options();
+
+    // This is the zombie mount.
+    // we start by checking if our mount point doesnt exist, as in the directory to "mount" our filesystem
+    // TANGENT_EXPLAINATION: the reason this is a zombie mount is due to the difference in how linux's filesystem works.
+    // linux doesnt have drives, it has a huge filesystem which is associated with permissions.
+    // However, with linux, if our BlockframeFS crashes for some reason, our linux kernel wont be informed properly.
+    // If blockframe crashes, it wont have time to inform the kernel, beacuse unmounting is a manual call that needs to be made.
+    // The reason we have to unmount, if blockframe crashes, and we couldnt call fusermount -u, linux will still sustain that mountpoint still belongs to a process that's not alive anymore, making that mountpoint stale.
+    if !mountpoint.exists() {
+        // if our mountpoint doesnt exist
+        // we then attempt to check if creating out mountpoint causes an error
+        match std::fs::create_dir_all(mountpoint) {
+            // if there were no errors, we exit the match predicate no problems.
+            // we created our mountpoint
+            Ok(_) => {}
+            // if creating the dir does cause an error, we check to see if its an AlreadyExists
+            // meaning we still have a successful outcome
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                tracing::warn!("Mountpoint appears to be stale. Attempting cleanup...");
+                let _ = std::process::Command::new("fusermount") // fusermount allows non-root users to mount and unmount FUSE filesystems.
+                    .arg("-u") // -u is unmount
+                    .arg("-q") // -q (Quiet). We are calling unmount speculatively.
+                    // If the directory wasn't actually mounted (and the mkdir failed for a different reason),
+                    // fusermount would normally error out. -q suppresses that error so we don't spam logs.
+                    .arg(mountpoint) // we also pass in our given mountpoint as the directory we're trying to fix
+                    .status();
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // and finally we mount the blockframe filesystem.
+    // with linux this is a bit different.
+    // TANGENT_EXPLAINATION: On windows our filesystem is "plugged in"
+    // where as on linux, our filesystem is "placed on-top".
+    // Linux doesnt have drives, as akin to windows E: or C:. Linux is just one filesystem with a bunch of folders.
+    // When we mount or filesystem on linux, what is happening is, blockframe creats a telephone line (a socket) to the linux kernel
+    // when the user checks to see the files, instead of seeing the physical files placed in that folder, the linux kernel intercepts `ls` request and understands that there is a process attached to that folder
+    // instead of being served the actual files in that folder, blockframe instead serves the files.
+    tracing::info!("MOUNT | mounting to: {:?}", mountpoint);
+    let mut session = fuser::Session::new(fs, mountpoint, &fuser_options)?;
+    let unmounter = Unmounter {
+        session: Arc::new(parking_lot::Mutex::new(session.unmount_callable())),
+    };
+    // the session answers the kernel until the mount goes away
+    let serving = thread::Builder::new()
+        .name("blockframe-mount".to_string())
+        .spawn(move || session.run())?;
+
+    Ok(MountHandle {
+        mountpoint: mountpoint.to_path_buf(),
+        unmounter,
+        serving: Some(serving),
+    })
+}
+
+#[cfg(windows)]
+fn windows_mount(
+    source: Box<dyn SegmentSource>,
+    mountpoint: &Path,
+    options: &MountOptions,
+    cache_config: &CacheConfig,
+    mount_config: &MountConfig,
+) -> Result<MountHandle, Box<dyn std::error::Error>> {
+    use std::sync::mpsc;
+
+    // the serial is taken before the source moves into the filesystem,
+    // `auto` lists the archive for it
+    let volume_serial = mount_config.volume.serial_number(|| source.list_files())?;
+
+    tracing::info!("MOUNT | creating filesystem");
+    let fs = BlockframeFS::new(source, cache_config, mount_config)?;
+
+    // winfsp_init_or_die is called to check if WinFsp runtime is loaded and ready
+    // if WinFsp isnt installed or cant initalise, the process aborts immediately.
+    tracing::info!("MOUNT | initializing WinFsp");
+    winfsp::winfsp_init_or_die();
+
+    let unmounter = Unmounter {
+        requested: Arc::default(),
+    };
+    let waiter = unmounter.clone();
+    let target = mountpoint.to_path_buf();
+    let options = options.clone();
+    let mount_config = mount_config.clone();
+    // the host is made, run and stopped on one thread, which reports back once
+    // the volume is mounted or failed to
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    let serving = thread::Builder::new()
+        .name("blockframe-mount".to_string())
+        .spawn(move || {
+            let volume_params = volume_params(&mount_config, volume_serial, &options);
+            let started = (|| -> Result<_, Box<dyn std::error::Error>> {
+                // host is a FileSystemHost object which manages the lifetime of the mounted volume
+                // it binds the `BlockframeFS` to WinFsp using the `volume_params` that were defined.
+                tracing::info!("MOUNT | creating filesystem host");
+                let mut host = winfsp::host::FileSystemHost::new(volume_params, fs)?;
+
+                // this mounts our provided mountpoint which is the directory where the filesystem will mount
+                tracing::info!("MOUNT | mounting to: {:?}", target);
+                host.mount(&target)?;
+
+                // we then start our request loop.
+                // from this point on, windows explore, dir, file reads will actively be called into the filesystem
+                tracing::info!("MOUNT | starting filesystem");
+                host.start()?;
+                Ok(host)
+            })();
+            let mut host = match started {
+                Ok(host) => {
+                    let _ = ready_tx.send(Ok(()));
+                    host
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return Ok(());
+                }
+            };
+            waiter.wait();
+            host.stop();
+            host.unmount();
+            Ok(())
+        })?;
+    ready_rx
+        .recv()
+        .map_err(|_| "mount thread exited before mounting")??;
+
+    Ok(MountHandle {
+        mountpoint: mountpoint.to_path_buf(),
+        unmounter,
+        serving: Some(serving),
+    })
+}
+
+#[cfg(windows)]
+fn volume_params(
+    mount_config: &MountConfig,
+    volume_serial: u32,
+    options: &MountOptions,
+) -> winfsp::host::VolumeParams {
+    use super::volume::format_serial;
+
+    // volume_params is a VolumeParams object
+    // VolumeParams is an object that configures the medata about the virtual disk being presented to windows
+    // its used to describe the shape and rules of the filesystem volume so windows knows how to interact with it
+    tracing::info!("MOUNT | configuring volume parameters");
+    let mut volume_params = winfsp::host::VolumeParams::new();
+
+    // The `sector_size` tells windows the logical sector size of the filesystem
+    // it defaults to 512 bytes which is the traditional disk sector size.
+    volume_params.sector_size(mount_config.volume.sector_size);
+
+    // `sectors_per_allocation_unit` defines the allocation unit (cluster) size.
+    // with the default 1 sector per allocation unit, the cluster size is 512 bytes.
+    // small clusters reduce wasted space but increase metadata churn, but this is a conservative and filesystem-friendly
+    volume_params.sectors_per_allocation_unit(mount_config.volume.sectors_per_allocation_unit);
+
+    // `volume_serial_number` is the filesystems ID. Windows uses it to recognise whether a volume is "the same" across mounts.
+    // `auto` derives it from the archive's file names, so remounting the same archive gives the same volume.
+    tracing::info!("MOUNT | volume serial: {}", format_serial(volume_serial));
+    volume_params.volume_serial_number(volume_serial);
+
+    // `file_info_timeout` is a cache hint in miliseconds
+    // we're flagging that windows is allowed to cache the file's metadata for `mount.attr_ttl` seconds before asking the filesystem again
+    // the 1 second default is used for reducing call spam and a reasonable balance between refreshes.
+    volume_params.file_info_timeout(
+        u32::try_from(mount_config.attr_ttl.saturating_mul(1000)).unwrap_or(u32::MAX),
+    );
+
+    // `case_sensitive_search` off treats 'File.txt' and 'file.txt' the same when searching,
+    // unless `--case-sensitive` or mount.volume.case_sensitive is set
+    volume_params.case_sensitive_search(mount_config.volume.case_sensitive);
+
+    // `case_preserved_names` ensures filenames sustain thier casing when displayed. Even though seraches are case-insensitive
+    // quality of life option, and its enabled to match windows-esk functionality
+    volume_params.case_preserved_names(true);
+    // `unicode_on_disk` flags if filenames are treated as unicode. This ensures multi-lingual and obscure characters dont panic the volume
+    volume_params.unicode_on_disk(true);
+    // `persistent_acls` is flagged to false as Blockframe doesnt impliment Access Control Lists (acl)
+    // ACL's are not stored persistently by Blockframe. Windows will still ask about permissions
+    // If ACL's are persisted, windows would start relying on behaviour which is not supported by Blockframe
+    // enabling would create subtle breakages such as access denied errors, explorer weirdness or files being unreadable for no obvious reason.
+    volume_params.persistent_acls(false);
+    // `post_cleanup_when_modified_only` is an optimisation.
+    // cleanup work after file handles close only happens if the file was actually modified.
+    // less overhead, fewer pointless calls.
+    volume_params.post_cleanup_when_modified_only(true);
+
+    // file system name, UNC prefix and the rest of `--mount-opt`
+    options.apply_to(&mut volume_params);
+    volume_params
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunker;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    #[ignore] // Skipping: needs FUSE and fusermount on the machine running the tests
+    fn test_mount_local_serves_until_unmounted() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        fs::write(&path, "hello from the archive").unwrap();
+        chunker.commit(&path).unwrap();
+
+        let mountpoint = temp_dir.path().join("mnt");
+        let handle = mount_local(&archive_dir, &mountpoint, MountOptions::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(handle.mountpoint().join("hello.txt")).unwrap(),
+            "hello from the archive"
+        );
+        handle.unmount().unwrap();
+        assert!(!mountpoint.join("hello.txt").exists());
+    }
+}
@@ -1,22 +1,68 @@
-use crate::filestore::FileStore;
+use crate::filestore::{FileReader, FileStore, Integrity};
 use crate::merkle_tree::manifest::ManifestFile;
-use serde::{Deserialize, Serialize};
+use crate::signing::ManifestVerifier;
+use crate::utils::{blake3_hash_bytes, replace_shard};
+use bytes::Bytes;
 use std::fs;
-use std::path::Path;
-use std::path::PathBuf;
-// NEW: Match server's FileInfo response
-
-#[derive(Debug, Deserialize, Serialize)]
-struct FileInfoResponse {
-    name: String,
-    size: i64,
-    tier: u8,
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "remote")]
+pub use super::remote::{
+    MultiPeerSource, RemoteSource, RemoteUnavailable, ShardMismatch, is_shard_mismatch,
+    unless_mismatched,
+};
+
+/// Shards at least this large are mapped by [`LocalSource`], smaller ones
+/// cost less to copy than to map.
+const MAP_THRESHOLD: u64 = 256 * 1024;
+
+/// Maps the shard at `path` on Unix when it is at least [`MAP_THRESHOLD`]
+/// long, so serving it neither allocates nor copies, and reads it otherwise.
+/// Either way slices of the result share its bytes.
+/// Windows keeps a mapped file from being replaced or removed, so shards are
+/// always read there.
+pub fn map_shard(path: &Path) -> Result<Bytes, std::io::Error> {
+    #[cfg(unix)]
+    {
+        let file = fs::File::open(path)?;
+        if file.metadata()?.len() >= MAP_THRESHOLD {
+            // SAFETY: blockframe replaces shards by renaming a new file over
+            // them (see `replace_shard`) and never rewrites one in place, so
+            // the mapped file can't change or shrink unless something outside
+            // blockframe edits the archive
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            // mounts mostly stream media front to back
+            let _ = map.advise(memmap2::Advice::Sequential);
+            return Ok(Bytes::from_owner(map));
+        }
+    }
+    Ok(Bytes::from(fs::read(path)?))
 }
 
-// NEW: Match server's manifest response wrapper
-#[derive(Debug, Deserialize)]
-struct ManifestResponse {
-    manifest: ManifestFile,
+/// A segment read as a mount's [`Integrity`] policy has it: the bytes if they
+/// can be served, `None` if the segment should be recovered from parity.
+/// With [`Integrity::Always`] the bytes are checked against `expected`; with
+/// [`Integrity::Off`] a failed read is returned as the error it is.
+pub fn checked_read<T: AsRef<[u8]>>(
+    read: Result<T, Box<dyn std::error::Error>>,
+    expected: Option<&str>,
+    integrity: Integrity,
+) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    match (read, integrity) {
+        (Ok(bytes), Integrity::Always) => {
+            let intact = match expected {
+                Some(hash) => blake3_hash_bytes(bytes.as_ref())? == hash,
+                None => true,
+            };
+            Ok(intact.then_some(bytes))
+        }
+        (Ok(bytes), _) => Ok(Some(bytes)),
+        (Err(e), Integrity::Off) => Err(e),
+        (Err(e), _) => {
+            tracing::error!("MOUNT | {}", e);
+            Ok(None)
+        }
+    }
 }
 
 pub trait SegmentSource: Send + Sync {
@@ -48,6 +94,50 @@ pub trait SegmentSource: Send + Sync {
         recovered_bytes: &[u8],
     ) -> Result<bool, Box<dyn std::error::Error>>;
     fn read_data(&self, filename: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    /// [`SegmentSource::read_segment`] for the mount's segment cache, whose
+    /// slices share the segment's bytes. Sources with the shard files on hand
+    /// can answer with a mapping.
+    fn read_segment_bytes(
+        &self,
+        filename: &str,
+        segment_id: usize,
+    ) -> Result<Bytes, Box<dyn std::error::Error>> {
+        self.read_segment(filename, segment_id).map(Bytes::from)
+    }
+
+    /// [`SegmentSource::read_block_segment`] for the segment cache, see
+    /// [`SegmentSource::read_segment_bytes`].
+    fn read_block_segment_bytes(
+        &self,
+        filename: &str,
+        block_id: usize,
+        segment_id: usize,
+    ) -> Result<Bytes, Box<dyn std::error::Error>> {
+        self.read_block_segment(filename, block_id, segment_id)
+            .map(Bytes::from)
+    }
+
+    /// [`SegmentSource::read_data`] for the segment cache, see
+    /// [`SegmentSource::read_segment_bytes`].
+    fn read_data_bytes(&self, filename: &str) -> Result<Bytes, Box<dyn std::error::Error>> {
+        self.read_data(filename).map(Bytes::from)
+    }
+
+    /// A reader over the original bytes of `filename`, for sources with the
+    /// archive on hand. The mount reads an open file through it instead of
+    /// segment by segment; `None` for sources that can only fetch segments.
+    fn open_reader(
+        &self,
+        _filename: &str,
+    ) -> Option<Result<FileReader, Box<dyn std::error::Error>>> {
+        None
+    }
+
+    /// Bytes available on the disk holding the archive, when the source can tell.
+    fn free_space(&self) -> Option<u64> {
+        None
+    }
 }
 
 pub struct LocalSource {
@@ -59,6 +149,12 @@ impl LocalSource {
         let store = FileStore::new(&archive_path)?;
         Ok(Self { store })
     }
+
+    /// Rejects manifests that fail signature verification.
+    pub fn with_verifier(mut self, verifier: Option<ManifestVerifier>) -> Self {
+        self.store.verifier = verifier;
+        self
+    }
 }
 
 impl SegmentSource for LocalSource {
@@ -69,6 +165,7 @@ impl SegmentSource for LocalSource {
 
     fn get_manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
         let file = self.store.find(&filename.to_string())?;
+        self.store.verify_manifest(&file)?;
         Ok(file.manifest)
     }
 
@@ -143,7 +240,7 @@ impl SegmentSource for LocalSource {
                     .parent()
                     .ok_or("No parent directory")?;
                 let data_path = file_path.join("data.dat");
-                fs::write(&data_path, recovered_bytes)?;
+                replace_shard(&data_path, recovered_bytes)?;
                 Ok(true)
             }
             2 => {
@@ -152,7 +249,7 @@ impl SegmentSource for LocalSource {
                     .ok_or("No parent directory")?
                     .join("segments")
                     .join(format!("segment_{}.dat", segment_id));
-                fs::write(file_path, recovered_bytes)?;
+                replace_shard(&file_path, recovered_bytes)?;
 
                 Ok(true)
             }
@@ -166,7 +263,7 @@ impl SegmentSource for LocalSource {
                     .join(format!("block_{}", block_id))
                     .join("segments")
                     .join(format!("segment_{}.dat", segment_id));
-                fs::write(file_path, recovered_bytes)?;
+                replace_shard(&file_path, recovered_bytes)?;
 
                 Ok(true)
             }
@@ -180,144 +277,97 @@ impl SegmentSource for LocalSource {
         let file_bytes = fs::read(self.store.get_data_path(&file)?)?;
         Ok(file_bytes)
     }
-}
-
-pub struct RemoteSource {
-    base_url: String,
-    agent: ureq::Agent,
-}
-
-impl RemoteSource {
-    pub fn new(base_url: String) -> Self {
-        let agent = ureq::Agent::new_with_defaults();
-
-        Self { base_url, agent }
-    }
-}
-
-impl SegmentSource for RemoteSource {
-    fn list_files(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let url = format!("{}/api/files", self.base_url);
-        let response: Vec<FileInfoResponse> = self
-            .agent
-            .get(&url)
-            .call()?
-            .body_mut()
-            .with_config()
-            .read_json()?;
-        Ok(response.into_iter().map(|f| f.name).collect())
-    }
 
-    fn get_manifest(&self, filename: &str) -> Result<ManifestFile, Box<dyn std::error::Error>> {
-        let url = format!("{}/api/files/{}/manifest", self.base_url, filename);
-        let response: ManifestResponse = self
-            .agent
-            .get(&url)
-            .call()?
-            .body_mut()
-            .with_config()
-            .read_json()?;
-        Ok(response.manifest)
-    }
-
-    fn read_segment(
+    fn read_segment_bytes(
         &self,
         filename: &str,
         segment_id: usize,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let url = format!(
-            "{}/api/files/{}/segment/{}",
-            self.base_url, filename, segment_id
-        );
-
-        let response: Vec<u8> = self
-            .agent
-            .get(&url)
-            .call()?
-            .body_mut()
-            .with_config()
-            .read_to_vec()?;
-
-        Ok(response)
+    ) -> Result<Bytes, Box<dyn std::error::Error>> {
+        let file = self.store.find(&filename.to_string())?;
+        Ok(map_shard(&self.store.get_segment_path(&file, segment_id)?)?)
     }
 
-    fn read_block_segment(
+    fn read_block_segment_bytes(
         &self,
         filename: &str,
         block_id: usize,
         segment_id: usize,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let url = format!(
-            "{}/api/files/{}/block/{}/segment/{}",
-            self.base_url, filename, block_id, segment_id
-        );
-        let response: Vec<u8> = self
-            .agent
-            .get(&url)
-            .call()?
-            .body_mut()
-            .with_config()
-            .read_to_vec()?;
-
-        Ok(response)
+    ) -> Result<Bytes, Box<dyn std::error::Error>> {
+        let file = self.store.find(&filename.to_string())?;
+        let path = self
+            .store
+            .get_block_segment_path(&file, block_id, segment_id)?;
+        Ok(map_shard(&path)?)
     }
 
-    fn read_parity(
-        &self,
-        filename: &str,
-        segment_id: usize,
-        parity_id: usize,
-        block_id: Option<usize>,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let url = if let Some(bid) = block_id {
-            format!(
-                "{}/api/files/{}/parity/?block_id={}&segment_id={}&parity_id={}",
-                self.base_url, filename, bid, segment_id, parity_id
-            )
-        } else {
-            format!(
-                "{}/api/files/{}/parity/?segment_id={}&parity_id={}",
-                self.base_url, filename, segment_id, parity_id
-            )
-        };
-        let response: Vec<u8> = self
-            .agent
-            .get(&url)
-            .call()?
-            .body_mut()
-            .with_config()
-            .read_to_vec()?;
-
-        Ok(response)
+    fn read_data_bytes(&self, filename: &str) -> Result<Bytes, Box<dyn std::error::Error>> {
+        let file = self.store.find(&filename.to_string())?;
+        Ok(map_shard(&self.store.get_data_path(&file)?)?)
     }
 
-    fn write_parity(
+    fn open_reader(
         &self,
         filename: &str,
-        segment_id: usize,
-        block_id: Option<usize>,
-        _recovered_bytes: &[u8],
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let url = format!(
-            "{}/api/files/{}/parity/?block_id={}&segment_id={}",
-            self.base_url,
-            filename,
-            block_id.unwrap_or(0),
-            segment_id,
-        );
-        self.agent.get(&url).call()?;
-        Ok(true)
+    ) -> Option<Result<FileReader, Box<dyn std::error::Error>>> {
+        let reader = self.store.find(&filename.to_string()).and_then(|file| {
+            self.store.verify_manifest(&file)?;
+            // repairs what it recovers, as segment reads through write_parity do
+            Ok(self.store.open_reader(&file)?.heal_on_read())
+        });
+        Some(reader)
     }
 
-    fn read_data(&self, filename: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let url = format!("{}/api/files/{}", self.base_url, filename);
-        let response: Vec<u8> = self
-            .agent
-            .get(&url)
-            .call()?
-            .body_mut()
-            .with_config()
-            .read_to_vec()?;
-        Ok(response)
+    fn free_space(&self) -> Option<u64> {
+        match crate::utils::disk_space(&self.store.store_path) {
+            Ok((_, available)) => Some(available),
+            Err(e) => {
+                tracing::warn!("MOUNT | cannot read free space of archive: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_read_follows_integrity() {
+        let good = b"segment".to_vec();
+        let hash = blake3_hash_bytes(&good).unwrap();
+        let bad = b"segmenT".to_vec();
+        let failed = || Err::<Vec<u8>, Box<dyn std::error::Error>>("gone".into());
+
+        for (integrity, bad_read, failed_read) in [
+            (Integrity::Always, Some(None), Some(None)),
+            (Integrity::OnCorruption, Some(Some(bad.clone())), Some(None)),
+            (Integrity::Off, Some(Some(bad.clone())), None),
+        ] {
+            let read = checked_read(Ok(good.clone()), Some(&hash), integrity).unwrap();
+            assert_eq!(read, Some(good.clone()), "{:?}", integrity);
+            let read = checked_read(Ok(bad.clone()), Some(&hash), integrity).ok();
+            assert_eq!(read, bad_read, "{:?}", integrity);
+            let read = checked_read(failed(), Some(&hash), integrity).ok();
+            assert_eq!(read, failed_read, "{:?}", integrity);
+        }
+    }
+
+    #[test]
+    fn test_large_shards_are_mapped_and_survive_repair() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.dat");
+        fs::write(&small, b"small").unwrap();
+        assert_eq!(map_shard(&small).unwrap(), &b"small"[..]);
+
+        let path = dir.path().join("segment_0.dat");
+        let data: Vec<u8> = (0..MAP_THRESHOLD).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &data).unwrap();
+        let segment = map_shard(&path).unwrap();
+
+        // a repair renames a new shard over the old one, the mapping keeps its bytes
+        replace_shard(&path, b"repaired").unwrap();
+        assert_eq!(segment.slice(1000..2000), &data[1000..2000]);
+        assert_eq!(fs::read(&path).unwrap(), b"repaired");
     }
 }
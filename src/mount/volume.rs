@@ -0,0 +1,137 @@
+//! The volume a Windows mount presents: label, serial number, case rules and
+//! sector geometry, from `[mount.volume]` and the `mount` flags.
+//!
+//! Windows tells volumes apart by serial number, e.g. for shortcuts and the
+//! recycle bin, and WinFsp picks a random one when it is 0. The `auto` serial
+//! is instead a hash of the archive's file names, so remounting the same
+//! archive gives the same volume. Committing a new name changes it; new
+//! versions of existing names don't.
+
+use crate::config::VolumeConfig;
+
+/// Longest label WinFsp reports, in UTF-16 units.
+pub const MAX_LABEL_LEN: usize = 32;
+
+/// Serial number for an archive holding the files `names`, in any order.
+///
+/// # Examples
+///
+/// ```
+/// # use blockframe::mount::volume::catalog_serial;
+/// let a = catalog_serial(&["b.txt".to_string(), "a.txt".to_string()]);
+/// assert_eq!(a, catalog_serial(&["a.txt".to_string(), "b.txt".to_string()]));
+/// assert_ne!(a, catalog_serial(&["a.txt".to_string()]));
+/// ```
+pub fn catalog_serial(names: &[String]) -> u32 {
+    let mut names: Vec<&str> = names.iter().map(String::as_str).collect();
+    names.sort_unstable();
+    names.dedup();
+    let mut hasher = blake3::Hasher::new();
+    for name in names {
+        // length first, so no two lists hash the same bytes
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+    }
+    let hash = hasher.finalize();
+    let serial = u32::from_le_bytes(hash.as_bytes()[..4].try_into().expect("4 bytes"));
+    // 0 would have WinFsp pick a random one
+    serial.max(1)
+}
+
+/// A serial number as `dir` shows it, `1A2B-3C4D`, or as 8 plain hex digits.
+/// `None` for `auto`.
+pub fn parse_serial(serial: &str) -> Result<Option<u32>, String> {
+    if serial.eq_ignore_ascii_case("auto") {
+        return Ok(None);
+    }
+    let digits: String = serial.chars().filter(|&c| c != '-').collect();
+    let digits = digits.trim_start_matches("0x");
+    if digits.len() != 8 {
+        return Err(format!(
+            "volume serial {:?} must be 8 hex digits or auto",
+            serial
+        ));
+    }
+    let serial = u32::from_str_radix(digits, 16)
+        .map_err(|_| format!("volume serial {:?} must be 8 hex digits or auto", serial))?;
+    if serial == 0 {
+        return Err("volume serial must not be 0000-0000".to_string());
+    }
+    Ok(Some(serial))
+}
+
+/// The serial as `dir` shows it.
+pub fn format_serial(serial: u32) -> String {
+    format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff)
+}
+
+impl VolumeConfig {
+    /// Checks the settings, as `mount` does before mounting.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.label.encode_utf16().count() > MAX_LABEL_LEN {
+            return Err(format!(
+                "mount.volume.label is longer than {} characters",
+                MAX_LABEL_LEN
+            ));
+        }
+        parse_serial(&self.serial).map_err(|e| format!("mount.volume.serial: {}", e))?;
+        if !self.sector_size.is_power_of_two() || !(512..=4096).contains(&self.sector_size) {
+            return Err(
+                "mount.volume.sector_size must be a power of two from 512 to 4096".to_string(),
+            );
+        }
+        if !self.sectors_per_allocation_unit.is_power_of_two() {
+            return Err(
+                "mount.volume.sectors_per_allocation_unit must be a power of two".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// The serial number to mount with, calling `names` for the archive's
+    /// file names only when it is `auto`.
+    pub fn serial_number<F>(&self, names: F) -> Result<u32, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<Vec<String>, Box<dyn std::error::Error>>,
+    {
+        match parse_serial(&self.serial)? {
+            Some(serial) => Ok(serial),
+            None => Ok(catalog_serial(&names()?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_settings() {
+        assert_eq!(parse_serial("auto").unwrap(), None);
+        assert_eq!(parse_serial("1A2B-3c4d").unwrap(), Some(0x1a2b_3c4d));
+        assert_eq!(parse_serial("0x0000beef").unwrap(), Some(0xbeef));
+        for bad in ["1A2B", "1A2B-3C4G", "0000-0000", ""] {
+            assert!(parse_serial(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(format_serial(0x1a2b_3c4d), "1A2B-3C4D");
+
+        let mut volume = VolumeConfig::default();
+        volume.validate().unwrap();
+        let names = || Ok(vec!["a.txt".to_string(), "a.txt".to_string()]);
+        assert_eq!(
+            volume.serial_number(names).unwrap(),
+            catalog_serial(&["a.txt".to_string()])
+        );
+        volume.serial = "1234-5678".to_string();
+        assert_eq!(
+            volume.serial_number(|| Err("not listed".into())).unwrap(),
+            0x1234_5678
+        );
+
+        volume.sector_size = 1000;
+        assert!(volume.validate().is_err());
+        volume.sector_size = 4096;
+        volume.label = "x".repeat(33);
+        assert!(volume.validate().is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Portable names for archive entry directories.
+//!
+//! An entry lives in `{name}_{hash}`, but a file name that is fine where it was
+//! committed isn't always a valid directory name elsewhere: Windows rejects
+//! `:` and a handful of other characters, trailing dots and spaces, and device
+//! names such as `CON`, while macOS rewrites unicode names to their decomposed
+//! form, so the directory stops matching the name it was created from.
+//!
+//! Entry directories are therefore named with [`portable_name`], which
+//! percent-encodes everything outside a small set of ASCII characters. The
+//! result is plain ASCII, valid on every platform, and left untouched by any
+//! unicode normalization. The original name is only kept in the manifest,
+//! which is what listings, lookups and mounts go by, so an archive moved
+//! between platforms reads the same. Names made of safe characters, which is
+//! most of them, are unchanged, as are entries committed before this.
+
+use percent_encoding::percent_decode_str;
+
+/// ASCII characters escaped on top of control characters: path separators,
+/// characters Windows reserves, and `%` itself so decoding is unambiguous.
+const UNSAFE: [char; 10] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*', '%'];
+
+/// Longest portable name used in an entry directory, leaving room for `_`, the
+/// 64 character hash and the `_computing` placeholder within the 255 byte
+/// limit most filesystems have for a path component.
+pub const MAX_NAME_LEN: usize = 180;
+
+/// Device names Windows won't create as a file or directory, with or without
+/// an extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Encodes `name` so it can be used as a file or directory name on any
+/// platform. Reversed by [`original_name`].
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::naming::portable_name;
+///
+/// assert_eq!(portable_name("report.pdf"), "report.pdf");
+/// assert_eq!(portable_name("12:30 notes.txt"), "12%3A30 notes.txt");
+/// assert_eq!(portable_name("café.txt"), "caf%C3%A9.txt");
+/// assert_eq!(portable_name("CON.txt"), "%43ON.txt");
+/// assert_eq!(portable_name("draft."), "draft%2E");
+/// ```
+pub fn portable_name(name: &str) -> String {
+    escape(name, false)
+}
+
+/// Like [`portable_name`] but keeps non-ASCII characters, for names shown
+/// through the Windows mount where unicode is fine but `:` and friends are
+/// not. Reversed by [`original_name`] as well.
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::naming::windows_name;
+///
+/// assert_eq!(windows_name("café 12:30.txt"), "café 12%3A30.txt");
+/// ```
+pub fn windows_name(name: &str) -> String {
+    escape(name, true)
+}
+
+fn escape(name: &str, keep_unicode: bool) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    let mut buf = [0u8; 4];
+    for c in name.chars() {
+        let unsafe_char = if c.is_ascii() {
+            c.is_ascii_control() || UNSAFE.contains(&c)
+        } else {
+            !keep_unicode
+        };
+        if unsafe_char {
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        escaped = format!("%{:02X}{}", escaped.as_bytes()[0], &escaped[1..]);
+    }
+    // windows drops trailing dots and spaces
+    if let Some(last) = escaped.pop() {
+        if last == '.' || last == ' ' {
+            escaped.push_str(&format!("%{:02X}", last as u8));
+        } else {
+            escaped.push(last);
+        }
+    }
+    escaped
+}
+
+/// Decodes a name made by [`portable_name`]. `None` if it doesn't decode to
+/// UTF-8.
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::naming::{original_name, portable_name};
+///
+/// let name = "a:b/c?.txt";
+/// assert_eq!(original_name(&portable_name(name)).as_deref(), Some(name));
+/// ```
+pub fn original_name(portable: &str) -> Option<String> {
+    percent_decode_str(portable)
+        .decode_utf8()
+        .ok()
+        .map(|name| name.into_owned())
+}
+
+/// Directory name of the archive entry for `name` with `hash`: the portable
+/// name, shortened to [`MAX_NAME_LEN`] if needed, then `_{hash}`. A shortened
+/// name no longer decodes to the original, the manifest still has it.
+pub fn entry_dir_name(name: &str, hash: &str) -> String {
+    let mut portable = portable_name(name);
+    if portable.len() > MAX_NAME_LEN {
+        let mut end = MAX_NAME_LEN;
+        // don't cut an escape in half
+        if let Some(pct) = portable[end - 2..end].find('%') {
+            end = end - 2 + pct;
+        }
+        portable.truncate(end);
+    }
+    format!("{}_{}", portable, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunker;
+    use crate::filestore::FileStore;
+    use tempfile::TempDir;
+
+    const NAMES: [&str; 8] = [
+        "plain.txt",
+        "12:30 meeting.txt",
+        "what?.md",
+        "caf\u{e9}.txt",   // composed é
+        "cafe\u{301}.txt", // e + combining accent
+        "NUL",
+        "trailing. ",
+        "\u{65e5}\u{672c}\u{8a9e}<1>|2|.bin",
+    ];
+
+    #[test]
+    fn test_portable_names_round_trip() {
+        let dir = TempDir::new().unwrap();
+        for name in NAMES {
+            let portable = portable_name(name);
+            assert!(portable.is_ascii(), "{:?} -> {:?}", name, portable);
+            assert!(
+                !portable.contains(['<', '>', ':', '"', '/', '\\', '|', '?', '*']),
+                "{:?} -> {:?}",
+                name,
+                portable
+            );
+            assert_eq!(original_name(&portable).as_deref(), Some(name));
+            assert_eq!(original_name(&windows_name(name)).as_deref(), Some(name));
+
+            // the encoded name can be created and found again as is
+            let path = dir.path().join(&portable);
+            std::fs::create_dir(&path).unwrap();
+            assert!(path.is_dir());
+        }
+        // both normal forms stay distinct rather than colliding on disk
+        assert_ne!(portable_name(NAMES[3]), portable_name(NAMES[4]));
+
+        let long = "\u{e9}".repeat(200);
+        let dir_name = entry_dir_name(&long, "abc");
+        assert!(dir_name.len() <= MAX_NAME_LEN + 4);
+        assert!(dir_name.ends_with("%A9_abc") || dir_name.ends_with("%C3_abc"));
+    }
+
+    #[test]
+    fn test_commit_keeps_original_name() {
+        let dir = TempDir::new().unwrap();
+        let chunker = Chunker::builder()
+            .archive_dir(dir.path().join("archive"))
+            .build()
+            .unwrap();
+        let inputs = dir.path().join("inputs");
+        std::fs::create_dir_all(&inputs).unwrap();
+
+        // keep to names the test host can create as source files
+        let names: Vec<&str> = NAMES
+            .iter()
+            .copied()
+            .filter(|name| std::fs::write(inputs.join(name), name.as_bytes()).is_ok())
+            .collect();
+        for name in &names {
+            let chunked = chunker.commit(&inputs.join(name)).unwrap();
+            let dir_name = chunked.file_dir.file_name().unwrap().to_str().unwrap();
+            assert!(dir_name.is_ascii());
+        }
+
+        let store = FileStore::new(chunker.archive_dir()).unwrap();
+        for name in &names {
+            let file = store.find(&name.to_string()).unwrap();
+            assert_eq!(file.file_name, *name);
+            let mut out = Vec::new();
+            store.reconstruct_to(&file, &mut out).unwrap();
+            assert_eq!(out, name.as_bytes());
+        }
+    }
+}
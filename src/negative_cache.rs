@@ -0,0 +1,85 @@
+//! Short-lived memory of names that weren't found.
+//!
+//! File managers and shells look up names that are never archived, such as
+//! `.DS_Store`, `Thumbs.db`, `desktop.ini` or `.git`, over and over. Each miss
+//! can cost a rescan of the archive or a refresh of a mount's file list, so
+//! [`FileStore::find`](crate::filestore::FileStore::find) and the mount
+//! lookups remember misses for a few seconds and answer them from here.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Names kept at most, expired ones are dropped first when it fills up.
+const MAX_NAMES: usize = 4096;
+
+/// Names recently found missing, each for `ttl`. A zero `ttl` remembers nothing.
+pub struct NegativeCache {
+    ttl: Duration,
+    names: Mutex<HashMap<String, Instant>>,
+}
+
+impl NegativeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            names: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `name` was found missing less than the TTL ago.
+    pub fn contains(&self, name: &str) -> bool {
+        let mut names = self.names.lock();
+        match names.get(name) {
+            Some(missed) if missed.elapsed() < self.ttl => true,
+            Some(_) => {
+                names.remove(name);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Remembers that `name` wasn't found.
+    pub fn insert(&self, name: &str) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut names = self.names.lock();
+        if names.len() >= MAX_NAMES {
+            names.retain(|_, missed| missed.elapsed() < self.ttl);
+            if names.len() >= MAX_NAMES {
+                names.clear();
+            }
+        }
+        names.insert(name.to_string(), Instant::now());
+    }
+
+    /// Forgets every name, e.g. once files were added.
+    pub fn clear(&self) {
+        self.names.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_expire_and_clear() {
+        let missing = NegativeCache::new(Duration::from_millis(50));
+        assert!(!missing.contains(".DS_Store"));
+        missing.insert(".DS_Store");
+        assert!(missing.contains(".DS_Store"));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!missing.contains(".DS_Store"));
+
+        missing.insert("Thumbs.db");
+        missing.clear();
+        assert!(!missing.contains("Thumbs.db"));
+
+        let off = NegativeCache::new(Duration::ZERO);
+        off.insert(".git");
+        assert!(!off.contains(".git"));
+    }
+}
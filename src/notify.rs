@@ -0,0 +1,307 @@
+//! Health notifications for `health` and the daemon's scrub.
+//!
+//! Only changes are reported. The last status seen for each file is kept in
+//! `.health-state.json` inside the archive, and a notification goes out when a
+//! file reaches `min_status` (Recoverable by default) having been below it on the
+//! previous pass. A file that stays broken is reported once, and again only after
+//! it has been healthy in between.
+//!
+//! Webhooks are posted with a Generic, Slack or Discord shaped body. Email is
+//! handed to a local `sendmail -t`, so there is no SMTP configuration here.
+
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::{NotifyConfig, WebhookFormat};
+use crate::filestore::models::{BatchHealthReport, HealthReport, HealthStatus};
+
+pub const STATE_FILE: &str = ".health-state.json";
+
+/// Discord rejects message content longer than this.
+const DISCORD_LIMIT: usize = 2000;
+
+#[derive(Clone)]
+pub struct Notifier {
+    config: NotifyConfig,
+    agent: ureq::Agent,
+}
+
+impl Notifier {
+    /// `None` when neither a webhook nor an email recipient is configured.
+    pub fn from_config(config: &NotifyConfig) -> Option<Self> {
+        if config.webhook_url.is_empty() && config.email_to.is_empty() {
+            return None;
+        }
+        Some(Self {
+            config: config.clone(),
+            agent: ureq::Agent::new_with_defaults(),
+        })
+    }
+
+    /// Compares `report` with the state saved for `archive`, sends a notification
+    /// for files that crossed `min_status` and saves the new state. Returns how
+    /// many files were reported.
+    ///
+    /// When sending fails the state is left alone so the same changes are tried
+    /// again on the next pass.
+    pub fn notify(
+        &self,
+        archive: &Path,
+        report: &BatchHealthReport,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let state_path = archive.join(STATE_FILE);
+        let previous = load_state(&state_path);
+        let changed = transitions(&previous, report, self.config.min_status);
+
+        let mut state: HashMap<String, HealthStatus> = report
+            .reports
+            .iter()
+            .map(|(name, r)| (name.clone(), r.status))
+            .collect();
+
+        if changed.is_empty() {
+            save_state(&state_path, &state)?;
+            return Ok(0);
+        }
+        if changed.len() < self.config.min_files {
+            tracing::info!(
+                "NOTIFY | {} files changed state, holding until {}",
+                changed.len(),
+                self.config.min_files
+            );
+            // keep the old status so these still count as changes next time
+            for (name, _) in &changed {
+                match previous.get(*name) {
+                    Some(status) => state.insert(name.to_string(), *status),
+                    None => state.remove(*name),
+                };
+            }
+            save_state(&state_path, &state)?;
+            return Ok(0);
+        }
+
+        let text = summary(archive, report, &changed);
+        if !self.config.webhook_url.is_empty() {
+            let body = webhook_body(self.config.webhook_format, archive, report, &changed, &text);
+            self.agent
+                .post(&self.config.webhook_url)
+                .send_json(&body)
+                .map_err(|e| format!("webhook {} failed: {}", self.config.webhook_url, e))?;
+            tracing::info!("NOTIFY | posted {} changes to webhook", changed.len());
+        }
+        if !self.config.email_to.is_empty() {
+            self.send_email(&text)?;
+            tracing::info!(
+                "NOTIFY | mailed {} changes to {}",
+                changed.len(),
+                self.config.email_to.join(", ")
+            );
+        }
+
+        save_state(&state_path, &state)?;
+        Ok(changed.len())
+    }
+
+    fn send_email(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let subject = text.lines().next().unwrap_or("blockframe health");
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            self.config.email_from,
+            self.config.email_to.join(", "),
+            subject,
+            text
+        );
+
+        let mut child = Command::new(&self.config.sendmail)
+            .arg("-t")
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", self.config.sendmail.display(), e))?;
+        child
+            .stdin
+            .take()
+            .ok_or("sendmail stdin unavailable")?
+            .write_all(message.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(
+                format!("{} exited with {}", self.config.sendmail.display(), status).into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Files at or above `min_status` that were below it (or unknown) before.
+fn transitions<'a>(
+    previous: &HashMap<String, HealthStatus>,
+    report: &'a BatchHealthReport,
+    min_status: HealthStatus,
+) -> Vec<(&'a str, &'a HealthReport)> {
+    report
+        .reports
+        .iter()
+        .filter(|(_, r)| r.status >= min_status)
+        .filter(|(name, _)| previous.get(name).is_none_or(|before| *before < min_status))
+        .map(|(name, r)| (name.as_str(), r))
+        .collect()
+}
+
+fn summary(
+    archive: &Path,
+    report: &BatchHealthReport,
+    changed: &[(&str, &HealthReport)],
+) -> String {
+    let mut text = format!(
+        "blockframe: {} file(s) need attention in {} ({}/{} healthy, {} recoverable, {} unrecoverable)",
+        changed.len(),
+        archive.display(),
+        report.healthy,
+        report.total_files,
+        report.recoverable,
+        report.unrecoverable
+    );
+    for (name, r) in changed {
+        text.push_str(&format!("\n- {}: {:?} ({})", name, r.status, r.details));
+    }
+    text
+}
+
+fn webhook_body(
+    format: WebhookFormat,
+    archive: &Path,
+    report: &BatchHealthReport,
+    changed: &[(&str, &HealthReport)],
+    text: &str,
+) -> Value {
+    match format {
+        WebhookFormat::Slack => json!({ "text": text }),
+        WebhookFormat::Discord => {
+            json!({ "content": text.chars().take(DISCORD_LIMIT).collect::<String>() })
+        }
+        WebhookFormat::Generic => json!({
+            "archive": archive.display().to_string(),
+            "total_files": report.total_files,
+            "healthy": report.healthy,
+            "degraded": report.degraded,
+            "recoverable": report.recoverable,
+            "unrecoverable": report.unrecoverable,
+            "files": changed
+                .iter()
+                .map(|(name, r)| json!({
+                    "file": name,
+                    "status": r.status,
+                    "details": r.details,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    }
+}
+
+fn load_state(path: &Path) -> HashMap<String, HealthStatus> {
+    let Ok(json) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        tracing::warn!("NOTIFY | ignoring unreadable {:?}: {}", path, e);
+        HashMap::new()
+    })
+}
+
+fn save_state(
+    path: &Path,
+    state: &HashMap<String, HealthStatus>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn report(statuses: &[(&str, HealthStatus)]) -> BatchHealthReport {
+        let count = |s: HealthStatus| statuses.iter().filter(|(_, st)| *st == s).count();
+        BatchHealthReport {
+            total_files: statuses.len(),
+            healthy: count(HealthStatus::Healthy),
+            degraded: count(HealthStatus::Degraded),
+            recoverable: count(HealthStatus::Recoverable),
+            unrecoverable: count(HealthStatus::Unrecoverable),
+            reports: statuses
+                .iter()
+                .map(|(name, status)| {
+                    (
+                        name.to_string(),
+                        HealthReport {
+                            status: *status,
+                            missing_data: Vec::new(),
+                            missing_parity: Vec::new(),
+                            corrupt_segments: Vec::new(),
+                            recoverable: *status != HealthStatus::Unrecoverable,
+                            details: String::new(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_only_new_failures_are_reported() {
+        let previous = HashMap::from([
+            ("a".to_string(), HealthStatus::Healthy),
+            ("b".to_string(), HealthStatus::Recoverable),
+        ]);
+        let current = report(&[
+            ("a", HealthStatus::Unrecoverable),
+            ("b", HealthStatus::Unrecoverable),
+            ("c", HealthStatus::Degraded),
+        ]);
+
+        let changed = transitions(&previous, &current, HealthStatus::Recoverable);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0, "a");
+
+        // lowering the threshold picks up the degraded newcomer too
+        assert_eq!(
+            transitions(&previous, &current, HealthStatus::Degraded).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_held_changes_are_kept_for_next_pass() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = NotifyConfig {
+            webhook_url: "http://127.0.0.1:9/unused".to_string(),
+            min_files: 2,
+            ..NotifyConfig::default()
+        };
+        let notifier = Notifier::from_config(&config).unwrap();
+
+        let first = report(&[
+            ("a", HealthStatus::Recoverable),
+            ("b", HealthStatus::Healthy),
+        ]);
+        assert_eq!(notifier.notify(temp_dir.path(), &first).unwrap(), 0);
+
+        // "a" was held back, so it still counts as a change alongside "b"
+        let state = load_state(&temp_dir.path().join(STATE_FILE));
+        assert_eq!(state.get("a"), None);
+        assert_eq!(state.get("b"), Some(&HealthStatus::Healthy));
+        let second = report(&[
+            ("a", HealthStatus::Recoverable),
+            ("b", HealthStatus::Recoverable),
+        ]);
+        assert_eq!(
+            transitions(&state, &second, HealthStatus::Recoverable).len(),
+            2
+        );
+    }
+}
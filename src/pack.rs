@@ -0,0 +1,258 @@
+//! Packing many small files into one archive entry.
+//!
+//! Every committed file gets its own directory with a manifest and at least four
+//! shards, which for thousands of tiny files is mostly inodes and padding. A pack
+//! concatenates the files into one stream and commits that as a single ordinary
+//! entry, so health checks, repair, retier, serving and replication treat it like
+//! any other file. The manifest's `pack` list records where each file sits in the
+//! stream and its own BLAKE3 hash; being part of the manifest it is covered by
+//! the manifest signature and travels with `GET /api/files/<name>/manifest`.
+//!
+//! Packed files are looked up by name with [`FileStore::find_member`], restored
+//! with [`FileStore::restore_member`], and show up as plain files in a mount.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::chunker::{ChunkedFile, Chunker};
+use crate::filestore::FileStore;
+use crate::filestore::models::{File, HealthReport};
+use crate::merkle_tree::manifest::PackMember;
+
+impl Chunker {
+    /// Concatenates `paths` into one pack called `pack_name` and commits it.
+    /// Files are stored under their file name, which must be unique in the pack.
+    /// `tier` forces the pack's encoding as in [`Chunker::commit_as`].
+    ///
+    /// # Errors
+    ///
+    /// Fails before writing to the archive if a file can't be read or two files
+    /// share a name.
+    pub fn commit_pack(
+        &self,
+        paths: &[PathBuf],
+        pack_name: &str,
+        tier: Option<u8>,
+    ) -> Result<ChunkedFile, Box<dyn std::error::Error>> {
+        if Path::new(pack_name).file_name() != Some(pack_name.as_ref()) {
+            return Err(format!("invalid pack name {:?}", pack_name).into());
+        }
+
+        fs::create_dir_all(self.archive_dir())?;
+        let work = tempfile::Builder::new()
+            .prefix(".pack-")
+            .tempdir_in(self.archive_dir())?;
+        let stream_path = work.path().join(pack_name);
+        let mut stream = BufWriter::new(fs::File::create(&stream_path)?);
+
+        let mut members = Vec::with_capacity(paths.len());
+        let mut names = HashSet::new();
+        let mut offset = 0u64;
+        let mut stream_hasher = blake3::Hasher::new();
+        for path in paths {
+            let name = path
+                .file_name()
+                .ok_or_else(|| format!("{} has no file name", path.display()))?
+                .to_string_lossy()
+                .to_string();
+            if !names.insert(name.clone()) {
+                return Err(format!("{} appears twice in the pack", name).into());
+            }
+
+            let bytes = fs::read(path)?;
+            stream.write_all(&bytes)?;
+            stream_hasher.update(&bytes);
+            members.push(PackMember {
+                name,
+                offset,
+                size: bytes.len() as u64,
+                hash: blake3::hash(&bytes).to_string(),
+            });
+            offset += bytes.len() as u64;
+        }
+        stream.flush()?;
+        drop(stream);
+        tracing::info!(
+            "PACK | packing {} files ({} bytes) into {}",
+            members.len(),
+            offset,
+            pack_name
+        );
+
+        let stream_hash = stream_hasher.finalize().to_string();
+        // the index goes into the manifest before the entry is published
+        self.with_pack(members)
+            .encode_as(&stream_path, tier, &stream_hash)
+    }
+}
+
+impl FileStore {
+    /// The pack holding the file `name`, with that file's index entry.
+    pub fn find_member(
+        &self,
+        name: &str,
+    ) -> Result<(File, PackMember), Box<dyn std::error::Error>> {
+        for file in self.get_all()? {
+            if let Some(member) = file.manifest.pack.iter().find(|m| m.name == name) {
+                let member = member.clone();
+                return Ok((file, member));
+            }
+        }
+        Err(format!("{} is not in the archive or any pack", name).into())
+    }
+
+    /// Streams one packed file into `out` and returns its hash. The whole pack
+    /// is restored through [`FileStore::reconstruct_to`], so damaged shards are
+    /// recovered from parity on the way.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pack can't be restored or the member doesn't match the hash
+    /// in the index.
+    pub fn restore_member<W: Write>(
+        &self,
+        pack: &File,
+        member: &PackMember,
+        out: W,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut range = RangeWriter {
+            inner: out,
+            start: member.offset,
+            end: member.offset + member.size,
+            pos: 0,
+            hasher: blake3::Hasher::new(),
+        };
+        self.reconstruct_to(pack, &mut range)?;
+
+        let hash = range.hasher.finalize().to_string();
+        if hash != member.hash {
+            return Err(format!("{} does not match its packed hash", member.name).into());
+        }
+        Ok(hash)
+    }
+
+    /// Health of the pack holding `name`. A packed file is exactly as healthy
+    /// as its pack.
+    pub fn member_health(&self, name: &str) -> Result<HealthReport, Box<dyn std::error::Error>> {
+        let (pack, _) = self.find_member(name)?;
+        self.health_check(&pack)
+    }
+}
+
+/// Passes through only the bytes in `start..end` of everything written to it.
+struct RangeWriter<W: Write> {
+    inner: W,
+    start: u64,
+    end: u64,
+    pos: u64,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for RangeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let buf_end = self.pos + buf.len() as u64;
+        let from = self.start.max(self.pos);
+        let to = self.end.min(buf_end);
+        if from < to {
+            let slice = &buf[(from - self.pos) as usize..(to - self.pos) as usize];
+            self.hasher.update(slice);
+            self.inner.write_all(slice)?;
+        }
+        self.pos = buf_end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filestore::models::HealthStatus;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pack_lookup_restore_and_health() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+
+        let mut paths = Vec::new();
+        for (name, len) in [("a.txt", 10), ("empty.txt", 0), ("c.bin", 5000)] {
+            let path = temp_dir.path().join(name);
+            let bytes: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            fs::write(&path, bytes).unwrap();
+            paths.push(path);
+        }
+        let chunked = chunker.commit_pack(&paths, "small.pack", None).unwrap();
+
+        let store = FileStore::new(&archive_dir).unwrap();
+        assert_eq!(store.get_all().unwrap().len(), 1);
+        for path in &paths {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let (pack, member) = store.find_member(name).unwrap();
+            assert_eq!(pack.file_name, "small.pack");
+
+            let mut restored = Vec::new();
+            store.restore_member(&pack, &member, &mut restored).unwrap();
+            assert_eq!(restored, fs::read(path).unwrap());
+        }
+        assert!(store.find_member("missing.txt").is_err());
+
+        // a damaged pack is still read through parity, and reported as such
+        let data = chunked.file_dir.join("data.dat");
+        let mut bytes = fs::read(&data).unwrap();
+        bytes[20] ^= 0xff;
+        fs::write(&data, bytes).unwrap();
+        let (pack, member) = store.find_member("c.bin").unwrap();
+        let mut restored = Vec::new();
+        store.restore_member(&pack, &member, &mut restored).unwrap();
+        assert_eq!(restored, fs::read(&paths[2]).unwrap());
+        assert_ne!(
+            store.member_health("c.bin").unwrap().status,
+            HealthStatus::Healthy
+        );
+
+        let dup = vec![paths[0].clone(), paths[0].clone()];
+        assert!(chunker.commit_pack(&dup, "dup.pack", None).is_err());
+    }
+
+    #[test]
+    fn test_pack_is_published_with_its_index_signed() {
+        use crate::merkle_tree::manifest::ManifestFile;
+        use crate::signing::{ManifestSigner, read_signature};
+
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let signer = ManifestSigner::generate();
+        let verifier = signer.verifier(true);
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .signer(signer)
+            .build()
+            .unwrap();
+        // scanned before the pack exists, picked up again once it is published
+        fs::create_dir_all(&archive_dir).unwrap();
+        let store = FileStore::new(&archive_dir).unwrap();
+        assert!(store.get_all().unwrap().is_empty());
+
+        let path = temp_dir.path().join("a.txt");
+        fs::write(&path, b"packed").unwrap();
+        let chunked = chunker.commit_pack(&[path], "one.pack", None).unwrap();
+
+        let manifest_path = chunked.file_dir.join("manifest.json");
+        let manifest = ManifestFile::new(manifest_path.display().to_string()).unwrap();
+        assert_eq!(manifest.pack.len(), 1);
+        verifier
+            .verify(&manifest, read_signature(&manifest_path).as_deref())
+            .unwrap();
+        assert_eq!(store.find_member("a.txt").unwrap().0.file_name, "one.pack");
+    }
+}
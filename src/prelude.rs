@@ -0,0 +1,16 @@
+//! The supported library API in one import.
+//!
+//! ```
+//! use blockframe::prelude::*;
+//!
+//! let chunker = Chunker::builder().archive_dir("archive_directory").build().unwrap();
+//! assert_eq!(chunker.archive_dir(), std::path::Path::new("archive_directory"));
+//! ```
+
+pub use crate::chunker::{ChunkedFile, Chunker, ChunkerBuilder};
+pub use crate::config::Config;
+pub use crate::filestore::FileStore;
+pub use crate::filestore::models::{BatchHealthReport, File, HealthReport, HealthStatus};
+pub use crate::merkle_tree::MerkleTree;
+pub use crate::merkle_tree::manifest::ManifestFile;
+pub use crate::signing::{ManifestSigner, ManifestVerifier};
@@ -0,0 +1,279 @@
+//! Where `serve` listens: a TCP address, a Unix domain socket, or sockets
+//! handed over by systemd.
+//!
+//! A Unix socket suits a reverse proxy on the same machine, no TCP port is
+//! opened at all. With socket activation systemd binds the socket itself
+//! (`blockframe.socket`) and starts the server on the first connection,
+//! passing the listening sockets in from fd 3 as described by `LISTEN_FDS`.
+//! When those are present they are used whatever was asked for on the command
+//! line.
+
+use poem::listener::{AcceptorExt, BoxAcceptor, Listener, TcpListener};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::config::ServerConfig;
+
+/// Prefix marking a `--bind` value as a Unix socket path.
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// A place to listen, as given to `--bind` or `[server] bind`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bind {
+    /// `host:port`, e.g. `127.0.0.1:8080` or `[::]:8080`.
+    Tcp(String),
+    /// `unix:/run/blockframe.sock`.
+    Unix(PathBuf),
+}
+
+impl Bind {
+    /// Every interface on `port`, what `serve` binds without `--bind`.
+    pub fn port(port: u16) -> Self {
+        Self::Tcp(format!("0.0.0.0:{}", port))
+    }
+
+    /// The base URL clients reach the server on, for the start-up banner.
+    pub fn url(&self, scheme: &str) -> String {
+        match self {
+            Self::Tcp(addr) => format!("{}://{}", scheme, addr),
+            Self::Unix(path) => format!("{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+
+    /// `[server] bind`, `None` when it's empty and `default_port` applies.
+    pub fn from_config(config: &ServerConfig) -> Result<Option<Self>, String> {
+        if config.bind.trim().is_empty() {
+            return Ok(None);
+        }
+        config
+            .bind
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("[server] bind: {}", e))
+    }
+
+    /// A listener for this address, or for the sockets systemd passed in if
+    /// there are any.
+    pub fn listener(self) -> BindListener {
+        BindListener(self)
+    }
+}
+
+impl FromStr for Bind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err("unix: needs a socket path, e.g. unix:/run/blockframe.sock".to_string());
+            }
+            if cfg!(unix) {
+                return Ok(Self::Unix(PathBuf::from(path)));
+            }
+            return Err("unix sockets are only supported on Unix".to_string());
+        }
+        let (host, port) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("'{}' is neither host:port nor unix:<path>", s))?;
+        port.parse::<u16>()
+            .map_err(|e| format!("bad port in '{}': {}", s, e))?;
+        let host = if host.is_empty() { "0.0.0.0" } else { host };
+        Ok(Self::Tcp(format!("{}:{}", host, port)))
+    }
+}
+
+impl fmt::Display for Bind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => f.write_str(addr),
+            Self::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+/// The [`Listener`] for a [`Bind`], see [`Bind::listener`].
+pub struct BindListener(Bind);
+
+impl Listener for BindListener {
+    type Acceptor = BoxAcceptor;
+
+    async fn into_acceptor(self) -> io::Result<Self::Acceptor> {
+        #[cfg(unix)]
+        if let Some(acceptor) = systemd::acceptor()? {
+            return Ok(acceptor);
+        }
+        match self.0 {
+            Bind::Tcp(addr) => Ok(TcpListener::bind(addr).into_acceptor().await?.boxed()),
+            #[cfg(unix)]
+            Bind::Unix(path) => {
+                remove_stale_socket(&path)?;
+                let acceptor = poem::listener::UnixListener::bind(path)
+                    .into_acceptor()
+                    .await?;
+                Ok(acceptor.boxed())
+            }
+            #[cfg(not(unix))]
+            Bind::Unix(_) => Err(io::Error::other("unix sockets are only supported on Unix")),
+        }
+    }
+}
+
+/// Removes the socket file a previous run left behind, so binding doesn't
+/// fail with "address in use". A socket something still answers on is left
+/// alone, and so is anything that isn't a socket.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another server", path.display()),
+                ));
+            }
+            tracing::info!("SERVE | removing stale socket {:?}", path);
+            std::fs::remove_file(path)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Unix socket files are left behind when the server stops, remove one once
+/// it's no longer listened on. Sockets systemd passed in are its to remove.
+#[cfg(unix)]
+pub fn remove_socket(bind: &Bind) {
+    if let Bind::Unix(path) = bind
+        && !socket_activated()
+        && let Err(e) = std::fs::remove_file(path)
+    {
+        tracing::debug!("SERVE | could not remove socket {:?}: {}", path, e);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn remove_socket(_bind: &Bind) {}
+
+/// Whether systemd passed in the sockets to listen on, overriding any [`Bind`].
+pub fn socket_activated() -> bool {
+    #[cfg(unix)]
+    return systemd::activated();
+    #[cfg(not(unix))]
+    false
+}
+
+#[cfg(unix)]
+mod systemd {
+    use poem::listener::{AcceptorExt, BoxAcceptor, TcpAcceptor, UnixAcceptor};
+    use std::io;
+    use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+
+    /// First fd systemd passes, after stdin, stdout and stderr.
+    const LISTEN_FDS_START: RawFd = 3;
+
+    /// The sockets passed in by systemd as one acceptor, `None` when the
+    /// process wasn't socket activated.
+    pub fn acceptor() -> io::Result<Option<BoxAcceptor>> {
+        let count = match passed_fds() {
+            Some(count) if count > 0 => count,
+            _ => return Ok(None),
+        };
+        let mut acceptors = (LISTEN_FDS_START..LISTEN_FDS_START + count).map(adopt);
+        let mut combined = acceptors.next().unwrap()?;
+        for acceptor in acceptors {
+            combined = combined.combine(acceptor?).boxed();
+        }
+        tracing::info!("SERVE | using {} sockets passed by systemd", count);
+        Ok(Some(combined))
+    }
+
+    /// Whether systemd passed this process any sockets.
+    pub fn activated() -> bool {
+        passed_fds().is_some_and(|count| count > 0)
+    }
+
+    /// How many fds systemd passed to this process, if it passed any.
+    fn passed_fds() -> Option<RawFd> {
+        // LISTEN_PID guards against a child inheriting its parent's sockets
+        let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+        if pid != std::process::id() {
+            return None;
+        }
+        std::env::var("LISTEN_FDS").ok()?.parse().ok()
+    }
+
+    /// Takes over the listening socket `fd`, which may be a Unix or a TCP one.
+    fn adopt(fd: RawFd) -> io::Result<BoxAcceptor> {
+        // SAFETY: systemd hands this process the fds from LISTEN_FDS_START on,
+        // open and owned by nobody else here, and each one is adopted once
+        let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+        // local_addr fails on anything that isn't a Unix socket
+        if unix.local_addr().is_ok() {
+            unix.set_nonblocking(true)?;
+            return Ok(UnixAcceptor::from_std(unix)?.boxed());
+        }
+        let fd = unix.into_raw_fd();
+        // SAFETY: the same fd, released by the Unix listener just above
+        let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        tcp.set_nonblocking(true)?;
+        Ok(TcpAcceptor::from_std(tcp)?.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind() {
+        assert_eq!(
+            "127.0.0.1:9000".parse::<Bind>().unwrap(),
+            Bind::Tcp("127.0.0.1:9000".to_string())
+        );
+        assert_eq!(":8080".parse::<Bind>().unwrap(), Bind::port(8080));
+        assert_eq!("[::]:80".parse::<Bind>().unwrap().to_string(), "[::]:80");
+        assert!("localhost".parse::<Bind>().is_err());
+        assert!("127.0.0.1:http".parse::<Bind>().is_err());
+        assert!("unix:".parse::<Bind>().is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            "unix:/run/blockframe.sock".parse::<Bind>().unwrap(),
+            Bind::Unix(PathBuf::from("/run/blockframe.sock"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serves_over_a_unix_socket() {
+        use poem::listener::Acceptor;
+        use std::io::Write;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("blockframe.sock");
+        // left behind by a server that is gone
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let mut acceptor = Bind::Unix(path.clone())
+            .listener()
+            .into_acceptor()
+            .await
+            .unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+            stream.write_all(b"ping").unwrap();
+        });
+        let (mut io, _, _, _) = acceptor.accept().await.unwrap();
+        let mut buf = [0u8; 4];
+        tokio::io::AsyncReadExt::read_exact(&mut io, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"ping");
+        client.join().unwrap();
+    }
+}
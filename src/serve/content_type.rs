@@ -0,0 +1,94 @@
+//! `Content-Type` and `Content-Disposition` for files sent whole, so a
+//! browser opens or saves them under their own name instead of as
+//! `download` of type `application/octet-stream`.
+//!
+//! The type comes from the file name's extension. Names without a known one
+//! can be given the first bytes of the file, which are checked against the
+//! signatures of common formats.
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+
+pub use crate::classify::{SNIFF_LEN, sniff};
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Escaped in `filename*`, RFC 5987 leaves these as they are.
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// The type of the file called `name`, whose first bytes are `head` if the
+/// caller read them. Falls back to `application/octet-stream`.
+pub fn content_type(name: &str, head: Option<&[u8]>) -> String {
+    if let Some(guess) = mime_guess::from_path(name).first_raw() {
+        return guess.to_string();
+    }
+    head.and_then(sniff).unwrap_or(OCTET_STREAM).to_string()
+}
+
+/// Whether [`content_type`] needs the first bytes of `name` to do better than
+/// `application/octet-stream`.
+pub fn needs_sniffing(name: &str) -> bool {
+    mime_guess::from_path(name).first_raw().is_none()
+}
+
+/// A `Content-Disposition` asking to save the response as `name`, with a
+/// plain ASCII fallback for clients that don't read `filename*`.
+pub fn attachment(name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        utf8_percent_encode(name, ATTR_CHAR)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_type_by_name_then_signature() {
+        assert_eq!(content_type("photo.JPG", None), "image/jpeg");
+        assert_eq!(
+            content_type("report.pdf", Some(b"PK\x03\x04")),
+            "application/pdf"
+        );
+        assert_eq!(
+            content_type("blob", Some(b"\x89PNG\r\n\x1a\n....")),
+            "image/png"
+        );
+        assert_eq!(
+            content_type("notes", Some("caf\u{e9}".as_bytes())),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(content_type("blob", Some(&[0, 1, 2, 255])), OCTET_STREAM);
+        assert_eq!(content_type("blob", None), OCTET_STREAM);
+        assert!(needs_sniffing("blob") && !needs_sniffing("a.txt"));
+    }
+
+    #[test]
+    fn test_attachment_keeps_the_name() {
+        assert_eq!(
+            attachment("résumé \"final\".txt"),
+            "attachment; filename=\"r_sum_ _final_.txt\"; \
+             filename*=UTF-8''r%C3%A9sum%C3%A9%20%22final%22.txt"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Archive change events for `GET /api/events`.
+//!
+//! Commits, repairs and retiers can come from any process (the CLI, the daemon's
+//! watch folder, another server), so the server doesn't rely on being told about
+//! them. A background task polls the archive once a second: new lines in
+//! `audit.log` become events, and entries that disappeared from the archive
+//! become `removed` events. Every connected client gets its own copy through a
+//! broadcast channel; a client that falls too far behind skips ahead.
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+use poem_openapi::Object;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::audit::{AuditEntry, AuditLog};
+use crate::filestore::FileStore;
+
+/// Events buffered per client before it starts missing some.
+const CHANNEL_CAPACITY: usize = 256;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One change to the archive.
+#[derive(Debug, Clone, Object)]
+pub struct ArchiveEvent {
+    /// `commit`, `repair`, `delete`, `replicate`, `retier` or `removed`.
+    pub kind: String,
+    pub file: String,
+    pub hash: Option<String>,
+    /// RFC 3339 time of the change, or of its detection for `removed`.
+    pub timestamp: String,
+    /// `ok`, or `failed: <reason>`.
+    pub outcome: String,
+}
+
+impl From<AuditEntry> for ArchiveEvent {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            kind: entry.operation.to_string(),
+            file: entry.file_name,
+            hash: entry.hash,
+            timestamp: entry.timestamp.to_rfc3339(),
+            outcome: entry.outcome,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<ArchiveEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx }
+    }
+
+    /// Sends `event` to every connected client. Dropped when nobody listens.
+    pub fn publish(&self, event: ArchiveEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    /// Stream of events published from now on.
+    pub fn subscribe(&self) -> BoxStream<'static, ArchiveEvent> {
+        stream::unfold(self.tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("EVENTS | client lagged, skipped {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Starts polling `store`'s archive for changes. Abort the handle to stop.
+    pub fn watch(&self, store: Arc<FileStore>) -> JoinHandle<()> {
+        let bus = self.clone();
+        tokio::spawn(async move {
+            let log = AuditLog::for_archive(&store.store_path);
+            // only changes made after the server started are reported
+            let mut offset = log.end_offset();
+            let mut known = file_names(&store);
+
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let store = store.clone();
+                let result = tokio::task::spawn_blocking(move || poll(&store, offset, known)).await;
+                match result {
+                    Ok((events, next_offset, names)) => {
+                        for event in events {
+                            bus.publish(event);
+                        }
+                        offset = next_offset;
+                        known = names;
+                    }
+                    Err(e) => {
+                        tracing::error!("EVENTS | archive poll panicked: {}", e);
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Events since `offset` plus `removed` events for files no longer in the
+/// archive. Returns the new offset and file names to poll from next time.
+fn poll(
+    store: &FileStore,
+    offset: u64,
+    known: HashSet<String>,
+) -> (Vec<ArchiveEvent>, u64, HashSet<String>) {
+    let log = AuditLog::for_archive(&store.store_path);
+    let (entries, next_offset) = match log.read_from(offset) {
+        Ok(read) => read,
+        Err(e) => {
+            tracing::warn!("EVENTS | cannot read audit log: {}", e);
+            (Vec::new(), offset)
+        }
+    };
+
+    let mut events: Vec<ArchiveEvent> = entries.into_iter().map(ArchiveEvent::from).collect();
+    let names = file_names(store);
+    let now = chrono::Utc::now().to_rfc3339();
+    for removed in known.difference(&names) {
+        events.push(ArchiveEvent {
+            kind: "removed".to_string(),
+            file: removed.clone(),
+            hash: None,
+            timestamp: now.clone(),
+            outcome: "ok".to_string(),
+        });
+    }
+    (events, next_offset, names)
+}
+
+fn file_names(store: &FileStore) -> HashSet<String> {
+    match store.get_all() {
+        Ok(files) => files.into_iter().map(|f| f.file_name).collect(),
+        Err(e) => {
+            tracing::debug!("EVENTS | cannot list archive: {}", e);
+            HashSet::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditEntry, AuditOp};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_poll_reports_audit_entries_and_removals() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = FileStore::new(temp_dir.path()).unwrap();
+        let log = AuditLog::for_archive(temp_dir.path());
+        log.append(&AuditEntry::new(AuditOp::Commit, "old.txt"))
+            .unwrap();
+
+        let known = HashSet::from(["gone.txt".to_string()]);
+        let offset = log.end_offset();
+        log.append(&AuditEntry::new(AuditOp::Repair, "a.txt").hash("aa"))
+            .unwrap();
+
+        let (events, next, names) = poll(&store, offset, known);
+        assert_eq!(next, fs::metadata(log.path()).unwrap().len());
+        assert!(names.is_empty());
+        let kinds: Vec<(&str, &str)> = events
+            .iter()
+            .map(|e| (e.kind.as_str(), e.file.as_str()))
+            .collect();
+        assert_eq!(kinds, vec![("repair", "a.txt"), ("removed", "gone.txt")]);
+    }
+}
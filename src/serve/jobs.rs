@@ -0,0 +1,323 @@
+//! Background jobs for `/api/jobs`.
+//!
+//! Scrubs, repairs and uploads can run for hours, longer than a client should
+//! hold a request open. They are started as jobs instead: the request returns
+//! the job's id at once and the client polls `GET /api/jobs/:id` for its state,
+//! progress and log, or cancels it with `POST /api/jobs/:id/cancel`.
+//!
+//! Jobs live in memory only and are forgotten when the server stops. The most
+//! recent [`KEPT_FINISHED`] finished jobs are kept for clients to collect.
+//! Cancelling is cooperative: the job sees its [`CancelToken`] set at its
+//! next check and stops there.
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use poem_openapi::{Enum, Object};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::Instrument;
+
+use crate::utils::CancelToken;
+
+/// Finished jobs kept around for clients to read, oldest dropped first.
+pub const KEPT_FINISHED: usize = 200;
+
+/// Log lines kept per job, oldest dropped first.
+const KEPT_LOG_LINES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+
+/// What `GET /api/jobs/:id` reports about a job.
+#[derive(Debug, Clone, Object)]
+pub struct JobStatus {
+    pub id: u64,
+    /// `scrub`, `repair` or `upload`.
+    pub kind: String,
+    /// File the job works on, if it is about one file.
+    pub target: Option<String>,
+    pub state: JobState,
+    /// Units of work done so far, files for a scrub, bytes for an upload.
+    pub done: u64,
+    /// Units of work in total, once known.
+    pub total: Option<u64>,
+    /// RFC 3339 times the job was created, started and finished.
+    pub created: String,
+    pub started: Option<String>,
+    pub finished: Option<String>,
+    /// Summary on success, the error on failure.
+    pub result: Option<String>,
+    /// The job's most recent log lines.
+    pub log: Vec<String>,
+}
+
+struct JobEntry {
+    status: JobStatus,
+    /// Set by [`JobRegistry::cancel`], along with any tokens the job linked.
+    cancel: Vec<CancelToken>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    jobs: BTreeMap<u64, JobEntry>,
+}
+
+/// Jobs started through the API. Clones share the same jobs.
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    inner: Arc<Mutex<Registry>>,
+}
+
+/// Handed to a running job to report on itself and check for cancellation.
+#[derive(Clone)]
+pub struct JobContext {
+    id: u64,
+    cancel: CancelToken,
+    registry: JobRegistry,
+}
+
+impl JobContext {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// The job's own token, for work that takes one.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Also cancels `token` when the job is cancelled, for work that made
+    /// its own, such as a [`CommitHandle`](crate::chunker::CommitHandle).
+    pub fn link(&self, token: CancelToken) {
+        if self.is_cancelled() {
+            token.cancel();
+        }
+        self.registry
+            .update(self.id, |entry| entry.cancel.push(token));
+    }
+
+    pub fn progress(&self, done: u64, total: Option<u64>) {
+        self.registry.update(self.id, |entry| {
+            entry.status.done = done;
+            entry.status.total = total;
+        });
+    }
+
+    pub fn log(&self, line: impl Into<String>) {
+        let line = line.into();
+        tracing::info!("JOBS | #{} {}", self.id, line);
+        self.registry.update(self.id, |entry| {
+            let log = &mut entry.status.log;
+            log.push(line);
+            if log.len() > KEPT_LOG_LINES {
+                log.remove(0);
+            }
+        });
+    }
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts `run` as a tokio task and returns the job as queued. Blocking
+    /// work inside it belongs on `spawn_blocking`. A job that returns after
+    /// being cancelled is recorded as cancelled whatever it returned.
+    pub fn spawn<F, Fut>(&self, kind: &str, target: Option<String>, run: F) -> JobStatus
+    where
+        F: FnOnce(JobContext) -> Fut,
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        let cancel = CancelToken::new();
+        let status = {
+            let mut registry = self.inner.lock();
+            registry.next_id += 1;
+            let status = JobStatus {
+                id: registry.next_id,
+                kind: kind.to_string(),
+                target,
+                state: JobState::Queued,
+                done: 0,
+                total: None,
+                created: Utc::now().to_rfc3339(),
+                started: None,
+                finished: None,
+                result: None,
+                log: Vec::new(),
+            };
+            registry.jobs.insert(
+                status.id,
+                JobEntry {
+                    status: status.clone(),
+                    cancel: vec![cancel.clone()],
+                },
+            );
+            prune(&mut registry);
+            status
+        };
+
+        let ctx = JobContext {
+            id: status.id,
+            cancel,
+            registry: self.clone(),
+        };
+        let job = run(ctx.clone());
+        // logs of the job carry the ID of the request that started it
+        let span = tracing::Span::current();
+        tokio::spawn(
+            async move {
+                ctx.registry.update(ctx.id, |entry| {
+                    entry.status.state = JobState::Running;
+                    entry.status.started = Some(Utc::now().to_rfc3339());
+                });
+                let outcome = job.await;
+                let (state, result) = match outcome {
+                    _ if ctx.is_cancelled() => (JobState::Cancelled, Some("cancelled".to_string())),
+                    Ok(summary) => (JobState::Succeeded, Some(summary)),
+                    Err(e) => (JobState::Failed, Some(e)),
+                };
+                tracing::info!(
+                    "JOBS | #{} {:?}: {}",
+                    ctx.id,
+                    state,
+                    result.as_deref().unwrap_or("")
+                );
+                ctx.registry.update(ctx.id, |entry| {
+                    entry.status.state = state;
+                    entry.status.result = result;
+                    entry.status.finished = Some(Utc::now().to_rfc3339());
+                });
+            }
+            .instrument(span),
+        );
+        status
+    }
+
+    /// Every job still kept, oldest first.
+    pub fn list(&self) -> Vec<JobStatus> {
+        let registry = self.inner.lock();
+        registry
+            .jobs
+            .values()
+            .map(|entry| entry.status.clone())
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<JobStatus> {
+        self.inner
+            .lock()
+            .jobs
+            .get(&id)
+            .map(|entry| entry.status.clone())
+    }
+
+    /// Asks job `id` to stop. `None` if there is no such job; a finished job
+    /// is returned unchanged.
+    pub fn cancel(&self, id: u64) -> Option<JobStatus> {
+        let registry = self.inner.lock();
+        let entry = registry.jobs.get(&id)?;
+        if !entry.status.state.is_finished() {
+            entry.cancel.iter().for_each(CancelToken::cancel);
+        }
+        Some(entry.status.clone())
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut JobEntry)) {
+        if let Some(entry) = self.inner.lock().jobs.get_mut(&id) {
+            change(entry);
+        }
+    }
+}
+
+/// Drops the oldest finished jobs beyond [`KEPT_FINISHED`].
+fn prune(registry: &mut Registry) {
+    let finished: Vec<u64> = registry
+        .jobs
+        .iter()
+        .filter(|(_, entry)| entry.status.state.is_finished())
+        .map(|(id, _)| *id)
+        .collect();
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(KEPT_FINISHED))
+    {
+        registry.jobs.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn wait_finished(jobs: &JobRegistry, id: u64) -> JobStatus {
+        for _ in 0..500 {
+            let status = jobs.get(id).unwrap();
+            if status.state.is_finished() {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} never finished", id);
+    }
+
+    #[tokio::test]
+    async fn test_jobs_report_progress_and_cancel() {
+        let jobs = JobRegistry::new();
+
+        let done = jobs.spawn("scrub", None, |ctx| async move {
+            ctx.progress(3, Some(3));
+            ctx.log("checked 3 files");
+            Ok("3/3 healthy".to_string())
+        });
+        assert_eq!(done.state, JobState::Queued);
+        let done = wait_finished(&jobs, done.id).await;
+        assert_eq!(done.state, JobState::Succeeded);
+        assert_eq!((done.done, done.total), (3, Some(3)));
+        assert_eq!(done.log, ["checked 3 files"]);
+        assert_eq!(done.result.as_deref(), Some("3/3 healthy"));
+
+        // a linked token is cancelled along with the job
+        let linked = CancelToken::new();
+        let seen = linked.clone();
+        let looping = jobs.spawn("upload", Some("big.bin".into()), |ctx| async move {
+            ctx.link(seen);
+            while !ctx.is_cancelled() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            Err("stopped".to_string())
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        jobs.cancel(looping.id).unwrap();
+        let looping = wait_finished(&jobs, looping.id).await;
+        assert_eq!(looping.state, JobState::Cancelled);
+        assert!(linked.is_cancelled());
+
+        assert_eq!(jobs.list().len(), 2);
+        assert!(jobs.get(99).is_none());
+        assert!(jobs.cancel(99).is_none());
+    }
+}
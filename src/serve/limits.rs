@@ -0,0 +1,340 @@
+//! Request and bandwidth limits for `serve`, so one greedy client, such as a
+//! remote mount reading ahead, can't take the whole disk from everyone else.
+//!
+//! Every limit is a token bucket. Requests over a client's rate are refused
+//! with `429 Too Many Requests` and a `Retry-After`. Bytes are never refused:
+//! upload and download bodies are paced chunk by chunk instead, against the
+//! server-wide bucket and the client's own. Clients are told apart by IP
+//! address.
+
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use poem::http::StatusCode;
+use poem::{Body, Endpoint, IntoResponse, Middleware, Request, Response};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::{ServerConfig, parse_size};
+
+/// Clients tracked before idle ones are forgotten.
+const MAX_CLIENTS: usize = 4096;
+
+/// How long a client is remembered after its last request.
+const CLIENT_IDLE: Duration = Duration::from_secs(60);
+
+/// The limits from the `[server]` config section. Each one is off when unset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimits {
+    /// Requests per second from one client.
+    pub requests_per_second: Option<f64>,
+    /// Requests one client can make at once after being idle.
+    pub burst: u32,
+    /// Body bytes per second across every client.
+    pub bandwidth: Option<u64>,
+    /// Body bytes per second to and from one client.
+    pub client_bandwidth: Option<u64>,
+}
+
+impl RateLimits {
+    /// Reads the limits from the `[server]` config section, `None` if none
+    /// are set.
+    pub fn from_config(server: &ServerConfig) -> Result<Option<Self>, String> {
+        let bytes = |key: &str, text: &str| -> Result<Option<u64>, String> {
+            if text.trim().is_empty() {
+                return Ok(None);
+            }
+            match parse_size(text) {
+                Ok(0) => Ok(None),
+                Ok(size) => Ok(Some(size as u64)),
+                Err(e) => Err(format!("server.{} '{}': {}", key, text, e)),
+            }
+        };
+        if server.rate_limit < 0.0 {
+            return Err("server.rate_limit must not be negative".to_string());
+        }
+        let limits = Self {
+            requests_per_second: (server.rate_limit > 0.0).then_some(server.rate_limit),
+            burst: server.rate_burst.max(1),
+            bandwidth: bytes("max_bandwidth", &server.max_bandwidth)?,
+            client_bandwidth: bytes("max_client_bandwidth", &server.max_client_bandwidth)?,
+        };
+        let any = limits.requests_per_second.is_some()
+            || limits.bandwidth.is_some()
+            || limits.client_bandwidth.is_some();
+        Ok(any.then_some(limits))
+    }
+}
+
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+    }
+
+    /// Takes `amount` if there is that much, otherwise returns how long until
+    /// there will be.
+    fn try_take(&mut self, amount: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((amount - self.tokens) / self.rate))
+        }
+    }
+
+    /// Takes `amount` whether or not it is there, returning how long the
+    /// caller has to wait for the debt to be paid off. Callers waiting in turn
+    /// are spaced out by the time their own amounts take.
+    fn reserve(&mut self, amount: f64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= amount;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Client {
+    requests: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+struct Buckets {
+    limits: RateLimits,
+    global: Option<Mutex<TokenBucket>>,
+    clients: Mutex<HashMap<IpAddr, (Client, Instant)>>,
+}
+
+impl Buckets {
+    fn with_client<T>(&self, ip: IpAddr, f: impl FnOnce(&mut Client, Instant) -> T) -> T {
+        let now = Instant::now();
+        let mut clients = self.clients.lock();
+        if clients.len() >= MAX_CLIENTS && !clients.contains_key(&ip) {
+            clients.retain(|_, (_, seen)| now.duration_since(*seen) < CLIENT_IDLE);
+        }
+        let (client, seen) = clients.entry(ip).or_insert_with(|| {
+            let limits = &self.limits;
+            let client = Client {
+                requests: limits
+                    .requests_per_second
+                    .map(|rate| TokenBucket::new(rate, limits.burst as f64)),
+                // a second's worth, so short bursts aren't paced
+                bytes: limits
+                    .client_bandwidth
+                    .map(|rate| TokenBucket::new(rate as f64, rate as f64)),
+            };
+            (client, now)
+        });
+        *seen = now;
+        f(client, now)
+    }
+
+    /// Whether `ip` may make a request now, or how long it should wait.
+    fn admit(&self, ip: Option<IpAddr>) -> Result<(), Duration> {
+        match ip {
+            Some(ip) if self.limits.requests_per_second.is_some() => {
+                self.with_client(ip, |client, now| match &mut client.requests {
+                    Some(bucket) => bucket.try_take(1.0, now),
+                    None => Ok(()),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// How long to hold `len` bytes to or from `ip` back to stay in the limits.
+    fn pace(&self, ip: Option<IpAddr>, len: usize) -> Duration {
+        let len = len as f64;
+        let global = self.global.as_ref().map_or(Duration::ZERO, |bucket| {
+            bucket.lock().reserve(len, Instant::now())
+        });
+        let client = match ip {
+            Some(ip) if self.limits.client_bandwidth.is_some() => {
+                self.with_client(ip, |client, now| match &mut client.bytes {
+                    Some(bucket) => bucket.reserve(len, now),
+                    None => Duration::ZERO,
+                })
+            }
+            _ => Duration::ZERO,
+        };
+        global.max(client)
+    }
+
+    fn throttles_bytes(&self) -> bool {
+        self.limits.bandwidth.is_some() || self.limits.client_bandwidth.is_some()
+    }
+}
+
+/// Middleware applying [`RateLimits`] to every route it wraps.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        let global = limits
+            .bandwidth
+            .map(|rate| Mutex::new(TokenBucket::new(rate as f64, rate as f64)));
+        Self {
+            buckets: Arc::new(Buckets {
+                limits,
+                global,
+                clients: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RateLimiter {
+    type Output = RateLimitEndpoint<E>;
+
+    fn transform(&self, inner: E) -> Self::Output {
+        RateLimitEndpoint {
+            inner,
+            buckets: self.buckets.clone(),
+        }
+    }
+}
+
+pub struct RateLimitEndpoint<E> {
+    inner: E,
+    buckets: Arc<Buckets>,
+}
+
+impl<E: Endpoint> Endpoint for RateLimitEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let ip = req.remote_addr().as_socket_addr().map(|addr| addr.ip());
+        if let Err(wait) = self.buckets.admit(ip) {
+            tracing::debug!("SERVE | rate limited {:?} on {}", ip, req.uri().path());
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(
+                    "Retry-After",
+                    wait.as_secs_f64().ceil().max(1.0).to_string(),
+                )
+                .body("Too many requests"));
+        }
+        if !self.buckets.throttles_bytes() {
+            return Ok(self.inner.call(req).await?.into_response());
+        }
+
+        let body = req.take_body();
+        req.set_body(paced(body, self.buckets.clone(), ip));
+        let mut response = self.inner.call(req).await?.into_response();
+        let body = response.take_body();
+        response.set_body(paced(body, self.buckets.clone(), ip));
+        Ok(response)
+    }
+}
+
+/// `body`, with each chunk held back as long as the limits require.
+fn paced(body: Body, buckets: Arc<Buckets>, ip: Option<IpAddr>) -> Body {
+    let stream = body.into_bytes_stream().then(move |chunk| {
+        let buckets = buckets.clone();
+        async move {
+            if let Ok(bytes) = &chunk {
+                let wait = buckets.pace(ip, bytes.len());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            chunk
+        }
+    });
+    Body::from_bytes_stream(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poem::EndpointExt;
+
+    #[test]
+    fn test_buckets_refill_at_their_rate() {
+        let start = Instant::now();
+        let mut requests = TokenBucket::new(2.0, 2.0);
+        assert!(requests.try_take(1.0, start).is_ok());
+        assert!(requests.try_take(1.0, start).is_ok());
+        let wait = requests.try_take(1.0, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        assert!(requests.try_take(1.0, start + wait).is_ok());
+
+        // a second's worth goes through at once, then each chunk waits its turn
+        let mut bytes = TokenBucket::new(1000.0, 1000.0);
+        assert_eq!(bytes.reserve(1000.0, start), Duration::ZERO);
+        assert_eq!(bytes.reserve(500.0, start), Duration::from_millis(500));
+        assert_eq!(bytes.reserve(500.0, start), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_the_rate_are_refused() {
+        let limits = RateLimits {
+            requests_per_second: Some(0.5),
+            burst: 2,
+            ..RateLimits::default()
+        };
+        let app = poem::endpoint::make_sync(|_| "ok").with(RateLimiter::new(limits));
+        let request = |ip: &str| {
+            let (parts, ()) = poem::http::Request::new(()).into_parts();
+            let addr = poem::Addr::SocketAddr(format!("{}:1234", ip).parse().unwrap());
+            let parts = poem::RequestParts::from((
+                parts,
+                poem::web::LocalAddr::default(),
+                poem::web::RemoteAddr(addr),
+                poem::http::uri::Scheme::HTTP,
+            ));
+            Request::from_parts(parts, Body::empty())
+        };
+
+        for _ in 0..2 {
+            let response = app.get_response(request("10.0.0.1")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let refused = app.get_response(request("10.0.0.1")).await;
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(refused.headers()["Retry-After"], "2");
+        // another client has its own allowance
+        let other = app.get_response(request("10.0.0.2")).await;
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_limits_from_config() {
+        let mut server = ServerConfig::default();
+        assert_eq!(RateLimits::from_config(&server).unwrap(), None);
+        server.max_bandwidth = "200MB".to_string();
+        server.rate_limit = 50.0;
+        let limits = RateLimits::from_config(&server).unwrap().unwrap();
+        assert_eq!(limits.bandwidth, Some(200_000_000));
+        assert_eq!(limits.requests_per_second, Some(50.0));
+        assert_eq!(limits.client_bandwidth, None);
+        server.max_client_bandwidth = "fast".to_string();
+        assert!(RateLimits::from_config(&server).is_err());
+    }
+}
@@ -1,16 +1,177 @@
+pub mod bind;
+pub mod content_type;
+pub mod events;
+pub mod jobs;
+pub mod limits;
+pub mod probes;
+pub mod read_only;
+pub mod request_log;
 pub mod routes;
+#[cfg(feature = "web-ui")]
+pub mod ui;
+pub mod webdav;
 
-use poem::{EndpointExt, Route, Server, listener::TcpListener, middleware::Cors};
+use poem::{
+    EndpointExt, Route, Server,
+    listener::{Listener, RustlsCertificate, RustlsConfig},
+    middleware::Cors,
+};
 use poem_openapi::OpenApiService;
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
 
+use crate::chunker::Chunker;
+use crate::config::ServerConfig;
 use crate::filestore::FileStore;
 
-pub async fn run_server(
-    archive_path: PathBuf,
-    port: u16,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let store = FileStore::new(&archive_path)?;
+/// PEM certificate chain and private key used to serve over HTTPS.
+#[derive(Debug, Clone)]
+pub struct TlsPaths {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsPaths {
+    /// Reads the TLS paths from the `[server]` config section. Both or neither
+    /// must be set.
+    pub fn from_config(server: &ServerConfig) -> Result<Option<Self>, String> {
+        match (&server.tls_cert, &server.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some(Self {
+                cert: cert.clone(),
+                key: key.clone(),
+            })),
+            (None, None) => Ok(None),
+            _ => Err("server.tls_cert and server.tls_key must be set together".to_string()),
+        }
+    }
+}
+
+/// Everything `serve` needs to start listening.
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub archive_path: PathBuf,
+    pub port: u16,
+    pub tls: Option<TlsPaths>,
+    /// Accept `PUT /files/:filename` and commit uploads with this chunker.
+    pub uploads: Option<Arc<Chunker>>,
+    /// Also serve the archive as a read-only WebDAV share at `/dav`.
+    pub webdav: bool,
+    /// Request and bandwidth limits applied to every route.
+    pub limits: Option<limits::RateLimits>,
+    /// Refuse every request that could change the archive, see [`read_only`],
+    /// and open it with [`FileStore::read_only`].
+    pub read_only: bool,
+    /// How long in-flight requests get to finish once shutdown starts.
+    pub drain_timeout: Duration,
+    /// How long a file name that wasn't found is answered as missing, see
+    /// [`FileStore::with_negative_ttl`].
+    pub negative_ttl: Duration,
+    /// Where to listen instead of every interface on `port`.
+    pub bind: Option<bind::Bind>,
+}
+
+impl ServeOptions {
+    pub fn new(archive_path: PathBuf, port: u16) -> Self {
+        Self {
+            archive_path,
+            port,
+            tls: None,
+            uploads: None,
+            webdav: false,
+            limits: None,
+            read_only: false,
+            drain_timeout: Duration::from_secs(30),
+            negative_ttl: Duration::from_secs(5),
+            bind: None,
+        }
+    }
+}
+
+/// Serves until Ctrl-C or SIGTERM, then stops accepting connections and gives
+/// in-flight requests `options.drain_timeout` to finish. A second signal
+/// stops at once.
+pub async fn run_server(options: ServeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let stopping = Arc::new(Notify::new());
+    let first = {
+        let stopping = stopping.clone();
+        async move {
+            shutdown_signal().await;
+            stopping.notify_one();
+        }
+    };
+    tokio::select! {
+        result = run_server_until(options, first) => result,
+        _ = async {
+            stopping.notified().await;
+            shutdown_signal().await;
+        } => {
+            tracing::warn!("SERVE | signalled again, stopping without waiting for requests");
+            Ok(())
+        }
+    }
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Same as [`run_server`] but stops accepting connections once `shutdown`
+/// resolves, giving in-flight requests `options.drain_timeout` to finish.
+pub async fn run_server_until<F>(
+    options: ServeOptions,
+    shutdown: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Future<Output = ()> + Send,
+{
+    let mut store = FileStore::new(&options.archive_path)?.with_negative_ttl(options.negative_ttl);
+    if options.read_only {
+        // reads record health and repair what they find, keep that out too
+        store = store.read_only(None);
+    }
+    run_server_with_store(options, Arc::new(store), shutdown).await
+}
+
+/// Same as [`run_server_until`] but serves an existing store, so a caller that
+/// also changes the archive can share its file list cache with the server.
+/// `options.archive_path` is ignored in favour of the store's own path. With
+/// `options.read_only` the store should be [`FileStore::read_only`] too, or
+/// the requests that are let through may still write health history.
+pub async fn run_server_with_store<F>(
+    options: ServeOptions,
+    store: Arc<FileStore>,
+    shutdown: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Future<Output = ()> + Send,
+{
+    let ServeOptions {
+        port,
+        tls,
+        uploads,
+        webdav,
+        limits,
+        read_only,
+        drain_timeout,
+        bind,
+        ..
+    } = options;
+    let bind = bind.unwrap_or_else(|| bind::Bind::port(port));
 
     // Add CORS middleware to allow cross-origin requests for remote mounting
     // Create separate CORS instances for each route
@@ -24,7 +185,12 @@ pub async fn run_server(
             "Origin",
             "X-Requested-With",
         ])
-        .expose_headers(vec!["Content-Length", "Content-Type"])
+        .expose_headers(vec![
+            "Content-Length",
+            "Content-Type",
+            "X-Request-Id",
+            "X-Total-Count",
+        ])
         .max_age(3600);
 
     let cors_docs = Cors::new()
@@ -37,27 +203,208 @@ pub async fn run_server(
             "Origin",
             "X-Requested-With",
         ])
-        .expose_headers(vec!["Content-Length", "Content-Type"])
+        .expose_headers(vec![
+            "Content-Length",
+            "Content-Type",
+            "X-Request-Id",
+            "X-Total-Count",
+        ])
         .max_age(3600);
 
+    let stats = Arc::new(request_log::ServerStats::new());
+    let events = events::EventBus::new();
+    let watcher = events.watch(store.clone());
+
     // Use relative server path so Swagger UI knows routes are under /api
-    let api_service =
-        OpenApiService::new(routes::BlockframeApi::new(store), "BlockFrame API", "0.3.0")
-            .server("/api");
+    let dav = webdav.then(|| webdav::WebDav::new(store.clone()));
+    let probe_store = store.clone();
+    let api_service = OpenApiService::new(
+        routes::BlockframeApi::new(store, events).with_uploads(uploads),
+        "BlockFrame API",
+        "0.3.0",
+    )
+    .server("/api");
     let ui = api_service.swagger_ui();
 
     // Apply CORS to both the API and docs separately
-    let app = Route::new()
+    let mut app = Route::new()
         .nest("/api", api_service.with(cors_api))
         .nest("/docs", ui.with(cors_docs));
+    if let Some(dav) = dav {
+        app = app.nest(webdav::DAV_PATH, dav);
+    }
+    #[cfg(feature = "web-ui")]
+    {
+        app = app.at(ui::UI_PATH, poem::get(ui::page));
+    }
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let base = if bind::socket_activated() {
+        println!("Server running on the sockets passed by systemd");
+        String::new()
+    } else {
+        let base = bind.url(scheme);
+        println!("Server running at {}", base);
+        base
+    };
+    println!("API docs at {}/docs", base);
+    #[cfg(feature = "web-ui")]
+    println!("Browse the archive at {}{}", base, ui::UI_PATH);
+    if webdav {
+        println!("WebDAV share at {}{}/", base, webdav::DAV_PATH);
+    }
+    println!(
+        "Probes at {}{} and {}",
+        base,
+        probes::HEALTHZ_PATH,
+        probes::READYZ_PATH
+    );
+    if let bind::Bind::Tcp(_) = bind
+        && !base.is_empty()
+    {
+        println!("Access from network using your IP address");
+    }
 
-    println!("Server running at http://0.0.0.0:{}", port);
-    println!("API docs at http://0.0.0.0:{}/docs", port);
-    println!("Access from network using your IP address");
+    let app = match limits {
+        Some(limits) => {
+            tracing::info!("SERVE | rate limits {:?}", limits);
+            app.with(limits::RateLimiter::new(limits)).boxed()
+        }
+        None => app.boxed(),
+    };
+    let app = if read_only {
+        tracing::info!("SERVE | read-only, refusing uploads, repairs and jobs");
+        app.with(read_only::ReadOnly).boxed()
+    } else {
+        app
+    };
+    let app = Route::new()
+        .at(probes::HEALTHZ_PATH, poem::get(probes::healthz))
+        .at(
+            probes::READYZ_PATH,
+            poem::get(probes::readyz).data(probe_store),
+        )
+        .nest("/", app.with(request_log::RequestLog::new(stats.clone())));
+
+    let listener = bind.clone().listener();
+    let listener = match tls {
+        Some(tls) => {
+            let certificate = RustlsCertificate::new()
+                .cert(std::fs::read(&tls.cert)?)
+                .key(std::fs::read(&tls.key)?);
+            listener
+                .rustls(RustlsConfig::new().fallback(certificate))
+                .boxed()
+        }
+        None => listener.boxed(),
+    };
 
-    Server::new(TcpListener::bind(format!("0.0.0.0:{}", port)))
-        .run(app)
-        .await?;
+    // the listener is closed as soon as `shutdown` resolves, so nothing new
+    // is accepted while the requests already running are drained
+    let draining = stats.clone();
+    let shutdown = async move {
+        shutdown.await;
+        draining.start_drain(drain_timeout);
+        tracing::info!(
+            "SERVE | shutting down, no longer accepting connections, waiting up to {:?} for {} requests",
+            drain_timeout,
+            draining.in_flight()
+        );
+    };
+    let result = Server::new(listener)
+        .run_with_graceful_shutdown(app, shutdown, Some(drain_timeout))
+        .await;
+    watcher.abort();
+    bind::remove_socket(&bind);
+    if stats.dropped() > 0 {
+        tracing::warn!(
+            "SERVE | drain timed out, {} requests were cut off",
+            stats.dropped()
+        );
+    }
+    tracing::info!("SERVE | stopped, {}", stats.summary());
+    result?;
 
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use tempfile::TempDir;
+
+    /// Every file under `dir` with its length, sorted.
+    fn snapshot(dir: &std::path::Path) -> Vec<(PathBuf, u64)> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                match path.is_dir() {
+                    true => dirs.push(path),
+                    false => files.push((path.clone(), std::fs::metadata(&path).unwrap().len())),
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_only_health_leaves_the_archive_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("archive_directory");
+        let source = temp_dir.path().join("notes.txt");
+        std::fs::write(&source, vec![7u8; 10_000]).unwrap();
+        Chunker::builder()
+            .archive_dir(archive.clone())
+            .build()
+            .unwrap()
+            .commit(&source)
+            .unwrap();
+        let before = snapshot(&archive);
+
+        let socket = temp_dir.path().join("blockframe.sock");
+        let mut options = ServeOptions::new(archive.clone(), 0);
+        options.read_only = true;
+        options.bind = Some(bind::Bind::Unix(socket.clone()));
+        let stop = Arc::new(Notify::new());
+        let server = run_server_until(options, {
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        });
+        let client = async {
+            let response = tokio::task::spawn_blocking(move || {
+                let mut stream = loop {
+                    match UnixStream::connect(&socket) {
+                        Ok(stream) => break stream,
+                        Err(_) => std::thread::sleep(Duration::from_millis(20)),
+                    }
+                };
+                stream
+                    .write_all(
+                        b"GET /api/files/notes.txt/health HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                    )
+                    .unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            })
+            .await
+            .unwrap();
+            stop.notify_one();
+            response
+        };
+        let (served, response) = tokio::join!(server, client);
+        served.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert_eq!(snapshot(&archive), before);
+        assert!(
+            !archive
+                .join(crate::filestore::history::HISTORY_FILE)
+                .exists()
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Liveness and readiness probes for load balancers, reverse proxies and
+//! Kubernetes.
+//!
+//! `/healthz` answers as long as the process serves requests. `/readyz` also
+//! checks that the archive directory can be opened and that its file list has
+//! been scanned, so listings won't stall on a first scan. Neither one reads a
+//! manifest. Both sit outside the rate limits and the request log, which
+//! probes every few seconds would otherwise fill.
+
+use poem::http::StatusCode;
+use poem::web::{Data, Json};
+use poem::{IntoResponse, Response, handler};
+use serde_json::json;
+use std::fs;
+use std::sync::Arc;
+
+use crate::filestore::FileStore;
+
+pub const HEALTHZ_PATH: &str = "/healthz";
+pub const READYZ_PATH: &str = "/readyz";
+
+#[handler]
+pub fn healthz() -> &'static str {
+    "ok"
+}
+
+/// `200` with the checks that passed, or `503` naming the one that didn't.
+#[handler]
+pub fn readyz(store: Data<&Arc<FileStore>>) -> Response {
+    let archive = fs::read_dir(&store.store_path).map(|_| ());
+    let loaded = store.is_loaded();
+    let status = if archive.is_ok() && loaded {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "ready": status == StatusCode::OK,
+        "archive": match &archive {
+            Ok(()) => "readable".to_string(),
+            Err(e) => e.to_string(),
+        },
+        "catalog": if loaded { "loaded" } else { "loading" },
+    });
+    Json(body).with_status(status).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poem::{Endpoint, EndpointExt, Request, Route, get};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_ready_once_the_catalog_is_loaded() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = Arc::new(FileStore::new(temp_dir.path()).unwrap());
+        let app = Route::new()
+            .at(HEALTHZ_PATH, get(healthz))
+            .at(READYZ_PATH, get(readyz))
+            .data(store.clone());
+        let probe = |path: &str| Request::builder().uri_str(path).finish();
+
+        let live = app.get_response(probe(HEALTHZ_PATH)).await;
+        assert_eq!(live.status(), StatusCode::OK);
+        let ready = app.get_response(probe(READYZ_PATH)).await;
+        assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        store.get_all().unwrap();
+        let ready = app.get_response(probe(READYZ_PATH)).await;
+        assert_eq!(ready.status(), StatusCode::OK);
+
+        // still loaded, but the archive has gone away
+        drop(temp_dir);
+        let ready = app.get_response(probe(READYZ_PATH)).await;
+        assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = ready.into_body().into_string().await.unwrap();
+        assert!(body.contains("\"catalog\":\"loaded\""));
+    }
+}
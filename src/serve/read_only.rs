@@ -0,0 +1,80 @@
+//! `serve --read-only`: every request that could change the archive is
+//! refused before it reaches a route, whatever else the server was started
+//! with, so an archive can be shared on a wide network without a stray upload,
+//! repair or job touching it.
+//!
+//! It goes by method rather than by route, so endpoints added later are
+//! covered without being listed here. Only methods that read are let through.
+
+use poem::http::{Method, StatusCode, header};
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+
+/// Methods that never change anything. `PROPFIND` lists the WebDAV share.
+const READ_METHODS: &[&str] = &["GET", "HEAD", "OPTIONS", "PROPFIND"];
+
+/// Middleware refusing every method not in [`READ_METHODS`] with `405`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOnly;
+
+impl<E: Endpoint> Middleware<E> for ReadOnly {
+    type Output = ReadOnlyEndpoint<E>;
+
+    fn transform(&self, inner: E) -> Self::Output {
+        ReadOnlyEndpoint { inner }
+    }
+}
+
+pub struct ReadOnlyEndpoint<E> {
+    inner: E,
+}
+
+impl<E: Endpoint> Endpoint for ReadOnlyEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        if !is_read(req.method()) {
+            tracing::info!(
+                "SERVE | refused {} {}, the server is read-only",
+                req.method(),
+                req.uri().path()
+            );
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, READ_METHODS.join(", "))
+                .body("the server is read-only"));
+        }
+        Ok(self.inner.call(req).await?.into_response())
+    }
+}
+
+fn is_read(method: &Method) -> bool {
+    READ_METHODS.contains(&method.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poem::EndpointExt;
+
+    #[tokio::test]
+    async fn test_only_reads_get_through() {
+        let app = poem::endpoint::make_sync(|_| "ok").with(ReadOnly);
+        for method in ["GET", "HEAD", "OPTIONS", "PROPFIND"] {
+            let method = Method::from_bytes(method.as_bytes()).unwrap();
+            let response = app
+                .get_response(Request::builder().method(method).finish())
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        for method in [Method::PUT, Method::POST, Method::DELETE, Method::PATCH] {
+            let response = app
+                .get_response(Request::builder().method(method).finish())
+                .await;
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(
+                response.headers()[header::ALLOW],
+                "GET, HEAD, OPTIONS, PROPFIND"
+            );
+        }
+    }
+}
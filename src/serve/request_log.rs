@@ -0,0 +1,325 @@
+//! One log line per request, written when the response has been sent: method,
+//! path, status, body bytes, duration and client address.
+//!
+//! Each request gets a correlation ID, taken from its `X-Request-Id` header
+//! when the client sent a usable one and made up otherwise, and sent back in
+//! the same header. Handlers run inside a `request` span carrying the ID, and
+//! [`spawn_blocking`] and background jobs take that span with them, so the
+//! FileStore's own logs for a slow mount read can be found by its ID.
+
+use bytes::Bytes;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use http_body_util::combinators::BoxBody;
+use poem::http::HeaderValue;
+use poem::{Body, Endpoint, IntoResponse, Middleware, Request, Response};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Span};
+
+pub use crate::utils::REQUEST_ID_HEADER;
+
+/// Longest client-supplied ID that is kept.
+const MAX_ID_LEN: usize = 64;
+
+/// Running totals over every request a [`RequestLog`] has seen.
+#[derive(Debug)]
+pub struct ServerStats {
+    started: Instant,
+    requests: AtomicU64,
+    in_flight: AtomicU64,
+    bytes: AtomicU64,
+    server_errors: AtomicU64,
+    aborted: AtomicU64,
+    dropped: AtomicU64,
+    /// Nanoseconds after `started` that shutdown gives up waiting, 0 while
+    /// serving normally.
+    drain_deadline: AtomicU64,
+}
+
+impl ServerStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            server_errors: AtomicU64::new(0),
+            aborted: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            drain_deadline: AtomicU64::new(0),
+        }
+    }
+
+    /// Requests whose response hasn't been sent in full yet.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Marks the start of shutdown. Requests still unfinished `timeout` from
+    /// now are counted as dropped by the server rather than by their client.
+    pub fn start_drain(&self, timeout: Duration) {
+        let deadline = self.started.elapsed() + timeout;
+        self.drain_deadline
+            .store(deadline.as_nanos().max(1) as u64, Ordering::Relaxed);
+    }
+
+    /// Requests the server gave up on when the drain timed out.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// One line on everything served so far, for the shutdown log.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} requests in {:?}, {} body bytes sent, {} server errors, {} cut off by the client, {} dropped at shutdown",
+            self.requests.load(Ordering::Relaxed),
+            self.started.elapsed(),
+            self.bytes.load(Ordering::Relaxed),
+            self.server_errors.load(Ordering::Relaxed),
+            self.aborted.load(Ordering::Relaxed),
+            self.dropped(),
+        )
+    }
+
+    fn past_drain_deadline(&self) -> bool {
+        let deadline = self.drain_deadline.load(Ordering::Relaxed);
+        deadline > 0 && self.started.elapsed().as_nanos() as u64 >= deadline
+    }
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware logging every request it wraps, see the module docs.
+#[derive(Debug, Clone, Default)]
+pub struct RequestLog {
+    stats: Arc<ServerStats>,
+}
+
+impl RequestLog {
+    /// Logs requests and counts them in `stats`.
+    pub fn new(stats: Arc<ServerStats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequestLog {
+    type Output = RequestLogEndpoint<E>;
+
+    fn transform(&self, inner: E) -> Self::Output {
+        RequestLogEndpoint {
+            inner,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+pub struct RequestLogEndpoint<E> {
+    inner: E,
+    stats: Arc<ServerStats>,
+}
+
+impl<E: Endpoint> Endpoint for RequestLogEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let started = Instant::now();
+        let id = req
+            .header(REQUEST_ID_HEADER)
+            .filter(|id| usable_id(id))
+            .map(str::to_string)
+            .unwrap_or_else(new_id);
+        let span = tracing::info_span!("request", id = %id);
+        let mut entry = Entry {
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+            client: req
+                .remote_addr()
+                .as_socket_addr()
+                .map_or_else(|| req.remote_addr().to_string(), |addr| addr.to_string()),
+            status: 0,
+            bytes: 0,
+            complete: false,
+            started,
+            span: span.clone(),
+            stats: self.stats.clone(),
+        };
+        self.stats.requests.fetch_add(1, Ordering::Relaxed);
+        self.stats.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        let mut response = match self.inner.call(req).instrument(span).await {
+            Ok(response) => response.into_response(),
+            Err(e) => e.into_response(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&id) {
+            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        entry.status = response.status().as_u16();
+        let body: BoxBody<Bytes, io::Error> = response.take_body().into();
+        entry.complete = body.is_end_stream();
+        response.set_body(Body::from(BoxBody::new(Counted { body, entry })));
+        Ok(response)
+    }
+}
+
+/// Runs `work` on tokio's blocking pool inside the current span, so its logs
+/// keep the ID of the request that started it.
+pub fn spawn_blocking<F, R>(work: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let span = Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(work))
+}
+
+/// Whether a client's ID is short and plain enough to log and echo back.
+fn usable_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+fn new_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// What is known about a request so far, logged when it is dropped.
+struct Entry {
+    method: String,
+    path: String,
+    client: String,
+    status: u16,
+    bytes: u64,
+    /// Whether the whole body was sent, a client that hangs up leaves it false.
+    complete: bool,
+    started: Instant,
+    span: Span,
+    stats: Arc<ServerStats>,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        let stats = &self.stats;
+        stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+        stats.bytes.fetch_add(self.bytes, Ordering::Relaxed);
+        if self.status >= 500 {
+            stats.server_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if !self.complete {
+            let counter = if stats.past_drain_deadline() {
+                &stats.dropped
+            } else {
+                &stats.aborted
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        tracing::info!(
+            parent: &self.span,
+            method = %self.method,
+            path = %self.path,
+            status = self.status,
+            bytes = self.bytes,
+            duration_ms = elapsed.as_millis() as u64,
+            client = %self.client,
+            complete = self.complete,
+            "SERVE | {} {} {} {}B in {:?}{}",
+            self.method,
+            self.path,
+            self.status,
+            self.bytes,
+            elapsed,
+            if self.complete { "" } else { " (aborted)" }
+        );
+    }
+}
+
+/// A response body that counts what passes through it and logs its request
+/// once it is done with. The size hint is passed on, so `Content-Length` is
+/// still sent.
+struct Counted {
+    body: BoxBody<Bytes, io::Error>,
+    entry: Entry,
+}
+
+impl HttpBody for Counted {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let polled = Pin::new(&mut self.body).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.entry.bytes += data.len() as u64;
+                }
+                // a body of known length isn't polled again after its last frame
+                self.entry.complete = self.body.is_end_stream();
+            }
+            Poll::Ready(None) => self.entry.complete = true,
+            _ => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poem::EndpointExt;
+
+    #[tokio::test]
+    async fn test_request_ids_are_kept_or_made_up() {
+        let stats = Arc::new(ServerStats::new());
+        let app = poem::endpoint::make_sync(|_| "hello").with(RequestLog::new(stats.clone()));
+
+        let response = app
+            .get_response(
+                Request::builder()
+                    .header(REQUEST_ID_HEADER, "mount-42")
+                    .finish(),
+            )
+            .await;
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "mount-42");
+        assert_eq!(response.into_body().into_string().await.unwrap(), "hello");
+
+        let response = app
+            .get_response(
+                Request::builder()
+                    .header(REQUEST_ID_HEADER, "has spaces\tand tabs")
+                    .finish(),
+            )
+            .await;
+        let id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert_eq!(id.len(), 16);
+        assert!(usable_id(id));
+
+        // the first body was read to the end, the second one never was
+        drop(response);
+        assert_eq!(stats.requests.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.in_flight(), 0);
+        assert_eq!(stats.bytes.load(Ordering::Relaxed), 5);
+        assert_eq!(stats.aborted.load(Ordering::Relaxed), 1);
+    }
+}
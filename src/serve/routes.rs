@@ -1,33 +1,177 @@
-use parking_lot::RwLock;
-use poem::http::StatusCode;
+use futures_util::stream::BoxStream;
+use poem::{Body, http::StatusCode};
 use poem_openapi::{
-    Object, OpenApi,
+    ApiResponse, Object, OpenApi,
+    param::Header,
     param::Path,
     param::Query,
-    payload::{Binary, Json},
+    payload::{Binary, EventStream, Json},
     types::ToJSON,
 };
 use serde_json::json;
-use std::{fs, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use super::content_type;
+use super::events::{ArchiveEvent, EventBus};
+use super::jobs::{JobContext, JobRegistry, JobStatus};
+use super::request_log;
+use crate::chunker::Chunker;
+use crate::filestore::{
+    FileReader, FileStore,
+    history::HistorySummary,
+    models::{File, HealthStatus},
+    query::{FileQuery, SortKey},
+};
+use crate::tags::TagFilter;
+use crate::utils::hash_file_streaming;
+
+pub use crate::filestore::compact::UPLOAD_DIR_PREFIX;
 
-use crate::filestore::FileStore;
+/// Bytes read from the archive per chunk of a download.
+const DOWNLOAD_CHUNK: usize = 1 << 20;
 
 #[derive(Object)]
 pub struct FileInfo {
     name: String,
     size: i64,
     tier: u8,
+    /// Set when the file is a duplicate recorded as an alias of this file.
+    alias_of: Option<String>,
+    /// Tags given at commit time.
+    #[oai(skip_serializing_if_is_empty)]
+    tags: BTreeMap<String, String>,
+}
+
+/// A file committed through `PUT /files/:filename`.
+#[derive(Object)]
+pub struct Uploaded {
+    name: String,
+    hash: String,
+    size: u64,
+}
+
+/// A page of the file list, or 304 when the client's copy is still current.
+#[derive(ApiResponse)]
+pub enum FileListResponse {
+    #[oai(status = 200)]
+    Ok(
+        Json<Vec<FileInfo>>,
+        #[oai(header = "ETag")] String,
+        /// Files matching the filters, of which the body is one page.
+        #[oai(header = "X-Total-Count")]
+        u64,
+    ),
+    #[oai(status = 304)]
+    NotModified,
+}
+
+/// A manifest and its signature, or 304 when the client's copy is still current.
+#[derive(ApiResponse)]
+pub enum ManifestResponse {
+    #[oai(status = 200)]
+    Ok(Json<serde_json::Value>, #[oai(header = "ETag")] String),
+    #[oai(status = 304)]
+    NotModified,
+}
+
+/// An original file, typed and named so a browser saves it as itself.
+#[derive(ApiResponse)]
+pub enum DownloadResponse {
+    #[oai(status = 200)]
+    Ok(
+        Binary<Body>,
+        #[oai(header = "Content-Type")] String,
+        #[oai(header = "Content-Disposition")] String,
+    ),
+}
+
+#[derive(ApiResponse)]
+pub enum UploadResponse {
+    /// The upload was committed.
+    #[oai(status = 200)]
+    Committed(Json<Uploaded>),
+    /// The upload was received and a job is committing it.
+    #[oai(status = 202)]
+    Started(Json<JobStatus>),
+}
+
+/// Outcome of checking one file's shards against its manifest.
+#[derive(Object)]
+pub struct FileHealth {
+    name: String,
+    /// `healthy`, `degraded`, `recoverable` or `unrecoverable`.
+    status: String,
+    recoverable: bool,
+    missing_data: Vec<String>,
+    missing_parity: Vec<String>,
+    corrupt_segments: Vec<String>,
+    details: String,
+}
+
+/// A file's recorded health checks, oldest first, and what they add up to.
+#[derive(Object)]
+pub struct FileHealthHistory {
+    name: String,
+    checks: u64,
+    /// Checks that found the file anything but healthy.
+    unhealthy: u64,
+    /// Separate runs of unhealthy checks, with a healthy check between them.
+    incidents: u64,
+    /// Most shards found missing or corrupt in a single check.
+    worst_damage: u64,
+    /// The latest incident is ongoing and each check found more damage.
+    deteriorating: bool,
+    records: Vec<HealthHistoryRecord>,
+}
+
+/// One recorded health check of a file.
+#[derive(Object)]
+pub struct HealthHistoryRecord {
+    /// RFC 3339 time of the check.
+    timestamp: String,
+    /// `healthy`, `degraded`, `recoverable` or `unrecoverable`.
+    status: String,
+    missing_data: u64,
+    missing_parity: u64,
+    corrupt: u64,
+}
+
+/// BLAKE3 hash and size of a single shard as stored on the server.
+#[derive(Object)]
+pub struct ShardHash {
+    hash: String,
+    size: u64,
 }
 
 pub struct BlockframeApi {
-    store: Arc<RwLock<FileStore>>,
+    store: Arc<FileStore>,
+    events: EventBus,
+    /// Commits uploads, which are refused without one.
+    uploads: Option<Arc<Chunker>>,
+    jobs: JobRegistry,
 }
 impl BlockframeApi {
-    pub fn new(store: FileStore) -> Self {
+    pub fn new(store: Arc<FileStore>, events: EventBus) -> Self {
         Self {
-            store: Arc::new(RwLock::new(store)),
+            store,
+            events,
+            uploads: None,
+            jobs: JobRegistry::new(),
         }
     }
+
+    /// Accepts uploads and commits them with `chunker`.
+    pub fn with_uploads(mut self, chunker: Option<Arc<Chunker>>) -> Self {
+        self.uploads = chunker;
+        self
+    }
 }
 
 #[OpenApi]
@@ -41,30 +185,95 @@ impl BlockframeApi {
         tracing::error!("{}: {}", msg, err);
         poem::Error::from_string(err.to_string(), status)
     }
-    // list all files in archive
+    // list files in archive, optionally filtered, sorted and paged
+    #[allow(clippy::too_many_arguments)]
     #[oai(path = "/files", method = "get")]
-    async fn list_files(&self) -> Result<Json<Vec<FileInfo>>, poem::Error> {
-        tracing::info!("API | GET /files - listing all files");
-        let store = self.store.read();
-        let files = store.get_all().map_err(|err: Box<dyn std::error::Error>| {
+    async fn list_files(
+        &self,
+        offset: Query<Option<usize>>,
+        limit: Query<Option<usize>>,
+        name: Query<Option<String>>,
+        glob: Query<Option<String>>,
+        ignore_case: Query<Option<bool>>,
+        tier: Query<Option<u8>>,
+        tag: Query<Vec<String>>,
+        sort: Query<Option<String>>,
+        order: Query<Option<String>>,
+        #[oai(name = "If-None-Match")] if_none_match: Header<Option<String>>,
+    ) -> Result<FileListResponse, poem::Error> {
+        tracing::info!("API | GET /files - listing files");
+        let bad_request = |err: String| poem::Error::from_string(err, StatusCode::BAD_REQUEST);
+        let sort = match sort.0.as_deref() {
+            Some(key) => key.parse::<SortKey>().map_err(bad_request)?,
+            None => SortKey::default(),
+        };
+        let descending = match order.0.as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => {
+                return Err(bad_request(format!(
+                    "unknown order '{}', expected asc or desc",
+                    other
+                )));
+            }
+        };
+        let mut query = FileQuery::default()
+            .with_sort(sort, descending)
+            .with_page(offset.0.unwrap_or(0), limit.0);
+        if let Some(needle) = name.0 {
+            query = query.with_name(needle);
+        }
+        if let Some(pattern) = glob.0 {
+            query = query.with_glob(&pattern).map_err(bad_request)?;
+        }
+        if ignore_case.0.unwrap_or(false) {
+            query = query.ignoring_case();
+        }
+        if let Some(tier) = tier.0 {
+            query = query.with_tier(tier);
+        }
+        for tag in tag.0 {
+            query = query.with_tag(tag.parse::<TagFilter>().map_err(bad_request)?);
+        }
+
+        let (total, files) = self.store.query(&query).map_err(|err| {
             self.io_to_poem(
                 err,
                 "Failed to fetch files",
                 StatusCode::INTERNAL_SERVER_ERROR,
             )
-        })?; // <--- The '?' operator propagates the error to the endpoint return type
-
-        tracing::info!("API | returning {} files", files.len());
-        Ok(Json(
-            files
-                .iter()
-                .map(|f| FileInfo {
-                    name: f.file_name.clone(),
-                    size: f.manifest.size,
-                    tier: f.manifest.tier,
-                })
-                .collect(),
-        ))
+        })?;
+
+        let files: Vec<FileInfo> = files
+            .iter()
+            .map(|f| FileInfo {
+                name: f.file_name.clone(),
+                size: f.manifest.size,
+                tier: f.manifest.tier,
+                alias_of: f.alias_of.clone(),
+                tags: f.manifest.tags.clone(),
+            })
+            .collect();
+        // the total is part of the tag, a page can stay the same while it changes
+        let tag = etag(&json!({ "total": total, "files": files.to_json() }));
+        if matches_etag(if_none_match.0.as_deref(), &tag) {
+            return Ok(FileListResponse::NotModified);
+        }
+
+        tracing::info!("API | returning {} of {} files", files.len(), total);
+        Ok(FileListResponse::Ok(Json(files), tag, total as u64))
+    }
+
+    // server-sent events for commits, repairs and removals
+    #[oai(path = "/events", method = "get")]
+    async fn events(&self) -> EventStream<BoxStream<'static, ArchiveEvent>> {
+        tracing::info!("API | GET /events - client subscribed");
+        EventStream::new(self.events.subscribe())
+            .to_event(|event| {
+                let kind = event.kind.clone();
+                poem::web::sse::Event::message(event.to_json_string()).event_type(kind)
+            })
+            .keep_alive(Duration::from_secs(15))
     }
 
     // get file manifest
@@ -72,11 +281,12 @@ impl BlockframeApi {
     async fn get_manifest(
         &self,
         filename: Path<String>,
-    ) -> Result<Json<serde_json::Value>, poem::Error> {
+        #[oai(name = "If-None-Match")] if_none_match: Header<Option<String>>,
+    ) -> Result<ManifestResponse, poem::Error> {
         tracing::info!("API | GET /files/{}/manifest", filename.0);
         // return manifest.json content
-        let store = self.store.read();
-        let manifest = store
+        let store = &self.store;
+        let file_obj = store
             .find(&filename)
             .map_err(|err: Box<dyn std::error::Error>| {
                 self.io_to_poem(
@@ -84,30 +294,40 @@ impl BlockframeApi {
                     &format!("Failed to find file {}", filename.0),
                     StatusCode::NOT_FOUND,
                 )
-            })?
-            .manifest;
+            })?;
+        // clients verify the signature themselves, the server just passes it along
+        let signature = store.manifest_signature(&file_obj);
+
+        let body = json!({
+            "manifest": file_obj.manifest,
+            "signature": signature
+        })
+        .to_json()
+        .ok_or_else(|| {
+            let err = std::io::Error::other("JSON serialization failed");
+            self.io_to_poem(
+                Box::new(err),
+                &format!("Failed to serialize manifest for file {}", filename.0),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        let tag = etag(&body);
+        if matches_etag(if_none_match.0.as_deref(), &tag) {
+            return Ok(ManifestResponse::NotModified);
+        }
 
         tracing::info!("API | returning manifest for: {}", filename.0);
-        Ok(Json(
-            json!({
-                "manifest": manifest
-            })
-            .to_json()
-            .ok_or_else(|| {
-                let err = std::io::Error::other("JSON serialization failed");
-                self.io_to_poem(
-                    Box::new(err),
-                    &format!("Failed to serialize manifest for file {}", filename.0),
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                )
-            })?,
-        ))
+        Ok(ManifestResponse::Ok(Json(body), tag))
     }
-    // get segment data
+    // get segment data, from `offset` on to resume an interrupted download
     #[oai(path = "/files/:filename", method = "get")]
-    async fn get_data(&self, filename: Path<String>) -> Result<Binary<Vec<u8>>, poem::Error> {
+    async fn get_data(
+        &self,
+        filename: Path<String>,
+        offset: Query<Option<u64>>,
+    ) -> Result<Binary<Vec<u8>>, poem::Error> {
         tracing::info!("API | GET /files/{}", filename.0);
-        let store = self.store.read();
+        let store = &self.store;
 
         let file_obj = store
             .find(&filename)
@@ -134,7 +354,269 @@ impl BlockframeApi {
             )
         })?;
 
-        Ok(Binary(file_bytes))
+        from_offset(file_bytes, offset.0)
+    }
+
+    // download the original file, whatever its tier, from `offset` on
+    #[oai(path = "/files/:filename/download", method = "get")]
+    async fn download(
+        &self,
+        filename: Path<String>,
+        offset: Query<Option<u64>>,
+    ) -> Result<DownloadResponse, poem::Error> {
+        tracing::info!(
+            "API | GET /files/{}/download (offset: {:?})",
+            filename.0,
+            offset.0
+        );
+        let file_obj = self.find_file(&self.store, &filename.0)?;
+        let mut reader = self.store.open_reader(&file_obj).map_err(|err| {
+            self.io_to_poem(
+                err,
+                &format!("Failed to open file {}", filename.0),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        // names without a known extension are typed by their first bytes
+        let head = if content_type::needs_sniffing(&filename.0) {
+            let (returned, head) = request_log::spawn_blocking(move || {
+                let mut head = Vec::new();
+                let read = (&mut reader)
+                    .take(content_type::SNIFF_LEN as u64)
+                    .read_to_end(&mut head);
+                (reader, read.map(|_| head))
+            })
+            .await
+            .map_err(|e| {
+                poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+            reader = returned;
+            head.ok()
+        } else {
+            None
+        };
+        let kind = content_type::content_type(&filename.0, head.as_deref());
+
+        let offset = offset.0.unwrap_or(0);
+        if offset > reader.len() {
+            return Err(poem::Error::from_string(
+                format!("offset {} is past the end of {}", offset, filename.0),
+                StatusCode::RANGE_NOT_SATISFIABLE,
+            ));
+        }
+        reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(|err| poem::Error::from_string(err.to_string(), StatusCode::BAD_REQUEST))?;
+        Ok(DownloadResponse::Ok(
+            Binary(stream_reader(reader, u64::MAX)),
+            kind,
+            content_type::attachment(&filename.0),
+        ))
+    }
+
+    // upload a file and commit it, only when the server was started with uploads on.
+    // with `background` the commit runs as a job and its status comes back at once
+    #[oai(path = "/files/:filename", method = "put")]
+    async fn upload(
+        &self,
+        filename: Path<String>,
+        background: Query<Option<bool>>,
+        body: Binary<Body>,
+    ) -> Result<UploadResponse, poem::Error> {
+        tracing::info!("API | PUT /files/{}", filename.0);
+        let Some(chunker) = &self.uploads else {
+            return Err(poem::Error::from_string(
+                "uploads are disabled on this server",
+                StatusCode::FORBIDDEN,
+            ));
+        };
+        let name = filename.0;
+        if name.starts_with('.') || std::path::Path::new(&name).file_name() != Some(name.as_ref()) {
+            return Err(poem::Error::from_string(
+                format!("invalid file name {:?}", name),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        // spool the body into a hidden work dir in the archive, then commit from there
+        let internal = |err: std::io::Error| {
+            self.io_to_poem(
+                Box::new(err),
+                &format!("Failed to receive {}", name),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        };
+        fs::create_dir_all(chunker.archive_dir()).map_err(internal)?;
+        let work = tempfile::Builder::new()
+            .prefix(UPLOAD_DIR_PREFIX)
+            .tempdir_in(chunker.archive_dir())
+            .map_err(internal)?;
+        let path = work.path().join(&name);
+        let mut file = tokio::fs::File::create(&path).await.map_err(internal)?;
+        let mut reader = body.0.into_async_read();
+        let received = tokio::io::copy(&mut reader, &mut file)
+            .await
+            .map_err(internal)?;
+        drop(file);
+
+        if background.0.unwrap_or(false) {
+            let (chunker, store) = (chunker.clone(), self.store.clone());
+            let job = self
+                .jobs
+                .spawn("upload", Some(name), move |ctx| async move {
+                    // the work dir goes once the commit is done with it
+                    let _work = work;
+                    ctx.progress(received, Some(received));
+                    ctx.log(format!("received {} bytes, committing", received));
+                    let handle = chunker.commit_async(&path, None);
+                    ctx.link(handle.cancel_token());
+                    let committed = handle.await.map_err(|e| e.to_string())?;
+                    store.invalidate();
+                    Ok(format!("committed as {}", committed.file_hash))
+                });
+            return Ok(UploadResponse::Started(Json(job)));
+        }
+
+        // dropping the handle when the client goes away cancels the commit
+        let committed = chunker.commit_async(&path, None).await.map_err(|err| {
+            tracing::error!("API | commit of upload {} failed: {}", name, err);
+            poem::Error::from_string(err.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        self.store.invalidate();
+        Ok(UploadResponse::Committed(Json(Uploaded {
+            name: committed.file_name,
+            hash: committed.file_hash,
+            size: committed.file_size as u64,
+        })))
+    }
+
+    // repair one file in a background job
+    #[oai(path = "/files/:filename/repair", method = "post")]
+    async fn repair(&self, filename: Path<String>) -> Result<Json<JobStatus>, poem::Error> {
+        tracing::info!("API | POST /files/{}/repair", filename.0);
+        let file_obj = self.find_file(&self.store, &filename.0)?;
+        let store = self.store.clone();
+        let job = self
+            .jobs
+            .spawn("repair", Some(filename.0), move |ctx| async move {
+                blocking(move || {
+                    ctx.progress(0, Some(1));
+                    store
+                        .repair(&file_obj)
+                        .map_err(|e| format!("repair failed: {}", e))?;
+                    ctx.progress(1, Some(1));
+                    Ok("repaired".to_string())
+                })
+                .await
+            });
+        Ok(Json(job))
+    }
+
+    // check one file's shards against its manifest, without repairing anything
+    #[oai(path = "/files/:filename/health", method = "get")]
+    async fn health(&self, filename: Path<String>) -> Result<Json<FileHealth>, poem::Error> {
+        tracing::info!("API | GET /files/{}/health", filename.0);
+        let file_obj = self.find_file(&self.store, &filename.0)?;
+        let store = self.store.clone();
+        // every shard is hashed, so keep it off the async runtime
+        let report = request_log::spawn_blocking(move || {
+            let report = store.health_check(&file_obj).map_err(|e| e.to_string())?;
+            store.try_record_health([(file_obj.file_name.as_str(), &report)]);
+            Ok::<_, String>(report)
+        })
+        .await
+        .map_err(|e| poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?
+        .map_err(|e| {
+            tracing::error!("Failed to check {}: {}", filename.0, e);
+            poem::Error::from_string(e, StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        Ok(Json(FileHealth {
+            name: filename.0,
+            status: status_name(report.status),
+            recoverable: report.recoverable,
+            missing_data: report.missing_data,
+            missing_parity: report.missing_parity,
+            corrupt_segments: report.corrupt_segments,
+            details: report.details,
+        }))
+    }
+
+    // recorded health check outcomes for one file, oldest first
+    #[oai(path = "/files/:filename/history", method = "get")]
+    async fn health_history(
+        &self,
+        filename: Path<String>,
+        limit: Query<Option<usize>>,
+    ) -> Result<Json<FileHealthHistory>, poem::Error> {
+        tracing::info!("API | GET /files/{}/history", filename.0);
+        self.find_file(&self.store, &filename.0)?;
+        let store = self.store.clone();
+        let name = filename.0.clone();
+        let records = request_log::spawn_blocking(move || {
+            store
+                .health_history(&name, limit.0)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?
+        .map_err(|e| poem::Error::from_string(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+        let summary = HistorySummary::of(&records);
+        Ok(Json(FileHealthHistory {
+            name: filename.0,
+            checks: summary.checks as u64,
+            unhealthy: summary.unhealthy as u64,
+            incidents: summary.incidents as u64,
+            worst_damage: summary.worst_damage as u64,
+            deteriorating: summary.deteriorating,
+            records: records
+                .into_iter()
+                .map(|record| HealthHistoryRecord {
+                    timestamp: record.timestamp.to_rfc3339(),
+                    status: status_name(record.status),
+                    missing_data: record.missing_data as u64,
+                    missing_parity: record.missing_parity as u64,
+                    corrupt: record.corrupt as u64,
+                })
+                .collect(),
+        }))
+    }
+
+    // check every file and repair the ones that need it, in a background job
+    #[oai(path = "/jobs/scrub", method = "post")]
+    async fn start_scrub(&self) -> Json<JobStatus> {
+        tracing::info!("API | POST /jobs/scrub");
+        let store = self.store.clone();
+        Json(self.jobs.spawn("scrub", None, move |ctx| async move {
+            blocking(move || scrub_job(&store, &ctx)).await
+        }))
+    }
+
+    // every job the server still remembers, oldest first
+    #[oai(path = "/jobs", method = "get")]
+    async fn list_jobs(&self) -> Json<Vec<JobStatus>> {
+        Json(self.jobs.list())
+    }
+
+    #[oai(path = "/jobs/:id", method = "get")]
+    async fn get_job(&self, id: Path<u64>) -> Result<Json<JobStatus>, poem::Error> {
+        self.jobs
+            .get(id.0)
+            .map(Json)
+            .ok_or_else(|| no_such_job(id.0))
+    }
+
+    // ask a job to stop, it is marked cancelled once it does
+    #[oai(path = "/jobs/:id/cancel", method = "post")]
+    async fn cancel_job(&self, id: Path<u64>) -> Result<Json<JobStatus>, poem::Error> {
+        tracing::info!("API | POST /jobs/{}/cancel", id.0);
+        let job = self.jobs.cancel(id.0).ok_or_else(|| no_such_job(id.0))?;
+        if job.state.is_finished() {
+            return Err(poem::Error::from_string(
+                format!("job {} already finished", id.0),
+                StatusCode::CONFLICT,
+            ));
+        }
+        Ok(Json(job))
     }
 
     // get segment data
@@ -143,9 +625,10 @@ impl BlockframeApi {
         &self,
         filename: Path<String>,
         segment_id: Path<usize>,
+        offset: Query<Option<u64>>,
     ) -> Result<Binary<Vec<u8>>, poem::Error> {
         tracing::info!("API | GET /files/{}/segment/{}", filename.0, segment_id.0);
-        let store = self.store.read();
+        let store = &self.store;
 
         let file_obj = store
             .find(&filename)
@@ -180,7 +663,7 @@ impl BlockframeApi {
                 StatusCode::NOT_FOUND,
             )
         })?;
-        Ok(Binary(file_bytes))
+        from_offset(file_bytes, offset.0)
     }
 
     // get block segment (Tier 3)
@@ -194,6 +677,7 @@ impl BlockframeApi {
         filename: Path<String>,
         block_id: Path<usize>,
         segment_id: Path<usize>,
+        offset: Query<Option<u64>>,
     ) -> Result<Binary<Vec<u8>>, poem::Error> {
         tracing::info!(
             "API | GET /files/{}/block/{}/segment/{}",
@@ -202,7 +686,7 @@ impl BlockframeApi {
             segment_id.0
         );
         // read and return segment bytes
-        let store = self.store.read();
+        let store = &self.store;
 
         let file_obj = store
             .find(&filename)
@@ -229,94 +713,226 @@ impl BlockframeApi {
             )
         })?;
 
-        Ok(Binary(file_bytes))
+        from_offset(file_bytes, offset.0)
     }
 
     // get parity shard
-    #[oai(path = "/files/:filename/parity/", method = "get")]
+    #[oai(path = "/files/:filename/parity", method = "get")]
     async fn get_parity(
         &self,
         filename: Path<String>,
         block_id: Query<Option<usize>>,
         segment_id: Query<Option<usize>>,
         parity_id: Query<Option<usize>>,
+        offset: Query<Option<u64>>,
     ) -> Result<Binary<Vec<u8>>, poem::Error> {
         tracing::info!(
-            "API | GET /files/{}/parity/ (tier {}, parity_id: {:?})",
+            "API | GET /files/{}/parity (parity_id: {:?})",
             filename.0,
-            "", // will be determined below
             parity_id.0
         );
-        let store = self.store.read();
+        let parity_path = self.parity_path(&filename, block_id.0, segment_id.0, parity_id.0)?;
+        let parity_bytes = fs::read(parity_path).map_err(|err: std::io::Error| {
+            tracing::error!("Failed to find parity for file {}: {}", filename.0, err);
+            poem::Error::from_string(err.to_string(), StatusCode::NOT_FOUND)
+        })?;
+        from_offset(parity_bytes, offset.0)
+    }
 
-        let file_obj = store
-            .find(&filename)
-            .map_err(|err: Box<dyn std::error::Error>| {
-                tracing::error!("Failed to find file {}: {}", filename.0, err);
-                poem::Error::from_string(err.to_string(), StatusCode::NOT_FOUND)
+    // hash of tier 1 data.dat
+    #[oai(path = "/files/:filename/hash", method = "get")]
+    async fn get_data_hash(&self, filename: Path<String>) -> Result<Json<ShardHash>, poem::Error> {
+        tracing::info!("API | GET /files/{}/hash", filename.0);
+        let store = &self.store;
+        let file_obj = self.find_file(store, &filename)?;
+        let data_path = store.get_data_path(&file_obj).map_err(|err| {
+            self.io_to_poem(
+                Box::new(err),
+                &format!("Invalid data path for file {}", filename.0),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        self.hash_shard(&data_path)
+    }
+
+    // hash of a tier 2 segment
+    #[oai(path = "/files/:filename/segment/:segment_id/hash", method = "get")]
+    async fn get_segment_hash(
+        &self,
+        filename: Path<String>,
+        segment_id: Path<usize>,
+    ) -> Result<Json<ShardHash>, poem::Error> {
+        tracing::info!(
+            "API | GET /files/{}/segment/{}/hash",
+            filename.0,
+            segment_id.0
+        );
+        let store = &self.store;
+        let file_obj = self.find_file(store, &filename)?;
+        let segment_path = store
+            .get_segment_path(&file_obj, segment_id.0)
+            .map_err(|err| {
+                self.io_to_poem(
+                    Box::new(err),
+                    &format!("Failed to get segment path for file {}", filename.0),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
             })?;
+        self.hash_shard(&segment_path)
+    }
+
+    // hash of a tier 3 block segment
+    #[oai(
+        path = "/files/:filename/block/:block_id/segment/:segment_id/hash",
+        method = "get"
+    )]
+    async fn get_block_segment_hash(
+        &self,
+        filename: Path<String>,
+        block_id: Path<usize>,
+        segment_id: Path<usize>,
+    ) -> Result<Json<ShardHash>, poem::Error> {
+        tracing::info!(
+            "API | GET /files/{}/block/{}/segment/{}/hash",
+            filename.0,
+            block_id.0,
+            segment_id.0
+        );
+        let store = &self.store;
+        let file_obj = self.find_file(store, &filename)?;
+        let block_segment_path = store
+            .get_block_segment_path(&file_obj, block_id.0, segment_id.0)
+            .map_err(|err| {
+                self.io_to_poem(
+                    Box::new(err),
+                    &format!("Failed to get block segment path for file {}", filename.0),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+        self.hash_shard(&block_segment_path)
+    }
+
+    // hash of a parity shard, same query parameters as /parity
+    #[oai(path = "/files/:filename/parity/hash", method = "get")]
+    async fn get_parity_hash(
+        &self,
+        filename: Path<String>,
+        block_id: Query<Option<usize>>,
+        segment_id: Query<Option<usize>>,
+        parity_id: Query<Option<usize>>,
+    ) -> Result<Json<ShardHash>, poem::Error> {
+        tracing::info!(
+            "API | GET /files/{}/parity/hash (parity_id: {:?})",
+            filename.0,
+            parity_id.0
+        );
+        let parity_path = self.parity_path(&filename, block_id.0, segment_id.0, parity_id.0)?;
+        self.hash_shard(&parity_path)
+    }
+}
+
+impl BlockframeApi {
+    fn find_file(&self, store: &FileStore, filename: &str) -> Result<File, poem::Error> {
+        store
+            .find(&filename.to_string())
+            .map_err(|err: Box<dyn std::error::Error>| {
+                self.io_to_poem(
+                    err,
+                    &format!("Failed to find file {}", filename),
+                    StatusCode::NOT_FOUND,
+                )
+            })
+    }
+
+    /// Hashes a shard on disk without sending it, so remote health checks only
+    /// move 64 hex characters per shard instead of the whole segment.
+    fn hash_shard(&self, shard_path: &std::path::Path) -> Result<Json<ShardHash>, poem::Error> {
+        let size = fs::metadata(shard_path)
+            .map_err(|err| {
+                self.io_to_poem(
+                    Box::new(err),
+                    &format!("Failed to find shard {:?}", shard_path),
+                    StatusCode::NOT_FOUND,
+                )
+            })?
+            .len();
+        let hash = hash_file_streaming(shard_path).map_err(|err| {
+            self.io_to_poem(
+                Box::new(err),
+                &format!("Failed to hash shard {:?}", shard_path),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        Ok(Json(ShardHash { hash, size }))
+    }
+
+    /// Resolves the parity shard addressed by the `/parity` query parameters,
+    /// which differ by tier.
+    fn parity_path(
+        &self,
+        filename: &str,
+        block_id: Option<usize>,
+        segment_id: Option<usize>,
+        parity_id: Option<usize>,
+    ) -> Result<PathBuf, poem::Error> {
+        let store = &self.store;
+        let file_obj = self.find_file(store, filename)?;
 
         match file_obj.manifest.tier {
             1 => {
-                let parity_id = parity_id.0.ok_or_else(|| {
+                let parity_id = parity_id.ok_or_else(|| {
                     poem::Error::from_string("Missing parity_id", StatusCode::BAD_REQUEST)
                 })?;
-                let parity_path = store
-                    .get_parity_path_t1(&file_obj, parity_id)
-                    .map_err(|e| {
-                        poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
-                    })?;
-                let parity_bytes = fs::read(parity_path).map_err(|err: std::io::Error| {
-                    tracing::error!("Failed to find file {}: {}", filename.0, err);
-                    poem::Error::from_string(err.to_string(), StatusCode::NOT_FOUND)
-                })?;
-                Ok(Binary(parity_bytes))
+                store.get_parity_path_t1(&file_obj, parity_id).map_err(|e| {
+                    poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+                })
             }
             2 => {
-                let segment_id = segment_id.0.ok_or_else(|| {
+                let segment_id = segment_id.ok_or_else(|| {
                     poem::Error::from_string("Missing segment_id", StatusCode::BAD_REQUEST)
                 })?;
-                let parity_id = parity_id.0.ok_or_else(|| {
+                let parity_id = parity_id.ok_or_else(|| {
                     poem::Error::from_string("Missing parity_id", StatusCode::BAD_REQUEST)
                 })?;
-                let parity_path = store
+                store
                     .get_parity_path_t2(&file_obj, segment_id, parity_id)
                     .map_err(|e| {
                         poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
-                    })?;
-                let parity_bytes = fs::read(parity_path).map_err(|err: std::io::Error| {
-                    tracing::error!(
-                        "Failed to find segment {:?} for file {}: {}",
-                        segment_id,
-                        filename.0,
-                        err
-                    );
-                    poem::Error::from_string(err.to_string(), StatusCode::NOT_FOUND)
-                })?;
-                Ok(Binary(parity_bytes))
+                    })
             }
             3 => {
-                let block_id = block_id.0.ok_or_else(|| {
+                let block_id = block_id.ok_or_else(|| {
                     poem::Error::from_string("block_id is required", StatusCode::BAD_REQUEST)
                 })?;
-                let parity_id = parity_id.0.ok_or_else(|| {
+                let parity_id = parity_id.ok_or_else(|| {
                     poem::Error::from_string("Missing parity_id", StatusCode::BAD_REQUEST)
                 })?;
 
-                if (&file_obj.manifest.merkle_tree.leaves.len() as &usize) - 1 < block_id {
+                // tier 3 manifests key their hashes by block, leaves is empty
+                let blocks = &file_obj.manifest.merkle_tree.blocks;
+                if !blocks.is_empty() && !blocks.contains_key(&block_id) {
                     return Err(poem::Error::from_string(
                         "block_id is out of range",
                         StatusCode::BAD_REQUEST,
                     ));
                 }
 
-                if parity_id > 2 {
+                // a partial block has fewer parity shards than a full one, and
+                // local parity is numbered after the global shards
+                let parity_shards = blocks
+                    .get(&block_id)
+                    .map(|block| block.geometry().1 + block.local_parity.len())
+                    .unwrap_or(3);
+                if parity_id >= parity_shards {
                     return Err(poem::Error::from_string(
-                        "tier 3 files have only 3 parity shards. Parity index out of range",
+                        format!(
+                            "block {} has only {} parity shards. Parity index out of range",
+                            block_id, parity_shards
+                        ),
                         StatusCode::BAD_REQUEST,
                     ));
                 }
-                if let Some(sid) = segment_id.0
+                if let Some(sid) = segment_id
                     && sid > 30
                 {
                     return Err(poem::Error::from_string(
@@ -324,19 +940,142 @@ impl BlockframeApi {
                         StatusCode::BAD_REQUEST,
                     ));
                 }
-                let parity_path = store
+                store
                     .get_parity_path_t3(&file_obj, block_id, parity_id)
                     .map_err(|e| {
                         poem::Error::from_string(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
-                    })?;
-                let parity_bytes = fs::read(parity_path).map_err(|err: std::io::Error| {
-                    tracing::error!("Failed to find block segment {}: {}", filename.0, err);
-                    poem::Error::from_string(err.to_string(), StatusCode::NOT_FOUND)
-                })?;
+                    })
+            }
+            _ => Err(poem::Error::from_string(
+                "unknown tier",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )),
+        }
+    }
+}
+
+/// Strong ETag of a JSON response body.
+fn etag(body: &impl ToJSON) -> String {
+    format!(
+        "\"{}\"",
+        blake3::hash(body.to_json_string().as_bytes()).to_hex()
+    )
+}
+
+/// Streams up to `limit` bytes from where `reader` is. Shards are read and
+/// recovered on a blocking thread, a few chunks ahead of the client.
+pub(crate) fn stream_reader(reader: FileReader, limit: u64) -> Body {
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Vec<u8>>>(4);
+    request_log::spawn_blocking(move || {
+        let mut reader = reader.take(limit);
+        let mut buf = vec![0u8; DOWNLOAD_CHUNK];
+        loop {
+            let chunk = match reader.read(&mut buf) {
+                Ok(0) => return,
+                Ok(read) => Ok(buf[..read].to_vec()),
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if tx.blocking_send(chunk).is_err() || failed {
+                return;
+            }
+        }
+    });
+    let chunks = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Body::from_bytes_stream(chunks)
+}
 
-                Ok(Binary(parity_bytes))
+/// Whether an `If-None-Match` header names `tag`.
+fn matches_etag(if_none_match: Option<&str>, tag: &str) -> bool {
+    if_none_match.is_some_and(|header| {
+        header
+            .split(',')
+            .map(|candidate| candidate.trim().trim_start_matches("W/"))
+            .any(|candidate| candidate == tag || candidate == "*")
+    })
+}
+
+/// The shard `bytes` from `offset` on, for a client resuming a download.
+fn from_offset(mut bytes: Vec<u8>, offset: Option<u64>) -> Result<Binary<Vec<u8>>, poem::Error> {
+    let offset = offset.unwrap_or(0);
+    if offset > bytes.len() as u64 {
+        return Err(poem::Error::from_string(
+            format!("offset {} is past the end of the shard", offset),
+            StatusCode::RANGE_NOT_SATISFIABLE,
+        ));
+    }
+    bytes.drain(..offset as usize);
+    Ok(Binary(bytes))
+}
+
+/// `healthy`, `degraded`, `recoverable` or `unrecoverable`, as in the JSON.
+fn status_name(status: HealthStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|status| status.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn no_such_job(id: u64) -> poem::Error {
+    poem::Error::from_string(format!("no job {}", id), StatusCode::NOT_FOUND)
+}
+
+/// Runs a job's blocking work on tokio's blocking pool, in the span of the
+/// request that started it.
+async fn blocking<F>(work: F) -> Result<String, String>
+where
+    F: FnOnce() -> Result<String, String> + Send + 'static,
+{
+    request_log::spawn_blocking(work)
+        .await
+        .map_err(|e| format!("job task failed: {}", e))?
+}
+
+/// Health checks every file and repairs the unhealthy ones, stopping between
+/// files once cancelled.
+fn scrub_job(store: &FileStore, ctx: &JobContext) -> Result<String, String> {
+    let files: Vec<File> = store
+        .get_all()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|file| file.alias_of.is_none())
+        .collect();
+    let total = files.len() as u64;
+    let (mut repaired, mut failed) = (0, 0);
+    for (checked, file) in files.iter().enumerate() {
+        if ctx.is_cancelled() {
+            return Err(format!("cancelled after {} of {} files", checked, total));
+        }
+        ctx.progress(checked as u64, Some(total));
+        let healthy = store
+            .health_check(file)
+            .map(|report| report.status == HealthStatus::Healthy);
+        match healthy {
+            Ok(true) => continue,
+            Ok(false) => match store.repair(file) {
+                Ok(()) => {
+                    repaired += 1;
+                    ctx.log(format!("repaired {}", file.file_name));
+                }
+                Err(e) => {
+                    failed += 1;
+                    ctx.log(format!("repair of {} failed: {}", file.file_name, e));
+                }
+            },
+            Err(e) => {
+                failed += 1;
+                ctx.log(format!("health check of {} failed: {}", file.file_name, e));
             }
-            _ => Ok(Binary(vec![0])),
         }
     }
+    ctx.progress(total, Some(total));
+    match failed {
+        0 => Ok(format!("{} files checked, {} repaired", total, repaired)),
+        _ => Err(format!(
+            "{} files checked, {} repaired, {} failed",
+            total, repaired, failed
+        )),
+    }
 }
@@ -0,0 +1,15 @@
+//! A browse page for the archive at `/ui`, built with the `web-ui` feature.
+//!
+//! It is one self-contained HTML file compiled into the binary, talking to the
+//! JSON API: it lists the files with their size and tier, checks each one's
+//! health a few at a time, and offers downloads and repairs.
+
+use poem::handler;
+use poem::web::Html;
+
+pub const UI_PATH: &str = "/ui";
+
+#[handler]
+pub fn page() -> Html<&'static str> {
+    Html(include_str!("ui.html"))
+}
@@ -0,0 +1,351 @@
+//! A read-only WebDAV share of the archive, served at `/dav` by
+//! `serve --webdav`, so Windows ("Map network drive") and macOS ("Connect to
+//! Server") can open the archive without WinFsp or FUSE installed.
+//!
+//! Only what a read-only client needs is spoken: `OPTIONS`, `PROPFIND` at
+//! depth 0 or 1, and `GET`/`HEAD` with byte ranges, streamed through the same
+//! reader as `/api/files/<name>/download`. Anything that would change the
+//! archive gets `405 Method Not Allowed`. Like a mount, the share is one flat
+//! directory and a pack (see [`crate::pack`]) shows as the files inside it.
+
+use chrono::{DateTime, Utc};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use poem::http::{Method, StatusCode, header};
+use poem::{Endpoint, Request, Response};
+use std::collections::BTreeMap;
+use std::io::{Seek, SeekFrom};
+use std::sync::Arc;
+
+use super::content_type::content_type;
+use super::routes::stream_reader;
+use crate::filestore::FileStore;
+use crate::filestore::models::File;
+
+/// Where the share is nested in the server.
+pub const DAV_PATH: &str = "/dav";
+
+const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+/// Everything but unreserved characters is escaped in hrefs.
+const HREF: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A file in the share: the archive entry holding it and where in it it is.
+struct Resource {
+    entry: File,
+    offset: u64,
+    size: u64,
+}
+
+impl Resource {
+    fn etag(&self) -> String {
+        format!("\"{}-{}\"", self.entry.manifest.original_hash, self.offset)
+    }
+
+    fn modified(&self) -> DateTime<Utc> {
+        self.entry.committed.unwrap_or(DateTime::UNIX_EPOCH)
+    }
+}
+
+pub struct WebDav {
+    store: Arc<FileStore>,
+}
+
+impl WebDav {
+    pub fn new(store: Arc<FileStore>) -> Self {
+        Self { store }
+    }
+
+    /// The files in the share by name. A pack member named like another file
+    /// is left out, as it is on a mount.
+    fn resources(&self) -> poem::Result<BTreeMap<String, Resource>> {
+        let files = self.store.get_all().map_err(|err| {
+            tracing::error!("WEBDAV | cannot list archive: {}", err);
+            poem::Error::from_string(err.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        let mut resources = BTreeMap::new();
+        for entry in files {
+            let placements: Vec<(String, u64, u64)> = if entry.manifest.pack.is_empty() {
+                let size = entry.manifest.size.max(0) as u64;
+                vec![(entry.file_name.clone(), 0, size)]
+            } else {
+                entry
+                    .manifest
+                    .pack
+                    .iter()
+                    .map(|member| (member.name.clone(), member.offset, member.size))
+                    .collect()
+            };
+            for (name, offset, size) in placements {
+                resources.entry(name).or_insert(Resource {
+                    entry: entry.clone(),
+                    offset,
+                    size,
+                });
+            }
+        }
+        Ok(resources)
+    }
+
+    fn propfind(&self, name: &str, depth: Option<&str>) -> poem::Result<Response> {
+        let resources = self.resources()?;
+        let mut body = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+        );
+        if name.is_empty() {
+            let newest = resources.values().map(Resource::modified).max();
+            body.push_str(&collection_response(newest.unwrap_or(DateTime::UNIX_EPOCH)));
+            // the share is flat, so infinity lists no more than 1 does
+            if depth != Some("0") {
+                for (name, resource) in &resources {
+                    body.push_str(&file_response(name, resource));
+                }
+            }
+        } else {
+            let resource = resources.get(name).ok_or_else(|| not_found(name))?;
+            body.push_str(&file_response(name, resource));
+        }
+        body.push_str("</D:multistatus>\n");
+        Ok(Response::builder()
+            .status(StatusCode::MULTI_STATUS)
+            .content_type("application/xml; charset=utf-8")
+            .body(body))
+    }
+
+    fn get(&self, name: &str, range: Option<&str>, head: bool) -> poem::Result<Response> {
+        if name.is_empty() {
+            return Ok(not_allowed());
+        }
+        let resources = self.resources()?;
+        let resource = resources.get(name).ok_or_else(|| not_found(name))?;
+        let size = resource.size;
+
+        let response = Response::builder()
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::ETAG, resource.etag())
+            .header(header::LAST_MODIFIED, http_date(resource.modified()))
+            .content_type(content_type(name, None));
+        let (response, start, len) = match range.map(|range| parse_range(range, size)) {
+            Some(Ok(Some((start, end)))) => (
+                response.status(StatusCode::PARTIAL_CONTENT).header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, size),
+                ),
+                start,
+                end - start + 1,
+            ),
+            Some(Err(())) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                    .finish());
+            }
+            // no range, or one we don't serve (several at once): the whole file
+            _ => (response.status(StatusCode::OK), 0, size),
+        };
+        let response = response.header(header::CONTENT_LENGTH, len);
+        if head || len == 0 {
+            return Ok(response.finish());
+        }
+
+        let mut reader = self.store.open_reader(&resource.entry).map_err(|err| {
+            tracing::error!("WEBDAV | cannot open {}: {}", name, err);
+            poem::Error::from_string(err.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        reader
+            .seek(SeekFrom::Start(resource.offset + start))
+            .map_err(|err| poem::Error::from_string(err.to_string(), StatusCode::BAD_REQUEST))?;
+        Ok(response.body(stream_reader(reader, len)))
+    }
+}
+
+impl Endpoint for WebDav {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Response> {
+        let path = percent_decode_str(req.uri().path())
+            .decode_utf8()
+            .map_err(|err| poem::Error::from_string(err.to_string(), StatusCode::BAD_REQUEST))?;
+        let name = path.trim_start_matches('/');
+        tracing::info!("WEBDAV | {} /{}", req.method(), name);
+        let header_value = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+
+        match req.method().as_str() {
+            "OPTIONS" => Ok(Response::builder()
+                .header("DAV", "1")
+                .header("MS-Author-Via", "DAV")
+                .header(header::ALLOW, ALLOW)
+                .finish()),
+            "PROPFIND" => self.propfind(name, header_value("Depth")),
+            _ if req.method() == Method::GET || req.method() == Method::HEAD => self.get(
+                name,
+                header_value(header::RANGE.as_str()),
+                req.method() == Method::HEAD,
+            ),
+            _ => Ok(not_allowed()),
+        }
+    }
+}
+
+fn collection_response(modified: DateTime<Utc>) -> String {
+    format!(
+        "<D:response><D:href>{}/</D:href><D:propstat><D:prop>\
+         <D:displayname>blockframe</D:displayname>\
+         <D:resourcetype><D:collection/></D:resourcetype>\
+         <D:getlastmodified>{}</D:getlastmodified>\
+         </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+        DAV_PATH,
+        http_date(modified)
+    )
+}
+
+fn file_response(name: &str, resource: &Resource) -> String {
+    let modified = resource.modified();
+    format!(
+        "<D:response><D:href>{}/{}</D:href><D:propstat><D:prop>\
+         <D:displayname>{}</D:displayname>\
+         <D:resourcetype/>\
+         <D:getcontentlength>{}</D:getcontentlength>\
+         <D:getcontenttype>{}</D:getcontenttype>\
+         <D:getetag>{}</D:getetag>\
+         <D:creationdate>{}</D:creationdate>\
+         <D:getlastmodified>{}</D:getlastmodified>\
+         </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+        DAV_PATH,
+        utf8_percent_encode(name, HREF),
+        xml_escape(name),
+        resource.size,
+        xml_escape(&content_type(name, None)),
+        xml_escape(&resource.etag()),
+        modified.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        http_date(modified)
+    )
+}
+
+fn not_found(name: &str) -> poem::Error {
+    poem::Error::from_string(format!("{} not found", name), StatusCode::NOT_FOUND)
+}
+
+fn not_allowed() -> Response {
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(header::ALLOW, ALLOW)
+        .body("the WebDAV share is read-only")
+}
+
+/// RFC 1123 date, as `getlastmodified` and `Last-Modified` want it.
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The inclusive byte range a `Range` header asks for in a file of `len`
+/// bytes. `Ok(None)` for forms that aren't served and get the whole file
+/// instead, `Err` when the range lies past the end.
+fn parse_range(header: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        // the last `suffix` bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) | Err(_) => return Err(()),
+            Ok(suffix) => (len.saturating_sub(suffix), len.wrapping_sub(1)),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, len.wrapping_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.wrapping_sub(1))),
+            _ => return Ok(None),
+        },
+    };
+    if len == 0 || start >= len {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunker;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Ok(Some((0, 9))));
+        assert_eq!(parse_range("bytes=90-", 100), Ok(Some((90, 99))));
+        assert_eq!(parse_range("bytes=-10", 100), Ok(Some((90, 99))));
+        assert_eq!(parse_range("bytes=50-500", 100), Ok(Some((50, 99))));
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), Ok(None));
+        assert_eq!(parse_range("items=0-1", 100), Ok(None));
+        assert_eq!(parse_range("bytes=100-", 100), Err(()));
+        assert_eq!(parse_range("bytes=0-", 0), Err(()));
+    }
+
+    #[tokio::test]
+    async fn test_share_lists_and_serves_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = temp_dir.path().join("archive");
+        let chunker = Chunker::builder()
+            .archive_dir(&archive_dir)
+            .build()
+            .unwrap();
+        let path = temp_dir.path().join("a & b.txt");
+        fs::write(&path, b"0123456789").unwrap();
+        chunker.commit(&path).unwrap();
+        let dav = WebDav::new(Arc::new(FileStore::new(&archive_dir).unwrap()));
+
+        let call = |method: &str, uri: &str, headers: &[(&str, &str)]| {
+            let mut req = Request::builder()
+                .method(Method::from_bytes(method.as_bytes()).unwrap())
+                .uri(uri.parse().unwrap());
+            for (name, value) in headers {
+                req = req.header(*name, *value);
+            }
+            dav.get_response(req.finish())
+        };
+
+        let listing = call("PROPFIND", "/", &[("Depth", "1")]).await;
+        assert_eq!(listing.status(), StatusCode::MULTI_STATUS);
+        let xml = listing.into_body().into_string().await.unwrap();
+        assert!(xml.contains("<D:href>/dav/a%20%26%20b.txt</D:href>"));
+        assert!(xml.contains("<D:displayname>a &amp; b.txt</D:displayname>"));
+        assert!(xml.contains("<D:getcontentlength>10</D:getcontentlength>"));
+
+        let only_root = call("PROPFIND", "/", &[("Depth", "0")]).await;
+        let xml = only_root.into_body().into_string().await.unwrap();
+        assert_eq!(xml.matches("<D:response>").count(), 1);
+
+        let part = call("GET", "/a%20%26%20b.txt", &[("Range", "bytes=2-4")]).await;
+        assert_eq!(part.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            part.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 2-4/10"
+        );
+        assert_eq!(part.into_body().into_string().await.unwrap(), "234");
+
+        let missing = call("GET", "/nope.txt", &[]).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        let put = call("PUT", "/new.txt", &[]).await;
+        assert_eq!(put.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -0,0 +1,334 @@
+//! Ed25519 signatures over manifests.
+//!
+//! Hashes in a manifest only prove that shards match the manifest. Anyone who can
+//! write to the storage host can replace both the shards and the manifest and the
+//! hashes will still line up. Signing the manifest at commit time with a key that
+//! never lives on the storage host closes that gap.
+//!
+//! The signature is stored next to the manifest as `manifest.sig` (hex). It covers
+//! a canonical JSON encoding of the parsed manifest, with object keys sorted, so a
+//! manifest fetched over HTTP verifies the same as one read from disk.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::config::SigningConfig;
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::utils::replace_shard;
+
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+
+/// Environment variable holding a hex secret key, used instead of `signing.key_file`.
+pub const SIGNING_KEY_ENV: &str = "BLOCKFRAME_SIGNING_KEY";
+
+#[derive(Clone)]
+pub struct ManifestSigner {
+    key: SigningKey,
+}
+
+impl ManifestSigner {
+    /// Creates a signer from a fresh random key.
+    pub fn generate() -> Self {
+        Self {
+            key: SigningKey::from_bytes(&rand::random::<[u8; 32]>()),
+        }
+    }
+
+    /// Parses a 64 character hex secret key.
+    pub fn from_hex(secret: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes: [u8; 32] = decode_hex(secret.trim())?
+            .try_into()
+            .map_err(|_| "signing key must be 32 bytes")?;
+        Ok(Self {
+            key: SigningKey::from_bytes(&bytes),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let secret = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read signing key {}: {}", path.display(), e))?;
+        Self::from_hex(&secret)
+    }
+
+    /// Signer from `BLOCKFRAME_SIGNING_KEY` or `signing.key_file`, `None` when
+    /// neither is set.
+    pub fn from_config(config: &SigningConfig) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if let Ok(secret) = std::env::var(SIGNING_KEY_ENV) {
+            return Ok(Some(Self::from_hex(&secret)?));
+        }
+        match &config.key_file {
+            Some(path) => Ok(Some(Self::from_file(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn secret_key_hex(&self) -> String {
+        encode_hex(&self.key.to_bytes())
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        encode_hex(self.key.verifying_key().as_bytes())
+    }
+
+    /// Verifier for this signer's public key.
+    pub fn verifier(&self, require: bool) -> ManifestVerifier {
+        ManifestVerifier {
+            key: self.key.verifying_key(),
+            require,
+        }
+    }
+
+    /// Returns the hex signature over `manifest`.
+    pub fn sign(&self, manifest: &ManifestFile) -> Result<String, Box<dyn std::error::Error>> {
+        let signature = self.key.sign(&canonical_bytes(manifest)?);
+        Ok(encode_hex(&signature.to_bytes()))
+    }
+
+    /// Signs `file_dir/manifest.json` and writes `file_dir/manifest.sig`.
+    pub fn sign_dir(&self, file_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let manifest_path = file_dir.join("manifest.json");
+        let manifest = ManifestFile::new(manifest_path.display().to_string())?;
+        replace_shard(
+            &file_dir.join(SIGNATURE_FILE),
+            self.sign(&manifest)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Replaces `file_dir/manifest.json` of a published entry with `manifest`, and
+/// its signature when there is a `signer`. Both are renamed over the old files
+/// with the signature ready beforehand, so a mount or server reading the entry
+/// meanwhile never gets a truncated manifest and a signature for another one
+/// only between the two renames.
+pub(crate) fn replace_manifest(
+    file_dir: &Path,
+    manifest: &ManifestFile,
+    signer: Option<&ManifestSigner>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signature = signer.map(|signer| signer.sign(manifest)).transpose()?;
+    replace_shard(
+        &file_dir.join("manifest.json"),
+        serde_json::to_string(manifest)?.as_bytes(),
+    )?;
+    if let Some(signature) = signature {
+        replace_shard(&file_dir.join(SIGNATURE_FILE), signature.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct ManifestVerifier {
+    key: VerifyingKey,
+    /// Reject manifests without a signature instead of warning.
+    require: bool,
+}
+
+impl ManifestVerifier {
+    /// Parses a 64 character hex public key.
+    pub fn from_hex(public: &str, require: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes: [u8; 32] = decode_hex(public.trim())?
+            .try_into()
+            .map_err(|_| "public key must be 32 bytes")?;
+        Ok(Self {
+            key: VerifyingKey::from_bytes(&bytes)?,
+            require,
+        })
+    }
+
+    /// Verifier from `signing.public_key`, falling back to the public half of the
+    /// signing key. `None` when no key is configured at all.
+    pub fn from_config(config: &SigningConfig) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if let Some(public) = &config.public_key {
+            return Ok(Some(Self::from_hex(public, config.require)?));
+        }
+        Ok(ManifestSigner::from_config(config)?.map(|signer| signer.verifier(config.require)))
+    }
+
+    /// Checks `signature` (hex) against `manifest`. A missing signature is an
+    /// error only when signatures are required.
+    pub fn verify(
+        &self,
+        manifest: &ManifestFile,
+        signature: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(signature) = signature else {
+            if self.require {
+                return Err(format!("manifest for {} is not signed", manifest.name).into());
+            }
+            tracing::warn!("SIGNING | manifest for {} is not signed", manifest.name);
+            return Ok(());
+        };
+
+        let bytes: [u8; 64] = decode_hex(signature.trim())?
+            .try_into()
+            .map_err(|_| "signature must be 64 bytes")?;
+        self.key
+            .verify(&canonical_bytes(manifest)?, &Signature::from_bytes(&bytes))
+            .map_err(|_| format!("manifest signature for {} is invalid", manifest.name).into())
+    }
+}
+
+/// Reads the detached signature stored next to `manifest_path`, if any.
+pub fn read_signature(manifest_path: &Path) -> Option<String> {
+    let sig_path = manifest_path.with_file_name(SIGNATURE_FILE);
+    fs::read_to_string(sig_path).ok()
+}
+
+/// JSON encoding of the manifest with sorted object keys and no whitespace.
+fn canonical_bytes(manifest: &ManifestFile) -> Result<Vec<u8>, serde_json::Error> {
+    let mut out = String::new();
+    write_canonical(&serde_json::to_value(manifest)?, &mut out)?;
+    Ok(out.into_bytes())
+}
+
+fn write_canonical(value: &Value, out: &mut String) -> Result<(), serde_json::Error> {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                write_canonical(&map[key], out)?;
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out)?;
+            }
+            out.push(']');
+        }
+        other => out.push_str(&serde_json::to_string(other)?),
+    }
+    Ok(())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err("hex string has odd length".to_string());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex at offset {}", i))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::manifest::{ErasureCoding, MerkleTreeStructure};
+    use std::collections::HashMap;
+
+    fn manifest() -> ManifestFile {
+        ManifestFile {
+            erasure_coding: ErasureCoding {
+                data_shards: 1,
+                parity_shards: 3,
+                r#type: "RS".to_string(),
+            },
+            merkle_tree: MerkleTreeStructure {
+                leaves: HashMap::from([(0, "a".repeat(64)), (1, "b".repeat(64))]),
+                segments: HashMap::new(),
+                blocks: HashMap::new(),
+                root: "c".repeat(64),
+            },
+            name: "example.txt".to_string(),
+            original_hash: "d".repeat(64),
+            size: 10,
+            time_of_creation: "2025-01-01".to_string(),
+            tier: 1,
+            segment_size: 64,
+            segment_policy: None,
+            pack: Vec::new(),
+            members: Vec::new(),
+            content: None,
+            parity_dir: None,
+            tags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = ManifestSigner::generate();
+        let verifier = ManifestVerifier::from_hex(&signer.public_key_hex(), true).unwrap();
+        let manifest = manifest();
+        let signature = signer.sign(&manifest).unwrap();
+
+        assert!(verifier.verify(&manifest, Some(&signature)).is_ok());
+
+        // swapping a hash in the manifest breaks the signature
+        let mut tampered = manifest.clone();
+        tampered.merkle_tree.leaves.insert(0, "e".repeat(64));
+        assert!(verifier.verify(&tampered, Some(&signature)).is_err());
+
+        // a different key can't produce a valid signature
+        let forged = ManifestSigner::generate().sign(&manifest).unwrap();
+        assert!(verifier.verify(&manifest, Some(&forged)).is_err());
+    }
+
+    #[test]
+    fn test_unsigned_manifest_only_fails_when_required() {
+        let signer = ManifestSigner::generate();
+        assert!(signer.verifier(false).verify(&manifest(), None).is_ok());
+        assert!(signer.verifier(true).verify(&manifest(), None).is_err());
+    }
+
+    #[test]
+    fn test_secret_key_hex_round_trip() {
+        let signer = ManifestSigner::generate();
+        let restored = ManifestSigner::from_hex(&signer.secret_key_hex()).unwrap();
+        assert_eq!(restored.public_key_hex(), signer.public_key_hex());
+    }
+
+    #[test]
+    fn test_replace_manifest_swaps_both_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = ManifestSigner::generate();
+        fs::write(
+            dir.path().join("manifest.json"),
+            serde_json::to_string(&manifest()).unwrap(),
+        )
+        .unwrap();
+        signer.sign_dir(dir.path()).unwrap();
+
+        let mut tagged = manifest();
+        tagged.tags.insert("project".into(), "apollo".into());
+        replace_manifest(dir.path(), &tagged, Some(&signer)).unwrap();
+
+        let read =
+            ManifestFile::new(dir.path().join("manifest.json").display().to_string()).unwrap();
+        assert_eq!(read.tags["project"], "apollo");
+        let signature = fs::read_to_string(dir.path().join(SIGNATURE_FILE)).unwrap();
+        signer
+            .verifier(true)
+            .verify(&read, Some(&signature))
+            .unwrap();
+        // nothing staged is left behind
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["manifest.json", SIGNATURE_FILE]);
+    }
+}
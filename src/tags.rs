@@ -0,0 +1,161 @@
+//! Key/value tags on archive entries.
+//!
+//! `commit --tag project=apollo --tag owner=ops` records tags in the entry's
+//! manifest, where a signature covers them like the rest of it. Committing
+//! the same content again with tags adds to the ones it has. Listings,
+//! searches and `GET /api/files` keep entries matching a [`TagFilter`], and
+//! the mount shows tags as `user.<key>` extended attributes.
+//!
+//! An alias has no manifest of its own, so its tags go to the entry it points
+//! at, which holds the same bytes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::str::FromStr;
+
+use crate::alias::{self, Alias};
+use crate::chunker::{ChunkedFile, Chunker};
+use crate::merkle_tree::manifest::ManifestFile;
+use crate::signing;
+
+/// Tags of an entry, by key.
+pub type Tags = BTreeMap<String, String>;
+
+/// Longest key or value accepted.
+const MAX_LEN: usize = 255;
+
+/// Parses `key=value`, as `commit --tag` takes it.
+///
+/// ```
+/// use blockframe::tags::parse_tag;
+///
+/// assert_eq!(
+///     parse_tag("project=apollo").unwrap(),
+///     ("project".to_string(), "apollo".to_string())
+/// );
+/// assert!(parse_tag("project").is_err());
+/// assert!(parse_tag("has space=x").is_err());
+/// ```
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    let (key, value) = tag
+        .split_once('=')
+        .ok_or_else(|| format!("tag '{}' should be key=value", tag))?;
+    check_key(key)?;
+    if value.len() > MAX_LEN {
+        return Err(format!("value of tag {} is over {} bytes", key, MAX_LEN));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Keys are letters, digits, `.`, `_` and `-`, so they make valid attribute
+/// names on every platform.
+fn check_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > MAX_LEN {
+        return Err(format!(
+            "tag key '{}' should be 1 to {} bytes",
+            key, MAX_LEN
+        ));
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(format!(
+            "tag key '{}' may only hold letters, digits, '.', '_' and '-'",
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// Keeps entries with the tag `key`, set to `value` if one is given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl TagFilter {
+    pub fn matches(&self, tags: &Tags) -> bool {
+        match (tags.get(&self.key), &self.value) {
+            (Some(found), Some(value)) => found == value,
+            (found, None) => found.is_some(),
+            (None, Some(_)) => false,
+        }
+    }
+}
+
+impl FromStr for TagFilter {
+    type Err = String;
+
+    /// `key` for any value, `key=value` for that one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (s, None),
+        };
+        check_key(key)?;
+        Ok(Self {
+            key: key.to_string(),
+            value,
+        })
+    }
+}
+
+impl Chunker {
+    /// Adds the chunker's tags to the manifest of the committed `chunked` and
+    /// signs it again so they are covered, see the module docs for aliases.
+    /// A newly encoded entry already has them, so this only changes an entry
+    /// that was archived before.
+    pub(crate) fn record_tags(
+        &self,
+        chunked: &ChunkedFile,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file_dir = chunked.file_dir.clone();
+        if alias::is_alias(&file_dir) {
+            let alias: Alias =
+                serde_json::from_str(&fs::read_to_string(file_dir.join(alias::ALIAS_FILE))?)?;
+            file_dir = self.archive_dir().join(alias.target);
+        }
+        let manifest_path = file_dir.join("manifest.json");
+        let mut manifest = ManifestFile::new(manifest_path.display().to_string())?;
+        let before = manifest.tags.clone();
+        manifest.tags.extend(self.tags().clone());
+        if manifest.tags == before {
+            return Ok(());
+        }
+        tracing::info!(
+            "TAGS | tagged {} with {}",
+            chunked.file_name,
+            self.tags()
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        signing::replace_manifest(&file_dir, &manifest, self.signer())?;
+        self.refresh_global_parity(&manifest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_match_keys_and_values() {
+        let tags: Tags = [("project", "apollo"), ("owner", "ops")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let filter = |s: &str| s.parse::<TagFilter>().unwrap();
+        assert!(filter("project").matches(&tags));
+        assert!(filter("project=apollo").matches(&tags));
+        assert!(!filter("project=gemini").matches(&tags));
+        assert!(!filter("team").matches(&tags));
+        assert!(filter("empty=").matches(&Tags::from([("empty".into(), String::new())])));
+        assert!("bad key".parse::<TagFilter>().is_err());
+        assert!("=apollo".parse::<TagFilter>().is_err());
+    }
+}
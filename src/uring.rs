@@ -0,0 +1,298 @@
+//! Batched shard IO through io_uring, built with the `uring` feature on Linux.
+//!
+//! A tier 3 block is written and read as 30 segment files and their parity.
+//! [`write_files`] and [`read_each`] hand a block's files to the kernel as one
+//! batch on a ring per thread, instead of one blocking call after another,
+//! which keeps the queue of an NVMe disk full.
+//!
+//! Where no ring can be set up, on kernels before 5.6 or under a seccomp
+//! policy that blocks io_uring, both fail with [`io::ErrorKind::Unsupported`]
+//! before touching anything, and callers fall back to plain IO.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use io_uring::{IoUring, opcode, types};
+
+use crate::direct_io::{self, ALIGN, AlignedBuf};
+use crate::utils::{is_zero, preallocate, write_shard};
+
+/// Submission queue entries per ring.
+const QUEUE_DEPTH: u32 = 64;
+
+/// Bytes [`read_each`] holds in memory at once, a tier 3 block of large
+/// segments is read in several batches.
+const BATCH_BYTES: usize = 256 * 1024 * 1024;
+
+/// Longest single read or write, longer ones are resubmitted for the rest.
+const MAX_OP: usize = 1 << 30;
+
+/// Set once a ring failed to set up, so it isn't tried on every call.
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+}
+
+/// Writes each `(path, data)` as [`write_shard`] does, all in one batch.
+pub fn write_files(files: &[(PathBuf, &[u8])]) -> io::Result<()> {
+    with_ring(|ring| {
+        let mut handles = Vec::with_capacity(files.len());
+        let mut ops = Vec::with_capacity(files.len());
+        for (path, data) in files {
+            // a hole needs no write at all
+            if is_zero(data) {
+                write_shard(path, data)?;
+                continue;
+            }
+            let file = File::create(path)?;
+            preallocate(&file, data.len() as u64)?;
+            ops.push(Op {
+                fd: file.as_raw_fd(),
+                buf: data.as_ptr().cast_mut(),
+                cap: data.len(),
+                want: data.len(),
+                done: 0,
+                write: true,
+                result: None,
+            });
+            handles.push(file);
+        }
+        // SAFETY: `files` outlives the call. Should the ring fail with writes
+        // in flight, the kernel only reads from them
+        unsafe { run(ring, &mut ops)? };
+        ops.into_iter()
+            .try_for_each(|op| op.result.unwrap_or(Err(io::ErrorKind::Interrupted.into())))
+    })
+}
+
+/// Reads each of `paths` whole, past the page cache with `direct` as
+/// [`direct_io::read`] does, and passes each to `visit` with its index in
+/// `paths`. Files are read in batches of up to [`BATCH_BYTES`].
+pub fn read_each(
+    paths: &[PathBuf],
+    direct: bool,
+    mut visit: impl FnMut(usize, io::Result<Vec<u8>>),
+) -> io::Result<()> {
+    with_ring(|ring| {
+        let mut next = 0;
+        while next < paths.len() {
+            let mut batch: Vec<(usize, File, AlignedBuf, usize)> = Vec::new();
+            let mut batch_bytes = 0;
+            while next < paths.len() && (batch.is_empty() || batch_bytes < BATCH_BYTES) {
+                match open_for_read(&paths[next], direct) {
+                    Ok((file, len)) => {
+                        batch_bytes += len;
+                        batch.push((
+                            next,
+                            file,
+                            AlignedBuf::new(len.div_ceil(ALIGN) * ALIGN),
+                            len,
+                        ));
+                    }
+                    Err(e) => visit(next, Err(e)),
+                }
+                next += 1;
+            }
+
+            let mut ops: Vec<Op> = batch
+                .iter_mut()
+                .map(|(_, file, buf, len)| Op {
+                    fd: file.as_raw_fd(),
+                    buf: buf.as_mut_ptr(),
+                    cap: buf.len(),
+                    want: *len,
+                    done: 0,
+                    write: false,
+                    result: None,
+                })
+                .collect();
+            // SAFETY: the buffers live in `batch` until every op is complete,
+            // and are leaked if the ring fails with reads still in flight
+            if let Err(e) = unsafe { run(ring, &mut ops) } {
+                std::mem::forget(batch);
+                return Err(e);
+            }
+            for (op, (index, _, buf, _)) in ops.into_iter().zip(batch) {
+                let read = op.result.unwrap_or(Err(io::ErrorKind::Interrupted.into()));
+                visit(index, read.map(|()| buf[..op.done.min(op.want)].to_vec()));
+            }
+        }
+        Ok(())
+    })
+}
+
+fn open_for_read(path: &Path, direct: bool) -> io::Result<(File, usize)> {
+    let file = match direct {
+        true => direct_io::open(path)?,
+        false => File::open(path)?,
+    };
+    let len = file.metadata()?.len() as usize;
+    Ok((file, len))
+}
+
+/// Runs `f` on this thread's ring, setting it up on first use.
+fn with_ring<T>(f: impl FnOnce(&mut IoUring) -> io::Result<T>) -> io::Result<T> {
+    if UNAVAILABLE.load(Ordering::Relaxed) {
+        return Err(io::ErrorKind::Unsupported.into());
+    }
+    RING.with(|cell| {
+        let mut slot = cell.borrow_mut();
+        let ring = match &mut *slot {
+            Some(ring) => ring,
+            empty => empty.insert(IoUring::new(QUEUE_DEPTH).map_err(|e| {
+                if !UNAVAILABLE.swap(true, Ordering::Relaxed) {
+                    tracing::warn!("URING | unavailable ({}), using plain IO", e);
+                }
+                io::Error::from(io::ErrorKind::Unsupported)
+            })?),
+        };
+        f(ring)
+    })
+}
+
+/// One file's read or write, resubmitted until `want` bytes are done, a read
+/// reaches the end of the file or it fails.
+struct Op {
+    fd: RawFd,
+    buf: *mut u8,
+    /// Bytes `buf` has room for, at least `want`.
+    cap: usize,
+    want: usize,
+    done: usize,
+    write: bool,
+    result: Option<io::Result<()>>,
+}
+
+impl Op {
+    fn entry(&self, index: usize) -> io_uring::squeue::Entry {
+        let len = (self.cap - self.done).min(MAX_OP) as u32;
+        // SAFETY: `done` < `cap`, so the pointer stays inside the buffer
+        let buf = unsafe { self.buf.add(self.done) };
+        let entry = match self.write {
+            true => opcode::Write::new(types::Fd(self.fd), buf, len)
+                .offset(self.done as u64)
+                .build(),
+            false => opcode::Read::new(types::Fd(self.fd), buf, len)
+                .offset(self.done as u64)
+                .build(),
+        };
+        entry.user_data(index as u64)
+    }
+}
+
+/// Submits every op and waits until all have completed, leaving the outcome
+/// of each in its `result`. Fails only if the ring itself does.
+///
+/// # Safety
+///
+/// Every op's buffer must stay valid for `cap` bytes until this returns, and
+/// if it fails, for as long as the ring lives.
+unsafe fn run(ring: &mut IoUring, ops: &mut [Op]) -> io::Result<()> {
+    let mut queue: VecDeque<usize> = (0..ops.len()).collect();
+    let mut in_flight = 0;
+    while !queue.is_empty() || in_flight > 0 {
+        {
+            let mut submission = ring.submission();
+            while !submission.is_full()
+                && let Some(index) = queue.pop_front()
+            {
+                // SAFETY: the caller keeps the buffer valid, see above
+                unsafe { submission.push(&ops[index].entry(index)) }
+                    .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+                in_flight += 1;
+            }
+        }
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::Interrupted
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::ResourceBusy
+                ) => {}
+            Err(e) => return Err(e),
+        }
+        let completed: Vec<(u64, i32)> = ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect();
+        for (index, result) in completed {
+            in_flight -= 1;
+            let index = index as usize;
+            let op = &mut ops[index];
+            match result {
+                n if n < 0 => {
+                    let e = io::Error::from_raw_os_error(-n);
+                    match e.kind() {
+                        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => {
+                            queue.push_back(index)
+                        }
+                        _ => op.result = Some(Err(e)),
+                    }
+                }
+                0 if op.write => op.result = Some(Err(io::ErrorKind::WriteZero.into())),
+                0 => op.result = Some(Ok(())),
+                n => {
+                    op.done += n as usize;
+                    match op.done < op.want {
+                        true => queue.push_back(index),
+                        false => op.result = Some(Ok(())),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_match_plain_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let shards: Vec<(PathBuf, Vec<u8>)> = (0..40)
+            .map(|i| {
+                let data = match i % 4 {
+                    0 => vec![0; 9000],
+                    _ => (0..i * 7919).map(|b| (b % 251) as u8).collect(),
+                };
+                (dir.path().join(format!("shard_{}.dat", i)), data)
+            })
+            .collect();
+        let files: Vec<(PathBuf, &[u8])> = shards
+            .iter()
+            .map(|(path, data)| (path.clone(), data.as_slice()))
+            .collect();
+        match write_files(&files) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return,
+            result => result.unwrap(),
+        }
+        for (path, data) in &shards {
+            assert_eq!(&std::fs::read(path).unwrap(), data);
+        }
+
+        let mut paths: Vec<PathBuf> = shards.iter().map(|(path, _)| path.clone()).collect();
+        paths.push(dir.path().join("missing.dat"));
+        for direct in [false, true] {
+            let mut seen = vec![false; paths.len()];
+            read_each(&paths, direct, |index, read| {
+                seen[index] = true;
+                match shards.get(index) {
+                    Some((_, data)) => assert_eq!(&read.unwrap(), data),
+                    None => assert!(read.is_err()),
+                }
+            })
+            .unwrap();
+            assert!(seen.iter().all(|seen| *seen));
+        }
+    }
+}
@@ -1,6 +1,14 @@
 use blake3::Hasher;
-use std::{fs::File, io, path::Path};
-use sysinfo::System;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
 
 /// Computes the BLAKE3 digest of the provided bytes and returns it as a
 /// hexadecimal string.
@@ -55,7 +63,14 @@ pub fn determine_segment_size(file_size: u64) -> Result<usize, std::io::Error> {
     }
 
     // adaptive option: for more juice
-    let available_ram = detect_available_memory()?;
+    let available_ram = match detect_available_memory() {
+        Ok(available) => available,
+        // nothing to go by on this build, size it from the file alone
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            return Ok(deterministic_segment_size(file_size));
+        }
+        Err(e) => return Err(e),
+    };
 
     if available_ram < 4_000_000 {
         // 1mb
@@ -69,20 +84,332 @@ pub fn determine_segment_size(file_size: u64) -> Result<usize, std::io::Error> {
     }
 }
 
+/// Picks a segment size from the file size alone, so the same file is laid out
+/// the same way on every host. Small files stay a single segment as with
+/// [`determine_segment_size`].
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::utils::deterministic_segment_size;
+///
+/// assert_eq!(deterministic_segment_size(1024), 1024);
+/// assert_eq!(deterministic_segment_size(30_000_000), 1024 * 1024);
+/// assert_eq!(deterministic_segment_size(500_000_000), 8 * 1024 * 1024);
+/// assert_eq!(deterministic_segment_size(5_000_000_000), 32 * 1024 * 1024);
+/// ```
+pub fn deterministic_segment_size(file_size: u64) -> usize {
+    const MIN_SEGMENT: u64 = 512 * 1024;
+    if file_size < MIN_SEGMENT {
+        file_size as usize
+    } else if file_size < 64 * 1024 * 1024 {
+        1024 * 1024
+    } else if file_size < 1024 * 1024 * 1024 {
+        8 * 1024 * 1024
+    } else {
+        32 * 1024 * 1024
+    }
+}
+
+/// Smallest segment size [`SegmentPolicy::Fixed`] accepts.
+pub const MIN_FIXED_SEGMENT: usize = 64 * 1024;
+/// Largest segment size [`SegmentPolicy::Fixed`] accepts, each segment is held
+/// in memory with its parity while encoding.
+pub const MAX_FIXED_SEGMENT: usize = 256 * 1024 * 1024;
+
+/// How tier 2 and 3 commits choose their segment size. The policy is recorded
+/// in the manifest next to the size it produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SegmentPolicy {
+    /// From the file size and the committing host's free memory, see
+    /// [`determine_segment_size`]. The same file can be laid out differently
+    /// on different hosts.
+    #[default]
+    Adaptive,
+    /// From the file size alone, see [`deterministic_segment_size`].
+    Deterministic,
+    /// Always this many bytes, or the whole file when it is smaller.
+    Fixed(usize),
+}
+
+impl SegmentPolicy {
+    /// The segment size for a file of `file_size` bytes.
+    pub fn segment_size(&self, file_size: u64) -> Result<usize, std::io::Error> {
+        match self {
+            Self::Adaptive => determine_segment_size(file_size),
+            Self::Deterministic => Ok(deterministic_segment_size(file_size)),
+            Self::Fixed(size) => Ok((*size).min(file_size as usize)),
+        }
+    }
+
+    /// Name written to the manifest.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Adaptive => "adaptive",
+            Self::Deterministic => "deterministic",
+            Self::Fixed(_) => "fixed",
+        }
+    }
+}
+
+impl std::str::FromStr for SegmentPolicy {
+    type Err = String;
+
+    /// Parses `adaptive`, `deterministic` or a size such as `8MB`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use blockframe::utils::SegmentPolicy;
+    ///
+    /// assert_eq!("deterministic".parse(), Ok(SegmentPolicy::Deterministic));
+    /// assert_eq!("4MB".parse(), Ok(SegmentPolicy::Fixed(4_000_000)));
+    /// assert!("1KB".parse::<SegmentPolicy>().is_err());
+    /// ```
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "adaptive" | "auto" => Ok(Self::Adaptive),
+            "deterministic" => Ok(Self::Deterministic),
+            _ => {
+                let size = crate::config::parse_size(text).map_err(|e| e.to_string())?;
+                if !(MIN_FIXED_SEGMENT..=MAX_FIXED_SEGMENT).contains(&size) {
+                    return Err(format!(
+                        "segment size {} is outside {} to {} bytes",
+                        size, MIN_FIXED_SEGMENT, MAX_FIXED_SEGMENT
+                    ));
+                }
+                Ok(Self::Fixed(size))
+            }
+        }
+    }
+}
+
+/// Segments in a full tier 3 block.
+pub const BLOCK_SEGMENTS: usize = 30;
+
+/// Parity shards per data shard of a tier 3 block when not configured, 3 for a
+/// full block of 30.
+pub const DEFAULT_BLOCK_PARITY_RATIO: f64 = 0.1;
+
+/// Parity shards for a tier 3 block of `segments` data shards, `ratio` of them
+/// rounded up and at least one, so a partial last block isn't protected (and
+/// sized) like a full one.
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::utils::block_parity_shards;
+///
+/// assert_eq!(block_parity_shards(30, 0.1), 3);
+/// assert_eq!(block_parity_shards(12, 0.1), 2);
+/// assert_eq!(block_parity_shards(2, 0.1), 1);
+/// assert_eq!(block_parity_shards(30, 0.25), 8);
+/// ```
+pub fn block_parity_shards(segments: usize, ratio: f64) -> usize {
+    ((segments as f64 * ratio).ceil() as usize).max(1)
+}
+
 /// Returns the amount of free memory reported by the host operating system in
-/// kibibytes.
+/// bytes. Without the `host-info` feature it is read from `/proc/meminfo`, and
+/// fails with [`io::ErrorKind::Unsupported`] on other platforms.
 ///
 /// # Examples
 ///
 /// ```
-/// let available = blockframe::utils::detect_available_memory().unwrap()
+/// # #[cfg(any(feature = "host-info", target_os = "linux"))] {
+/// let available = blockframe::utils::detect_available_memory().unwrap();
 /// assert!(available > 0);
+/// # }
 /// ```
+#[cfg(feature = "host-info")]
 pub fn detect_available_memory() -> Result<u64, std::io::Error> {
-    let sys = System::new_all();
+    let sys = sysinfo::System::new_all();
     Ok(sys.available_memory())
 }
 
+#[cfg(not(feature = "host-info"))]
+pub fn detect_available_memory() -> Result<u64, std::io::Error> {
+    #[cfg(target_os = "linux")]
+    {
+        let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix("MemAvailable:"))
+            .and_then(|kib| kib.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            .map(|kib| kib * 1024)
+            .ok_or_else(|| io::Error::other("no MemAvailable in /proc/meminfo"))
+    }
+    #[cfg(not(target_os = "linux"))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free memory needs the host-info feature on this platform",
+    ))
+}
+
+/// Returns the `(total, available)` bytes of the disk holding `path`, picking
+/// the mounted disk with the longest mount point that contains it. Without the
+/// `host-info` feature it asks `statvfs`, and fails with
+/// [`io::ErrorKind::Unsupported`] on other platforms.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(any(feature = "host-info", unix))] {
+/// let (total, available) = blockframe::utils::disk_space(&std::env::temp_dir()).unwrap();
+/// assert!(available <= total);
+/// # }
+/// ```
+#[cfg(feature = "host-info")]
+pub fn disk_space(path: &Path) -> Result<(u64, u64), std::io::Error> {
+    let path = path.canonicalize()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| (disk.total_space(), disk.available_space()))
+        .ok_or_else(|| io::Error::other(format!("no disk found for {}", path.display())))
+}
+
+#[cfg(not(feature = "host-info"))]
+pub fn disk_space(path: &Path) -> Result<(u64, u64), std::io::Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(path.canonicalize()?.as_os_str().as_bytes())
+            .map_err(io::Error::other)?;
+        // SAFETY: statvfs is plain data, filled in by the call below
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `path` is NUL-terminated and `stat` outlives the call
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // the field types differ between platforms
+        #[allow(clippy::useless_conversion)]
+        let fragment = u64::from(stat.f_frsize);
+        #[allow(clippy::useless_conversion)]
+        Ok((
+            u64::from(stat.f_blocks) * fragment,
+            u64::from(stat.f_bavail) * fragment,
+        ))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "disk space needs the host-info feature on this platform",
+        ))
+    }
+}
+
+/// Whether every byte of `data` is zero.
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::utils::is_zero;
+///
+/// assert!(is_zero(&[0; 100]));
+/// assert!(!is_zero(&[0, 0, 1]));
+/// ```
+pub fn is_zero(data: &[u8]) -> bool {
+    // SAFETY: every bit pattern is a valid u128
+    let (head, words, tail) = unsafe { data.align_to::<u128>() };
+    head.iter().all(|&b| b == 0) && words.iter().all(|&w| w == 0) && tail.iter().all(|&b| b == 0)
+}
+
+/// Reserves `len` bytes on disk for `file` before it is written, so a shard
+/// lands in one extent instead of growing piecemeal next to the others being
+/// written. Uses `fallocate` on Linux; on Windows `set_len`, which extends the
+/// file through `SetFileInformationByHandle` and has NTFS reserve its
+/// clusters. Elsewhere, or on a filesystem that can't, it does nothing.
+pub fn preallocate(file: &File, len: u64) -> Result<(), std::io::Error> {
+    if len == 0 {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+        // SAFETY: the descriptor is owned by `file` and stays open for the call
+        let result = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len as libc::off_t) };
+        if result != 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(()),
+                _ => Err(err),
+            };
+        }
+    }
+    #[cfg(windows)]
+    file.set_len(len)?;
+    #[cfg(not(any(target_os = "linux", windows)))]
+    let _ = file;
+    Ok(())
+}
+
+/// Writes a shard file at `path`, replacing any file there. An all-zero shard
+/// is only extended to its length, which leaves a hole that takes no space on
+/// filesystems with sparse files; other shards are preallocated first, see
+/// [`preallocate`].
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::utils::write_shard;
+///
+/// let dir = tempfile::tempdir()?;
+/// write_shard(&dir.path().join("zeros.dat"), &[0; 4096])?;
+/// write_shard(&dir.path().join("data.dat"), b"data")?;
+/// assert_eq!(std::fs::read(dir.path().join("zeros.dat"))?, vec![0; 4096]);
+/// assert_eq!(std::fs::read(dir.path().join("data.dat"))?, b"data");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn write_shard(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    let mut file = File::create(path)?;
+    if is_zero(data) {
+        return file.set_len(data.len() as u64);
+    }
+    preallocate(&file, data.len() as u64)?;
+    file.write_all(data)
+}
+
+/// Replaces the shard at `path` with `data` by writing a new file next to it
+/// and renaming it over, so a reader that has the old shard open or mapped,
+/// such as a mount, keeps seeing the old bytes instead of a truncated file.
+/// Each call stages under a name of its own, so two writers replacing one
+/// shard at once don't truncate or move each other's file.
+pub fn replace_shard(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let staged = std::path::PathBuf::from(staged);
+    let replaced = write_shard(&staged, data).and_then(|()| std::fs::rename(&staged, path));
+    if replaced.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    replaced
+}
+
+/// Writes each `(path, data)` with [`write_shard`], in one io_uring batch when
+/// built with the `uring` feature on Linux and in parallel otherwise.
+pub fn write_shards(shards: &[(std::path::PathBuf, &[u8])]) -> Result<(), std::io::Error> {
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    match crate::uring::write_files(shards) {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+        written => return written,
+    }
+    use rayon::prelude::*;
+    shards
+        .par_iter()
+        .try_for_each(|(path, data)| write_shard(path, data))
+}
+
 /// Calculates the BLAKE3 hash of a file by streaming its contents from disk.
 ///
 /// The function avoids loading the entire file into memory at once, making it
@@ -112,8 +439,224 @@ pub fn detect_available_memory() -> Result<u64, std::io::Error> {
 /// # }
 /// ```
 pub fn hash_file_streaming(file_path: &Path) -> Result<String, std::io::Error> {
-    let mut file = File::open(file_path)?;
-    let mut hasher = Hasher::new();
-    io::copy(&mut file, &mut hasher)?;
-    Ok(hasher.finalize().to_string())
+    Ok(HashSession::new().hash_file(file_path)?.blake3)
+}
+
+/// Header a request's correlation ID travels in, sent by remote mounts and
+/// read and sent back by `serve`.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Read size used by [`HashSession`] unless told otherwise.
+pub const DEFAULT_HASH_BUFFER: usize = 1024 * 1024;
+
+/// Stops a running [`HashSession`] from another thread. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress callback of a [`HashSession`]: bytes done and the total if known.
+type ProgressFn<'a> = Box<dyn FnMut(u64, Option<u64>) + 'a>;
+
+/// Result of a [`HashSession`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digests {
+    /// BLAKE3 as hex, the hash used throughout the archive.
+    pub blake3: String,
+    /// SHA-256 as hex, when asked for with [`HashSession::with_sha256`].
+    pub sha256: Option<String>,
+    /// Bytes hashed.
+    pub bytes: u64,
+}
+
+/// Hashes a stream one buffer at a time, so memory stays at one buffer no
+/// matter how large the input or how slow the consumer.
+///
+/// Between buffers it reports progress and checks for cancellation, and it can
+/// compute SHA-256 alongside BLAKE3 in the same read for tools that expect it.
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::utils::{CancelToken, HashSession, blake3_hash_bytes};
+///
+/// let data = vec![7u8; 10_000];
+/// let mut seen = Vec::new();
+/// let digests = HashSession::new()
+///     .buffer_size(4096)
+///     .with_sha256()
+///     .on_progress(|done, total| seen.push((done, total)))
+///     .hash_reader(&data[..], Some(data.len() as u64))
+///     .unwrap();
+///
+/// assert_eq!(digests.blake3, blake3_hash_bytes(&data).unwrap());
+/// assert_eq!(digests.sha256.unwrap().len(), 64);
+/// assert_eq!(seen.last(), Some(&(10_000, Some(10_000))));
+///
+/// let abc = HashSession::new().with_sha256().hash_reader(&b"abc"[..], None).unwrap();
+/// assert_eq!(
+///     abc.sha256.as_deref(),
+///     Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+/// );
+///
+/// // a cancelled session stops with `Interrupted`
+/// let token = CancelToken::new();
+/// token.cancel();
+/// let err = HashSession::new()
+///     .cancel_token(token)
+///     .hash_reader(&data[..], None)
+///     .unwrap_err();
+/// assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+/// ```
+pub struct HashSession<'a> {
+    buffer_size: usize,
+    sha256: bool,
+    progress: Option<ProgressFn<'a>>,
+    cancel: Option<CancelToken>,
+}
+
+impl Default for HashSession<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> HashSession<'a> {
+    pub fn new() -> Self {
+        Self {
+            buffer_size: DEFAULT_HASH_BUFFER,
+            sha256: false,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Bytes read per step, at least one.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Also computes SHA-256 in the same pass.
+    pub fn with_sha256(mut self) -> Self {
+        self.sha256 = true;
+        self
+    }
+
+    /// Called after every buffer with the bytes hashed so far and the total
+    /// when known.
+    pub fn on_progress(mut self, progress: impl FnMut(u64, Option<u64>) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Checked before every buffer; once cancelled the session stops with
+    /// [`io::ErrorKind::Interrupted`].
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Hashes the file at `path`, reporting its size as the total.
+    pub fn hash_file(self, path: &Path) -> Result<Digests, io::Error> {
+        let file = File::open(path)?;
+        let total = file.metadata()?.len();
+        self.hash_reader(file, Some(total))
+    }
+
+    /// Hashes everything `reader` yields. `total` is only passed on to the
+    /// progress callback.
+    pub fn hash_reader<R: Read>(
+        mut self,
+        mut reader: R,
+        total: Option<u64>,
+    ) -> Result<Digests, io::Error> {
+        let mut blake3 = Hasher::new();
+        let mut sha256 = self.sha256.then(Sha256::new);
+        let mut buffer = vec![0u8; self.buffer_size];
+        let mut bytes = 0u64;
+
+        loop {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    format!("hashing cancelled after {} bytes", bytes),
+                ));
+            }
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            blake3.update(&buffer[..read]);
+            if let Some(sha256) = &mut sha256 {
+                sha256.update(&buffer[..read]);
+            }
+            bytes += read as u64;
+            if let Some(progress) = &mut self.progress {
+                progress(bytes, total);
+            }
+        }
+
+        Ok(Digests {
+            blake3: blake3.finalize().to_string(),
+            sha256: sha256.map(|sha256| {
+                sha256
+                    .finalize()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect()
+            }),
+            bytes,
+        })
+    }
+}
+
+/// Parses a byte range such as `100MB-200MB` against a file of `size` bytes.
+/// Either end may be left out: `-1MB` is the first megabyte and `100MB-` runs
+/// to the end of the file. Units are those of [`crate::config::parse_size`],
+/// and an end past the file is clamped to its size.
+///
+/// # Examples
+///
+/// ```
+/// use blockframe::utils::parse_range;
+///
+/// assert_eq!(parse_range("100MB-200MB", 1_000_000_000).unwrap(), 100_000_000..200_000_000);
+/// assert_eq!(parse_range("500-", 800).unwrap(), 500..800);
+/// assert_eq!(parse_range("-2KB", 800).unwrap(), 0..800);
+/// assert!(parse_range("200-100", 800).is_err());
+/// ```
+pub fn parse_range(
+    text: &str,
+    size: u64,
+) -> Result<std::ops::Range<u64>, Box<dyn std::error::Error>> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("range {:?} should look like START-END", text))?;
+    let start = match start.trim() {
+        "" => 0,
+        start => crate::config::parse_size(start)? as u64,
+    };
+    let end = match end.trim() {
+        "" => size,
+        end => (crate::config::parse_size(end)? as u64).min(size),
+    };
+    if start >= end {
+        return Err(format!("range {:?} is empty within {} bytes", text, size).into());
+    }
+    Ok(start..end)
 }
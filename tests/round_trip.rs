@@ -0,0 +1,200 @@
+//! Property tests for commit, damage and recovery round trips.
+//!
+//! Each case commits a file of random size and content at a given tier, loses
+//! or corrupts as many shards as the tier tolerates, and checks that the file
+//! reconstructs byte for byte and that repair puts the data shards back. Sizes
+//! are drawn around the edges encoding cares about: the 64 byte shard
+//! alignment, segment boundaries and the 30 segment block boundary.
+//!
+//! The suite commits a few hundred files, so it only runs with
+//! `cargo test --features slow-tests`.
+
+#![cfg(feature = "slow-tests")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use blockframe::chunker::Chunker;
+use blockframe::filestore::FileStore;
+use blockframe::filestore::models::File;
+use blockframe::utils::{BLOCK_SEGMENTS, SegmentPolicy};
+use proptest::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, RngCore, SeedableRng};
+use tempfile::TempDir;
+
+/// Segment size the files are committed with, small enough that a few MB
+/// spans several tier 3 blocks.
+const SEGMENT: usize = 65_536;
+
+const NAME: &str = "round_trip.bin";
+
+/// File sizes around the boundaries, plus anything up to a few segments.
+fn file_size() -> impl Strategy<Value = usize> {
+    let block = SEGMENT * BLOCK_SEGMENTS;
+    prop_oneof![
+        1usize..300_000,
+        (1usize..64, -1isize..=1).prop_map(|(n, d)| (64 * n).saturating_add_signed(d)),
+        (1usize..6, -1isize..=1).prop_map(|(n, d)| (SEGMENT * n).saturating_add_signed(d)),
+        (1usize..3, -1isize..=1).prop_map(move |(n, d)| (block * n).saturating_add_signed(d)),
+    ]
+}
+
+/// Loses or flips a byte of `path`, picked by `rng`.
+fn damage(path: &Path, rng: &mut StdRng) {
+    if rng.random_bool(0.5) {
+        fs::remove_file(path).unwrap();
+    } else {
+        let mut bytes = fs::read(path).unwrap();
+        let at = rng.random_range(0..bytes.len());
+        bytes[at] ^= 0xFF;
+        fs::write(path, bytes).unwrap();
+    }
+}
+
+/// Damages `file` as far as its tier can recover from, returning the data
+/// shards that were damaged.
+fn damage_within_tolerance(store: &FileStore, file: &File, rng: &mut StdRng) -> Vec<PathBuf> {
+    let mut damaged = Vec::new();
+    match file.manifest.tier {
+        // RS(1,3): the data shard and all but one parity shard
+        1 => {
+            damaged.push(store.get_data_path(file).unwrap());
+            let bad_parity = rng.random_range(0..3);
+            for parity in sample(rng, 3, bad_parity) {
+                damage(&store.get_parity_path_t1(file, parity).unwrap(), rng);
+            }
+        }
+        // RS(1,3) per segment, up to as many segments as the file has parity
+        // shards, each with all but one of its parity shards
+        2 => {
+            let segments = file.manifest.merkle_tree.segments.len();
+            let parity = file.manifest.erasure_coding.parity_shards as usize;
+            let lost = rng.random_range(1..=parity.min(segments));
+            for segment in sample(rng, segments, lost) {
+                damaged.push(store.get_segment_path(file, segment).unwrap());
+                let bad_parity = rng.random_range(0..parity);
+                for p in sample(rng, parity, bad_parity) {
+                    damage(&store.get_parity_path_t2(file, segment, p).unwrap(), rng);
+                }
+            }
+        }
+        // per block, up to as many segments as the block has parity shards
+        _ => {
+            for (&block_id, block) in &file.manifest.merkle_tree.blocks {
+                let (data, parity) = block.geometry();
+                let lost = rng.random_range(0..=parity.min(data));
+                for segment in sample(rng, data, lost) {
+                    damaged.push(
+                        store
+                            .get_block_segment_path(file, block_id, segment)
+                            .unwrap(),
+                    );
+                }
+            }
+        }
+    }
+    for path in &damaged {
+        damage(path, rng);
+    }
+    damaged
+}
+
+fn round_trip(size: usize, tier: u8, seed: u64) -> Result<(), TestCaseError> {
+    let dir = TempDir::new().unwrap();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut data = vec![0u8; size];
+    rng.fill_bytes(&mut data);
+    let source = dir.path().join(NAME);
+    fs::write(&source, &data).unwrap();
+
+    let chunker = Chunker::builder()
+        .archive_dir(dir.path().join("archive"))
+        .segment_policy(SegmentPolicy::Fixed(SEGMENT))
+        .build()
+        .unwrap();
+    chunker.commit_as(&source, Some(tier)).unwrap();
+
+    let store = FileStore::new(chunker.archive_dir()).unwrap();
+    let file = store.find(&NAME.to_string()).unwrap();
+    let mut restored = Vec::new();
+    store.reconstruct_to(&file, &mut restored).unwrap();
+    prop_assert!(
+        restored == data,
+        "intact tier {} file of {} bytes",
+        tier,
+        size
+    );
+
+    let pristine: Vec<(PathBuf, Vec<u8>)> = data_shards(&store, &file)
+        .into_iter()
+        .map(|path| {
+            let bytes = fs::read(&path).unwrap();
+            (path, bytes)
+        })
+        .collect();
+    let damaged = damage_within_tolerance(&store, &file, &mut rng);
+
+    let mut restored = Vec::new();
+    store.reconstruct_to(&file, &mut restored).unwrap();
+    prop_assert!(
+        restored == data,
+        "tier {} file of {} bytes with {:?} damaged",
+        tier,
+        size,
+        damaged
+    );
+
+    store.repair(&file).unwrap();
+    for (path, bytes) in &pristine {
+        prop_assert!(
+            fs::read(path).ok().as_ref() == Some(bytes),
+            "repair left {:?} different",
+            path
+        );
+    }
+    Ok(())
+}
+
+/// Every data shard of `file`.
+fn data_shards(store: &FileStore, file: &File) -> Vec<PathBuf> {
+    let tree = &file.manifest.merkle_tree;
+    match file.manifest.tier {
+        1 => vec![store.get_data_path(file).unwrap()],
+        2 => (0..tree.segments.len())
+            .map(|segment| store.get_segment_path(file, segment).unwrap())
+            .collect(),
+        _ => tree
+            .blocks
+            .iter()
+            .flat_map(|(&block_id, block)| {
+                (0..block.geometry().0).map(move |segment| (block_id, segment))
+            })
+            .map(|(block_id, segment)| {
+                store
+                    .get_block_segment_path(file, block_id, segment)
+                    .unwrap()
+            })
+            .collect(),
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn tier_1_round_trips(size in file_size(), seed in any::<u64>()) {
+        round_trip(size, 1, seed)?;
+    }
+
+    #[test]
+    fn tier_2_round_trips(size in file_size(), seed in any::<u64>()) {
+        round_trip(size, 2, seed)?;
+    }
+
+    #[test]
+    fn tier_3_round_trips(size in file_size(), seed in any::<u64>()) {
+        round_trip(size, 3, seed)?;
+    }
+}
@@ -0,0 +1,87 @@
+//! `blockframe-core`'s verification for the browser, so the web UI can check
+//! a download before it saves it.
+//!
+//! Build with `wasm-pack build wasm --target web`. From JavaScript:
+//!
+//! ```js
+//! import init, { shard_proof, verify_segment } from "./pkg/blockframe_wasm.js";
+//!
+//! await init();
+//! const served = await (await fetch(`/api/files/${name}/manifest`)).text();
+//! const { manifest } = JSON.parse(served);
+//! const bytes = new Uint8Array(await (await fetch(`/api/files/${name}/segment/3`)).arrayBuffer());
+//! const proof = shard_proof(served, 3);
+//! if (!verify_segment(bytes, proof, manifest.merkle_tree.root)) throw new Error("corrupt");
+//! ```
+//!
+//! The root should come from somewhere the client trusts, a signed manifest
+//! or a link it was given; one read off the same response only shows the
+//! segment and manifest agree.
+
+use blockframe_core::manifest::{Manifest, Shard};
+use blockframe_core::merkle::{self, Proof};
+use wasm_bindgen::prelude::*;
+
+/// Whether `bytes` lead to `root` along `proof`, an array of
+/// `{ sibling, sibling_on_left }` steps as [`shard_proof`] gives.
+#[wasm_bindgen]
+pub fn verify_segment(bytes: &[u8], proof: JsValue, root: &str) -> Result<bool, JsError> {
+    let proof: Proof = serde_wasm_bindgen::from_value(proof)?;
+    Ok(merkle::verify_proof(bytes, &proof, root))
+}
+
+/// The proof for a segment of the file `manifest` describes, as
+/// `GET /api/files/:name/manifest` or `manifest.json` has it. `segment_id`
+/// counts as the segment routes do: 0 for a tier 1 file's data, the segment
+/// for tier 2, and the segment within block `block_id` for tier 3.
+#[wasm_bindgen]
+pub fn shard_proof(
+    manifest: &str,
+    segment_id: usize,
+    block_id: Option<usize>,
+) -> Result<JsValue, JsError> {
+    let proof = proof_for(manifest.as_bytes(), segment_id, block_id).map_err(JsError::from)?;
+    Ok(serde_wasm_bindgen::to_value(&proof)?)
+}
+
+fn proof_for(
+    manifest: &[u8],
+    segment_id: usize,
+    block_id: Option<usize>,
+) -> Result<Proof, blockframe_core::manifest::Error> {
+    let manifest = Manifest::parse(manifest)?;
+    let shard = match (manifest.tier, block_id) {
+        (1, _) if segment_id == 0 => Shard::Tiny,
+        (3, Some(block)) => Shard::Block(block, segment_id),
+        (3, None) => Shard::Block(0, segment_id),
+        _ => Shard::Segment(segment_id),
+    };
+    manifest.proof(shard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockframe_core::merkle::hash_hex;
+
+    #[test]
+    fn test_proof_for_served_manifest() {
+        let leaves: Vec<String> = ["data", "p0", "p1", "p2"]
+            .iter()
+            .map(|shard| hash_hex(shard.as_bytes()))
+            .collect();
+        let root = merkle::root_of(&leaves).unwrap();
+        let served = format!(
+            r#"{{"manifest": {{"name": "a.txt", "original_hash": "", "size": 4, "tier": 1,
+                "merkle_tree": {{"leaves": {{"0": "{}", "1": "{}", "2": "{}", "3": "{}"}},
+                "root": "{}"}}}}, "signature": null}}"#,
+            leaves[0], leaves[1], leaves[2], leaves[3], root
+        );
+
+        let proof = proof_for(served.as_bytes(), 0, None).unwrap();
+        assert!(merkle::verify_proof(b"data", &proof, &root));
+        assert!(!merkle::verify_proof(b"date", &proof, &root));
+        assert!(proof_for(served.as_bytes(), 1, None).is_err());
+        assert!(proof_for(b"not json", 0, None).is_err());
+    }
+}